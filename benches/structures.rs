@@ -0,0 +1,296 @@
+// Benchmarks of the common operations of each structure, grouped by the
+// trait or role they share, with the nearest `std::collections` type as a
+// baseline in each group.
+//
+// Run with `cargo bench`; `cargo bench -- Map/` runs a single family. Keys
+// are scattered with a multiplicative hash so that the unbalanced `Tree`
+// does not degenerate into a list.
+
+#[macro_use]
+extern crate criterion;
+extern crate functional_datastructures;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use criterion::measurement::WallTime;
+use criterion::{black_box, BenchmarkGroup, BenchmarkId, Criterion};
+
+use functional_datastructures::hamt::{HamtMap, HamtSet};
+use functional_datastructures::intmap::{IntMap, IntSet};
+use functional_datastructures::ord_seq::{OrdSeqMap, OrdSeqSet};
+use functional_datastructures::seq::Seq;
+use functional_datastructures::set::{Map, Set, Tree};
+use functional_datastructures::skip_list::SkipListMap;
+use functional_datastructures::stack::{CustomStack, Stack};
+
+const SIZES: [u64; 3] = [100, 1000, 10000];
+
+/// Returns `size` distinct keys in no particular order.
+fn keys(size: u64) -> Vec<u64> {
+    (0..size).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)).collect()
+}
+
+fn bind_all<M: Map<u64, u64>>(keys: &[u64]) -> M {
+    keys.iter().fold(M::empty_map(), |map, &key| map.bind(key, key))
+}
+
+fn insert_all<S: Set<u64>>(keys: &[u64]) -> S {
+    keys.iter().fold(S::empty(), |set, &key| set.insert(key))
+}
+
+/// Adds a benchmark of binding every key, one at a time, to `group`.
+fn bench_bind<M: Map<u64, u64>>(group: &mut BenchmarkGroup<WallTime>, name: &str, keys: &[u64]) {
+    group.bench_with_input(BenchmarkId::new(name, keys.len()), keys, |b, keys| {
+        b.iter(|| bind_all::<M>(keys))
+    });
+}
+
+/// Adds a benchmark of looking up every key in a map holding them all to
+/// `group`.
+fn bench_lookup<M: Map<u64, u64>>(group: &mut BenchmarkGroup<WallTime>,
+                                  name: &str,
+                                  keys: &[u64]) {
+    let map = bind_all::<M>(keys);
+    group.bench_with_input(BenchmarkId::new(name, keys.len()), keys, |b, keys| {
+        b.iter(|| keys.iter().filter(|&&key| map.lookup(key).is_some()).count())
+    });
+}
+
+fn bench_insert<S: Set<u64>>(group: &mut BenchmarkGroup<WallTime>, name: &str, keys: &[u64]) {
+    group.bench_with_input(BenchmarkId::new(name, keys.len()), keys, |b, keys| {
+        b.iter(|| insert_all::<S>(keys))
+    });
+}
+
+fn bench_member<S: Set<u64>>(group: &mut BenchmarkGroup<WallTime>,
+                             name: &str,
+                             keys: &[u64]) {
+    let set = insert_all::<S>(keys);
+    group.bench_with_input(BenchmarkId::new(name, keys.len()), keys, |b, keys| {
+        b.iter(|| keys.iter().filter(|&&key| set.member(key)).count())
+    });
+}
+
+/// Adds a benchmark of `operation` on `input` to `group`, for structures
+/// whose operations share no trait.
+fn bench_with<T, R, F>(group: &mut BenchmarkGroup<WallTime>,
+                       name: &str,
+                       size: u64,
+                       input: &T,
+                       operation: F)
+    where F: Fn(&T) -> R
+{
+    group.bench_with_input(BenchmarkId::new(name, size), input, |b, input| {
+        b.iter(|| operation(black_box(input)))
+    });
+}
+
+fn maps(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Map/bind");
+    for &size in SIZES.iter() {
+        let keys = keys(size);
+        bench_bind::<Tree<u64, u64>>(&mut group, "Tree", &keys);
+        bench_bind::<HamtMap<u64, u64>>(&mut group, "HamtMap", &keys);
+        bench_bind::<IntMap<u64>>(&mut group, "IntMap", &keys);
+        bench_bind::<OrdSeqMap<u64, u64>>(&mut group, "OrdSeqMap", &keys);
+        bench_bind::<SkipListMap<u64, u64>>(&mut group, "SkipListMap", &keys);
+        bench_with(&mut group, "std BTreeMap", size, &keys, |keys| {
+            keys.iter().map(|&key| (key, key)).collect::<BTreeMap<_, _>>()
+        });
+        bench_with(&mut group, "std HashMap", size, &keys, |keys| {
+            keys.iter().map(|&key| (key, key)).collect::<HashMap<_, _>>()
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("Map/lookup");
+    for &size in SIZES.iter() {
+        let keys = keys(size);
+        bench_lookup::<Tree<u64, u64>>(&mut group, "Tree", &keys);
+        bench_lookup::<HamtMap<u64, u64>>(&mut group, "HamtMap", &keys);
+        bench_lookup::<IntMap<u64>>(&mut group, "IntMap", &keys);
+        bench_lookup::<OrdSeqMap<u64, u64>>(&mut group, "OrdSeqMap", &keys);
+        bench_lookup::<SkipListMap<u64, u64>>(&mut group, "SkipListMap", &keys);
+        let btree: BTreeMap<_, _> = keys.iter().map(|&key| (key, key)).collect();
+        bench_with(&mut group, "std BTreeMap", size, &keys, |keys| {
+            keys.iter().filter(|key| btree.contains_key(key)).count()
+        });
+        let hash: HashMap<_, _> = keys.iter().map(|&key| (key, key)).collect();
+        bench_with(&mut group, "std HashMap", size, &keys, |keys| {
+            keys.iter().filter(|key| hash.contains_key(key)).count()
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("Map/iterate");
+    for &size in SIZES.iter() {
+        let keys = keys(size);
+        bench_with(&mut group, "Tree", size, &bind_all::<Tree<u64, u64>>(&keys), |map| {
+            map.iter().count()
+        });
+        bench_with(&mut group, "HamtMap", size, &bind_all::<HamtMap<u64, u64>>(&keys), |map| {
+            map.iter().count()
+        });
+        bench_with(&mut group, "IntMap", size, &bind_all::<IntMap<u64>>(&keys), |map| {
+            map.iter().count()
+        });
+        bench_with(&mut group,
+                   "OrdSeqMap",
+                   size,
+                   &bind_all::<OrdSeqMap<u64, u64>>(&keys),
+                   |map| map.iter().count());
+        bench_with(&mut group,
+                   "SkipListMap",
+                   size,
+                   &bind_all::<SkipListMap<u64, u64>>(&keys),
+                   |map| map.iter().count());
+        let btree: BTreeMap<_, _> = keys.iter().map(|&key| (key, key)).collect();
+        bench_with(&mut group, "std BTreeMap", size, &btree, |map| map.iter().count());
+        let hash: HashMap<_, _> = keys.iter().map(|&key| (key, key)).collect();
+        bench_with(&mut group, "std HashMap", size, &hash, |map| map.iter().count());
+    }
+    group.finish();
+
+    // Each map is merged with one holding the other half of the keys.
+    let mut group = c.benchmark_group("Map/merge");
+    for &size in SIZES.iter() {
+        let keys = keys(size);
+        let (evens, odds): (Vec<u64>, Vec<u64>) = keys.iter().partition(|&&key| key % 2 == 0);
+        let hamts = (bind_all::<HamtMap<u64, u64>>(&evens), bind_all::<HamtMap<u64, u64>>(&odds));
+        bench_with(&mut group, "HamtMap", size, &hamts, |maps| maps.0.union(&maps.1));
+        let ints = (bind_all::<IntMap<u64>>(&evens), bind_all::<IntMap<u64>>(&odds));
+        bench_with(&mut group, "IntMap", size, &ints, |maps| maps.0.union(&maps.1));
+        let ord_seqs = (bind_all::<OrdSeqMap<u64, u64>>(&evens),
+                        bind_all::<OrdSeqMap<u64, u64>>(&odds));
+        bench_with(&mut group, "OrdSeqMap", size, &ord_seqs, |maps| maps.0.merge(&maps.1));
+        let btrees: (BTreeMap<u64, u64>, BTreeMap<u64, u64>) =
+            (evens.iter().map(|&key| (key, key)).collect(),
+             odds.iter().map(|&key| (key, key)).collect());
+        bench_with(&mut group, "std BTreeMap", size, &btrees, |maps| {
+            let mut merged = maps.0.clone();
+            merged.extend(maps.1.iter().map(|(&key, &value)| (key, value)));
+            merged
+        });
+    }
+    group.finish();
+}
+
+fn sets(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Set/insert");
+    for &size in SIZES.iter() {
+        let keys = keys(size);
+        bench_insert::<Tree<u64, ()>>(&mut group, "Tree", &keys);
+        bench_insert::<HamtSet<u64>>(&mut group, "HamtSet", &keys);
+        bench_insert::<IntSet>(&mut group, "IntSet", &keys);
+        bench_insert::<OrdSeqSet<u64>>(&mut group, "OrdSeqSet", &keys);
+        bench_with(&mut group, "std BTreeSet", size, &keys, |keys| {
+            keys.iter().cloned().collect::<BTreeSet<_>>()
+        });
+        bench_with(&mut group, "std HashSet", size, &keys, |keys| {
+            keys.iter().cloned().collect::<HashSet<_>>()
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("Set/member");
+    for &size in SIZES.iter() {
+        let keys = keys(size);
+        bench_member::<Tree<u64, ()>>(&mut group, "Tree", &keys);
+        bench_member::<HamtSet<u64>>(&mut group, "HamtSet", &keys);
+        bench_member::<IntSet>(&mut group, "IntSet", &keys);
+        bench_member::<OrdSeqSet<u64>>(&mut group, "OrdSeqSet", &keys);
+        let btree: BTreeSet<_> = keys.iter().cloned().collect();
+        bench_with(&mut group, "std BTreeSet", size, &keys, |keys| {
+            keys.iter().filter(|key| btree.contains(key)).count()
+        });
+        let hash: HashSet<_> = keys.iter().cloned().collect();
+        bench_with(&mut group, "std HashSet", size, &keys, |keys| {
+            keys.iter().filter(|key| hash.contains(key)).count()
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("Set/union");
+    for &size in SIZES.iter() {
+        let keys = keys(size);
+        let (evens, odds): (Vec<u64>, Vec<u64>) = keys.iter().partition(|&&key| key % 2 == 0);
+        let hamts = (insert_all::<HamtSet<u64>>(&evens), insert_all::<HamtSet<u64>>(&odds));
+        bench_with(&mut group, "HamtSet", size, &hamts, |sets| sets.0.union(&sets.1));
+        let ints = (insert_all::<IntSet>(&evens), insert_all::<IntSet>(&odds));
+        bench_with(&mut group, "IntSet", size, &ints, |sets| sets.0.union(&sets.1));
+        let ord_seqs = (insert_all::<OrdSeqSet<u64>>(&evens),
+                        insert_all::<OrdSeqSet<u64>>(&odds));
+        bench_with(&mut group, "OrdSeqSet", size, &ord_seqs, |sets| sets.0.union(&sets.1));
+        let btrees: (BTreeSet<u64>, BTreeSet<u64>) = (evens.iter().cloned().collect(),
+                                                      odds.iter().cloned().collect());
+        bench_with(&mut group, "std BTreeSet", size, &btrees, |sets| {
+            sets.0.union(&sets.1).cloned().collect::<BTreeSet<_>>()
+        });
+    }
+    group.finish();
+}
+
+fn sequences(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Sequence/push");
+    for &size in SIZES.iter() {
+        bench_with(&mut group, "CustomStack", size, &size, |&size| {
+            (0..size).fold(CustomStack::empty(), |stack, i| stack.cons(i))
+        });
+        bench_with(&mut group, "Seq", size, &size, |&size| {
+            (0..size).fold(Seq::empty(), |seq, i| seq.push_back(i))
+        });
+        bench_with(&mut group, "std Vec", size, &size, |&size| (0..size).collect::<Vec<_>>());
+        bench_with(&mut group, "std VecDeque", size, &size, |&size| {
+            (0..size).collect::<VecDeque<_>>()
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("Sequence/index");
+    for &size in SIZES.iter() {
+        let seq = (0..size).fold(Seq::empty(), |seq, i| seq.push_back(i));
+        bench_with(&mut group, "Seq", size, &seq, |seq| {
+            (0..seq.size()).filter_map(|i| seq.get(i)).count()
+        });
+        let deque: VecDeque<_> = (0..size).collect();
+        bench_with(&mut group, "std VecDeque", size, &deque, |deque| {
+            (0..deque.len()).filter_map(|i| deque.get(i)).count()
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("Sequence/iterate");
+    for &size in SIZES.iter() {
+        let stack = (0..size).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+        bench_with(&mut group, "CustomStack", size, &stack, |stack| {
+            let mut count = 0;
+            let mut rest = stack;
+            while let CustomStack::Cons { ref tail, .. } = *rest {
+                count += 1;
+                rest = tail;
+            }
+            count
+        });
+        let seq = (0..size).fold(Seq::empty(), |seq, i| seq.push_back(i));
+        bench_with(&mut group, "Seq", size, &seq, |seq| seq.iter().count());
+        let deque: VecDeque<_> = (0..size).collect();
+        bench_with(&mut group, "std VecDeque", size, &deque, |deque| deque.iter().count());
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("Sequence/concat");
+    for &size in SIZES.iter() {
+        let seq = (0..size).fold(Seq::empty(), |seq, i| seq.push_back(i));
+        bench_with(&mut group, "Seq", size, &seq, |seq| seq.concat(seq));
+        let deque: VecDeque<_> = (0..size).collect();
+        bench_with(&mut group, "std VecDeque", size, &deque, |deque| {
+            let mut joined = deque.clone();
+            joined.extend(deque.iter().cloned());
+            joined
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, maps, sets, sequences);
+criterion_main!(benches);
@@ -0,0 +1,279 @@
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use set::{Tree, TreeBuilder};
+
+/// A bump arena holding the nodes of persistent binary trees, as an
+/// alternative to allocating each node separately behind an `Arc`.
+///
+/// Nodes are appended to a single vector and never freed or changed, so
+/// every version of every tree built in an arena stays valid for as long as
+/// the arena does. Building a tree costs one vector push per node rather than
+/// one allocation, and dropping the arena frees every node at once. This
+/// suits large transient trees, which are built, queried and then thrown
+/// away together; a tree which outlives its arena can be copied out with
+/// `to_tree`.
+///
+/// The trees themselves are `ArenaTree` handles, which are only meaningful
+/// to the arena they were built in. Passing a handle to another arena gives
+/// meaningless results, or a panic.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::arena::{Arena, ArenaTree};
+/// use functional_datastructures::set::Map;
+///
+/// let mut arena = Arena::with_capacity(1000);
+/// let mut tree = ArenaTree::empty();
+/// for i in 0..100 {
+///     tree = arena.bind(tree, (i * 37) % 100, i);
+/// }
+/// let old = tree;
+/// tree = arena.bind(tree, 0, 1000);
+///
+/// assert!(arena.lookup(tree, &0) == Some(&1000));
+/// assert!(arena.lookup(old, &0) == Some(&0));
+/// assert!(arena.to_tree(tree).lookup(37) == Some(1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Arena<K, V> {
+    nodes: Vec<Node<K, V>>,
+}
+
+/// A node of an arena, which refers to its children by their indexes.
+#[derive(Debug, Clone)]
+struct Node<K, V> {
+    left: Option<u32>,
+    key: K,
+    value: V,
+    right: Option<u32>,
+}
+
+/// An unbalanced binary tree whose nodes are held by an `Arena`.
+///
+/// A handle is just the index of the root node, so it is `Copy` whatever
+/// the types of its keys and values.
+#[derive(Debug)]
+pub struct ArenaTree<K, V> {
+    root: Option<u32>,
+    bindings: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> ArenaTree<K, V> {
+    /// Returns the empty tree, which is valid in any arena.
+    pub fn empty() -> Self {
+        ArenaTree::at(None)
+    }
+    /// Tests whether this tree is empty.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+    fn at(root: Option<u32>) -> Self {
+        ArenaTree {
+            root: root,
+            bindings: PhantomData,
+        }
+    }
+}
+
+impl<K, V> Clone for ArenaTree<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for ArenaTree<K, V> {}
+
+impl<K: Ord + Clone, V: Clone> Arena<K, V> {
+    /// Returns an arena which holds no nodes.
+    pub fn new() -> Self {
+        Arena { nodes: Vec::new() }
+    }
+    /// Returns an arena with room for `capacity` nodes before it next needs
+    /// to grow.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Arena { nodes: Vec::with_capacity(capacity) }
+    }
+    /// Returns the number of nodes held by this arena, across every tree
+    /// built in it.
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+    /// Returns a copy of `tree` with `key` bound to `value`, adding the
+    /// nodes on the path to `key` to this arena.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this arena already holds `u32::MAX` nodes.
+    pub fn bind(&mut self, tree: ArenaTree<K, V>, key: K, value: V) -> ArenaTree<K, V> {
+        // Walk down to where `key` belongs, remembering the nodes passed on
+        // the way and which side of each the path went.
+        let mut path = Vec::new();
+        let mut current = tree.root;
+        let mut replaced = None;
+        while let Some(index) = current {
+            let node = &self.nodes[index as usize];
+            if key < node.key {
+                path.push((index, true));
+                current = node.left;
+            } else if key > node.key {
+                path.push((index, false));
+                current = node.right;
+            } else {
+                replaced = Some((node.left, node.right));
+                break;
+            }
+        }
+        let (left, right) = replaced.unwrap_or((None, None));
+        let mut child = self.push(Node {
+            left: left,
+            key: key,
+            value: value,
+            right: right,
+        });
+        // Then copy the path back up to the root, pointing each copy at the
+        // copy below it.
+        while let Some((index, went_left)) = path.pop() {
+            let mut node = self.nodes[index as usize].clone();
+            if went_left {
+                node.left = Some(child);
+            } else {
+                node.right = Some(child);
+            }
+            child = self.push(node);
+        }
+        ArenaTree::at(Some(child))
+    }
+    /// Returns the value bound to `key` in `tree`, or `None` if `key` is
+    /// not a key in it.
+    pub fn lookup(&self, tree: ArenaTree<K, V>, key: &K) -> Option<&V> {
+        let mut current = tree.root;
+        while let Some(index) = current {
+            let node = &self.nodes[index as usize];
+            if *key < node.key {
+                current = node.left;
+            } else if *key > node.key {
+                current = node.right;
+            } else {
+                return Some(&node.value);
+            }
+        }
+        None
+    }
+    /// Returns an iterator over the bindings in `tree`, in order of key.
+    pub fn iter(&self, tree: ArenaTree<K, V>) -> Iter<'_, K, V> {
+        let mut iter = Iter {
+            nodes: &self.nodes,
+            stack: Vec::new(),
+        };
+        iter.push_left_spine(tree.root);
+        iter
+    }
+    /// Returns a `Tree` of the bindings in `tree`, which shares nothing with
+    /// this arena and so can outlive it.
+    pub fn to_tree(&self, tree: ArenaTree<K, V>) -> Tree<K, V>
+        where K: Debug,
+              V: Debug
+    {
+        let mut builder = TreeBuilder::new();
+        builder.extend(self.iter(tree).map(|(key, value)| (key.clone(), value.clone())));
+        builder.freeze()
+    }
+    /// Adds `node` to this arena and returns its index.
+    fn push(&mut self, node: Node<K, V>) -> u32 {
+        assert!(self.nodes.len() < u32::MAX as usize, "arena is full");
+        self.nodes.push(node);
+        (self.nodes.len() - 1) as u32
+    }
+}
+
+impl<T: Ord + Clone> Arena<T, ()> {
+    /// Returns a copy of `tree` with `value` added to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this arena already holds `u32::MAX` nodes.
+    pub fn insert(&mut self, tree: ArenaTree<T, ()>, value: T) -> ArenaTree<T, ()> {
+        if self.member(tree, &value) {
+            tree
+        } else {
+            self.bind(tree, value, ())
+        }
+    }
+    /// Tests whether `value` is a member of `tree`.
+    pub fn member(&self, tree: ArenaTree<T, ()>, value: &T) -> bool {
+        self.lookup(tree, value).is_some()
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Default for Arena<K, V> {
+    fn default() -> Self {
+        Arena::new()
+    }
+}
+
+/// An iterator over the bindings of an `ArenaTree`, in order of key,
+/// returned by `Arena::iter`.
+pub struct Iter<'a, K: 'a, V: 'a> {
+    nodes: &'a [Node<K, V>],
+    /// The nodes whose bindings are still to be returned, the next one last.
+    /// Their right subtrees are still to be visited too.
+    stack: Vec<&'a Node<K, V>>,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    fn push_left_spine(&mut self, mut current: Option<u32>) {
+        while let Some(index) = current {
+            let node = &self.nodes[index as usize];
+            self.stack.push(node);
+            current = node.left;
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.stack.pop().map(|node| {
+            self.push_left_spine(node.right);
+            (&node.key, &node.value)
+        })
+    }
+}
+
+#[cfg(test)]
+use set::Set;
+
+#[test]
+fn arena_trees_are_persistent() {
+    let mut arena = Arena::new();
+    let mut versions = vec![ArenaTree::empty()];
+    for i in 0..200 {
+        let latest = *versions.last().unwrap();
+        versions.push(arena.insert(latest, (i * 71) % 200));
+    }
+
+    for (size, version) in versions.iter().enumerate() {
+        let mut expected: Vec<_> = (0..size).map(|i| (i * 71) % 200).collect();
+        expected.sort();
+        assert!(arena.iter(*version).map(|(value, _)| *value).collect::<Vec<_>>() == expected);
+    }
+    let all = *versions.last().unwrap();
+    assert!(arena.insert(all, 5).root == all.root);
+    assert!(arena.member(all, &199) && !arena.member(all, &200));
+    assert!(!arena.member(versions[1], &71));
+}
+
+#[test]
+fn arena_trees_copy_out_into_trees() {
+    let mut arena = Arena::new();
+    let tree = (0..1000).fold(ArenaTree::empty(), |tree, i| arena.insert(tree, i));
+    let nodes = arena.size();
+    let tree = arena.to_tree(tree);
+    drop(arena);
+
+    assert!(nodes == 1000 * 1001 / 2);
+    assert!((0..1000).all(|i| tree.member(i)) && !tree.member(1000));
+}
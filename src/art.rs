@@ -0,0 +1,726 @@
+use std::array;
+
+use collection::Collection;
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+
+/// An immutable map keyed by byte strings, implemented as an adaptive radix
+/// tree.
+///
+/// Like `TrieMap`, this branches on one byte of the key at each level, but it
+/// is much more compact. A branch's children are stored in one of four node
+/// types holding up to 4, 16, 48 or 256 children, chosen to fit the number it
+/// actually has; chains of branches with a single child are collapsed into a
+/// prefix stored on the node below; and a key is kept in a single leaf until
+/// another key shares the bytes leading to it. Operations take time
+/// proportional to the length of the key, updates copy only the nodes on the
+/// path to the key, and iteration visits keys in lexicographic byte order.
+///
+/// This is the structure described by Leis, Kemper and Neumann in "The
+/// Adaptive Radix Tree", made persistent by copying nodes on write.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::art::ArtMap;
+///
+/// let urls = ArtMap::empty()
+///                .insert("http://a.com/", 1)
+///                .insert("http://a.com/x", 2)
+///                .insert("http://b.com/", 3);
+///
+/// assert!(urls.lookup("http://a.com/x").unwrap() == 2);
+/// assert!(urls.lookup("http://a.com").is_none());
+/// assert!(urls.remove("http://b.com/").size() == 2);
+/// assert!(urls.iter().map(|(_, v)| *v).collect::<Vec<_>>() == vec![1, 2, 3]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ArtMapIn<V, P: SharedPointer> {
+    size: u32,
+    root: Option<Child<V, P>>,
+}
+
+/// An `ArtMapIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type ArtMap<V> = ArtMapIn<V, DefaultPointer>;
+
+#[derive(Debug)]
+enum Node<V, P: SharedPointer> {
+    /// A single key and its value. The whole key is kept, so that the leaf
+    /// can sit at the first level where no other key shares its bytes.
+    Leaf { key: Box<[u8]>, value: V },
+    Inner {
+        /// The bytes which every key below this node has next, before the
+        /// byte used to choose a child.
+        prefix: Box<[u8]>,
+        /// The leaf for the key which ends after `prefix`, if there is one.
+        terminal: Option<Child<V, P>>,
+        children: Children<V, P>,
+    },
+}
+
+/// A child of a node, shared with the other versions of the map that hold it.
+type Child<V, P> = Shared<Node<V, P>, P>;
+
+
+/// The children of an inner node, keyed by byte. The smaller node types are
+/// used whenever the children fit, so that most nodes take up little space.
+#[derive(Debug, Clone)]
+enum Children<V, P: SharedPointer> {
+    Node4(Sorted<V, 4, P>),
+    Node16(Box<Sorted<V, 16, P>>),
+    Node48(Box<Indexed<V, P>>),
+    Node256(Box<Direct<V, P>>),
+}
+
+/// Up to `N` children, with their bytes kept sorted in `keys`.
+#[derive(Debug, Clone)]
+struct Sorted<V, const N: usize, P: SharedPointer> {
+    len: u8,
+    keys: [u8; N],
+    nodes: [Option<Child<V, P>>; N],
+}
+
+/// Up to 48 children in `nodes`, with the slot for each byte recorded in
+/// `index` as one more than its position, or zero if it has no child.
+#[derive(Debug, Clone)]
+struct Indexed<V, P: SharedPointer> {
+    len: u8,
+    index: [u8; 256],
+    nodes: [Option<Child<V, P>>; 48],
+}
+
+/// A slot for the child of every byte.
+#[derive(Debug, Clone)]
+struct Direct<V, P: SharedPointer> {
+    len: u16,
+    nodes: [Option<Child<V, P>>; 256],
+}
+
+impl<V: Clone, P: SharedPointer> ArtMapIn<V, P> {
+    /// Returns a map containing nothing.
+    pub fn empty() -> Self {
+        ArtMapIn {
+            size: 0,
+            root: None,
+        }
+    }
+    /// Returns a copy of this map with `key` bound to `value`, replacing any
+    /// existing binding for `key`.
+    pub fn insert<K: AsRef<[u8]>>(&self, key: K, value: V) -> Self {
+        let key = key.as_ref();
+        let (root, added) = match self.root {
+            None => (Node::leaf(key, value), true),
+            Some(ref root) => Node::insert(root, key, 0, value),
+        };
+        ArtMapIn {
+            size: if added { self.size + 1 } else { self.size },
+            root: Some(Shared::new(root)),
+        }
+    }
+    /// Returns a copy of this map without any binding for `key`.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Self {
+        let removed = self.root.as_ref().and_then(|root| Node::remove(root, key.as_ref(), 0));
+        match removed {
+            None => self.clone(),
+            Some(root) => {
+                ArtMapIn {
+                    size: self.size - 1,
+                    root: root,
+                }
+            }
+        }
+    }
+    /// Returns a `Some` of the value bound to `key`, or `None` if `key` is
+    /// not a key in this map.
+    pub fn lookup<K: AsRef<[u8]>>(&self, key: K) -> Option<V> {
+        self.get(key).cloned()
+    }
+    /// Returns a reference to the value bound to `key`, or `None` if `key` is
+    /// not a key in this map.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<&V> {
+        let key = key.as_ref();
+        let mut current = match self.root {
+            None => return None,
+            Some(ref root) => root,
+        };
+        let mut depth = 0;
+        loop {
+            match **current {
+                Node::Leaf { key: ref leaf_key, ref value } => {
+                    return if **leaf_key == *key { Some(value) } else { None };
+                }
+                Node::Inner { ref prefix, ref terminal, ref children } => {
+                    if !key[depth..].starts_with(prefix) {
+                        return None;
+                    }
+                    depth += prefix.len();
+                    if depth == key.len() {
+                        current = terminal.as_ref()?;
+                    } else {
+                        current = children.get(key[depth])?;
+                        depth += 1;
+                    }
+                }
+            }
+        }
+    }
+    /// Tests whether `key` is bound in this map.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> bool {
+        self.get(key).is_some()
+    }
+    /// Tests whether this map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+    /// Returns the number of keys bound in this map.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+    /// Returns an iterator over the bindings in this map, in lexicographic
+    /// order of key.
+    pub fn iter(&self) -> Iter<'_, V, P> {
+        Iter { pending: self.root.iter().collect() }
+    }
+}
+
+impl<V: Clone, P: SharedPointer> Collection for ArtMapIn<V, P> {
+    type Iter<'a> = Iter<'a, V, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, V, P> {
+        self.iter()
+    }
+}
+
+impl<V: Clone, P: SharedPointer> Node<V, P> {
+    fn leaf(key: &[u8], value: V) -> Self {
+        Node::Leaf {
+            key: key.into(),
+            value: value,
+        }
+    }
+    /// Returns an inner node with the given prefix and entries, where an
+    /// entry with no byte is the terminal leaf.
+    fn branch(prefix: &[u8], entries: Vec<(Option<u8>, Shared<Self, P>)>) -> Self {
+        let mut terminal = None;
+        let mut children = Children::empty();
+        for (byte, node) in entries {
+            match byte {
+                None => terminal = Some(node),
+                Some(byte) => children = children.with(byte, node),
+            }
+        }
+        Node::Inner {
+            prefix: prefix.into(),
+            terminal: terminal,
+            children: children,
+        }
+    }
+    /// Returns a copy of `node`, the first `depth` bytes of `key` having led
+    /// to it, with `key` bound to `value`. Also returns whether `key` was not
+    /// already bound in it.
+    fn insert(node: &Shared<Self, P>, key: &[u8], depth: usize, value: V) -> (Self, bool) {
+        // The entry for a key in a branch which starts at `position`.
+        let slot = |key: &[u8], position: usize| {
+            if position == key.len() {
+                None
+            } else {
+                Some(key[position])
+            }
+        };
+        match **node {
+            Node::Leaf { key: ref leaf_key, .. } => {
+                if **leaf_key == *key {
+                    return (Node::leaf(key, value), false);
+                }
+                let split = depth + common_prefix(&leaf_key[depth..], &key[depth..]);
+                let leaf = Shared::new(Node::leaf(key, value));
+                (Node::branch(&key[depth..split],
+                              vec![(slot(leaf_key, split), node.clone()),
+                                   (slot(key, split), leaf)]),
+                 true)
+            }
+            Node::Inner { ref prefix, ref terminal, ref children } => {
+                let common = common_prefix(prefix, &key[depth..]);
+                if common < prefix.len() {
+                    // The key leaves this node's prefix part way along, so
+                    // the prefix is split at that point.
+                    let shortened = Node::Inner {
+                        prefix: prefix[common + 1..].into(),
+                        terminal: terminal.clone(),
+                        children: children.clone(),
+                    };
+                    let leaf = Shared::new(Node::leaf(key, value));
+                    return (Node::branch(&prefix[..common],
+                                         vec![(Some(prefix[common]), Shared::new(shortened)),
+                                              (slot(key, depth + common), leaf)]),
+                            true);
+                }
+                let depth = depth + prefix.len();
+                if depth == key.len() {
+                    return (Node::Inner {
+                        prefix: prefix.clone(),
+                        terminal: Some(Shared::new(Node::leaf(key, value))),
+                        children: children.clone(),
+                    },
+                            terminal.is_none());
+                }
+                let byte = key[depth];
+                let (child, added) = match children.get(byte) {
+                    Some(child) => Node::insert(child, key, depth + 1, value),
+                    None => (Node::leaf(key, value), true),
+                };
+                (Node::Inner {
+                    prefix: prefix.clone(),
+                    terminal: terminal.clone(),
+                    children: children.with(byte, Shared::new(child)),
+                },
+                 added)
+            }
+        }
+    }
+    /// Returns a copy of `node`, the first `depth` bytes of `key` having led
+    /// to it, without any binding for `key`. Returns `None` if `key` is not
+    /// bound in it, and `Some(None)` if nothing is left.
+    fn remove(node: &Shared<Self, P>, key: &[u8], depth: usize) -> Option<Option<Shared<Self, P>>> {
+        match **node {
+            Node::Leaf { key: ref leaf_key, .. } => {
+                if **leaf_key == *key {
+                    Some(None)
+                } else {
+                    None
+                }
+            }
+            Node::Inner { ref prefix, ref terminal, ref children } => {
+                if !key[depth..].starts_with(prefix) {
+                    return None;
+                }
+                let depth = depth + prefix.len();
+                if depth == key.len() {
+                    return terminal.as_ref()
+                                   .map(|_| Node::collapse(prefix, None, children.clone()));
+                }
+                let byte = key[depth];
+                let child = match children.get(byte) {
+                    None => return None,
+                    Some(child) => Node::remove(child, key, depth + 1),
+                };
+                child.map(|child| {
+                    let children = match child {
+                        None => children.without(byte),
+                        Some(child) => children.with(byte, child),
+                    };
+                    Node::collapse(prefix, terminal.clone(), children)
+                })
+            }
+        }
+    }
+    /// Returns an inner node with the given parts, or a simpler node with the
+    /// same contents if it would have fewer than two entries.
+    fn collapse(prefix: &[u8],
+                terminal: Option<Shared<Self, P>>,
+                children: Children<V, P>)
+                -> Option<Shared<Self, P>> {
+        if children.len() == 0 {
+            return terminal;
+        }
+        if terminal.is_none() && children.len() == 1 {
+            let (byte, child) = children.entries().pop().unwrap();
+            return Some(match **child {
+                Node::Leaf { .. } => child.clone(),
+                Node::Inner { prefix: ref child_prefix, ref terminal, ref children } => {
+                    let mut merged = prefix.to_vec();
+                    merged.push(byte);
+                    merged.extend_from_slice(child_prefix);
+                    Shared::new(Node::Inner {
+                        prefix: merged.into(),
+                        terminal: terminal.clone(),
+                        children: children.clone(),
+                    })
+                }
+            });
+        }
+        Some(Shared::new(Node::Inner {
+            prefix: prefix.into(),
+            terminal: terminal,
+            children: children,
+        }))
+    }
+}
+
+impl<V: Clone, P: SharedPointer> Children<V, P> {
+    fn empty() -> Self {
+        Children::Node4(Sorted::empty())
+    }
+    /// Returns the smallest node type large enough for `entries`, which must
+    /// be in byte order, holding exactly those entries.
+    fn from_entries(entries: Vec<(u8, Child<V, P>)>) -> Self {
+        let len = entries.len();
+        if len <= 4 {
+            Children::Node4(Sorted::from_entries(entries))
+        } else if len <= 16 {
+            Children::Node16(Box::new(Sorted::from_entries(entries)))
+        } else if len <= 48 {
+            let mut indexed = Indexed::empty();
+            for (slot, (byte, node)) in entries.into_iter().enumerate() {
+                indexed.index[byte as usize] = slot as u8 + 1;
+                indexed.nodes[slot] = Some(node);
+            }
+            indexed.len = len as u8;
+            Children::Node48(Box::new(indexed))
+        } else {
+            let mut direct = Direct::empty();
+            for (byte, node) in entries {
+                direct.nodes[byte as usize] = Some(node);
+            }
+            direct.len = len as u16;
+            Children::Node256(Box::new(direct))
+        }
+    }
+    fn len(&self) -> usize {
+        match *self {
+            Children::Node4(ref sorted) => sorted.len as usize,
+            Children::Node16(ref sorted) => sorted.len as usize,
+            Children::Node48(ref indexed) => indexed.len as usize,
+            Children::Node256(ref direct) => direct.len as usize,
+        }
+    }
+    fn get(&self, byte: u8) -> Option<&Child<V, P>> {
+        match *self {
+            Children::Node4(ref sorted) => sorted.get(byte),
+            Children::Node16(ref sorted) => sorted.get(byte),
+            Children::Node48(ref indexed) => {
+                match indexed.index[byte as usize] {
+                    0 => None,
+                    slot => indexed.nodes[slot as usize - 1].as_ref(),
+                }
+            }
+            Children::Node256(ref direct) => direct.nodes[byte as usize].as_ref(),
+        }
+    }
+    /// Returns the children in byte order.
+    fn entries(&self) -> Vec<(u8, &Child<V, P>)> {
+        match *self {
+            Children::Node4(ref sorted) => sorted.entries(),
+            Children::Node16(ref sorted) => sorted.entries(),
+            Children::Node48(ref indexed) => {
+                (0..256)
+                    .filter(|byte| indexed.index[*byte] != 0)
+                    .map(|byte| {
+                        let slot = indexed.index[byte] as usize - 1;
+                        (byte as u8, indexed.nodes[slot].as_ref().unwrap())
+                    })
+                    .collect()
+            }
+            Children::Node256(ref direct) => {
+                (0..256)
+                    .filter_map(|byte| direct.nodes[byte].as_ref().map(|node| (byte as u8, node)))
+                    .collect()
+            }
+        }
+    }
+    /// Returns a copy of these children with the child for `byte` set to
+    /// `node`, moving to a larger node type if this one is full.
+    fn with(&self, byte: u8, node: Child<V, P>) -> Self {
+        let present = self.get(byte).is_some();
+        match *self {
+            Children::Node4(ref sorted) if present || sorted.len < 4 => {
+                return Children::Node4(sorted.with(byte, node));
+            }
+            Children::Node16(ref sorted) if present || sorted.len < 16 => {
+                return Children::Node16(Box::new(sorted.with(byte, node)));
+            }
+            Children::Node48(ref indexed) if present || indexed.len < 48 => {
+                let mut indexed = indexed.clone();
+                let slot = match indexed.index[byte as usize] {
+                    0 => {
+                        indexed.len += 1;
+                        indexed.nodes.iter().position(|node| node.is_none()).unwrap()
+                    }
+                    slot => slot as usize - 1,
+                };
+                indexed.index[byte as usize] = slot as u8 + 1;
+                indexed.nodes[slot] = Some(node);
+                return Children::Node48(indexed);
+            }
+            Children::Node256(ref direct) => {
+                let mut direct = direct.clone();
+                if !present {
+                    direct.len += 1;
+                }
+                direct.nodes[byte as usize] = Some(node);
+                return Children::Node256(direct);
+            }
+            _ => {}
+        }
+        let mut entries = self.owned_entries();
+        let position = entries.iter().position(|entry| entry.0 > byte).unwrap_or(entries.len());
+        entries.insert(position, (byte, node));
+        Children::from_entries(entries)
+    }
+    /// Returns a copy of these children without the child for `byte`, which
+    /// must be present, moving to a smaller node type once few enough are
+    /// left. The thresholds leave some room, so that a node on the boundary
+    /// does not change type on every update.
+    fn without(&self, byte: u8) -> Self {
+        let shrink_at = match *self {
+            Children::Node4(_) => 0,
+            Children::Node16(_) => 3,
+            Children::Node48(_) => 12,
+            Children::Node256(_) => 40,
+        };
+        if self.len() - 1 <= shrink_at {
+            let mut entries = self.owned_entries();
+            entries.retain(|entry| entry.0 != byte);
+            return Children::from_entries(entries);
+        }
+        match *self {
+            Children::Node4(ref sorted) => Children::Node4(sorted.without(byte)),
+            Children::Node16(ref sorted) => Children::Node16(Box::new(sorted.without(byte))),
+            Children::Node48(ref indexed) => {
+                let mut indexed = indexed.clone();
+                let slot = indexed.index[byte as usize] as usize - 1;
+                indexed.index[byte as usize] = 0;
+                indexed.nodes[slot] = None;
+                indexed.len -= 1;
+                Children::Node48(indexed)
+            }
+            Children::Node256(ref direct) => {
+                let mut direct = direct.clone();
+                direct.nodes[byte as usize] = None;
+                direct.len -= 1;
+                Children::Node256(direct)
+            }
+        }
+    }
+    fn owned_entries(&self) -> Vec<(u8, Child<V, P>)> {
+        self.entries().into_iter().map(|(byte, node)| (byte, node.clone())).collect()
+    }
+}
+
+impl<V: Clone, const N: usize, P: SharedPointer> Sorted<V, N, P> {
+    fn empty() -> Self {
+        Sorted {
+            len: 0,
+            keys: [0; N],
+            nodes: array::from_fn(|_| None),
+        }
+    }
+    fn from_entries(entries: Vec<(u8, Child<V, P>)>) -> Self {
+        let mut sorted = Sorted::empty();
+        for (position, (byte, node)) in entries.into_iter().enumerate() {
+            sorted.keys[position] = byte;
+            sorted.nodes[position] = Some(node);
+            sorted.len += 1;
+        }
+        sorted
+    }
+    fn position(&self, byte: u8) -> Result<usize, usize> {
+        self.keys[..self.len as usize].binary_search(&byte)
+    }
+    fn get(&self, byte: u8) -> Option<&Child<V, P>> {
+        self.position(byte).ok().and_then(|position| self.nodes[position].as_ref())
+    }
+    fn entries(&self) -> Vec<(u8, &Child<V, P>)> {
+        (0..self.len as usize).map(|i| (self.keys[i], self.nodes[i].as_ref().unwrap())).collect()
+    }
+    /// Returns a copy with the child for `byte` set to `node`. There must be
+    /// room for it if it is not already present.
+    fn with(&self, byte: u8, node: Child<V, P>) -> Self {
+        let mut sorted = self.clone();
+        match self.position(byte) {
+            Ok(position) => sorted.nodes[position] = Some(node),
+            Err(position) => {
+                let len = self.len as usize;
+                sorted.keys.copy_within(position..len, position + 1);
+                sorted.nodes[position..len + 1].rotate_right(1);
+                sorted.keys[position] = byte;
+                sorted.nodes[position] = Some(node);
+                sorted.len += 1;
+            }
+        }
+        sorted
+    }
+    /// Returns a copy without the child for `byte`, which must be present.
+    fn without(&self, byte: u8) -> Self {
+        let mut sorted = self.clone();
+        let position = self.position(byte).unwrap();
+        let len = self.len as usize;
+        sorted.keys.copy_within(position + 1..len, position);
+        sorted.nodes[position] = None;
+        sorted.nodes[position..len].rotate_left(1);
+        sorted.len -= 1;
+        sorted
+    }
+}
+
+impl<V, P: SharedPointer> Indexed<V, P> {
+    fn empty() -> Self {
+        Indexed {
+            len: 0,
+            index: [0; 256],
+            nodes: array::from_fn(|_| None),
+        }
+    }
+}
+
+impl<V, P: SharedPointer> Direct<V, P> {
+    fn empty() -> Self {
+        Direct {
+            len: 0,
+            nodes: array::from_fn(|_| None),
+        }
+    }
+}
+
+/// Returns the length of the longest common prefix of `xs` and `ys`.
+fn common_prefix(xs: &[u8], ys: &[u8]) -> usize {
+    xs.iter().zip(ys.iter()).take_while(|&(x, y)| x == y).count()
+}
+
+/// An iterator over the bindings of an `ArtMap`, returned by `ArtMap::iter`.
+pub struct Iter<'a, V: 'a, P: SharedPointer> {
+    /// The nodes still to be visited, the next one last.
+    pending: Vec<&'a Child<V, P>>,
+}
+
+impl<'a, V: Clone, P: SharedPointer> Iterator for Iter<'a, V, P> {
+    type Item = (&'a [u8], &'a V);
+
+    fn next(&mut self) -> Option<(&'a [u8], &'a V)> {
+        while let Some(node) = self.pending.pop() {
+            match **node {
+                Node::Leaf { ref key, ref value } => return Some((key, value)),
+                Node::Inner { ref terminal, ref children, .. } => {
+                    for (_, child) in children.entries().into_iter().rev() {
+                        self.pending.push(child);
+                    }
+                    // A key ending at this node comes before all those that
+                    // continue past it.
+                    if let Some(ref terminal) = *terminal {
+                        self.pending.push(terminal);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+fn root_children<V>(map: &ArtMap<V>) -> &Children<V, DefaultPointer> {
+    match **map.root.as_ref().unwrap() {
+        Node::Inner { ref children, .. } => children,
+        Node::Leaf { .. } => panic!("root should be an inner node"),
+    }
+}
+
+#[test]
+fn empty_map_contains_nothing() {
+    let map: ArtMap<u32> = ArtMap::empty();
+
+    assert!(map.lookup("").is_none());
+    assert!(map.is_empty());
+    assert!(map.remove("a").is_empty());
+    assert!(map.iter().next().is_none());
+}
+
+#[test]
+fn keys_which_are_prefixes_of_each_other() {
+    let keys = ["", "a", "ab", "abc", "abd", "b", "abcdef"];
+    let map = keys.iter().enumerate().fold(ArtMap::empty(), |map, (i, k)| map.insert(k, i));
+
+    assert!(map.size() == 7);
+    for (i, key) in keys.iter().enumerate() {
+        assert!(map.lookup(key).unwrap() == i);
+    }
+    assert!(map.lookup("abcd").is_none());
+    assert!(map.lookup("abcdefg").is_none());
+    assert!(map.insert("ab", 100).lookup("ab").unwrap() == 100);
+    assert!(map.insert("ab", 100).size() == 7);
+
+    let mut sorted = keys.to_vec();
+    sorted.sort();
+    let iterated: Vec<&[u8]> = map.iter().map(|(k, _)| k).collect();
+    assert!(iterated == sorted.iter().map(|k| k.as_bytes()).collect::<Vec<_>>());
+}
+
+#[test]
+fn nodes_grow_and_shrink() {
+    let key = |byte: u8| vec![b'k', byte, b'!'];
+    let node_type = |map: &ArtMap<u8>| match *root_children(map) {
+        Children::Node4(_) => 4,
+        Children::Node16(_) => 16,
+        Children::Node48(_) => 48,
+        Children::Node256(_) => 256,
+    };
+
+    let mut map = ArtMap::empty().insert(key(0), 0);
+    let mut sizes = Vec::new();
+    for byte in 1..=255 {
+        map = map.insert(key(byte), byte);
+        sizes.push((map.size(), node_type(&map)));
+    }
+    assert!(sizes.contains(&(4, 4)) && sizes.contains(&(5, 16)));
+    assert!(sizes.contains(&(16, 16)) && sizes.contains(&(17, 48)));
+    assert!(sizes.contains(&(48, 48)) && sizes.contains(&(49, 256)));
+    for byte in 0..=255 {
+        assert!(map.lookup(key(byte)).unwrap() == byte);
+    }
+
+    sizes.clear();
+    for byte in (2..=255).rev() {
+        map = map.remove(key(byte));
+        sizes.push((map.size(), node_type(&map)));
+    }
+    assert!(sizes.contains(&(41, 256)) && sizes.contains(&(40, 48)));
+    assert!(sizes.contains(&(13, 48)) && sizes.contains(&(12, 16)));
+    assert!(sizes.contains(&(4, 16)) && sizes.contains(&(3, 4)));
+    assert!(map.iter().map(|(_, v)| *v).collect::<Vec<_>>() == vec![0, 1]);
+}
+
+#[test]
+fn removal_collapses_single_children() {
+    let map = ArtMap::empty().insert("prefix-one", 1).insert("prefix-two", 2).insert("p", 0);
+    let removed = map.remove("p").remove("prefix-one");
+
+    match **removed.root.as_ref().unwrap() {
+        Node::Leaf { ref key, .. } => assert!(&**key == b"prefix-two"),
+        Node::Inner { .. } => panic!("a single key should be a leaf"),
+    }
+    let removed = map.remove("prefix-one");
+    match **removed.root.as_ref().unwrap() {
+        Node::Inner { ref prefix, .. } => assert!(&**prefix == b"p"),
+        Node::Leaf { .. } => panic!("two keys should need an inner node"),
+    }
+    assert!(removed.lookup("prefix-two").unwrap() == 2);
+    assert!(removed.lookup("p").unwrap() == 0);
+    assert!(map.remove("prefix").size() == 3);
+}
+
+#[test]
+fn many_keys() {
+    let keys: Vec<String> = (0..5000)
+                                .map(|i| format!("http://example.com/{}/{}", i % 7, i))
+                                .collect();
+    let map = keys.iter().fold(ArtMap::empty(), |map, k| map.insert(k, k.len()));
+
+    assert!(map.size() == 5000);
+    for key in &keys {
+        assert!(map.lookup(key).unwrap() == key.len());
+    }
+    let mut sorted = keys.clone();
+    sorted.sort();
+    assert!(map.iter().map(|(k, _)| k.to_vec()).collect::<Vec<_>>() ==
+            sorted.iter().map(|k| k.as_bytes().to_vec()).collect::<Vec<_>>());
+
+    let halved = keys.iter().step_by(2).fold(map, |map, k| map.remove(k));
+    assert!(halved.size() == 2500);
+    for (i, key) in keys.iter().enumerate() {
+        assert!(halved.contains_key(key) == (i % 2 == 1));
+    }
+}
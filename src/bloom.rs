@@ -0,0 +1,234 @@
+use std::f64::consts::LN_2;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+
+use hasher::FxBuildHasher;
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+
+/// The false positive rate of filters collected from an iterator.
+const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// An immutable Bloom filter: a compact summary of a set of values which can
+/// tell for certain that a value is not in the set, but may mistake a value
+/// which is not in the set for one which is.
+///
+/// A filter is a bitmap of m bits. Each value sets k of them, chosen by
+/// hashing it, and a value is possibly in the set if all k of its bits are
+/// set. A filter is built from all of its values at once, with a `Builder`
+/// which chooses m and k so that the false positive rate is close to the one
+/// asked for; collecting an iterator into a filter uses a rate of 1%.
+///
+/// Filters are never changed once built, so copying one is cheap and shares
+/// its bitmap. Two filters with the same parameters can be combined with
+/// `union` into a filter of the values in either.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::bloom::{BloomFilter, Builder};
+///
+/// let seen: BloomFilter<&str> = vec!["apple", "banana"].into_iter().collect();
+/// assert!(seen.contains(&"apple"));
+///
+/// let more = Builder::new(0.01).expected_items(2).build(vec!["cherry", "damson"]);
+/// let both = seen.union(&more).unwrap();
+/// assert!(both.contains(&"banana") && both.contains(&"damson"));
+/// assert!(seen.union(&Builder::new(0.5).build(vec!["elder"])).is_none());
+/// ```
+#[derive(Debug)]
+pub struct BloomFilterIn<T, P: SharedPointer, S = FxBuildHasher> {
+    bits: Shared<Vec<u64>, P>,
+    /// The number of bits in the filter, m.
+    len: u64,
+    /// The number of bits set for each value, k.
+    hashes: u32,
+    hasher: S,
+    marker: PhantomData<fn(&T)>,
+}
+
+/// A `BloomFilterIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type BloomFilter<T, S = FxBuildHasher> = BloomFilterIn<T, DefaultPointer, S>;
+
+impl<T, S: Clone, P: SharedPointer> Clone for BloomFilterIn<T, P, S> {
+    fn clone(&self) -> Self {
+        BloomFilterIn {
+            bits: self.bits.clone(),
+            len: self.len,
+            hashes: self.hashes,
+            hasher: self.hasher.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Builds a `BloomFilter` sized for a given false positive rate.
+#[derive(Debug, Clone)]
+pub struct BuilderIn<P: SharedPointer, S = FxBuildHasher> {
+    false_positive_rate: f64,
+    expected_items: Option<usize>,
+    hasher: S,
+    marker: PhantomData<P>,
+}
+
+/// A `BuilderIn` of the filters exported at the root of the crate.
+pub type Builder<S = FxBuildHasher> = BuilderIn<DefaultPointer, S>;
+
+impl<P: SharedPointer> BuilderIn<P> {
+    /// Returns a builder for filters which mistake a value for a member with
+    /// probability `false_positive_rate`, using the default hasher.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `false_positive_rate` is not strictly between 0 and 1.
+    pub fn new(false_positive_rate: f64) -> Self {
+        BuilderIn::with_hasher(false_positive_rate, FxBuildHasher::default())
+    }
+}
+
+impl<S: BuildHasher, P: SharedPointer> BuilderIn<P, S> {
+    /// Returns a builder for filters which mistake a value for a member with
+    /// probability `false_positive_rate`, hashing values with `hasher`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `false_positive_rate` is not strictly between 0 and 1.
+    pub fn with_hasher(false_positive_rate: f64, hasher: S) -> Self {
+        assert!(false_positive_rate > 0.0 && false_positive_rate < 1.0,
+                "the false positive rate must be between 0 and 1");
+        BuilderIn {
+            false_positive_rate: false_positive_rate,
+            expected_items: None,
+            hasher: hasher,
+            marker: PhantomData,
+        }
+    }
+    /// Returns a copy of this builder which sizes filters for `count` values,
+    /// however many they are built from, so that filters built from
+    /// different values have the same parameters and can be combined.
+    /// Otherwise filters are sized for the values they are built from.
+    pub fn expected_items(self, count: usize) -> Self {
+        BuilderIn { expected_items: Some(count), ..self }
+    }
+    /// Returns a filter of the values in `values`.
+    pub fn build<T, I>(self, values: I) -> BloomFilterIn<T, P, S>
+        where T: Hash,
+              I: IntoIterator<Item = T>
+    {
+        let hashes: Vec<u64> = values.into_iter()
+                                     .map(|value| hash_of(&self.hasher, &value))
+                                     .collect();
+        let count = self.expected_items.unwrap_or(hashes.len()).max(1) as f64;
+        // The optimal size is -n ln p / (ln 2)^2 bits, with (m / n) ln 2
+        // hashes per value.
+        let len = (-count * self.false_positive_rate.ln() / (LN_2 * LN_2)).ceil().max(64.0);
+        let len = ((len as u64) + 63) & !63;
+        let k = ((len as f64 / count) * LN_2).round().max(1.0) as u32;
+        let mut bits = vec![0; (len / 64) as usize];
+        for hash in hashes {
+            for bit in bits_for(hash, len, k) {
+                bits[(bit / 64) as usize] |= 1 << (bit % 64);
+            }
+        }
+        BloomFilterIn {
+            bits: Shared::new(bits),
+            len: len,
+            hashes: k,
+            hasher: self.hasher,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Hash, S: BuildHasher + Clone, P: SharedPointer> BloomFilterIn<T, P, S> {
+    /// Tests whether `value` may be in this filter. If this returns `false`,
+    /// `value` is certainly not in it.
+    pub fn contains(&self, value: &T) -> bool {
+        let hash = hash_of(&self.hasher, value);
+        bits_for(hash, self.len, self.hashes).all(|bit| {
+            self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0
+        })
+    }
+    /// Returns a filter of the values in either this filter or `other`, or
+    /// `None` if the two have different numbers of bits or of hashes per
+    /// value. The filters must also hash values in the same way, as the
+    /// default hasher does.
+    pub fn union(&self, other: &Self) -> Option<Self> {
+        if self.len != other.len || self.hashes != other.hashes {
+            return None;
+        }
+        let bits = self.bits.iter().zip(other.bits.iter()).map(|(ours, theirs)| ours | theirs);
+        Some(BloomFilterIn { bits: Shared::new(bits.collect()), ..self.clone() })
+    }
+    /// Returns the number of bits in this filter.
+    pub fn num_bits(&self) -> u64 {
+        self.len
+    }
+    /// Returns the number of bits which are set for each value.
+    pub fn num_hashes(&self) -> u32 {
+        self.hashes
+    }
+}
+
+impl<T: Hash, P: SharedPointer> FromIterator<T> for BloomFilterIn<T, P> {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        BuilderIn::new(DEFAULT_FALSE_POSITIVE_RATE).build(values)
+    }
+}
+
+/// Hashes `value`, then scrambles the hash so that every bit of it depends on
+/// every bit of the input, as hashers such as `FxHasher` leave the low bits
+/// of small integers poorly mixed.
+fn hash_of<T: Hash, S: BuildHasher>(hasher: &S, value: &T) -> u64 {
+    let mut hash = hasher.hash_one(value);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Returns the `k` bits out of `len` which are set for a value with the given
+/// hash, derived from its two halves by double hashing.
+fn bits_for(hash: u64, len: u64, k: u32) -> impl Iterator<Item = u64> {
+    let first = hash & 0xffff_ffff;
+    let step = (hash >> 32) | 1;
+    (0..k as u64).map(move |i| first.wrapping_add(i.wrapping_mul(step)) % len)
+}
+
+#[test]
+fn members_are_always_found() {
+    let filter: BloomFilter<u32> = (0..1000).collect();
+
+    assert!((0..1000).all(|i| filter.contains(&i)));
+    assert!(filter.num_bits() >= 9585);
+    assert!(filter.num_hashes() == 7);
+}
+
+#[test]
+fn false_positives_are_near_the_requested_rate() {
+    let filter = Builder::new(0.05).build(0..2000u32);
+
+    let false_positives = (2000..22000u32).filter(|i| filter.contains(i)).count();
+    assert!(false_positives < 1500);
+    assert!(false_positives > 200);
+}
+
+#[test]
+fn unions_contain_both_filters() {
+    let builder = Builder::new(0.01).expected_items(500);
+    let evens = builder.clone().build((0..500).map(|i| i * 2));
+    let odds = builder.build((0..500).map(|i| i * 2 + 1));
+
+    let all = evens.union(&odds).unwrap();
+    assert!((0..1000).all(|i| all.contains(&i)));
+    assert!(evens.union(&Builder::new(0.01).build(0..10)).is_none());
+}
+
+#[test]
+#[should_panic]
+fn rates_must_be_probabilities() {
+    Builder::new(1.5);
+}
@@ -0,0 +1,279 @@
+use collection::Collection;
+use memory::{Census, MemoryUsage};
+use ptr::{DefaultPointer, SharedPointer};
+use seq::{self, SeqIn};
+
+pub use error::CapacityError;
+
+/// An immutable stack which holds at most a fixed number of elements.
+///
+/// When the stack is full, `try_cons` refuses a new element, while `cons`
+/// makes room for it by evicting the element at the bottom of the stack,
+/// which is the one added longest ago. This keeps the last N elements pushed,
+/// as a ring buffer would, but every version stays valid.
+///
+/// The elements are held in a `Seq`, so `cons` and `uncons` take O(1)
+/// amortized time, evictions included.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::bounded::BoundedStack;
+///
+/// let stack = (0..3).fold(BoundedStack::new(3), |stack, i| stack.try_cons(i).unwrap());
+/// let (pushed, evicted) = stack.cons(3);
+///
+/// assert!(stack.is_full() && stack.try_cons(3).is_err());
+/// assert!(evicted == Some(0));
+/// assert!(pushed.iter().cloned().collect::<Vec<_>>() == vec![3, 2, 1]);
+/// assert!(stack.iter().cloned().collect::<Vec<_>>() == vec![2, 1, 0]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BoundedStackIn<T, P: SharedPointer> {
+    elems: SeqIn<T, P>,
+    capacity: u32,
+}
+
+/// A `BoundedStackIn` whose nodes are shared through `DefaultPointer`, as
+/// exported at the root of the crate.
+pub type BoundedStack<T> = BoundedStackIn<T, DefaultPointer>;
+
+impl<T: Clone, P: SharedPointer> BoundedStackIn<T, P> {
+    /// Returns an empty stack which can hold up to `capacity` elements.
+    pub fn new(capacity: u32) -> Self {
+        BoundedStackIn { elems: SeqIn::empty(), capacity: capacity }
+    }
+    /// Returns the greatest number of elements this stack can hold.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+    /// Returns the number of elements in this stack.
+    pub fn size(&self) -> u32 {
+        self.elems.size()
+    }
+    /// Tests whether this stack is empty.
+    pub fn is_empty(&self) -> bool {
+        self.elems.is_empty()
+    }
+    /// Tests whether this stack holds as many elements as it can.
+    pub fn is_full(&self) -> bool {
+        self.elems.size() >= self.capacity
+    }
+    /// Returns a copy of this stack with `value` as its head, and the element
+    /// evicted from its bottom to make room, if it was full.
+    ///
+    /// A stack with no capacity evicts `value` itself.
+    pub fn cons(&self, value: T) -> (Self, Option<T>) {
+        if self.capacity == 0 {
+            return (self.clone(), Some(value));
+        }
+        let (elems, evicted) = if self.is_full() {
+            let (evicted, rest) = self.elems.pop_back().unwrap();
+            (rest, Some(evicted))
+        } else {
+            (self.elems.clone(), None)
+        };
+        (BoundedStackIn { elems: elems.push_front(value), capacity: self.capacity }, evicted)
+    }
+    /// Returns a copy of this stack with `value` as its head.
+    ///
+    /// # Failures
+    ///
+    /// Returns `CapacityError::Full` if this stack is full.
+    pub fn try_cons(&self, value: T) -> Result<Self, CapacityError> {
+        if self.is_full() {
+            return Err(CapacityError::Full);
+        }
+        Ok(BoundedStackIn { elems: self.elems.push_front(value), capacity: self.capacity })
+    }
+    /// Returns the head of this stack, or `None` if it is empty.
+    pub fn head(&self) -> Option<&T> {
+        self.elems.front()
+    }
+    /// Returns the head of this stack and the stack without it, or `None` if
+    /// it is empty.
+    pub fn uncons(&self) -> Option<(T, Self)> {
+        self.elems
+            .pop_front()
+            .map(|(value, elems)| (value, BoundedStackIn { elems: elems, capacity: self.capacity }))
+    }
+    /// Returns an iterator over the elements of this stack, from the head to
+    /// the bottom.
+    pub fn iter(&self) -> seq::Iter<'_, T, P> {
+        self.elems.iter()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Collection for BoundedStackIn<T, P> {
+    type Iter<'a> = seq::Iter<'a, T, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> seq::Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T, P: SharedPointer> MemoryUsage for BoundedStackIn<T, P> {
+    fn census(&self, census: &mut Census) {
+        self.elems.census(census)
+    }
+}
+
+/// An immutable FIFO queue which holds at most a fixed number of elements.
+///
+/// When the queue is full, `try_snoc` refuses a new element, while `snoc`
+/// makes room for it by evicting the element at the front of the queue,
+/// which is the one added longest ago. This keeps the last N elements added,
+/// as a ring buffer would, but every version stays valid.
+///
+/// The elements are held in a `Seq`, so `snoc` and `pop_front` take O(1)
+/// amortized time, evictions included.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::bounded::BoundedQueue;
+///
+/// let events = (0..5).fold(BoundedQueue::new(3), |events, i| events.snoc(i).0);
+/// let (oldest, rest) = events.pop_front().unwrap();
+///
+/// assert!(events.iter().cloned().collect::<Vec<_>>() == vec![2, 3, 4]);
+/// assert!(oldest == 2 && rest.try_snoc(5).is_ok());
+/// ```
+#[derive(Debug, Clone)]
+pub struct BoundedQueueIn<T, P: SharedPointer> {
+    elems: SeqIn<T, P>,
+    capacity: u32,
+}
+
+/// A `BoundedQueueIn` whose nodes are shared through `DefaultPointer`, as
+/// exported at the root of the crate.
+pub type BoundedQueue<T> = BoundedQueueIn<T, DefaultPointer>;
+
+impl<T: Clone, P: SharedPointer> BoundedQueueIn<T, P> {
+    /// Returns an empty queue which can hold up to `capacity` elements.
+    pub fn new(capacity: u32) -> Self {
+        BoundedQueueIn { elems: SeqIn::empty(), capacity: capacity }
+    }
+    /// Returns the greatest number of elements this queue can hold.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+    /// Returns the number of elements in this queue.
+    pub fn size(&self) -> u32 {
+        self.elems.size()
+    }
+    /// Tests whether this queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.elems.is_empty()
+    }
+    /// Tests whether this queue holds as many elements as it can.
+    pub fn is_full(&self) -> bool {
+        self.elems.size() >= self.capacity
+    }
+    /// Returns a copy of this queue with `value` added at the back, and the
+    /// element evicted from its front to make room, if it was full.
+    ///
+    /// A queue with no capacity evicts `value` itself.
+    pub fn snoc(&self, value: T) -> (Self, Option<T>) {
+        if self.capacity == 0 {
+            return (self.clone(), Some(value));
+        }
+        let (elems, evicted) = if self.is_full() {
+            let (evicted, rest) = self.elems.pop_front().unwrap();
+            (rest, Some(evicted))
+        } else {
+            (self.elems.clone(), None)
+        };
+        (BoundedQueueIn { elems: elems.push_back(value), capacity: self.capacity }, evicted)
+    }
+    /// Returns a copy of this queue with `value` added at the back.
+    ///
+    /// # Failures
+    ///
+    /// Returns `CapacityError::Full` if this queue is full.
+    pub fn try_snoc(&self, value: T) -> Result<Self, CapacityError> {
+        if self.is_full() {
+            return Err(CapacityError::Full);
+        }
+        Ok(BoundedQueueIn { elems: self.elems.push_back(value), capacity: self.capacity })
+    }
+    /// Returns the element at the front of this queue, or `None` if it is
+    /// empty.
+    pub fn front(&self) -> Option<&T> {
+        self.elems.front()
+    }
+    /// Returns the element at the front of this queue and the queue without
+    /// it, or `None` if it is empty.
+    pub fn pop_front(&self) -> Option<(T, Self)> {
+        self.elems
+            .pop_front()
+            .map(|(value, elems)| (value, BoundedQueueIn { elems: elems, capacity: self.capacity }))
+    }
+    /// Returns an iterator over the elements of this queue, from front to
+    /// back.
+    pub fn iter(&self) -> seq::Iter<'_, T, P> {
+        self.elems.iter()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Collection for BoundedQueueIn<T, P> {
+    type Iter<'a> = seq::Iter<'a, T, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> seq::Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T, P: SharedPointer> MemoryUsage for BoundedQueueIn<T, P> {
+    fn census(&self, census: &mut Census) {
+        self.elems.census(census)
+    }
+}
+
+#[test]
+fn stack_evicts_from_the_bottom_or_refuses_when_full() {
+    let elements = |stack: &BoundedStack<u32>| stack.iter().cloned().collect::<Vec<_>>();
+    let mut stack = BoundedStack::new(40);
+    let mut evictions = Vec::new();
+    for i in 0..100 {
+        let (pushed, evicted) = stack.cons(i);
+        evictions.extend(evicted);
+        stack = pushed;
+    }
+
+    assert!(evictions == (0..60).collect::<Vec<_>>());
+    assert!(elements(&stack) == (60..100).rev().collect::<Vec<_>>());
+    assert!(stack.try_cons(100).unwrap_err() == CapacityError::Full);
+    let (head, popped) = stack.uncons().unwrap();
+    assert!(head == 99 && elements(&popped.try_cons(7).unwrap())[..2] == [7, 98]);
+    assert!(elements(&stack) == (60..100).rev().collect::<Vec<_>>());
+}
+
+#[test]
+fn queue_evicts_from_the_front_or_refuses_when_full() {
+    let elements = |queue: &BoundedQueue<u32>| queue.iter().cloned().collect::<Vec<_>>();
+    let queue = (0..3).fold(BoundedQueue::new(3), |queue, i| queue.try_snoc(i).unwrap());
+
+    assert!(queue.try_snoc(3).unwrap_err() == CapacityError::Full);
+    let (later, evicted) = queue.snoc(3);
+    assert!(evicted == Some(0) && elements(&later) == vec![1, 2, 3]);
+    assert!(elements(&queue) == vec![0, 1, 2]);
+    let (front, rest) = later.pop_front().unwrap();
+    assert!(front == 1 && !rest.is_full() && elements(&rest.try_snoc(4).unwrap()) == vec![2, 3, 4]);
+}
+
+#[test]
+fn structures_with_no_capacity_evict_every_value() {
+    let stack: BoundedStack<u32> = BoundedStack::new(0);
+    let queue: BoundedQueue<u32> = BoundedQueue::new(0);
+
+    assert!(stack.cons(1).1 == Some(1) && stack.cons(1).0.is_empty());
+    assert!(queue.snoc(1).1 == Some(1) && queue.snoc(1).0.is_empty());
+    assert!(stack.try_cons(1).is_err() && queue.try_snoc(1).is_err());
+}
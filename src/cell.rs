@@ -0,0 +1,1035 @@
+use std::any::Any;
+use std::cmp;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use arc_swap::ArcSwap;
+
+use seq::Seq;
+
+/// A holder for the current version of a persistent structure, which can be
+/// shared between threads and replaced atomically.
+///
+/// Readers take a snapshot with `load`, which only clones an `Arc`, and can
+/// then read the snapshot for as long as they like without blocking anyone:
+/// since the structure is persistent, later updates leave it as it was.
+/// Writers build the next version from the current one with `update`, which
+/// installs it only if no other writer got there first, and otherwise
+/// builds it again from the version that did. This is compare-and-swap on
+/// the pointer to the current version.
+///
+/// The pointer is an `ArcSwap`, so neither loading nor replacing it takes a
+/// lock, and readers never wait for writers. The alternative of wrapping the
+/// structure itself in a `Mutex` locks out readers for the whole of every
+/// update.
+///
+/// Each version installed is numbered with a generation, starting from zero
+/// for the version the cell was created with. Checking whether the cell has
+/// moved on from a generation is a single atomic load, and a reader which
+/// wants to react to changes can block until one happens with
+/// `wait_for_change` or `Subscription::wait`, rather than polling. Only these
+/// waits use a lock, which writers take just long enough to wake the readers
+/// waiting on it, and only if there are any.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use functional_datastructures::cell::PersistentCell;
+/// use functional_datastructures::hamt::HamtMap;
+///
+/// let cell = Arc::new(PersistentCell::new(HamtMap::new()));
+/// let before = cell.load();
+/// let writers: Vec<_> = (0..4)
+///                           .map(|i| {
+///                               let cell = cell.clone();
+///                               thread::spawn(move || {
+///                                   for j in 0..100 {
+///                                       cell.update(|map| map.insert(i * 100 + j, i));
+///                                   }
+///                               })
+///                           })
+///                           .collect();
+/// for writer in writers {
+///     writer.join().unwrap();
+/// }
+///
+/// assert!(cell.load().size() == 400);
+/// assert!(before.is_empty());
+/// ```
+pub struct PersistentCell<T> {
+    current: ArcSwap<Version<T>>,
+    /// The number of readers blocked in `wait_for_change`, which writers
+    /// check to skip taking `waiting` when there are none.
+    waiters: AtomicUsize,
+    /// Held by readers while they check for a change and wait on `changed`,
+    /// and by writers while they notify it.
+    waiting: Mutex<()>,
+    /// Notified whenever a version is installed while a reader waits.
+    changed: Condvar,
+}
+
+/// A version of the structure held by a `PersistentCell`.
+struct Version<T> {
+    value: Arc<T>,
+    generation: u64,
+    /// Whether a transaction committing over this cell has claimed it, in
+    /// which case no other writer may replace it until the transaction
+    /// installs its write or releases it.
+    claimed: bool,
+}
+
+impl<T> Version<T> {
+    fn new(value: Arc<T>, generation: u64, claimed: bool) -> Arc<Self> {
+        Arc::new(Version {
+            value: value,
+            generation: generation,
+            claimed: claimed,
+        })
+    }
+}
+
+/// A snapshot of a version of a `PersistentCell`, along with its
+/// generation, returned by `PersistentCell::read` and the methods which wait
+/// for changes.
+///
+/// Holding a guard keeps its version alive but does not stop newer ones
+/// from being installed; it derefs to the version it was taken of.
+pub struct ReadGuard<'a, T: 'a> {
+    cell: &'a PersistentCell<T>,
+    value: Arc<T>,
+    generation: u64,
+}
+
+/// A reader of a `PersistentCell` which remembers the last generation it
+/// has seen, returned by `PersistentCell::subscribe`.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use functional_datastructures::cell::PersistentCell;
+///
+/// let config = Arc::new(PersistentCell::new(vec!["verbose"]));
+/// let mut subscription = config.subscribe();
+/// assert!(subscription.poll().is_none());
+///
+/// let writer = {
+///     let config = config.clone();
+///     thread::spawn(move || config.store(vec!["quiet"]))
+/// };
+/// let latest = subscription.wait();
+/// assert!(*latest == vec!["quiet"] && latest.generation() == 1);
+/// writer.join().unwrap();
+/// ```
+pub struct Subscription<'a, T: 'a> {
+    cell: &'a PersistentCell<T>,
+    seen: u64,
+}
+
+impl<T> PersistentCell<T> {
+    /// Returns a cell holding `value`.
+    pub fn new(value: T) -> Self {
+        PersistentCell {
+            current: ArcSwap::new(Version::new(Arc::new(value), 0, false)),
+            waiters: AtomicUsize::new(0),
+            waiting: Mutex::new(()),
+            changed: Condvar::new(),
+        }
+    }
+    /// Returns a snapshot of the current version.
+    pub fn load(&self) -> Arc<T> {
+        self.current.load().value.clone()
+    }
+    /// Returns the generation of the current version.
+    pub fn generation(&self) -> u64 {
+        self.current.load().generation
+    }
+    /// Returns a snapshot of the current version, along with its
+    /// generation.
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        self.guard(&self.current.load())
+    }
+    /// Blocks until the cell holds a version newer than `generation`, and
+    /// returns a snapshot of it. Returns at once if it already does.
+    pub fn wait_for_change(&self, generation: u64) -> ReadGuard<'_, T> {
+        self.wait_until(generation, None).expect("waits without a deadline end in a change")
+    }
+    /// Blocks until the cell holds a version newer than `generation`, as for
+    /// `wait_for_change`, but for no longer than `timeout`. Returns `None`
+    /// if no newer version was installed in that time.
+    pub fn wait_for_change_timeout(&self,
+                                   generation: u64,
+                                   timeout: Duration)
+                                   -> Option<ReadGuard<'_, T>> {
+        self.wait_until(generation, Some(Instant::now() + timeout))
+    }
+    /// Returns a subscription which has seen the current generation, and
+    /// so reports only the versions installed after this call.
+    pub fn subscribe(&self) -> Subscription<'_, T> {
+        Subscription {
+            cell: self,
+            seen: self.generation(),
+        }
+    }
+    /// Makes `value` the current version, whatever it was before.
+    pub fn store(&self, value: T) {
+        self.swap(value);
+    }
+    /// Makes `value` the current version, and returns the version it
+    /// replaces.
+    pub fn swap(&self, value: T) -> Arc<T> {
+        let value = Arc::new(value);
+        let mut current = self.unclaimed();
+        loop {
+            let next = Version::new(value.clone(), current.generation + 1, false);
+            match self.replace(&current, next) {
+                Ok(()) => return current.value.clone(),
+                Err(latest) => current = latest,
+            }
+        }
+    }
+    /// Makes `new` the current version if `expected` still is, which is to
+    /// say if it is the same snapshot, not just an equal value.
+    ///
+    /// # Failures
+    ///
+    /// Returns `new` back, along with a snapshot of the current version, if
+    /// another version has been installed since `expected` was loaded.
+    pub fn compare_and_swap(&self, expected: &Arc<T>, new: T) -> Result<Arc<T>, (T, Arc<T>)> {
+        let new = Arc::new(new);
+        let mut current = self.unclaimed();
+        while Arc::ptr_eq(&current.value, expected) {
+            let next = Version::new(new.clone(), current.generation + 1, false);
+            match self.replace(&current, next) {
+                Ok(()) => return Ok(new),
+                Err(latest) => current = latest,
+            }
+        }
+        // `new` was only ever shared with versions which were not
+        // installed, and those have all been dropped.
+        let new = Arc::try_unwrap(new).ok().expect("uninstalled versions are not shared");
+        Err((new, current.value.clone()))
+    }
+    /// Replaces the current version with `f` of it, and returns the version
+    /// installed.
+    ///
+    /// `f` is called without the cell locked, so if another thread installs
+    /// a version in the meantime, `f` is called again on that one. It should
+    /// therefore have no effects beyond building its result.
+    pub fn update<F>(&self, mut f: F) -> Arc<T>
+        where F: FnMut(&T) -> T
+    {
+        let mut expected = self.load();
+        loop {
+            match self.compare_and_swap(&expected, f(&expected)) {
+                Ok(installed) => return installed,
+                Err((_, current)) => expected = current,
+            }
+        }
+    }
+    /// Returns the current version, which is only unwrapped from its `Arc`
+    /// if no snapshot of it is still held.
+    pub fn into_inner(self) -> Arc<T> {
+        self.current.into_inner().value.clone()
+    }
+    /// Returns the current version once no transaction has it claimed,
+    /// yielding to the committing thread until then. Commits hold their
+    /// claims only while installing what they have already built.
+    fn unclaimed(&self) -> Arc<Version<T>> {
+        loop {
+            let current = self.current.load_full();
+            if !current.claimed {
+                return current;
+            }
+            thread::yield_now();
+        }
+    }
+    /// Installs `next` in place of `current`, waking any waiting readers if
+    /// it is a new generation.
+    ///
+    /// # Failures
+    ///
+    /// Returns the version installed in the meantime, once no transaction
+    /// has it claimed, if `current` has been replaced.
+    fn replace(&self, current: &Arc<Version<T>>, next: Arc<Version<T>>)
+               -> Result<(), Arc<Version<T>>> {
+        let generation = next.generation;
+        let previous = self.current.compare_and_swap(current, next);
+        if !Arc::ptr_eq(&previous, current) {
+            return Err(self.unclaimed());
+        }
+        if generation != current.generation {
+            self.installed();
+        }
+        Ok(())
+    }
+    /// Wakes any readers waiting for a change, once a version with a new
+    /// generation has been installed.
+    fn installed(&self) {
+        // Pairs with the fence in `wait_until`: either this sees the waiter,
+        // or the waiter sees the new version.
+        atomic::fence(Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _waiting = self.waiting.lock().unwrap();
+            self.changed.notify_all();
+        }
+    }
+    /// Blocks until the cell holds a version newer than `generation`, or
+    /// until `deadline` if there is one.
+    fn wait_until(&self, generation: u64, deadline: Option<Instant>) -> Option<ReadGuard<'_, T>> {
+        let mut waiting = self.waiting.lock().unwrap();
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let changed = loop {
+            atomic::fence(Ordering::SeqCst);
+            let current = self.current.load();
+            if current.generation > generation {
+                break Some(self.guard(&current));
+            }
+            waiting = match deadline {
+                None => self.changed.wait(waiting).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break None;
+                    }
+                    self.changed.wait_timeout(waiting, deadline - now).unwrap().0
+                }
+            };
+        };
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+        changed
+    }
+    /// Returns a guard for `version`.
+    fn guard(&self, version: &Version<T>) -> ReadGuard<'_, T> {
+        ReadGuard {
+            cell: self,
+            value: version.value.clone(),
+            generation: version.generation,
+        }
+    }
+}
+
+impl<'a, T> ReadGuard<'a, T> {
+    /// Returns the generation of this version.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    /// Tests whether this is still the current version of its cell, without
+    /// locking the cell.
+    pub fn is_current(&self) -> bool {
+        self.cell.generation() == self.generation
+    }
+    /// Returns the snapshot held by this guard, which can outlive it.
+    pub fn snapshot(&self) -> &Arc<T> {
+        &self.value
+    }
+}
+
+impl<'a, T> Deref for ReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<'a, T: fmt::Debug> fmt::Debug for ReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReadGuard")
+         .field("value", &self.value)
+         .field("generation", &self.generation)
+         .finish()
+    }
+}
+
+impl<'a, T> Subscription<'a, T> {
+    /// Tests whether a version has been installed since the last one this
+    /// subscription returned, without locking the cell.
+    pub fn changed(&self) -> bool {
+        self.cell.generation() != self.seen
+    }
+    /// Returns a snapshot of the current version if it is newer than the
+    /// last one this subscription returned, and otherwise `None`.
+    pub fn poll(&mut self) -> Option<ReadGuard<'a, T>> {
+        if !self.changed() {
+            return None;
+        }
+        let guard = self.cell.read();
+        self.seen = guard.generation;
+        Some(guard)
+    }
+    /// Blocks until a version newer than the last one this subscription
+    /// returned has been installed, and returns a snapshot of the current
+    /// version. Versions installed in quick succession may be skipped.
+    pub fn wait(&mut self) -> ReadGuard<'a, T> {
+        let guard = self.cell.wait_for_change(self.seen);
+        self.seen = guard.generation;
+        guard
+    }
+}
+
+impl<T: Default> Default for PersistentCell<T> {
+    fn default() -> Self {
+        PersistentCell::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PersistentCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("PersistentCell").field(&self.load()).finish()
+    }
+}
+
+/// Runs `f` as a transaction over any number of `PersistentCell`s, and
+/// returns its result once the transaction has committed.
+///
+/// Within `f`, `Transaction::read` takes snapshots of cells and
+/// `Transaction::write` gives them new versions. When `f` returns, the
+/// cells it used are claimed together and the transaction commits only if
+/// none of those it read has changed since, in which case all of its writes
+/// are installed at once, each as a new generation of its cell. Otherwise
+/// its writes are thrown away and `f` is run again. Other threads therefore
+/// see either all of a transaction's writes or none of them, and a
+/// transaction never commits writes computed from versions which have
+/// already been replaced.
+///
+/// An attempt may read a cell which another transaction has changed since
+/// one read earlier, and so see versions of the two which were never
+/// current together; such an attempt never commits. Like the closure given
+/// to `PersistentCell::update`, `f` should therefore have no effects beyond
+/// its reads, writes and result.
+///
+/// Cells are claimed in order of address when committing, so transactions
+/// over overlapping sets of cells cannot deadlock. A claimed cell can still
+/// be read; other writers to it wait for the commit to install its writes,
+/// which have all been built by then.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use functional_datastructures::cell::{atomically, PersistentCell};
+/// use functional_datastructures::hamt::HamtMap;
+///
+/// type Accounts = PersistentCell<HamtMap<&'static str, i32>>;
+///
+/// fn transfer(from: &Accounts, to: &Accounts, amount: i32) {
+///     atomically(|transaction| {
+///         let debited = transaction.read(from);
+///         let credited = transaction.read(to);
+///         let balance = debited.get(&"alice").unwrap() - amount;
+///         transaction.write(from, debited.insert("alice", balance));
+///         let balance = credited.get(&"alice").unwrap() + amount;
+///         transaction.write(to, credited.insert("alice", balance));
+///     })
+/// }
+///
+/// let checking = Arc::new(PersistentCell::new(HamtMap::new().insert("alice", 100)));
+/// let savings = Arc::new(PersistentCell::new(HamtMap::new().insert("alice", 0)));
+/// let transfers: Vec<_> = (0..10)
+///                             .map(|_| {
+///                                 let (checking, savings) = (checking.clone(), savings.clone());
+///                                 thread::spawn(move || transfer(&checking, &savings, 10))
+///                             })
+///                             .collect();
+/// for handle in transfers {
+///     handle.join().unwrap();
+/// }
+///
+/// assert!(checking.load().get(&"alice") == Some(&0));
+/// assert!(savings.load().get(&"alice") == Some(&100));
+/// ```
+pub fn atomically<'a, R, F>(mut f: F) -> R
+    where F: FnMut(&mut Transaction<'a>) -> R
+{
+    loop {
+        let mut transaction = Transaction {
+            accesses: Vec::new(),
+            stale: false,
+        };
+        let result = f(&mut transaction);
+        if transaction.commit() {
+            return result;
+        }
+    }
+}
+
+/// The reads and writes of one attempt at a transaction run by
+/// `atomically`.
+pub struct Transaction<'a> {
+    /// The cells read or written so far, in order of address, which is the
+    /// order they are claimed in when committing.
+    accesses: Vec<Box<dyn Access<'a> + 'a>>,
+    /// Whether a cell read earlier in this attempt has changed since, so
+    /// that the attempt cannot commit.
+    stale: bool,
+}
+
+/// A snapshot or a new version held by a transaction, with its type erased
+/// so that cells of different types can be used together.
+type Value = Arc<dyn Any + Send + Sync>;
+
+/// A cell used by a transaction, with its type erased.
+trait Access<'a> {
+    fn address(&self) -> usize;
+    /// Returns the version of the cell this transaction sees.
+    fn value(&self) -> Value;
+    /// Replaces the version this transaction sees, and will install.
+    fn write(&mut self, value: Value);
+    /// Tests whether the cell was read and has changed since.
+    fn is_stale(&self) -> bool;
+    /// Claims the cell for committing, unless it was read and has changed
+    /// since.
+    fn claim(&self) -> Option<Box<dyn Claim + 'a>>;
+}
+
+/// A cell claimed by a committing transaction, with its type erased.
+trait Claim {
+    /// Installs the version this transaction wrote, if it wrote one, and
+    /// gives up the claim.
+    fn install(&mut self);
+    /// Gives up the claim, leaving the cell as it was.
+    fn release(&mut self);
+}
+
+struct CellAccess<'a, T: 'a> {
+    cell: &'a PersistentCell<T>,
+    value: Arc<T>,
+    /// The generation read, if the cell was read before being written.
+    read: Option<u64>,
+    written: bool,
+}
+
+struct ClaimedCell<'a, T: 'a> {
+    cell: &'a PersistentCell<T>,
+    /// The version installed to claim the cell, which holds the same value
+    /// and generation as the one it replaced.
+    claimed: Arc<Version<T>>,
+    written: Option<Arc<T>>,
+}
+
+impl<'a> Transaction<'a> {
+    /// Returns the version of `cell` this transaction sees: the one it
+    /// wrote, if it has written one, and otherwise a snapshot of the
+    /// version current when it first read the cell.
+    pub fn read<T: Send + Sync + 'static>(&mut self, cell: &'a PersistentCell<T>) -> Arc<T> {
+        let index = match self.find(cell) {
+            Ok(index) => index,
+            Err(index) => {
+                let guard = cell.read();
+                self.stale = self.stale || self.accesses.iter().any(|access| access.is_stale());
+                self.accesses.insert(index,
+                                     Box::new(CellAccess {
+                                         cell: cell,
+                                         value: guard.value.clone(),
+                                         read: Some(guard.generation),
+                                         written: false,
+                                     }));
+                index
+            }
+        };
+        self.accesses[index].value().downcast().expect("a cell holds one type")
+    }
+    /// Makes `value` the version of `cell` this transaction sees, and will
+    /// install if it commits.
+    pub fn write<T: Send + Sync + 'static>(&mut self, cell: &'a PersistentCell<T>, value: T) {
+        let value = Arc::new(value);
+        match self.find(cell) {
+            Ok(index) => self.accesses[index].write(value),
+            Err(index) => {
+                self.accesses.insert(index,
+                                     Box::new(CellAccess {
+                                         cell: cell,
+                                         value: value,
+                                         read: None,
+                                         written: true,
+                                     }))
+            }
+        }
+    }
+    /// Writes `f` of the version of `cell` this transaction sees, as read
+    /// by `read`.
+    pub fn modify<T, F>(&mut self, cell: &'a PersistentCell<T>, f: F)
+        where T: Send + Sync + 'static,
+              F: FnOnce(&T) -> T
+    {
+        let value = f(&self.read(cell));
+        self.write(cell, value);
+    }
+    /// Returns the index of `cell` among the cells used so far, or where it
+    /// belongs if it has not been used.
+    fn find<T>(&self, cell: &PersistentCell<T>) -> Result<usize, usize> {
+        let address = cell as *const PersistentCell<T> as usize;
+        self.accesses.binary_search_by_key(&address, |access| access.address())
+    }
+    /// Tries to commit this attempt, and returns whether it did.
+    fn commit(self) -> bool {
+        if self.stale {
+            return false;
+        }
+        let mut claimed = Vec::with_capacity(self.accesses.len());
+        for access in &self.accesses {
+            match access.claim() {
+                Some(claim) => claimed.push(claim),
+                None => {
+                    for claim in &mut claimed {
+                        claim.release();
+                    }
+                    return false;
+                }
+            }
+        }
+        for claim in &mut claimed {
+            claim.install();
+        }
+        true
+    }
+}
+
+impl<'a, T: Send + Sync + 'static> Access<'a> for CellAccess<'a, T> {
+    fn address(&self) -> usize {
+        self.cell as *const PersistentCell<T> as usize
+    }
+    fn value(&self) -> Value {
+        self.value.clone()
+    }
+    fn write(&mut self, value: Value) {
+        self.value = value.downcast().expect("a cell holds one type");
+        self.written = true;
+    }
+    fn is_stale(&self) -> bool {
+        self.read.is_some_and(|generation| self.cell.generation() != generation)
+    }
+    fn claim(&self) -> Option<Box<dyn Claim + 'a>> {
+        let mut current = self.cell.unclaimed();
+        loop {
+            if self.read.is_some_and(|generation| current.generation != generation) {
+                return None;
+            }
+            let claimed = Version::new(current.value.clone(), current.generation, true);
+            match self.cell.replace(&current, claimed.clone()) {
+                Ok(()) => {
+                    return Some(Box::new(ClaimedCell {
+                        cell: self.cell,
+                        claimed: claimed,
+                        written: if self.written {
+                            Some(self.value.clone())
+                        } else {
+                            None
+                        },
+                    }))
+                }
+                Err(latest) => current = latest,
+            }
+        }
+    }
+}
+
+impl<'a, T> Claim for ClaimedCell<'a, T> {
+    // No other writer replaces a claimed version, so these can store the
+    // next one outright.
+    fn install(&mut self) {
+        match self.written.take() {
+            Some(value) => {
+                let generation = self.claimed.generation + 1;
+                self.cell.current.store(Version::new(value, generation, false));
+                self.cell.installed();
+            }
+            None => self.release(),
+        }
+    }
+    fn release(&mut self) {
+        let claimed = &self.claimed;
+        self.cell.current.store(Version::new(claimed.value.clone(), claimed.generation, false));
+    }
+}
+
+/// A first-in, first-out queue which any number of threads can push to and
+/// pop from, kept as a persistent `Seq` in a `PersistentCell`.
+///
+/// Each push or pop builds the next version of the queue and installs it
+/// with compare-and-swap, as `PersistentCell::update` does, so pushes and
+/// pops never take a lock: a thread only ever retries because another one
+/// installed a version first, which means that one made progress. Only
+/// `pop_wait` blocks, and only while the queue is empty. Contention comes
+/// from threads racing to install versions; `push_all` and `pop_batch` move
+/// many values with one swap to keep it down.
+///
+/// Unlike a `VecDeque` behind a `Mutex`, the queue can be inspected without
+/// stopping anyone: `snapshot` returns its contents at one moment as a
+/// `Seq`, which can be iterated over at leisure while pushes and pops carry
+/// on.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+/// use functional_datastructures::cell::SharedQueue;
+///
+/// let queue = Arc::new(SharedQueue::new());
+/// let producers: Vec<_> = (0..4)
+///                             .map(|i| {
+///                                 let queue = queue.clone();
+///                                 thread::spawn(move || queue.push_all(i * 100..i * 100 + 100))
+///                             })
+///                             .collect();
+/// let mut popped = Vec::new();
+/// while popped.len() < 400 {
+///     popped.push(queue.pop_wait());
+/// }
+/// for producer in producers {
+///     producer.join().unwrap();
+/// }
+///
+/// popped.sort();
+/// assert!(popped == (0..400).collect::<Vec<_>>());
+/// assert!(queue.is_empty());
+/// ```
+pub struct SharedQueue<T> {
+    cell: PersistentCell<Seq<T>>,
+}
+
+impl<T: Clone> SharedQueue<T> {
+    /// Returns an empty queue.
+    pub fn new() -> Self {
+        SharedQueue { cell: PersistentCell::new(Seq::empty()) }
+    }
+    /// Adds `value` to the back of the queue.
+    pub fn push(&self, value: T) {
+        self.cell.update(|queue| queue.push_back(value.clone()));
+    }
+    /// Adds `values` to the back of the queue, in order and all at once, so
+    /// that no other push comes between them.
+    pub fn push_all<I: IntoIterator<Item = T>>(&self, values: I) {
+        let batch = values.into_iter().fold(Seq::empty(), |batch, value| batch.push_back(value));
+        if !batch.is_empty() {
+            self.cell.update(|queue| queue.concat(&batch));
+        }
+    }
+    /// Removes and returns the value at the front of the queue, or `None` if
+    /// the queue is empty.
+    pub fn pop(&self) -> Option<T> {
+        self.pop_batch(1).pop()
+    }
+    /// Removes and returns up to `max` values from the front of the queue at
+    /// once, front first. Returns an empty vector if the queue is empty.
+    pub fn pop_batch(&self, max: u32) -> Vec<T> {
+        let mut current = self.cell.load();
+        loop {
+            if current.is_empty() || max == 0 {
+                return Vec::new();
+            }
+            let (taken, rest) = current.split_at(cmp::min(max, current.size()));
+            match self.cell.compare_and_swap(&current, rest) {
+                Ok(_) => return taken.iter().cloned().collect(),
+                Err((_, latest)) => current = latest,
+            }
+        }
+    }
+    /// Removes and returns the value at the front of the queue, blocking
+    /// until there is one if the queue is empty.
+    pub fn pop_wait(&self) -> T {
+        loop {
+            let generation = self.cell.generation();
+            if let Some(value) = self.pop() {
+                return value;
+            }
+            self.cell.wait_for_change(generation);
+        }
+    }
+    /// Returns the contents of the queue at this moment, front first.
+    pub fn snapshot(&self) -> Arc<Seq<T>> {
+        self.cell.load()
+    }
+    /// Returns the number of values in the queue.
+    pub fn size(&self) -> u32 {
+        self.cell.load().size()
+    }
+    /// Tests whether the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.cell.load().is_empty()
+    }
+}
+
+impl<T: Clone> Default for SharedQueue<T> {
+    fn default() -> Self {
+        SharedQueue::new()
+    }
+}
+
+impl<T: Clone + fmt::Debug> fmt::Debug for SharedQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.cell.load().iter()).finish()
+    }
+}
+
+#[cfg(test)]
+use std::sync::Barrier;
+#[cfg(test)]
+use hamt::HamtMap;
+#[cfg(test)]
+use set::{Map, Set, Tree};
+
+#[test]
+fn snapshots_are_unaffected_by_later_versions() {
+    let cell = PersistentCell::new(Tree::empty().insert(1));
+    let snapshot = cell.load();
+    cell.update(|tree| tree.insert(2));
+    let replaced = cell.swap(Tree::empty());
+
+    assert!(snapshot.member(1) && !snapshot.member(2));
+    assert!(replaced.member(2));
+    assert!(!cell.load().member(1));
+}
+
+#[test]
+fn compare_and_swap_fails_on_stale_snapshots() {
+    let cell = PersistentCell::new(0);
+    let stale = cell.load();
+    cell.store(1);
+
+    let (rejected, current) = cell.compare_and_swap(&stale, 2).unwrap_err();
+    assert!(rejected == 2 && *current == 1);
+    assert!(*cell.compare_and_swap(&current, 3).unwrap() == 3);
+    assert!(*cell.into_inner() == 3);
+}
+
+#[test]
+fn concurrent_updates_are_not_lost() {
+    let cell = Arc::new(PersistentCell::new(HamtMap::new()));
+    let threads: Vec<_> = (0..8)
+                              .map(|i| {
+                                  let cell = cell.clone();
+                                  thread::spawn(move || {
+                                      for j in 0..200 {
+                                          cell.update(|map| {
+                                              map.insert(j, map.get(&j).map_or(i, |v| v + i))
+                                          });
+                                      }
+                                  })
+                              })
+                              .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let map = cell.load();
+    assert!(map.size() == 200);
+    assert!((0..200).all(|j| map.get(&j) == Some(&28)));
+}
+
+#[test]
+fn generations_count_installed_versions() {
+    let cell = PersistentCell::new(0);
+    let first = cell.read();
+    assert!(first.generation() == 0 && first.is_current());
+
+    cell.store(1);
+    cell.update(|value| value + 1);
+    let stale = cell.load();
+    cell.store(3);
+    assert!(cell.compare_and_swap(&stale, 4).is_err());
+
+    let latest = cell.read();
+    assert!(!first.is_current() && *first == 0);
+    assert!(latest.generation() == 3 && *latest == 3 && cell.generation() == 3);
+}
+
+#[test]
+fn waiters_wake_on_new_versions() {
+    let cell = Arc::new(PersistentCell::new(0));
+    let barrier = Arc::new(Barrier::new(2));
+    let writer = {
+        let cell = cell.clone();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            barrier.wait();
+            cell.store(1);
+        })
+    };
+
+    assert!(cell.wait_for_change_timeout(0, Duration::from_millis(10)).is_none());
+    barrier.wait();
+    let changed = cell.wait_for_change(0);
+    assert!(*changed == 1 && changed.generation() == 1);
+    assert!(cell.wait_for_change(0).generation() == 1);
+    writer.join().unwrap();
+}
+
+#[test]
+fn subscriptions_report_each_change_once() {
+    let cell = PersistentCell::new(Tree::empty());
+    let mut subscription = cell.subscribe();
+    assert!(!subscription.changed() && subscription.poll().is_none());
+
+    cell.update(|tree| tree.insert(1));
+    cell.update(|tree| tree.insert(2));
+    assert!(subscription.changed());
+    let latest = subscription.poll().unwrap();
+    assert!(latest.member(1) && latest.member(2));
+    assert!(!subscription.changed() && subscription.poll().is_none());
+
+    let late = cell.subscribe();
+    cell.store(Tree::empty());
+    assert!(late.changed() && subscription.changed());
+}
+
+#[test]
+fn shared_queues_pop_in_push_order() {
+    let queue = SharedQueue::new();
+    queue.push(1);
+    queue.push_all(vec![2, 3, 4, 5]);
+    queue.push_all(Vec::new());
+    let snapshot = queue.snapshot();
+
+    assert!(queue.pop() == Some(1));
+    assert!(queue.pop_batch(3) == vec![2, 3, 4]);
+    assert!(queue.pop_batch(0).is_empty() && queue.size() == 1);
+    assert!(queue.pop_batch(10) == vec![5] && queue.pop().is_none());
+    assert!(snapshot.iter().cloned().collect::<Vec<_>>() == vec![1, 2, 3, 4, 5]);
+    assert!(format!("{:?}", queue) == "[]");
+}
+
+#[test]
+fn shared_queues_hand_each_value_to_one_consumer() {
+    let queue = Arc::new(SharedQueue::new());
+    let producers: Vec<_> = (0..4)
+                                .map(|i| {
+                                    let queue = queue.clone();
+                                    thread::spawn(move || {
+                                        for j in 0..250 {
+                                            if j % 2 == 0 {
+                                                queue.push(i * 1000 + j);
+                                            } else {
+                                                queue.push_all(vec![i * 1000 + j]);
+                                            }
+                                        }
+                                    })
+                                })
+                                .collect();
+    let consumers: Vec<_> = (0..4)
+                                .map(|_| {
+                                    let queue = queue.clone();
+                                    thread::spawn(move || {
+                                        let mut popped = Vec::new();
+                                        while popped.len() < 250 {
+                                            popped.push(queue.pop_wait());
+                                        }
+                                        popped
+                                    })
+                                })
+                                .collect();
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    let mut popped: Vec<_> = consumers.into_iter()
+                                      .flat_map(|consumer| consumer.join().unwrap())
+                                      .collect();
+
+    popped.sort();
+    let mut expected: Vec<_> = (0..4).flat_map(|i| (0..250).map(move |j| i * 1000 + j)).collect();
+    expected.sort();
+    assert!(popped == expected && queue.is_empty());
+}
+
+#[test]
+fn transactions_commit_all_their_writes_together() {
+    let names = PersistentCell::new(HamtMap::new());
+    let count = PersistentCell::new(0);
+    let result = atomically(|transaction| {
+        transaction.modify(&names, |names| names.insert(1, "one"));
+        transaction.modify(&count, |count| count + 1);
+        // Reads see the transaction's own writes.
+        *transaction.read(&count)
+    });
+
+    assert!(result == 1 && *count.load() == 1 && count.generation() == 1);
+    assert!(names.load().get(&1) == Some(&"one"));
+
+    let read_only = atomically(|transaction| transaction.read(&names).size());
+    assert!(read_only == 1 && names.generation() == 1);
+}
+
+#[test]
+fn transactions_retry_when_what_they_read_changes() {
+    let source = PersistentCell::new(1);
+    let target = PersistentCell::new(0);
+    let mut attempts = 0;
+    atomically(|transaction| {
+        attempts += 1;
+        let value = *transaction.read(&source);
+        if attempts == 1 {
+            // Another writer gets in before this attempt commits.
+            source.store(10);
+        }
+        transaction.write(&target, value * 2);
+    });
+
+    assert!(attempts == 2 && *target.load() == 20);
+    assert!(target.generation() == 1);
+}
+
+#[test]
+fn concurrent_transactions_keep_cells_consistent() {
+    let cells = Arc::new((PersistentCell::new(Tree::empty_map()), PersistentCell::new(0u32)));
+    let threads: Vec<_> = (0..8)
+                              .map(|i| {
+                                  let cells = cells.clone();
+                                  thread::spawn(move || {
+                                      for j in 0..100 {
+                                          atomically(|transaction| {
+                                              let (ref tree, ref size) = *cells;
+                                              let key = i * 100 + j;
+                                              transaction.modify(tree, |tree| tree.bind(key, i));
+                                              transaction.modify(size, |size| size + 1);
+                                          });
+                                      }
+                                  })
+                              })
+                              .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let (tree, size) = atomically(|transaction| {
+        (transaction.read(&cells.0), transaction.read(&cells.1))
+    });
+    assert!(*size == 800 && tree.iter().count() == 800);
+}
+
+#[test]
+fn updates_and_transactions_on_the_same_cells_are_not_lost() {
+    let cells = Arc::new((PersistentCell::new(0u32), PersistentCell::new(0u32)));
+    let threads: Vec<_> = (0..8)
+                              .map(|i| {
+                                  let cells = cells.clone();
+                                  thread::spawn(move || {
+                                      for _ in 0..200 {
+                                          if i % 2 == 0 {
+                                              cells.0.update(|count| count + 1);
+                                          } else {
+                                              atomically(|transaction| {
+                                                  transaction.modify(&cells.0, |count| count + 1);
+                                                  transaction.modify(&cells.1, |count| count + 1);
+                                              });
+                                          }
+                                      }
+                                  })
+                              })
+                              .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert!(*cells.0.load() == 1600 && *cells.1.load() == 800);
+    assert!(cells.0.generation() == 1600 && cells.1.generation() == 800);
+}
@@ -0,0 +1,286 @@
+use ptr::{DefaultPointer, Shared};
+use stack::CustomStack;
+
+/// Implemented by the structures in this crate which hold items that can be
+/// counted and iterated over, so that code which only needs to do that can
+/// be written once for all of them.
+///
+/// Each structure's `iter` method returns the same iterator as its
+/// inherent `iter`, or `chars` for a `Rope`, and `len` counts the items
+/// that iterator visits. For most structures `len` takes constant time; the
+/// few which do not keep a count of their items say so.
+///
+/// The structures in `sortable` are not included: they give up their
+/// elements only through `sort`, which builds a new stack, and cannot lend
+/// them out in any order of their own.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::collection::Collection;
+/// use functional_datastructures::hamt::HamtSet;
+/// use functional_datastructures::seq::Seq;
+/// use functional_datastructures::set::Set;
+///
+/// fn describe<C: Collection>(collection: &C) -> String
+///     where for<'a> <C::Iter<'a> as Iterator>::Item: std::fmt::Debug
+/// {
+///     let first: Vec<_> = collection.iter().take(3).collect();
+///     format!("{} items, starting {:?}", collection.len(), first)
+/// }
+///
+/// let seq = (0..10).fold(Seq::empty(), |seq, i| seq.push_back(i));
+/// assert!(describe(&seq) == "10 items, starting [0, 1, 2]");
+/// let set = HamtSet::new().insert("only");
+/// assert!(describe(&set) == "1 items, starting [\"only\"]");
+/// ```
+pub trait Collection {
+    /// The iterator returned by `iter`, which borrows the collection.
+    type Iter<'a>: Iterator where Self: 'a;
+
+    /// Returns the number of items in this collection.
+    fn len(&self) -> usize;
+    /// Tests whether this collection holds no items.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Returns an iterator over the items in this collection.
+    fn iter<'a>(&'a self) -> Self::Iter<'a>;
+}
+
+/// Implemented by the structures in this crate which can be rebuilt with
+/// each of their elements of type `T` replaced by a function of it, so that
+/// code transforming the contents of a structure can be written once for
+/// all of them.
+///
+/// Stacks and sequences map their items, keeping their order, and maps map
+/// their values, keeping their keys. The trait is implemented for references
+/// to the structures, which are left as they were, so that it does not hide
+/// the structures' own `map` methods where it is in scope.
+///
+/// `Stream` is not included: mapping a stream lazily keeps `f` until the
+/// stream is forced, so `Stream::map` needs bounds on `f` that this trait
+/// does not place on the others.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::collection::MapElems;
+/// use functional_datastructures::seq::Seq;
+/// use functional_datastructures::stack::{CustomStack, Stack};
+///
+/// fn labels<C: MapElems<u32>>(numbers: C) -> C::Output<String> {
+///     MapElems::map(numbers, |number| format!("#{}", number))
+/// }
+///
+/// let seq = Seq::empty().push_back(1).push_back(2);
+/// assert!(labels(&seq).get(1) == Some(&"#2".to_string()));
+/// let stack = CustomStack::empty().cons(2).cons(1);
+/// assert!(labels(&stack).head().unwrap() == "#1");
+/// ```
+pub trait MapElems<T> {
+    /// The structure of the same kind holding elements of type `U`.
+    type Output<U: Clone>;
+
+    /// Returns a structure of the same shape as this one with `f` of each
+    /// of its elements in their place. `f` is called on the elements in
+    /// the order `iter` visits them.
+    fn map<U: Clone, F: Fn(&T) -> U>(self, f: F) -> Self::Output<U>;
+}
+
+/// Ways of consuming the items of a `Collection`, implemented for every
+/// collection in terms of its `iter`, so that code which only reads a
+/// structure need not commit to a particular one.
+///
+/// The items are those `iter` visits, in the same order: references to the
+/// items of most structures, pairs of references to the keys and values of
+/// maps, and the items themselves for a `Stream` or the characters of a
+/// `Rope`.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::collection::Foldable;
+/// use functional_datastructures::seq::Seq;
+/// use functional_datastructures::stack::Stack;
+///
+/// let seq = (1..5).fold(Seq::empty(), |seq, i| seq.push_back(i));
+///
+/// assert!(seq.fold(0, |sum, &i| sum + i) == 10);
+/// assert!(seq.fold_right(String::new(), |digits, i| digits + &i.to_string()) == "4321");
+/// assert!(seq.any(|&i| i > 3) && !seq.all(|&i| i > 3) && seq.length() == 4);
+/// assert!(*seq.to_stack().head().unwrap() == 1);
+/// ```
+pub trait Foldable: Collection {
+    /// Returns the result of calling `f` on `init` and the first item, then
+    /// on that result and the second item, and so on to the last item.
+    fn fold<'a, B, F>(&'a self, init: B, f: F) -> B
+        where F: FnMut(B, <Self::Iter<'a> as Iterator>::Item) -> B
+    {
+        self.iter().fold(init, f)
+    }
+    /// Returns the result of folding the items as `fold` does, but from the
+    /// last item back to the first. The items are collected into a vector
+    /// first, since not every iterator can be run backwards.
+    fn fold_right<'a, B, F>(&'a self, init: B, f: F) -> B
+        where F: FnMut(B, <Self::Iter<'a> as Iterator>::Item) -> B
+    {
+        let items: Vec<_> = self.iter().collect();
+        items.into_iter().rev().fold(init, f)
+    }
+    /// Tests whether `predicate` holds for any item, stopping at the first
+    /// for which it does.
+    fn any<'a, P>(&'a self, predicate: P) -> bool
+        where P: FnMut(<Self::Iter<'a> as Iterator>::Item) -> bool
+    {
+        self.iter().any(predicate)
+    }
+    /// Tests whether `predicate` holds for every item, stopping at the first
+    /// for which it does not.
+    fn all<'a, P>(&'a self, predicate: P) -> bool
+        where P: FnMut(<Self::Iter<'a> as Iterator>::Item) -> bool
+    {
+        self.iter().all(predicate)
+    }
+    /// Returns the number of items. The same as `len`.
+    fn length(&self) -> usize {
+        self.len()
+    }
+    /// Returns a stack of the items, the first of them at its head.
+    fn to_stack<'a>(&'a self)
+                    -> Shared<CustomStack<<Self::Iter<'a> as Iterator>::Item>, DefaultPointer> {
+        let items: Vec<_> = self.iter().collect();
+        Shared::new(items.into_iter().rev().fold(CustomStack::Empty, |tail, item| {
+            CustomStack::Cons {
+                value: item,
+                tail: Shared::new(tail),
+            }
+        }))
+    }
+}
+
+impl<C: Collection + ?Sized> Foldable for C {}
+
+/// Implemented by the structures which implement `MapElems`, for mapping
+/// their elements with a function which can fail.
+///
+/// The function is called on the elements in the order `iter` visits them
+/// until it first fails, and the structure is only rebuilt, with the same
+/// shape as `MapElems::map` gives it, once every element has been mapped.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::collection::Traverse;
+/// use functional_datastructures::seq::Seq;
+///
+/// let words = Seq::empty().push_back("1").push_back("22").push_back("x");
+///
+/// assert!(words.pop_back().unwrap().1.try_map(|word| word.parse::<u32>()).is_ok());
+/// assert!(words.try_map(|word| word.parse::<u32>()).is_err());
+/// assert!(words.traverse(|word| word.chars().next()).unwrap().get(2) == Some(&'x'));
+/// ```
+pub trait Traverse<T>: MapElems<T> + Sized {
+    /// Returns a structure with `f` of each element in its place, or the
+    /// first error `f` returns.
+    fn try_map<U, E, F>(self, f: F) -> Result<Self::Output<U>, E>
+        where U: Clone,
+              F: FnMut(&T) -> Result<U, E>;
+
+    /// Returns a structure with `f` of each element in its place, or `None`
+    /// if `f` returns `None` for any of them.
+    fn traverse<U, F>(self, mut f: F) -> Option<Self::Output<U>>
+        where U: Clone,
+              F: FnMut(&T) -> Option<U>
+    {
+        self.try_map(|element| f(element).ok_or(())).ok()
+    }
+}
+
+#[cfg(test)]
+use hamt::HamtMap;
+#[cfg(test)]
+use rope::Rope;
+#[cfg(test)]
+use seq::Seq;
+#[cfg(test)]
+use set::{Map, Tree};
+#[cfg(test)]
+use stack::Stack;
+#[cfg(test)]
+use stream::Stream;
+
+#[cfg(test)]
+fn squares<C: MapElems<u32>>(numbers: C) -> C::Output<u64> {
+    MapElems::map(numbers, |&number| number as u64 * number as u64)
+}
+
+#[cfg(test)]
+fn counts_agree<C: Collection>(collection: &C, expected: usize) -> bool {
+    collection.len() == expected && collection.iter().count() == expected &&
+    collection.is_empty() == (expected == 0)
+}
+
+#[test]
+fn len_counts_what_iter_visits() {
+    let tree: Tree<u32, u32> = (0..100).fold(Tree::empty_map(), |tree, i| tree.bind(i % 40, i));
+    let map = (0..100).fold(HamtMap::new(), |map, i| map.insert(i % 60, i));
+    let seq = (0..100).fold(Seq::empty(), |seq, i| seq.push_back(i));
+    let stack = (0..100).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+    let stream = Stream::from_iter(0..100u32);
+
+    assert!(counts_agree(&tree, 40) && counts_agree(&map, 60) && counts_agree(&seq, 100));
+    assert!(counts_agree(&stack, 100) && counts_agree(&stream, 100));
+    assert!(counts_agree(&Rope::from("héllo"), 5) && counts_agree(&Seq::<u32>::empty(), 0));
+    assert!(counts_agree(&CustomStack::<u32>::empty(), 0));
+    assert!(Collection::iter(&stack).take(3).cloned().collect::<Vec<_>>() == vec![99, 98, 97]);
+}
+
+#[test]
+fn map_elems_keeps_shape() {
+    let tree: Tree<u32, u32> = (0..100).fold(Tree::empty_map(), |tree, i| tree.bind(i, i));
+    let map = (0..100).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let seq = (0..100).fold(Seq::empty(), |seq, i| seq.push_back(i));
+    let stack: CustomStack<u32> = (0..100).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+
+    assert!(squares(&tree).lookup(99) == Some(9801) && squares(&map).get(&7) == Some(&49));
+    assert!(squares(&seq).iter().cloned().eq((0..100u64).map(|i| i * i)));
+    assert!(squares(&stack).iter().cloned().eq((0..100u64).rev().map(|i| i * i)));
+    assert!(seq.size() == 100 && tree.lookup(99) == Some(99));
+}
+
+#[test]
+fn foldable_agrees_with_iter() {
+    let map = (0..100).fold(HamtMap::new(), |map, i| map.insert(i, i * 2));
+    let stream = Stream::from_iter(1..11u32);
+    let rope = Rope::from("abc");
+
+    assert!(map.fold(0, |sum, (_, &value)| sum + value) == 9900 && map.length() == 100);
+    assert!(map.all(|(&key, &value)| value == key * 2) && !map.any(|(&key, _)| key >= 100));
+    assert!(stream.fold(1u64, |product, i| product * i as u64) == 3628800);
+    assert!(stream.fold_right(Vec::new(), |mut items, i| {
+        items.push(i);
+        items
+    }) == (1..11).rev().collect::<Vec<_>>());
+    assert!(rope.to_stack().head().unwrap() == 'a' && rope.to_stack().get(2).unwrap() == 'c');
+    assert!(Seq::<u32>::empty().to_stack().is_empty() && !Seq::<u32>::empty().any(|_| true));
+}
+
+#[test]
+fn try_map_stops_at_the_first_failure() {
+    let tree: Tree<u32, i32> = (0..100).fold(Tree::empty_map(), |tree, i| tree.bind(i, i as i32));
+    let map = (0..100u32).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let stack: CustomStack<u32> = (0..100).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+    let mut calls = 0;
+    let failed = tree.try_map(|&value| {
+        calls += 1;
+        if value < 50 { Ok(value as u32) } else { Err(value) }
+    });
+
+    assert!(failed.err() == Some(50) && calls == 51);
+    assert!(tree.traverse(|&value| Some(value + 1)).unwrap().lookup(99) == Some(100));
+    assert!(map.try_map(|&value| value.checked_sub(1).ok_or(value)).err() == Some(0));
+    assert!(map.traverse(|&value| value.checked_add(1)).unwrap().get(&99) == Some(&100));
+    assert!(stack.traverse(|&value| if value < 99 { Some(value) } else { None }).is_none());
+    assert!(stack.try_map(|&value| Ok::<_, ()>(value * 2)).unwrap().head().unwrap() == 198);
+}
@@ -0,0 +1,319 @@
+// Conversions between the shared flavor of each collection, at the root of
+// the crate, and its local twin in `local`.
+//
+// The two flavors share no nodes, so a conversion rebuilds the collection
+// from its contents, in time linear in its size for those built front to
+// back and O(n log n) for the rest. Hashed collections keep their hasher.
+//
+// The lazy structures, zippers and histories have no conversions: they hold
+// suspended computations or positions within a structure, which cannot be
+// carried across without forcing or rebuilding what they point into.
+
+// Implements `From` for every collection of the flavor under `$from` into
+// its twin under `$to`, where each is a path prefix ending just before the
+// module names: empty for the root of the crate, `::local` for the other.
+macro_rules! conversions {
+    ($module:ident, [$($from:tt)*] => [$($to:tt)*]) => {
+        mod $module {
+            use std::hash::{BuildHasher, Hash};
+
+            use measure::{Measured, Monoid};
+            use $($to)*::set::{Map, Set};
+
+            impl<T: Clone> From<$($from)*::stack::CustomStack<T>>
+                for $($to)*::stack::CustomStack<T>
+            {
+                fn from(stack: $($from)*::stack::CustomStack<T>) -> Self {
+                    let mut values = Vec::new();
+                    let mut rest = &stack;
+                    while let $($from)*::stack::CustomStack::Cons { ref value, ref tail } = *rest {
+                        values.push(value.clone());
+                        rest = tail;
+                    }
+                    values.into_iter().rev().fold(Self::Empty, |tail, value| {
+                        $($to)*::stack::CustomStack::Cons {
+                            value: value,
+                            tail: ::ptr::Shared::new(tail),
+                        }
+                    })
+                }
+            }
+
+            impl<K: Ord + Clone, V: Clone> From<$($from)*::set::Tree<K, V>>
+                for $($to)*::set::Tree<K, V>
+            {
+                fn from(tree: $($from)*::set::Tree<K, V>) -> Self {
+                    let entries: Vec<_> = tree.iter()
+                                              .map(|(key, value)| (key.clone(), value.clone()))
+                                              .collect();
+                    balanced(&entries)
+                }
+            }
+
+            /// Returns a balanced tree of `entries`, which must be in order
+            /// of key, so that the converted tree is no deeper than needed.
+            fn balanced<K: Ord + Clone, V: Clone>(entries: &[(K, V)])
+                                                  -> $($to)*::set::Tree<K, V> {
+                if entries.is_empty() {
+                    return $($to)*::set::Tree::Empty;
+                }
+                let middle = entries.len() / 2;
+                $($to)*::set::Tree::Node {
+                    left: ::ptr::Shared::new(balanced(&entries[..middle])),
+                    key: entries[middle].0.clone(),
+                    value: entries[middle].1.clone(),
+                    right: ::ptr::Shared::new(balanced(&entries[middle + 1..])),
+                }
+            }
+
+            impl<K: Ord + Copy, V: Copy> From<$($from)*::small_tree::SmallTree<K, V>>
+                for $($to)*::small_tree::SmallTree<K, V>
+            {
+                fn from(tree: $($from)*::small_tree::SmallTree<K, V>) -> Self {
+                    // Binding the middle of each run of keys before the
+                    // rest keeps the converted tree balanced.
+                    let entries: Vec<_> = tree.iter().map(|(&key, &value)| (key, value)).collect();
+                    let mut converted = Self::empty_map();
+                    let mut runs = vec![(0, entries.len())];
+                    while let Some((start, end)) = runs.pop() {
+                        if start < end {
+                            let middle = start + (end - start) / 2;
+                            converted = converted.bind(entries[middle].0, entries[middle].1);
+                            runs.push((middle + 1, end));
+                            runs.push((start, middle));
+                        }
+                    }
+                    converted
+                }
+            }
+
+            impl<K, V, S> From<$($from)*::hamt::HamtMap<K, V, S>>
+                for $($to)*::hamt::HamtMap<K, V, S>
+                where K: Hash + Eq + Clone,
+                      V: Clone,
+                      S: BuildHasher + Clone
+            {
+                fn from(map: $($from)*::hamt::HamtMap<K, V, S>) -> Self {
+                    let empty = Self::with_hasher(map.hasher().clone());
+                    map.iter().fold(empty, |converted, (key, value)| {
+                        converted.insert(key.clone(), value.clone())
+                    })
+                }
+            }
+
+            impl<T, S> From<$($from)*::hamt::HamtSet<T, S>> for $($to)*::hamt::HamtSet<T, S>
+                where T: Hash + Eq + Clone,
+                      S: BuildHasher + Clone + Default
+            {
+                fn from(set: $($from)*::hamt::HamtSet<T, S>) -> Self {
+                    let empty = Self::with_hasher(set.hasher().clone());
+                    set.iter().fold(empty, |converted, value| converted.insert(value.clone()))
+                }
+            }
+
+            impl<V: Clone> From<$($from)*::intmap::IntMap<V>> for $($to)*::intmap::IntMap<V> {
+                fn from(map: $($from)*::intmap::IntMap<V>) -> Self {
+                    map.iter().fold(Self::empty_map(), |converted, (key, value)| {
+                        converted.insert(key, value.clone())
+                    })
+                }
+            }
+
+            impl From<$($from)*::intmap::IntSet> for $($to)*::intmap::IntSet {
+                fn from(set: $($from)*::intmap::IntSet) -> Self {
+                    set.iter().fold(Self::empty(), |converted, value| converted.insert(value))
+                }
+            }
+
+            impl<V: Clone> From<$($from)*::trie::TrieMap<V>> for $($to)*::trie::TrieMap<V> {
+                fn from(map: $($from)*::trie::TrieMap<V>) -> Self {
+                    map.iter().fold(Self::empty(), |converted, (key, value)| {
+                        converted.insert(key, value.clone())
+                    })
+                }
+            }
+
+            impl<V: Clone> From<$($from)*::art::ArtMap<V>> for $($to)*::art::ArtMap<V> {
+                fn from(map: $($from)*::art::ArtMap<V>) -> Self {
+                    map.iter().fold(Self::empty(), |converted, (key, value)| {
+                        converted.insert(key, value.clone())
+                    })
+                }
+            }
+
+            impl<T: Measured + Clone> From<$($from)*::finger_tree::FingerTree<T>>
+                for $($to)*::finger_tree::FingerTree<T>
+            {
+                fn from(tree: $($from)*::finger_tree::FingerTree<T>) -> Self {
+                    tree.iter().fold(Self::empty(), |converted, value| {
+                        converted.push_back(value.clone())
+                    })
+                }
+            }
+
+            impl<T: Clone> From<$($from)*::seq::Seq<T>> for $($to)*::seq::Seq<T> {
+                fn from(seq: $($from)*::seq::Seq<T>) -> Self {
+                    seq.iter().fold(Self::empty(), |converted, value| {
+                        converted.push_back(value.clone())
+                    })
+                }
+            }
+
+            impl<K: Ord + Clone, V: Clone> From<$($from)*::ord_seq::OrdSeqMap<K, V>>
+                for $($to)*::ord_seq::OrdSeqMap<K, V>
+            {
+                fn from(map: $($from)*::ord_seq::OrdSeqMap<K, V>) -> Self {
+                    map.iter().fold(Self::empty_map(), |converted, (key, value)| {
+                        converted.insert(key.clone(), value.clone())
+                    })
+                }
+            }
+
+            impl<T: Ord + Clone> From<$($from)*::ord_seq::OrdSeqSet<T>>
+                for $($to)*::ord_seq::OrdSeqSet<T>
+            {
+                fn from(set: $($from)*::ord_seq::OrdSeqSet<T>) -> Self {
+                    set.iter().fold(Self::empty(), |converted, value| {
+                        converted.insert(value.clone())
+                    })
+                }
+            }
+
+            impl<P: Ord + Clone, V: Clone> From<$($from)*::interval_map::IntervalMap<P, V>>
+                for $($to)*::interval_map::IntervalMap<P, V>
+            {
+                fn from(map: $($from)*::interval_map::IntervalMap<P, V>) -> Self {
+                    map.iter().fold(Self::empty_map(), |converted, (interval, value)| {
+                        let interval = $($to)*::interval_map::Interval::new(interval.low.clone(),
+                                                                             interval.high.clone());
+                        converted.insert(interval, value.clone())
+                    })
+                }
+            }
+
+            impl<T: Ord + Clone> From<$($from)*::priority_seq::PrioritySeq<T>>
+                for $($to)*::priority_seq::PrioritySeq<T>
+            {
+                fn from(seq: $($from)*::priority_seq::PrioritySeq<T>) -> Self {
+                    seq.iter().fold(Self::empty(), |converted, value| {
+                        converted.push_back(value.clone())
+                    })
+                }
+            }
+
+            impl From<$($from)*::rope::Rope> for $($to)*::rope::Rope {
+                fn from(rope: $($from)*::rope::Rope) -> Self {
+                    Self::from(&*rope.to_string())
+                }
+            }
+
+            impl<K: Ord + Clone, V: Clone> From<$($from)*::skip_list::SkipListMap<K, V>>
+                for $($to)*::skip_list::SkipListMap<K, V>
+            {
+                fn from(map: $($from)*::skip_list::SkipListMap<K, V>) -> Self {
+                    map.iter().fold(Self::empty_map(), |converted, (key, value)| {
+                        converted.insert(key.clone(), value.clone())
+                    })
+                }
+            }
+
+            impl<T, S> From<$($from)*::multiset::Multiset<T, S>>
+                for $($to)*::multiset::Multiset<T, S>
+                where T: Hash + Eq + Clone,
+                      S: BuildHasher + Clone + Default
+            {
+                fn from(multiset: $($from)*::multiset::Multiset<T, S>) -> Self {
+                    multiset.iter().fold(Self::default(), |converted, (value, count)| {
+                        (0..count).fold(converted, |converted, _| converted.insert(value.clone()))
+                    })
+                }
+            }
+
+            impl<K, V, S> From<$($from)*::multimap::Multimap<K, V, S>>
+                for $($to)*::multimap::Multimap<K, V, S>
+                where K: Hash + Eq + Clone,
+                      V: Hash + Eq + Clone,
+                      S: BuildHasher + Clone + Default
+            {
+                fn from(multimap: $($from)*::multimap::Multimap<K, V, S>) -> Self {
+                    multimap.iter().fold(Self::default(), |converted, (key, value)| {
+                        converted.insert(key.clone(), value.clone())
+                    })
+                }
+            }
+
+            impl<K: Ord + Clone, V: Clone + PartialEq> From<$($from)*::range_map::RangeMap<K, V>>
+                for $($to)*::range_map::RangeMap<K, V>
+            {
+                fn from(map: $($from)*::range_map::RangeMap<K, V>) -> Self {
+                    map.iter().fold(Self::empty(), |converted, (range, value)| {
+                        converted.insert(range.clone(), value.clone())
+                    })
+                }
+            }
+
+            impl<M: Monoid> From<$($from)*::segment_tree::SegmentTree<M>>
+                for $($to)*::segment_tree::SegmentTree<M>
+            {
+                fn from(tree: $($from)*::segment_tree::SegmentTree<M>) -> Self {
+                    (0..tree.size()).map(|i| tree.get(i).unwrap().clone()).collect()
+                }
+            }
+
+            impl<T: Ord + Clone> From<$($from)*::sorted_list::SortedList<T>>
+                for $($to)*::sorted_list::SortedList<T>
+            {
+                fn from(list: $($from)*::sorted_list::SortedList<T>) -> Self {
+                    // Inserting from the greatest down puts each value at the
+                    // front, so the list is built in linear time.
+                    let values: Vec<_> = list.iter().cloned().collect();
+                    values.into_iter().rev().fold(Self::empty(), |converted, value| {
+                        converted.insert(value)
+                    })
+                }
+            }
+        }
+    }
+}
+
+conversions!(to_local, [] => [::local]);
+conversions!(to_shared, [::local] => []);
+
+#[cfg(test)]
+use local;
+#[cfg(test)]
+use set::Set;
+
+#[test]
+fn conversions_keep_contents_and_order() {
+    let tree = (0..100).map(|i| (i * 37) % 100)
+                       .fold(::set::Tree::empty(), |tree, i| tree.insert(i));
+    let local_tree = local::set::Tree::from(tree.clone());
+    assert!(local_tree.iter().map(|(key, _)| *key).collect::<Vec<_>>() ==
+            (0..100).collect::<Vec<_>>());
+    let back = ::set::Tree::from(local_tree);
+    assert!((0..100).all(|i| back.member(i)) && !back.member(100));
+
+    let stack = ::stack::CustomStack::Empty.cons(3).cons(2).cons(1);
+    let local_stack = local::stack::CustomStack::from(stack);
+    assert!(local::stack::Stack::get(&local_stack, 0).unwrap() == 1);
+    assert!(local::stack::Stack::size(&local_stack) == 3);
+
+    let seq = (0..1000).fold(local::seq::Seq::empty(), |seq, i| seq.push_back(i));
+    let shared_seq = ::seq::Seq::from(seq.clone());
+    assert!(shared_seq.iter().eq(seq.iter()));
+
+    let map = (0..1000u32).fold(::hamt::HamtMap::new(), |map, i| map.insert(i, i * 2));
+    let local_map = local::hamt::HamtMap::from(map.clone());
+    assert!(local_map.size() == 1000 && local_map.get(&999) == Some(&1998));
+
+    let small: local::small_tree::SmallTree<u32, u32> =
+        (0..100).fold(local::set::Map::empty_map(), |tree, i| {
+            local::set::Map::bind(&tree, i, i * 2)
+        });
+    let shared_small = ::small_tree::SmallTree::from(small.clone());
+    assert!(shared_small.iter().eq(small.iter()));
+
+    let rope = local::rope::Rope::from("hello, world");
+    assert!(::rope::Rope::from(rope).to_string() == "hello, world");
+}
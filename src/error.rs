@@ -0,0 +1,114 @@
+use std::error;
+use std::fmt;
+
+/// The error returned by stacks, and by the sequences which index and pop
+/// their elements the same way, when an element asked for is not there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackError {
+    /// The structure was empty.
+    NoSuchElementException,
+    /// The index given was not that of an element of the structure.
+    IndexOutOfRange,
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StackError::NoSuchElementException => write!(f, "the structure is empty"),
+            StackError::IndexOutOfRange => write!(f, "index out of range"),
+        }
+    }
+}
+
+impl error::Error for StackError {}
+
+/// The error returned by bounded structures when an element is added to one
+/// which is already full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapacityError {
+    /// The structure held as many elements as it can.
+    Full,
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CapacityError::Full => write!(f, "the structure is full"),
+        }
+    }
+}
+
+impl error::Error for CapacityError {}
+
+/// Any of the errors returned by the structures in this crate, for code
+/// which handles them all the same way. Each kind of error the structures
+/// return converts into this with `From`, so `?` works on any of them in a
+/// function returning `Error`.
+///
+/// Further variants will be added for the errors of new structures.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::Error;
+/// use functional_datastructures::seq::Seq;
+///
+/// fn second_and_third(seq: &Seq<u32>) -> Result<(u32, u32), Error> {
+///     let (_, rest) = seq.remove_at(0)?;
+///     let (second, rest) = rest.remove_at(0)?;
+///     let (third, _) = rest.remove_at(0)?;
+///     Ok((second, third))
+/// }
+///
+/// let seq = (0..3).fold(Seq::empty(), |seq, i| seq.push_back(i));
+/// assert!(second_and_third(&seq) == Ok((1, 2)));
+/// assert!(second_and_third(&seq.pop_back().unwrap().1).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// An error from a stack or sequence.
+    Stack(StackError),
+    /// An error from a bounded structure.
+    Capacity(CapacityError),
+}
+
+impl From<StackError> for Error {
+    fn from(error: StackError) -> Self {
+        Error::Stack(error)
+    }
+}
+
+impl From<CapacityError> for Error {
+    fn from(error: CapacityError) -> Self {
+        Error::Capacity(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Stack(ref error) => error.fmt(f),
+            Error::Capacity(ref error) => error.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+#[test]
+fn errors_convert_into_boxed_errors() {
+    fn empty_head() -> Result<u32, Box<dyn error::Error>> {
+        Err(StackError::NoSuchElementException)?
+    }
+    fn out_of_range() -> Result<u32, Box<dyn error::Error>> {
+        Err(Error::from(StackError::IndexOutOfRange))?
+    }
+    fn full() -> Result<u32, Box<dyn error::Error>> {
+        Err(CapacityError::Full)?
+    }
+
+    assert!(empty_head().unwrap_err().to_string() == "the structure is empty");
+    assert!(out_of_range().unwrap_err().to_string() == "index out of range");
+    assert!(full().unwrap_err().to_string() == "the structure is full");
+}
@@ -0,0 +1,860 @@
+use std::mem;
+
+use collection::Collection;
+pub use measure::{Measured, Monoid};
+use memory::{Census, MemoryUsage};
+#[cfg(feature = "rayon")]
+use par::Split;
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+
+/// An immutable 2-3 finger tree, annotated with a monoidal measure.
+///
+/// A finger tree keeps a few elements at each end of the sequence in easy
+/// reach, and the rest in a tree of 2-3 nodes one level deeper. Every
+/// subtree caches the combined measure of its elements, so choosing the
+/// measure turns the same structure into an indexed sequence, an ordered
+/// map, a priority queue or an interval tree.
+///
+/// Looking at either end takes O(1) time. Pushing and popping take O(1)
+/// amortized time when each version is only updated once, and O(log n) time
+/// in the worst case, since the middle of the tree is not suspended as in a
+/// lazy implementation. Concatenation and splitting take O(log n) time.
+///
+/// This is the structure described by Hinze and Paterson in "Finger trees:
+/// a simple general-purpose data structure".
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::finger_tree::FingerTree;
+/// use functional_datastructures::measure::{Count, Measured};
+///
+/// #[derive(Clone)]
+/// struct Item(char);
+///
+/// impl Measured for Item {
+///     type Measure = Count;
+///     fn measure(&self) -> Count {
+///         Count(1)
+///     }
+/// }
+///
+/// let tree = "abcde".chars().fold(FingerTree::empty(), |tree, c| tree.push_back(Item(c)));
+/// let (before, after) = tree.split(|count| count.0 > 2);
+///
+/// assert!(tree.measure().0 == 5);
+/// assert!(before.iter().map(|item| item.0).collect::<String>() == "ab");
+/// assert!(after.front().unwrap().0 == 'c');
+/// assert!(after.concat(&before).iter().map(|item| item.0).collect::<String>() == "cdeab");
+/// ```
+#[derive(Debug, Clone)]
+pub struct FingerTreeIn<T: Measured, P: SharedPointer> {
+    tree: Tree<T, T::Measure, P>,
+}
+
+/// A `FingerTreeIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type FingerTree<T> = FingerTreeIn<T, DefaultPointer>;
+
+/// An element of the tree. The top level of the tree holds leaves, and each
+/// level below it holds branches of two or three nodes of the level above,
+/// so a node's depth is implied by where it is stored rather than its type.
+#[derive(Debug, Clone)]
+enum Node<T, M, P: SharedPointer> {
+    Leaf(T),
+    /// A 2-3 node, with the combined measure of its children.
+    Branch(M, Vec<Link<T, M, P>>),
+}
+
+/// A node, shared between the versions of the tree that hold it.
+type Link<T, M, P> = Shared<Node<T, M, P>, P>;
+
+/// One to four nodes at one end of a deep tree.
+type Digit<T, M, P> = Vec<Link<T, M, P>>;
+
+/// A finger tree of nodes of one depth.
+#[derive(Debug, Clone)]
+enum Tree<T, M, P: SharedPointer> {
+    Empty,
+    Single(Link<T, M, P>),
+    /// Digits at each end, and a tree of nodes one level deeper in between.
+    /// `measure` is the combined measure of all three.
+    Deep {
+        measure: M,
+        prefix: Digit<T, M, P>,
+        middle: Shared<Tree<T, M, P>, P>,
+        suffix: Digit<T, M, P>,
+    },
+}
+
+/// A node taken from one end of a tree, and the tree without it.
+type View<T, M, P> = (Link<T, M, P>, Tree<T, M, P>);
+
+/// The nodes of a digit before the one it is split around, that node, and
+/// the nodes after it.
+type SplitDigit<T, M, P> = (Digit<T, M, P>, Link<T, M, P>, Digit<T, M, P>);
+
+impl<T: Measured + Clone, P: SharedPointer> FingerTreeIn<T, P> {
+    /// Returns an empty tree.
+    pub fn empty() -> Self {
+        FingerTreeIn { tree: Tree::Empty }
+    }
+    /// Tests whether this tree is empty.
+    pub fn is_empty(&self) -> bool {
+        match self.tree {
+            Tree::Empty => true,
+            _ => false,
+        }
+    }
+    /// Returns the combined measure of all the elements of this tree.
+    pub fn measure(&self) -> T::Measure {
+        self.tree.measure()
+    }
+    /// Returns a copy of this tree with `value` added at the front.
+    pub fn push_front(&self, value: T) -> Self {
+        FingerTreeIn { tree: self.tree.push_front(Shared::new(Node::Leaf(value))) }
+    }
+    /// Returns a copy of this tree with `value` added at the back.
+    pub fn push_back(&self, value: T) -> Self {
+        FingerTreeIn { tree: self.tree.push_back(Shared::new(Node::Leaf(value))) }
+    }
+    /// Adds `value` at the front of this tree in place.
+    ///
+    /// The digits and the middle of the tree are updated where they are
+    /// unless another tree shares them, in which case they are copied as by
+    /// `push_front`, so a tree which is pushed to repeatedly without being
+    /// cloned in between copies no nodes.
+    pub fn push_front_mut(&mut self, value: T) {
+        self.tree.push_front_mut(Shared::new(Node::Leaf(value)))
+    }
+    /// Adds `value` at the back of this tree in place, as for
+    /// `push_front_mut`.
+    pub fn push_back_mut(&mut self, value: T) {
+        self.tree.push_back_mut(Shared::new(Node::Leaf(value)))
+    }
+    /// Applies `f` to the element at the front of this tree in place, and
+    /// updates the measure of the tree to match. The element is cloned
+    /// first if another tree shares it. Does nothing if this tree is empty.
+    pub fn modify_front<F: FnOnce(&mut T)>(&mut self, f: F) {
+        match self.tree {
+            Tree::Empty => {}
+            Tree::Single(ref mut node) => f(Shared::make_mut(node).value_mut()),
+            Tree::Deep { ref mut measure, ref mut prefix, ref middle, ref suffix } => {
+                f(Shared::make_mut(&mut prefix[0]).value_mut());
+                *measure = measure_digit(prefix)
+                               .combine(&middle.measure())
+                               .combine(&measure_digit(suffix));
+            }
+        }
+    }
+    /// Applies `f` to the element at the back of this tree in place, as for
+    /// `modify_front`.
+    pub fn modify_back<F: FnOnce(&mut T)>(&mut self, f: F) {
+        match self.tree {
+            Tree::Empty => {}
+            Tree::Single(ref mut node) => f(Shared::make_mut(node).value_mut()),
+            Tree::Deep { ref mut measure, ref prefix, ref middle, ref mut suffix } => {
+                let last = suffix.len() - 1;
+                f(Shared::make_mut(&mut suffix[last]).value_mut());
+                *measure = measure_digit(prefix)
+                               .combine(&middle.measure())
+                               .combine(&measure_digit(suffix));
+            }
+        }
+    }
+    /// Returns the element at the front of this tree, or `None` if it is
+    /// empty.
+    pub fn front(&self) -> Option<&T> {
+        match self.tree {
+            Tree::Empty => None,
+            Tree::Single(ref node) => Some(node.value()),
+            Tree::Deep { ref prefix, .. } => Some(prefix[0].value()),
+        }
+    }
+    /// Returns the element at the back of this tree, or `None` if it is
+    /// empty.
+    pub fn back(&self) -> Option<&T> {
+        match self.tree {
+            Tree::Empty => None,
+            Tree::Single(ref node) => Some(node.value()),
+            Tree::Deep { ref suffix, .. } => Some(suffix[suffix.len() - 1].value()),
+        }
+    }
+    /// Returns the element at the front of this tree and the tree without
+    /// it, or `None` if it is empty.
+    pub fn pop_front(&self) -> Option<(T, Self)> {
+        self.tree
+            .view_front()
+            .map(|(node, rest)| (node.value().clone(), FingerTreeIn { tree: rest }))
+    }
+    /// Returns the element at the back of this tree and the tree without it,
+    /// or `None` if it is empty.
+    pub fn pop_back(&self) -> Option<(T, Self)> {
+        self.tree
+            .view_back()
+            .map(|(node, rest)| (node.value().clone(), FingerTreeIn { tree: rest }))
+    }
+    /// Returns a tree of the elements of this tree followed by those of
+    /// `other`.
+    pub fn concat(&self, other: &Self) -> Self {
+        FingerTreeIn { tree: Tree::concat(&self.tree, Vec::new(), &other.tree) }
+    }
+    /// Splits this tree before the first element at which `predicate` holds
+    /// of the combined measure of the elements up to and including it. The
+    /// second tree starts with that element, and is empty if there is none.
+    ///
+    /// `predicate` must be monotonic: once it holds of a measure, it must
+    /// also hold of that measure combined with any other.
+    pub fn split<F>(&self, predicate: F) -> (Self, Self)
+        where F: Fn(&T::Measure) -> bool
+    {
+        if self.is_empty() || !predicate(&self.tree.measure()) {
+            return (self.clone(), FingerTreeIn::empty());
+        }
+        let (before, found, after) = self.tree.split(&predicate, &T::Measure::empty());
+        (FingerTreeIn { tree: before }, FingerTreeIn { tree: after.push_front(found) })
+    }
+    /// Returns the first element at which `predicate` holds of the combined
+    /// measure of the elements up to and including it, or `None` if there
+    /// is none. This finds the element which `split` would split before,
+    /// without building either half.
+    ///
+    /// `predicate` must be monotonic, as for `split`.
+    pub fn find<F>(&self, predicate: F) -> Option<&T>
+        where F: Fn(&T::Measure) -> bool
+    {
+        self.find_with_measure(predicate).map(|(_, value)| value)
+    }
+    /// Returns the element which `find` would find, along with the combined
+    /// measure of the elements before it.
+    pub fn find_with_measure<F>(&self, predicate: F) -> Option<(T::Measure, &T)>
+        where F: Fn(&T::Measure) -> bool
+    {
+        if self.is_empty() || !predicate(&self.tree.measure()) {
+            return None;
+        }
+        let (mut before, mut node) = self.tree.find(&predicate, &T::Measure::empty());
+        while let Node::Branch(_, ref children) = **node {
+            let (inner, child) = find_in_digit(&predicate, &before, children);
+            before = inner;
+            node = child;
+        }
+        Some((before, node.value()))
+    }
+    /// Returns an iterator over the elements of this tree, from front to
+    /// back.
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter { stack: vec![Work::Tree(&self.tree)] }
+    }
+}
+
+impl<T: Measured, P: SharedPointer> FingerTreeIn<T, P> {
+    /// Passes each node of this tree to `census`, as `MemoryUsage::census`
+    /// does, counting `heap_bytes(value)` bytes of other heap memory as
+    /// owned by the leaf holding each element.
+    pub fn census_with<F>(&self, census: &mut Census, heap_bytes: F)
+        where F: Fn(&T) -> usize
+    {
+        let pointer_size = mem::size_of::<Link<T, T::Measure, P>>();
+        let digit_bytes = |tree: &Tree<T, T::Measure, P>| match *tree {
+            Tree::Deep { ref prefix, ref suffix, .. } => {
+                (prefix.capacity() + suffix.capacity()) * pointer_size
+            }
+            _ => 0,
+        };
+        // The digits of the outermost tree are owned by this structure
+        // itself, and those of each tree in the middle by the node holding
+        // it.
+        census.heap(digit_bytes(&self.tree));
+        let mut trees = vec![&self.tree];
+        let mut nodes = Vec::new();
+        while let Some(tree) = trees.pop() {
+            match *tree {
+                Tree::Empty => {}
+                Tree::Single(ref node) => nodes.push(node),
+                Tree::Deep { ref prefix, ref middle, ref suffix, .. } => {
+                    nodes.extend(prefix);
+                    nodes.extend(suffix);
+                    if census.node(&**middle, digit_bytes(middle)) {
+                        trees.push(middle);
+                    }
+                }
+            }
+        }
+        while let Some(node) = nodes.pop() {
+            match **node {
+                Node::Leaf(ref value) => {
+                    census.node(&**node, heap_bytes(value));
+                }
+                Node::Branch(_, ref children) => {
+                    if census.node(&**node, children.capacity() * pointer_size) {
+                        nodes.extend(children);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T: Measured + Clone, P: SharedPointer> Collection for FingerTreeIn<T, P> {
+    type Iter<'a> = Iter<'a, T, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        // The measure need not count the elements, so this takes linear time.
+        self.iter().count()
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T: Measured, P: SharedPointer> MemoryUsage for FingerTreeIn<T, P> {
+    fn census(&self, census: &mut Census) {
+        self.census_with(census, |_| 0)
+    }
+}
+
+impl<T: Measured + Clone, P: SharedPointer> Node<T, T::Measure, P> {
+    /// Returns a branch with the given two or three children.
+    fn branch(children: Vec<Shared<Self, P>>) -> Self {
+        Node::Branch(measure_digit(&children), children)
+    }
+    fn measure(&self) -> T::Measure {
+        match *self {
+            Node::Leaf(ref value) => value.measure(),
+            Node::Branch(ref measure, _) => measure.clone(),
+        }
+    }
+    /// Returns the value of this node, which must be a leaf.
+    fn value(&self) -> &T {
+        match *self {
+            Node::Leaf(ref value) => value,
+            Node::Branch(..) => unreachable!("the top level of a finger tree only holds leaves"),
+        }
+    }
+    fn value_mut(&mut self) -> &mut T {
+        match *self {
+            Node::Leaf(ref mut value) => value,
+            Node::Branch(..) => unreachable!("the top level of a finger tree only holds leaves"),
+        }
+    }
+    /// Returns the children of this node, which must be a branch.
+    fn children(&self) -> &[Shared<Self, P>] {
+        match *self {
+            Node::Leaf(_) => unreachable!("the lower levels of a finger tree only hold branches"),
+            Node::Branch(_, ref children) => children,
+        }
+    }
+}
+
+impl<T: Measured + Clone, P: SharedPointer> Tree<T, T::Measure, P> {
+    fn measure(&self) -> T::Measure {
+        match *self {
+            Tree::Empty => T::Measure::empty(),
+            Tree::Single(ref node) => node.measure(),
+            Tree::Deep { ref measure, .. } => measure.clone(),
+        }
+    }
+    /// Returns a deep tree, computing its measure.
+    fn deep(prefix: Digit<T, T::Measure, P>,
+            middle: Shared<Self, P>,
+            suffix: Digit<T, T::Measure, P>)
+            -> Self {
+        let measure = measure_digit(&prefix)
+                          .combine(&middle.measure())
+                          .combine(&measure_digit(&suffix));
+        Tree::Deep {
+            measure: measure,
+            prefix: prefix,
+            middle: middle,
+            suffix: suffix,
+        }
+    }
+    /// Returns a tree of the nodes in `digit`, which may be empty.
+    fn from_digit(digit: Digit<T, T::Measure, P>) -> Self {
+        digit.into_iter().fold(Tree::Empty, |tree, node| tree.push_back(node))
+    }
+    fn push_front(&self, node: Link<T, T::Measure, P>) -> Self {
+        match *self {
+            Tree::Empty => Tree::Single(node),
+            Tree::Single(ref other) => {
+                Tree::deep(vec![node], Shared::new(Tree::Empty), vec![other.clone()])
+            }
+            Tree::Deep { ref prefix, ref middle, ref suffix, .. } => {
+                if prefix.len() == 4 {
+                    // The prefix is full, so three of its nodes move down
+                    // into the middle together.
+                    let carried = Shared::new(Node::branch(prefix[1..].to_vec()));
+                    Tree::deep(vec![node, prefix[0].clone()],
+                               Shared::new(middle.push_front(carried)),
+                               suffix.clone())
+                } else {
+                    let mut prefix = prefix.clone();
+                    prefix.insert(0, node);
+                    Tree::deep(prefix, middle.clone(), suffix.clone())
+                }
+            }
+        }
+    }
+    fn push_back(&self, node: Link<T, T::Measure, P>) -> Self {
+        match *self {
+            Tree::Empty => Tree::Single(node),
+            Tree::Single(ref other) => {
+                Tree::deep(vec![other.clone()], Shared::new(Tree::Empty), vec![node])
+            }
+            Tree::Deep { ref prefix, ref middle, ref suffix, .. } => {
+                if suffix.len() == 4 {
+                    let carried = Shared::new(Node::branch(suffix[..3].to_vec()));
+                    Tree::deep(prefix.clone(),
+                               Shared::new(middle.push_back(carried)),
+                               vec![suffix[3].clone(), node])
+                } else {
+                    let mut suffix = suffix.clone();
+                    suffix.push(node);
+                    Tree::deep(prefix.clone(), middle.clone(), suffix)
+                }
+            }
+        }
+    }
+    /// Adds `node` at the front of this tree in place, copying the middle
+    /// only if it is shared.
+    fn push_front_mut(&mut self, node: Link<T, T::Measure, P>) {
+        if let Tree::Deep { ref mut measure, ref mut prefix, ref mut middle, .. } = *self {
+            *measure = node.measure().combine(measure);
+            if prefix.len() == 4 {
+                let carried = Shared::new(Node::branch(prefix.drain(1..).collect()));
+                Shared::make_mut(middle).push_front_mut(carried);
+            }
+            prefix.insert(0, node);
+            return;
+        }
+        *self = self.push_front(node);
+    }
+    fn push_back_mut(&mut self, node: Link<T, T::Measure, P>) {
+        if let Tree::Deep { ref mut measure, ref mut middle, ref mut suffix, .. } = *self {
+            *measure = measure.combine(&node.measure());
+            if suffix.len() == 4 {
+                let carried = Shared::new(Node::branch(suffix.drain(..3).collect()));
+                Shared::make_mut(middle).push_back_mut(carried);
+            }
+            suffix.push(node);
+            return;
+        }
+        *self = self.push_back(node);
+    }
+    /// Returns the node at the front of this tree and the tree without it.
+    fn view_front(&self) -> Option<View<T, T::Measure, P>> {
+        match *self {
+            Tree::Empty => None,
+            Tree::Single(ref node) => Some((node.clone(), Tree::Empty)),
+            Tree::Deep { ref prefix, ref middle, ref suffix, .. } => {
+                Some((prefix[0].clone(),
+                      Tree::deep_left(prefix[1..].to_vec(), middle, suffix.clone())))
+            }
+        }
+    }
+    /// Returns the node at the back of this tree and the tree without it.
+    fn view_back(&self) -> Option<View<T, T::Measure, P>> {
+        match *self {
+            Tree::Empty => None,
+            Tree::Single(ref node) => Some((node.clone(), Tree::Empty)),
+            Tree::Deep { ref prefix, ref middle, ref suffix, .. } => {
+                let last = suffix.len() - 1;
+                Some((suffix[last].clone(),
+                      Tree::deep_right(prefix.clone(), middle, suffix[..last].to_vec())))
+            }
+        }
+    }
+    /// Returns a deep tree like `deep`, except that `prefix` may be empty, in
+    /// which case it is refilled from the middle.
+    fn deep_left(prefix: Digit<T, T::Measure, P>,
+                 middle: &Shared<Self, P>,
+                 suffix: Digit<T, T::Measure, P>)
+                 -> Self {
+        if !prefix.is_empty() {
+            return Tree::deep(prefix, middle.clone(), suffix);
+        }
+        match middle.view_front() {
+            None => Tree::from_digit(suffix),
+            Some((node, rest)) => Tree::deep(node.children().to_vec(), Shared::new(rest), suffix),
+        }
+    }
+    /// Returns a deep tree like `deep`, except that `suffix` may be empty, in
+    /// which case it is refilled from the middle.
+    fn deep_right(prefix: Digit<T, T::Measure, P>,
+                  middle: &Shared<Self, P>,
+                  suffix: Digit<T, T::Measure, P>)
+                  -> Self {
+        if !suffix.is_empty() {
+            return Tree::deep(prefix, middle.clone(), suffix);
+        }
+        match middle.view_back() {
+            None => Tree::from_digit(prefix),
+            Some((node, rest)) => Tree::deep(prefix, Shared::new(rest), node.children().to_vec()),
+        }
+    }
+    /// Returns a tree of the nodes of `left`, then `between`, then `right`.
+    fn concat(left: &Self, between: Vec<Link<T, T::Measure, P>>, right: &Self) -> Self {
+        match (left, right) {
+            (&Tree::Empty, _) => {
+                between.into_iter().rev().fold(right.clone(), |tree, node| tree.push_front(node))
+            }
+            (_, &Tree::Empty) => {
+                between.into_iter().fold(left.clone(), |tree, node| tree.push_back(node))
+            }
+            (&Tree::Single(ref node), _) => {
+                Tree::concat(&Tree::Empty, between, right).push_front(node.clone())
+            }
+            (_, &Tree::Single(ref node)) => {
+                Tree::concat(left, between, &Tree::Empty).push_back(node.clone())
+            }
+            (&Tree::Deep { prefix: ref left_prefix,
+                           middle: ref left_middle,
+                           suffix: ref left_suffix,
+                           .. },
+             &Tree::Deep { prefix: ref right_prefix,
+                           middle: ref right_middle,
+                           suffix: ref right_suffix,
+                           .. }) => {
+                // The digits which meet in the middle are packed into nodes
+                // and carried down into the concatenation of the middles.
+                let mut inner = left_suffix.clone();
+                inner.extend(between);
+                inner.extend(right_prefix.iter().cloned());
+                Tree::deep(left_prefix.clone(),
+                           Shared::new(Tree::concat(left_middle, pack(inner), right_middle)),
+                           right_suffix.clone())
+            }
+        }
+    }
+    /// Splits this non-empty tree around the first node at which `predicate`
+    /// holds of `before` combined with the measures of the nodes up to and
+    /// including it, or around its last node if there is none.
+    fn split<F>(&self, predicate: &F, before: &T::Measure) -> (Self, Link<T, T::Measure, P>, Self)
+        where F: Fn(&T::Measure) -> bool
+    {
+        match *self {
+            Tree::Empty => unreachable!("empty trees cannot be split"),
+            Tree::Single(ref node) => (Tree::Empty, node.clone(), Tree::Empty),
+            Tree::Deep { ref prefix, ref middle, ref suffix, .. } => {
+                let after_prefix = before.combine(&measure_digit(prefix));
+                if predicate(&after_prefix) {
+                    let (left, found, right) = split_digit(predicate, before, prefix);
+                    return (Tree::from_digit(left),
+                            found,
+                            Tree::deep_left(right, middle, suffix.clone()));
+                }
+                let after_middle = after_prefix.combine(&middle.measure());
+                if predicate(&after_middle) {
+                    let (middle_left, node, middle_right) = middle.split(predicate, &after_prefix);
+                    let before_node = after_prefix.combine(&middle_left.measure());
+                    let (left, found, right) = split_digit(predicate,
+                                                           &before_node,
+                                                           node.children());
+                    (Tree::deep_right(prefix.clone(), &Shared::new(middle_left), left),
+                     found,
+                     Tree::deep_left(right, &Shared::new(middle_right), suffix.clone()))
+                } else {
+                    let (left, found, right) = split_digit(predicate, &after_middle, suffix);
+                    (Tree::deep_right(prefix.clone(), middle, left), found, Tree::from_digit(right))
+                }
+            }
+        }
+    }
+    /// Finds the node which `split` would split this non-empty tree around,
+    /// returning it along with `before` combined with the measures of the
+    /// nodes before it.
+    fn find<F>(&self, predicate: &F, before: &T::Measure) -> (T::Measure, &Link<T, T::Measure, P>)
+        where F: Fn(&T::Measure) -> bool
+    {
+        match *self {
+            Tree::Empty => unreachable!("empty trees contain nothing to find"),
+            Tree::Single(ref node) => (before.clone(), node),
+            Tree::Deep { ref prefix, ref middle, ref suffix, .. } => {
+                let after_prefix = before.combine(&measure_digit(prefix));
+                if predicate(&after_prefix) {
+                    return find_in_digit(predicate, before, prefix);
+                }
+                let after_middle = after_prefix.combine(&middle.measure());
+                if predicate(&after_middle) {
+                    let (before_node, node) = middle.find(predicate, &after_prefix);
+                    find_in_digit(predicate, &before_node, node.children())
+                } else {
+                    find_in_digit(predicate, &after_middle, suffix)
+                }
+            }
+        }
+    }
+}
+
+/// Returns the combined measure of the nodes in `digit`.
+fn measure_digit<T, P>(digit: &[Link<T, T::Measure, P>]) -> T::Measure
+    where T: Measured + Clone,
+          P: SharedPointer
+{
+    digit.iter().fold(T::Measure::empty(), |measure, node| measure.combine(&node.measure()))
+}
+
+/// Packs at least two nodes into a sequence of 2-3 nodes one level deeper.
+fn pack<T: Measured + Clone, P: SharedPointer>(nodes: Vec<Link<T, T::Measure, P>>)
+                             -> Vec<Link<T, T::Measure, P>> {
+    let mut packed = Vec::new();
+    let mut rest = &nodes[..];
+    loop {
+        match rest.len() {
+            2 | 3 => {
+                packed.push(Shared::new(Node::branch(rest.to_vec())));
+                return packed;
+            }
+            4 => {
+                packed.push(Shared::new(Node::branch(rest[..2].to_vec())));
+                packed.push(Shared::new(Node::branch(rest[2..].to_vec())));
+                return packed;
+            }
+            _ => {
+                packed.push(Shared::new(Node::branch(rest[..3].to_vec())));
+                rest = &rest[3..];
+            }
+        }
+    }
+}
+
+/// Splits a non-empty digit around the node which `Tree::split` would split
+/// it around.
+fn split_digit<T, F, P>(predicate: &F,
+                        before: &T::Measure,
+                        digit: &[Link<T, T::Measure, P>])
+                        -> SplitDigit<T, T::Measure, P>
+    where T: Measured + Clone,
+          F: Fn(&T::Measure) -> bool,
+          P: SharedPointer
+{
+    let (_, index) = position_in_digit(predicate, before, digit);
+    (digit[..index].to_vec(), digit[index].clone(), digit[index + 1..].to_vec())
+}
+
+/// Finds the node which `Tree::find` would find in a non-empty digit.
+fn find_in_digit<'a, T, F, P>(predicate: &F,
+                              before: &T::Measure,
+                              digit: &'a [Link<T, T::Measure, P>])
+                              -> (T::Measure, &'a Link<T, T::Measure, P>)
+    where T: Measured + Clone,
+          F: Fn(&T::Measure) -> bool,
+          P: SharedPointer
+{
+    let (before, index) = position_in_digit(predicate, before, digit);
+    (before, &digit[index])
+}
+
+/// Returns the index of the first node in a non-empty digit at which
+/// `predicate` holds of `before` combined with the measures of the nodes up
+/// to and including it, or of its last node if there is none, along with
+/// `before` combined with the measures of the nodes before that one.
+fn position_in_digit<T, F, P>(predicate: &F,
+                              before: &T::Measure,
+                              digit: &[Link<T, T::Measure, P>])
+                              -> (T::Measure, usize)
+    where T: Measured + Clone,
+          F: Fn(&T::Measure) -> bool,
+          P: SharedPointer
+{
+    let mut before = before.clone();
+    for (index, node) in digit.iter().enumerate() {
+        if index + 1 == digit.len() {
+            return (before, index);
+        }
+        let after = before.combine(&node.measure());
+        if predicate(&after) {
+            return (before, index);
+        }
+        before = after;
+    }
+    unreachable!("digits are never empty")
+}
+
+/// An iterator over the elements of a `FingerTree`, from front to back.
+pub struct Iter<'a, T: Measured + 'a, P: SharedPointer> {
+    stack: Vec<Work<'a, T, T::Measure, P>>,
+}
+
+/// Part of a tree still to be visited by an `Iter`.
+enum Work<'a, T: 'a, M: 'a, P: SharedPointer> {
+    Tree(&'a Tree<T, M, P>),
+    Node(&'a Node<T, M, P>),
+}
+
+impl<'a, T: Measured, P: SharedPointer> Clone for Iter<'a, T, P> {
+    fn clone(&self) -> Self {
+        Iter { stack: self.stack.clone() }
+    }
+}
+
+impl<'a, T, M, P: SharedPointer> Clone for Work<'a, T, M, P> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, M, P: SharedPointer> Copy for Work<'a, T, M, P> {}
+
+impl<'a, T: Measured, P: SharedPointer> Iter<'a, T, P> {
+    /// Pushes the parts of `work` onto the stack, first part last, returning
+    /// its value instead if it is a leaf.
+    fn push_parts(&mut self, work: Work<'a, T, T::Measure, P>) -> Option<&'a T> {
+        match work {
+            Work::Node(&Node::Leaf(ref value)) => return Some(value),
+            Work::Node(&Node::Branch(_, ref children)) => {
+                self.stack.extend(children.iter().rev().map(|child| Work::Node(child)));
+            }
+            Work::Tree(&Tree::Empty) => {}
+            Work::Tree(&Tree::Single(ref node)) => self.stack.push(Work::Node(node)),
+            Work::Tree(&Tree::Deep { ref prefix, ref middle, ref suffix, .. }) => {
+                self.stack.extend(suffix.iter().rev().map(|node| Work::Node(node)));
+                // Leaving out an empty middle means that everything on the
+                // stack has something in it, which `split` relies on.
+                if let Tree::Deep { .. } | Tree::Single(_) = **middle {
+                    self.stack.push(Work::Tree(middle));
+                }
+                self.stack.extend(prefix.iter().rev().map(|node| Work::Node(node)));
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T: Measured, P: SharedPointer> Iterator for Iter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        while let Some(work) = self.stack.pop() {
+            if let Some(value) = self.push_parts(work) {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, T: Measured, P: SharedPointer> Split for Iter<'a, T, P> {
+    fn split(&mut self) -> Option<Self> {
+        while self.stack.len() == 1 {
+            if let Some(&Work::Node(&Node::Leaf(_))) = self.stack.last() {
+                return None;
+            }
+            let work = self.stack.pop().unwrap();
+            self.push_parts(work);
+        }
+        if self.stack.len() < 2 {
+            return None;
+        }
+        let half = self.stack.len() / 2;
+        Some(Iter { stack: self.stack.drain(..half).collect() })
+    }
+}
+
+/// An element of the trees in the tests.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+struct Item(u32);
+
+#[cfg(test)]
+impl Measured for Item {
+    type Measure = Count;
+    fn measure(&self) -> Count {
+        Count(1)
+    }
+}
+
+#[cfg(test)]
+use measure::Count;
+
+#[cfg(test)]
+fn tree_of(values: ::std::ops::Range<u32>) -> FingerTree<Item> {
+    values.fold(FingerTree::empty(), |tree, value| tree.push_back(Item(value)))
+}
+
+#[cfg(test)]
+fn contents(tree: &FingerTree<Item>) -> Vec<u32> {
+    tree.iter().map(|item| item.0).collect()
+}
+
+#[test]
+fn empty_tree_has_nothing() {
+    let tree: FingerTree<Item> = FingerTree::empty();
+
+    assert!(tree.is_empty() && Collection::is_empty(&tree));
+    assert!(tree.measure() == Count(0));
+    assert!(tree.front().is_none());
+    assert!(tree.pop_back().is_none());
+    assert!(tree.iter().next().is_none());
+}
+
+#[test]
+fn pushes_and_pops_at_both_ends() {
+    let tree = (0..100).fold(FingerTree::empty(),
+                             |tree, i| tree.push_front(Item(i)).push_back(Item(i + 100)));
+
+    assert!(tree.measure() == Count(200) && tree.len() == 200);
+    assert!(tree.front().unwrap().0 == 99);
+    assert!(tree.back().unwrap().0 == 199);
+
+    let mut fronts = Vec::new();
+    let mut rest = tree.clone();
+    while let Some((item, remaining)) = rest.pop_front() {
+        fronts.push(item.0);
+        rest = remaining;
+    }
+    assert!(fronts == (0..100).rev().chain(100..200).collect::<Vec<_>>());
+
+    let mut backs = Vec::new();
+    let mut rest = tree;
+    while let Some((item, remaining)) = rest.pop_back() {
+        backs.push(item.0);
+        rest = remaining;
+    }
+    assert!(backs == (100..200).rev().chain(0..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn concat_preserves_order_and_measure() {
+    for left in 0..20 {
+        for right in 0..20 {
+            let joined = tree_of(0..left).concat(&tree_of(left..left + right));
+
+            assert!(joined.measure() == Count(left + right));
+            assert!(contents(&joined) == (0..left + right).collect::<Vec<_>>());
+        }
+    }
+    let big = tree_of(0..1000).concat(&tree_of(1000..3000));
+    assert!(contents(&big) == (0..3000).collect::<Vec<_>>());
+}
+
+#[test]
+fn split_at_every_position() {
+    let tree = tree_of(0..200).concat(&tree_of(200..300));
+
+    for index in 0..301 {
+        let (before, after) = tree.split(|count| count.0 > index);
+
+        assert!(before.measure() == Count(index));
+        assert!(contents(&before) == (0..index).collect::<Vec<_>>());
+        assert!(contents(&after) == (index..300).collect::<Vec<_>>());
+        assert!(tree.find(|count| count.0 > index) == after.front());
+        if index < 300 {
+            assert!(tree.find_with_measure(|count| count.0 > index).unwrap().0 == Count(index));
+        }
+    }
+}
+
+#[test]
+fn split_halves_can_be_rejoined() {
+    let tree = tree_of(0..500);
+    let (before, after) = tree.split(|count| count.0 > 123);
+    let (middle, end) = after.split(|count| count.0 > 200);
+
+    assert!(contents(&before.concat(&middle).concat(&end)) == (0..500).collect::<Vec<_>>());
+    assert!(contents(&middle) == (123..323).collect::<Vec<_>>());
+    assert!(contents(&tree) == (0..500).collect::<Vec<_>>());
+}
@@ -0,0 +1,3008 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash, Hasher};
+#[cfg(test)]
+use std::ptr;
+use std::mem;
+#[cfg(feature = "rayon")]
+use std::ops::Range;
+use std::slice;
+use std::sync::OnceLock;
+
+use collection::{Collection, MapElems, Traverse};
+use hasher::FxBuildHasher;
+use measure::Monoid;
+use memory::{Census, MemoryUsage};
+#[cfg(feature = "rayon")]
+use par::Split;
+#[cfg(feature = "rand")]
+use rand::{Rng, RngExt};
+#[cfg(feature = "rand")]
+use rand::seq::index;
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+#[cfg(feature = "rayon")]
+use ptr::{join, MaybeSend, MaybeSync};
+use set::{Map, PersistentMap, PersistentSet, Set};
+use stack::{CustomStackIn, StackBuilderIn};
+
+/// The number of bits of the hash consumed at each level of the trie.
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+/// The level below which the parallel operations stop splitting the work
+/// on each branch's children between separate tasks.
+#[cfg(feature = "rayon")]
+const PARALLEL_SHIFT: u32 = 2 * BITS;
+
+/// An immutable hash map implemented as a hash array mapped trie.
+///
+/// Each level of the trie branches on the next five bits of a key's hash, and
+/// a branch only stores the entries and children which are present, alongside
+/// bitmaps recording which ones those are. Lookups, insertions and removals
+/// therefore take O(log32 n) time, and an update copies only the branches on
+/// the path to the key while sharing everything else with the original map.
+/// Keys only need to be hashable, not ordered.
+///
+/// Nodes use the CHAMP layout of Steindorfer and Vinju: entries are stored
+/// directly in their branch rather than behind a pointer of their own, and
+/// removals keep the trie in a canonical shape, so it stays compact and is
+/// quick to iterate over.
+///
+/// Keys are hashed with an `S: BuildHasher`, as for `HashMap` in the standard
+/// library. The default is the fast but unkeyed `FxBuildHasher`; maps whose
+/// keys come from untrusted input should use a keyed hasher such as
+/// `RandomState` instead, which hashes with SipHash.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::hash_map::RandomState;
+/// use functional_datastructures::hamt::HamtMap;
+/// use functional_datastructures::set::Map;
+///
+/// let empty: HamtMap<&str, u32> = HamtMap::empty_map();
+/// let map = empty.insert("one", 1).insert("two", 2);
+/// let removed = map.remove(&"one");
+///
+/// assert!(map.lookup("one").unwrap() == 1);
+/// assert!(map.lookup("two").unwrap() == 2);
+/// assert!(map.size() == 2);
+///
+/// assert!(removed.lookup("one").is_none());
+/// assert!(removed.lookup("two").unwrap() == 2);
+/// assert!(removed.size() == 1);
+///
+/// let keyed: HamtMap<&str, u32, RandomState> = HamtMap::with_hasher(RandomState::new());
+/// assert!(keyed.insert("one", 1).lookup("one").unwrap() == 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct HamtMapIn<K, V, P: SharedPointer, S = FxBuildHasher> {
+    root: Child<K, V, P>,
+    hasher: S,
+}
+
+/// A `HamtMapIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type HamtMap<K, V, S = FxBuildHasher> = HamtMapIn<K, V, DefaultPointer, S>;
+
+/// A node of the trie, in the CHAMP layout: a branch keeps the entries which
+/// are alone at their hash fragment inline, and only points to a child node
+/// where several entries share a fragment.
+#[derive(Debug, Clone)]
+enum Node<K, V, P: SharedPointer> {
+    /// An interior node. Bit `i` of `datamap` is set if there is an entry for
+    /// hash fragment `i` in `entries`, and bit `i` of `nodemap` is set if
+    /// there is a child for it in `children`; the two are never both set.
+    /// Entries and children are each stored in fragment order, and `size` is
+    /// the number of entries in the branch and all the nodes below it.
+    /// `content_hash` caches the hash of those entries once it is first
+    /// asked for.
+    Branch {
+        size: u32,
+        datamap: u32,
+        nodemap: u32,
+        entries: Vec<(K, V)>,
+        children: Vec<Child<K, V, P>>,
+        content_hash: OnceLock<u64>,
+    },
+    /// Several keys whose hashes are identical in all 64 bits. These only
+    /// appear below the last level of branches.
+    Collision { entries: Vec<(K, V)> },
+}
+
+/// A child of a branch, shared with the other versions of the map that hold
+/// it.
+type Child<K, V, P> = Shared<Node<K, V, P>, P>;
+
+
+/// What a branch holds for one hash fragment: nothing, an entry or a child.
+/// Used both with borrowed and with owned contents.
+enum Slot<E, C> {
+    Empty,
+    Entry(E),
+    Child(C),
+}
+
+/// The owned slots of a branch, each with the bit of its hash fragment.
+type Slots<K, V, P> = Vec<(u32, Slot<(K, V), Child<K, V, P>>)>;
+
+/// The outcome of removing a key from a node.
+enum Removal<K, V, P: SharedPointer> {
+    NotFound,
+    /// The key was removed, leaving the given node.
+    Removed(Node<K, V, P>),
+}
+
+/// The outcome of looking up a key in a node, binding it if it is not bound.
+enum Lookup<'a, K, V, P: SharedPointer> {
+    /// The key was bound to the given value.
+    Found(&'a V),
+    /// The key was not bound, and is bound to the given value in the given
+    /// node.
+    Inserted(Node<K, V, P>, V),
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, P: SharedPointer> HamtMapIn<K, V, P> {
+    /// Returns an empty map which hashes its keys with the default
+    /// `FxBuildHasher`.
+    pub fn new() -> Self {
+        HamtMapIn::with_hasher(FxBuildHasher::default())
+    }
+}
+
+impl<K, V, P, S> HamtMapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Clone,
+          P: SharedPointer,
+          S: BuildHasher + Clone
+{
+    /// Returns an empty map which hashes its keys with `hasher`.
+    pub fn with_hasher(hasher: S) -> Self {
+        HamtMapIn {
+            root: Shared::new(Node::empty()),
+            hasher: hasher,
+        }
+    }
+    /// Returns the hasher this map hashes its keys with.
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+    /// Returns a copy of this map with `key` bound to `value`, replacing any
+    /// existing binding for `key`.
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let hash = hash_of(&self.hasher, &key);
+        let (root, _) = self.root
+                            .insert_with(&self.hasher, hash, 0, key, value, &|_, new| new.clone());
+        self.with_root(root)
+    }
+    /// Returns a copy of this map without any binding for `key`.
+    pub fn remove(&self, key: &K) -> Self {
+        match self.root.remove(hash_of(&self.hasher, key), 0, key) {
+            Removal::NotFound => self.clone(),
+            Removal::Removed(root) => self.with_root(root),
+        }
+    }
+    /// Binds `key` to `value` in this map in place, replacing any existing
+    /// binding for `key`.
+    ///
+    /// The branches on the path to `key` are updated where they are unless
+    /// another map shares them, in which case they are copied as by
+    /// `insert`. A map which is updated repeatedly without being cloned in
+    /// between therefore allocates little more than a mutable one would.
+    pub fn insert_mut(&mut self, key: K, value: V) {
+        let hash = hash_of(&self.hasher, &key);
+        Shared::make_mut(&mut self.root).insert_mut(&self.hasher, hash, 0, key, value);
+    }
+    /// Removes any binding for `key` from this map in place, updating the
+    /// branches on the path to it as `insert_mut` does.
+    pub fn remove_mut(&mut self, key: &K) {
+        if self.contains_key(key) {
+            Shared::make_mut(&mut self.root).remove_mut(hash_of(&self.hasher, key), 0, key);
+        }
+    }
+    /// Returns the value bound to `key`, or `None` if it is not bound.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.root.get(hash_of(&self.hasher, key), 0, key)
+    }
+    /// Returns the value bound to `key` along with this map, or if `key` is
+    /// not bound, `default()` along with a copy of this map binding `key` to
+    /// it. `default` is only called if `key` is not bound.
+    ///
+    /// The binding is looked for and added in the same descent, so a miss
+    /// costs one traversal of the map rather than the two of `get` followed
+    /// by `insert`.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&self, key: &K, default: F) -> (V, Self) {
+        let hash = hash_of(&self.hasher, key);
+        match self.root.get_or_insert_with(&self.hasher, hash, 0, key, default) {
+            Lookup::Found(value) => (value.clone(), self.clone()),
+            Lookup::Inserted(root, value) => (value, self.with_root(root)),
+        }
+    }
+    /// Tests whether `key` is bound in this map.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+    /// Tests whether this map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+    /// Returns the number of keys bound in this map.
+    pub fn size(&self) -> u32 {
+        self.root.size()
+    }
+    /// Returns a map of the bindings in this map and `other`. Where a key is
+    /// bound in both, the binding from this map is kept.
+    ///
+    /// This merges the two tries node by node rather than inserting one
+    /// map's bindings into the other, and shares any subtrie the two maps
+    /// already have in common without visiting it. The result uses this
+    /// map's hasher; if `other` hashes keys differently, its bindings are
+    /// rehashed first.
+    pub fn union(&self, other: &Self) -> Self {
+        if Shared::ptr_eq(&self.root, &other.root) {
+            return self.clone();
+        }
+        let other = self.compatible_root(other);
+        let keep = |value: &V, _: &V| value.clone();
+        self.with_root(self.root.union_with(&other, &self.hasher, 0, true, &keep))
+    }
+    /// Returns a map of the bindings in this map and `other`. Where a key is
+    /// bound in both, it is bound to `combine(this_value, other_value)`.
+    ///
+    /// Like `union` this merges the tries node by node, but since `combine`
+    /// has to see every key bound in both maps, shared subtries are visited.
+    pub fn union_with<F>(&self, other: &Self, combine: &F) -> Self
+        where F: Fn(&V, &V) -> V
+    {
+        let other = self.compatible_root(other);
+        self.with_root(self.root.union_with(&other, &self.hasher, 0, false, combine))
+    }
+    /// Returns a map of the bindings in this map whose keys are also bound in
+    /// `other`, merging the tries node by node.
+    pub fn intersection(&self, other: &Self) -> Self {
+        if Shared::ptr_eq(&self.root, &other.root) {
+            return self.clone();
+        }
+        let other = self.compatible_root(other);
+        let keep = |value: &V, _: &V| value.clone();
+        self.with_root(self.root.intersection_with(&other, &self.hasher, 0, true, &keep))
+    }
+    /// Returns a map of the keys bound in both this map and `other`, each
+    /// bound to `combine(this_value, other_value)`.
+    ///
+    /// Like `intersection` this merges the tries node by node, but since
+    /// `combine` has to see every key bound in both maps, shared subtries are
+    /// visited.
+    pub fn intersection_with<F>(&self, other: &Self, combine: &F) -> Self
+        where F: Fn(&V, &V) -> V
+    {
+        let other = self.compatible_root(other);
+        self.with_root(self.root.intersection_with(&other, &self.hasher, 0, false, combine))
+    }
+    /// Returns a map of the bindings in this map whose keys are not bound in
+    /// `other`, merging the tries node by node.
+    pub fn difference(&self, other: &Self) -> Self {
+        if Shared::ptr_eq(&self.root, &other.root) {
+            return self.with_root(Node::empty());
+        }
+        let other = self.compatible_root(other);
+        let drop = |_: &V, _: &V| None;
+        self.with_root(self.root.difference_with(&other, &self.hasher, 0, true, &drop))
+    }
+    /// Returns a map of the bindings in this map whose keys are not bound in
+    /// `other`, along with those whose keys are bound in both for which
+    /// `combine(this_value, other_value)` returns `Some` of a new value, bound
+    /// to that value.
+    ///
+    /// Like `difference` this merges the tries node by node, but since
+    /// `combine` has to see every key bound in both maps, shared subtries are
+    /// visited.
+    pub fn difference_with<F>(&self, other: &Self, combine: &F) -> Self
+        where F: Fn(&V, &V) -> Option<V>
+    {
+        let other = self.compatible_root(other);
+        self.with_root(self.root.difference_with(&other, &self.hasher, 0, false, combine))
+    }
+    /// Returns the bindings which differ between this map and `other`,
+    /// taking this map as the earlier of the two.
+    ///
+    /// The tries are compared node by node, and any subtrie the two maps
+    /// share is skipped without being visited, so diffing a map against one
+    /// derived from it by a few updates takes time proportional to those
+    /// updates rather than to the size of the maps.
+    pub fn diff(&self, other: &Self) -> Diff<K, V>
+        where V: PartialEq
+    {
+        let mut diff = Diff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        };
+        if !Shared::ptr_eq(&self.root, &other.root) {
+            let other = self.compatible_root(other);
+            self.root.diff_into(&other, &mut diff);
+        }
+        diff
+    }
+    /// Returns a copy of this map with the edits in `patch` made to it.
+    ///
+    /// # Failures
+    ///
+    /// Returns a `PatchConflict` for the first key found not to be bound as
+    /// the patch expects, in which case no edits are made.
+    pub fn apply(&self, patch: &MapPatch<K, V>) -> Result<Self, PatchConflict<K, V>>
+        where V: PartialEq
+    {
+        let mut map = self.clone();
+        for edit in &patch.edits {
+            let (key, expected, value) = match *edit {
+                Edit::Insert(ref key, ref value) => (key, None, Some(value)),
+                Edit::Remove(ref key, ref old) => (key, Some(old), None),
+                Edit::Update(ref key, ref old, ref new) => (key, Some(old), Some(new)),
+            };
+            let found = map.get(key);
+            if found != expected {
+                return Err(PatchConflict {
+                    key: key.clone(),
+                    expected: expected.cloned(),
+                    found: found.cloned(),
+                });
+            }
+            map = match value {
+                Some(value) => map.insert(key.clone(), value.clone()),
+                None => map.remove(key),
+            };
+        }
+        Ok(map)
+    }
+    /// Returns an iterator over the bindings in this map, in an order fixed
+    /// by the hashes of their keys.
+    ///
+    /// Since removals keep the trie in its canonical shape, two maps with
+    /// the same bindings and the same hasher are iterated in the same order
+    /// however they were built, except that keys whose hashes are identical
+    /// in all 64 bits come in the order they were added. The order is
+    /// unrelated to any ordering of the keys themselves; `iter_sorted`
+    /// visits the bindings in order of key.
+    pub fn iter(&self) -> Iter<'_, K, V, P> {
+        Iter {
+            branches: vec![slice::from_ref(&self.root).iter()],
+            entries: [].iter(),
+        }
+    }
+    /// Returns an iterator over the keys of this map, in the same order as
+    /// `iter`.
+    pub fn keys(&self) -> Keys<'_, K, V, P> {
+        Keys { iter: self.iter() }
+    }
+    /// Returns an iterator over the bindings in this map, in order of key.
+    ///
+    /// The bindings are not sorted up front: the iterator heaps them in
+    /// O(n) time, then takes O(log n) time to find each next one, so taking
+    /// only the first few is cheap. Nothing is kept once the iterator is
+    /// dropped, so a map which is only occasionally needed in order does not
+    /// have to be kept in a `Tree` as well.
+    pub fn iter_sorted(&self) -> SortedIter<'_, K, V>
+        where K: Ord
+    {
+        SortedIter { heap: self.iter().map(|(key, value)| ByKey(key, value)).collect() }
+    }
+    /// Returns a map binding each class `classify` puts the bindings of
+    /// this map into to a stack of the values of the bindings in it, in the
+    /// order `iter` visits them. The new map uses this map's hasher.
+    pub fn group_by<C, F>(&self, mut classify: F) -> HamtMapIn<C, CustomStackIn<V, P>, P, S>
+        where C: Hash + Eq + Clone,
+              F: FnMut(&K, &V) -> C
+    {
+        let mut groups: HashMap<C, StackBuilderIn<V, P>> = HashMap::new();
+        for (key, value) in self.iter() {
+            groups.entry(classify(key, value)).or_default().push(value.clone());
+        }
+        let mut grouped = HamtMapIn::with_hasher(self.hasher.clone());
+        for (class, values) in groups {
+            grouped.insert_mut(class, values.freeze());
+        }
+        grouped
+    }
+    /// Returns a binding of this map chosen uniformly at random, or `None`
+    /// if it is empty.
+    ///
+    /// Each branch of the trie records how many bindings are below it, so
+    /// this walks straight down to the chosen binding in O(log n) time.
+    #[cfg(feature = "rand")]
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let (ref key, ref value) = *self.root.nth(rng.random_range(0..self.size()));
+        Some((key, value))
+    }
+    /// Returns `amount` distinct bindings of this map chosen uniformly at
+    /// random, in random order, or all of them if it has fewer. This takes
+    /// O(amount log n) time.
+    #[cfg(feature = "rand")]
+    pub fn sample_n<R: Rng + ?Sized>(&self, rng: &mut R, amount: usize) -> Vec<(&K, &V)> {
+        let size = self.size() as usize;
+        index::sample(rng, size, amount.min(size))
+            .into_iter()
+            .map(|i| {
+                let (ref key, ref value) = *self.root.nth(i as u32);
+                (key, value)
+            })
+            .collect()
+    }
+    /// Checks the invariants the trie relies on, returning a description of
+    /// the first problem found if there is one: that each key is stored
+    /// where its hash leads, that each branch's bitmaps and size agree with
+    /// what it holds, that collisions only appear below the last level of
+    /// branches, and that removals have left the trie in its canonical
+    /// shape.
+    ///
+    /// Nothing but a bug, or a key whose hash has changed since it was
+    /// inserted, should break these. This is meant for tests and for
+    /// validating maps which have been deserialized or built by hand.
+    pub fn check_invariants(&self) -> Result<(), String>
+        where K: Debug
+    {
+        self.root.check(&self.hasher, 0, 0)
+    }
+    /// Returns a map binding each key of this map to `f` of its value.
+    ///
+    /// The keys keep their hashes, so the new map has exactly the shape of
+    /// this one and is built without hashing anything. `f` is called on the
+    /// values in the order `iter` visits them.
+    pub fn map_values<W, F>(&self, mut f: F) -> HamtMapIn<K, W, P, S>
+        where W: Clone,
+              F: FnMut(&V) -> W
+    {
+        HamtMapIn {
+            root: Shared::new(self.root.map_values(&mut f)),
+            hasher: self.hasher.clone(),
+        }
+    }
+    /// Returns a hash of the bindings in this map, which is the same for any
+    /// two equal maps however they were built and whatever hashers they
+    /// use. This is also the hash used by the `Hash` impl.
+    ///
+    /// Each node caches the hash of the bindings below it the first time it
+    /// is asked for, so hashing a map again takes constant time, and hashing
+    /// a map derived from it by a few updates only visits the nodes those
+    /// updates copied. Comparing content hashes is therefore a cheap check
+    /// of whether two maps which share no nodes, such as two snapshots
+    /// deserialized separately, might be equal; it is only conclusive when
+    /// the hashes differ.
+    pub fn content_hash(&self) -> u64
+        where V: Hash
+    {
+        self.root.content_hash()
+    }
+    /// Returns a map with the given root and this map's hasher.
+    fn with_root(&self, root: Node<K, V, P>) -> Self {
+        HamtMapIn {
+            root: Shared::new(root),
+            hasher: self.hasher.clone(),
+        }
+    }
+    /// Returns the root of `other`, or of a copy of it rehashed with this
+    /// map's hasher if the two hash keys differently, so that the tries can
+    /// be merged. Whether they do is checked by comparing the hashes they
+    /// give one key, which only keyed hashers with different keys disagree
+    /// on.
+    fn compatible_root(&self, other: &Self) -> Child<K, V, P> {
+        match other.keys().next() {
+            Some(key) if hash_of(&self.hasher, key) != hash_of(&other.hasher, key) => {
+                other.iter()
+                     .fold(self.with_root(Node::empty()),
+                           |map, (key, value)| map.insert(key.clone(), value.clone()))
+                     .root
+            }
+            _ => other.root.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> HamtMapIn<K, V, DefaultPointer, S> {
+    /// Like `check_invariants`, but checks the subtries of the top levels of
+    /// the trie as separate tasks on rayon's thread pool.
+    pub fn par_check_invariants(&self) -> Result<(), String>
+        where K: Debug + MaybeSend + MaybeSync,
+              V: MaybeSend + MaybeSync,
+              S: MaybeSync
+    {
+        self.root.par_check(&self.hasher, 0, 0)
+    }
+    /// Tests whether this map and `other` hold the same bindings, as `==`
+    /// does, but compares the subtries of the top levels of the tries as
+    /// separate tasks on rayon's thread pool.
+    pub fn par_eq(&self, other: &Self) -> bool
+        where K: MaybeSend + MaybeSync,
+              V: PartialEq + MaybeSend + MaybeSync
+    {
+        self.size() == other.size() &&
+        (Shared::ptr_eq(&self.root, &other.root) ||
+         self.root.par_equals(&self.compatible_root(other), 0))
+    }
+}
+
+impl<K, V, P, S> Collection for HamtMapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Clone,
+          P: SharedPointer,
+          S: BuildHasher + Clone
+{
+    type Iter<'a> = Iter<'a, K, V, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, K, V, P> {
+        self.iter()
+    }
+}
+
+impl<K, V, S, P: SharedPointer> MapElems<V> for &HamtMapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Clone,
+          S: BuildHasher + Clone
+{
+    type Output<U: Clone> = HamtMapIn<K, U, P, S>;
+
+    fn map<U: Clone, F: Fn(&V) -> U>(self, f: F) -> HamtMapIn<K, U, P, S> {
+        self.map_values(f)
+    }
+}
+
+impl<K, V, S, P: SharedPointer> Traverse<V> for &HamtMapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Clone,
+          S: BuildHasher + Clone
+{
+    fn try_map<U, E, F>(self, mut f: F) -> Result<HamtMapIn<K, U, P, S>, E>
+        where U: Clone,
+              F: FnMut(&V) -> Result<U, E>
+    {
+        let mapped = self.iter().map(|(_, value)| f(value)).collect::<Result<Vec<U>, E>>()?;
+        let mut mapped = mapped.into_iter();
+        Ok(self.map_values(|_| mapped.next().unwrap()))
+    }
+}
+
+/// The bindings which differ between two maps, returned by `HamtMap::diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diff<K, V> {
+    /// The bindings of keys which are only bound in the later map.
+    pub added: Vec<(K, V)>,
+    /// The bindings of keys which are only bound in the earlier map.
+    pub removed: Vec<(K, V)>,
+    /// The keys bound to different values in the two maps, each with its
+    /// earlier value and then its later one.
+    pub changed: Vec<(K, V, V)>,
+}
+
+impl<K, V> Diff<K, V> {
+    /// Tests whether the two maps had the same bindings.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// A list of edits which turns one map into another, made from a `Diff` and
+/// applied with `HamtMap::apply`.
+///
+/// Each edit records the binding it expects to find as well as the one it
+/// makes, so applying a patch to a map other than the one it was made
+/// against is detected rather than silently mixing the two. A patch is plain
+/// owned data, so it can be written out in whatever format suits, to ship
+/// the changes between two versions of a map instead of a whole copy.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::hamt::{HamtMap, MapPatch};
+///
+/// let base = HamtMap::new().insert("a", 1).insert("b", 2);
+/// let next = base.insert("a", 10).remove(&"b").insert("c", 3);
+/// let patch = MapPatch::from(base.diff(&next));
+///
+/// let replica = base.clone();
+/// let patched = replica.apply(&patch).unwrap();
+/// assert!(patched.diff(&next).is_empty());
+///
+/// let conflict = base.insert("b", 20).apply(&patch).unwrap_err();
+/// assert!(conflict.key == "b" && conflict.expected == Some(2) && conflict.found == Some(20));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapPatch<K, V> {
+    /// The edits to make, in no particular order, since each is to a
+    /// different key.
+    pub edits: Vec<Edit<K, V>>,
+}
+
+/// A change to the binding of one key, as part of a `MapPatch`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit<K, V> {
+    /// Binds the key, which must not be bound, to the value.
+    Insert(K, V),
+    /// Unbinds the key, which must be bound to the value.
+    Remove(K, V),
+    /// Rebinds the key from the first value, which it must be bound to, to
+    /// the second.
+    Update(K, V, V),
+}
+
+/// A key whose binding was not what a `MapPatch` expected, returned by
+/// `HamtMap::apply`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchConflict<K, V> {
+    /// The key whose edit could not be made.
+    pub key: K,
+    /// The value the patch expected the key to be bound to, if any.
+    pub expected: Option<V>,
+    /// The value the key was actually bound to, if any.
+    pub found: Option<V>,
+}
+
+impl<K, V> MapPatch<K, V> {
+    /// Tests whether this patch makes no edits.
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+}
+
+impl<K, V> From<Diff<K, V>> for MapPatch<K, V> {
+    fn from(diff: Diff<K, V>) -> Self {
+        let added = diff.added.into_iter().map(|(key, value)| Edit::Insert(key, value));
+        let removed = diff.removed.into_iter().map(|(key, value)| Edit::Remove(key, value));
+        let changed = diff.changed.into_iter().map(|(key, old, new)| Edit::Update(key, old, new));
+        MapPatch { edits: added.chain(removed).chain(changed).collect() }
+    }
+}
+
+impl<K, V, S, P: SharedPointer> MemoryUsage for HamtMapIn<K, V, P, S> {
+    fn census(&self, census: &mut Census) {
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            let entry_size = mem::size_of::<(K, V)>();
+            let (heap_bytes, children) = match **node {
+                Node::Branch { ref entries, ref children, .. } => {
+                    (entries.capacity() * entry_size +
+                     children.capacity() * mem::size_of::<Child<K, V, P>>(),
+                     &children[..])
+                }
+                Node::Collision { ref entries } => (entries.capacity() * entry_size, &[][..]),
+            };
+            if census.node(&**node, heap_bytes) {
+                stack.extend(children);
+            }
+        }
+    }
+}
+
+impl<K, V, S, P: SharedPointer> Default for HamtMapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Clone,
+          S: BuildHasher + Clone + Default
+{
+    fn default() -> Self {
+        HamtMapIn::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S, P: SharedPointer> PartialEq for HamtMapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Clone + PartialEq,
+          S: BuildHasher + Clone
+{
+    fn eq(&self, other: &Self) -> bool {
+        // Equal maps whose keys are hashed the same way have tries of the
+        // same shape, so they can be compared node by node.
+        self.size() == other.size() &&
+        (Shared::ptr_eq(&self.root, &other.root) || self.root.equals(&self.compatible_root(other)))
+    }
+}
+
+impl<K, V, S, P: SharedPointer> Eq for HamtMapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Clone + Eq,
+          S: BuildHasher + Clone
+{
+}
+
+impl<K, V, S, P: SharedPointer> Hash for HamtMapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Clone + Hash,
+          S: BuildHasher + Clone
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.content_hash());
+    }
+}
+
+impl<K, V, S, P: SharedPointer> Map<K, V> for HamtMapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Clone,
+          S: BuildHasher + Clone + Default
+{
+    fn empty_map() -> Self {
+        HamtMapIn::with_hasher(S::default())
+    }
+    fn bind(&self, key: K, value: V) -> Self {
+        self.insert(key, value)
+    }
+    fn lookup(&self, key: K) -> Option<V> {
+        self.get(&key).cloned()
+    }
+    fn bind_mut(&mut self, key: K, value: V) {
+        self.insert_mut(key, value)
+    }
+    fn get_or_insert_with<F: FnOnce() -> V>(&self, key: &K, default: F) -> (V, Self) {
+        HamtMapIn::get_or_insert_with(self, key, default)
+    }
+}
+
+impl<K, V, S, P: SharedPointer> PersistentMap<K, V> for HamtMapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Clone,
+          S: BuildHasher + Clone + Default
+{
+    fn get(&self, key: &K) -> Option<&V> {
+        HamtMapIn::get(self, key)
+    }
+    fn remove(&self, key: &K) -> Self {
+        HamtMapIn::remove(self, key)
+    }
+    fn union(&self, other: &Self) -> Self {
+        HamtMapIn::union(self, other)
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        HamtMapIn::intersection(self, other)
+    }
+    fn difference(&self, other: &Self) -> Self {
+        HamtMapIn::difference(self, other)
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, P: SharedPointer> Node<K, V, P> {
+    fn empty() -> Self {
+        Node::Branch {
+            size: 0,
+            datamap: 0,
+            nodemap: 0,
+            entries: Vec::new(),
+            children: Vec::new(),
+            content_hash: OnceLock::new(),
+        }
+    }
+    /// Returns the number of entries in this node and all the nodes below it.
+    fn size(&self) -> u32 {
+        match *self {
+            Node::Branch { size, .. } => size,
+            Node::Collision { ref entries } => entries.len() as u32,
+        }
+    }
+    /// Returns the binding at `index` in the order `Iter` visits the
+    /// bindings below this node.
+    #[cfg(feature = "rand")]
+    fn nth(&self, mut index: u32) -> &(K, V) {
+        let mut node = self;
+        loop {
+            match *node {
+                Node::Branch { ref entries, ref children, .. } => {
+                    if (index as usize) < entries.len() {
+                        return &entries[index as usize];
+                    }
+                    index -= entries.len() as u32;
+                    for child in children {
+                        if index < child.size() {
+                            node = child;
+                            break;
+                        }
+                        index -= child.size();
+                    }
+                }
+                Node::Collision { ref entries } => return &entries[index as usize],
+            }
+        }
+    }
+    fn get(&self, hash: u64, shift: u32, key: &K) -> Option<&V> {
+        match *self {
+            Node::Branch { datamap, nodemap, ref entries, ref children, .. } => {
+                let bit = bit_for(hash, shift);
+                if datamap & bit != 0 {
+                    let entry = &entries[index_of(datamap, bit)];
+                    if entry.0 == *key {
+                        Some(&entry.1)
+                    } else {
+                        None
+                    }
+                } else if nodemap & bit != 0 {
+                    children[index_of(nodemap, bit)].get(hash, shift + BITS, key)
+                } else {
+                    None
+                }
+            }
+            Node::Collision { ref entries } => {
+                entries.iter().find(|entry| entry.0 == *key).map(|entry| &entry.1)
+            }
+        }
+    }
+    /// Returns the value bound to `key` in this node, or if it is not bound,
+    /// a copy of this node binding it to `default()` along with that value.
+    fn get_or_insert_with<S, F>(&self,
+                                hasher: &S,
+                                hash: u64,
+                                shift: u32,
+                                key: &K,
+                                default: F)
+                                -> Lookup<'_, K, V, P>
+        where S: BuildHasher,
+              F: FnOnce() -> V
+    {
+        match *self {
+            Node::Branch { size, datamap, nodemap, ref entries, ref children, .. } => {
+                let bit = bit_for(hash, shift);
+                if datamap & bit != 0 {
+                    let entry = &entries[index_of(datamap, bit)];
+                    if entry.0 == *key {
+                        return Lookup::Found(&entry.1);
+                    }
+                } else if nodemap & bit != 0 {
+                    let index = index_of(nodemap, bit);
+                    return match children[index].get_or_insert_with(hasher,
+                                                                     hash,
+                                                                     shift + BITS,
+                                                                     key,
+                                                                     default) {
+                        Lookup::Found(value) => Lookup::Found(value),
+                        Lookup::Inserted(child, value) => {
+                            let mut children = children.clone();
+                            children[index] = Shared::new(child);
+                            Lookup::Inserted(Node::Branch {
+                                                 size: size + 1,
+                                                 datamap: datamap,
+                                                 nodemap: nodemap,
+                                                 entries: entries.clone(),
+                                                 children: children,
+                                                 content_hash: OnceLock::new(),
+                                             },
+                                             value)
+                        }
+                    };
+                }
+            }
+            Node::Collision { ref entries } => {
+                if let Some(entry) = entries.iter().find(|entry| entry.0 == *key) {
+                    return Lookup::Found(&entry.1);
+                }
+            }
+        }
+        // `key` is not bound and has no child to descend into, so binding it
+        // only changes this node.
+        let value = default();
+        let (node, _) = self.insert_with(hasher,
+                                         hash,
+                                         shift,
+                                         key.clone(),
+                                         value.clone(),
+                                         &|_, new| new.clone());
+        Lookup::Inserted(node, value)
+    }
+    /// Returns a copy of this node with `key` bound to `value`, and whether
+    /// `key` was not already bound in it. If it was, `key` is instead bound to
+    /// `combine(existing, value)`.
+    fn insert_with<S, F>(&self,
+                         hasher: &S,
+                         hash: u64,
+                         shift: u32,
+                         key: K,
+                         value: V,
+                         combine: &F)
+                         -> (Self, bool)
+        where S: BuildHasher,
+              F: Fn(&V, &V) -> V
+    {
+        match *self {
+            Node::Branch { size, datamap, nodemap, ref entries, ref children, .. } => {
+                let bit = bit_for(hash, shift);
+                if datamap & bit != 0 {
+                    let index = index_of(datamap, bit);
+                    let mut entries = entries.clone();
+                    if entries[index].0 == key {
+                        let value = combine(&entries[index].1, &value);
+                        entries[index] = (key, value);
+                        return (Node::Branch {
+                            size: size,
+                            datamap: datamap,
+                            nodemap: nodemap,
+                            entries: entries,
+                            children: children.clone(),
+                            content_hash: OnceLock::new(),
+                        },
+                                false);
+                    }
+                    // Two entries now share this fragment, so they move down
+                    // into a new child together.
+                    let (existing_key, existing_value) = entries.remove(index);
+                    let existing_hash = hash_of(hasher, &existing_key);
+                    let child = Node::pair(shift + BITS,
+                                           (existing_key, existing_value),
+                                           existing_hash,
+                                           (key, value),
+                                           hash);
+                    let mut children = children.clone();
+                    children.insert(index_of(nodemap, bit), Shared::new(child));
+                    (Node::Branch {
+                        size: size + 1,
+                        datamap: datamap & !bit,
+                        nodemap: nodemap | bit,
+                        entries: entries,
+                        children: children,
+                        content_hash: OnceLock::new(),
+                    },
+                     true)
+                } else if nodemap & bit != 0 {
+                    let index = index_of(nodemap, bit);
+                    let (child, added) = children[index].insert_with(hasher,
+                                                                     hash,
+                                                                     shift + BITS,
+                                                                     key,
+                                                                     value,
+                                                                     combine);
+                    let mut children = children.clone();
+                    children[index] = Shared::new(child);
+                    (Node::Branch {
+                        size: if added { size + 1 } else { size },
+                        datamap: datamap,
+                        nodemap: nodemap,
+                        entries: entries.clone(),
+                        children: children,
+                        content_hash: OnceLock::new(),
+                    },
+                     added)
+                } else {
+                    let mut entries = entries.clone();
+                    entries.insert(index_of(datamap, bit), (key, value));
+                    (Node::Branch {
+                        size: size + 1,
+                        datamap: datamap | bit,
+                        nodemap: nodemap,
+                        entries: entries,
+                        children: children.clone(),
+                        content_hash: OnceLock::new(),
+                    },
+                     true)
+                }
+            }
+            Node::Collision { ref entries } => {
+                let mut entries = entries.clone();
+                let added = match entries.iter().position(|entry| entry.0 == key) {
+                    Some(index) => {
+                        let value = combine(&entries[index].1, &value);
+                        entries[index] = (key, value);
+                        false
+                    }
+                    None => {
+                        entries.push((key, value));
+                        true
+                    }
+                };
+                (Node::Collision { entries: entries }, added)
+            }
+        }
+    }
+    fn remove(&self, hash: u64, shift: u32, key: &K) -> Removal<K, V, P> {
+        match *self {
+            Node::Branch { size, datamap, nodemap, ref entries, ref children, .. } => {
+                let bit = bit_for(hash, shift);
+                if datamap & bit != 0 {
+                    let index = index_of(datamap, bit);
+                    if entries[index].0 != *key {
+                        return Removal::NotFound;
+                    }
+                    let mut entries = entries.clone();
+                    entries.remove(index);
+                    Removal::Removed(Node::Branch {
+                        size: size - 1,
+                        datamap: datamap & !bit,
+                        nodemap: nodemap,
+                        entries: entries,
+                        children: children.clone(),
+                        content_hash: OnceLock::new(),
+                    })
+                } else if nodemap & bit != 0 {
+                    let index = index_of(nodemap, bit);
+                    let child = match children[index].remove(hash, shift + BITS, key) {
+                        Removal::NotFound => return Removal::NotFound,
+                        Removal::Removed(child) => child,
+                    };
+                    let mut children = children.clone();
+                    match child.into_singleton() {
+                        // A child left with a single entry is replaced by
+                        // that entry, so that every child holds at least two.
+                        Ok(entry) => {
+                            children.remove(index);
+                            let mut entries = entries.clone();
+                            entries.insert(index_of(datamap, bit), entry);
+                            Removal::Removed(Node::Branch {
+                                size: size - 1,
+                                datamap: datamap | bit,
+                                nodemap: nodemap & !bit,
+                                entries: entries,
+                                children: children,
+                                content_hash: OnceLock::new(),
+                            })
+                        }
+                        Err(child) => {
+                            children[index] = Shared::new(child);
+                            Removal::Removed(Node::Branch {
+                                size: size - 1,
+                                datamap: datamap,
+                                nodemap: nodemap,
+                                entries: entries.clone(),
+                                children: children,
+                                content_hash: OnceLock::new(),
+                            })
+                        }
+                    }
+                } else {
+                    Removal::NotFound
+                }
+            }
+            Node::Collision { ref entries } => {
+                let index = match entries.iter().position(|entry| entry.0 == *key) {
+                    Some(index) => index,
+                    None => return Removal::NotFound,
+                };
+                let mut entries = entries.clone();
+                entries.remove(index);
+                Removal::Removed(Node::Collision { entries: entries })
+            }
+        }
+    }
+    /// Binds `key` to `value` in this node in place, copying any child on
+    /// the way which is shared, and returns whether `key` was not already
+    /// bound in it.
+    fn insert_mut<S: BuildHasher>(&mut self,
+                                  hasher: &S,
+                                  hash: u64,
+                                  shift: u32,
+                                  key: K,
+                                  value: V)
+                                  -> bool {
+        let added = match *self {
+            Node::Branch { ref mut datamap,
+                           ref mut nodemap,
+                           ref mut entries,
+                           ref mut children,
+                           ref mut content_hash,
+                           .. } => {
+                // This node may be a copy of a shared one, whose hash no
+                // longer applies.
+                *content_hash = OnceLock::new();
+                let bit = bit_for(hash, shift);
+                if *datamap & bit != 0 {
+                    let index = index_of(*datamap, bit);
+                    if entries[index].0 == key {
+                        entries[index] = (key, value);
+                        return false;
+                    }
+                    let (existing_key, existing_value) = entries.remove(index);
+                    let existing_hash = hash_of(hasher, &existing_key);
+                    let child = Node::pair(shift + BITS,
+                                           (existing_key, existing_value),
+                                           existing_hash,
+                                           (key, value),
+                                           hash);
+                    children.insert(index_of(*nodemap, bit), Shared::new(child));
+                    *datamap &= !bit;
+                    *nodemap |= bit;
+                    true
+                } else if *nodemap & bit != 0 {
+                    let child = Shared::make_mut(&mut children[index_of(*nodemap, bit)]);
+                    child.insert_mut(hasher, hash, shift + BITS, key, value)
+                } else {
+                    entries.insert(index_of(*datamap, bit), (key, value));
+                    *datamap |= bit;
+                    true
+                }
+            }
+            Node::Collision { ref mut entries } => {
+                match entries.iter().position(|entry| entry.0 == key) {
+                    Some(index) => {
+                        entries[index] = (key, value);
+                        return false;
+                    }
+                    None => entries.push((key, value)),
+                }
+                return true;
+            }
+        };
+        if let Node::Branch { ref mut size, .. } = *self {
+            if added {
+                *size += 1;
+            }
+        }
+        added
+    }
+    /// Removes `key`, which must be bound in this node, in place, copying
+    /// any child on the way which is shared.
+    fn remove_mut(&mut self, hash: u64, shift: u32, key: &K) {
+        match *self {
+            Node::Branch { ref mut size,
+                           ref mut datamap,
+                           ref mut nodemap,
+                           ref mut entries,
+                           ref mut children,
+                           ref mut content_hash } => {
+                *content_hash = OnceLock::new();
+                *size -= 1;
+                let bit = bit_for(hash, shift);
+                if *datamap & bit != 0 {
+                    entries.remove(index_of(*datamap, bit));
+                    *datamap &= !bit;
+                    return;
+                }
+                let index = index_of(*nodemap, bit);
+                Shared::make_mut(&mut children[index]).remove_mut(hash, shift + BITS, key);
+                if children[index].size() == 1 {
+                    // As in `remove`, a child left with a single entry is
+                    // replaced by that entry.
+                    let child = children.remove(index);
+                    let child = Shared::try_unwrap(child).unwrap_or_else(|child| (*child).clone());
+                    let entry = child.into_singleton().ok().expect("a child of one entry");
+                    entries.insert(index_of(*datamap, bit), entry);
+                    *datamap |= bit;
+                    *nodemap &= !bit;
+                }
+            }
+            Node::Collision { ref mut entries } => {
+                entries.retain(|entry| entry.0 != *key);
+            }
+        }
+    }
+    /// Returns a node at level `shift` containing both `first` and `second`.
+    fn pair(shift: u32, first: (K, V), first_hash: u64, second: (K, V), second_hash: u64) -> Self {
+        if shift >= 64 {
+            return Node::Collision { entries: vec![first, second] };
+        }
+        let first_bit = bit_for(first_hash, shift);
+        let second_bit = bit_for(second_hash, shift);
+        if first_bit == second_bit {
+            let child = Node::pair(shift + BITS, first, first_hash, second, second_hash);
+            Node::Branch {
+                size: 2,
+                datamap: 0,
+                nodemap: first_bit,
+                entries: Vec::new(),
+                children: vec![Shared::new(child)],
+                content_hash: OnceLock::new(),
+            }
+        } else {
+            let entries = if first_bit < second_bit {
+                vec![first, second]
+            } else {
+                vec![second, first]
+            };
+            Node::Branch {
+                size: 2,
+                datamap: first_bit | second_bit,
+                nodemap: 0,
+                entries: entries,
+                children: Vec::new(),
+                content_hash: OnceLock::new(),
+            }
+        }
+    }
+    /// Returns what this branch holds for the fragment `bit`.
+    fn slot(&self, bit: u32) -> Slot<&(K, V), &Shared<Self, P>> {
+        match *self {
+            Node::Branch { datamap, nodemap, ref entries, ref children, .. } => {
+                if datamap & bit != 0 {
+                    Slot::Entry(&entries[index_of(datamap, bit)])
+                } else if nodemap & bit != 0 {
+                    Slot::Child(&children[index_of(nodemap, bit)])
+                } else {
+                    Slot::Empty
+                }
+            }
+            Node::Collision { .. } => unreachable!("collisions only appear below every branch"),
+        }
+    }
+    /// Returns the fragments for which this branch holds anything.
+    fn occupied(&self) -> u32 {
+        match *self {
+            Node::Branch { datamap, nodemap, .. } => datamap | nodemap,
+            Node::Collision { .. } => unreachable!("collisions only appear below every branch"),
+        }
+    }
+    /// Returns a branch holding the given slots, whose bits must be in
+    /// increasing order.
+    fn from_slots(slots: Slots<K, V, P>) -> Self {
+        let mut size = 0;
+        let mut datamap = 0;
+        let mut nodemap = 0;
+        let mut entries = Vec::new();
+        let mut children = Vec::new();
+        for (bit, slot) in slots {
+            match slot {
+                Slot::Empty => {}
+                Slot::Entry(entry) => {
+                    size += 1;
+                    datamap |= bit;
+                    entries.push(entry);
+                }
+                Slot::Child(child) => {
+                    size += child.size();
+                    nodemap |= bit;
+                    children.push(child);
+                }
+            }
+        }
+        Node::Branch {
+            size: size,
+            datamap: datamap,
+            nodemap: nodemap,
+            entries: entries,
+            children: children,
+            content_hash: OnceLock::new(),
+        }
+    }
+    /// Returns the slot this node should occupy in its parent: nothing if it
+    /// is empty, its entry if it has only one, and otherwise the node itself.
+    fn into_slot(self) -> Slot<(K, V), Child<K, V, P>> {
+        if self.size() == 0 {
+            return Slot::Empty;
+        }
+        match self.into_singleton() {
+            Ok(entry) => Slot::Entry(entry),
+            Err(node) => Slot::Child(Shared::new(node)),
+        }
+    }
+    /// Returns a node with the entries of this node and `other`, both at
+    /// level `shift`, binding keys in both to `combine(ours, theirs)`. If
+    /// `share` is set, children the two nodes share are reused as they are.
+    fn union_with<S, F>(&self,
+                        other: &Self,
+                        hasher: &S,
+                        shift: u32,
+                        share: bool,
+                        combine: &F)
+                        -> Self
+        where S: BuildHasher,
+              F: Fn(&V, &V) -> V
+    {
+        if let (&Node::Collision { entries: ref ours }, &Node::Collision { entries: ref theirs }) =
+               (self, other) {
+            let mut entries = ours.clone();
+            for &(ref key, ref value) in theirs {
+                match entries.iter().position(|entry| entry.0 == *key) {
+                    Some(index) => entries[index].1 = combine(&entries[index].1, value),
+                    None => entries.push((key.clone(), value.clone())),
+                }
+            }
+            return Node::Collision { entries: entries };
+        }
+        let slots = bits_of(self.occupied() | other.occupied()).map(|bit| {
+            let slot = match (self.slot(bit), other.slot(bit)) {
+                (Slot::Empty, Slot::Empty) => Slot::Empty,
+                (Slot::Entry(entry), Slot::Empty) |
+                (Slot::Empty, Slot::Entry(entry)) => Slot::Entry(entry.clone()),
+                (Slot::Child(child), Slot::Empty) |
+                (Slot::Empty, Slot::Child(child)) => Slot::Child(child.clone()),
+                (Slot::Entry(ours), Slot::Entry(theirs)) => {
+                    if ours.0 == theirs.0 {
+                        Slot::Entry((ours.0.clone(), combine(&ours.1, &theirs.1)))
+                    } else {
+                        Slot::Child(Shared::new(Node::pair(shift + BITS,
+                                                           ours.clone(),
+                                                           hash_of(hasher, &ours.0),
+                                                           theirs.clone(),
+                                                           hash_of(hasher, &theirs.0))))
+                    }
+                }
+                (Slot::Entry(ours), Slot::Child(theirs)) => {
+                    let (child, _) = theirs.insert_with(hasher,
+                                                        hash_of(hasher, &ours.0),
+                                                        shift + BITS,
+                                                        ours.0.clone(),
+                                                        ours.1.clone(),
+                                                        &|theirs, ours| combine(ours, theirs));
+                    Slot::Child(Shared::new(child))
+                }
+                (Slot::Child(ours), Slot::Entry(theirs)) => {
+                    let (child, _) = ours.insert_with(hasher,
+                                                      hash_of(hasher, &theirs.0),
+                                                      shift + BITS,
+                                                      theirs.0.clone(),
+                                                      theirs.1.clone(),
+                                                      combine);
+                    Slot::Child(Shared::new(child))
+                }
+                (Slot::Child(ours), Slot::Child(theirs)) => {
+                    if share && Shared::ptr_eq(ours, theirs) {
+                        Slot::Child(ours.clone())
+                    } else {
+                        let child = ours.union_with(theirs, hasher, shift + BITS, share, combine);
+                        Slot::Child(Shared::new(child))
+                    }
+                }
+            };
+            (bit, slot)
+        });
+        Node::from_slots(slots.collect())
+    }
+    /// Returns a node with the entries of this node whose keys are also in
+    /// `other`, both at level `shift`, bound to `combine(ours, theirs)`. If
+    /// `share` is set, children the two nodes share are reused as they are.
+    fn intersection_with<S, F>(&self,
+                               other: &Self,
+                               hasher: &S,
+                               shift: u32,
+                               share: bool,
+                               combine: &F)
+                               -> Self
+        where S: BuildHasher,
+              F: Fn(&V, &V) -> V
+    {
+        if let (&Node::Collision { entries: ref ours }, &Node::Collision { entries: ref theirs }) =
+               (self, other) {
+            let entries = ours.iter()
+                              .filter_map(|ours| {
+                                  theirs.iter()
+                                        .find(|theirs| theirs.0 == ours.0)
+                                        .map(|theirs| (ours.0.clone(), combine(&ours.1, &theirs.1)))
+                              })
+                              .collect();
+            return Node::Collision { entries: entries };
+        }
+        let slots = bits_of(self.occupied() & other.occupied()).map(|bit| {
+            let slot = match (self.slot(bit), other.slot(bit)) {
+                (Slot::Entry(ours), Slot::Entry(theirs)) => {
+                    if ours.0 == theirs.0 {
+                        Slot::Entry((ours.0.clone(), combine(&ours.1, &theirs.1)))
+                    } else {
+                        Slot::Empty
+                    }
+                }
+                (Slot::Entry(ours), Slot::Child(theirs)) => {
+                    match theirs.get(hash_of(hasher, &ours.0), shift + BITS, &ours.0) {
+                        Some(value) => Slot::Entry((ours.0.clone(), combine(&ours.1, value))),
+                        None => Slot::Empty,
+                    }
+                }
+                (Slot::Child(ours), Slot::Entry(theirs)) => {
+                    match ours.get(hash_of(hasher, &theirs.0), shift + BITS, &theirs.0) {
+                        Some(value) => Slot::Entry((theirs.0.clone(), combine(value, &theirs.1))),
+                        None => Slot::Empty,
+                    }
+                }
+                (Slot::Child(ours), Slot::Child(theirs)) => {
+                    if share && Shared::ptr_eq(ours, theirs) {
+                        Slot::Child(ours.clone())
+                    } else {
+                        ours.intersection_with(theirs, hasher, shift + BITS, share, combine)
+                            .into_slot()
+                    }
+                }
+                _ => Slot::Empty,
+            };
+            (bit, slot)
+        });
+        Node::from_slots(slots.collect())
+    }
+    /// Returns a node with the entries of this node whose keys are not in
+    /// `other`, both at level `shift`, and those whose keys are, bound to
+    /// `combine(ours, theirs)` where that is `Some`. If `share` is set,
+    /// children the two nodes share are taken to leave nothing.
+    fn difference_with<S, F>(&self,
+                             other: &Self,
+                             hasher: &S,
+                             shift: u32,
+                             share: bool,
+                             combine: &F)
+                             -> Self
+        where S: BuildHasher,
+              F: Fn(&V, &V) -> Option<V>
+    {
+        if let (&Node::Collision { entries: ref ours }, &Node::Collision { entries: ref theirs }) =
+               (self, other) {
+            let entries = ours.iter()
+                              .filter_map(|ours| {
+                                  match theirs.iter().find(|theirs| theirs.0 == ours.0) {
+                                      None => Some(ours.clone()),
+                                      Some(theirs) => {
+                                          combine(&ours.1, &theirs.1)
+                                              .map(|value| (ours.0.clone(), value))
+                                      }
+                                  }
+                              })
+                              .collect();
+            return Node::Collision { entries: entries };
+        }
+        let slots = bits_of(self.occupied()).map(|bit| {
+            let slot = match (self.slot(bit), other.slot(bit)) {
+                (Slot::Entry(ours), Slot::Empty) => Slot::Entry(ours.clone()),
+                (Slot::Child(ours), Slot::Empty) => Slot::Child(ours.clone()),
+                (Slot::Entry(ours), Slot::Entry(theirs)) => {
+                    if ours.0 == theirs.0 {
+                        match combine(&ours.1, &theirs.1) {
+                            Some(value) => Slot::Entry((ours.0.clone(), value)),
+                            None => Slot::Empty,
+                        }
+                    } else {
+                        Slot::Entry(ours.clone())
+                    }
+                }
+                (Slot::Entry(ours), Slot::Child(theirs)) => {
+                    match theirs.get(hash_of(hasher, &ours.0), shift + BITS, &ours.0) {
+                        Some(value) => {
+                            match combine(&ours.1, value) {
+                                Some(value) => Slot::Entry((ours.0.clone(), value)),
+                                None => Slot::Empty,
+                            }
+                        }
+                        None => Slot::Entry(ours.clone()),
+                    }
+                }
+                (Slot::Child(ours), Slot::Entry(theirs)) => {
+                    let hash = hash_of(hasher, &theirs.0);
+                    let combined = ours.get(hash, shift + BITS, &theirs.0)
+                                       .map(|value| combine(value, &theirs.1));
+                    match combined {
+                        None => Slot::Child(ours.clone()),
+                        Some(Some(value)) => {
+                            let replace = |_: &V, value: &V| value.clone();
+                            let (child, _) = ours.insert_with(hasher,
+                                                              hash,
+                                                              shift + BITS,
+                                                              theirs.0.clone(),
+                                                              value,
+                                                              &replace);
+                            Slot::Child(Shared::new(child))
+                        }
+                        Some(None) => {
+                            match ours.remove(hash, shift + BITS, &theirs.0) {
+                                Removal::NotFound => Slot::Child(ours.clone()),
+                                Removal::Removed(child) => child.into_slot(),
+                            }
+                        }
+                    }
+                }
+                (Slot::Child(ours), Slot::Child(theirs)) => {
+                    if share && Shared::ptr_eq(ours, theirs) {
+                        Slot::Empty
+                    } else {
+                        ours.difference_with(theirs, hasher, shift + BITS, share, combine)
+                            .into_slot()
+                    }
+                }
+                (Slot::Empty, _) => Slot::Empty,
+            };
+            (bit, slot)
+        });
+        Node::from_slots(slots.collect())
+    }
+    /// Records in `diff` the bindings which differ between this node and
+    /// `other`, which are at the same level, skipping the children they
+    /// share.
+    fn diff_into(&self, other: &Self, diff: &mut Diff<K, V>)
+        where V: PartialEq
+    {
+        if let (&Node::Collision { entries: ref ours }, &Node::Collision { entries: ref theirs }) =
+               (self, other) {
+            diff_entries(ours, theirs, diff);
+            return;
+        }
+        for bit in bits_of(self.occupied() | other.occupied()) {
+            match (self.slot(bit), other.slot(bit)) {
+                (Slot::Child(ours), Slot::Child(theirs)) => {
+                    if !Shared::ptr_eq(ours, theirs) {
+                        ours.diff_into(theirs, diff);
+                    }
+                }
+                (ours, theirs) => {
+                    let mut ours_entries = Vec::new();
+                    let mut theirs_entries = Vec::new();
+                    ours.collect_into(&mut ours_entries);
+                    theirs.collect_into(&mut theirs_entries);
+                    diff_entries(&ours_entries, &theirs_entries, diff);
+                }
+            }
+        }
+    }
+    /// Pushes every entry in this node and the nodes below it onto `out`.
+    fn collect_into(&self, out: &mut Vec<(K, V)>) {
+        match *self {
+            Node::Branch { ref entries, ref children, .. } => {
+                out.extend(entries.iter().cloned());
+                for child in children {
+                    child.collect_into(out);
+                }
+            }
+            Node::Collision { ref entries } => out.extend(entries.iter().cloned()),
+        }
+    }
+    /// Returns the hash of the entries in this node and the nodes below it,
+    /// working it out only if it has not been already. The hash of each
+    /// entry is mixed and the results summed, so it does not depend on the
+    /// order of the entries or the shape of the trie.
+    fn content_hash(&self) -> u64
+        where V: Hash
+    {
+        match *self {
+            Node::Branch { ref entries, ref children, ref content_hash, .. } => {
+                *content_hash.get_or_init(|| {
+                    children.iter()
+                            .map(|child| child.content_hash())
+                            .fold(entries_hash(entries), u64::wrapping_add)
+                })
+            }
+            Node::Collision { ref entries } => entries_hash(entries),
+        }
+    }
+    /// Tests whether this node and `other`, which are at the same level of
+    /// tries whose keys are hashed the same way, hold the same entries.
+    /// Children the two share are not visited, and nodes whose hashes have
+    /// been cached are only visited if their hashes agree.
+    fn equals(&self, other: &Self) -> bool
+        where V: PartialEq
+    {
+        self.equals_shallowly(other) &&
+        self.children().iter().zip(other.children()).all(|(ours, theirs)| {
+            Shared::ptr_eq(ours, theirs) || ours.equals(theirs)
+        })
+    }
+    /// Tests whether this node and `other` agree on everything but their
+    /// children.
+    fn equals_shallowly(&self, other: &Self) -> bool
+        where V: PartialEq
+    {
+        match (self, other) {
+            (Node::Branch { size, datamap, nodemap, entries, content_hash, .. },
+             Node::Branch { size: other_size,
+                            datamap: other_datamap,
+                            nodemap: other_nodemap,
+                            entries: other_entries,
+                            content_hash: other_content_hash,
+                            .. }) => {
+                if let (Some(ours), Some(theirs)) = (content_hash.get(), other_content_hash.get()) {
+                    if ours != theirs {
+                        return false;
+                    }
+                }
+                size == other_size && datamap == other_datamap && nodemap == other_nodemap &&
+                entries == other_entries
+            }
+            (Node::Collision { entries: ours }, Node::Collision { entries: theirs }) => {
+                ours.len() == theirs.len() && ours.iter().all(|entry| theirs.contains(entry))
+            }
+            _ => false,
+        }
+    }
+    /// Returns the children of this node, in fragment order.
+    fn children(&self) -> &[Shared<Self, P>] {
+        match *self {
+            Node::Branch { ref children, .. } => children,
+            Node::Collision { .. } => &[],
+        }
+    }
+    /// Checks the invariants of this node and the nodes below it, where this
+    /// node is at level `shift` and every key below it has a hash whose low
+    /// `shift` bits are `prefix`.
+    fn check<S: BuildHasher>(&self, hasher: &S, shift: u32, prefix: u64) -> Result<(), String>
+        where K: Debug
+    {
+        self.check_node(hasher, shift, prefix)?;
+        if let Node::Branch { nodemap, ref children, .. } = *self {
+            for (bit, child) in bits_of(nodemap).zip(children) {
+                child.check(hasher, shift + BITS, prefix | fragment_of(bit) << shift)?;
+            }
+        }
+        Ok(())
+    }
+    /// Checks the invariants of this node alone, without looking below its
+    /// children. See `check`.
+    fn check_node<S: BuildHasher>(&self, hasher: &S, shift: u32, prefix: u64) -> Result<(), String>
+        where K: Debug
+    {
+        match *self {
+            Node::Branch { size, datamap, nodemap, ref entries, ref children, .. } => {
+                if shift >= 64 {
+                    return Err(format!("a branch appears below the last level, at shift {}",
+                                       shift));
+                }
+                if datamap & nodemap != 0 {
+                    return Err(format!("a branch at shift {} has an entry and a child for the \
+                                        same fragment",
+                                       shift));
+                }
+                if entries.len() != datamap.count_ones() as usize ||
+                   children.len() != nodemap.count_ones() as usize {
+                    return Err(format!("a branch at shift {} has {} entries and {} children, \
+                                        which its bitmaps disagree with",
+                                       shift,
+                                       entries.len(),
+                                       children.len()));
+                }
+                for (bit, entry) in bits_of(datamap).zip(entries) {
+                    let hash = hash_of(hasher, &entry.0);
+                    if bit_for(hash, shift) != bit || low_bits(hash, shift) != prefix {
+                        return Err(format!("{:?} is not where its hash leads", entry.0));
+                    }
+                }
+                for child in children {
+                    match **child {
+                        Node::Branch { size, .. } if size < 2 => {
+                            return Err(format!("a branch at shift {} holds {} entries, and \
+                                                should have been inlined into its parent",
+                                               shift + BITS,
+                                               size));
+                        }
+                        Node::Collision { .. } if shift + BITS < 64 => {
+                            return Err(format!("a collision appears above the last level, at \
+                                                shift {}",
+                                               shift + BITS));
+                        }
+                        _ => {}
+                    }
+                }
+                let held = children.iter().fold(entries.len() as u32, |held, child| {
+                    held + child.size()
+                });
+                if size != held {
+                    return Err(format!("a branch at shift {} has a size of {} but holds {} \
+                                        entries",
+                                       shift,
+                                       size,
+                                       held));
+                }
+                Ok(())
+            }
+            Node::Collision { ref entries } => {
+                if entries.len() < 2 {
+                    return Err(format!("a collision holds {} entries", entries.len()));
+                }
+                for (i, entry) in entries.iter().enumerate() {
+                    if hash_of(hasher, &entry.0) != prefix {
+                        return Err(format!("{:?} is not where its hash leads", entry.0));
+                    }
+                    if entries[..i].iter().any(|earlier| earlier.0 == entry.0) {
+                        return Err(format!("{:?} is bound twice in a collision", entry.0));
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+    /// Returns a copy of this node and the nodes below it with `f` applied to
+    /// each value, visiting the values in the same order as `Iter`.
+    fn map_values<W: Clone, F: FnMut(&V) -> W>(&self, f: &mut F) -> Node<K, W, P> {
+        match *self {
+            Node::Branch { size, datamap, nodemap, ref entries, ref children, .. } => {
+                let entries = entries.iter()
+                                     .map(|(key, value)| (key.clone(), f(value)))
+                                     .collect();
+                Node::Branch {
+                    size: size,
+                    datamap: datamap,
+                    nodemap: nodemap,
+                    entries: entries,
+                    children: children.iter()
+                                      .map(|child| Shared::new(child.map_values(f)))
+                                      .collect(),
+                    content_hash: OnceLock::new(),
+                }
+            }
+            Node::Collision { ref entries } => {
+                Node::Collision {
+                    entries: entries.iter()
+                                    .map(|(key, value)| (key.clone(), f(value)))
+                                    .collect(),
+                }
+            }
+        }
+    }
+    /// Returns the only entry in this node, or the node itself if it does not
+    /// contain exactly one entry.
+    fn into_singleton(self) -> Result<(K, V), Self> {
+        let single = match self {
+            Node::Branch { ref entries, ref children, .. } => {
+                entries.len() == 1 && children.is_empty()
+            }
+            Node::Collision { ref entries } => entries.len() == 1,
+        };
+        if !single {
+            return Err(self);
+        }
+        match self {
+            Node::Branch { mut entries, .. } |
+            Node::Collision { mut entries } => Ok(entries.pop().unwrap()),
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: Hash + Eq + Clone, V: Clone> Node<K, V, DefaultPointer> {
+    /// Like `equals`, but for nodes above `PARALLEL_SHIFT` compares the
+    /// children as separate tasks.
+    fn par_equals(&self, other: &Self, shift: u32) -> bool
+        where K: MaybeSend + MaybeSync,
+              V: PartialEq + MaybeSend + MaybeSync
+    {
+        if shift >= PARALLEL_SHIFT {
+            return self.equals(other);
+        }
+        let (ours, theirs) = (self.children(), other.children());
+        self.equals_shallowly(other) &&
+        fork_all(0..ours.len(), &|i| {
+                if Shared::ptr_eq(&ours[i], &theirs[i]) ||
+                   ours[i].par_equals(&theirs[i], shift + BITS) {
+                    Ok(())
+                } else {
+                    Err(())
+                }
+            })
+            .is_ok()
+    }
+    /// Like `check`, but for nodes above `PARALLEL_SHIFT` checks the
+    /// children as separate tasks.
+    fn par_check<S>(&self, hasher: &S, shift: u32, prefix: u64) -> Result<(), String>
+        where K: Debug + MaybeSend + MaybeSync,
+              V: MaybeSend + MaybeSync,
+              S: BuildHasher + MaybeSync
+    {
+        if shift >= PARALLEL_SHIFT {
+            return self.check(hasher, shift, prefix);
+        }
+        self.check_node(hasher, shift, prefix)?;
+        match *self {
+            Node::Branch { nodemap, ref children, .. } => {
+                let prefixes: Vec<u64> = bits_of(nodemap)
+                                             .map(|bit| prefix | fragment_of(bit) << shift)
+                                             .collect();
+                fork_all(0..children.len(), &|i| {
+                    children[i].par_check(hasher, shift + BITS, prefixes[i])
+                })
+            }
+            Node::Collision { .. } => Ok(()),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq + Clone, V: Clone, P: SharedPointer> Slot<&'a (K, V), &'a Child<K, V, P>> {
+    /// Pushes every entry this slot holds onto `out`.
+    fn collect_into(&self, out: &mut Vec<(K, V)>) {
+        match *self {
+            Slot::Empty => {}
+            Slot::Entry(entry) => out.push(entry.clone()),
+            Slot::Child(child) => child.collect_into(out),
+        }
+    }
+}
+
+/// Records in `diff` the bindings which differ between two lists of entries
+/// for the same part of the trie, comparing each against each, which is
+/// fine since at most one of the lists is ever long.
+fn diff_entries<K: Eq + Clone, V: Clone + PartialEq>(ours: &[(K, V)],
+                                                     theirs: &[(K, V)],
+                                                     diff: &mut Diff<K, V>) {
+    for &(ref key, ref value) in ours {
+        match theirs.iter().find(|entry| entry.0 == *key) {
+            Some(&(_, ref new)) if new != value => {
+                diff.changed.push((key.clone(), value.clone(), new.clone()))
+            }
+            Some(_) => {}
+            None => diff.removed.push((key.clone(), value.clone())),
+        }
+    }
+    for entry in theirs {
+        if !ours.iter().any(|ours| ours.0 == entry.0) {
+            diff.added.push(entry.clone());
+        }
+    }
+}
+
+/// An iterator over the bindings of a `HamtMap`, returned by `HamtMap::iter`.
+pub struct Iter<'a, K: 'a, V: 'a, P: SharedPointer> {
+    /// The children of each branch on the path to the current position which
+    /// are still to be visited.
+    branches: Vec<slice::Iter<'a, Child<K, V, P>>>,
+    /// The rest of the entries of the node currently being visited.
+    entries: slice::Iter<'a, (K, V)>,
+}
+
+impl<'a, K, V, P: SharedPointer> Iter<'a, K, V, P> {
+    /// Moves on to the next node to be visited, returning false if there are
+    /// none left.
+    fn descend(&mut self) -> bool {
+        loop {
+            let node = match self.branches.last_mut() {
+                None => return false,
+                Some(children) => children.next(),
+            };
+            match node.map(|node| &**node) {
+                None => {
+                    self.branches.pop();
+                }
+                Some(&Node::Branch { ref entries, ref children, .. }) => {
+                    self.entries = entries.iter();
+                    self.branches.push(children.iter());
+                    return true;
+                }
+                Some(&Node::Collision { ref entries }) => {
+                    self.entries = entries.iter();
+                    return true;
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K, V, P: SharedPointer> Iterator for Iter<'a, K, V, P> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some((&entry.0, &entry.1));
+            }
+            if !self.descend() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, K, V, P: SharedPointer> Split for Iter<'a, K, V, P> {
+    fn split(&mut self) -> Option<Self> {
+        loop {
+            // Split the children of the shallowest branch with more than one
+            // left to visit, taking the deeper branches' children with them.
+            if let Some(level) = self.branches.iter().position(|children| children.len() > 1) {
+                let (front, back) = {
+                    let children = self.branches[level].as_slice();
+                    children.split_at(children.len() / 2)
+                };
+                self.branches[level] = front.iter();
+                let mut branches: Vec<_> = self.branches.drain(..level).collect();
+                branches.push(back.iter());
+                return Some(Iter {
+                    branches: branches,
+                    entries: [].iter(),
+                });
+            }
+            if self.branches.iter().any(|children| children.len() == 1) {
+                if self.entries.len() > 0 {
+                    return Some(Iter {
+                        branches: self.branches.drain(..).collect(),
+                        entries: [].iter(),
+                    });
+                }
+                self.descend();
+                continue;
+            }
+            let entries = self.entries.as_slice();
+            if entries.len() < 2 {
+                return None;
+            }
+            let (front, back) = entries.split_at(entries.len() / 2);
+            self.entries = front.iter();
+            return Some(Iter {
+                branches: Vec::new(),
+                entries: back.iter(),
+            });
+        }
+    }
+}
+
+/// An iterator over the keys of a `HamtMap`, returned by `HamtMap::keys`.
+pub struct Keys<'a, K: 'a, V: 'a, P: SharedPointer> {
+    iter: Iter<'a, K, V, P>,
+}
+
+impl<'a, K, V, P: SharedPointer> Iterator for Keys<'a, K, V, P> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.iter.next().map(|(key, _)| key)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, K, V, P: SharedPointer> Split for Keys<'a, K, V, P> {
+    fn split(&mut self) -> Option<Self> {
+        self.iter.split().map(|iter| Keys { iter: iter })
+    }
+}
+
+/// An iterator over the bindings of a `HamtMap` in order of key, returned by
+/// `HamtMap::iter_sorted`.
+pub struct SortedIter<'a, K: 'a + Ord, V: 'a> {
+    heap: BinaryHeap<ByKey<'a, K, V>>,
+}
+
+/// A binding in a `SortedIter`'s heap, ordered so that the least key is
+/// greatest and so is taken first.
+struct ByKey<'a, K: 'a, V: 'a>(&'a K, &'a V);
+
+impl<'a, K: Ord, V> PartialEq for ByKey<'a, K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<'a, K: Ord, V> Eq for ByKey<'a, K, V> {}
+
+impl<'a, K: Ord, V> PartialOrd for ByKey<'a, K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, K: Ord, V> Ord for ByKey<'a, K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.cmp(self.0)
+    }
+}
+
+impl<'a, K: Ord, V> Iterator for SortedIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.heap.pop().map(|ByKey(key, value)| (key, value))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+/// A monoidal aggregate over the bindings of a `HamtMap`, such as a sum or a
+/// count, which is kept up to date cheaply as the map changes.
+///
+/// The fold remembers the aggregate of every subtrie of the last version of
+/// the map it was given. Given a new version, it reuses the aggregate of any
+/// subtrie the two versions share, so after a few updates to a large map it
+/// only visits the O(log32 n) nodes on the paths to them. This works with
+/// any `Monoid`, including ones such as `Max` which cannot be updated by
+/// subtracting old values.
+///
+/// The bindings are combined in no particular order, so the monoid should
+/// be commutative for the aggregate to be meaningful.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::hamt::{HamtMap, IncrementalFold};
+/// use functional_datastructures::measure::{Max, Sum};
+///
+/// let map = (0..10000).fold(HamtMap::new(), |map, i| map.insert(i, i as u64));
+/// let mut total = IncrementalFold::new(|_: &u32, value: &u64| Sum(*value));
+/// let mut largest = IncrementalFold::new(|_: &u32, value: &u64| Max(Some(*value)));
+///
+/// assert!(total.fold(&map) == Sum(49995000));
+/// assert!(total.fold(&map.insert(0, 5)) == Sum(49995005));
+/// assert!(largest.fold(&map.remove(&9999)) == Max(Some(9998)));
+/// ```
+pub struct IncrementalFold<K, V, M, F, P: SharedPointer> {
+    f: F,
+    cache: Option<FoldedChild<K, V, M, P>>,
+}
+
+/// The aggregate of a node, and the aggregates of its children in the same
+/// order as the node's children.
+struct Folded<K, V, M, P: SharedPointer> {
+    node: Child<K, V, P>,
+    total: M,
+    children: Vec<FoldedChild<K, V, M, P>>,
+}
+
+/// The aggregate of a child, shared with the caches of the other versions
+/// of the map that hold the child.
+type FoldedChild<K, V, M, P> = Shared<Folded<K, V, M, P>, P>;
+
+impl<K, V, M, F, P: SharedPointer> IncrementalFold<K, V, M, F, P>
+    where K: Hash + Eq + Clone,
+          V: Clone,
+          M: Monoid,
+          F: Fn(&K, &V) -> M
+{
+    /// Returns a fold which maps each binding to an aggregate with `f`, and
+    /// combines them.
+    pub fn new(f: F) -> Self {
+        IncrementalFold {
+            f: f,
+            cache: None,
+        }
+    }
+    /// Returns the aggregate of the bindings in `map`, reusing what is
+    /// remembered of the last map folded, and remembers `map` in its place.
+    pub fn fold<S>(&mut self, map: &HamtMapIn<K, V, P, S>) -> M {
+        let folded = self.refold(&map.root, self.cache.as_ref());
+        let total = folded.total.clone();
+        self.cache = Some(folded);
+        total
+    }
+    /// Returns the aggregate of the last map folded, or `M::empty()` if none
+    /// has been.
+    pub fn total(&self) -> M {
+        self.cache.as_ref().map_or_else(M::empty, |folded| folded.total.clone())
+    }
+    /// Folds `node`, given what was remembered of the node which was in the
+    /// same place in the last map folded, if there was one.
+    fn refold(&self,
+              node: &Child<K, V, P>,
+              old: Option<&FoldedChild<K, V, M, P>>)
+              -> FoldedChild<K, V, M, P> {
+        if let Some(old) = old {
+            if Shared::ptr_eq(&old.node, node) {
+                return old.clone();
+            }
+        }
+        let (entries, children) = match **node {
+            Node::Branch { nodemap, ref entries, ref children, .. } => {
+                let old_children = old.and_then(|old| match *old.node {
+                    Node::Branch { nodemap: old_nodemap, .. } => Some((old_nodemap, old)),
+                    Node::Collision { .. } => None,
+                });
+                let folded = bits_of(nodemap).zip(children).map(|(bit, child)| {
+                    let old_child = old_children.and_then(|(old_nodemap, old)| {
+                        if old_nodemap & bit != 0 {
+                            Some(&old.children[index_of(old_nodemap, bit)])
+                        } else {
+                            None
+                        }
+                    });
+                    self.refold(child, old_child)
+                });
+                (entries, folded.collect::<Vec<_>>())
+            }
+            Node::Collision { ref entries } => (entries, Vec::new()),
+        };
+        let total = entries.iter()
+                           .map(|&(ref key, ref value)| (self.f)(key, value))
+                           .chain(children.iter().map(|child| child.total.clone()))
+                           .fold(M::empty(), |total, part| total.combine(&part));
+        Shared::new(Folded {
+            node: node.clone(),
+            total: total,
+            children: children,
+        })
+    }
+}
+
+/// An immutable hash set, implemented as a `HamtMap` with no values.
+///
+/// Membership tests, insertions and removals take O(log32 n) time. The set
+/// algebra operations merge the two tries node by node, as for `HamtMap`, and
+/// members are hashed with an `S: BuildHasher` in the same way.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::hamt::HamtSet;
+/// use functional_datastructures::set::Set;
+///
+/// let odds = HamtSet::new().insert(1).insert(3).insert(5);
+/// let small = HamtSet::new().insert(1).insert(2).insert(3);
+///
+/// assert!(odds.member(3));
+/// assert!(!odds.remove(&3).contains(&3));
+///
+/// assert!(odds.union(&small).size() == 4);
+/// assert!(odds.intersection(&small).size() == 2);
+/// assert!(odds.difference(&small).contains(&5));
+/// ```
+#[derive(Debug, Clone)]
+pub struct HamtSetIn<T, P: SharedPointer, S = FxBuildHasher> {
+    map: HamtMapIn<T, (), P, S>,
+}
+
+/// A `HamtSetIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type HamtSet<T, S = FxBuildHasher> = HamtSetIn<T, DefaultPointer, S>;
+
+impl<T: Hash + Eq + Clone, P: SharedPointer> HamtSetIn<T, P> {
+    /// Returns an empty set which hashes its members with the default
+    /// `FxBuildHasher`.
+    pub fn new() -> Self {
+        HamtSetIn::with_hasher(FxBuildHasher::default())
+    }
+}
+
+impl<T: Hash + Eq + Clone, S: BuildHasher + Clone, P: SharedPointer> HamtSetIn<T, P, S> {
+    /// Returns an empty set which hashes its members with `hasher`.
+    pub fn with_hasher(hasher: S) -> Self {
+        HamtSetIn { map: HamtMapIn::with_hasher(hasher) }
+    }
+    /// Returns the hasher this set hashes its members with.
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+    /// Tests whether `value` is a member of this set.
+    pub fn contains(&self, value: &T) -> bool {
+        self.map.contains_key(value)
+    }
+    /// Returns a copy of this set without `value`.
+    pub fn remove(&self, value: &T) -> Self {
+        HamtSetIn { map: self.map.remove(value) }
+    }
+    /// Removes `value` from this set in place, as by `HamtMap::remove_mut`.
+    pub fn remove_mut(&mut self, value: &T) {
+        self.map.remove_mut(value)
+    }
+    /// Tests whether this set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+    /// Returns the number of members of this set.
+    pub fn size(&self) -> u32 {
+        self.map.size()
+    }
+    /// Returns an iterator over the members of this set, in no particular
+    /// order.
+    pub fn iter(&self) -> Keys<'_, T, (), P> {
+        self.map.keys()
+    }
+    /// Returns a member of this set chosen uniformly at random, or `None` if
+    /// it is empty, as by `HamtMap::sample`.
+    #[cfg(feature = "rand")]
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<&T> {
+        self.map.sample(rng).map(|(value, _)| value)
+    }
+    /// Returns `amount` distinct members of this set chosen uniformly at
+    /// random, as by `HamtMap::sample_n`.
+    #[cfg(feature = "rand")]
+    pub fn sample_n<R: Rng + ?Sized>(&self, rng: &mut R, amount: usize) -> Vec<&T> {
+        self.map.sample_n(rng, amount).into_iter().map(|(value, _)| value).collect()
+    }
+    /// Returns a set of the values which are members of either this set or
+    /// `other`.
+    pub fn union(&self, other: &Self) -> Self {
+        HamtSetIn { map: self.map.union(&other.map) }
+    }
+    /// Returns a set of the values which are members of both this set and
+    /// `other`.
+    pub fn intersection(&self, other: &Self) -> Self {
+        HamtSetIn { map: self.map.intersection(&other.map) }
+    }
+    /// Returns a set of the values which are members of this set but not of
+    /// `other`.
+    pub fn difference(&self, other: &Self) -> Self {
+        HamtSetIn { map: self.map.difference(&other.map) }
+    }
+    /// Tests whether every member of this set is also a member of `other`.
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.size() <= other.size() && self.iter().all(|value| other.contains(value))
+    }
+    /// Returns a hash of the members of this set, cached in its nodes as
+    /// for `HamtMap::content_hash`.
+    pub fn content_hash(&self) -> u64 {
+        self.map.content_hash()
+    }
+}
+
+impl<T, P, S> Collection for HamtSetIn<T, P, S>
+    where T: Hash + Eq + Clone,
+          P: SharedPointer,
+          S: BuildHasher + Clone
+{
+    type Iter<'a> = Keys<'a, T, (), P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Keys<'a, T, (), P> {
+        self.iter()
+    }
+}
+
+impl<T, S, P: SharedPointer> MemoryUsage for HamtSetIn<T, P, S> {
+    fn census(&self, census: &mut Census) {
+        self.map.census(census)
+    }
+}
+
+impl<T, S, P: SharedPointer> Default for HamtSetIn<T, P, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Clone + Default
+{
+    fn default() -> Self {
+        HamtSetIn::with_hasher(S::default())
+    }
+}
+
+impl<T, S, P: SharedPointer> PartialEq for HamtSetIn<T, P, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Clone
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<T, S, P: SharedPointer> Eq for HamtSetIn<T, P, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Clone
+{
+}
+
+impl<T, S, P: SharedPointer> Hash for HamtSetIn<T, P, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Clone
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.map.hash(state)
+    }
+}
+
+impl<T, S, P: SharedPointer> Set<T> for HamtSetIn<T, P, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Clone + Default
+{
+    fn empty() -> Self {
+        HamtSetIn { map: HamtMapIn::empty_map() }
+    }
+    fn insert(&self, value: T) -> Self {
+        HamtSetIn { map: self.map.insert(value, ()) }
+    }
+    fn member(&self, value: T) -> bool {
+        self.contains(&value)
+    }
+    fn insert_mut(&mut self, value: T) {
+        self.map.insert_mut(value, ())
+    }
+}
+
+impl<T, S, P: SharedPointer> PersistentSet<T> for HamtSetIn<T, P, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Clone + Default
+{
+    fn contains(&self, value: &T) -> bool {
+        HamtSetIn::contains(self, value)
+    }
+    fn remove(&self, value: &T) -> Self {
+        HamtSetIn::remove(self, value)
+    }
+    fn union(&self, other: &Self) -> Self {
+        HamtSetIn::union(self, other)
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        HamtSetIn::intersection(self, other)
+    }
+    fn difference(&self, other: &Self) -> Self {
+        HamtSetIn::difference(self, other)
+    }
+}
+
+fn hash_of<K: Hash, S: BuildHasher>(hasher: &S, key: &K) -> u64 {
+    hasher.hash_one(key)
+}
+
+/// Returns the sum of the mixed hashes of `entries`, which does not depend
+/// on their order.
+fn entries_hash<K: Hash, V: Hash>(entries: &[(K, V)]) -> u64 {
+    entries.iter()
+           .map(|entry| {
+               // The finalizer of SplitMix64, so that the hashes of similar
+               // entries do not cancel out when summed.
+               let mut hash = hash_of(&FxBuildHasher::default(), entry);
+               hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+               hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+               hash ^ (hash >> 31)
+           })
+           .fold(0, u64::wrapping_add)
+}
+
+/// Returns each bit which is set in `bitmap`, lowest first.
+fn bits_of(bitmap: u32) -> impl Iterator<Item = u32> {
+    (0..32).map(|i| 1 << i).filter(move |bit| bitmap & bit != 0)
+}
+
+/// Returns the fragment of a hash which selects `bit` of a branch's bitmap.
+fn fragment_of(bit: u32) -> u64 {
+    bit.trailing_zeros() as u64
+}
+
+/// Returns the low `shift` bits of `hash`, which select the branches above
+/// level `shift`.
+fn low_bits(hash: u64, shift: u32) -> u64 {
+    if shift >= 64 {
+        hash
+    } else {
+        hash & ((1 << shift) - 1)
+    }
+}
+
+/// Returns the first error `check` gives for the numbers in `range`,
+/// checking the two halves of the range as separate tasks, and the halves
+/// of those in turn.
+#[cfg(feature = "rayon")]
+fn fork_all<E, F>(range: Range<usize>, check: &F) -> Result<(), E>
+    where E: MaybeSend,
+          F: Fn(usize) -> Result<(), E> + MaybeSync
+{
+    if range.len() <= 1 {
+        return range.clone().try_for_each(check);
+    }
+    let middle = range.start + range.len() / 2;
+    let (first, second) = join(|| fork_all(range.start..middle, check),
+                               || fork_all(middle..range.end, check));
+    first.and(second)
+}
+
+/// Returns the bit of a branch's bitmap which `hash` selects at level `shift`.
+fn bit_for(hash: u64, shift: u32) -> u32 {
+    1 << ((hash >> shift) & MASK)
+}
+
+/// Returns the position in a branch's children of the child for `bit`.
+fn index_of(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+
+#[cfg(test)]
+use std::collections::hash_map::RandomState;
+#[cfg(test)]
+use measure::{Count, Max, Sum};
+#[cfg(test)]
+use intmap::{IntMap, IntSet};
+#[cfg(test)]
+use set::Tree;
+
+/// A key whose hash only depends on `value / 4`, so that keys collide.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Colliding(u32);
+
+#[cfg(test)]
+impl Hash for Colliding {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0 / 4).hash(state);
+    }
+}
+
+#[test]
+fn empty_map_contains_nothing() {
+    let map: HamtMap<u32, u32> = HamtMap::new();
+
+    assert!(map.lookup(0).is_none());
+    assert!(map.is_empty());
+    assert!(map.remove(&0).is_empty());
+}
+
+#[test]
+fn many_keys_can_be_inserted_and_found() {
+    let map = (0..2000).fold(HamtMap::new(), |map, i| map.insert(i, i * 2));
+
+    assert!(map.size() == 2000);
+    for i in 0..2000 {
+        assert!(map.lookup(i).unwrap() == i * 2);
+    }
+    assert!(map.lookup(2000).is_none());
+}
+
+#[test]
+fn values_can_be_replaced() {
+    let map1 = HamtMap::new().insert(1, "one").insert(2, "two");
+    let map2 = map1.insert(2, "not two");
+
+    assert!(map1.lookup(2).unwrap() == "two");
+    assert!(map2.lookup(2).unwrap() == "not two");
+    assert!(map2.size() == 2);
+}
+
+#[test]
+fn removed_keys_are_gone() {
+    let map = (0..500).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let evens = (0..500).filter(|i| i % 2 == 1).fold(map.clone(), |map, i| map.remove(&i));
+
+    assert!(evens.size() == 250);
+    for i in 0..500 {
+        assert!(evens.contains_key(&i) == (i % 2 == 0));
+        assert!(map.contains_key(&i));
+    }
+    let empty = (0..500).fold(evens, |map, i| map.remove(&i));
+    assert!(empty.is_empty());
+    assert!(empty.lookup(0).is_none());
+}
+
+#[test]
+fn removing_missing_key_changes_nothing() {
+    let map = HamtMap::new().insert(1, 1);
+
+    assert!(map.remove(&2).size() == 1);
+    assert!(map.remove(&2).lookup(1).unwrap() == 1);
+}
+
+#[test]
+fn colliding_keys_are_kept_apart() {
+    let map = (0..12).fold(HamtMap::new(), |map, i| map.insert(Colliding(i), i));
+
+    assert!(map.size() == 12);
+    for i in 0..12 {
+        assert!(map.lookup(Colliding(i)).unwrap() == i);
+    }
+
+    let replaced = map.insert(Colliding(5), 50);
+    assert!(replaced.size() == 12);
+    assert!(replaced.lookup(Colliding(5)).unwrap() == 50);
+    assert!(replaced.lookup(Colliding(4)).unwrap() == 4);
+
+    let removed = map.remove(&Colliding(5)).remove(&Colliding(6)).remove(&Colliding(7));
+    assert!(removed.size() == 9);
+    assert!(removed.lookup(Colliding(5)).is_none());
+    assert!(removed.lookup(Colliding(4)).unwrap() == 4);
+}
+
+#[test]
+fn removals_leave_entries_inline() {
+    let map = (0..1000).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let one = (0..1000).filter(|i| *i != 7).fold(map, |map, i| map.remove(&i));
+
+    match *one.root {
+        Node::Branch { ref entries, ref children, .. } => {
+            assert!(entries.len() == 1);
+            assert!(children.is_empty());
+        }
+        Node::Collision { .. } => panic!("root should be a branch"),
+    }
+    assert!(one.lookup(7).unwrap() == 7);
+}
+
+#[test]
+fn iter_visits_every_binding_once() {
+    let map = (0..1000).fold(HamtMap::new(), |map, i| map.insert(i, i + 1));
+    let colliding = (0..10).fold(HamtMap::new(), |map, i| map.insert(Colliding(i), i));
+
+    let mut bindings: Vec<(u32, u32)> = map.iter().map(|(k, v)| (*k, *v)).collect();
+    bindings.sort();
+    assert!(bindings == (0..1000).map(|i| (i, i + 1)).collect::<Vec<_>>());
+
+    let mut keys: Vec<u32> = colliding.keys().map(|k| k.0).collect();
+    keys.sort();
+    assert!(keys == (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn sorted_iteration_is_in_key_order() {
+    let forwards = (0..1000).fold(HamtMap::new(), |map, i| map.insert(i * 7 % 1000, i));
+    let backwards = (0..1000).rev().fold(HamtMap::new(), |map, i| map.insert(i * 7 % 1000, i));
+    let sorted: Vec<(u32, u32)> = forwards.iter_sorted().map(|(&k, &v)| (k, v)).collect();
+
+    assert!(sorted.len() == 1000 && sorted.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert!(sorted.iter().all(|&(key, value)| value * 7 % 1000 == key));
+    assert!(forwards.iter_sorted().take(3).map(|(&k, _)| k).collect::<Vec<_>>() == vec![0, 1, 2]);
+    assert!(forwards.iter().eq(backwards.iter()));
+    assert!(forwards.iter_sorted().size_hint() == (1000, Some(1000)));
+
+    let colliding = (0..8).rev().fold(HamtMap::new(), |map, i| map.insert(Colliding(i), i));
+    assert!(colliding.iter_sorted().map(|(_, &v)| v).eq(0..8));
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn samples_are_spread_over_every_binding() {
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    let mut rng = XorShiftRng::seed_from_u64(7);
+    let map = (0..100).fold(HamtMap::new(), |map, i| map.insert(i, i * 2));
+    let mut counts = vec![0; 100];
+    for _ in 0..100000 {
+        let (&key, &value) = map.sample(&mut rng).unwrap();
+        assert!(value == key * 2);
+        counts[key as usize] += 1;
+    }
+    assert!(counts.iter().all(|&count| count > 800 && count < 1200));
+    assert!(HamtMap::<u32, u32>::new().sample(&mut rng).is_none());
+
+    let set = (0..8).fold(HamtSet::new(), |set, i| set.insert(Colliding(i)));
+    let mut drawn: Vec<u32> = set.sample_n(&mut rng, 5).into_iter().map(|c| c.0).collect();
+    drawn.sort();
+    drawn.dedup();
+    assert!(drawn.len() == 5 && drawn.iter().all(|&i| i < 8));
+    assert!(set.sample_n(&mut rng, 20).len() == 8 && set.sample(&mut rng).is_some());
+}
+
+#[test]
+fn grouped_values_keep_the_order_of_iteration() {
+    let map = (0..1000).fold(HamtMap::new(), |map, i| map.insert(i, i * 2));
+    let groups = map.group_by(|_, value| value % 3);
+    let order: Vec<u32> = map.iter().map(|(_, &value)| value).collect();
+
+    assert!(groups.size() == 3 && groups.check_invariants().is_ok());
+    for class in 0..3 {
+        let expected: Vec<u32> = order.iter().cloned().filter(|value| value % 3 == class).collect();
+        assert!(groups.get(&class).unwrap().iter().cloned().collect::<Vec<_>>() == expected);
+    }
+}
+
+#[test]
+fn bulk_operations_match_per_key_results() {
+    let threes = (0..600).filter(|i| i % 3 == 0).fold(HamtMap::new(), |m, i| m.insert(i, 1));
+    let fives = (0..600).filter(|i| i % 5 == 0).fold(HamtMap::new(), |m, i| m.insert(i, 10));
+
+    let sum = threes.union_with(&fives, &|a, b| a + b);
+    assert!(sum.size() == (0..600).filter(|i| i % 3 == 0 || i % 5 == 0).count() as u32);
+    for i in 0..600 {
+        let expected = match (i % 3 == 0, i % 5 == 0) {
+            (true, true) => Some(11),
+            (true, false) => Some(1),
+            (false, true) => Some(10),
+            (false, false) => None,
+        };
+        assert!(sum.lookup(i) == expected);
+        assert!(threes.union(&fives).lookup(i) == expected.map(|v| if v == 10 { 10 } else { 1 }));
+        assert!(threes.intersection(&fives).lookup(i) ==
+                if i % 15 == 0 { Some(1) } else { None });
+        assert!(threes.difference(&fives).contains_key(&i) == (i % 3 == 0 && i % 5 != 0));
+    }
+    assert!(threes.intersection(&fives).size() == 40);
+    assert!(threes.difference(&fives).size() == 160);
+    assert!(threes.difference(&threes).is_empty());
+}
+
+#[test]
+fn bulk_operations_handle_collisions() {
+    let low = (0..8).fold(HamtMap::new(), |m, i| m.insert(Colliding(i), i));
+    let high = (4..12).fold(HamtMap::new(), |m, i| m.insert(Colliding(i), i * 10));
+
+    let union = low.union(&high);
+    assert!(union.size() == 12);
+    assert!(union.lookup(Colliding(5)).unwrap() == 5);
+    assert!(union.lookup(Colliding(9)).unwrap() == 90);
+
+    let intersection = low.intersection(&high);
+    assert!(intersection.size() == 4);
+    assert!(intersection.lookup(Colliding(6)).unwrap() == 6);
+
+    let difference = low.difference(&high);
+    assert!(difference.size() == 4);
+    assert!(difference.lookup(Colliding(3)).unwrap() == 3);
+    assert!(difference.lookup(Colliding(4)).is_none());
+}
+
+#[test]
+fn combining_intersection_and_difference_visit_every_shared_key() {
+    let map = (0..1000).fold(HamtMap::new(), |m, i| m.insert(i, i));
+    let changed = map.insert(1000, 1000);
+
+    let doubled = map.intersection_with(&changed, &|a, b| a + b);
+    assert!(doubled.size() == 1000);
+    assert!((0..1000).all(|i| doubled.lookup(i) == Some(2 * i)));
+
+    let odds = map.difference_with(&changed, &|&a, _| if a % 2 == 1 { Some(-a) } else { None });
+    assert!(odds.size() == 500);
+    assert!((0..1000).all(|i| odds.lookup(i) == if i % 2 == 1 { Some(-i) } else { None }));
+
+    let low = (0..8).fold(HamtMap::new(), |m, i| m.insert(Colliding(i), i));
+    let high = (4..12).fold(HamtMap::new(), |m, i| m.insert(Colliding(i), i * 10));
+    let products = low.intersection_with(&high, &|a, b| a * b);
+    assert!(products.size() == 4);
+    assert!(products.lookup(Colliding(5)).unwrap() == 250);
+    let kept = low.difference_with(&high, &|&a, _| if a == 6 { Some(60) } else { None });
+    assert!(kept.size() == 5);
+    assert!(kept.lookup(Colliding(3)).unwrap() == 3);
+    assert!(kept.lookup(Colliding(6)).unwrap() == 60);
+    assert!(kept.lookup(Colliding(7)).is_none());
+}
+
+#[test]
+fn bulk_results_are_canonical() {
+    let map = (0..1000).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let others = (0..1000).filter(|i| *i != 7).fold(HamtMap::new(), |m, i| m.insert(i, i));
+    let one = map.difference(&others);
+
+    match *one.root {
+        Node::Branch { ref entries, ref children, .. } => {
+            assert!(entries.len() == 1);
+            assert!(children.is_empty());
+        }
+        Node::Collision { .. } => panic!("root should be a branch"),
+    }
+    assert!(one.size() == 1);
+    assert!(one.lookup(7).unwrap() == 7);
+}
+
+#[test]
+fn union_shares_common_subtries() {
+    let map = (0..1000).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let changed = map.insert(1000, 1000);
+
+    let union = map.union(&changed);
+    match (&*union.root, &*map.root) {
+        (&Node::Branch { children: ref ours, .. }, &Node::Branch { children: ref theirs, .. }) => {
+            let shared = ours.iter().zip(theirs).filter(|&(a, b)| Shared::ptr_eq(a, b)).count();
+            assert!(shared >= ours.len() - 1);
+        }
+        _ => panic!("roots should be branches"),
+    }
+    assert!(union.size() == 1001);
+}
+
+#[test]
+fn keyed_hashers_can_be_used() {
+    let map = (0..500).fold(HamtMap::with_hasher(RandomState::new()), |m, i| m.insert(i, i));
+
+    assert!(map.size() == 500);
+    assert!(map.remove(&7).lookup(7).is_none());
+    assert!((0..500).all(|i| map.lookup(i).unwrap() == i));
+}
+
+#[test]
+fn bulk_operations_rehash_maps_with_other_keys() {
+    let threes = (0..300).filter(|i| i % 3 == 0).fold(HamtMap::with_hasher(RandomState::new()),
+                                                       |map, i| map.insert(i, i));
+    let fives = (0..300).filter(|i| i % 5 == 0).fold(HamtMap::with_hasher(RandomState::new()),
+                                                      |map, i| map.insert(i, i));
+
+    assert!(threes.union(&fives).size() == 140);
+    assert!(threes.intersection(&fives).size() == 20);
+    assert!(threes.difference(&fives).size() == 80);
+    for i in 0..300 {
+        assert!(threes.union(&fives).contains_key(&i) == (i % 3 == 0 || i % 5 == 0));
+        assert!(threes.difference(&fives).contains_key(&i) == (i % 3 == 0 && i % 5 != 0));
+    }
+}
+
+#[cfg(test)]
+thread_local!(static COMPARISONS: ::std::cell::Cell<u32> = const { ::std::cell::Cell::new(0) });
+
+/// A value which counts how many times it is compared.
+#[cfg(test)]
+#[derive(Debug, Clone)]
+struct Counted(u32);
+
+#[cfg(test)]
+impl Hash for Counted {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+#[cfg(test)]
+impl PartialEq for Counted {
+    fn eq(&self, other: &Counted) -> bool {
+        COMPARISONS.with(|count| count.set(count.get() + 1));
+        self.0 == other.0
+    }
+}
+
+#[test]
+fn diff_finds_added_removed_and_changed() {
+    let before = (0..500).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let after = (0..500)
+                    .filter(|i| i % 7 == 0)
+                    .fold(before.clone(), |map, i| map.remove(&i))
+                    .insert(10, 100)
+                    .insert(11, 11)
+                    .insert(600, 600);
+
+    let mut diff = before.diff(&after);
+    diff.removed.sort();
+    assert!(diff.removed == (0..500).filter(|i| i % 7 == 0).map(|i| (i, i)).collect::<Vec<_>>());
+    assert!(diff.added == vec![(600, 600)]);
+    assert!(diff.changed == vec![(10, 10, 100)]);
+    assert!(before.diff(&before).is_empty());
+
+    let reversed = after.diff(&before);
+    assert!(reversed.added.len() == diff.removed.len());
+    assert!(reversed.changed == vec![(10, 100, 10)]);
+}
+
+#[test]
+fn diff_handles_collisions_and_other_hashers() {
+    let low = (0..8).fold(HamtMap::new(), |m, i| m.insert(Colliding(i), i));
+    let high = (4..12).fold(HamtMap::new(), |m, i| m.insert(Colliding(i), i));
+
+    let diff = low.diff(&high.insert(Colliding(5), 0));
+    assert!(diff.added.len() == 4 && diff.removed.len() == 4);
+    assert!(diff.changed == vec![(Colliding(5), 5, 0)]);
+
+    let keyed = (0..100).fold(HamtMap::with_hasher(RandomState::new()), |m, i| m.insert(i, i));
+    let other = (1..100).fold(HamtMap::with_hasher(RandomState::new()), |m, i| m.insert(i, i));
+    let diff = keyed.diff(&other);
+    assert!(diff.removed == vec![(0, 0)] && diff.added.is_empty() && diff.changed.is_empty());
+}
+
+#[test]
+fn diff_skips_shared_subtries() {
+    let map = (0..10000).fold(HamtMap::new(), |map, i| map.insert(i, Counted(i)));
+    let changed = map.insert(5, Counted(50)).insert(10000, Counted(10000));
+
+    COMPARISONS.with(|count| count.set(0));
+    let diff = map.diff(&changed);
+    assert!(diff.changed.len() == 1 && diff.added.len() == 1 && diff.removed.is_empty());
+    assert!(COMPARISONS.with(|count| count.get()) < 100);
+}
+
+#[test]
+fn patches_replay_diffs() {
+    let base = (0..300).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let next = (0..300)
+                   .filter(|i| i % 10 == 0)
+                   .fold(base.clone(), |map, i| map.remove(&i).insert(i + 1000, i))
+                   .insert(7, 70);
+
+    let patch = MapPatch::from(base.diff(&next));
+    assert!(patch.edits.len() == 61);
+    assert!(patch.edits.contains(&Edit::Update(7, 7, 70)));
+    assert!(base.apply(&patch).unwrap().diff(&next).is_empty());
+    assert!(next.apply(&MapPatch::from(next.diff(&base))).unwrap().diff(&base).is_empty());
+    assert!(base.apply(&MapPatch::from(base.diff(&base))).unwrap().size() == 300);
+}
+
+#[test]
+fn patches_detect_conflicts() {
+    let base = HamtMap::new().insert(1, "one").insert(2, "two");
+    let patch = MapPatch::from(base.diff(&base.insert(1, "uno").insert(3, "tres")));
+
+    let changed = base.insert(1, "ein");
+    assert!(changed.apply(&patch).unwrap_err() ==
+            PatchConflict {
+                key: 1,
+                expected: Some("one"),
+                found: Some("ein"),
+            });
+    let conflict = base.insert(3, "three").apply(&patch).unwrap_err();
+    assert!(conflict.key == 3 && conflict.expected.is_none() && conflict.found == Some("three"));
+    let removed = MapPatch { edits: vec![Edit::Remove(4, "four")] };
+    assert!(base.apply(&removed).unwrap_err().found.is_none());
+}
+
+#[test]
+fn incremental_fold_matches_a_full_fold() {
+    let mut count = IncrementalFold::new(|_: &u32, _: &u32| Count(1));
+    let mut max = IncrementalFold::new(|_: &u32, value: &u32| Max(Some(*value)));
+    let mut map = HamtMap::new();
+    assert!(count.total() == Count(0));
+    for i in 0..2000u32 {
+        map = if i % 3 == 2 { map.remove(&(i / 2)) } else { map.insert(i, (i * 7919) % 1000) };
+        assert!(count.fold(&map) == Count(map.size()));
+        assert!(max.fold(&map) == Max(map.iter().map(|(_, value)| *value).max()));
+    }
+    let colliding = (0..10).fold(HamtMap::new(), |m, i| m.insert(Colliding(i), i));
+    let mut sum = IncrementalFold::new(|_: &Colliding, value: &u32| Sum(*value));
+    assert!(sum.fold(&colliding) == Sum(45));
+    assert!(sum.fold(&colliding.remove(&Colliding(9))) == Sum(36));
+}
+
+#[test]
+fn incremental_fold_skips_shared_subtries() {
+    let calls = ::std::cell::Cell::new(0);
+    let mut sum = IncrementalFold::new(|_: &u32, value: &u32| {
+        calls.set(calls.get() + 1);
+        Sum(*value)
+    });
+    let map = (0..100000).fold(HamtMap::new(), |map, i| map.insert(i, 1));
+
+    assert!(sum.fold(&map) == Sum(100000));
+    assert!(calls.get() == 100000);
+    calls.set(0);
+    let updated = map.insert(5, 10).remove(&7).insert(100000, 1);
+    assert!(sum.fold(&updated) == Sum(100009));
+    assert!(calls.get() < 500);
+    assert!(sum.fold(&map) == Sum(100000));
+}
+
+#[test]
+fn set_insert_contains_remove() {
+    let set = HamtSet::new().insert(1).insert(2).insert(2);
+
+    assert!(set.size() == 2);
+    assert!(set.contains(&1));
+    assert!(set.member(2));
+    assert!(!set.contains(&3));
+    assert!(!set.remove(&1).contains(&1));
+    assert!(set.contains(&1));
+}
+
+#[test]
+fn set_algebra() {
+    let threes = (0..30).filter(|i| i % 3 == 0).fold(HamtSet::new(), |s, i| s.insert(i));
+    let twos = (0..30).filter(|i| i % 2 == 0).fold(HamtSet::new(), |s, i| s.insert(i));
+
+    let sorted = |set: HamtSet<u32>| {
+        let mut values: Vec<u32> = set.iter().cloned().collect();
+        values.sort();
+        values
+    };
+
+    assert!(sorted(threes.union(&twos)) ==
+            (0..30).filter(|i| i % 2 == 0 || i % 3 == 0).collect::<Vec<_>>());
+    assert!(sorted(threes.intersection(&twos)) == vec![0, 6, 12, 18, 24]);
+    assert!(sorted(threes.difference(&twos)) == vec![3, 9, 15, 21, 27]);
+    assert!(sorted(twos.difference(&threes.insert(100))) ==
+            vec![2, 4, 8, 10, 14, 16, 20, 22, 26, 28]);
+    assert!(threes.intersection(&twos).is_subset(&threes));
+    assert!(!threes.is_subset(&twos));
+}
+
+#[test]
+fn in_place_updates_match_persistent_ones() {
+    let mut map = HamtMap::new();
+    for i in 0..2000u32 {
+        map.insert_mut(i, i);
+    }
+    let shared = map.clone();
+    for i in 0..1000u32 {
+        map.remove_mut(&(i * 2));
+        map.insert_mut(i, 0);
+    }
+    map.remove_mut(&5000);
+
+    let expected = (0..1000u32).fold(shared.clone(), |map, i| map.remove(&(i * 2)).insert(i, 0));
+    assert!(map.size() == expected.size());
+    assert!(expected.iter().all(|(key, value)| map.get(key) == Some(value)));
+    assert!(shared.size() == 2000 && shared.get(&2) == Some(&2));
+
+    let mut colliding = HamtMap::new();
+    for i in 0..6 {
+        colliding.insert_mut(Colliding(i), i);
+    }
+    colliding.remove_mut(&Colliding(0));
+    colliding.insert_mut(Colliding(1), 10);
+    assert!(colliding.size() == 5 && colliding.get(&Colliding(1)) == Some(&10));
+    assert!(colliding.get(&Colliding(0)).is_none());
+}
+
+#[test]
+fn get_or_insert_with_binds_only_missing_keys() {
+    let map = (0..1000).fold(HamtMap::new(), |map, i| map.insert(i, i * 2));
+    let (value, same) = map.get_or_insert_with(&500, || panic!("500 is already bound"));
+    assert!(value == 1000 && Shared::ptr_eq(&same.root, &map.root));
+
+    let (value, extended) = map.get_or_insert_with(&5000, || 1);
+    assert!(value == 1 && extended.get(&5000) == Some(&1) && extended.size() == 1001);
+    assert!(extended.check_invariants().is_ok() && map.get(&5000).is_none());
+
+    let colliding = (0..12).fold(HamtMap::new(), |map, i| map.insert(Colliding(i), i));
+    let (value, _) = colliding.get_or_insert_with(&Colliding(5), || panic!("5 is bound"));
+    let (added, extended) = colliding.get_or_insert_with(&Colliding(13), || 13);
+    assert!(value == 5 && added == 13 && extended.size() == 13);
+    assert!(extended.get(&Colliding(13)) == Some(&13) && extended.get(&Colliding(12)).is_none());
+}
+
+#[test]
+fn in_place_updates_reuse_unshared_nodes() {
+    let mut map = (0..100u32).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let root = &*map.root as *const Node<u32, u32, DefaultPointer>;
+    map.insert_mut(3, 30);
+    map.remove_mut(&4);
+    assert!(ptr::eq(&*map.root, root));
+
+    let shared = map.clone();
+    map.insert_mut(5, 50);
+    assert!(!Shared::ptr_eq(&map.root, &shared.root));
+    assert!(shared.get(&5) == Some(&5));
+}
+
+#[test]
+fn equal_maps_compare_and_hash_equal_however_built() {
+    let forwards = (0..1000).fold(HamtMap::new(), |map, i| map.insert(i, i * 2));
+    let backwards = (0..1000).rev().fold(HamtMap::new(), |map, i| map.insert(i, i * 2));
+    let keyed = (0..1000).fold(HamtMap::with_hasher(RandomState::new()),
+                               |map, i| map.insert(i, i * 2));
+    assert!(forwards == backwards && forwards.content_hash() == backwards.content_hash());
+    assert!(keyed.content_hash() == forwards.content_hash());
+    assert!(forwards != forwards.insert(5, 0) && forwards != forwards.remove(&5));
+    assert!(forwards.content_hash() != forwards.insert(5, 0).content_hash());
+
+    let colliding = (0..8).fold(HamtMap::new(), |map, i| map.insert(Colliding(i), i));
+    let reversed = (0..8).rev().fold(HamtMap::new(), |map, i| map.insert(Colliding(i), i));
+    assert!(colliding == reversed && colliding.content_hash() == reversed.content_hash());
+    assert!(colliding != reversed.insert(Colliding(3), 0));
+
+    let sets: Vec<_> = (0..10)
+                           .map(|i| {
+                               (0..100).fold(HamtSet::new(), |set, j| set.insert((i + j) % 100))
+                           })
+                           .collect();
+    let state = RandomState::new();
+    assert!(sets.iter().all(|set| *set == sets[0]));
+    assert!(sets.iter().all(|set| hash_of(&state, set) == hash_of(&state, &sets[0])));
+}
+
+#[test]
+fn cached_hashes_cut_comparisons_short() {
+    let map = (0..10000).fold(HamtMap::new(), |map, i| map.insert(i, Counted(i)));
+    let copy = (0..10000).rev().fold(HamtMap::new(), |map, i| map.insert(i, Counted(i)));
+    let changed = copy.insert(5, Counted(50));
+    assert!(map == copy);
+
+    assert!(map.content_hash() != changed.content_hash());
+    COMPARISONS.with(|count| count.set(0));
+    assert!(map != changed);
+    assert!(COMPARISONS.with(|count| count.get()) == 0);
+}
+
+#[test]
+fn in_place_updates_refresh_cached_hashes() {
+    let mut map = (0..1000u32).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let before = map.content_hash();
+    let shared = map.clone();
+    map.insert_mut(3, 30);
+    assert!(map.content_hash() != before && shared.content_hash() == before);
+    map.insert_mut(3, 3);
+    assert!(map.content_hash() == before);
+
+    map.remove_mut(&4);
+    let expected = (0..1000u32).filter(|&i| i != 4).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    assert!(map.content_hash() == expected.content_hash() && map == expected);
+}
+
+#[test]
+fn mapped_values_keep_keys_and_shape() {
+    let map = (0..1000u32).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let mut visited = Vec::new();
+    let doubled = map.map_values(|value| {
+        visited.push(*value);
+        *value as u64 * 2
+    });
+
+    assert!(visited == map.iter().map(|(_, value)| *value).collect::<Vec<_>>());
+    assert!(doubled.size() == 1000 && doubled.get(&7) == Some(&14));
+    assert!(doubled.keys().eq(map.keys()));
+    assert!(doubled == (0..1000u32).fold(HamtMap::new(), |map, i| map.insert(i, i as u64 * 2)));
+
+    let colliding = (0..12).fold(HamtMap::new(), |map, i| map.insert(Colliding(i), i));
+    let named = colliding.map_values(|value| value.to_string());
+    assert!(named.size() == 12 && named.lookup(Colliding(5)) == Some("5".to_string()));
+}
+
+#[test]
+fn invariant_checks_find_broken_tries() {
+    let map = (0..1000).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let removed = (0..990).fold(map.clone(), |map, i| map.remove(&i));
+    let colliding = (0..12).fold(HamtMap::new(), |map, i| map.insert(Colliding(i), i));
+    assert!(map.check_invariants().is_ok() && removed.check_invariants().is_ok());
+    assert!(colliding.remove(&Colliding(3)).check_invariants().is_ok());
+
+    let resized = match *map.root {
+        Node::Branch { datamap, nodemap, ref entries, ref children, .. } => {
+            Node::Branch {
+                size: 999,
+                datamap: datamap,
+                nodemap: nodemap,
+                entries: entries.clone(),
+                children: children.clone(),
+                content_hash: OnceLock::new(),
+            }
+        }
+        Node::Collision { .. } => panic!("root should be a branch"),
+    };
+    assert!(map.with_root(resized).check_invariants() ==
+            Err("a branch at shift 0 has a size of 999 but holds 1000 entries".to_string()));
+
+    let rehashed = HamtMap {
+        root: map.root.clone(),
+        hasher: RandomState::new(),
+    };
+    assert!(rehashed.check_invariants().unwrap_err().ends_with("is not where its hash leads"));
+}
+
+#[test]
+fn persistent_traits_agree_across_structures() {
+    fn check<M: PersistentMap<u64, u64>>() -> bool {
+        let map = (0..100).fold(M::empty_map(), |map, i| map.bind(i, i * i));
+        let odds = (0..100).filter(|i| i % 2 == 0).fold(map.clone(), |map, i| map.remove(&i));
+        odds.len() == 50 && odds.get(&7) == Some(&49) && !odds.contains_key(&8) &&
+        map.difference(&odds).len() == 50 && map.intersection(&odds).len() == 50 &&
+        odds.union(&map).len() == 100
+    }
+    fn check_set<S: PersistentSet<u64>>() -> bool {
+        let set = (0..100).fold(S::empty(), |set, i| set.insert(i));
+        let odds = (0..100).filter(|i| i % 2 == 0).fold(set.clone(), |set, i| set.remove(&i));
+        odds.len() == 50 && odds.contains(&7) && !odds.contains(&8) &&
+        set.difference(&odds).len() == 50 && odds.union(&set).len() == 100
+    }
+
+    assert!(check::<HamtMap<u64, u64>>() && check::<Tree<u64, u64>>());
+    assert!(check::<IntMap<u64>>());
+    assert!(check_set::<HamtSet<u64>>() && check_set::<Tree<u64, ()>>());
+    assert!(check_set::<IntSet>());
+}
@@ -0,0 +1,100 @@
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// The multiplier applied to each word mixed into an `FxHasher`.
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+/// A fast, non-cryptographic hasher, as used by the Rust compiler and Firefox.
+///
+/// Each word written to the hasher is mixed in with a rotation, an xor and a
+/// multiplication, which makes it much cheaper than SipHash for short keys
+/// such as integers. It is not keyed, so anyone who can choose the keys of a
+/// map can also choose keys which collide; use `RandomState` from
+/// `std::collections::hash_map` where keys come from untrusted input.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FxHasher {
+    hash: u64,
+}
+
+/// Builds `FxHasher`s. This is the default hasher for the hash-based
+/// structures in this crate.
+pub type FxBuildHasher = BuildHasherDefault<FxHasher>;
+
+impl FxHasher {
+    fn add_to_hash(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            self.add_to_hash(u64::from_le_bytes(word));
+        }
+        let mut rest = chunks.remainder();
+        if rest.len() >= 4 {
+            let mut word = [0; 4];
+            word.copy_from_slice(&rest[..4]);
+            self.add_to_hash(u32::from_le_bytes(word) as u64);
+            rest = &rest[4..];
+        }
+        if rest.len() >= 2 {
+            let mut word = [0; 2];
+            word.copy_from_slice(&rest[..2]);
+            self.add_to_hash(u16::from_le_bytes(word) as u64);
+            rest = &rest[2..];
+        }
+        if let Some(&byte) = rest.first() {
+            self.add_to_hash(byte as u64);
+        }
+    }
+    fn write_u8(&mut self, value: u8) {
+        self.add_to_hash(value as u64);
+    }
+    fn write_u16(&mut self, value: u16) {
+        self.add_to_hash(value as u64);
+    }
+    fn write_u32(&mut self, value: u32) {
+        self.add_to_hash(value as u64);
+    }
+    fn write_u64(&mut self, value: u64) {
+        self.add_to_hash(value);
+    }
+    fn write_usize(&mut self, value: usize) {
+        self.add_to_hash(value as u64);
+    }
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+fn fx_hash<T: ::std::hash::Hash>(value: &T) -> u64 {
+    use std::hash::BuildHasher;
+    FxBuildHasher::default().hash_one(value)
+}
+
+#[test]
+fn equal_values_hash_equally() {
+    assert!(fx_hash(&"a string of more than eight bytes") ==
+            fx_hash(&"a string of more than eight bytes"));
+    assert!(fx_hash(&12345u64) == fx_hash(&12345u64));
+}
+
+#[test]
+fn small_integers_differ_in_their_low_bits() {
+    let mut fragments: Vec<u64> = (0..32u32).map(|i| fx_hash(&i) & 31).collect();
+    fragments.sort();
+    fragments.dedup();
+    assert!(fragments.len() == 32);
+}
+
+#[test]
+fn trailing_bytes_are_hashed() {
+    let lengths: Vec<u64> = (0..16).map(|n| fx_hash(&vec![7u8; n])).collect();
+    for (i, a) in lengths.iter().enumerate() {
+        assert!(lengths[i + 1..].iter().all(|b| a != b));
+    }
+}
@@ -0,0 +1,182 @@
+use ptr::{DefaultPointer, SharedPointer};
+use seq::SeqIn;
+
+/// An undo and redo history of versions of a value, for use with any of the
+/// persistent structures in this crate.
+///
+/// Since each version of a persistent structure shares most of its contents
+/// with the versions before it, keeping every version costs little more than
+/// the changes between them. The history is itself immutable: each method
+/// returns a new history and leaves this one as it was.
+///
+/// Versions to undo are kept in a `Seq`, so a history can be given a limit
+/// on how many it keeps, past which the oldest are forgotten. Edits which
+/// should be undone together, such as consecutive keystrokes, can be
+/// coalesced into one step by recording the later ones with `amend` rather
+/// than `push`.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::history::History;
+/// use functional_datastructures::seq::Seq;
+///
+/// let history = History::new(Seq::empty());
+/// let typed = history.push(history.current().push_back('a'));
+/// let typed = typed.amend(typed.current().push_back('b'));
+/// let typed = typed.push(typed.current().push_back('c'));
+///
+/// let undone = typed.undo().unwrap();
+/// assert!(undone.current().iter().collect::<String>() == "ab");
+/// assert!(undone.undo().unwrap().current().is_empty());
+/// assert!(undone.redo().unwrap().current().size() == 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct HistoryIn<T, P: SharedPointer> {
+    current: T,
+    /// The versions before the current one, oldest first.
+    undo: SeqIn<T, P>,
+    /// The versions which have been undone, most recently undone first.
+    redo: SeqIn<T, P>,
+    limit: Option<u32>,
+}
+
+/// A `HistoryIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type History<T> = HistoryIn<T, DefaultPointer>;
+
+impl<T: Clone, P: SharedPointer> HistoryIn<T, P> {
+    /// Returns a history with `initial` as its only version, which keeps any
+    /// number of versions to undo.
+    pub fn new(initial: T) -> Self {
+        HistoryIn {
+            current: initial,
+            undo: SeqIn::empty(),
+            redo: SeqIn::empty(),
+            limit: None,
+        }
+    }
+    /// Returns a history with `initial` as its only version, which keeps at
+    /// most `limit` versions to undo.
+    pub fn with_limit(initial: T, limit: u32) -> Self {
+        HistoryIn { limit: Some(limit), ..HistoryIn::new(initial) }
+    }
+    /// Returns the current version.
+    pub fn current(&self) -> &T {
+        &self.current
+    }
+    /// Returns a copy of this history with `value` as the current version,
+    /// which undoing returns from. Anything which could have been redone is
+    /// forgotten, as is the oldest version if there are more to undo than
+    /// the limit.
+    pub fn push(&self, value: T) -> Self {
+        let mut undo = self.undo.push_back(self.current.clone());
+        if self.limit.is_some_and(|limit| undo.size() > limit) {
+            undo = undo.pop_front().unwrap().1;
+        }
+        HistoryIn {
+            current: value,
+            undo: undo,
+            redo: SeqIn::empty(),
+            limit: self.limit,
+        }
+    }
+    /// Returns a copy of this history with the current version replaced by
+    /// `value`, so that it is undone in the same step as the edit which made
+    /// the current version. Anything which could have been redone is
+    /// forgotten.
+    pub fn amend(&self, value: T) -> Self {
+        HistoryIn {
+            current: value,
+            undo: self.undo.clone(),
+            redo: SeqIn::empty(),
+            limit: self.limit,
+        }
+    }
+    /// Returns a copy of this history with the latest step undone, or `None`
+    /// if there is nothing to undo.
+    pub fn undo(&self) -> Option<Self> {
+        self.undo.pop_back().map(|(previous, undo)| {
+            HistoryIn {
+                current: previous,
+                undo: undo,
+                redo: self.redo.push_front(self.current.clone()),
+                limit: self.limit,
+            }
+        })
+    }
+    /// Returns a copy of this history with the most recently undone step
+    /// redone, or `None` if there is nothing to redo.
+    pub fn redo(&self) -> Option<Self> {
+        self.redo.pop_front().map(|(next, redo)| {
+            HistoryIn {
+                current: next,
+                undo: self.undo.push_back(self.current.clone()),
+                redo: redo,
+                limit: self.limit,
+            }
+        })
+    }
+    /// Returns the number of steps which can be undone.
+    pub fn undo_depth(&self) -> u32 {
+        self.undo.size()
+    }
+    /// Returns the number of steps which can be redone.
+    pub fn redo_depth(&self) -> u32 {
+        self.redo.size()
+    }
+}
+
+#[cfg(test)]
+use hamt::HamtMap;
+
+#[test]
+fn undo_and_redo_walk_through_versions() {
+    let history = (1..6).fold(History::new(0), |history, i| history.push(i));
+
+    assert!(*history.current() == 5 && history.undo_depth() == 5);
+    assert!(history.redo().is_none());
+    let undone = history.undo().unwrap().undo().unwrap();
+    assert!(*undone.current() == 3);
+    assert!(undone.undo_depth() == 3 && undone.redo_depth() == 2);
+    assert!(*undone.redo().unwrap().current() == 4);
+    assert!(*undone.redo().unwrap().redo().unwrap().current() == 5);
+
+    let first = (0..5).fold(history.clone(), |history, _| history.undo().unwrap());
+    assert!(*first.current() == 0 && first.undo().is_none());
+    assert!(*history.current() == 5);
+}
+
+#[test]
+fn pushing_forgets_what_could_be_redone() {
+    let history = History::new("a").push("b").push("c").undo().unwrap();
+
+    let branched = history.push("d");
+    assert!(branched.redo().is_none());
+    assert!(*branched.undo().unwrap().current() == "b");
+    assert!(history.amend("e").redo().is_none());
+    assert!(*history.redo().unwrap().current() == "c");
+}
+
+#[test]
+fn limit_forgets_the_oldest_versions() {
+    let history = (1..100).fold(History::with_limit(0, 10), |history, i| history.push(i));
+
+    assert!(history.undo_depth() == 10);
+    let oldest = (0..10).fold(history, |history, _| history.undo().unwrap());
+    assert!(*oldest.current() == 89 && oldest.undo().is_none());
+    assert!(oldest.redo_depth() == 10);
+}
+
+#[test]
+fn amended_edits_are_undone_together() {
+    let history = History::new(HamtMap::new());
+    let history = history.push(history.current().insert("x", 1));
+    let history = history.amend(history.current().insert("y", 2));
+    let history = history.push(history.current().insert("z", 3));
+
+    let undone = history.undo().unwrap();
+    assert!(undone.current().size() == 2);
+    assert!(undone.undo().unwrap().current().is_empty());
+    assert!(undone.undo_depth() == 1);
+}
@@ -0,0 +1,263 @@
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use hasher::FxBuildHasher;
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+use set::TreeIn;
+
+/// An interning context for `Tree`s, which keeps one copy of each distinct
+/// subtree built through it and shares that copy between every tree which
+/// contains it (hash-consing).
+///
+/// A node is only looked up once its children have been interned, so two
+/// nodes are structurally equal exactly when their keys and values are equal
+/// and their children are the same nodes. Finding a node in the context
+/// therefore hashes only its own key and value, never a whole subtree. Since
+/// every interned tree is held exactly once, two trees returned by the same
+/// context are equal exactly when `Shared::ptr_eq` says they are.
+///
+/// The context holds every node interned through it until `purge` is
+/// called, so the nodes of trees which have been dropped are not freed
+/// until then.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::intern::Interner;
+/// use functional_datastructures::ptr::Shared;
+/// use functional_datastructures::set::{Set, Tree};
+///
+/// let mut interner = Interner::new();
+/// let tree: Tree<u32, ()> = Tree::empty().insert(2).insert(1).insert(3);
+/// let first = interner.intern(&tree);
+/// let mut second = interner.intern(&Tree::empty());
+/// for value in vec![2, 1, 3] {
+///     second = interner.insert(&second, value);
+/// }
+///
+/// assert!(Shared::ptr_eq(&first, &second));
+///
+/// // The nodes of the versions built along the way are held until a purge.
+/// assert!(interner.size() == 5);
+/// interner.purge();
+/// assert!(interner.size() == 3);
+///
+/// drop(first);
+/// drop(second);
+/// interner.purge();
+/// assert!(interner.size() == 0);
+/// ```
+#[derive(Debug)]
+pub struct InternerIn<K: Ord + Clone, V: Clone, P: SharedPointer> {
+    nodes: HashSet<Interned<K, V, P>, FxBuildHasher>,
+    empty: Shared<TreeIn<K, V, P>, P>,
+}
+
+/// An `InternerIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type Interner<K, V> = InternerIn<K, V, DefaultPointer>;
+
+/// A node held by an `Interner`, hashed and compared by its key, its value
+/// and the addresses of its children.
+#[derive(Debug)]
+struct Interned<K: Ord + Clone, V: Clone, P: SharedPointer>(Shared<TreeIn<K, V, P>, P>);
+
+impl<K, V, P: SharedPointer> InternerIn<K, V, P>
+    where K: Ord + Clone + Hash,
+          V: Clone + Hash + Eq
+{
+    /// Returns a context which holds no nodes.
+    pub fn new() -> Self {
+        InternerIn {
+            nodes: HashSet::default(),
+            empty: Shared::new(TreeIn::Empty),
+        }
+    }
+    /// Returns the number of distinct nodes held by this context.
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+    /// Returns the interned copy of `tree`, interning each of its subtrees
+    /// which is not already held by this context.
+    ///
+    /// This visits every node of `tree`, so it is best used once on trees
+    /// built elsewhere; `bind` and `insert` keep a tree interned as it
+    /// changes.
+    pub fn intern(&mut self, tree: &TreeIn<K, V, P>) -> Shared<TreeIn<K, V, P>, P> {
+        match *tree {
+            TreeIn::Empty => self.empty.clone(),
+            TreeIn::Node { ref left, ref key, ref value, ref right } => {
+                let left = self.intern(left);
+                let right = self.intern(right);
+                self.node(left, key.clone(), value.clone(), right)
+            }
+        }
+    }
+    /// Returns the interned copy of `tree` with `key` bound to `value`.
+    ///
+    /// Only the nodes on the path to `key` are looked up, so `tree` must
+    /// itself have been returned by this context for the result to be fully
+    /// interned.
+    pub fn bind(&mut self,
+                tree: &Shared<TreeIn<K, V, P>, P>,
+                key: K,
+                value: V)
+                -> Shared<TreeIn<K, V, P>, P> {
+        match **tree {
+            TreeIn::Empty => {
+                let empty = self.empty.clone();
+                self.node(empty.clone(), key, value, empty)
+            }
+            TreeIn::Node { left: ref node_left, key: ref node_key, value: ref node_value,
+                           right: ref node_right } => {
+                if key < *node_key {
+                    let left = self.bind(node_left, key, value);
+                    self.node(left, node_key.clone(), node_value.clone(), node_right.clone())
+                } else if key > *node_key {
+                    let right = self.bind(node_right, key, value);
+                    self.node(node_left.clone(), node_key.clone(), node_value.clone(), right)
+                } else {
+                    self.node(node_left.clone(), key, value, node_right.clone())
+                }
+            }
+        }
+    }
+    /// Frees the nodes which are held only by this context, and not by any
+    /// tree outside it.
+    pub fn purge(&mut self) {
+        // Freeing a node can leave its children held only by this context,
+        // so keep going until a pass frees nothing.
+        loop {
+            let size = self.nodes.len();
+            self.nodes.retain(|node| Shared::strong_count(&node.0) > 1);
+            if self.nodes.len() == size {
+                break;
+            }
+        }
+    }
+    /// Returns the interned node with the given key, value and children,
+    /// which must already be interned.
+    fn node(&mut self,
+            left: Shared<TreeIn<K, V, P>, P>,
+            key: K,
+            value: V,
+            right: Shared<TreeIn<K, V, P>, P>)
+            -> Shared<TreeIn<K, V, P>, P> {
+        let candidate = Interned(Shared::new(TreeIn::Node {
+            left: left,
+            key: key,
+            value: value,
+            right: right,
+        }));
+        if let Some(existing) = self.nodes.get(&candidate) {
+            return existing.0.clone();
+        }
+        let node = candidate.0.clone();
+        self.nodes.insert(candidate);
+        node
+    }
+}
+
+impl<T: Ord + Clone + Hash, P: SharedPointer> InternerIn<T, (), P> {
+    /// Returns the interned copy of `tree` with `value` added to it, under
+    /// the same conditions as `bind`.
+    pub fn insert(&mut self,
+                  tree: &Shared<TreeIn<T, (), P>, P>,
+                  value: T)
+                  -> Shared<TreeIn<T, (), P>, P> {
+        self.bind(tree, value, ())
+    }
+}
+
+impl<K, V, P: SharedPointer> Default for InternerIn<K, V, P>
+    where K: Ord + Clone + Hash,
+          V: Clone + Hash + Eq
+{
+    fn default() -> Self {
+        InternerIn::new()
+    }
+}
+
+impl<K: Ord + Clone + Hash, V: Clone + Hash, P: SharedPointer> Hash for Interned<K, V, P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        if let TreeIn::Node { ref left, ref key, ref value, ref right } = *self.0 {
+            key.hash(state);
+            value.hash(state);
+            (&**left as *const TreeIn<K, V, P>).hash(state);
+            (&**right as *const TreeIn<K, V, P>).hash(state);
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + Eq, P: SharedPointer> PartialEq for Interned<K, V, P> {
+    fn eq(&self, other: &Self) -> bool {
+        match (&*self.0, &*other.0) {
+            (TreeIn::Node { left: left_a, key: key_a, value: value_a, right: right_a },
+             TreeIn::Node { left: left_b, key: key_b, value: value_b, right: right_b }) => {
+                key_a == key_b && value_a == value_b && Shared::ptr_eq(left_a, left_b) &&
+                Shared::ptr_eq(right_a, right_b)
+            }
+            (TreeIn::Empty, TreeIn::Empty) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + Eq, P: SharedPointer> Eq for Interned<K, V, P> {}
+
+#[cfg(test)]
+use set::{Map, Set, Tree};
+
+#[test]
+fn equal_trees_are_interned_once() {
+    let mut interner = Interner::new();
+    let trees: Vec<_> = (0..100)
+                            .map(|i| {
+                                let tree = (0..10).fold(Tree::empty(), |tree, j| {
+                                    tree.insert((i + j) % 10)
+                                });
+                                interner.intern(&tree)
+                            })
+                            .collect();
+
+    // Trees of the same values inserted in the same order are identical.
+    for (i, tree) in trees.iter().enumerate() {
+        assert!(Shared::ptr_eq(tree, &trees[i % 10]));
+        assert!((0..10).all(|value| tree.member(value)));
+    }
+    let nodes = interner.size();
+    interner.intern(&trees[3]);
+    assert!(interner.size() == nodes);
+}
+
+#[test]
+fn binds_keep_trees_interned() {
+    let mut interner = Interner::new();
+    let empty = interner.intern(&Tree::empty_map());
+    let mut bound = empty.clone();
+    let mut expected = Tree::empty_map();
+    for i in 0..50u32 {
+        bound = interner.bind(&bound, (i * 7) % 50, i.to_string());
+        expected = expected.bind((i * 7) % 50, i.to_string());
+    }
+    bound = interner.bind(&bound, 7, "seven".to_string());
+    expected = expected.bind(7, "seven".to_string());
+
+    assert!(bound.iter().eq(expected.iter()));
+    assert!(Shared::ptr_eq(&bound, &interner.intern(&expected)));
+}
+
+#[test]
+fn purging_frees_only_unused_nodes() {
+    let mut interner = Interner::new();
+    let kept = interner.intern(&(0..10).fold(Tree::empty(), |tree, i| tree.insert(i)));
+    let dropped = interner.intern(&(10..20).fold(Tree::empty(), |tree, i| tree.insert(i)));
+    assert!(interner.size() == 20);
+
+    drop(dropped);
+    interner.purge();
+    assert!(interner.size() == 10);
+    let rebuilt = (0..10).fold(Tree::empty(), |tree, i| tree.insert(i));
+    assert!(Shared::ptr_eq(&kept, &interner.intern(&rebuilt)));
+}
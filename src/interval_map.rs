@@ -0,0 +1,354 @@
+use collection::Collection;
+use measure::{Measured, Monoid};
+use memory::{Census, MemoryUsage};
+use ptr::{DefaultPointer, SharedPointer};
+
+use finger_tree::{self, FingerTreeIn};
+use set::Map;
+
+/// A closed interval of points, from `low` to `high` inclusive.
+///
+/// Intervals are ordered by their low endpoints, and then by their high
+/// endpoints.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Interval<T> {
+    pub low: T,
+    pub high: T,
+}
+
+impl<T: Ord> Interval<T> {
+    /// Returns the interval from `low` to `high`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `low` is greater than `high`.
+    pub fn new(low: T, high: T) -> Self {
+        assert!(low <= high, "an interval cannot end before it starts");
+        Interval {
+            low: low,
+            high: high,
+        }
+    }
+    /// Tests whether `point` lies within this interval.
+    pub fn contains(&self, point: &T) -> bool {
+        self.low <= *point && *point <= self.high
+    }
+    /// Tests whether this interval and `other` have any point in common.
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.low <= other.high && other.low <= self.high
+    }
+}
+
+/// An immutable map from intervals to values, implemented as a finger tree of
+/// bindings in increasing order of interval, measured by the greatest
+/// interval and the greatest high endpoint.
+///
+/// Lookups, insertions and removals take O(log n) time. Finding the k
+/// intervals which contain a point, or which overlap another interval, takes
+/// O((k + 1) log n) time, as each one is split out of the tree in turn by
+/// looking for the first interval which reaches far enough.
+///
+/// This is the interval tree of Hinze and Paterson's "Finger trees: a simple
+/// general-purpose data structure".
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::interval_map::{Interval, IntervalMap};
+/// use functional_datastructures::set::Map;
+///
+/// let empty: IntervalMap<u32, &str> = IntervalMap::empty_map();
+/// let map = empty.insert(Interval::new(9, 12), "standup")
+///                .insert(Interval::new(10, 11), "review")
+///                .insert(Interval::new(13, 17), "lunch");
+///
+/// let at_eleven: Vec<&str> = map.intervals_containing(&11).into_iter().map(|(_, v)| v).collect();
+/// assert!(at_eleven == vec!["standup", "review"]);
+/// assert!(map.overlapping(&Interval::new(12, 13)).len() == 2);
+/// assert!(map.intervals_containing(&18).is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct IntervalMapIn<T: Ord + Clone, V, P: SharedPointer> {
+    tree: FingerTreeIn<Entry<T, V>, P>,
+}
+
+/// An `IntervalMapIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type IntervalMap<T, V> = IntervalMapIn<T, V, DefaultPointer>;
+
+/// A binding in an `IntervalMap`, measured by its interval.
+#[derive(Debug, Clone)]
+struct Entry<T, V> {
+    interval: Interval<T>,
+    value: V,
+}
+
+/// The measure of part of an interval map: its last and so greatest
+/// interval, and the greatest high endpoint of any of its intervals.
+#[derive(Debug, Clone)]
+struct Bounds<T> {
+    last: Option<Interval<T>>,
+    max_high: Option<T>,
+}
+
+impl<T: Ord + Clone> Monoid for Bounds<T> {
+    fn empty() -> Self {
+        Bounds {
+            last: None,
+            max_high: None,
+        }
+    }
+    fn combine(&self, other: &Self) -> Self {
+        let last = match other.last {
+            Some(_) => other.last.clone(),
+            None => self.last.clone(),
+        };
+        let max_high = match (&self.max_high, &other.max_high) {
+            (&Some(ref ours), &Some(ref theirs)) => Some(if ours > theirs { ours } else { theirs }),
+            (&Some(ref high), &None) | (&None, &Some(ref high)) => Some(high),
+            (&None, &None) => None,
+        };
+        Bounds {
+            last: last,
+            max_high: max_high.cloned(),
+        }
+    }
+}
+
+impl<T: Ord + Clone, V> Measured for Entry<T, V> {
+    type Measure = Bounds<T>;
+    fn measure(&self) -> Bounds<T> {
+        Bounds {
+            last: Some(self.interval.clone()),
+            max_high: Some(self.interval.high.clone()),
+        }
+    }
+}
+
+impl<T: Ord> Bounds<T> {
+    /// Tests whether the part of the map this measures contains an interval
+    /// greater than or equal to `interval`.
+    fn reaches(&self, interval: &Interval<T>) -> bool {
+        match self.last {
+            Some(ref last) => last >= interval,
+            None => false,
+        }
+    }
+    /// Tests whether the part of the map this measures contains an interval
+    /// which starts after `point`.
+    fn starts_after(&self, point: &T) -> bool {
+        match self.last {
+            Some(ref last) => last.low > *point,
+            None => false,
+        }
+    }
+    /// Tests whether the part of the map this measures contains an interval
+    /// which ends at or after `point`.
+    fn ends_at_or_after(&self, point: &T) -> bool {
+        match self.max_high {
+            Some(ref high) => high >= point,
+            None => false,
+        }
+    }
+}
+
+impl<T: Ord + Clone, V: Clone, P: SharedPointer> IntervalMapIn<T, V, P> {
+    /// Returns a copy of this map with `interval` bound to `value`, replacing
+    /// any existing binding for `interval`.
+    pub fn insert(&self, interval: Interval<T>, value: V) -> Self {
+        let (before, after) = self.tree.split(|bounds| bounds.reaches(&interval));
+        let after = match after.pop_front() {
+            Some((ref entry, ref rest)) if entry.interval == interval => rest.clone(),
+            _ => after,
+        };
+        let entry = Entry {
+            interval: interval,
+            value: value,
+        };
+        IntervalMapIn { tree: before.push_back(entry).concat(&after) }
+    }
+    /// Returns a copy of this map without any binding for `interval`.
+    pub fn remove(&self, interval: &Interval<T>) -> Self {
+        let (before, after) = self.tree.split(|bounds| bounds.reaches(interval));
+        match after.pop_front() {
+            Some((ref entry, ref rest)) if entry.interval == *interval => {
+                IntervalMapIn { tree: before.concat(rest) }
+            }
+            _ => self.clone(),
+        }
+    }
+    /// Returns the value bound to `interval`, or `None` if it is not bound.
+    pub fn get(&self, interval: &Interval<T>) -> Option<&V> {
+        self.tree
+            .find(|bounds| bounds.reaches(interval))
+            .and_then(|entry| if entry.interval == *interval {
+                Some(&entry.value)
+            } else {
+                None
+            })
+    }
+    /// Tests whether this map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    /// Returns the number of intervals bound in this map. This takes O(n)
+    /// time.
+    pub fn size(&self) -> u32 {
+        self.iter().count() as u32
+    }
+    /// Returns the bindings whose intervals contain `point`, in increasing
+    /// order of interval.
+    pub fn intervals_containing(&self, point: &T) -> Vec<(Interval<T>, V)> {
+        ending_at_or_after(self.tree.split(|bounds| bounds.starts_after(point)).0, point)
+    }
+    /// Returns the bindings whose intervals overlap `interval`, in increasing
+    /// order of interval.
+    pub fn overlapping(&self, interval: &Interval<T>) -> Vec<(Interval<T>, V)> {
+        let candidates = self.tree.split(|bounds| bounds.starts_after(&interval.high)).0;
+        ending_at_or_after(candidates, &interval.low)
+    }
+    /// Returns an iterator over the bindings in this map, in increasing order
+    /// of interval.
+    pub fn iter(&self) -> Iter<'_, T, V, P> {
+        Iter { iter: self.tree.iter() }
+    }
+}
+
+impl<T: Ord + Clone, V: Clone, P: SharedPointer> Collection for IntervalMapIn<T, V, P> {
+    type Iter<'a> = Iter<'a, T, V, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, T, V, P> {
+        self.iter()
+    }
+}
+
+/// Returns the bindings in `candidates` whose intervals end at or after
+/// `point`, splitting each one out in turn.
+fn ending_at_or_after<T, V, P: SharedPointer>(candidates: FingerTreeIn<Entry<T, V>, P>,
+                                              point: &T)
+                                              -> Vec<(Interval<T>, V)>
+    where T: Ord + Clone,
+          V: Clone
+{
+    let mut found = Vec::new();
+    let mut rest = candidates;
+    loop {
+        let (_, from_next) = rest.split(|bounds| bounds.ends_at_or_after(point));
+        match from_next.pop_front() {
+            Some((entry, after)) => {
+                found.push((entry.interval, entry.value));
+                rest = after;
+            }
+            None => return found,
+        }
+    }
+}
+
+impl<T: Ord + Clone, V, P: SharedPointer> MemoryUsage for IntervalMapIn<T, V, P> {
+    fn census(&self, census: &mut Census) {
+        self.tree.census(census)
+    }
+}
+
+impl<T: Ord + Clone, V: Clone, P: SharedPointer> Map<Interval<T>, V> for IntervalMapIn<T, V, P> {
+    fn empty_map() -> Self {
+        IntervalMapIn { tree: FingerTreeIn::empty() }
+    }
+    fn bind(&self, interval: Interval<T>, value: V) -> Self {
+        self.insert(interval, value)
+    }
+    fn lookup(&self, interval: Interval<T>) -> Option<V> {
+        self.get(&interval).cloned()
+    }
+}
+
+/// An iterator over the bindings of an `IntervalMap`, in increasing order of
+/// interval.
+pub struct Iter<'a, T: Ord + Clone + 'a, V: 'a, P: SharedPointer> {
+    iter: finger_tree::Iter<'a, Entry<T, V>, P>,
+}
+
+impl<'a, T: Ord + Clone, V, P: SharedPointer> Iterator for Iter<'a, T, V, P> {
+    type Item = (&'a Interval<T>, &'a V);
+
+    fn next(&mut self) -> Option<(&'a Interval<T>, &'a V)> {
+        self.iter.next().map(|entry| (&entry.interval, &entry.value))
+    }
+}
+
+#[cfg(test)]
+fn intervals_of(bindings: Vec<(Interval<u32>, u32)>) -> Vec<(u32, u32)> {
+    bindings.into_iter().map(|(interval, _)| (interval.low, interval.high)).collect()
+}
+
+#[test]
+fn intervals_can_be_bound_and_removed() {
+    let map = IntervalMap::empty_map()
+                  .insert(Interval::new(5, 10), "a")
+                  .insert(Interval::new(1, 3), "b")
+                  .insert(Interval::new(5, 7), "c");
+
+    assert!(map.size() == 3);
+    assert!(map.lookup(Interval::new(5, 7)).unwrap() == "c");
+    assert!(map.lookup(Interval::new(5, 8)).is_none());
+    assert!(map.insert(Interval::new(5, 7), "d").lookup(Interval::new(5, 7)).unwrap() == "d");
+    assert!(map.remove(&Interval::new(5, 10)).size() == 2);
+    assert!(map.remove(&Interval::new(5, 11)).size() == 3);
+    assert!(map.iter().map(|(interval, _)| interval.low).collect::<Vec<_>>() == vec![1, 5, 5]);
+}
+
+#[test]
+fn stabbing_queries_match_a_scan() {
+    let intervals: Vec<Interval<u32>> = (0..300)
+                                            .map(|i| {
+                                                let low = (i * 37) % 1000;
+                                                Interval::new(low, low + (i * 13) % 90)
+                                            })
+                                            .collect();
+    let map = intervals.iter()
+                       .enumerate()
+                       .fold(IntervalMap::empty_map(),
+                             |map, (i, interval)| map.insert(interval.clone(), i as u32));
+
+    for point in 0..1100 {
+        let mut expected: Vec<(u32, u32)> = intervals.iter()
+                                                     .filter(|interval| interval.contains(&point))
+                                                     .map(|interval| (interval.low, interval.high))
+                                                     .collect();
+        expected.sort();
+        assert!(intervals_of(map.intervals_containing(&point)) == expected);
+    }
+}
+
+#[test]
+fn overlap_queries_match_a_scan() {
+    let intervals: Vec<Interval<u32>> = (0..200)
+                                            .map(|i| {
+                                                let low = (i * 53) % 500;
+                                                Interval::new(low, low + i % 20)
+                                            })
+                                            .collect();
+    let map = intervals.iter()
+                       .fold(IntervalMap::empty_map(),
+                             |map, interval| map.insert(interval.clone(), 0));
+
+    for low in (0..520).filter(|low| low % 7 == 0) {
+        let query = Interval::new(low, low + 15);
+        let mut expected: Vec<(u32, u32)> = intervals.iter()
+                                                     .filter(|interval| interval.overlaps(&query))
+                                                     .map(|interval| (interval.low, interval.high))
+                                                     .collect();
+        expected.sort();
+        expected.dedup();
+        assert!(intervals_of(map.overlapping(&query)) == expected);
+    }
+}
+
+#[test]
+#[should_panic]
+fn backwards_intervals_are_rejected() {
+    Interval::new(2, 1);
+}
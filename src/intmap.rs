@@ -0,0 +1,1270 @@
+use std::mem;
+use std::ops::Range;
+
+use collection::Collection;
+use memory::{Census, MemoryUsage};
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+use set::{Map, PersistentMap, PersistentSet, Set};
+
+/// An immutable map from `u64` keys, implemented as a big-endian Patricia
+/// trie.
+///
+/// Each branch records the prefix which all of its keys share and the single
+/// bit at which its two subtrees first differ, so a trie never has more than
+/// 64 levels and operations take O(min(n, 64)) time. Because the shape of the
+/// trie depends only on the keys it contains, two maps can be merged by
+/// walking their structure side by side, which makes `merge` and
+/// `union_with` much faster than inserting one map's bindings into the
+/// other. Iteration visits keys in ascending order.
+///
+/// This is the structure described by Okasaki and Gill in "Fast Mergeable
+/// Integer Maps".
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::intmap::IntMap;
+/// use functional_datastructures::set::Map;
+///
+/// let evens = IntMap::empty_map().insert(2, "two").insert(4, "four");
+/// let odds = IntMap::empty_map().insert(1, "one").insert(3, "three");
+/// let all = evens.merge(&odds);
+///
+/// assert!(all.lookup(3).unwrap() == "three");
+/// assert!(all.remove(3).lookup(3).is_none());
+/// assert!(all.keys().collect::<Vec<_>>() == vec![1, 2, 3, 4]);
+/// ```
+#[derive(Debug, Clone)]
+pub enum IntMapIn<V, P: SharedPointer> {
+    #[doc(hidden)]
+    Empty,
+    #[doc(hidden)]
+    Leaf { key: u64, value: V },
+    /// Keys whose bit `mask` is zero are in `left`, the rest in `right`.
+    /// Every key in the branch agrees with `prefix` on the bits above `mask`.
+    #[doc(hidden)]
+    Branch {
+        prefix: u64,
+        mask: u64,
+        left: Shared<IntMapIn<V, P>, P>,
+        right: Shared<IntMapIn<V, P>, P>,
+    },
+}
+
+/// An `IntMapIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type IntMap<V> = IntMapIn<V, DefaultPointer>;
+
+impl<V: Clone, P: SharedPointer> IntMapIn<V, P> {
+    /// Returns a map containing only a binding of `key` to `value`.
+    pub fn singleton(key: u64, value: V) -> Self {
+        IntMapIn::Leaf {
+            key: key,
+            value: value,
+        }
+    }
+    /// Returns a copy of this map with `key` bound to `value`, replacing any
+    /// existing binding for `key`.
+    pub fn insert(&self, key: u64, value: V) -> Self {
+        self.insert_with(key, value, &|_, new| new.clone())
+    }
+    /// Returns a copy of this map with `key` bound to `value`. If `key` was
+    /// already bound, it is instead bound to `combine(existing, value)`.
+    pub fn insert_with<F>(&self, key: u64, value: V, combine: &F) -> Self
+        where F: Fn(&V, &V) -> V
+    {
+        match *self {
+            IntMapIn::Empty => IntMapIn::singleton(key, value),
+            IntMapIn::Leaf { key: leaf_key, value: ref leaf_value } => {
+                if leaf_key == key {
+                    IntMapIn::singleton(key, combine(leaf_value, &value))
+                } else {
+                    join(key,
+                         Shared::new(IntMapIn::singleton(key, value)),
+                         leaf_key,
+                         Shared::new(self.clone()))
+                }
+            }
+            IntMapIn::Branch { prefix, mask, ref left, ref right } => {
+                if !matches_prefix(key, prefix, mask) {
+                    join(key,
+                         Shared::new(IntMapIn::singleton(key, value)),
+                         prefix,
+                         Shared::new(self.clone()))
+                } else if key & mask == 0 {
+                    IntMapIn::Branch {
+                        prefix: prefix,
+                        mask: mask,
+                        left: Shared::new(left.insert_with(key, value, combine)),
+                        right: right.clone(),
+                    }
+                } else {
+                    IntMapIn::Branch {
+                        prefix: prefix,
+                        mask: mask,
+                        left: left.clone(),
+                        right: Shared::new(right.insert_with(key, value, combine)),
+                    }
+                }
+            }
+        }
+    }
+    /// Binds `key` to `value` in this map in place, replacing any existing
+    /// binding for `key`. The branches on the path to `key` are updated
+    /// where they are unless another map shares them.
+    pub fn insert_mut(&mut self, key: u64, value: V) {
+        match *self {
+            IntMapIn::Branch { prefix, mask, ref mut left, ref mut right }
+                if matches_prefix(key, prefix, mask) => {
+                let child = if key & mask == 0 { left } else { right };
+                return Shared::make_mut(child).insert_mut(key, value);
+            }
+            IntMapIn::Leaf { key: leaf_key, value: ref mut leaf_value } if leaf_key == key => {
+                *leaf_value = value;
+                return;
+            }
+            _ => {}
+        }
+        // `key` belongs beside what is here rather than inside it, so the
+        // old node moves down into a new branch without being copied.
+        let other = match *self {
+            IntMapIn::Empty => None,
+            IntMapIn::Leaf { key, .. } => Some(key),
+            IntMapIn::Branch { prefix, .. } => Some(prefix),
+        };
+        let old = mem::replace(self, IntMapIn::Empty);
+        *self = match other {
+            None => IntMapIn::singleton(key, value),
+            Some(other) => {
+                join(key, Shared::new(IntMapIn::singleton(key, value)), other, Shared::new(old))
+            }
+        };
+    }
+    /// Returns a copy of this map without any binding for `key`.
+    pub fn remove(&self, key: u64) -> Self {
+        match *self {
+            IntMapIn::Empty => IntMapIn::Empty,
+            IntMapIn::Leaf { key: leaf_key, .. } => {
+                if leaf_key == key {
+                    IntMapIn::Empty
+                } else {
+                    self.clone()
+                }
+            }
+            IntMapIn::Branch { prefix, mask, ref left, ref right } => {
+                if !matches_prefix(key, prefix, mask) {
+                    self.clone()
+                } else if key & mask == 0 {
+                    branch(prefix, mask, Shared::new(left.remove(key)), right.clone())
+                } else {
+                    branch(prefix, mask, left.clone(), Shared::new(right.remove(key)))
+                }
+            }
+        }
+    }
+    /// Returns a reference to the value bound to `key`, or `None` if `key` is
+    /// not bound in this map.
+    pub fn get(&self, key: u64) -> Option<&V> {
+        let mut current = self;
+        loop {
+            match *current {
+                IntMapIn::Empty => return None,
+                IntMapIn::Leaf { key: leaf_key, ref value } => {
+                    return if leaf_key == key { Some(value) } else { None };
+                }
+                IntMapIn::Branch { prefix, mask, ref left, ref right } => {
+                    if !matches_prefix(key, prefix, mask) {
+                        return None;
+                    }
+                    current = if key & mask == 0 { left } else { right };
+                }
+            }
+        }
+    }
+    /// Tests whether `key` is bound in this map.
+    pub fn contains_key(&self, key: u64) -> bool {
+        self.get(key).is_some()
+    }
+    /// Tests whether this map is empty.
+    pub fn is_empty(&self) -> bool {
+        match *self {
+            IntMapIn::Empty => true,
+            _ => false,
+        }
+    }
+    /// Returns the number of keys bound in this map. Takes O(n) time.
+    pub fn size(&self) -> u32 {
+        match *self {
+            IntMapIn::Empty => 0,
+            IntMapIn::Leaf { .. } => 1,
+            IntMapIn::Branch { ref left, ref right, .. } => left.size() + right.size(),
+        }
+    }
+    /// Returns a map of the bindings in this map and `other`. Where a key is
+    /// bound in both, the binding from this map is kept.
+    ///
+    /// This is the same as `union`.
+    pub fn merge(&self, other: &Self) -> Self {
+        self.union(other)
+    }
+    /// Returns a map of the bindings in this map and `other`. Where a key is
+    /// bound in both, the binding from this map is kept.
+    ///
+    /// Subtrees which only occur in one of the two maps, or which the two
+    /// maps share, are reused in the result without being visited.
+    pub fn union(&self, other: &Self) -> Self {
+        self.union_sharing(other, true, &|value, _| value.clone())
+    }
+    /// Returns a map of the bindings in this map and `other`. Where a key is
+    /// bound in both, it is bound to `combine(this_value, other_value)`.
+    ///
+    /// Subtrees which only occur in one of the two maps are shared with the
+    /// result without being visited. Subtrees the two maps share are still
+    /// visited, since `combine` has to see every key bound in both.
+    pub fn union_with<F>(&self, other: &Self, combine: &F) -> Self
+        where F: Fn(&V, &V) -> V
+    {
+        self.union_sharing(other, false, combine)
+    }
+    /// Returns a map of the bindings in this map whose keys are also bound in
+    /// `other`.
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.intersection_sharing(other, true, &|value, _| value.clone())
+    }
+    /// Returns a map of the keys bound in both this map and `other`, each
+    /// bound to `combine(this_value, other_value)`.
+    ///
+    /// Subtrees the two maps share are still visited, since `combine` has to
+    /// see every key bound in both.
+    pub fn intersection_with<F>(&self, other: &Self, combine: &F) -> Self
+        where F: Fn(&V, &V) -> V
+    {
+        self.intersection_sharing(other, false, combine)
+    }
+    /// Returns a map of the bindings in this map whose keys are not bound in
+    /// `other`.
+    pub fn difference(&self, other: &Self) -> Self {
+        self.difference_sharing(other, true, &|_, _| None)
+    }
+    /// Returns a map of the bindings in this map whose keys are not bound in
+    /// `other`, along with those whose keys are bound in both for which
+    /// `combine(this_value, other_value)` returns `Some` of a new value, bound
+    /// to that value.
+    ///
+    /// Subtrees only in this map are shared with the result without being
+    /// visited. Subtrees the two maps share are still visited, since
+    /// `combine` has to see every key bound in both.
+    pub fn difference_with<F>(&self, other: &Self, combine: &F) -> Self
+        where F: Fn(&V, &V) -> Option<V>
+    {
+        self.difference_sharing(other, false, combine)
+    }
+    /// Returns the union of this map and `other`, binding keys in both to
+    /// `combine(this_value, other_value)`. If `share` is set, subtrees the
+    /// two maps share are reused as they are.
+    fn union_sharing<F>(&self, other: &Self, share: bool, combine: &F) -> Self
+        where F: Fn(&V, &V) -> V
+    {
+        match (self, other) {
+            (&IntMapIn::Empty, _) => other.clone(),
+            (_, &IntMapIn::Empty) => self.clone(),
+            (&IntMapIn::Leaf { key, ref value }, _) => {
+                other.insert_with(key, value.clone(), &|theirs, ours| combine(ours, theirs))
+            }
+            (_, &IntMapIn::Leaf { key, ref value }) => {
+                self.insert_with(key, value.clone(), combine)
+            }
+            (&IntMapIn::Branch { prefix: p, mask: m, left: ref s0, right: ref s1 },
+             &IntMapIn::Branch { prefix: q, mask: n, left: ref t0, right: ref t1 }) => {
+                if m == n && p == q {
+                    let side = |ours: &Shared<Self, P>, theirs: &Shared<Self, P>| {
+                        if share && Shared::ptr_eq(ours, theirs) {
+                            ours.clone()
+                        } else {
+                            Shared::new(ours.union_sharing(theirs, share, combine))
+                        }
+                    };
+                    IntMapIn::Branch {
+                        prefix: p,
+                        mask: m,
+                        left: side(s0, t0),
+                        right: side(s1, t1),
+                    }
+                } else if m > n && matches_prefix(q, p, m) {
+                    // `other` fits entirely inside one side of this branch.
+                    if q & m == 0 {
+                        IntMapIn::Branch {
+                            prefix: p,
+                            mask: m,
+                            left: Shared::new(s0.union_sharing(other, share, combine)),
+                            right: s1.clone(),
+                        }
+                    } else {
+                        IntMapIn::Branch {
+                            prefix: p,
+                            mask: m,
+                            left: s0.clone(),
+                            right: Shared::new(s1.union_sharing(other, share, combine)),
+                        }
+                    }
+                } else if m < n && matches_prefix(p, q, n) {
+                    // This map fits entirely inside one side of `other`.
+                    if p & n == 0 {
+                        IntMapIn::Branch {
+                            prefix: q,
+                            mask: n,
+                            left: Shared::new(self.union_sharing(t0, share, combine)),
+                            right: t1.clone(),
+                        }
+                    } else {
+                        IntMapIn::Branch {
+                            prefix: q,
+                            mask: n,
+                            left: t0.clone(),
+                            right: Shared::new(self.union_sharing(t1, share, combine)),
+                        }
+                    }
+                } else {
+                    join(p, Shared::new(self.clone()), q, Shared::new(other.clone()))
+                }
+            }
+        }
+    }
+    /// Returns the intersection of this map and `other`, binding each key to
+    /// `combine(this_value, other_value)`. If `share` is set, subtrees the
+    /// two maps share are reused as they are.
+    fn intersection_sharing<F>(&self, other: &Self, share: bool, combine: &F) -> Self
+        where F: Fn(&V, &V) -> V
+    {
+        match (self, other) {
+            (&IntMapIn::Empty, _) | (_, &IntMapIn::Empty) => IntMapIn::Empty,
+            (&IntMapIn::Leaf { key, ref value }, _) => {
+                match other.get(key) {
+                    Some(theirs) => IntMapIn::singleton(key, combine(value, theirs)),
+                    None => IntMapIn::Empty,
+                }
+            }
+            (_, &IntMapIn::Leaf { key, ref value }) => {
+                match self.get(key) {
+                    Some(ours) => IntMapIn::singleton(key, combine(ours, value)),
+                    None => IntMapIn::Empty,
+                }
+            }
+            (&IntMapIn::Branch { prefix: p, mask: m, left: ref s0, right: ref s1 },
+             &IntMapIn::Branch { prefix: q, mask: n, left: ref t0, right: ref t1 }) => {
+                if m == n && p == q {
+                    let side = |ours: &Shared<Self, P>, theirs: &Shared<Self, P>| {
+                        if share && Shared::ptr_eq(ours, theirs) {
+                            ours.clone()
+                        } else {
+                            Shared::new(ours.intersection_sharing(theirs, share, combine))
+                        }
+                    };
+                    branch(p, m, side(s0, t0), side(s1, t1))
+                } else if m > n && matches_prefix(q, p, m) {
+                    if q & m == 0 {
+                        s0.intersection_sharing(other, share, combine)
+                    } else {
+                        s1.intersection_sharing(other, share, combine)
+                    }
+                } else if m < n && matches_prefix(p, q, n) {
+                    if p & n == 0 {
+                        self.intersection_sharing(t0, share, combine)
+                    } else {
+                        self.intersection_sharing(t1, share, combine)
+                    }
+                } else {
+                    IntMapIn::Empty
+                }
+            }
+        }
+    }
+    /// Returns the difference of this map and `other`, keeping keys in both
+    /// bound to `combine(this_value, other_value)` where that is `Some`. If
+    /// `share` is set, subtrees the two maps share are taken to leave nothing.
+    fn difference_sharing<F>(&self, other: &Self, share: bool, combine: &F) -> Self
+        where F: Fn(&V, &V) -> Option<V>
+    {
+        match (self, other) {
+            (&IntMapIn::Empty, _) => IntMapIn::Empty,
+            (_, &IntMapIn::Empty) => self.clone(),
+            (&IntMapIn::Leaf { key, ref value }, _) => {
+                match other.get(key).map(|theirs| combine(value, theirs)) {
+                    None => self.clone(),
+                    Some(Some(value)) => IntMapIn::singleton(key, value),
+                    Some(None) => IntMapIn::Empty,
+                }
+            }
+            (_, &IntMapIn::Leaf { key, ref value }) => {
+                match self.get(key).map(|ours| combine(ours, value)) {
+                    None => self.clone(),
+                    Some(Some(value)) => self.insert(key, value),
+                    Some(None) => self.remove(key),
+                }
+            }
+            (&IntMapIn::Branch { prefix: p, mask: m, left: ref s0, right: ref s1 },
+             &IntMapIn::Branch { prefix: q, mask: n, left: ref t0, right: ref t1 }) => {
+                if m == n && p == q {
+                    let side = |ours: &Shared<Self, P>, theirs: &Shared<Self, P>| {
+                        if share && Shared::ptr_eq(ours, theirs) {
+                            Shared::new(IntMapIn::Empty)
+                        } else {
+                            Shared::new(ours.difference_sharing(theirs, share, combine))
+                        }
+                    };
+                    branch(p, m, side(s0, t0), side(s1, t1))
+                } else if m > n && matches_prefix(q, p, m) {
+                    if q & m == 0 {
+                        let left = s0.difference_sharing(other, share, combine);
+                        branch(p, m, Shared::new(left), s1.clone())
+                    } else {
+                        let right = s1.difference_sharing(other, share, combine);
+                        branch(p, m, s0.clone(), Shared::new(right))
+                    }
+                } else if m < n && matches_prefix(p, q, n) {
+                    if p & n == 0 {
+                        self.difference_sharing(t0, share, combine)
+                    } else {
+                        self.difference_sharing(t1, share, combine)
+                    }
+                } else {
+                    self.clone()
+                }
+            }
+        }
+    }
+    /// Returns an iterator over the bindings in this map, in ascending order
+    /// of key.
+    pub fn iter(&self) -> Iter<'_, V, P> {
+        Iter { pending: vec![self] }
+    }
+    /// Returns an iterator over the keys of this map, in ascending order.
+    pub fn keys(&self) -> Keys<'_, V, P> {
+        Keys { iter: self.iter() }
+    }
+}
+
+impl<V: Clone, P: SharedPointer> Collection for IntMapIn<V, P> {
+    type Iter<'a> = Iter<'a, V, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, V, P> {
+        self.iter()
+    }
+}
+
+impl<V, P: SharedPointer> MemoryUsage for IntMapIn<V, P> {
+    fn census(&self, census: &mut Census) {
+        let mut stack = vec![self];
+        while let Some(map) = stack.pop() {
+            if let IntMapIn::Branch { ref left, ref right, .. } = *map {
+                for child in &[left, right] {
+                    if census.node(&***child, 0) {
+                        stack.push(child);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<V: Clone, P: SharedPointer> Map<u64, V> for IntMapIn<V, P> {
+    fn empty_map() -> Self {
+        IntMapIn::Empty
+    }
+    fn bind(&self, key: u64, value: V) -> Self {
+        self.insert(key, value)
+    }
+    fn lookup(&self, key: u64) -> Option<V> {
+        self.get(key).cloned()
+    }
+    fn bind_mut(&mut self, key: u64, value: V) {
+        self.insert_mut(key, value)
+    }
+}
+
+impl<V: Clone, P: SharedPointer> PersistentMap<u64, V> for IntMapIn<V, P> {
+    fn get(&self, key: &u64) -> Option<&V> {
+        IntMapIn::get(self, *key)
+    }
+    fn remove(&self, key: &u64) -> Self {
+        IntMapIn::remove(self, *key)
+    }
+    fn union(&self, other: &Self) -> Self {
+        IntMapIn::union(self, other)
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        IntMapIn::intersection(self, other)
+    }
+    fn difference(&self, other: &Self) -> Self {
+        IntMapIn::difference(self, other)
+    }
+}
+
+/// An iterator over the bindings of an `IntMap`, returned by `IntMap::iter`.
+pub struct Iter<'a, V: 'a, P: SharedPointer> {
+    /// The subtrees still to be visited, the next one last.
+    pending: Vec<&'a IntMapIn<V, P>>,
+}
+
+impl<'a, V, P: SharedPointer> Iterator for Iter<'a, V, P> {
+    type Item = (u64, &'a V);
+
+    fn next(&mut self) -> Option<(u64, &'a V)> {
+        while let Some(node) = self.pending.pop() {
+            match *node {
+                IntMapIn::Empty => {}
+                IntMapIn::Leaf { key, ref value } => return Some((key, value)),
+                IntMapIn::Branch { ref left, ref right, .. } => {
+                    self.pending.push(right);
+                    self.pending.push(left);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// An iterator over the keys of an `IntMap`, returned by `IntMap::keys`.
+pub struct Keys<'a, V: 'a, P: SharedPointer> {
+    iter: Iter<'a, V, P>,
+}
+
+impl<'a, V, P: SharedPointer> Iterator for Keys<'a, V, P> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        self.iter.next().map(|(key, _)| key)
+    }
+}
+
+/// An immutable set of `u64` values, implemented as a big-endian Patricia
+/// trie like `IntMap`.
+///
+/// Members are grouped by all but their lowest six bits, and each group is
+/// stored as a single leaf holding a 64-bit bitmap, so dense runs of values
+/// take up a fraction of the space and time they would as separate leaves.
+/// As with `IntMap`, `union`, `intersection` and `difference` walk the
+/// structure of both sets side by side and share any subtree which only
+/// occurs in one of them. Iteration visits members in ascending order.
+///
+/// This makes an `IntSet` a persistent bitset, and it is also available
+/// under that name as `BitSet`.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::intmap::IntSet;
+/// use functional_datastructures::set::Set;
+///
+/// let low = (0..10).fold(IntSet::empty(), |set, i| set.insert(i));
+/// let evens = (0..10).fold(IntSet::empty(), |set, i| set.insert(i * 2));
+///
+/// assert!(low.member(9));
+/// assert!(low.size() == 10);
+/// assert!(low.union(&evens).size() == 15);
+/// assert!(low.intersection(&evens).iter().collect::<Vec<_>>() == vec![0, 2, 4, 6, 8]);
+/// assert!(low.difference(&evens).iter().collect::<Vec<_>>() == vec![1, 3, 5, 7, 9]);
+/// ```
+#[derive(Debug, Clone)]
+pub enum IntSetIn<P: SharedPointer> {
+    #[doc(hidden)]
+    Empty,
+    /// The members whose bits above the lowest six equal `prefix`; member
+    /// `prefix + i` is present if bit `i` of `bits` is set. `bits` is never
+    /// zero.
+    #[doc(hidden)]
+    Tip { prefix: u64, bits: u64 },
+    /// As for `IntMap::Branch`.
+    #[doc(hidden)]
+    Branch {
+        prefix: u64,
+        mask: u64,
+        left: Shared<IntSetIn<P>, P>,
+        right: Shared<IntSetIn<P>, P>,
+    },
+}
+
+/// An `IntSetIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type IntSet = IntSetIn<DefaultPointer>;
+
+impl<P: SharedPointer> IntSetIn<P> {
+    /// Tests whether `value` is a member of this set.
+    pub fn contains(&self, value: u64) -> bool {
+        self.bits_for(tip_prefix(value)) & tip_bit(value) != 0
+    }
+    /// Returns a copy of this set without `value`.
+    pub fn remove(&self, value: u64) -> Self {
+        self.remove_bits(tip_prefix(value), tip_bit(value))
+    }
+    /// Tests whether this set is empty.
+    pub fn is_empty(&self) -> bool {
+        match *self {
+            IntSetIn::Empty => true,
+            _ => false,
+        }
+    }
+    /// Returns the number of members of this set. Takes O(n) time.
+    pub fn size(&self) -> u32 {
+        match *self {
+            IntSetIn::Empty => 0,
+            IntSetIn::Tip { bits, .. } => bits.count_ones(),
+            IntSetIn::Branch { ref left, ref right, .. } => left.size() + right.size(),
+        }
+    }
+    /// Returns a set of the values which are members of either this set or
+    /// `other`.
+    pub fn union(&self, other: &Self) -> Self {
+        match (self, other) {
+            (&IntSetIn::Empty, _) => other.clone(),
+            (_, &IntSetIn::Empty) => self.clone(),
+            (&IntSetIn::Tip { prefix, bits }, _) => other.insert_bits(prefix, bits),
+            (_, &IntSetIn::Tip { prefix, bits }) => self.insert_bits(prefix, bits),
+            (&IntSetIn::Branch { prefix: p, mask: m, left: ref s0, right: ref s1 },
+             &IntSetIn::Branch { prefix: q, mask: n, left: ref t0, right: ref t1 }) => {
+                if m == n && p == q {
+                    let side = |ours: &Shared<Self, P>, theirs: &Shared<Self, P>| {
+                        if Shared::ptr_eq(ours, theirs) {
+                            ours.clone()
+                        } else {
+                            Shared::new(ours.union(theirs))
+                        }
+                    };
+                    set_branch(p, m, side(s0, t0), side(s1, t1))
+                } else if m > n && matches_prefix(q, p, m) {
+                    if q & m == 0 {
+                        set_branch(p, m, Shared::new(s0.union(other)), s1.clone())
+                    } else {
+                        set_branch(p, m, s0.clone(), Shared::new(s1.union(other)))
+                    }
+                } else if m < n && matches_prefix(p, q, n) {
+                    if p & n == 0 {
+                        set_branch(q, n, Shared::new(self.union(t0)), t1.clone())
+                    } else {
+                        set_branch(q, n, t0.clone(), Shared::new(self.union(t1)))
+                    }
+                } else {
+                    set_join(p, Shared::new(self.clone()), q, Shared::new(other.clone()))
+                }
+            }
+        }
+    }
+    /// Returns a set of the values which are members of both this set and
+    /// `other`.
+    pub fn intersection(&self, other: &Self) -> Self {
+        match (self, other) {
+            (&IntSetIn::Empty, _) | (_, &IntSetIn::Empty) => IntSetIn::Empty,
+            (&IntSetIn::Tip { prefix, bits }, _) => tip(prefix, bits & other.bits_for(prefix)),
+            (_, &IntSetIn::Tip { prefix, bits }) => tip(prefix, bits & self.bits_for(prefix)),
+            (&IntSetIn::Branch { prefix: p, mask: m, left: ref s0, right: ref s1 },
+             &IntSetIn::Branch { prefix: q, mask: n, left: ref t0, right: ref t1 }) => {
+                if m == n && p == q {
+                    let side = |ours: &Shared<Self, P>, theirs: &Shared<Self, P>| {
+                        if Shared::ptr_eq(ours, theirs) {
+                            ours.clone()
+                        } else {
+                            Shared::new(ours.intersection(theirs))
+                        }
+                    };
+                    set_branch(p, m, side(s0, t0), side(s1, t1))
+                } else if m > n && matches_prefix(q, p, m) {
+                    if q & m == 0 {
+                        s0.intersection(other)
+                    } else {
+                        s1.intersection(other)
+                    }
+                } else if m < n && matches_prefix(p, q, n) {
+                    if p & n == 0 {
+                        self.intersection(t0)
+                    } else {
+                        self.intersection(t1)
+                    }
+                } else {
+                    IntSetIn::Empty
+                }
+            }
+        }
+    }
+    /// Returns a set of the values which are members of this set but not of
+    /// `other`.
+    pub fn difference(&self, other: &Self) -> Self {
+        match (self, other) {
+            (&IntSetIn::Empty, _) => IntSetIn::Empty,
+            (_, &IntSetIn::Empty) => self.clone(),
+            (&IntSetIn::Tip { prefix, bits }, _) => tip(prefix, bits & !other.bits_for(prefix)),
+            (_, &IntSetIn::Tip { prefix, bits }) => self.remove_bits(prefix, bits),
+            (&IntSetIn::Branch { prefix: p, mask: m, left: ref s0, right: ref s1 },
+             &IntSetIn::Branch { prefix: q, mask: n, left: ref t0, right: ref t1 }) => {
+                if m == n && p == q {
+                    let side = |ours: &Shared<Self, P>, theirs: &Shared<Self, P>| {
+                        if Shared::ptr_eq(ours, theirs) {
+                            Shared::new(IntSetIn::Empty)
+                        } else {
+                            Shared::new(ours.difference(theirs))
+                        }
+                    };
+                    set_branch(p, m, side(s0, t0), side(s1, t1))
+                } else if m > n && matches_prefix(q, p, m) {
+                    if q & m == 0 {
+                        set_branch(p, m, Shared::new(s0.difference(other)), s1.clone())
+                    } else {
+                        set_branch(p, m, s0.clone(), Shared::new(s1.difference(other)))
+                    }
+                } else if m < n && matches_prefix(p, q, n) {
+                    if p & n == 0 {
+                        self.difference(t0)
+                    } else {
+                        self.difference(t1)
+                    }
+                } else {
+                    self.clone()
+                }
+            }
+        }
+    }
+    /// Returns a set of the values in `range` which are not members of this
+    /// set. Takes O(n + r/64) time, where r is the length of the range.
+    pub fn complement_within(&self, range: Range<u64>) -> Self {
+        let mut full = IntSetIn::Empty;
+        let mut start = range.start;
+        while start < range.end {
+            let prefix = tip_prefix(start);
+            let below_end = if range.end - prefix >= 64 { !0 } else { tip_bit(range.end) - 1 };
+            full = full.insert_bits(prefix, below_end & !(tip_bit(start) - 1));
+            match prefix.checked_add(64) {
+                Some(next) => start = next,
+                None => break,
+            }
+        }
+        full.difference(self)
+    }
+    /// Tests whether every member of this set is also a member of `other`.
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.difference(other).is_empty()
+    }
+    /// Returns an iterator over the members of this set, in ascending order.
+    pub fn iter(&self) -> SetIter<'_, P> {
+        SetIter {
+            pending: vec![self],
+            prefix: 0,
+            bits: 0,
+        }
+    }
+    /// Returns the bitmap of the members of this set with the given prefix.
+    fn bits_for(&self, prefix: u64) -> u64 {
+        let mut current = self;
+        loop {
+            match *current {
+                IntSetIn::Empty => return 0,
+                IntSetIn::Tip { prefix: tip_prefix, bits } => {
+                    return if tip_prefix == prefix { bits } else { 0 };
+                }
+                IntSetIn::Branch { prefix: branch_prefix, mask, ref left, ref right } => {
+                    if !matches_prefix(prefix, branch_prefix, mask) {
+                        return 0;
+                    }
+                    current = if prefix & mask == 0 { left } else { right };
+                }
+            }
+        }
+    }
+    /// Returns a copy of this set with the members in `bits` under `prefix`
+    /// added.
+    fn insert_bits(&self, prefix: u64, bits: u64) -> Self {
+        match *self {
+            IntSetIn::Empty => tip(prefix, bits),
+            IntSetIn::Tip { prefix: tip_prefix, bits: tip_bits } => {
+                if tip_prefix == prefix {
+                    tip(prefix, tip_bits | bits)
+                } else {
+                    set_join(prefix,
+                             Shared::new(tip(prefix, bits)),
+                             tip_prefix,
+                             Shared::new(self.clone()))
+                }
+            }
+            IntSetIn::Branch { prefix: branch_prefix, mask, ref left, ref right } => {
+                if !matches_prefix(prefix, branch_prefix, mask) {
+                    set_join(prefix,
+                             Shared::new(tip(prefix, bits)),
+                             branch_prefix,
+                             Shared::new(self.clone()))
+                } else if prefix & mask == 0 {
+                    set_branch(branch_prefix,
+                               mask,
+                               Shared::new(left.insert_bits(prefix, bits)),
+                               right.clone())
+                } else {
+                    set_branch(branch_prefix,
+                               mask,
+                               left.clone(),
+                               Shared::new(right.insert_bits(prefix, bits)))
+                }
+            }
+        }
+    }
+    /// Returns a copy of this set with the members in `bits` under `prefix`
+    /// removed.
+    fn remove_bits(&self, prefix: u64, bits: u64) -> Self {
+        match *self {
+            IntSetIn::Empty => IntSetIn::Empty,
+            IntSetIn::Tip { prefix: tip_prefix, bits: tip_bits } => {
+                if tip_prefix == prefix {
+                    tip(prefix, tip_bits & !bits)
+                } else {
+                    self.clone()
+                }
+            }
+            IntSetIn::Branch { prefix: branch_prefix, mask, ref left, ref right } => {
+                if !matches_prefix(prefix, branch_prefix, mask) {
+                    self.clone()
+                } else if prefix & mask == 0 {
+                    set_branch(branch_prefix,
+                               mask,
+                               Shared::new(left.remove_bits(prefix, bits)),
+                               right.clone())
+                } else {
+                    set_branch(branch_prefix,
+                               mask,
+                               left.clone(),
+                               Shared::new(right.remove_bits(prefix, bits)))
+                }
+            }
+        }
+    }
+}
+
+impl<P: SharedPointer> Collection for IntSetIn<P> {
+    type Iter<'a> = SetIter<'a, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> SetIter<'a, P> {
+        self.iter()
+    }
+}
+
+impl<P: SharedPointer> MemoryUsage for IntSetIn<P> {
+    fn census(&self, census: &mut Census) {
+        let mut stack = vec![self];
+        while let Some(set) = stack.pop() {
+            if let IntSetIn::Branch { ref left, ref right, .. } = *set {
+                for child in &[left, right] {
+                    if census.node(&***child, 0) {
+                        stack.push(child);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<P: SharedPointer> Set<u64> for IntSetIn<P> {
+    fn empty() -> Self {
+        IntSetIn::Empty
+    }
+    fn insert(&self, value: u64) -> Self {
+        self.insert_bits(tip_prefix(value), tip_bit(value))
+    }
+    fn member(&self, value: u64) -> bool {
+        self.contains(value)
+    }
+}
+
+impl<P: SharedPointer> PersistentSet<u64> for IntSetIn<P> {
+    fn contains(&self, value: &u64) -> bool {
+        IntSetIn::contains(self, *value)
+    }
+    fn remove(&self, value: &u64) -> Self {
+        IntSetIn::remove(self, *value)
+    }
+    fn union(&self, other: &Self) -> Self {
+        IntSetIn::union(self, other)
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        IntSetIn::intersection(self, other)
+    }
+    fn difference(&self, other: &Self) -> Self {
+        IntSetIn::difference(self, other)
+    }
+}
+
+/// A persistent bitset: an `IntSet`, which stores its members as 64-bit words
+/// of bits.
+pub type BitSet = IntSet;
+
+/// An iterator over the members of an `IntSet`, returned by `IntSet::iter`.
+pub struct SetIter<'a, P: SharedPointer> {
+    /// The subtrees still to be visited, the next one last.
+    pending: Vec<&'a IntSetIn<P>>,
+    /// The prefix and remaining members of the leaf currently being visited.
+    prefix: u64,
+    bits: u64,
+}
+
+impl<'a, P: SharedPointer> Iterator for SetIter<'a, P> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while self.bits == 0 {
+            match *self.pending.pop()? {
+                IntSetIn::Empty => {}
+                IntSetIn::Tip { prefix, bits } => {
+                    self.prefix = prefix;
+                    self.bits = bits;
+                }
+                IntSetIn::Branch { ref left, ref right, .. } => {
+                    self.pending.push(right);
+                    self.pending.push(left);
+                }
+            }
+        }
+        let lowest = self.bits.trailing_zeros() as u64;
+        self.bits &= self.bits - 1;
+        Some(self.prefix + lowest)
+    }
+}
+
+/// Returns a branch containing the subtrees `left` and `right`, or just one of
+/// them if the other is empty.
+fn branch<V: Clone, P: SharedPointer>(prefix: u64,
+                                      mask: u64,
+                                      left: Shared<IntMapIn<V, P>, P>,
+                                      right: Shared<IntMapIn<V, P>, P>)
+                                      -> IntMapIn<V, P> {
+    if left.is_empty() {
+        (*right).clone()
+    } else if right.is_empty() {
+        (*left).clone()
+    } else {
+        IntMapIn::Branch {
+            prefix: prefix,
+            mask: mask,
+            left: left,
+            right: right,
+        }
+    }
+}
+
+/// Returns a branch containing two subtrees with prefixes that disagree.
+fn join<V, P: SharedPointer>(first_prefix: u64,
+                             first: Shared<IntMapIn<V, P>, P>,
+                             second_prefix: u64,
+                             second: Shared<IntMapIn<V, P>, P>)
+                             -> IntMapIn<V, P> {
+    let mask = branch_mask(first_prefix, second_prefix);
+    let (left, right) = if first_prefix & mask == 0 {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    IntMapIn::Branch {
+        prefix: mask_prefix(first_prefix, mask),
+        mask: mask,
+        left: left,
+        right: right,
+    }
+}
+
+/// Returns a set branch containing the subtrees `left` and `right`, or just
+/// one of them if the other is empty.
+fn set_branch<P: SharedPointer>(prefix: u64,
+                                mask: u64,
+                                left: Shared<IntSetIn<P>, P>,
+                                right: Shared<IntSetIn<P>, P>)
+                                -> IntSetIn<P> {
+    if left.is_empty() {
+        (*right).clone()
+    } else if right.is_empty() {
+        (*left).clone()
+    } else {
+        IntSetIn::Branch {
+            prefix: prefix,
+            mask: mask,
+            left: left,
+            right: right,
+        }
+    }
+}
+
+/// Returns a set branch containing two subtrees with prefixes that disagree.
+fn set_join<P: SharedPointer>(first_prefix: u64,
+                              first: Shared<IntSetIn<P>, P>,
+                              second_prefix: u64,
+                              second: Shared<IntSetIn<P>, P>)
+                              -> IntSetIn<P> {
+    let mask = branch_mask(first_prefix, second_prefix);
+    let (left, right) = if first_prefix & mask == 0 {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    IntSetIn::Branch {
+        prefix: mask_prefix(first_prefix, mask),
+        mask: mask,
+        left: left,
+        right: right,
+    }
+}
+
+/// Returns a set leaf, or an empty set if `bits` has no members.
+fn tip<P: SharedPointer>(prefix: u64, bits: u64) -> IntSetIn<P> {
+    if bits == 0 {
+        IntSetIn::Empty
+    } else {
+        IntSetIn::Tip {
+            prefix: prefix,
+            bits: bits,
+        }
+    }
+}
+
+/// Returns the prefix of the set leaf which `value` belongs in.
+fn tip_prefix(value: u64) -> u64 {
+    value & !63
+}
+
+/// Returns the bit for `value` in its set leaf's bitmap.
+fn tip_bit(value: u64) -> u64 {
+    1 << (value & 63)
+}
+
+/// Returns the bits of `key` above the bit `mask`.
+fn mask_prefix(key: u64, mask: u64) -> u64 {
+    key & !(mask | (mask - 1))
+}
+
+/// Tests whether `key` agrees with `prefix` on the bits above `mask`.
+fn matches_prefix(key: u64, prefix: u64, mask: u64) -> bool {
+    mask_prefix(key, mask) == prefix
+}
+
+/// Returns the highest bit at which two different prefixes disagree.
+fn branch_mask(first: u64, second: u64) -> u64 {
+    1 << (63 - (first ^ second).leading_zeros())
+}
+
+
+#[test]
+fn empty_map_contains_nothing() {
+    let map: IntMap<()> = IntMap::empty_map();
+
+    assert!(map.lookup(0).is_none());
+    assert!(map.size() == 0);
+    assert!(map.iter().next().is_none());
+}
+
+#[test]
+fn inserted_keys_are_found_in_order() {
+    let keys = vec![5, 0, u64::max_value(), 1 << 63, 12, 7, 1 << 32, 3];
+    let map = keys.iter().fold(IntMap::empty_map(), |map, k| map.insert(*k, k.wrapping_add(1)));
+
+    let mut sorted = keys.clone();
+    sorted.sort();
+
+    assert!(map.size() == 8);
+    for key in keys {
+        assert!(map.lookup(key).unwrap() == key.wrapping_add(1));
+    }
+    assert!(map.lookup(6).is_none());
+    assert!(map.keys().collect::<Vec<_>>() == sorted);
+}
+
+#[test]
+fn values_can_be_replaced() {
+    let map1 = IntMap::empty_map().insert(1, "one").insert(2, "two");
+    let map2 = map1.insert(2, "not two");
+
+    assert!(map1.lookup(2).unwrap() == "two");
+    assert!(map2.lookup(2).unwrap() == "not two");
+}
+
+#[test]
+fn removed_keys_are_gone() {
+    let map = (0..200).fold(IntMap::empty_map(), |map, i| map.insert(i * 3, i));
+    let removed = (0..100).fold(map.clone(), |map, i| map.remove(i * 6));
+
+    assert!(removed.size() == 100);
+    assert!(removed.lookup(6).is_none());
+    assert!(removed.lookup(9).unwrap() == 3);
+    assert!(map.lookup(6).unwrap() == 2);
+    assert!((0..200).fold(map, |map, i| map.remove(i * 3)).is_empty());
+}
+
+#[test]
+fn union_with_combines_shared_keys() {
+    let threes = (0..50).fold(IntMap::empty_map(), |map, i| map.insert(i * 3, 1));
+    let fives = (0..30).fold(IntMap::empty_map(), |map, i| map.insert(i * 5, 10));
+
+    let sum = threes.union_with(&fives, &|a, b| a + b);
+
+    let expected_value = |k: u64| if k % 15 == 0 {
+        11
+    } else if k % 3 == 0 {
+        1
+    } else {
+        10
+    };
+    let expected: Vec<(u64, u32)> = (0..150)
+                                        .filter(|k| k % 3 == 0 || k % 5 == 0)
+                                        .map(|k| (k, expected_value(k)))
+                                        .collect();
+    assert!(sum.iter().map(|(k, v)| (k, *v)).collect::<Vec<_>>() == expected);
+}
+
+#[test]
+fn intersection_with_and_difference_with_combine_shared_keys() {
+    let threes = (0..50).fold(IntMap::empty_map(), |map, i| map.insert(i * 3, 1));
+    let fives = (0..30).fold(IntMap::empty_map(), |map, i| map.insert(i * 5, 10));
+
+    let sum = threes.intersection_with(&fives, &|a, b| a + b);
+    let expected: Vec<(u64, u32)> = (0..10).map(|i| (i * 15, 11)).collect();
+    assert!(sum.iter().map(|(k, v)| (k, *v)).collect::<Vec<_>>() == expected);
+
+    let adjusted = threes.difference_with(&fives, &|a, b| if b > a { None } else { Some(a - b) });
+    assert!(adjusted.iter().map(|(k, _)| k).eq((0..150).filter(|k| k % 3 == 0 && k % 5 != 0)));
+    let kept = threes.difference_with(&fives, &|a, b| Some(a * b));
+    assert!(kept.size() == 50);
+    assert!(kept.lookup(30).unwrap() == 10);
+    assert!(kept.lookup(33).unwrap() == 1);
+
+    // Shared subtrees still pass through `combine`.
+    let bigger = threes.insert(1000, 1);
+    let doubled = threes.intersection_with(&bigger, &|a, b| a + b);
+    assert!(doubled.size() == 50 && doubled.iter().all(|(_, v)| *v == 2));
+    assert!(threes.difference_with(&bigger, &|a, _| Some(a + 1)).iter().all(|(_, v)| *v == 2));
+}
+
+#[test]
+fn merge_prefers_left_values() {
+    let left = IntMap::empty_map().insert(1, "left").insert(100, "left only");
+    let right = IntMap::empty_map().insert(1, "right").insert(1000, "right only");
+
+    let merged = left.merge(&right);
+
+    assert!(merged.lookup(1).unwrap() == "left");
+    assert!(merged.lookup(100).unwrap() == "left only");
+    assert!(merged.lookup(1000).unwrap() == "right only");
+    assert!(right.merge(&left).lookup(1).unwrap() == "right");
+}
+
+#[test]
+fn intersection_and_difference_keep_left_values() {
+    let threes = (0..200).fold(IntMap::empty_map(), |map, i| map.insert(i * 3, "three"));
+    let fives = (0..120).fold(IntMap::empty_map(), |map, i| map.insert(i * 5, "five"));
+
+    let both = threes.intersection(&fives);
+    assert!(both.iter().map(|(k, v)| (k, *v)).collect::<Vec<_>>() ==
+            (0..40).map(|i| (i * 15, "three")).collect::<Vec<_>>());
+
+    let only = threes.difference(&fives);
+    assert!(only.keys().collect::<Vec<_>>() ==
+            (0..600).filter(|k| k % 3 == 0 && k % 5 != 0).collect::<Vec<_>>());
+    assert!(threes.difference(&threes).is_empty());
+    assert!(threes.intersection(&IntMap::empty_map()).is_empty());
+}
+
+#[cfg(test)]
+fn set_of(values: &[u64]) -> IntSet {
+    values.iter().fold(IntSet::empty(), |set, v| set.insert(*v))
+}
+
+#[test]
+fn set_insert_contains_remove() {
+    let set = set_of(&[3, 64, 65, 1 << 40, u64::max_value()]);
+
+    assert!(set.size() == 5);
+    for value in &[3, 64, 65, 1 << 40, u64::max_value()] {
+        assert!(set.contains(*value));
+    }
+    assert!(!set.contains(4));
+    assert!(!set.remove(64).contains(64));
+    assert!(set.remove(64).contains(65));
+    assert!([3, 64, 65, 1 << 40, u64::max_value()]
+                .iter()
+                .fold(set, |set, v| set.remove(*v))
+                .is_empty());
+}
+
+#[test]
+fn dense_values_share_a_leaf() {
+    let set = (0..64).fold(IntSet::empty(), |set, i| set.insert(i));
+
+    match set {
+        IntSet::Tip { prefix, bits } => {
+            assert!(prefix == 0);
+            assert!(bits == u64::max_value());
+        }
+        _ => panic!("expected a single leaf"),
+    }
+    assert!(set.iter().collect::<Vec<_>>() == (0..64).collect::<Vec<_>>());
+}
+
+#[test]
+fn set_algebra_matches_filters() {
+    let threes: Vec<u64> = (0..1000).filter(|i| i % 3 == 0).collect();
+    let sevens: Vec<u64> = (0..1000).filter(|i| i % 7 == 0).map(|i| i * 5).collect();
+    let a = set_of(&threes);
+    let b = set_of(&sevens);
+
+    let union: Vec<u64> = a.union(&b).iter().collect();
+    let mut expected: Vec<u64> = threes.iter().chain(sevens.iter()).cloned().collect();
+    expected.sort();
+    expected.dedup();
+    assert!(union == expected);
+
+    let intersection: Vec<u64> = a.intersection(&b).iter().collect();
+    assert!(intersection ==
+            sevens.iter().cloned().filter(|v| v % 3 == 0 && *v < 1000).collect::<Vec<_>>());
+
+    let difference: Vec<u64> = a.difference(&b).iter().collect();
+    assert!(difference ==
+            threes.iter().cloned().filter(|v| !b.contains(*v)).collect::<Vec<_>>());
+
+    assert!(a.intersection(&b).is_subset(&a));
+    assert!(!a.is_subset(&b));
+    assert!(a.difference(&a).is_empty());
+}
+
+#[test]
+fn complement_within_flips_a_range() {
+    let set = set_of(&[0, 5, 63, 64, 100, 130, 500]);
+
+    let complement: Vec<u64> = set.complement_within(3..131).iter().collect();
+    assert!(complement ==
+            (3..131).filter(|v| ![5, 63, 64, 100, 130].contains(v)).collect::<Vec<_>>());
+    assert!(set.complement_within(64..128).size() == 62);
+    assert!(set.complement_within(7..7).is_empty());
+    assert!(IntSet::empty().complement_within(10..20).iter().collect::<Vec<_>>() ==
+            (10..20).collect::<Vec<_>>());
+
+    let top = u64::max_value() - 70;
+    let high: Vec<u64> = set_of(&[top + 1])
+                             .complement_within(top..u64::max_value())
+                             .iter()
+                             .collect();
+    assert!(high.len() == 69 && high[0] == top && high[1] == top + 2);
+}
+
+#[test]
+fn in_place_inserts_match_persistent_ones() {
+    let keys: Vec<u64> = (0..500).map(|i| (i * 7919) % 1000).collect();
+    let mut map = IntMap::empty_map();
+    let mut halfway = IntMap::empty_map();
+    for (n, &key) in keys.iter().enumerate() {
+        map.bind_mut(key, n);
+        if n == 250 {
+            halfway = map.clone();
+        }
+    }
+    let expected = keys.iter()
+                       .enumerate()
+                       .fold(IntMap::empty_map(), |map, (n, &key)| map.insert(key, n));
+    assert!(map.iter().collect::<Vec<_>>() == expected.iter().collect::<Vec<_>>());
+    assert!(halfway.size() == 251);
+}
@@ -0,0 +1,274 @@
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+#[cfg(any(test, feature = "amortization-debug"))]
+use std::sync::Arc;
+#[cfg(feature = "amortization-debug")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ptr::{DefaultPointer, SendFor, Shared, SharedPointer};
+
+/// A memoized suspension: a computation which is not run until its value
+/// is first demanded, and which is then never run again.
+///
+/// This is the `$` notation used from Chapter 4 of PFDL onwards. Clones of a
+/// suspension share their result, so forcing any one of them forces them all.
+pub struct SuspIn<T, P: SharedPointer> {
+    cell: Shared<SuspCell<T, P>, P>,
+}
+
+/// A `SuspIn` shared through `DefaultPointer`, as exported at the root of
+/// the crate.
+pub type Susp<T> = SuspIn<T, DefaultPointer>;
+
+struct SuspCell<T, P: SharedPointer> {
+    value: OnceLock<T>,
+    thunk: Mutex<Option<P::Thunk<T>>>,
+}
+
+impl<T, P: SharedPointer> SuspIn<T, P> {
+    /// Creates a suspension which will run `thunk` when first forced.
+    pub fn new<F>(thunk: F) -> Self
+        where F: FnOnce() -> T + SendFor<P> + 'static
+    {
+        // SAFETY: `thunk` is `SendFor<P>`.
+        unsafe { SuspIn::new_unchecked(thunk) }
+    }
+    /// Creates a suspension which will run `thunk` when first forced, where
+    /// the compiler cannot see that `thunk` is `SendFor<P>`.
+    ///
+    /// # Safety
+    ///
+    /// `thunk` must be `SendFor<P>`, as it is when everything it captures
+    /// is. The lazy structures build their suspensions from closures over
+    /// their own cells, which are `SendFor<P>` whenever their contents are,
+    /// but the compiler cannot prove that of a closure in code generic over
+    /// `P`.
+    pub(crate) unsafe fn new_unchecked<F>(thunk: F) -> Self
+        where F: FnOnce() -> T + 'static
+    {
+        SuspIn {
+            cell: Shared::new(SuspCell {
+                value: OnceLock::new(),
+                thunk: Mutex::new(Some(unsafe { P::thunk(thunk) })),
+            }),
+        }
+    }
+    /// Creates a suspension which has already been evaluated to `value`.
+    pub fn ready(value: T) -> Self {
+        let value_cell = OnceLock::new();
+        let _ = value_cell.set(value);
+        SuspIn {
+            cell: Shared::new(SuspCell {
+                value: value_cell,
+                thunk: Mutex::new(None),
+            }),
+        }
+    }
+    /// Returns the value of this suspension, evaluating it if necessary.
+    pub fn force(&self) -> &T {
+        self.cell.value.get_or_init(|| {
+            let thunk = self.cell
+                            .thunk
+                            .lock()
+                            .unwrap()
+                            .take()
+                            .expect("suspension has no value and no thunk");
+            thunk()
+        })
+    }
+    /// Tests whether this suspension has already been evaluated.
+    pub fn is_forced(&self) -> bool {
+        self.cell.value.get().is_some()
+    }
+    /// Returns a mutable reference to the value of this suspension, if it
+    /// has been evaluated and no clone of it remains to see the change.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        Shared::get_mut(&mut self.cell).and_then(|cell| cell.value.get_mut())
+    }
+}
+
+#[cfg(feature = "amortization-debug")]
+impl<T, P: SharedPointer> SuspIn<T, P> {
+    /// Creates a suspension which will run `thunk` when first forced, and
+    /// records its creation and evaluation in `counters`.
+    pub fn counted<F>(counters: &Counters, thunk: F) -> Self
+        where F: FnOnce() -> T + SendFor<P> + 'static
+    {
+        // SAFETY: `thunk` is `SendFor<P>`.
+        unsafe { SuspIn::counted_unchecked(counters, thunk) }
+    }
+    /// Creates a suspension as `counted` does, where the compiler cannot see
+    /// that `thunk` is `SendFor<P>`.
+    ///
+    /// # Safety
+    ///
+    /// `thunk` must be `SendFor<P>`. See `new_unchecked`.
+    pub(crate) unsafe fn counted_unchecked<F>(counters: &Counters, thunk: F) -> Self
+        where F: FnOnce() -> T + 'static
+    {
+        let counters = counters.clone();
+        counters.record_created();
+        // SAFETY: `counters` is `Send`, and the caller promises that `thunk`
+        // is `SendFor<P>`.
+        unsafe {
+            SuspIn::new_unchecked(move || {
+                counters.record_forced();
+                thunk()
+            })
+        }
+    }
+}
+
+impl<T, P: SharedPointer> Clone for SuspIn<T, P> {
+    fn clone(&self) -> Self {
+        SuspIn { cell: self.cell.clone() }
+    }
+}
+
+impl<T: fmt::Debug, P: SharedPointer> fmt::Debug for SuspIn<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.cell.value.get() {
+            Some(value) => write!(f, "Susp({:?})", value),
+            None => write!(f, "Susp(<unevaluated>)"),
+        }
+    }
+}
+
+/// A snapshot of the amortization counters of a structure.
+///
+/// Only available with the `amortization-debug` feature.
+#[cfg(feature = "amortization-debug")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    /// The number of suspensions that have been created.
+    pub suspensions_created: usize,
+    /// The number of suspensions that have been evaluated. A suspension is
+    /// only counted the first time it is forced.
+    pub suspensions_forced: usize,
+    /// The number of rotations (monolithic reversals) that have been run.
+    pub rotations: usize,
+    /// The total number of elements moved by all rotations.
+    pub rotated_elements: usize,
+    /// The number of elements moved by the largest single rotation.
+    pub largest_rotation: usize,
+}
+
+/// Shared counters which the suspensions and rotations of a structure
+/// record themselves in.
+///
+/// Every persistent version derived from a structure shares its counters, so
+/// a rotation that is forced from several versions is only counted once but
+/// one which is rebuilt in each version is counted every time.
+///
+/// Only available with the `amortization-debug` feature.
+#[cfg(feature = "amortization-debug")]
+#[derive(Debug, Clone, Default)]
+pub struct Counters {
+    inner: Arc<CountersInner>,
+}
+
+#[cfg(feature = "amortization-debug")]
+#[derive(Debug, Default)]
+struct CountersInner {
+    suspensions_created: AtomicUsize,
+    suspensions_forced: AtomicUsize,
+    rotations: AtomicUsize,
+    rotated_elements: AtomicUsize,
+    largest_rotation: AtomicUsize,
+}
+
+#[cfg(feature = "amortization-debug")]
+impl Counters {
+    /// Creates a fresh set of counters, all zero.
+    pub fn new() -> Self {
+        Counters::default()
+    }
+    /// Returns the current value of every counter.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            suspensions_created: self.inner.suspensions_created.load(Ordering::Relaxed),
+            suspensions_forced: self.inner.suspensions_forced.load(Ordering::Relaxed),
+            rotations: self.inner.rotations.load(Ordering::Relaxed),
+            rotated_elements: self.inner.rotated_elements.load(Ordering::Relaxed),
+            largest_rotation: self.inner.largest_rotation.load(Ordering::Relaxed),
+        }
+    }
+    /// Records that a suspension was created.
+    pub fn record_created(&self) {
+        self.inner.suspensions_created.fetch_add(1, Ordering::Relaxed);
+    }
+    /// Records that a suspension was evaluated.
+    pub fn record_forced(&self) {
+        self.inner.suspensions_forced.fetch_add(1, Ordering::Relaxed);
+    }
+    /// Records that a rotation moving `size` elements was run.
+    pub fn record_rotation(&self, size: usize) {
+        self.inner.rotations.fetch_add(1, Ordering::Relaxed);
+        self.inner.rotated_elements.fetch_add(size, Ordering::Relaxed);
+        self.inner.largest_rotation.fetch_max(size, Ordering::Relaxed);
+    }
+}
+
+
+#[test]
+fn susp_not_evaluated_until_forced() {
+    let susp = Susp::new(|| 2 + 2);
+
+    assert!(!susp.is_forced());
+    assert!(*susp.force() == 4);
+    assert!(susp.is_forced());
+}
+
+#[test]
+fn susp_evaluated_once() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let runs_in_thunk = runs.clone();
+    let susp = Susp::new(move || {
+        runs_in_thunk.fetch_add(1, Ordering::SeqCst);
+        "value"
+    });
+    let shared = susp.clone();
+
+    assert!(*susp.force() == "value");
+    assert!(*shared.force() == "value");
+    assert!(runs.load(Ordering::SeqCst) == 1);
+}
+
+#[test]
+fn susp_ready_is_forced() {
+    let susp = Susp::ready(7);
+
+    assert!(susp.is_forced());
+    assert!(*susp.force() == 7);
+}
+
+#[test]
+fn susp_get_mut_needs_a_forced_unshared_value() {
+    let mut susp = Susp::new(|| 1);
+    assert!(susp.get_mut().is_none());
+    susp.force();
+    let mut shared = susp.clone();
+    assert!(shared.get_mut().is_none());
+    drop(shared);
+
+    *susp.get_mut().unwrap() = 2;
+    assert!(*susp.force() == 2);
+}
+
+#[cfg(feature = "amortization-debug")]
+#[test]
+fn counted_susp_records_creation_and_forcing() {
+    let counters = Counters::new();
+    let susp = Susp::counted(&counters, || 1);
+    let shared = susp.clone();
+
+    assert!(counters.stats().suspensions_created == 1);
+    assert!(counters.stats().suspensions_forced == 0);
+
+    susp.force();
+    shared.force();
+
+    assert!(counters.stats().suspensions_forced == 1);
+}
@@ -1,2 +1,112 @@
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "quickcheck")]
+extern crate quickcheck;
+#[cfg(feature = "proptest")]
+extern crate proptest;
+#[cfg(feature = "rand")]
+extern crate rand;
+extern crate arc_swap;
+#[cfg(all(test, feature = "rand"))]
+extern crate rand_xorshift;
+
+pub mod ptr;
+pub mod error;
+pub mod hasher;
+pub mod measure;
+pub mod memory;
+pub mod collection;
+pub mod sequence;
+pub mod prelude;
+pub mod lazy;
+
 pub mod stack;
+pub mod bounded;
 pub mod set;
+pub mod small_tree;
+pub mod stream;
+pub mod memo;
+pub mod schedule;
+pub mod sortable;
+pub mod hamt;
+pub mod intmap;
+pub mod trie;
+pub mod art;
+pub mod finger_tree;
+pub mod seq;
+pub mod ord_seq;
+pub mod interval_map;
+pub mod priority_seq;
+pub mod rope;
+pub mod suffix_array;
+pub mod skip_list;
+pub mod bloom;
+pub mod multiset;
+pub mod multimap;
+pub mod lru;
+pub mod range_map;
+pub mod segment_tree;
+pub mod sorted_list;
+pub mod zipper;
+pub mod optics;
+pub mod history;
+pub mod intern;
+pub mod snapshot;
+pub mod arena;
+pub mod cell;
+pub mod local;
+
+mod convert;
+#[cfg(feature = "persistence")]
+pub mod wal;
+#[cfg(feature = "rayon")]
+pub mod par;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+#[cfg(feature = "serde")]
+mod serde_support;
+#[cfg(feature = "quickcheck")]
+mod quickcheck_support;
+
+pub use collection::{Collection, Foldable, MapElems, Traverse};
+pub use error::{CapacityError, Error, StackError};
+pub use memory::MemoryUsage;
+pub use sequence::{Cons, DequeSequence, FifoSequence, LifoSequence, Sequence, Snoc, Uncons, Unsnoc};
+pub use set::{Map, PersistentMap, PersistentSet, Set};
+pub use sortable::Sortable;
+pub use stack::Stack;
+
+pub use arena::Arena;
+pub use art::ArtMap;
+pub use bloom::BloomFilter;
+pub use bounded::{BoundedQueue, BoundedStack};
+pub use finger_tree::FingerTree;
+pub use hamt::{HamtMap, HamtSet};
+pub use history::History;
+pub use intern::Interner;
+pub use interval_map::IntervalMap;
+pub use intmap::{IntMap, IntSet};
+pub use lru::PersistentLru;
+pub use memo::Memo;
+pub use multimap::Multimap;
+pub use multiset::Multiset;
+pub use ord_seq::{OrdSeqMap, OrdSeqSet};
+pub use priority_seq::PrioritySeq;
+pub use range_map::RangeMap;
+pub use rope::Rope;
+pub use segment_tree::SegmentTree;
+pub use seq::Seq;
+pub use set::Tree;
+pub use skip_list::SkipListMap;
+pub use small_tree::SmallTree;
+pub use snapshot::SnapshotStore;
+pub use sorted_list::SortedList;
+pub use stack::CustomStack;
+pub use suffix_array::SuffixArray;
+pub use stream::Stream;
+pub use trie::TrieMap;
+pub use zipper::{ListZipper, TreeZipper};
@@ -0,0 +1,333 @@
+// The local flavor of the structures in this crate, whose nodes are shared
+// through `std::rc::Rc`.
+//
+// Each module here re-exports the module of the same name at the root of the
+// crate, with the structures it defines instantiated at `RcPointer` in place
+// of `DefaultPointer`. The traits, errors and iterators are the same items
+// as at the root. The structures of the two flavors are distinct types;
+// `From` converts a structure from either flavor into the other by
+// rebuilding it.
+//
+// A local structure cannot be sent to another thread, but it pays nothing
+// for atomic reference counting and its lazy structures place no `Send` or
+// `Sync` bounds on their contents or on the computations they suspend. A
+// program for a single-threaded target, such as WASM, can use this flavor
+// throughout rather than paying for atomic reference counts it never needs.
+
+pub use ptr::RcPointer;
+
+pub mod stack {
+    pub use stack::*;
+    use ptr::RcPointer;
+
+    /// A `CustomStackIn` of the local flavor.
+    pub type CustomStack<T> = CustomStackIn<T, RcPointer>;
+    /// A `StackBuilderIn` of the local flavor.
+    pub type StackBuilder<T> = StackBuilderIn<T, RcPointer>;
+}
+
+pub mod bounded {
+    pub use bounded::*;
+    use ptr::RcPointer;
+
+    /// A `BoundedStackIn` of the local flavor.
+    pub type BoundedStack<T> = BoundedStackIn<T, RcPointer>;
+    /// A `BoundedQueueIn` of the local flavor.
+    pub type BoundedQueue<T> = BoundedQueueIn<T, RcPointer>;
+}
+
+pub mod set {
+    pub use set::*;
+    use ptr::RcPointer;
+
+    /// A `TreeIn` of the local flavor.
+    pub type Tree<K, V> = TreeIn<K, V, RcPointer>;
+    /// A `TreeBuilderIn` of the local flavor.
+    pub type TreeBuilder<K, V> = TreeBuilderIn<K, V, RcPointer>;
+}
+
+pub mod small_tree {
+    pub use small_tree::*;
+    use ptr::RcPointer;
+
+    /// A `SmallTreeIn` of the local flavor.
+    pub type SmallTree<K, V> = SmallTreeIn<K, V, RcPointer>;
+}
+
+pub mod lazy {
+    pub use lazy::*;
+    use ptr::RcPointer;
+
+    /// A `SuspIn` of the local flavor.
+    pub type Susp<T> = SuspIn<T, RcPointer>;
+}
+
+pub mod stream {
+    pub use stream::*;
+    use ptr::RcPointer;
+
+    /// A `StreamIn` of the local flavor.
+    pub type Stream<T> = StreamIn<T, RcPointer>;
+    /// A `StreamCellIn` of the local flavor.
+    pub type StreamCell<T> = StreamCellIn<T, RcPointer>;
+}
+
+pub mod memo {
+    pub use memo::*;
+    use ptr::RcPointer;
+
+    /// A `MemoIn` of the local flavor.
+    pub type Memo<T, F> = MemoIn<T, F, RcPointer>;
+}
+
+pub mod schedule {
+    pub use schedule::*;
+    use ptr::RcPointer;
+
+    /// A `ScheduleIn` of the local flavor.
+    pub type Schedule<T> = ScheduleIn<T, RcPointer>;
+}
+
+pub mod sortable {
+    pub use sortable::*;
+    use ptr::RcPointer;
+
+    /// A `MergeSortIn` of the local flavor.
+    pub type MergeSort<T> = MergeSortIn<T, RcPointer>;
+    /// A `ScheduledMergeSortIn` of the local flavor.
+    pub type ScheduledMergeSort<T> = ScheduledMergeSortIn<T, RcPointer>;
+}
+
+pub mod hamt {
+    pub use hamt::*;
+    use hasher::FxBuildHasher;
+    use ptr::RcPointer;
+
+    /// A `HamtMapIn` of the local flavor.
+    pub type HamtMap<K, V, S = FxBuildHasher> = HamtMapIn<K, V, RcPointer, S>;
+    /// A `HamtSetIn` of the local flavor.
+    pub type HamtSet<T, S = FxBuildHasher> = HamtSetIn<T, RcPointer, S>;
+}
+
+pub mod intmap {
+    pub use intmap::*;
+    use ptr::RcPointer;
+
+    /// An `IntMapIn` of the local flavor.
+    pub type IntMap<V> = IntMapIn<V, RcPointer>;
+    /// An `IntSetIn` of the local flavor.
+    pub type IntSet = IntSetIn<RcPointer>;
+    /// An `IntSet`, under the name it is often known by.
+    pub type BitSet = IntSet;
+}
+
+pub mod trie {
+    pub use trie::*;
+    use ptr::RcPointer;
+
+    /// A `TrieMapIn` of the local flavor.
+    pub type TrieMap<V> = TrieMapIn<V, RcPointer>;
+}
+
+pub mod art {
+    pub use art::*;
+    use ptr::RcPointer;
+
+    /// An `ArtMapIn` of the local flavor.
+    pub type ArtMap<V> = ArtMapIn<V, RcPointer>;
+}
+
+pub mod finger_tree {
+    pub use finger_tree::*;
+    use ptr::RcPointer;
+
+    /// A `FingerTreeIn` of the local flavor.
+    pub type FingerTree<T> = FingerTreeIn<T, RcPointer>;
+}
+
+pub mod seq {
+    pub use seq::*;
+    use ptr::RcPointer;
+
+    /// A `SeqIn` of the local flavor.
+    pub type Seq<T> = SeqIn<T, RcPointer>;
+    /// A `SeqBuilderIn` of the local flavor.
+    pub type SeqBuilder<T> = SeqBuilderIn<T, RcPointer>;
+}
+
+pub mod ord_seq {
+    pub use ord_seq::*;
+    use ptr::RcPointer;
+
+    /// An `OrdSeqMapIn` of the local flavor.
+    pub type OrdSeqMap<K, V> = OrdSeqMapIn<K, V, RcPointer>;
+    /// An `OrdSeqSetIn` of the local flavor.
+    pub type OrdSeqSet<T> = OrdSeqSetIn<T, RcPointer>;
+}
+
+pub mod interval_map {
+    pub use interval_map::*;
+    use ptr::RcPointer;
+
+    /// An `IntervalMapIn` of the local flavor.
+    pub type IntervalMap<T, V> = IntervalMapIn<T, V, RcPointer>;
+}
+
+pub mod priority_seq {
+    pub use priority_seq::*;
+    use ptr::RcPointer;
+
+    /// A `PrioritySeqIn` of the local flavor.
+    pub type PrioritySeq<T> = PrioritySeqIn<T, RcPointer>;
+}
+
+pub mod rope {
+    pub use rope::*;
+    use ptr::RcPointer;
+
+    /// A `RopeIn` of the local flavor.
+    pub type Rope = RopeIn<RcPointer>;
+    /// A `RopeBuilderIn` of the local flavor.
+    pub type RopeBuilder = RopeBuilderIn<RcPointer>;
+}
+
+pub mod suffix_array {
+    pub use suffix_array::*;
+    use ptr::RcPointer;
+
+    /// A `SuffixArrayIn` of the local flavor.
+    pub type SuffixArray = SuffixArrayIn<RcPointer>;
+}
+
+pub mod skip_list {
+    pub use skip_list::*;
+    use ptr::RcPointer;
+
+    /// A `SkipListMapIn` of the local flavor.
+    pub type SkipListMap<K, V> = SkipListMapIn<K, V, RcPointer>;
+}
+
+pub mod bloom {
+    pub use bloom::*;
+    use hasher::FxBuildHasher;
+    use ptr::RcPointer;
+
+    /// A `BloomFilterIn` of the local flavor.
+    pub type BloomFilter<T, S = FxBuildHasher> = BloomFilterIn<T, RcPointer, S>;
+    /// A `BuilderIn` of the local flavor.
+    pub type Builder<S = FxBuildHasher> = BuilderIn<RcPointer, S>;
+}
+
+pub mod multiset {
+    pub use multiset::*;
+    use hasher::FxBuildHasher;
+    use ptr::RcPointer;
+
+    /// A `MultisetIn` of the local flavor.
+    pub type Multiset<T, S = FxBuildHasher> = MultisetIn<T, RcPointer, S>;
+}
+
+pub mod multimap {
+    pub use multimap::*;
+    use hasher::FxBuildHasher;
+    use ptr::RcPointer;
+
+    /// A `MultimapIn` of the local flavor.
+    pub type Multimap<K, V, S = FxBuildHasher> = MultimapIn<K, V, RcPointer, S>;
+}
+
+pub mod lru {
+    pub use lru::*;
+    use ptr::RcPointer;
+
+    /// A `PersistentLruIn` of the local flavor.
+    pub type PersistentLru<K, V> = PersistentLruIn<K, V, RcPointer>;
+}
+
+pub mod range_map {
+    pub use range_map::*;
+    use ptr::RcPointer;
+
+    /// A `RangeMapIn` of the local flavor.
+    pub type RangeMap<K, V> = RangeMapIn<K, V, RcPointer>;
+}
+
+pub mod segment_tree {
+    pub use segment_tree::*;
+    use ptr::RcPointer;
+
+    /// A `SegmentTreeIn` of the local flavor.
+    pub type SegmentTree<M> = SegmentTreeIn<M, RcPointer>;
+}
+
+pub mod sorted_list {
+    pub use sorted_list::*;
+    use ptr::RcPointer;
+
+    /// A `SortedListIn` of the local flavor.
+    pub type SortedList<T> = SortedListIn<T, RcPointer>;
+}
+
+pub mod zipper {
+    pub use zipper::*;
+    use ptr::RcPointer;
+
+    /// A `ListZipperIn` of the local flavor.
+    pub type ListZipper<T> = ListZipperIn<T, RcPointer>;
+    /// A `TreeZipperIn` of the local flavor.
+    pub type TreeZipper<K, V> = TreeZipperIn<K, V, RcPointer>;
+}
+
+pub mod history {
+    pub use history::*;
+    use ptr::RcPointer;
+
+    /// A `HistoryIn` of the local flavor.
+    pub type History<T> = HistoryIn<T, RcPointer>;
+}
+
+pub mod intern {
+    pub use intern::*;
+    use ptr::RcPointer;
+
+    /// An `InternerIn` of the local flavor.
+    pub type Interner<K, V> = InternerIn<K, V, RcPointer>;
+}
+
+pub mod snapshot {
+    pub use snapshot::*;
+    use ptr::RcPointer;
+
+    /// A `SnapshotStoreIn` of the local flavor.
+    pub type SnapshotStore<T> = SnapshotStoreIn<T, RcPointer>;
+}
+
+#[cfg(test)]
+use std::rc::Rc;
+
+#[cfg(test)]
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn shared_structures_are_send_and_sync() {
+    assert_send_sync::<::stack::CustomStack<u32>>();
+    assert_send_sync::<::set::Tree<u32, String>>();
+    assert_send_sync::<::hamt::HamtMap<String, u32>>();
+    assert_send_sync::<::intmap::IntMap<u32>>();
+    assert_send_sync::<::seq::Seq<u32>>();
+    assert_send_sync::<::rope::Rope>();
+    assert_send_sync::<::lazy::Susp<u32>>();
+    assert_send_sync::<::stream::Stream<u32>>();
+    assert_send_sync::<::sortable::ScheduledMergeSort<u32>>();
+    assert_send_sync::<::zipper::ListZipper<u32>>();
+}
+
+#[test]
+fn local_lazy_structures_take_values_which_are_not_send() {
+    let captured = Rc::new(5);
+    let suspended = lazy::Susp::new(move || *captured * 2);
+    assert!(*suspended.force() == 10);
+
+    let stream = stream::Stream::from_iter((0..3).map(Rc::new));
+    assert!(stream.iter().map(|value| *value).collect::<Vec<_>>() == vec![0, 1, 2]);
+}
@@ -0,0 +1,219 @@
+use std::hash::Hash;
+
+use collection::Collection;
+use hamt::HamtMapIn;
+use intmap::{self, IntMapIn};
+use memory::{Census, MemoryUsage};
+use ptr::{DefaultPointer, SharedPointer};
+
+/// An immutable cache which holds at most a fixed number of bindings,
+/// evicting the one least recently used to make room for a new one.
+///
+/// Every operation which changes the order of use returns a new version of
+/// the cache and leaves the old one as it was, so a cache can be
+/// snapshotted, and a run of lookups replayed against any earlier version.
+/// The bindings are kept in a `HamtMap` alongside the time each was last
+/// used, and an `IntMap` from those times back to the keys orders them by
+/// recency, so `get`, `insert` and `remove` take O(log n) time.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::lru::PersistentLru;
+///
+/// let cache = PersistentLru::new(2).insert("a", 1).0.insert("b", 2).0;
+/// let (hit, used) = cache.get(&"a");
+/// let (full, evicted) = used.insert("c", 3);
+///
+/// assert!(hit == Some(1));
+/// assert!(evicted == Some(("b", 2)));
+/// assert!(full.keys().cloned().collect::<Vec<_>>() == vec!["a", "c"]);
+/// assert!(cache.insert("c", 3).1 == Some(("a", 1)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PersistentLruIn<K, V, P: SharedPointer> {
+    entries: HamtMapIn<K, (V, u64), P>,
+    recency: IntMapIn<K, P>,
+    clock: u64,
+    capacity: u32,
+}
+
+/// A `PersistentLruIn` whose nodes are shared through `DefaultPointer`, as
+/// exported at the root of the crate.
+pub type PersistentLru<K, V> = PersistentLruIn<K, V, DefaultPointer>;
+
+impl<K: Hash + Eq + Clone, V: Clone, P: SharedPointer> PersistentLruIn<K, V, P> {
+    /// Returns an empty cache which can hold up to `capacity` bindings.
+    pub fn new(capacity: u32) -> Self {
+        PersistentLruIn {
+            entries: HamtMapIn::new(),
+            recency: IntMapIn::Empty,
+            clock: 0,
+            capacity: capacity,
+        }
+    }
+    /// Returns the greatest number of bindings this cache can hold.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+    /// Returns the number of bindings in this cache.
+    pub fn size(&self) -> u32 {
+        self.entries.size()
+    }
+    /// Tests whether this cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    /// Tests whether `key` is bound in this cache, without using it.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+    /// Returns the value bound to `key`, without using it, or `None` if it
+    /// is not bound.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.entries.get(key).map(|entry| &entry.0)
+    }
+    /// Returns the value bound to `key`, and a copy of this cache in which
+    /// `key` is the most recently used. If `key` is not bound, this returns
+    /// `None` and an unchanged copy.
+    pub fn get(&self, key: &K) -> (Option<V>, Self) {
+        match self.entries.get(key) {
+            None => (None, self.clone()),
+            Some(&(ref value, used)) => {
+                let recency = self.recency.remove(used);
+                (Some(value.clone()), self.used(key.clone(), value.clone(), recency))
+            }
+        }
+    }
+    /// Returns a copy of this cache with `key` bound to `value` as the most
+    /// recently used binding, and the binding evicted to make room for it.
+    ///
+    /// A binding is only evicted when `key` is not already bound and the
+    /// cache is full, and is then the least recently used one. A cache with
+    /// no capacity evicts the new binding itself.
+    pub fn insert(&self, key: K, value: V) -> (Self, Option<(K, V)>) {
+        if self.capacity == 0 {
+            return (self.clone(), Some((key, value)));
+        }
+        if let Some(&(_, used)) = self.entries.get(&key) {
+            let recency = self.recency.remove(used);
+            return (self.used(key, value, recency), None);
+        }
+        if self.size() < self.capacity {
+            return (self.used(key, value, self.recency.clone()), None);
+        }
+        let (used, oldest) = self.recency.iter().next().expect("a full cache has bindings");
+        let evicted = (oldest.clone(), self.entries.get(oldest).unwrap().0.clone());
+        let rest = PersistentLruIn { entries: self.entries.remove(oldest), ..self.clone() };
+        (rest.used(key, value, self.recency.remove(used)), Some(evicted))
+    }
+    /// Returns a copy of this cache without any binding for `key`.
+    pub fn remove(&self, key: &K) -> Self {
+        match self.entries.get(key) {
+            None => self.clone(),
+            Some(&(_, used)) => {
+                PersistentLruIn {
+                    entries: self.entries.remove(key),
+                    recency: self.recency.remove(used),
+                    ..self.clone()
+                }
+            }
+        }
+    }
+    /// Returns an iterator over the keys of this cache, from the least to the
+    /// most recently used.
+    pub fn keys(&self) -> Keys<'_, K, P> {
+        Keys { iter: self.recency.iter() }
+    }
+    /// Returns a copy of this cache with `key` bound to `value` as its most
+    /// recently used binding, given its recency order without `key`.
+    fn used(&self, key: K, value: V, recency: IntMapIn<K, P>) -> Self {
+        PersistentLruIn {
+            entries: self.entries.insert(key.clone(), (value, self.clock)),
+            recency: recency.insert(self.clock, key),
+            clock: self.clock + 1,
+            capacity: self.capacity,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, P: SharedPointer> Collection for PersistentLruIn<K, V, P> {
+    type Iter<'a> = Keys<'a, K, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Keys<'a, K, P> {
+        self.keys()
+    }
+}
+
+impl<K, V, P: SharedPointer> MemoryUsage for PersistentLruIn<K, V, P> {
+    fn census(&self, census: &mut Census) {
+        self.entries.census(census);
+        self.recency.census(census);
+    }
+}
+
+/// An iterator over the keys of a `PersistentLru`, from the least to the most
+/// recently used.
+pub struct Keys<'a, K: 'a, P: SharedPointer + 'a> {
+    iter: intmap::Iter<'a, K, P>,
+}
+
+impl<'a, K, P: SharedPointer> Iterator for Keys<'a, K, P> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.iter.next().map(|(_, key)| key)
+    }
+}
+
+#[cfg(test)]
+fn keys_of(cache: &PersistentLru<u32, String>) -> Vec<u32> {
+    cache.keys().cloned().collect()
+}
+
+#[test]
+fn insert_evicts_the_least_recently_used_binding() {
+    let mut cache = PersistentLru::new(3);
+    let mut evictions = Vec::new();
+    for i in 0..10 {
+        let (next, evicted) = cache.insert(i, i.to_string());
+        evictions.extend(evicted);
+        cache = next;
+        if i % 2 == 1 {
+            cache = cache.get(&(i - 1)).1;
+        }
+    }
+
+    assert!(keys_of(&cache) == vec![6, 9, 8]);
+    let evicted_keys = evictions.iter().map(|entry| entry.0).collect::<Vec<_>>();
+    assert!(evicted_keys == vec![1, 0, 3, 2, 5, 4, 7]);
+    assert!(evictions.iter().all(|&(key, ref value)| *value == key.to_string()));
+    assert!(cache.size() == 3 && cache.peek(&8) == Some(&"8".to_string()));
+}
+
+#[test]
+fn old_versions_are_unchanged_by_lookups_and_evictions() {
+    let cache = (0..4).fold(PersistentLru::new(4), |cache, i| cache.insert(i, i.to_string()).0);
+    let (hit, used) = cache.get(&0);
+    let (miss, unchanged) = cache.get(&10);
+    let (replaced, evicted) = used.insert(1, "one".to_string());
+    let (grown, _) = replaced.insert(4, "4".to_string());
+
+    assert!(hit == Some("0".to_string()) && miss.is_none() && evicted.is_none());
+    assert!(keys_of(&cache) == vec![0, 1, 2, 3] && keys_of(&unchanged) == keys_of(&cache));
+    assert!(keys_of(&used) == vec![1, 2, 3, 0]);
+    assert!(keys_of(&grown) == vec![3, 0, 1, 4] && grown.peek(&1) == Some(&"one".to_string()));
+    assert!(keys_of(&grown.remove(&0)) == vec![3, 1, 4] && !grown.remove(&0).contains_key(&0));
+    assert!(cache.peek(&1) == Some(&"1".to_string()));
+}
+
+#[test]
+fn cache_with_no_capacity_evicts_every_binding() {
+    let cache: PersistentLru<u32, String> = PersistentLru::new(0);
+    let (after, evicted) = cache.insert(1, "1".to_string());
+
+    assert!(after.is_empty() && evicted == Some((1, "1".to_string())));
+}
@@ -0,0 +1,121 @@
+use std::ops::Add;
+
+/// A trait representing a monoid: an associative operation with an identity.
+/// Finger trees annotate their subtrees with monoidal measures.
+pub trait Monoid: Clone {
+    /// Returns the identity of `combine`.
+    fn empty() -> Self;
+    /// Combines this value with `other`. This must be associative, and
+    /// combining with `empty()` on either side must leave a value unchanged.
+    fn combine(&self, other: &Self) -> Self;
+}
+
+/// A trait representing a value which can be measured, such as an element of
+/// a finger tree.
+pub trait Measured {
+    /// The type of measures, which combine as a monoid.
+    type Measure: Monoid;
+    /// Returns the measure of this value.
+    fn measure(&self) -> Self::Measure;
+}
+
+/// A number of elements. Measuring each element as `Count(1)` lets a finger
+/// tree be indexed by position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Count(pub u32);
+
+impl Monoid for Count {
+    fn empty() -> Self {
+        Count(0)
+    }
+    fn combine(&self, other: &Self) -> Self {
+        Count(self.0 + other.0)
+    }
+}
+
+/// The greatest of some values, or `None` if there are none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Max<T>(pub Option<T>);
+
+impl<T: Ord + Clone> Monoid for Max<T> {
+    fn empty() -> Self {
+        Max(None)
+    }
+    fn combine(&self, other: &Self) -> Self {
+        if other.0 > self.0 {
+            other.clone()
+        } else {
+            self.clone()
+        }
+    }
+}
+
+/// The least of some values, or `None` if there are none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Min<T>(pub Option<T>);
+
+impl<T: Ord + Clone> Monoid for Min<T> {
+    fn empty() -> Self {
+        Min(None)
+    }
+    fn combine(&self, other: &Self) -> Self {
+        match (&self.0, &other.0) {
+            (&Some(ref ours), &Some(ref theirs)) if theirs < ours => other.clone(),
+            (&None, _) => other.clone(),
+            _ => self.clone(),
+        }
+    }
+}
+
+/// The sum of some values, where the default value of `T` is zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sum<T>(pub T);
+
+impl<T: Add<Output = T> + Default + Clone> Monoid for Sum<T> {
+    fn empty() -> Self {
+        Sum(T::default())
+    }
+    fn combine(&self, other: &Self) -> Self {
+        Sum(self.0.clone() + other.0.clone())
+    }
+}
+
+/// Two measures combined side by side, so that one finger tree can be
+/// searched by either.
+impl<A: Monoid, B: Monoid> Monoid for (A, B) {
+    fn empty() -> Self {
+        (A::empty(), B::empty())
+    }
+    fn combine(&self, other: &Self) -> Self {
+        (self.0.combine(&other.0), self.1.combine(&other.1))
+    }
+}
+
+#[cfg(test)]
+fn combine_all<M: Monoid>(values: Vec<M>) -> M {
+    values.iter().fold(M::empty(), |total, value| total.combine(value))
+}
+
+#[test]
+fn counts_and_sums_add_up() {
+    assert!(combine_all(vec![Count(1), Count(2), Count(3)]) == Count(6));
+    assert!(combine_all(vec![Sum(1.5), Sum(2.5)]) == Sum(4.0));
+    assert!(combine_all::<Sum<i64>>(vec![]) == Sum(0));
+}
+
+#[test]
+fn max_and_min_ignore_empty_values() {
+    let values = vec![Some(3), None, Some(7), Some(1), None];
+
+    assert!(combine_all(values.iter().cloned().map(Max).collect()) == Max(Some(7)));
+    assert!(combine_all(values.iter().cloned().map(Min).collect()) == Min(Some(1)));
+    assert!(combine_all::<Max<u32>>(vec![]) == Max(None));
+    assert!(Min(None).combine(&Min(Some(2))) == Min(Some(2)));
+}
+
+#[test]
+fn pairs_combine_componentwise() {
+    let pairs = vec![(Count(1), Max(Some(4))), (Count(1), Max(Some(9))), (Count(1), Max(None))];
+
+    assert!(combine_all(pairs) == (Count(3), Max(Some(9))));
+}
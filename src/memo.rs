@@ -0,0 +1,188 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use lazy::SuspIn;
+use ptr::{DefaultPointer, Shared, SharedPointer};
+
+/// A memoization table for a function from `u64`, whose results are kept in
+/// a lazily built tree so that each is computed at most once.
+///
+/// The function is passed a `Recur` view of the table along with its
+/// argument, so a recursive function can look up its results for other
+/// arguments through `get` and share them rather than computing them again. Clones of a table
+/// share its tree, so work forced through any one of them is seen by all.
+///
+/// The tree is infinite, but each node and its children are only built
+/// when a lookup first passes through them: node `n` has children `2n + 1`
+/// and `2n + 2`, as in a Braun tree, so finding the result for `n` takes
+/// O(log n) time once it has been computed.
+///
+/// A function must not ask for its own result for the argument it is
+/// computing, directly or through other arguments: such a lookup never
+/// finishes.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::memo::{Memo, Recur};
+///
+/// let fib = Memo::new(|fib: &Recur<u64, _>, n| {
+///     if n < 2 { n } else { *fib.get(n - 1) + *fib.get(n - 2) }
+/// });
+///
+/// assert!(*fib.get(90) == 2880067194370816120);
+/// assert!(fib.is_computed(45) && !fib.is_computed(91));
+/// ```
+pub struct MemoIn<T, F, P: SharedPointer> {
+    function: Shared<F, P>,
+    root: Shared<Node<T, P>, P>,
+}
+
+/// A `MemoIn` whose nodes are shared through `DefaultPointer`, as exported at
+/// the root of the crate.
+pub type Memo<T, F> = MemoIn<T, F, DefaultPointer>;
+
+/// A node of the tree, holding the result for its argument once it has been
+/// computed and suspending the construction of its children.
+struct Node<T, P: SharedPointer> {
+    value: OnceLock<T>,
+    children: SuspIn<Children<T, P>, P>,
+}
+
+type Children<T, P> = (Shared<Node<T, P>, P>, Shared<Node<T, P>, P>);
+
+impl<T: 'static, P: SharedPointer> Node<T, P> {
+    fn new() -> Self {
+        Node {
+            value: OnceLock::new(),
+            // SAFETY: the thunk captures nothing, so it is `SendFor<P>`.
+            children: unsafe {
+                SuspIn::new_unchecked(|| (Shared::new(Node::new()), Shared::new(Node::new())))
+            },
+        }
+    }
+}
+
+impl<T: 'static, F, P: SharedPointer> MemoIn<T, F, P>
+    where F: Fn(&Recur<T, P>, u64) -> T
+{
+    /// Returns a table for `function`, in which nothing has been computed.
+    pub fn new(function: F) -> Self {
+        MemoIn {
+            function: Shared::new(function),
+            root: Shared::new(Node::new()),
+        }
+    }
+    /// Returns the result of the function for `n`, computing it if no clone
+    /// of this table has yet done so.
+    pub fn get(&self, n: u64) -> &T {
+        Recur { root: &self.root, function: &*self.function }.get(n)
+    }
+    /// Tests whether the result for `n` has already been computed.
+    ///
+    /// This does not build any part of the tree which has not been built.
+    pub fn is_computed(&self, n: u64) -> bool {
+        let mut node = &self.root;
+        for left in path(n) {
+            if !node.children.is_forced() {
+                return false;
+            }
+            let children = node.children.force();
+            node = if left { &children.0 } else { &children.1 };
+        }
+        node.value.get().is_some()
+    }
+}
+
+/// The view of a `Memo` passed to its function, through which it looks up
+/// its results for other arguments.
+pub struct Recur<'a, T: 'a, P: SharedPointer + 'a> {
+    root: &'a Node<T, P>,
+    function: &'a (dyn Fn(&Recur<T, P>, u64) -> T + 'a),
+}
+
+impl<'a, T: 'static, P: SharedPointer> Recur<'a, T, P> {
+    /// Returns the result of the function for `n`, computing it if it has
+    /// not yet been computed.
+    pub fn get(&self, n: u64) -> &'a T {
+        let mut node = self.root;
+        for left in path(n) {
+            let children = node.children.force();
+            node = if left { &children.0 } else { &children.1 };
+        }
+        node.value.get_or_init(|| (self.function)(self, n))
+    }
+}
+
+/// Returns the turns from the root to node `n`, with `true` for left.
+fn path(n: u64) -> Vec<bool> {
+    let mut turns = Vec::new();
+    let mut n = n;
+    while n > 0 {
+        let left = n % 2 == 1;
+        turns.push(left);
+        n = if left { (n - 1) / 2 } else { (n - 2) / 2 };
+    }
+    turns.reverse();
+    turns
+}
+
+impl<T, F, P: SharedPointer> Clone for MemoIn<T, F, P> {
+    fn clone(&self) -> Self {
+        MemoIn {
+            function: self.function.clone(),
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<T, F, P: SharedPointer> fmt::Debug for MemoIn<T, F, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Memo(<function>)")
+    }
+}
+
+#[cfg(test)]
+use std::sync::Arc;
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn results_are_computed_once_and_shared_between_clones() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let square = Memo::new(move |_: &Recur<u64, _>, n| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        n.wrapping_mul(n)
+    });
+    let copy = square.clone();
+
+    assert!(!square.is_computed(1000));
+    assert!(*square.get(1000) == 1_000_000);
+    assert!(copy.is_computed(1000) && !copy.is_computed(999));
+    assert!(*copy.get(1000) == 1_000_000);
+    assert!(*copy.get(0) == 0 && *square.get(u64::max_value()) == 1);
+    assert!(calls.load(Ordering::SeqCst) == 3);
+}
+
+#[test]
+fn recursive_functions_reuse_results_for_smaller_arguments() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    // The number of paths through a grid of `n / 100` by `n % 100` cells.
+    let paths = Memo::new(move |paths: &Recur<u64, _>, n| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        let (rows, columns) = (n / 100, n % 100);
+        if rows == 0 || columns == 0 {
+            1
+        } else {
+            paths.get(n - 100).wrapping_add(*paths.get(n - 1))
+        }
+    });
+
+    assert!(*paths.get(202) == 6);
+    assert!(*paths.get(1010) == 184756);
+    // Every cell up to 10 by 10 is computed once, except the corner at 0 by
+    // 0, which no other cell needs.
+    assert!(calls.load(Ordering::SeqCst) == 11 * 11 - 1);
+}
@@ -0,0 +1,206 @@
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use std::mem;
+
+use hasher::FxBuildHasher;
+
+/// Implemented by the structures which can account for the nodes they are
+/// made of, so that `MemoryReport` can measure them.
+pub trait MemoryUsage {
+    /// Passes each node reachable from this structure to `census`, visiting
+    /// the children of a node only when `Census::node` returns `true` for
+    /// it.
+    fn census(&self, census: &mut Census);
+}
+
+/// The nodes seen so far while measuring a structure or a set of versions of
+/// one, counted by address.
+#[derive(Debug, Default)]
+pub struct Census {
+    /// For each node seen, the number of times it has been reached.
+    nodes: HashMap<usize, usize, FxBuildHasher>,
+    /// The nodes seen in the version being measured.
+    version: HashSet<usize, FxBuildHasher>,
+    reachable: usize,
+    /// The estimated bytes of the versions themselves and of the distinct
+    /// nodes seen so far.
+    bytes: usize,
+}
+
+impl Census {
+    /// Records that `node`, which sits behind a reference counted pointer
+    /// and owns `heap_bytes` bytes of other heap memory, is reachable from
+    /// the version being measured.
+    ///
+    /// Returns whether this is the first time the node has been reached
+    /// from this version, in which case its children should be visited too.
+    pub fn node<T>(&mut self, node: &T, heap_bytes: usize) -> bool {
+        let address = node as *const T as usize;
+        // Both pointer flavors keep a strong and a weak count beside the
+        // value.
+        let bytes = mem::size_of::<T>() + 2 * mem::size_of::<usize>() + heap_bytes;
+        let reached = self.nodes.entry(address).or_insert(0);
+        if *reached == 0 {
+            self.bytes += bytes;
+        }
+        *reached += 1;
+        let first = self.version.insert(address);
+        if first {
+            self.reachable += 1;
+        }
+        first
+    }
+    /// Records `bytes` bytes of heap memory owned directly by the version
+    /// being measured, rather than by one of its nodes.
+    pub fn heap(&mut self, bytes: usize) {
+        self.bytes += bytes;
+    }
+    fn measure<S: MemoryUsage + ?Sized>(&mut self, version: &S) {
+        self.version.clear();
+        self.bytes += mem::size_of_val(version);
+        version.census(self);
+    }
+}
+
+/// Statistics on the memory taken up by a structure, or by several versions
+/// of one which may share nodes.
+///
+/// The byte footprint is an estimate: it counts the nodes, the reference
+/// counts beside them and the arrays they own, but not memory owned by the
+/// elements themselves, such as the contents of a `String`, nor any
+/// overhead of the allocator.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::memory::MemoryReport;
+/// use functional_datastructures::set::{Set, Tree};
+///
+/// let old: Tree<u32, ()> = (0..100).fold(Tree::empty(), |tree, i| tree.insert((i * 37) % 100));
+/// let new = old.insert(100);
+///
+/// let report = MemoryReport::of_versions(vec![&old, &new]);
+/// assert!(report.versions == 2);
+/// // The two versions share every node but those on the path to 100.
+/// assert!(report.unique_nodes < report.reachable_nodes);
+/// assert!(report.shared_percentage() > 80.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryReport {
+    /// The number of versions measured.
+    pub versions: usize,
+    /// The number of distinct nodes reachable from any of the versions.
+    pub unique_nodes: usize,
+    /// The number of nodes reachable from each version, summed over the
+    /// versions: the number of nodes they would take up between them if
+    /// they shared none.
+    pub reachable_nodes: usize,
+    /// The number of distinct nodes which are reached more than once,
+    /// whether from several versions or from several places in one.
+    pub shared_nodes: usize,
+    /// The estimated number of bytes taken up by the versions between them,
+    /// including the structures themselves as well as their nodes.
+    pub estimated_bytes: usize,
+}
+
+impl MemoryReport {
+    /// Measures a single structure.
+    pub fn of<S: MemoryUsage + ?Sized>(structure: &S) -> Self {
+        MemoryReport::of_versions(iter::once(structure))
+    }
+    /// Measures several versions of a structure together, counting each
+    /// node they share once.
+    pub fn of_versions<'a, S, I>(versions: I) -> Self
+        where S: MemoryUsage + ?Sized + 'a,
+              I: IntoIterator<Item = &'a S>
+    {
+        let mut census = Census::default();
+        let mut count = 0;
+        for version in versions {
+            census.measure(version);
+            count += 1;
+        }
+        MemoryReport {
+            versions: count,
+            unique_nodes: census.nodes.len(),
+            reachable_nodes: census.reachable,
+            shared_nodes: census.nodes.values().filter(|&&reached| reached > 1).count(),
+            estimated_bytes: census.bytes,
+        }
+    }
+    /// Returns the estimated number of bytes each of several versions of a
+    /// structure adds to the versions before it: the bytes of the version
+    /// itself and of the nodes it does not share with any earlier one.
+    ///
+    /// The costs add up to the `estimated_bytes` of `of_versions`.
+    pub fn incremental_bytes<'a, S, I>(versions: I) -> Vec<usize>
+        where S: MemoryUsage + ?Sized + 'a,
+              I: IntoIterator<Item = &'a S>
+    {
+        let mut census = Census::default();
+        versions.into_iter()
+                .map(|version| {
+                    let before = census.bytes;
+                    census.measure(version);
+                    census.bytes - before
+                })
+                .collect()
+    }
+    /// Returns the percentage of the distinct nodes which are shared, or
+    /// zero if there are no nodes.
+    pub fn shared_percentage(&self) -> f64 {
+        if self.unique_nodes == 0 {
+            0.0
+        } else {
+            100.0 * self.shared_nodes as f64 / self.unique_nodes as f64
+        }
+    }
+}
+
+#[cfg(test)]
+use seq::Seq;
+#[cfg(test)]
+use set::{Set, Tree};
+#[cfg(test)]
+use stack::{CustomStack, Stack};
+
+#[test]
+fn unshared_structures_have_no_shared_nodes() {
+    let stack = (0..10).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+    let report = MemoryReport::of(&stack);
+
+    // The first cell is held inline, so only the nine behind it and the
+    // empty stack at the end are nodes.
+    assert!(report.unique_nodes == 10 && report.reachable_nodes == 10);
+    assert!(report.shared_nodes == 0 && report.shared_percentage() == 0.0);
+    assert!(report.estimated_bytes > 10 * std::mem::size_of::<CustomStack<i32>>());
+}
+
+#[test]
+fn versions_count_their_shared_nodes_once() {
+    let base = (0..10).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+    let versions: Vec<_> = (0..5).map(|i| base.cons(i)).collect();
+    let report = MemoryReport::of_versions(&versions);
+
+    assert!(report.versions == 5);
+    assert!(report.reachable_nodes == 5 * 11);
+    assert!(report.unique_nodes == 5 + 10);
+    assert!(report.shared_nodes == 10);
+
+    let tree: Tree<u32, ()> = Tree::empty().insert(2).insert(1).insert(3);
+    assert!(MemoryReport::of(&tree).unique_nodes == 4);
+    let seq = (0..1000).fold(Seq::empty(), |seq, i| seq.push_back(i));
+    assert!(MemoryReport::of(&seq).unique_nodes > 32);
+}
+
+#[test]
+fn incremental_bytes_count_unshared_nodes() {
+    let base = (0..100).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+    let versions = vec![base.clone(), base.cons(100), base.clone()];
+    let costs = MemoryReport::incremental_bytes(&versions);
+
+    let cell = std::mem::size_of::<CustomStack<i32>>();
+    assert!(costs[0] > 100 * cell);
+    assert!(costs[1] < 3 * cell + 4 * std::mem::size_of::<usize>() && costs[2] == cell);
+    assert!(costs.iter().sum::<usize>() == MemoryReport::of_versions(&versions).estimated_bytes);
+}
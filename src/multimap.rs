@@ -0,0 +1,233 @@
+use std::hash::{BuildHasher, Hash};
+
+use collection::Collection;
+use hasher::FxBuildHasher;
+use memory::{Census, MemoryUsage};
+use ptr::{DefaultPointer, SharedPointer};
+
+use hamt::{self, HamtMapIn, HamtSetIn};
+use set::Set;
+
+/// An immutable multimap, which binds each key to a set of values. It is
+/// implemented as a `HamtMap` from each key to a `HamtSet` of its values,
+/// which is never empty: a key whose last value is removed is removed too.
+///
+/// Adding or removing a binding, or finding a key's values, takes O(log32 n)
+/// time.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::multimap::Multimap;
+///
+/// let authors = Multimap::new()
+///                   .insert("Okasaki", "Purely Functional Data Structures")
+///                   .insert("Okasaki", "Fast Mergeable Integer Maps")
+///                   .insert("Bagwell", "Ideal Hash Trees");
+///
+/// assert!(authors.get_all(&"Okasaki").size() == 2);
+/// assert!(authors.contains(&"Bagwell", &"Ideal Hash Trees"));
+/// assert!(authors.size() == 3);
+///
+/// let removed = authors.remove(&"Bagwell", &"Ideal Hash Trees");
+/// assert!(!removed.contains_key(&"Bagwell"));
+/// assert!(removed.get_all(&"Bagwell").is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultimapIn<K, V, P: SharedPointer, S = FxBuildHasher> {
+    buckets: HamtMapIn<K, HamtSetIn<V, P, S>, P, S>,
+}
+
+/// A `MultimapIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type Multimap<K, V, S = FxBuildHasher> = MultimapIn<K, V, DefaultPointer, S>;
+
+impl<K: Hash + Eq + Clone, V: Hash + Eq + Clone, P: SharedPointer> MultimapIn<K, V, P> {
+    /// Returns an empty multimap which hashes its keys and values with the
+    /// default `FxBuildHasher`.
+    pub fn new() -> Self {
+        MultimapIn::with_hasher(FxBuildHasher::default())
+    }
+}
+
+impl<K, V, S, P: SharedPointer> MultimapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Hash + Eq + Clone,
+          S: BuildHasher + Clone
+{
+    /// Returns an empty multimap which hashes its keys and values with
+    /// `hasher`.
+    pub fn with_hasher(hasher: S) -> Self {
+        MultimapIn { buckets: HamtMapIn::with_hasher(hasher) }
+    }
+    /// Returns a copy of this multimap with `value` added to the values bound
+    /// to `key`.
+    pub fn insert(&self, key: K, value: V) -> Self
+        where S: Default
+    {
+        let values = self.get_all(&key).insert(value);
+        MultimapIn { buckets: self.buckets.insert(key, values) }
+    }
+    /// Returns a copy of this multimap without the binding of `key` to
+    /// `value`. If that was the last value bound to `key`, `key` is removed
+    /// too.
+    pub fn remove(&self, key: &K, value: &V) -> Self {
+        match self.buckets.get(key) {
+            Some(values) if values.contains(value) => {
+                let values = values.remove(value);
+                if values.is_empty() {
+                    self.remove_all(key)
+                } else {
+                    MultimapIn { buckets: self.buckets.insert(key.clone(), values) }
+                }
+            }
+            _ => self.clone(),
+        }
+    }
+    /// Returns a copy of this multimap without any bindings for `key`.
+    pub fn remove_all(&self, key: &K) -> Self {
+        MultimapIn { buckets: self.buckets.remove(key) }
+    }
+    /// Returns the set of values bound to `key`, which is empty if it is not
+    /// bound.
+    pub fn get_all(&self, key: &K) -> HamtSetIn<V, P, S> {
+        match self.buckets.get(key) {
+            Some(values) => values.clone(),
+            None => HamtSetIn::with_hasher(self.buckets.hasher().clone()),
+        }
+    }
+    /// Tests whether `key` is bound to `value` in this multimap.
+    pub fn contains(&self, key: &K, value: &V) -> bool {
+        self.buckets.get(key).is_some_and(|values| values.contains(value))
+    }
+    /// Tests whether `key` is bound to any values in this multimap.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.buckets.contains_key(key)
+    }
+    /// Tests whether this multimap is empty.
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+    /// Returns the number of bindings of keys to values in this multimap.
+    /// This takes O(k) time, where k is the number of keys.
+    pub fn size(&self) -> u32 {
+        self.buckets.iter().map(|(_, values)| values.size()).sum()
+    }
+    /// Returns an iterator over the keys of this multimap, in no particular
+    /// order.
+    pub fn keys(&self) -> hamt::Keys<'_, K, HamtSetIn<V, P, S>, P> {
+        self.buckets.keys()
+    }
+    /// Returns an iterator over every binding of a key to a value in this
+    /// multimap, in no particular order, though all the bindings of each key
+    /// come together.
+    pub fn iter(&self) -> Iter<'_, K, V, P, S> {
+        Iter {
+            buckets: self.buckets.iter(),
+            current: None,
+        }
+    }
+}
+
+impl<K, V, S, P: SharedPointer> Collection for MultimapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Hash + Eq + Clone,
+          S: BuildHasher + Clone
+{
+    type Iter<'a> = Iter<'a, K, V, P, S> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, K, V, P, S> {
+        self.iter()
+    }
+}
+
+impl<K, V, S, P: SharedPointer> MemoryUsage for MultimapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Hash + Eq + Clone,
+          S: BuildHasher + Clone
+{
+    fn census(&self, census: &mut Census) {
+        self.buckets.census(census);
+        for (_, bucket) in self.buckets.iter() {
+            bucket.census(census);
+        }
+    }
+}
+
+impl<K, V, S, P: SharedPointer> Default for MultimapIn<K, V, P, S>
+    where K: Hash + Eq + Clone,
+          V: Hash + Eq + Clone,
+          S: BuildHasher + Clone + Default
+{
+    fn default() -> Self {
+        MultimapIn::with_hasher(S::default())
+    }
+}
+
+/// An iterator over the bindings of a `Multimap`.
+pub struct Iter<'a, K: 'a, V: 'a, P: SharedPointer, S: 'a> {
+    buckets: hamt::Iter<'a, K, HamtSetIn<V, P, S>, P>,
+    /// The key whose values are being visited, and those still to visit.
+    current: Option<(&'a K, hamt::Keys<'a, V, (), P>)>,
+}
+
+impl<'a, K, V, P: SharedPointer, S> Iterator for Iter<'a, K, V, P, S>
+    where V: Hash + Eq + Clone,
+          S: BuildHasher + Clone
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((key, ref mut values)) = self.current {
+                if let Some(value) = values.next() {
+                    return Some((key, value));
+                }
+            }
+            let (key, values) = self.buckets.next()?;
+            self.current = Some((key, values.iter()));
+        }
+    }
+}
+
+#[test]
+fn empty_multimap_has_nothing() {
+    let multimap: Multimap<u32, u32> = Multimap::new();
+
+    assert!(multimap.is_empty());
+    assert!(multimap.get_all(&0).is_empty());
+    assert!(multimap.remove(&0, &0).is_empty());
+    assert!(multimap.iter().next().is_none());
+}
+
+#[test]
+fn values_are_grouped_by_key() {
+    let multimap = (0..100).fold(Multimap::new(), |multimap, i| multimap.insert(i % 10, i));
+
+    assert!(multimap.size() == 100);
+    assert!(multimap.keys().count() == 10);
+    assert!(multimap.insert(3, 13).size() == 100);
+    let mut threes: Vec<u32> = multimap.get_all(&3).iter().cloned().collect();
+    threes.sort();
+    assert!(threes == (0..10).map(|i| i * 10 + 3).collect::<Vec<_>>());
+
+    let mut bindings: Vec<(u32, u32)> = multimap.iter().map(|(k, v)| (*k, *v)).collect();
+    bindings.sort_by_key(|&(_, v)| v);
+    assert!(bindings == (0..100).map(|i| (i % 10, i)).collect::<Vec<_>>());
+}
+
+#[test]
+fn removing_the_last_value_removes_the_key() {
+    let multimap = Multimap::new().insert("a", 1).insert("a", 2).insert("b", 3);
+
+    let fewer = multimap.remove(&"a", &1);
+    assert!(fewer.contains_key(&"a"));
+    assert!(!fewer.contains(&"a", &1));
+    assert!(!fewer.remove(&"a", &2).contains_key(&"a"));
+    assert!(multimap.remove(&"a", &3).size() == 3);
+    assert!(multimap.remove_all(&"a").size() == 1);
+    assert!(multimap.contains(&"a", &1));
+}
@@ -0,0 +1,206 @@
+use std::cmp;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+
+use collection::Collection;
+use hasher::FxBuildHasher;
+use memory::{Census, MemoryUsage};
+use ptr::{DefaultPointer, SharedPointer};
+
+use hamt::{self, HamtMapIn};
+
+/// An immutable multiset, or bag: a set in which a value can occur more than
+/// once. It is implemented as a `HamtMap` from each value to the number of
+/// times it occurs, which is never zero.
+///
+/// Adding or removing a value, or counting its occurrences, takes the same
+/// O(log32 n) time as a lookup in the map, where n is the number of distinct
+/// values.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::multiset::Multiset;
+///
+/// let words: Multiset<&str> = "the cat sat on the mat".split(' ').collect();
+///
+/// assert!(words.count(&"the") == 2);
+/// assert!(words.count(&"dog") == 0);
+/// assert!(words.size() == 6);
+/// assert!(words.remove_one(&"the").count(&"the") == 1);
+/// assert!(words.remove_all(&"the").count(&"the") == 0);
+/// assert!(words.sum(&words).count(&"the") == 4);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultisetIn<T, P: SharedPointer, S = FxBuildHasher> {
+    counts: HamtMapIn<T, u32, P, S>,
+}
+
+/// A `MultisetIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type Multiset<T, S = FxBuildHasher> = MultisetIn<T, DefaultPointer, S>;
+
+impl<T: Hash + Eq + Clone, P: SharedPointer> MultisetIn<T, P> {
+    /// Returns an empty multiset which hashes its values with the default
+    /// `FxBuildHasher`.
+    pub fn new() -> Self {
+        MultisetIn::with_hasher(FxBuildHasher::default())
+    }
+}
+
+impl<T: Hash + Eq + Clone, S: BuildHasher + Clone, P: SharedPointer> MultisetIn<T, P, S> {
+    /// Returns an empty multiset which hashes its values with `hasher`.
+    pub fn with_hasher(hasher: S) -> Self {
+        MultisetIn { counts: HamtMapIn::with_hasher(hasher) }
+    }
+    /// Returns a copy of this multiset with one more occurrence of `value`.
+    pub fn insert(&self, value: T) -> Self {
+        let count = self.count(&value);
+        MultisetIn { counts: self.counts.insert(value, count + 1) }
+    }
+    /// Returns a copy of this multiset with one fewer occurrence of `value`,
+    /// or an unchanged copy if `value` does not occur in it.
+    pub fn remove_one(&self, value: &T) -> Self {
+        match self.count(value) {
+            0 => self.clone(),
+            1 => self.remove_all(value),
+            count => MultisetIn { counts: self.counts.insert(value.clone(), count - 1) },
+        }
+    }
+    /// Returns a copy of this multiset without any occurrences of `value`.
+    pub fn remove_all(&self, value: &T) -> Self {
+        MultisetIn { counts: self.counts.remove(value) }
+    }
+    /// Returns the number of times `value` occurs in this multiset.
+    pub fn count(&self, value: &T) -> u32 {
+        self.counts.get(value).cloned().unwrap_or(0)
+    }
+    /// Tests whether `value` occurs in this multiset at all.
+    pub fn contains(&self, value: &T) -> bool {
+        self.counts.contains_key(value)
+    }
+    /// Tests whether this multiset is empty.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+    /// Returns the number of occurrences of all values in this multiset.
+    /// This takes O(n) time.
+    pub fn size(&self) -> u32 {
+        self.iter().map(|(_, count)| count).sum()
+    }
+    /// Returns a multiset in which each value occurs as many times as it does
+    /// in whichever of this multiset and `other` it occurs in more.
+    pub fn union(&self, other: &Self) -> Self {
+        let greater = |ours: &u32, theirs: &u32| *cmp::max(ours, theirs);
+        MultisetIn { counts: self.counts.union_with(&other.counts, &greater) }
+    }
+    /// Returns a multiset in which each value occurs as many times as it does
+    /// in this multiset and `other` put together.
+    pub fn sum(&self, other: &Self) -> Self {
+        MultisetIn { counts: self.counts.union_with(&other.counts, &|ours, theirs| ours + theirs) }
+    }
+    /// Returns a multiset in which each value occurs as many times as it does
+    /// in whichever of this multiset and `other` it occurs in less.
+    pub fn intersection(&self, other: &Self) -> Self {
+        let ours = self.counts.intersection(&other.counts);
+        let theirs = other.counts.intersection(&self.counts);
+        let lesser = |ours: &u32, theirs: &u32| *cmp::min(ours, theirs);
+        MultisetIn { counts: ours.union_with(&theirs, &lesser) }
+    }
+    /// Returns an iterator over the distinct values in this multiset and the
+    /// number of times each occurs, in no particular order.
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter { iter: self.counts.iter() }
+    }
+}
+
+impl<T, P, S> Collection for MultisetIn<T, P, S>
+    where T: Hash + Eq + Clone,
+          P: SharedPointer,
+          S: BuildHasher + Clone
+{
+    type Iter<'a> = Iter<'a, T, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T, S, P: SharedPointer> MemoryUsage for MultisetIn<T, P, S> {
+    fn census(&self, census: &mut Census) {
+        self.counts.census(census)
+    }
+}
+
+impl<T, S, P: SharedPointer> Default for MultisetIn<T, P, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Clone + Default
+{
+    fn default() -> Self {
+        MultisetIn::with_hasher(S::default())
+    }
+}
+
+impl<T: Hash + Eq + Clone, P: SharedPointer> FromIterator<T> for MultisetIn<T, P> {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        values.into_iter().fold(MultisetIn::new(), |multiset, value| multiset.insert(value))
+    }
+}
+
+/// An iterator over the distinct values of a `Multiset` and their counts.
+pub struct Iter<'a, T: 'a, P: SharedPointer> {
+    iter: hamt::Iter<'a, T, u32, P>,
+}
+
+impl<'a, T, P: SharedPointer> Iterator for Iter<'a, T, P> {
+    type Item = (&'a T, u32);
+
+    fn next(&mut self) -> Option<(&'a T, u32)> {
+        self.iter.next().map(|(value, count)| (value, *count))
+    }
+}
+
+#[cfg(test)]
+fn counts_of(multiset: &Multiset<u32>) -> Vec<(u32, u32)> {
+    let mut counts: Vec<(u32, u32)> = multiset.iter().map(|(v, c)| (*v, c)).collect();
+    counts.sort();
+    counts
+}
+
+#[test]
+fn empty_multiset_has_nothing() {
+    let multiset: Multiset<u32> = Multiset::new();
+
+    assert!(multiset.is_empty());
+    assert!(multiset.count(&1) == 0);
+    assert!(multiset.remove_one(&1).is_empty());
+    assert!(multiset.size() == 0);
+}
+
+#[test]
+fn occurrences_are_counted() {
+    let multiset: Multiset<u32> = (0..100).map(|i| i % 7).collect();
+
+    assert!(multiset.size() == 100);
+    assert!(multiset.count(&0) == 15);
+    assert!(multiset.count(&6) == 14);
+    let fewer = (0..20).fold(multiset.clone(), |multiset, _| multiset.remove_one(&0));
+    assert!(!fewer.contains(&0));
+    assert!(fewer.size() == 85);
+    assert!(multiset.remove_all(&6).size() == 86);
+    assert!(multiset.count(&0) == 15);
+}
+
+#[test]
+fn union_sum_and_intersection_combine_counts() {
+    let a: Multiset<u32> = vec![1, 1, 1, 2, 3, 3].into_iter().collect();
+    let b: Multiset<u32> = vec![1, 3, 3, 3, 4].into_iter().collect();
+
+    assert!(counts_of(&a.union(&b)) == vec![(1, 3), (2, 1), (3, 3), (4, 1)]);
+    assert!(counts_of(&a.sum(&b)) == vec![(1, 4), (2, 1), (3, 5), (4, 1)]);
+    assert!(counts_of(&a.intersection(&b)) == vec![(1, 1), (3, 2)]);
+    assert!(counts_of(&b.intersection(&a)) == vec![(1, 1), (3, 2)]);
+}
@@ -0,0 +1,292 @@
+use std::marker::PhantomData;
+
+use set::Map;
+use stack::{Stack, StackError};
+
+/// A trait representing a way to focus on a part of an immutable value, so
+/// that the part can be read, or replaced to give an updated copy of the
+/// whole.
+///
+/// Lenses compose with `then`, which makes it possible to update a value
+/// nested several levels deep, such as a value in a map of maps, without
+/// looking up and rebinding each level by hand.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::hamt::HamtMap;
+/// use functional_datastructures::optics::{At, Lens};
+///
+/// let inner = HamtMap::new().insert("port", 80);
+/// let config = HamtMap::new().insert("server", inner);
+///
+/// let port = At::new("server").then(At::new("port"));
+/// let updated = port.modify(&config, |port| port + 8000).unwrap();
+///
+/// assert!(port.get(&updated) == Some(8080));
+/// assert!(port.get(&config) == Some(80));
+/// assert!(At::new("client").then(At::new("port")).set(&config, 1).is_err());
+/// ```
+pub trait Lens<S> {
+    /// The type of the part this lens focuses on.
+    type Target;
+    /// Returns the part of `whole` this lens focuses on, or `None` if it is
+    /// not there.
+    fn get(&self, whole: &S) -> Option<Self::Target>;
+    /// Returns a copy of `whole` with the part this lens focuses on replaced
+    /// by `part`.
+    ///
+    /// # Failures
+    ///
+    /// Returns a `StackError` if there is nowhere in `whole` to put `part`,
+    /// such as when an index is out of range, or a lens composed with `then`
+    /// finds nothing to look inside.
+    fn set(&self, whole: &S, part: Self::Target) -> Result<S, StackError>;
+    /// Returns a copy of `whole` with the part this lens focuses on replaced
+    /// by the result of applying `f` to it.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::NoSuchElementException` if the part is not there,
+    /// or any error `set` would return.
+    fn modify<F>(&self, whole: &S, f: F) -> Result<S, StackError>
+        where F: FnOnce(Self::Target) -> Self::Target
+    {
+        match self.get(whole) {
+            Some(part) => self.set(whole, f(part)),
+            None => Err(StackError::NoSuchElementException),
+        }
+    }
+    /// Returns a lens which focuses on the part `inner` focuses on, within
+    /// the part this lens focuses on.
+    fn then<L: Lens<Self::Target>>(self, inner: L) -> Compose<Self, L>
+        where Self: Sized
+    {
+        Compose {
+            outer: self,
+            inner: inner,
+        }
+    }
+}
+
+/// A lens which focuses on the value bound to a key in a `Map`.
+#[derive(Debug, Clone)]
+pub struct At<M, K, V> {
+    key: K,
+    default: Option<V>,
+    marker: PhantomData<M>,
+}
+
+impl<M, K, V> At<M, K, V> {
+    /// Returns a lens which focuses on the value bound to `key`, finding
+    /// nothing if the key is not bound.
+    pub fn new(key: K) -> Self {
+        At {
+            key: key,
+            default: None,
+            marker: PhantomData,
+        }
+    }
+    /// Returns a lens which focuses on the value bound to `key`, finding
+    /// `default` if the key is not bound. Setting a value through it binds
+    /// the key, so a lens composed after it can fill in a missing level of
+    /// a nested map.
+    pub fn or_insert(key: K, default: V) -> Self {
+        At {
+            key: key,
+            default: Some(default),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<M: Map<K, V>, K: Eq + Clone, V: Clone> Lens<M> for At<M, K, V> {
+    type Target = V;
+    fn get(&self, whole: &M) -> Option<V> {
+        whole.lookup(self.key.clone()).or_else(|| self.default.clone())
+    }
+    fn set(&self, whole: &M, part: V) -> Result<M, StackError> {
+        Ok(whole.bind(self.key.clone(), part))
+    }
+}
+
+/// A lens which focuses on the item at an index in a `Stack`.
+#[derive(Debug, Clone)]
+pub struct Index<T> {
+    i: u32,
+    marker: PhantomData<T>,
+}
+
+impl<T> Index<T> {
+    /// Returns a lens which focuses on the item at index `i`.
+    pub fn new(i: u32) -> Self {
+        Index {
+            i: i,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Clone, S: Stack<T>> Lens<S> for Index<T> {
+    type Target = T;
+    fn get(&self, whole: &S) -> Option<T> {
+        whole.get(self.i).ok()
+    }
+    fn set(&self, whole: &S, part: T) -> Result<S, StackError> {
+        whole.update(self.i, part)
+    }
+}
+
+/// A lens which focuses on a part of a value through a pair of functions,
+/// one to read the part and one to make a copy of the value with the part
+/// replaced. This is the way to reach into a struct field.
+pub struct Field<S, T, G, P> {
+    get: G,
+    put: P,
+    marker: PhantomData<fn(&S) -> T>,
+}
+
+impl<S, T, G, P> Field<S, T, G, P>
+    where G: Fn(&S) -> T,
+          P: Fn(&S, T) -> S
+{
+    /// Returns a lens which reads its part with `get` and replaces it with
+    /// `put`.
+    pub fn new(get: G, put: P) -> Self {
+        Field {
+            get: get,
+            put: put,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<S, T, G, P> Lens<S> for Field<S, T, G, P>
+    where G: Fn(&S) -> T,
+          P: Fn(&S, T) -> S
+{
+    type Target = T;
+    fn get(&self, whole: &S) -> Option<T> {
+        Some((self.get)(whole))
+    }
+    fn set(&self, whole: &S, part: T) -> Result<S, StackError> {
+        Ok((self.put)(whole, part))
+    }
+}
+
+/// A lens made of two lenses, one focusing inside the other, returned by
+/// `Lens::then`.
+#[derive(Debug, Clone)]
+pub struct Compose<A, B> {
+    outer: A,
+    inner: B,
+}
+
+impl<S, A: Lens<S>, B: Lens<A::Target>> Lens<S> for Compose<A, B> {
+    type Target = B::Target;
+    fn get(&self, whole: &S) -> Option<B::Target> {
+        self.outer.get(whole).and_then(|middle| self.inner.get(&middle))
+    }
+    fn set(&self, whole: &S, part: B::Target) -> Result<S, StackError> {
+        match self.outer.get(whole) {
+            Some(middle) => self.outer.set(whole, self.inner.set(&middle, part)?),
+            None => Err(StackError::NoSuchElementException),
+        }
+    }
+}
+
+/// Updates a value nested inside `$whole` by applying `$f` to it, reaching
+/// it through each of the lenses in turn. This is shorthand for composing
+/// the lenses with `Lens::then` and calling `Lens::modify`, so it evaluates
+/// to a `Result` of the updated copy of `$whole`.
+///
+/// # Examples
+///
+/// ```
+/// #[macro_use]
+/// extern crate functional_datastructures;
+///
+/// use functional_datastructures::optics::{At, Index};
+/// use functional_datastructures::set::{Map, Tree};
+/// use functional_datastructures::stack::{CustomStack, Stack};
+///
+/// # fn main() {
+/// let scores = CustomStack::empty().cons(10).cons(20);
+/// let empty: Tree<&str, CustomStack<u32>> = Tree::empty_map();
+/// let players = empty.bind("alice", scores);
+///
+/// let updated = modify_in!(players, At::new("alice"), Index::new(1) => |s| s + 5).unwrap();
+/// assert!(updated.lookup("alice").unwrap().get(1).unwrap() == 15);
+/// assert!(modify_in!(players, At::new("bob"), Index::new(0) => |s| s + 5).is_err());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! modify_in {
+    ($whole:expr, $first:expr $(, $rest:expr)* => $f:expr) => {{
+        use $crate::optics::Lens;
+        $first$(.then($rest))*.modify(&$whole, $f)
+    }};
+}
+
+#[cfg(test)]
+use hamt::HamtMap;
+#[cfg(test)]
+use stack::CustomStack;
+
+#[test]
+fn at_reads_and_binds_keys() {
+    let map = HamtMap::new().insert(1, "one");
+
+    assert!(At::new(1).get(&map) == Some("one"));
+    assert!(At::new(2).get(&map).is_none());
+    assert!(At::new(2).set(&map, "two").unwrap().get(&2) == Some(&"two"));
+    assert!(At::new(2).modify(&map, |v| v).is_err());
+    assert!(At::or_insert(2, "none").get(&map) == Some("none"));
+}
+
+#[test]
+fn composed_lenses_update_nested_maps() {
+    let config = HamtMap::new().insert("a", HamtMap::new().insert("x", 1).insert("y", 2));
+
+    let x = At::new("a").then(At::new("x"));
+    let updated = x.modify(&config, |v| v * 10).unwrap();
+    assert!(x.get(&updated) == Some(10));
+    assert!(At::new("a").then(At::new("y")).get(&updated) == Some(2));
+    assert!(x.get(&config) == Some(1));
+
+    let missing = At::new("b").then(At::new("x"));
+    assert!(missing.set(&config, 5).is_err());
+    let created = At::or_insert("b", HamtMap::new()).then(At::new("x")).set(&config, 5).unwrap();
+    assert!(missing.get(&created) == Some(5));
+    assert!(x.get(&created) == Some(1));
+}
+
+#[test]
+fn index_and_field_reach_into_stacks_and_structs() {
+    #[derive(Clone)]
+    struct Player {
+        name: &'static str,
+        scores: CustomStack<u32>,
+    }
+    let player = Player {
+        name: "alice",
+        scores: CustomStack::empty().cons(3).cons(2).cons(1),
+    };
+    let scores = Field::new(|p: &Player| p.scores.clone(),
+                            |p: &Player, scores| Player { scores: scores, ..p.clone() });
+
+    let second = scores.then(Index::new(1));
+    let updated = second.modify(&player, |s| s + 100).unwrap();
+    assert!(second.get(&updated) == Some(102));
+    assert!(updated.name == "alice");
+    assert!(second.get(&player) == Some(2));
+    assert!(Field::new(|p: &Player| p.scores.clone(),
+                       |p: &Player, scores| Player { scores: scores, ..p.clone() })
+                .then(Index::new(3))
+                .set(&player, 0)
+                .is_err());
+
+    let nested = HamtMap::new().insert(0, player.clone());
+    let via_macro = modify_in!(nested, At::new(0), second => |s| s * 2).unwrap();
+    assert!(via_macro.get(&0).unwrap().scores.get(1).unwrap() == 4);
+}
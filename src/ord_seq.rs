@@ -0,0 +1,423 @@
+use collection::Collection;
+use measure::{Measured, Monoid};
+use memory::{Census, MemoryUsage};
+use ptr::{DefaultPointer, SharedPointer};
+
+use finger_tree::{self, FingerTreeIn};
+use set::{Map, Set};
+
+/// An immutable ordered map, implemented as a finger tree of bindings in
+/// increasing order of key, measured by the greatest key.
+///
+/// Lookups, insertions and removals take O(log n) time, as for the binary
+/// search trees, but the smallest and greatest bindings are at hand in O(1)
+/// time, and splitting a map at a key takes O(log n) time. Merging two maps
+/// takes O(m log(n/m)) time, where m is the size of the smaller map, so two
+/// maps whose keys do not interleave are merged in O(log n) time.
+///
+/// This is the ordered sequence of Hinze and Paterson's "Finger trees: a
+/// simple general-purpose data structure".
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::ord_seq::OrdSeqMap;
+/// use functional_datastructures::set::Map;
+///
+/// let empty: OrdSeqMap<u32, &str> = OrdSeqMap::empty_map();
+/// let map = empty.insert(3, "three").insert(1, "one").insert(2, "two");
+/// let (small, large) = map.split(&2);
+///
+/// assert!(map.lookup(2).unwrap() == "two");
+/// assert!(map.keys().cloned().collect::<Vec<_>>() == vec![1, 2, 3]);
+/// assert!(small.keys().cloned().collect::<Vec<_>>() == vec![1]);
+/// assert!(large.keys().cloned().collect::<Vec<_>>() == vec![2, 3]);
+/// assert!(large.merge(&small).size() == 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct OrdSeqMapIn<K: Clone, V, P: SharedPointer> {
+    tree: FingerTreeIn<Entry<K, V>, P>,
+}
+
+/// An `OrdSeqMapIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type OrdSeqMap<K, V> = OrdSeqMapIn<K, V, DefaultPointer>;
+
+/// A binding in an `OrdSeqMap`, measured by its key.
+#[derive(Debug, Clone)]
+struct Entry<K, V> {
+    key: K,
+    value: V,
+}
+
+/// The greatest key in part of a map, which is its last key since the keys
+/// are in increasing order, or `None` if that part is empty.
+#[derive(Debug, Clone)]
+struct LastKey<K>(Option<K>);
+
+impl<K: Clone> Monoid for LastKey<K> {
+    fn empty() -> Self {
+        LastKey(None)
+    }
+    fn combine(&self, other: &Self) -> Self {
+        match other.0 {
+            Some(_) => other.clone(),
+            None => self.clone(),
+        }
+    }
+}
+
+impl<K: Clone, V> Measured for Entry<K, V> {
+    type Measure = LastKey<K>;
+    fn measure(&self) -> LastKey<K> {
+        LastKey(Some(self.key.clone()))
+    }
+}
+
+impl<K: Ord> LastKey<K> {
+    /// Tests whether the part of the map this measures reaches `key`, that is
+    /// whether it contains a key greater than or equal to it.
+    fn reaches(&self, key: &K) -> bool {
+        match self.0 {
+            Some(ref last) => last >= key,
+            None => false,
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> OrdSeqMapIn<K, V, P> {
+    /// Returns a copy of this map with `key` bound to `value`, replacing any
+    /// existing binding for `key`.
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let (before, after) = self.tree.split(|last| last.reaches(&key));
+        let after = match after.pop_front() {
+            Some((ref entry, ref rest)) if entry.key == key => rest.clone(),
+            _ => after,
+        };
+        let entry = Entry {
+            key: key,
+            value: value,
+        };
+        OrdSeqMapIn { tree: before.push_back(entry).concat(&after) }
+    }
+    /// Returns a copy of this map without any binding for `key`.
+    pub fn remove(&self, key: &K) -> Self {
+        let (before, after) = self.tree.split(|last| last.reaches(key));
+        match after.pop_front() {
+            Some((ref entry, ref rest)) if entry.key == *key => {
+                OrdSeqMapIn { tree: before.concat(rest) }
+            }
+            _ => self.clone(),
+        }
+    }
+    /// Returns the value bound to `key`, or `None` if it is not bound.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.tree
+            .find(|last| last.reaches(key))
+            .and_then(|entry| if entry.key == *key {
+                Some(&entry.value)
+            } else {
+                None
+            })
+    }
+    /// Tests whether `key` is bound in this map.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+    /// Tests whether this map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    /// Returns the number of keys bound in this map. This takes O(n) time.
+    pub fn size(&self) -> u32 {
+        self.iter().count() as u32
+    }
+    /// Returns the binding with the smallest key, or `None` if this map is
+    /// empty.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.tree.front().map(|entry| (&entry.key, &entry.value))
+    }
+    /// Returns the binding with the greatest key, or `None` if this map is
+    /// empty.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.tree.back().map(|entry| (&entry.key, &entry.value))
+    }
+    /// Splits this map into the bindings with keys less than `key` and those
+    /// with keys greater than or equal to it.
+    pub fn split(&self, key: &K) -> (Self, Self) {
+        let (before, after) = self.tree.split(|last| last.reaches(key));
+        (OrdSeqMapIn { tree: before }, OrdSeqMapIn { tree: after })
+    }
+    /// Returns a map of the bindings in this map and `other`. Where a key is
+    /// bound in both, the binding from this map is kept.
+    ///
+    /// The maps are merged by splitting off alternate runs of bindings from
+    /// each, so this is cheap when their keys interleave little.
+    pub fn merge(&self, other: &Self) -> Self {
+        let mut merged = FingerTreeIn::empty();
+        let mut ours = self.tree.clone();
+        let mut theirs = other.tree.clone();
+        loop {
+            let (run, rest) = match theirs.front() {
+                None => return OrdSeqMapIn { tree: merged.concat(&ours) },
+                Some(entry) => ours.split(|last| last.reaches(&entry.key)),
+            };
+            merged = merged.concat(&run);
+            ours = rest;
+
+            let (run, rest) = match ours.front() {
+                None => return OrdSeqMapIn { tree: merged.concat(&theirs) },
+                Some(entry) => {
+                    if theirs.front().is_some_and(|other| other.key == entry.key) {
+                        theirs = theirs.pop_front().unwrap().1;
+                    }
+                    theirs.split(|last| last.reaches(&entry.key))
+                }
+            };
+            merged = merged.concat(&run);
+            theirs = rest;
+        }
+    }
+    /// Returns an iterator over the bindings in this map, in increasing order
+    /// of key.
+    pub fn iter(&self) -> Iter<'_, K, V, P> {
+        Iter { iter: self.tree.iter() }
+    }
+    /// Returns an iterator over the keys of this map, in increasing order.
+    pub fn keys(&self) -> Keys<'_, K, V, P> {
+        Keys { iter: self.iter() }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Collection for OrdSeqMapIn<K, V, P> {
+    type Iter<'a> = Iter<'a, K, V, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, K, V, P> {
+        self.iter()
+    }
+}
+
+impl<K: Clone, V, P: SharedPointer> MemoryUsage for OrdSeqMapIn<K, V, P> {
+    fn census(&self, census: &mut Census) {
+        self.tree.census(census)
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Map<K, V> for OrdSeqMapIn<K, V, P> {
+    fn empty_map() -> Self {
+        OrdSeqMapIn { tree: FingerTreeIn::empty() }
+    }
+    fn bind(&self, key: K, value: V) -> Self {
+        self.insert(key, value)
+    }
+    fn lookup(&self, key: K) -> Option<V> {
+        self.get(&key).cloned()
+    }
+}
+
+/// An iterator over the bindings of an `OrdSeqMap`, in increasing order of
+/// key.
+pub struct Iter<'a, K: Clone + 'a, V: 'a, P: SharedPointer> {
+    iter: finger_tree::Iter<'a, Entry<K, V>, P>,
+}
+
+impl<'a, K: Clone, V, P: SharedPointer> Iterator for Iter<'a, K, V, P> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.iter.next().map(|entry| (&entry.key, &entry.value))
+    }
+}
+
+/// An iterator over the keys of an `OrdSeqMap`, in increasing order.
+pub struct Keys<'a, K: Clone + 'a, V: 'a, P: SharedPointer> {
+    iter: Iter<'a, K, V, P>,
+}
+
+impl<'a, K: Clone, V, P: SharedPointer> Iterator for Keys<'a, K, V, P> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.iter.next().map(|(key, _)| key)
+    }
+}
+
+/// An immutable ordered set, implemented as an `OrdSeqMap` with no values.
+///
+/// Membership tests, insertions and removals take O(log n) time, and sets
+/// can be split at a value and merged as for `OrdSeqMap`.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::ord_seq::OrdSeqSet;
+/// use functional_datastructures::set::Set;
+///
+/// let low = (0..5).fold(OrdSeqSet::empty(), |set, i| set.insert(i));
+/// let high = (5..10).fold(OrdSeqSet::empty(), |set, i| set.insert(i));
+/// let all = high.union(&low);
+///
+/// assert!(all.member(7));
+/// assert!(all.iter().cloned().collect::<Vec<_>>() == (0..10).collect::<Vec<_>>());
+/// assert!(all.split(&5).1.iter().cloned().collect::<Vec<_>>() == (5..10).collect::<Vec<_>>());
+/// ```
+#[derive(Debug, Clone)]
+pub struct OrdSeqSetIn<T: Clone, P: SharedPointer> {
+    map: OrdSeqMapIn<T, (), P>,
+}
+
+/// An `OrdSeqSetIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type OrdSeqSet<T> = OrdSeqSetIn<T, DefaultPointer>;
+
+impl<T: Ord + Clone, P: SharedPointer> OrdSeqSetIn<T, P> {
+    /// Tests whether `value` is a member of this set.
+    pub fn contains(&self, value: &T) -> bool {
+        self.map.contains_key(value)
+    }
+    /// Returns a copy of this set without `value`.
+    pub fn remove(&self, value: &T) -> Self {
+        OrdSeqSetIn { map: self.map.remove(value) }
+    }
+    /// Tests whether this set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+    /// Returns the number of members of this set. This takes O(n) time.
+    pub fn size(&self) -> u32 {
+        self.map.size()
+    }
+    /// Splits this set into the members less than `value` and those greater
+    /// than or equal to it.
+    pub fn split(&self, value: &T) -> (Self, Self) {
+        let (before, after) = self.map.split(value);
+        (OrdSeqSetIn { map: before }, OrdSeqSetIn { map: after })
+    }
+    /// Returns a set of the values which are members of either this set or
+    /// `other`, merging them as for `OrdSeqMap::merge`.
+    pub fn union(&self, other: &Self) -> Self {
+        OrdSeqSetIn { map: self.map.merge(&other.map) }
+    }
+    /// Returns an iterator over the members of this set, in increasing order.
+    pub fn iter(&self) -> Keys<'_, T, (), P> {
+        self.map.keys()
+    }
+}
+
+impl<T: Ord + Clone, P: SharedPointer> Collection for OrdSeqSetIn<T, P> {
+    type Iter<'a> = Keys<'a, T, (), P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Keys<'a, T, (), P> {
+        self.iter()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> MemoryUsage for OrdSeqSetIn<T, P> {
+    fn census(&self, census: &mut Census) {
+        self.map.census(census)
+    }
+}
+
+impl<T: Ord + Clone, P: SharedPointer> Set<T> for OrdSeqSetIn<T, P> {
+    fn empty() -> Self {
+        OrdSeqSetIn { map: OrdSeqMapIn::empty_map() }
+    }
+    fn insert(&self, value: T) -> Self {
+        OrdSeqSetIn { map: self.map.insert(value, ()) }
+    }
+    fn member(&self, value: T) -> bool {
+        self.contains(&value)
+    }
+}
+
+#[test]
+fn empty_map_contains_nothing() {
+    let map: OrdSeqMap<u32, u32> = OrdSeqMap::empty_map();
+
+    assert!(map.is_empty());
+    assert!(map.lookup(0).is_none());
+    assert!(map.first().is_none());
+    assert!(map.remove(&0).is_empty());
+}
+
+#[test]
+fn keys_are_kept_in_order() {
+    let keys: Vec<u32> = (0..500).map(|i| (i * 7919) % 500).collect();
+    let map = keys.iter().fold(OrdSeqMap::empty_map(), |map, k| map.insert(*k, k * 2));
+
+    assert!(map.size() == 500);
+    assert!(map.keys().cloned().collect::<Vec<_>>() == (0..500).collect::<Vec<_>>());
+    for k in 0..500 {
+        assert!(map.lookup(k).unwrap() == k * 2);
+    }
+    assert!(map.first() == Some((&0, &0)));
+    assert!(map.last() == Some((&499, &998)));
+}
+
+#[test]
+fn values_can_be_replaced_and_removed() {
+    let map = (0..100).fold(OrdSeqMap::empty_map(), |map, k| map.insert(k, "old"));
+    let replaced = map.insert(50, "new");
+    let removed = (0..100).filter(|k| k % 2 == 0).fold(map.clone(), |map, k| map.remove(&k));
+
+    assert!(replaced.size() == 100);
+    assert!(replaced.lookup(50).unwrap() == "new");
+    assert!(map.lookup(50).unwrap() == "old");
+    assert!(removed.keys().cloned().collect::<Vec<_>>() ==
+            (0..100).filter(|k| k % 2 == 1).collect::<Vec<_>>());
+    assert!(removed.remove(&50).size() == 50);
+}
+
+#[test]
+fn split_divides_at_a_key() {
+    let map = (0..100).map(|k| k * 2).fold(OrdSeqMap::empty_map(), |map, k| map.insert(k, k));
+
+    let (before, after) = map.split(&51);
+    assert!(before.keys().cloned().collect::<Vec<_>>() ==
+            (0..26).map(|k| k * 2).collect::<Vec<_>>());
+    assert!(after.first() == Some((&52, &52)));
+
+    let (before, after) = map.split(&52);
+    assert!(before.last() == Some((&50, &50)));
+    assert!(after.first() == Some((&52, &52)));
+    assert!(map.split(&1000).1.is_empty());
+}
+
+#[test]
+fn merge_interleaves_and_prefers_left_values() {
+    let threes = (0..100).fold(OrdSeqMap::empty_map(), |map, k| map.insert(k * 3, "three"));
+    let fives = (0..60).fold(OrdSeqMap::empty_map(), |map, k| map.insert(k * 5, "five"));
+
+    let merged = threes.merge(&fives);
+    let expected: Vec<u32> = (0..300).filter(|k| k % 3 == 0 || k % 5 == 0).collect();
+    assert!(merged.keys().cloned().collect::<Vec<_>>() == expected);
+    assert!(merged.lookup(15).unwrap() == "three");
+    assert!(fives.merge(&threes).lookup(15).unwrap() == "five");
+    assert!(merged.lookup(10).unwrap() == "five");
+}
+
+#[test]
+fn merge_of_disjoint_ranges_appends() {
+    let low = (0..1000).fold(OrdSeqMap::empty_map(), |map, k| map.insert(k, k));
+    let high = (1000..2000).fold(OrdSeqMap::empty_map(), |map, k| map.insert(k, k));
+
+    let merged = high.merge(&low);
+    assert!(merged.keys().cloned().collect::<Vec<_>>() == (0..2000).collect::<Vec<_>>());
+}
+
+#[test]
+fn set_operations() {
+    let set = OrdSeqSet::empty().insert(3).insert(1).insert(2).insert(2);
+
+    assert!(set.size() == 3);
+    assert!(set.member(2));
+    assert!(!set.remove(&2).contains(&2));
+    assert!(set.union(&OrdSeqSet::empty().insert(0)).iter().cloned().collect::<Vec<_>>() ==
+            vec![0, 1, 2, 3]);
+}
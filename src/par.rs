@@ -0,0 +1,682 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash};
+
+use rayon;
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::iter::{FromParallelIterator, IntoParallelIterator, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+
+use hamt::{self, HamtMap, HamtSet};
+use ptr::{DefaultPointer, Shared};
+use seq::{self, Seq};
+use set::{self, Tree};
+
+/// The fewest bindings `par_bulk_load` hands to two tasks to build the
+/// subtrees of, rather than building both on the current thread.
+const PARALLEL_LOAD: usize = 4096;
+
+/// The number of levels at the top of a tree at which `par_union` and
+/// `par_intersection` split both trees around a node and work on the two
+/// sides as separate tasks. Below them, the two sides are merged in order.
+const PARALLEL_DEPTH: u32 = 10;
+
+/// A subtree of a `Tree`, as its nodes hold them.
+type Subtree<K, V> = Shared<Tree<K, V>, DefaultPointer>;
+
+/// A trait for iterators which can hand half of what they have left to
+/// visit to another iterator, so that the two halves can be visited on
+/// different threads.
+///
+/// The iterators of the tree-shaped structures in this crate split at their
+/// internal nodes, in time proportional to the depth of the tree, copying
+/// nothing but the iterator's own stack.
+pub trait Split: Iterator + Sized {
+    /// Removes the later part of what this iterator has left to visit and
+    /// returns an iterator over it, or returns `None` if there is too little
+    /// left to be worth splitting. Visiting this iterator and then the
+    /// returned one visits the same items in the same order as visiting this
+    /// iterator would have done before.
+    fn split(&mut self) -> Option<Self>;
+}
+
+/// A parallel iterator over one of the structures in this crate, returned by
+/// `into_par_iter` or `par_iter`.
+///
+/// The items are the same, and `collect` puts them in the same order, as for
+/// the structure's sequential iterator.
+///
+/// # Examples
+///
+/// ```
+/// extern crate functional_datastructures;
+/// extern crate rayon;
+///
+/// use functional_datastructures::hamt::HamtMap;
+/// use rayon::prelude::*;
+///
+/// # fn main() {
+/// let map = (0..10000u64).fold(HamtMap::new(), |map, i| map.insert(i, i * 2));
+///
+/// let total: u64 = map.par_iter().map(|(_, value)| *value).sum();
+/// assert!(total == 2 * (0..10000).sum::<u64>());
+/// # }
+/// ```
+pub struct ParIter<I> {
+    iter: I,
+}
+
+impl<I> ParallelIterator for ParIter<I>
+    where I: Split + Send,
+          I::Item: Send
+{
+    type Item = I::Item;
+
+    fn drive_unindexed<C: UnindexedConsumer<I::Item>>(self, consumer: C) -> C::Result {
+        bridge_unindexed(Producer(self.iter), consumer)
+    }
+}
+
+/// Adapts a `Split` iterator to rayon's producer interface.
+struct Producer<I>(I);
+
+impl<I> UnindexedProducer for Producer<I>
+    where I: Split + Send,
+          I::Item: Send
+{
+    type Item = I::Item;
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        let back = self.0.split().map(Producer);
+        (self, back)
+    }
+
+    fn fold_with<F: Folder<I::Item>>(self, folder: F) -> F {
+        folder.consume_iter(self.0)
+    }
+}
+
+impl<'a, K, V> IntoParallelIterator for &'a Tree<K, V>
+    where K: Ord + Clone + Send + Sync,
+          V: Clone + Send + Sync
+{
+    type Iter = ParIter<set::Iter<'a, K, V, DefaultPointer>>;
+    type Item = (&'a K, &'a V);
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter { iter: self.iter() }
+    }
+}
+
+impl<'a, T: Clone + Send + Sync> IntoParallelIterator for &'a Seq<T> {
+    type Iter = ParIter<seq::Iter<'a, T, DefaultPointer>>;
+    type Item = &'a T;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter { iter: self.iter() }
+    }
+}
+
+impl<'a, K, V, S> IntoParallelIterator for &'a HamtMap<K, V, S>
+    where K: Hash + Eq + Clone + Send + Sync,
+          V: Clone + Send + Sync,
+          S: BuildHasher + Clone
+{
+    type Iter = ParIter<hamt::Iter<'a, K, V, DefaultPointer>>;
+    type Item = (&'a K, &'a V);
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter { iter: self.iter() }
+    }
+}
+
+impl<'a, T, S> IntoParallelIterator for &'a HamtSet<T, S>
+    where T: Hash + Eq + Clone + Send + Sync,
+          S: BuildHasher + Clone
+{
+    type Iter = ParIter<hamt::Keys<'a, T, (), DefaultPointer>>;
+    type Item = &'a T;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter { iter: self.iter() }
+    }
+}
+
+/// Returns a balanced `Tree` of `bindings`, the same tree as
+/// `TreeBuilder::freeze` returns, but sorting the bindings and building the
+/// subtrees of large trees in parallel on rayon's thread pool. Where a key
+/// is bound more than once, the last binding wins.
+///
+/// Collecting a parallel iterator of bindings into a `Tree` loads it this
+/// way, with the last binding in the iterator's order winning.
+///
+/// # Examples
+///
+/// ```
+/// extern crate functional_datastructures;
+/// extern crate rayon;
+///
+/// use functional_datastructures::par::par_bulk_load;
+/// use functional_datastructures::set::{Map, Tree};
+/// use rayon::prelude::*;
+///
+/// # fn main() {
+/// let tree = par_bulk_load((0..100000u32).rev().map(|i| (i, i * 2)).collect());
+/// assert!(tree.lookup(500) == Some(1000));
+///
+/// let squares: Tree<u64, u64> = (0..100000u64).into_par_iter().map(|i| (i, i * i)).collect();
+/// assert!(squares.lookup(1000) == Some(1000000));
+/// # }
+/// ```
+pub fn par_bulk_load<K, V>(mut bindings: Vec<(K, V)>) -> Tree<K, V>
+    where K: Ord + Clone + Debug + Send + Sync,
+          V: Clone + Debug + Send + Sync
+{
+    // The sort is stable, so the last binding of each key is the last of
+    // its run.
+    bindings.par_sort_by(|a, b| a.0.cmp(&b.0));
+    let mut unique: Vec<Option<(K, V)>> = Vec::with_capacity(bindings.len());
+    for binding in bindings {
+        match unique.last_mut() {
+            Some(&mut Some(ref mut last)) if last.0 == binding.0 => {
+                *last = binding;
+                continue;
+            }
+            _ => {}
+        }
+        unique.push(Some(binding));
+    }
+    let empty = Shared::new(Tree::Empty);
+    match Shared::try_unwrap(par_balanced(&mut unique, &empty)) {
+        Ok(tree) => tree,
+        Err(_) => Tree::Empty,
+    }
+}
+
+/// Returns a balanced tree of `bindings`, which are in order of key, taking
+/// them out of the slice. The tree has the same shape as the one built by
+/// `TreeBuilder`.
+fn par_balanced<K, V>(bindings: &mut [Option<(K, V)>],
+                      empty: &Subtree<K, V>)
+                      -> Subtree<K, V>
+    where K: Ord + Clone + Send + Sync,
+          V: Clone + Send + Sync
+{
+    if bindings.is_empty() {
+        return empty.clone();
+    }
+    let parallel = bindings.len() >= PARALLEL_LOAD;
+    let (left, rest) = bindings.split_at_mut(bindings.len() / 2);
+    let (middle, right) = rest.split_first_mut().unwrap();
+    let (key, value) = middle.take().unwrap();
+    let (left, right) = if parallel {
+        rayon::join(|| par_balanced(left, empty), || par_balanced(right, empty))
+    } else {
+        (par_balanced(left, empty), par_balanced(right, empty))
+    };
+    Shared::new(Tree::Node {
+        left: left,
+        key: key,
+        value: value,
+        right: right,
+    })
+}
+
+impl<K, V> FromParallelIterator<(K, V)> for Tree<K, V>
+    where K: Ord + Clone + Debug + Send + Sync,
+          V: Clone + Debug + Send + Sync
+{
+    fn from_par_iter<I>(iter: I) -> Self
+        where I: IntoParallelIterator<Item = (K, V)>
+    {
+        par_bulk_load(iter.into_par_iter().collect())
+    }
+}
+
+/// A trait for maps whose values can be transformed on rayon's thread pool,
+/// for transformations costly enough to be worth spreading over threads.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::hamt::HamtMap;
+/// use functional_datastructures::par::{par_bulk_load, ParMapValues};
+/// use functional_datastructures::set::Map;
+///
+/// let map = (0..10000u32).fold(HamtMap::new(), |map, i| map.insert(i, i.to_string()));
+/// let parsed = map.par_map_values(|value| value.parse::<u64>().unwrap());
+/// assert!(parsed.get(&1234) == Some(&1234));
+///
+/// let tree = par_bulk_load((0..10000u32).map(|i| (i, i)).collect());
+/// assert!(tree.par_map_values(|value| value % 7 == 0).lookup(49) == Some(true));
+/// ```
+pub trait ParMapValues<V, W> {
+    /// The type of map returned, binding the same keys to values of type
+    /// `W`.
+    type Output;
+    /// Returns a map binding each key of this map to `f` of its value,
+    /// calling `f` on the values in parallel.
+    fn par_map_values<F>(&self, f: F) -> Self::Output where F: Fn(&V) -> W + Sync + Send;
+}
+
+/// The tree returned is balanced, and built in parallel as by
+/// `par_bulk_load`, whatever the shape of this one.
+impl<K, V, W> ParMapValues<V, W> for Tree<K, V>
+    where K: Ord + Clone + Send + Sync,
+          V: Clone + Send + Sync,
+          W: Clone + Send + Sync
+{
+    type Output = Tree<K, W>;
+
+    fn par_map_values<F>(&self, f: F) -> Tree<K, W>
+        where F: Fn(&V) -> W + Sync + Send
+    {
+        let mut bindings: Vec<_> = self.into_par_iter()
+                                       .map(|(key, value)| Some((key.clone(), f(value))))
+                                       .collect();
+        let empty = Shared::new(Tree::Empty);
+        match Shared::try_unwrap(par_balanced(&mut bindings, &empty)) {
+            Ok(tree) => tree,
+            Err(_) => Tree::Empty,
+        }
+    }
+}
+
+/// The values are transformed in parallel and the map then rebuilt with
+/// `HamtMap::map_values`, which keeps the shape of this map and hashes
+/// nothing.
+impl<K, V, W, S> ParMapValues<V, W> for HamtMap<K, V, S>
+    where K: Hash + Eq + Clone + Send + Sync,
+          V: Clone + Send + Sync,
+          W: Clone + Send,
+          S: BuildHasher + Clone
+{
+    type Output = HamtMap<K, W, S>;
+
+    fn par_map_values<F>(&self, f: F) -> HamtMap<K, W, S>
+        where F: Fn(&V) -> W + Sync + Send
+    {
+        // Collecting keeps the order the sequential iterator visits the
+        // values in, which is the order `map_values` asks for them.
+        let values: Vec<W> = self.into_par_iter().map(|(_, value)| f(value)).collect();
+        let mut values = values.into_iter();
+        self.map_values(|_| values.next().unwrap())
+    }
+}
+
+/// Returns a tree of the bindings in `tree` and `other`. Where a key is
+/// bound in both, the binding from `tree` is kept.
+///
+/// This divides and conquers: it splits `tree` around the root of `other`,
+/// and unions the two sides with the subtrees of `other` as separate tasks
+/// on rayon's thread pool, down to a fixed depth. Below that each pair of
+/// subtrees is merged in order into a balanced subtree, unless one of them
+/// is empty, in which case the other is shared with the result. Unioning
+/// two balanced trees therefore gives a balanced tree.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::par::{par_bulk_load, par_union};
+/// use functional_datastructures::set::Map;
+///
+/// let evens = par_bulk_load((0..60000u32).map(|i| (i * 2, "even")).collect());
+/// let threes = par_bulk_load((0..40000u32).map(|i| (i * 3, "three")).collect());
+/// let union = par_union(&evens, &threes);
+///
+/// assert!(union.lookup(6) == Some("even") && union.lookup(9) == Some("three"));
+/// assert!(union.lookup(7).is_none() && union.iter().count() == 80000);
+/// ```
+pub fn par_union<K, V>(tree: &Tree<K, V>, other: &Tree<K, V>) -> Tree<K, V>
+    where K: Ord + Clone + Send + Sync,
+          V: Clone + Send + Sync
+{
+    let union = combine(&Shared::new(tree.clone()),
+                        &Shared::new(other.clone()),
+                        PARALLEL_DEPTH,
+                        true);
+    Shared::try_unwrap(union).unwrap_or_else(|shared| (*shared).clone())
+}
+
+/// Returns a tree of the bindings in `tree` whose keys are also bound in
+/// `other`, dividing and conquering on rayon's thread pool as `par_union`
+/// does.
+///
+/// Where a key of `other` is missing from `tree`, the two sides around it
+/// are joined under the greatest binding of the left one, so the result is
+/// roughly, rather than exactly, balanced.
+pub fn par_intersection<K, V>(tree: &Tree<K, V>, other: &Tree<K, V>) -> Tree<K, V>
+    where K: Ord + Clone + Send + Sync,
+          V: Clone + Send + Sync
+{
+    let intersection = combine(&Shared::new(tree.clone()),
+                               &Shared::new(other.clone()),
+                               PARALLEL_DEPTH,
+                               false);
+    Shared::try_unwrap(intersection).unwrap_or_else(|shared| (*shared).clone())
+}
+
+/// Returns the union of `tree` and `other` if `union` is set, or their
+/// intersection if not, keeping the bindings from `tree` for keys bound in
+/// both. For the top `depth` levels of `other` the two sides of each node
+/// are combined in parallel.
+fn combine<K, V>(tree: &Subtree<K, V>,
+                 other: &Subtree<K, V>,
+                 depth: u32,
+                 union: bool)
+                 -> Subtree<K, V>
+    where K: Ord + Clone + Send + Sync,
+          V: Clone + Send + Sync
+{
+    let (other_left, key, other_value, other_right) = match **other {
+        Tree::Empty => return if union { tree.clone() } else { other.clone() },
+        Tree::Node { ref left, ref key, ref value, ref right } => (left, key, value, right),
+    };
+    if let Tree::Empty = **tree {
+        return if union { other.clone() } else { tree.clone() };
+    }
+    if depth == 0 {
+        return merge(tree, other, union);
+    }
+    let (before, found, after) = split(tree, key);
+    let (left, right) = rayon::join(|| combine(&before, other_left, depth - 1, union),
+                                    || combine(&after, other_right, depth - 1, union));
+    match found {
+        Some(value) => node(left, key.clone(), value, right),
+        None if union => node(left, key.clone(), other_value.clone(), right),
+        None => join(left, right),
+    }
+}
+
+/// Splits `tree` into the bindings with keys less than `key` and those with
+/// keys greater than it, along with the value bound to `key`, if any. Only
+/// the nodes on the path down to `key` are copied.
+fn split<K, V>(tree: &Subtree<K, V>, key: &K) -> (Subtree<K, V>, Option<V>, Subtree<K, V>)
+    where K: Ord + Clone,
+          V: Clone
+{
+    // The nodes passed on the way down, with the subtrees which stay on
+    // their side of `key`.
+    let mut lesser = Vec::new();
+    let mut greater = Vec::new();
+    let mut current = tree;
+    let (mut before, found, mut after) = loop {
+        match **current {
+            Tree::Empty => break (current.clone(), None, current.clone()),
+            Tree::Node { ref left, key: ref node_key, ref value, ref right } => {
+                match key.cmp(node_key) {
+                    Ordering::Less => {
+                        greater.push((node_key, value, right));
+                        current = left;
+                    }
+                    Ordering::Greater => {
+                        lesser.push((node_key, value, left));
+                        current = right;
+                    }
+                    Ordering::Equal => break (left.clone(), Some(value.clone()), right.clone()),
+                }
+            }
+        }
+    };
+    while let Some((key, value, left)) = lesser.pop() {
+        before = node(left.clone(), key.clone(), value.clone(), before);
+    }
+    while let Some((key, value, right)) = greater.pop() {
+        after = node(after, key.clone(), value.clone(), right.clone());
+    }
+    (before, found, after)
+}
+
+/// Returns a tree of the bindings in `left` and `right`, all of whose keys
+/// are greater than those in `left`, rooted at the greatest binding of
+/// `left`.
+fn join<K, V>(left: Subtree<K, V>, right: Subtree<K, V>) -> Subtree<K, V>
+    where K: Ord + Clone,
+          V: Clone
+{
+    if let Tree::Empty = *right {
+        return left;
+    }
+    let mut path: Vec<(&Subtree<K, V>, &K, &V)> = Vec::new();
+    let mut current = &left;
+    loop {
+        match **current {
+            Tree::Empty => return right,
+            Tree::Node { left: ref lesser, ref key, ref value, right: ref greater } => {
+                if let Tree::Empty = **greater {
+                    let mut rest = lesser.clone();
+                    while let Some((above, above_key, above_value)) = path.pop() {
+                        rest = node(above.clone(), above_key.clone(), above_value.clone(), rest);
+                    }
+                    return node(rest, key.clone(), value.clone(), right);
+                }
+                path.push((lesser, key, value));
+                current = greater;
+            }
+        }
+    }
+}
+
+/// Returns a balanced tree of the union of `tree` and `other` if `union` is
+/// set, or their intersection if not, merging their bindings in order.
+fn merge<K, V>(tree: &Tree<K, V>, other: &Tree<K, V>, union: bool) -> Subtree<K, V>
+    where K: Ord + Clone + Send + Sync,
+          V: Clone + Send + Sync
+{
+    let mut bindings = Vec::new();
+    let mut these = tree.iter().peekable();
+    let mut others = other.iter().peekable();
+    loop {
+        let order = match (these.peek(), others.peek()) {
+            (Some(&(this, _)), Some(&(other, _))) => this.cmp(other),
+            (Some(_), None) if union => Ordering::Less,
+            (None, Some(_)) if union => Ordering::Greater,
+            _ => break,
+        };
+        let binding = match order {
+            Ordering::Less => these.next(),
+            Ordering::Greater => others.next(),
+            Ordering::Equal => {
+                others.next();
+                these.next()
+            }
+        };
+        if union || order == Ordering::Equal {
+            bindings.extend(binding.map(|(key, value)| Some((key.clone(), value.clone()))));
+        }
+    }
+    par_balanced(&mut bindings, &Shared::new(Tree::Empty))
+}
+
+/// Returns a node with the given subtrees and binding.
+fn node<K, V>(left: Subtree<K, V>, key: K, value: V, right: Subtree<K, V>) -> Subtree<K, V>
+    where K: Ord + Clone,
+          V: Clone
+{
+    Shared::new(Tree::Node {
+        left: left,
+        key: key,
+        value: value,
+        right: right,
+    })
+}
+
+#[cfg(test)]
+use rayon::prelude::*;
+#[cfg(test)]
+use set::TreeBuilder;
+#[cfg(test)]
+use set::{Map, Set};
+
+/// Splits `iter` as finely as it will go and returns the items of the pieces
+/// in order, checking that every split leaves something on both sides.
+#[cfg(test)]
+fn split_fully<I: Split>(mut iter: I) -> Vec<I::Item> {
+    match iter.split() {
+        None => iter.collect(),
+        Some(back) => {
+            let mut items = split_fully(iter);
+            let back = split_fully(back);
+            assert!(!items.is_empty() && !back.is_empty());
+            items.extend(back);
+            items
+        }
+    }
+}
+
+#[test]
+fn splitting_keeps_every_item_in_order() {
+    let tree = (0..500).map(|i| (i * 7919) % 500)
+                       .fold(Tree::empty_map(), |tree, i| tree.bind(i, ()));
+    assert!(split_fully(tree.iter()) == tree.iter().collect::<Vec<_>>());
+    assert!(split_fully(tree.iter()).len() == 500);
+
+    let seq = (0..5000).fold(Seq::empty(), |seq, i| seq.push_front(i).push_back(i));
+    assert!(split_fully(seq.iter()) == seq.iter().collect::<Vec<_>>());
+
+    let map = (0..5000).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    assert!(split_fully(map.iter()) == map.iter().collect::<Vec<_>>());
+    assert!(split_fully(map.iter()).len() == 5000);
+
+    let empty: Seq<u32> = Seq::empty();
+    assert!(split_fully(empty.iter()).is_empty());
+    assert!(split_fully(Tree::empty().insert(1).iter()).len() == 1);
+}
+
+#[test]
+fn parallel_iterators_match_sequential_ones() {
+    let tree = (0..10000u64).map(|i| (i * 7919) % 10000)
+                            .fold(Tree::empty_map(), |tree, i| tree.bind(i, i));
+    assert!(tree.par_iter().map(|(_, value)| *value).sum::<u64>() == (0..10000).sum::<u64>());
+    assert!(tree.par_iter().map(|(key, _)| *key).collect::<Vec<_>>() ==
+            (0..10000).collect::<Vec<_>>());
+
+    let seq = (0..100000u64).fold(Seq::empty(), |seq, i| seq.push_back(i));
+    assert!(seq.par_iter().cloned().collect::<Vec<_>>() ==
+            seq.iter().cloned().collect::<Vec<_>>());
+    assert!(seq.par_iter().filter(|i| *i % 3 == 0).count() == 33334);
+
+    let map = (0..100000u64).fold(HamtMap::new(), |map, i| map.insert(i, i % 10));
+    assert!(map.par_iter().map(|(_, value)| *value).sum::<u64>() == 450000);
+    let set = (0..1000).fold(HamtSet::new(), |set, i| set.insert(i));
+    assert!(set.par_iter().max() == Some(&999));
+}
+
+/// Returns the number of nodes on the longest path down from the root of
+/// `tree`.
+#[cfg(test)]
+fn height<K: Ord + Clone, V: Clone>(tree: &Tree<K, V>) -> usize {
+    match *tree {
+        Tree::Empty => 0,
+        Tree::Node { ref left, ref right, .. } => 1 + height(left).max(height(right)),
+    }
+}
+
+#[test]
+fn parallel_bulk_loads_match_builders() {
+    let bindings: Vec<_> = (0..100000u32).map(|i| ((i * 7919) % 60000, i)).collect();
+    let mut builder = TreeBuilder::new();
+    builder.extend(bindings.iter().cloned());
+    let built = builder.freeze();
+    let loaded = par_bulk_load(bindings.clone());
+
+    assert!(loaded.iter().eq(built.iter()));
+    assert!(height(&loaded) == height(&built) && height(&loaded) == 16);
+
+    let collected: Tree<u32, u32> = bindings.into_par_iter().collect();
+    assert!(collected.iter().eq(built.iter()));
+    assert!(par_bulk_load(Vec::<(u32, ())>::new()).iter().next().is_none());
+}
+
+#[test]
+fn parallel_set_operations_match_merges() {
+    let evens = par_bulk_load((0..50000u32).map(|i| (i * 2, i)).collect());
+    let threes = par_bulk_load((0..40000u32).map(|i| (i * 3, i + 1000000)).collect());
+
+    let union = par_union(&evens, &threes);
+    let expected: Vec<_> = (0..120000u32).filter(|i| (i % 2 == 0 && *i < 100000) || i % 3 == 0)
+                                         .map(|i| if i % 2 == 0 && i < 100000 {
+                                             (i, i / 2)
+                                         } else {
+                                             (i, i / 3 + 1000000)
+                                         })
+                                         .collect();
+    assert!(union.iter().map(|(key, value)| (*key, *value)).collect::<Vec<_>>() == expected);
+    assert!(height(&union) <= 18);
+
+    let intersection = par_intersection(&evens, &threes);
+    assert!(intersection.iter().map(|(key, value)| (*key, *value)).collect::<Vec<_>>() ==
+            (0..100000u32).filter(|i| i % 6 == 0).map(|i| (i, i / 2)).collect::<Vec<_>>());
+    assert!(par_intersection(&threes, &evens).lookup(6) == Some(1000002));
+
+    let empty = Tree::empty_map();
+    assert!(par_union(&evens, &empty).iter().eq(evens.iter()));
+    assert!(par_union(&empty, &evens).iter().eq(evens.iter()));
+    assert!(par_intersection(&evens, &empty).iter().next().is_none());
+}
+
+#[test]
+fn parallel_set_operations_handle_degenerate_trees() {
+    let ascending = (0..5000u32).fold(Tree::empty(), |tree, i| tree.insert(i));
+    let descending = (0..5000u32).rev().fold(Tree::empty(), |tree, i| tree.insert(i * 2));
+
+    let union = par_union(&ascending, &descending);
+    assert!(union.iter().map(|(key, _)| *key).collect::<Vec<_>>() ==
+            (0..5000).chain((2500..5000).map(|i| i * 2)).collect::<Vec<_>>());
+    let intersection = par_intersection(&descending, &ascending);
+    assert!(intersection.iter().map(|(key, _)| *key).collect::<Vec<_>>() ==
+            (0..2500).map(|i| i * 2).collect::<Vec<_>>());
+}
+
+#[test]
+fn parallel_mapped_values_match_sequential_ones() {
+    let map = (0..20000u64).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let mapped = map.par_map_values(|value| value * value);
+    assert!(mapped == map.map_values(|value| value * value));
+    assert!(mapped.iter().zip(map.iter()).all(|(mapped, original)| mapped.0 == original.0));
+
+    let tree = (0..2000u64).fold(Tree::empty_map(), |tree, i| tree.bind(i, i));
+    let mapped = tree.par_map_values(|value| value.to_string());
+    assert!(mapped.iter().map(|(key, value)| (*key, value.clone())).collect::<Vec<_>>() ==
+            (0..2000u64).map(|i| (i, i.to_string())).collect::<Vec<_>>());
+    assert!(height(&mapped) == 11);
+    assert!(Tree::<u32, u32>::empty_map().par_map_values(|value| *value).iter().next().is_none());
+}
+
+#[test]
+fn parallel_checks_and_comparisons_match_sequential_ones() {
+    let tree = par_bulk_load((0..100000u32).map(|i| (i, i)).collect());
+    let rebuilt = par_bulk_load((0..100000u32).rev().map(|i| (i, i)).collect());
+    let shuffled = (0..2000u32).map(|i| (i * 7919) % 2000)
+                               .fold(Tree::empty_map(), |tree, i| tree.bind(i, i));
+    assert!(tree.par_check_invariants().is_ok() && shuffled.par_check_invariants().is_ok());
+    assert!(tree.par_eq(&rebuilt) && tree.par_eq(&tree.bind(5, 5)) && !tree.par_eq(&shuffled));
+    assert!(!tree.par_eq(&tree.bind(99999, 0)) && !tree.par_eq(&tree.bind(100000, 0)));
+    assert!(shuffled.par_eq(&par_bulk_load((0..2000u32).map(|i| (i, i)).collect())));
+
+    let misplaced = Tree::Node {
+        left: Shared::new(tree.clone()),
+        key: 50000,
+        value: 0,
+        right: Shared::new(Tree::Empty),
+    };
+    assert!(misplaced.par_check_invariants() == misplaced.check_invariants());
+    assert!(misplaced.par_check_invariants().is_err());
+
+    let map = (0..100000u64).fold(HamtMap::new(), |map, i| map.insert(i, i));
+    let rebuilt = (0..100000u64).rev().fold(HamtMap::new(), |map, i| map.insert(i, i));
+    assert!(map.par_check_invariants().is_ok() && map.par_eq(&rebuilt));
+    assert!(!map.par_eq(&rebuilt.insert(777, 0)) && !map.par_eq(&rebuilt.remove(&777)));
+}
+
+#[test]
+fn parallel_maps_of_sequences_keep_order() {
+    let seq = (0..100000u64).fold(Seq::empty(), |seq, i| seq.push_back(i));
+    let split = seq.split_at(12345).1.concat(&seq.split_at(777).0);
+    for seq in &[seq, split] {
+        let mapped = seq.par_map(|value| value * 3);
+        assert!(mapped.size() == seq.size());
+        assert!(mapped.iter().eq(seq.map(|value| value * 3).iter()));
+    }
+    assert!(Seq::<u64>::empty().par_map(|value| *value).is_empty());
+}
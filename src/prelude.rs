@@ -0,0 +1,23 @@
+//! The traits most programs need in scope to use the structures in this
+//! crate: `Stack`, `Set`, `Map`, their persistent counterparts and the
+//! collection traits.
+//!
+//! # Examples
+//!
+//! ```
+//! use functional_datastructures::prelude::*;
+//! use functional_datastructures::{CustomStack, Tree};
+//!
+//! let map: Tree<u32, &str> = Tree::empty_map().bind(1, "one").bind(2, "two");
+//! let stack: CustomStack<u32> = CustomStack::empty().cons(1);
+//!
+//! assert!(map.lookup(2) == Some("two") && map.length() == 2);
+//! assert!(stack.head().unwrap() == 1 && stack.fold(0, |sum, &i| sum + i) == 1);
+//! ```
+
+// Only traits belong here. The structures themselves are re-exported at the
+// root of the crate, and are named rather than glob imported.
+
+pub use collection::{Collection, Foldable, MapElems, Traverse};
+pub use set::{Map, PersistentMap, PersistentSet, Set};
+pub use stack::Stack;
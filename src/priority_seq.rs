@@ -0,0 +1,365 @@
+use std::cmp::Ordering;
+
+use collection::Collection;
+use measure::{Max, Measured};
+use memory::{Census, MemoryUsage};
+use ptr::{DefaultPointer, SharedPointer};
+
+use finger_tree::{self, FingerTreeIn};
+use stack::CustomStack;
+
+/// An immutable sequence which can also find and remove its greatest element,
+/// implemented as a finger tree measured by the greatest element.
+///
+/// Elements keep the order they were added in, so a `PrioritySeq` is a FIFO
+/// queue with O(1) amortized `push_back` and `pop_front`. Since every
+/// subtree knows its greatest element, `max` takes O(log n) time to find
+/// it, and `pop_max` removes it in O(log n) time while leaving the rest of
+/// the sequence in order. Where several elements are greatest, the one
+/// nearest the front is taken first.
+///
+/// This is the max-priority queue of Hinze and Paterson's "Finger trees: a
+/// simple general-purpose data structure".
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::priority_seq::PrioritySeq;
+///
+/// let seq = PrioritySeq::empty().push_back(3).push_back(7).push_back(1).push_back(7);
+/// let (max, rest) = seq.pop_max().unwrap();
+///
+/// assert!(max == 7);
+/// assert!(*seq.front().unwrap() == 3);
+/// assert!(rest.iter().cloned().collect::<Vec<_>>() == vec![3, 1, 7]);
+/// assert!(*rest.max().unwrap() == 7);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PrioritySeqIn<T: Ord + Clone, P: SharedPointer> {
+    tree: FingerTreeIn<Elem<T>, P>,
+}
+
+/// A `PrioritySeqIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type PrioritySeq<T> = PrioritySeqIn<T, DefaultPointer>;
+
+/// An element of a `PrioritySeq`, measured by itself.
+#[derive(Debug, Clone)]
+struct Elem<T>(T);
+
+impl<T: Ord + Clone> Measured for Elem<T> {
+    type Measure = Max<T>;
+    fn measure(&self) -> Max<T> {
+        Max(Some(self.0.clone()))
+    }
+}
+
+impl<T: Ord + Clone, P: SharedPointer> PrioritySeqIn<T, P> {
+    /// Returns an empty sequence.
+    pub fn empty() -> Self {
+        PrioritySeqIn { tree: FingerTreeIn::empty() }
+    }
+    /// Tests whether this sequence is empty.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    /// Returns a copy of this sequence with `value` added at the back.
+    pub fn push_back(&self, value: T) -> Self {
+        PrioritySeqIn { tree: self.tree.push_back(Elem(value)) }
+    }
+    /// Returns the element at the front of this sequence, or `None` if it is
+    /// empty.
+    pub fn front(&self) -> Option<&T> {
+        self.tree.front().map(|elem| &elem.0)
+    }
+    /// Returns the element at the front of this sequence and the sequence
+    /// without it, or `None` if it is empty.
+    pub fn pop_front(&self) -> Option<(T, Self)> {
+        self.tree.pop_front().map(|(elem, tree)| (elem.0, PrioritySeqIn { tree: tree }))
+    }
+    /// Returns the greatest element of this sequence, or `None` if it is
+    /// empty.
+    pub fn max(&self) -> Option<&T> {
+        let greatest = self.tree.measure();
+        self.tree.find(|prefix| prefix.0 >= greatest.0).map(|elem| &elem.0)
+    }
+    /// Returns the greatest element of this sequence and the sequence without
+    /// it, or `None` if it is empty.
+    pub fn pop_max(&self) -> Option<(T, Self)> {
+        let greatest = self.tree.measure();
+        let (before, after) = self.tree.split(|prefix| prefix.0 >= greatest.0);
+        after.pop_front()
+             .map(|(elem, after)| (elem.0, PrioritySeqIn { tree: before.concat(&after) }))
+    }
+    /// Tests whether `value` is an element of this sequence.
+    ///
+    /// Subtrees whose greatest element is less than `value` are skipped
+    /// without being visited, so this takes O(log n) time for each element
+    /// greater than `value` which comes before it, rather than walking the
+    /// whole sequence.
+    pub fn contains(&self, value: &T) -> bool {
+        let target = Some(value.clone());
+        let mut rest = self.tree.clone();
+        loop {
+            let (_, after) = rest.split(|prefix| prefix.0 >= target);
+            match after.pop_front() {
+                None => return false,
+                Some((ref elem, _)) if elem.0 == *value => return true,
+                Some((_, after)) => rest = after,
+            }
+        }
+    }
+    /// Returns a copy of this sequence without the element equal to `value`
+    /// nearest its front, leaving the others in order. If there is none, the
+    /// copy is unchanged.
+    ///
+    /// This finds the element as `contains` does, and takes O(log n) time
+    /// for each element greater than `value` which comes before it.
+    pub fn delete(&self, value: &T) -> Self {
+        let target = Some(value.clone());
+        let mut kept = FingerTreeIn::empty();
+        let mut rest = self.tree.clone();
+        loop {
+            let (before, after) = rest.split(|prefix| prefix.0 >= target);
+            let (elem, after) = match after.pop_front() {
+                None => return self.clone(),
+                Some(found) => found,
+            };
+            kept = kept.concat(&before);
+            if elem.0 == *value {
+                return PrioritySeqIn { tree: kept.concat(&after) };
+            }
+            kept = kept.push_back(elem);
+            rest = after;
+        }
+    }
+    /// Returns a sequence of the elements of this sequence followed by those
+    /// of `other`.
+    pub fn concat(&self, other: &Self) -> Self {
+        PrioritySeqIn { tree: self.tree.concat(&other.tree) }
+    }
+    /// Returns an iterator over the elements of this sequence, from front to
+    /// back.
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter { iter: self.tree.iter() }
+    }
+}
+
+impl<T: Ord + Clone, P: SharedPointer> Collection for PrioritySeqIn<T, P> {
+    type Iter<'a> = Iter<'a, T, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        // The measure kept is the greatest item, not the count, so this takes
+        // linear time.
+        self.iter().count()
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T: Ord + Clone, P: SharedPointer> MemoryUsage for PrioritySeqIn<T, P> {
+    fn census(&self, census: &mut Census) {
+        self.tree.census(census)
+    }
+}
+
+/// An iterator over the elements of a `PrioritySeq`, from front to back.
+pub struct Iter<'a, T: Ord + Clone + 'a, P: SharedPointer> {
+    iter: finger_tree::Iter<'a, Elem<T>, P>,
+}
+
+impl<'a, T: Ord + Clone, P: SharedPointer> Iterator for Iter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.iter.next().map(|elem| &elem.0)
+    }
+}
+
+/// Returns a stack of `values` in ascending order, with equal elements kept in
+/// the order they were given.
+///
+/// This is `sort_by` with the natural order of `T`.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::priority_seq::heap_sort;
+///
+/// let sorted = heap_sort(vec![5, 1, 4, 1, 3]);
+///
+/// assert!(sorted.iter().cloned().collect::<Vec<_>>() == vec![1, 1, 3, 4, 5]);
+/// ```
+pub fn heap_sort<T, I>(values: I) -> CustomStack<T>
+    where T: Ord + Clone,
+          I: IntoIterator<Item = T>
+{
+    sort_by(values, T::cmp)
+}
+
+/// Returns a stack of `values` in ascending order by `compare`, with elements
+/// it finds equal kept in the order they were given.
+///
+/// The values are added to a `PrioritySeq` and its greatest element is taken
+/// until it is empty, consing each onto the stack, so this takes O(n log n)
+/// time. Each value is tagged with its position, which breaks ties so that
+/// the sort is stable.
+pub fn sort_by<T, I, F>(values: I, compare: F) -> CustomStack<T>
+    where T: Clone,
+          I: IntoIterator<Item = T>,
+          F: Fn(&T, &T) -> Ordering
+{
+    let mut seq = values.into_iter()
+                        .enumerate()
+                        .map(|(index, value)| {
+                            Keyed { value: value, index: index, compare: &compare }
+                        })
+                        .fold(PrioritySeq::empty(), |seq, keyed| seq.push_back(keyed));
+    let mut sorted = CustomStack::Empty;
+    while let Some((greatest, rest)) = seq.pop_max() {
+        sorted = sorted.cons(greatest.value);
+        seq = rest;
+    }
+    sorted
+}
+
+/// A value being sorted by `sort_by`, ordered by its comparator and then by
+/// its position in the input.
+struct Keyed<'a, T, F: 'a> {
+    value: T,
+    index: usize,
+    compare: &'a F,
+}
+
+impl<'a, T: Clone, F> Clone for Keyed<'a, T, F> {
+    fn clone(&self) -> Self {
+        Keyed { value: self.value.clone(), index: self.index, compare: self.compare }
+    }
+}
+
+impl<'a, T, F> PartialEq for Keyed<'a, T, F>
+    where F: Fn(&T, &T) -> Ordering
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a, T, F> Eq for Keyed<'a, T, F> where F: Fn(&T, &T) -> Ordering {}
+
+impl<'a, T, F> PartialOrd for Keyed<'a, T, F>
+    where F: Fn(&T, &T) -> Ordering
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, T, F> Ord for Keyed<'a, T, F>
+    where F: Fn(&T, &T) -> Ordering
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.compare)(&self.value, &other.value).then(self.index.cmp(&other.index))
+    }
+}
+
+#[test]
+fn empty_sequence_has_no_max() {
+    let seq: PrioritySeq<u32> = PrioritySeq::empty();
+
+    assert!(seq.is_empty());
+    assert!(seq.max().is_none());
+    assert!(seq.pop_max().is_none());
+    assert!(seq.pop_front().is_none());
+}
+
+#[test]
+fn pop_max_drains_in_descending_order() {
+    let values: Vec<u32> = (0..300).map(|i| (i * 7919) % 1000).collect();
+    let seq = values.iter().fold(PrioritySeq::empty(), |seq, v| seq.push_back(*v));
+
+    let mut drained = Vec::new();
+    let mut rest = seq;
+    while let Some((max, remaining)) = rest.pop_max() {
+        assert!(rest.max() == Some(&max));
+        drained.push(max);
+        rest = remaining;
+    }
+    let mut expected = values;
+    expected.sort_by(|a, b| b.cmp(a));
+    assert!(drained == expected);
+}
+
+#[test]
+fn pop_max_keeps_the_rest_in_order() {
+    let seq = [4, 9, 2, 9, 5].iter().fold(PrioritySeq::empty(), |seq, v| seq.push_back(*v));
+
+    let (first, rest) = seq.pop_max().unwrap();
+    assert!(first == 9);
+    assert!(rest.iter().cloned().collect::<Vec<_>>() == vec![4, 2, 9, 5]);
+
+    let (second, rest) = rest.pop_max().unwrap();
+    assert!(second == 9);
+    assert!(rest.iter().cloned().collect::<Vec<_>>() == vec![4, 2, 5]);
+
+    let (front, rest) = rest.pop_front().unwrap();
+    assert!(front == 4);
+    assert!(*rest.max().unwrap() == 5);
+}
+
+#[test]
+fn works_as_a_fifo_queue() {
+    let seq = (0..100).fold(PrioritySeq::empty(), |seq, i| seq.push_back(i));
+    let joined = seq.concat(&PrioritySeq::empty().push_back(1000));
+
+    assert!(*joined.max().unwrap() == 1000);
+    let mut rest = seq;
+    for i in 0..100 {
+        let (front, remaining) = rest.pop_front().unwrap();
+        assert!(front == i);
+        rest = remaining;
+    }
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn elements_can_be_found_and_deleted() {
+    let seq = [5, 9, 2, 9, 4, 7, 2].iter().fold(PrioritySeq::empty(), |seq, &i| seq.push_back(i));
+    let elements = |seq: &PrioritySeq<u32>| seq.iter().cloned().collect::<Vec<_>>();
+
+    assert!([2, 4, 5, 7, 9].iter().all(|i| seq.contains(i)));
+    assert!(![0, 3, 8, 10].iter().any(|i| seq.contains(i)));
+    assert!(elements(&seq.delete(&2)) == vec![5, 9, 9, 4, 7, 2]);
+    assert!(elements(&seq.delete(&4)) == vec![5, 9, 2, 9, 7, 2]);
+    assert!(elements(&seq.delete(&9).delete(&9)) == vec![5, 2, 4, 7, 2]);
+    assert!(elements(&seq.delete(&3)) == elements(&seq));
+    assert!(*seq.delete(&9).delete(&9).max().unwrap() == 7);
+    assert!(!PrioritySeq::empty().contains(&1) && PrioritySeq::empty().delete(&1).is_empty());
+}
+
+#[test]
+fn heap_sort_orders_duplicates_and_sorted_input() {
+    let elements = |stack: CustomStack<u32>| stack.iter().cloned().collect::<Vec<_>>();
+    let values: Vec<u32> = (0..500).map(|i| (i * 7919) % 100).collect();
+    let mut expected = values.clone();
+    expected.sort();
+
+    assert!(elements(heap_sort(values)) == expected);
+    assert!(elements(heap_sort(expected.clone())) == expected);
+    assert!(elements(heap_sort(expected.iter().rev().cloned())) == expected);
+    assert!(heap_sort(Vec::<u32>::new()).is_empty());
+}
+
+#[test]
+fn sort_by_keeps_equal_elements_in_input_order() {
+    let values: Vec<(u32, u32)> = (0..300).map(|i| ((i * 31) % 7, i)).collect();
+    let sorted = sort_by(values.clone(), |a, b| a.0.cmp(&b.0));
+    let descending = sort_by(values.clone(), |a, b| b.0.cmp(&a.0));
+
+    let mut expected = values;
+    expected.sort_by_key(|pair| pair.0);
+    assert!(sorted.iter().cloned().collect::<Vec<_>>() == expected);
+    expected.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    assert!(descending.iter().cloned().collect::<Vec<_>>() == expected);
+}
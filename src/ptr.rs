@@ -0,0 +1,334 @@
+// The pointer layer of the structures in this crate: the reference-counted
+// pointers their nodes are shared through, and the bounds which go with them.
+//
+// Every structure takes the kind of pointer as a type parameter, `P:
+// SharedPointer`, and is written once against `Shared<T, P>`. There are two
+// kinds: `ArcPointer`, whose structures are `Send` and `Sync` whenever their
+// contents are, and `RcPointer`, which avoids the cost of atomic reference
+// counting but keeps its structures on one thread.
+//
+// The structures exported at the root of the crate use `DefaultPointer`,
+// which is `ArcPointer`. Those in `local` use `RcPointer`, so that a program
+// can keep the shared flavor for what crosses threads and use the local one
+// everywhere else, or throughout on a single-threaded target.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// A kind of reference-counted pointer which the nodes of a structure can
+/// be shared through: `ArcPointer` or `RcPointer`.
+///
+/// Every structure in this crate takes its kind of pointer as a type
+/// parameter, so that code generic over `P: SharedPointer` works with both
+/// flavors. The kinds are plain markers; the pointers themselves are
+/// `Shared<T, P>`. This trait is sealed.
+pub trait SharedPointer: sealed::Thunks + Debug + Clone + Copy + Default + Eq + Ord + Hash
+                         + 'static {
+    /// The pointer to a `T` of this kind.
+    type Pointer<T: ?Sized>: Deref<Target = T> + Clone;
+
+    /// Moves `value` behind a new pointer.
+    fn new<T>(value: T) -> Self::Pointer<T>;
+    /// Tests whether `a` and `b` point to the same allocation.
+    fn ptr_eq<T: ?Sized>(a: &Self::Pointer<T>, b: &Self::Pointer<T>) -> bool;
+    /// Returns a mutable reference to the value behind `pointer`, if no
+    /// other pointer shares it.
+    fn get_mut<T: ?Sized>(pointer: &mut Self::Pointer<T>) -> Option<&mut T>;
+    /// Returns a mutable reference to the value behind `pointer`, first
+    /// cloning it into a pointer of its own if it is shared.
+    fn make_mut<T: Clone>(pointer: &mut Self::Pointer<T>) -> &mut T;
+    /// Returns the value behind `pointer` if no other pointer shares it, or
+    /// `pointer` itself otherwise.
+    fn try_unwrap<T>(pointer: Self::Pointer<T>) -> Result<T, Self::Pointer<T>>;
+    /// Returns the number of pointers sharing the value behind `pointer`.
+    fn strong_count<T: ?Sized>(pointer: &Self::Pointer<T>) -> usize;
+}
+
+/// The kind of `std::sync::Arc`, whose structures are `Send` and `Sync`
+/// whenever their contents are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ArcPointer;
+
+/// The kind of `std::rc::Rc`, whose structures pay nothing for atomic
+/// reference counting but cannot leave the thread they were built on.
+///
+/// # Examples
+///
+/// Structures of the local flavor cannot be sent to other threads:
+///
+/// ```compile_fail
+/// use functional_datastructures::local::set::{Set, Tree};
+/// use std::thread;
+///
+/// let tree: Tree<u32, ()> = Tree::empty().insert(1);
+/// thread::spawn(move || tree.member(1));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RcPointer;
+
+/// The kind of pointer the structures at the root of the crate are shared
+/// through. Those in `local` are shared through `RcPointer` instead.
+pub type DefaultPointer = ArcPointer;
+
+impl SharedPointer for ArcPointer {
+    type Pointer<T: ?Sized> = Arc<T>;
+
+    fn new<T>(value: T) -> Arc<T> {
+        Arc::new(value)
+    }
+    fn ptr_eq<T: ?Sized>(a: &Arc<T>, b: &Arc<T>) -> bool {
+        Arc::ptr_eq(a, b)
+    }
+    fn get_mut<T: ?Sized>(pointer: &mut Arc<T>) -> Option<&mut T> {
+        Arc::get_mut(pointer)
+    }
+    fn make_mut<T: Clone>(pointer: &mut Arc<T>) -> &mut T {
+        Arc::make_mut(pointer)
+    }
+    fn try_unwrap<T>(pointer: Arc<T>) -> Result<T, Arc<T>> {
+        Arc::try_unwrap(pointer)
+    }
+    fn strong_count<T: ?Sized>(pointer: &Arc<T>) -> usize {
+        Arc::strong_count(pointer)
+    }
+}
+
+impl SharedPointer for RcPointer {
+    type Pointer<T: ?Sized> = Rc<T>;
+
+    fn new<T>(value: T) -> Rc<T> {
+        Rc::new(value)
+    }
+    fn ptr_eq<T: ?Sized>(a: &Rc<T>, b: &Rc<T>) -> bool {
+        Rc::ptr_eq(a, b)
+    }
+    fn get_mut<T: ?Sized>(pointer: &mut Rc<T>) -> Option<&mut T> {
+        Rc::get_mut(pointer)
+    }
+    fn make_mut<T: Clone>(pointer: &mut Rc<T>) -> &mut T {
+        Rc::make_mut(pointer)
+    }
+    fn try_unwrap<T>(pointer: Rc<T>) -> Result<T, Rc<T>> {
+        Rc::try_unwrap(pointer)
+    }
+    fn strong_count<T: ?Sized>(pointer: &Rc<T>) -> usize {
+        Rc::strong_count(pointer)
+    }
+}
+
+/// A reference-counted pointer of kind `P`: an `Arc<T>` for `ArcPointer`
+/// and an `Rc<T>` for `RcPointer`. Use this when building a `CustomStack`
+/// or `Tree` from its variants.
+///
+/// Like `Arc` and `Rc`, it dereferences to the value it points to and its
+/// operations are associated functions, such as `Shared::ptr_eq(&a, &b)`,
+/// so that they never shadow methods of the value.
+pub struct Shared<T: ?Sized, P: SharedPointer> {
+    pointer: P::Pointer<T>,
+}
+
+impl<T, P: SharedPointer> Shared<T, P> {
+    /// Moves `value` behind a new pointer.
+    pub fn new(value: T) -> Self {
+        Shared { pointer: P::new(value) }
+    }
+    /// Returns the value behind `this` if no other pointer shares it, or
+    /// `this` itself otherwise.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        P::try_unwrap(this.pointer).map_err(|pointer| Shared { pointer: pointer })
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Shared<T, P> {
+    /// Returns a mutable reference to the value behind `this`, first
+    /// cloning it into a pointer of its own if it is shared.
+    pub fn make_mut(this: &mut Self) -> &mut T {
+        P::make_mut(&mut this.pointer)
+    }
+}
+
+impl<T: ?Sized, P: SharedPointer> Shared<T, P> {
+    /// Tests whether `a` and `b` point to the same allocation.
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        P::ptr_eq(&a.pointer, &b.pointer)
+    }
+    /// Returns a mutable reference to the value behind `this`, if no other
+    /// pointer shares it.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        P::get_mut(&mut this.pointer)
+    }
+    /// Returns the number of pointers sharing the value behind `this`.
+    pub fn strong_count(this: &Self) -> usize {
+        P::strong_count(&this.pointer)
+    }
+}
+
+impl<T: ?Sized, P: SharedPointer> Clone for Shared<T, P> {
+    fn clone(&self) -> Self {
+        Shared { pointer: self.pointer.clone() }
+    }
+}
+
+impl<T: ?Sized, P: SharedPointer> Deref for Shared<T, P> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.pointer
+    }
+}
+
+impl<T: ?Sized, P: SharedPointer> AsRef<T> for Shared<T, P> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized, P: SharedPointer> Borrow<T> for Shared<T, P> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Default, P: SharedPointer> Default for Shared<T, P> {
+    fn default() -> Self {
+        Shared::new(T::default())
+    }
+}
+
+impl<T: Debug + ?Sized, P: SharedPointer> Debug for Shared<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: Display + ?Sized, P: SharedPointer> Display for Shared<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<T: PartialEq + ?Sized, P: SharedPointer> PartialEq for Shared<T, P> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Eq + ?Sized, P: SharedPointer> Eq for Shared<T, P> {}
+
+impl<T: PartialOrd + ?Sized, P: SharedPointer> PartialOrd for Shared<T, P> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: Ord + ?Sized, P: SharedPointer> Ord for Shared<T, P> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T: Hash + ?Sized, P: SharedPointer> Hash for Shared<T, P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+/// `Send` for `ArcPointer`, and implemented by every type for
+/// `RcPointer`, whose structures never leave their thread. Used in place of
+/// `Send` in the bounds of lazily evaluated structures, whose suspensions
+/// hold closures which capture nodes.
+pub trait SendFor<P: SharedPointer> {}
+impl<T: Send + ?Sized> SendFor<ArcPointer> for T {}
+impl<T: ?Sized> SendFor<RcPointer> for T {}
+
+/// `Sync` for `ArcPointer`, and implemented by every type for `RcPointer`.
+/// See `SendFor`.
+pub trait SyncFor<P: SharedPointer> {}
+impl<T: Sync + ?Sized> SyncFor<ArcPointer> for T {}
+impl<T: ?Sized> SyncFor<RcPointer> for T {}
+
+pub(crate) mod sealed {
+    use super::{ArcPointer, RcPointer};
+
+    /// Seals `SharedPointer`, and gives each kind of pointer the type of
+    /// the boxed computation held by an unevaluated suspension, which must
+    /// be `Send` for an `ArcPointer` suspension to be.
+    pub trait Thunks {
+        /// The boxed computation held by an unevaluated suspension.
+        type Thunk<T>: FnOnce() -> T;
+
+        /// Boxes `thunk` as a suspended computation.
+        ///
+        /// # Safety
+        ///
+        /// `thunk` must be `SendFor<Self>`. This holds of any closure which
+        /// only captures values which are, but the compiler cannot see it
+        /// for closures built in code generic over the kind of pointer.
+        unsafe fn thunk<T, F>(thunk: F) -> Self::Thunk<T> where F: FnOnce() -> T + 'static;
+    }
+
+    impl Thunks for ArcPointer {
+        type Thunk<T> = Box<dyn FnOnce() -> T + Send>;
+
+        unsafe fn thunk<T, F>(thunk: F) -> Self::Thunk<T>
+            where F: FnOnce() -> T + 'static
+        {
+            let thunk: Box<dyn FnOnce() -> T> = Box::new(thunk);
+            // SAFETY: the caller promises that `thunk` is `Send`, and a
+            // boxed trait object has the same layout whatever auto traits
+            // it is marked with.
+            unsafe { ::std::mem::transmute::<Box<dyn FnOnce() -> T>, Self::Thunk<T>>(thunk) }
+        }
+    }
+
+    impl Thunks for RcPointer {
+        type Thunk<T> = Box<dyn FnOnce() -> T>;
+
+        unsafe fn thunk<T, F>(thunk: F) -> Self::Thunk<T>
+            where F: FnOnce() -> T + 'static
+        {
+            Box::new(thunk)
+        }
+    }
+}
+
+/// Implemented by every `Send` type. Used in place of `Send` in the bounds of
+/// the parallel operations of the structures at the root of the crate.
+pub trait MaybeSend: Send {}
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// Implemented by every `Sync` type. See `MaybeSend`.
+pub trait MaybeSync: Sync {}
+impl<T: Sync + ?Sized> MaybeSync for T {}
+
+/// Runs `a` and `b` and returns their results. With the `rayon` feature the
+/// two may run in parallel on rayon's thread pool; otherwise they run one
+/// after the other on the current thread. Used by the structures' parallel
+/// operations to fan out across subtrees.
+#[cfg(feature = "rayon")]
+pub fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+    where A: FnOnce() -> RA + MaybeSend,
+          B: FnOnce() -> RB + MaybeSend,
+          RA: MaybeSend,
+          RB: MaybeSend
+{
+    ::rayon::join(a, b)
+}
+/// Runs `a` and `b` and returns their results. With the `rayon` feature the
+/// two may run in parallel on rayon's thread pool; otherwise they run one
+/// after the other on the current thread. Used by the structures' parallel
+/// operations to fan out across subtrees.
+#[cfg(not(feature = "rayon"))]
+pub fn join<A, B, RA, RB>(a: A, b: B) -> (RA, RB)
+    where A: FnOnce() -> RA + MaybeSend,
+          B: FnOnce() -> RB + MaybeSend,
+          RA: MaybeSend,
+          RB: MaybeSend
+{
+    (a(), b())
+}
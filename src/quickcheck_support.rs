@@ -0,0 +1,500 @@
+// Implementations of `quickcheck::Arbitrary`, available with the
+// `quickcheck` feature.
+//
+// Each structure is generated from an arbitrary sequence of the values it
+// holds, inserted one at a time through its public interface, and shrinks by
+// shrinking that sequence and building the structure again. Every value a
+// property sees, shrunk or not, is therefore one the structure could really
+// reach, and no invariant has to be restored by hand.
+//
+// `Tree` is the exception to building from sorted contents, since its shape
+// depends on the order of insertion: it is taken apart in pre-order, which
+// builds the same tree again, so a shrunk tree keeps the shape of the
+// original with some nodes removed.
+
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash};
+
+use quickcheck::{Arbitrary, Gen};
+
+use art::ArtMap;
+use finger_tree::FingerTree;
+use hamt::{HamtMap, HamtSet};
+use interval_map::{Interval, IntervalMap};
+use intmap::{IntMap, IntSet};
+use measure::{Count, Max, Measured, Min, Monoid, Sum};
+use multimap::Multimap;
+use multiset::Multiset;
+use ord_seq::{OrdSeqMap, OrdSeqSet};
+use priority_seq::PrioritySeq;
+use ptr::Shared;
+use range_map::RangeMap;
+use rope::Rope;
+use segment_tree::SegmentTree;
+use seq::Seq;
+use set::{Map, Set, Tree};
+use skip_list::SkipListMap;
+use sorted_list::SortedList;
+use stack::CustomStack;
+use trie::TrieMap;
+
+/// Returns the structures built by `build` from each of the shrinks of
+/// `contents`.
+fn shrink_by<C, S>(contents: C, build: fn(C) -> S) -> Box<dyn Iterator<Item = S>>
+    where C: Arbitrary,
+          S: 'static
+{
+    Box::new(contents.shrink().map(build))
+}
+
+fn stack_of<T: Clone>(values: Vec<T>) -> CustomStack<T> {
+    values.into_iter().rev().fold(CustomStack::Empty, |tail, value| {
+        CustomStack::Cons {
+            value: value,
+            tail: Shared::new(tail),
+        }
+    })
+}
+
+impl<T: Arbitrary> Arbitrary for CustomStack<T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        stack_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let mut values = Vec::new();
+        let mut rest = self;
+        while let CustomStack::Cons { ref value, ref tail } = *rest {
+            values.push(value.clone());
+            rest = tail;
+        }
+        shrink_by(values, stack_of)
+    }
+}
+
+fn tree_of<K: Ord + Clone + Debug, V: Clone + Debug>(bindings: Vec<(K, V)>) -> Tree<K, V> {
+    bindings.into_iter().fold(Tree::empty_map(), |tree, (key, value)| tree.bind(key, value))
+}
+
+/// Pushes the bindings in `tree` onto `out` in pre-order, the order which
+/// builds the same tree again when they are bound one at a time.
+fn pre_order<K: Ord + Clone, V: Clone>(tree: &Tree<K, V>, out: &mut Vec<(K, V)>) {
+    if let Tree::Node { ref left, ref key, ref value, ref right } = *tree {
+        out.push((key.clone(), value.clone()));
+        pre_order(left, out);
+        pre_order(right, out);
+    }
+}
+
+impl<K, V> Arbitrary for Tree<K, V>
+    where K: Arbitrary + Ord + Debug,
+          V: Arbitrary + Debug
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        tree_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let mut bindings = Vec::new();
+        pre_order(self, &mut bindings);
+        shrink_by(bindings, tree_of)
+    }
+}
+
+fn hamt_of<K, V, S>(bindings: Vec<(K, V)>) -> HamtMap<K, V, S>
+    where K: Hash + Eq + Clone,
+          V: Clone,
+          S: BuildHasher + Clone + Default
+{
+    bindings.into_iter().fold(HamtMap::default(), |map, (key, value)| map.insert(key, value))
+}
+
+impl<K, V, S> Arbitrary for HamtMap<K, V, S>
+    where K: Arbitrary + Hash + Eq,
+          V: Arbitrary,
+          S: BuildHasher + Clone + Default + 'static
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        hamt_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let bindings = self.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+        shrink_by(bindings, hamt_of)
+    }
+}
+
+fn hamt_set_of<T, S>(values: Vec<T>) -> HamtSet<T, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Clone + Default
+{
+    values.into_iter().fold(HamtSet::default(), |set, value| set.insert(value))
+}
+
+impl<T, S> Arbitrary for HamtSet<T, S>
+    where T: Arbitrary + Hash + Eq,
+          S: BuildHasher + Clone + Default + 'static
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        hamt_set_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_by(self.iter().cloned().collect(), hamt_set_of)
+    }
+}
+
+fn int_map_of<V: Clone>(bindings: Vec<(u64, V)>) -> IntMap<V> {
+    bindings.into_iter().fold(IntMap::empty_map(), |map, (key, value)| map.insert(key, value))
+}
+
+impl<V: Arbitrary> Arbitrary for IntMap<V> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        int_map_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let bindings = self.iter().map(|(key, value)| (key, value.clone())).collect();
+        shrink_by(bindings, int_map_of)
+    }
+}
+
+fn int_set_of(values: Vec<u64>) -> IntSet {
+    values.into_iter().fold(IntSet::empty(), |set, value| set.insert(value))
+}
+
+impl Arbitrary for IntSet {
+    fn arbitrary(g: &mut Gen) -> Self {
+        int_set_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_by(self.iter().collect(), int_set_of)
+    }
+}
+
+fn trie_of<V: Clone>(bindings: Vec<(Vec<u8>, V)>) -> TrieMap<V> {
+    bindings.into_iter().fold(TrieMap::empty(), |map, (key, value)| map.insert(key, value))
+}
+
+impl<V: Arbitrary> Arbitrary for TrieMap<V> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        trie_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let bindings = self.iter().map(|(key, value)| (key, value.clone())).collect();
+        shrink_by(bindings, trie_of)
+    }
+}
+
+fn art_of<V: Clone>(bindings: Vec<(Vec<u8>, V)>) -> ArtMap<V> {
+    bindings.into_iter().fold(ArtMap::empty(), |map, (key, value)| map.insert(key, value))
+}
+
+impl<V: Arbitrary> Arbitrary for ArtMap<V> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        art_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let bindings = self.iter().map(|(key, value)| (key.to_vec(), value.clone())).collect();
+        shrink_by(bindings, art_of)
+    }
+}
+
+fn finger_tree_of<T: Measured + Clone>(values: Vec<T>) -> FingerTree<T> {
+    values.into_iter().fold(FingerTree::empty(), |tree, value| tree.push_back(value))
+}
+
+impl<T: Arbitrary + Measured> Arbitrary for FingerTree<T>
+    where T::Measure: 'static
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        finger_tree_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_by(self.iter().cloned().collect(), finger_tree_of)
+    }
+}
+
+fn seq_of<T: Clone>(values: Vec<T>) -> Seq<T> {
+    values.into_iter().fold(Seq::empty(), |seq, value| seq.push_back(value))
+}
+
+impl<T: Arbitrary> Arbitrary for Seq<T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        seq_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_by(self.iter().cloned().collect(), seq_of)
+    }
+}
+
+fn ord_seq_map_of<K: Ord + Clone, V: Clone>(bindings: Vec<(K, V)>) -> OrdSeqMap<K, V> {
+    bindings.into_iter().fold(OrdSeqMap::empty_map(), |map, (key, value)| map.insert(key, value))
+}
+
+impl<K: Arbitrary + Ord, V: Arbitrary> Arbitrary for OrdSeqMap<K, V> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        ord_seq_map_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let bindings = self.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+        shrink_by(bindings, ord_seq_map_of)
+    }
+}
+
+fn ord_seq_set_of<T: Ord + Clone>(values: Vec<T>) -> OrdSeqSet<T> {
+    values.into_iter().fold(OrdSeqSet::empty(), |set, value| set.insert(value))
+}
+
+impl<T: Arbitrary + Ord> Arbitrary for OrdSeqSet<T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        ord_seq_set_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_by(self.iter().cloned().collect(), ord_seq_set_of)
+    }
+}
+
+fn priority_seq_of<T: Ord + Clone>(values: Vec<T>) -> PrioritySeq<T> {
+    values.into_iter().fold(PrioritySeq::empty(), |seq, value| seq.push_back(value))
+}
+
+impl<T: Arbitrary + Ord> Arbitrary for PrioritySeq<T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        priority_seq_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_by(self.iter().cloned().collect(), priority_seq_of)
+    }
+}
+
+fn skip_list_of<K: Ord + Clone, V: Clone>(bindings: Vec<(K, V)>) -> SkipListMap<K, V> {
+    bindings.into_iter()
+            .fold(SkipListMap::empty_map(), |map, (key, value)| map.insert(key, value))
+}
+
+impl<K: Arbitrary + Ord, V: Arbitrary> Arbitrary for SkipListMap<K, V> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        skip_list_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let bindings = self.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+        shrink_by(bindings, skip_list_of)
+    }
+}
+
+/// Builds an interval map from pairs of ends, in either order.
+fn interval_map_of<P: Ord + Clone, V: Clone>(bindings: Vec<((P, P), V)>) -> IntervalMap<P, V> {
+    bindings.into_iter().fold(IntervalMap::empty_map(), |map, ((a, b), value)| {
+        let interval = if a <= b { Interval::new(a, b) } else { Interval::new(b, a) };
+        map.insert(interval, value)
+    })
+}
+
+impl<P: Arbitrary + Ord, V: Arbitrary> Arbitrary for IntervalMap<P, V> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        interval_map_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let bindings = self.iter()
+                           .map(|(interval, value)| {
+                               ((interval.low.clone(), interval.high.clone()), value.clone())
+                           })
+                           .collect();
+        shrink_by(bindings, interval_map_of)
+    }
+}
+
+/// Builds a rope by joining the given pieces, so that ropes of the same text
+/// can have different shapes.
+fn rope_of(pieces: Vec<String>) -> Rope {
+    pieces.iter().fold(Rope::from(""), |rope, piece| rope.concat(&Rope::from(&**piece)))
+}
+
+impl Arbitrary for Rope {
+    fn arbitrary(g: &mut Gen) -> Self {
+        rope_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_by(self.chunks().map(String::from).collect(), rope_of)
+    }
+}
+
+fn multiset_of<T, S>(values: Vec<T>) -> Multiset<T, S>
+    where T: Hash + Eq + Clone,
+          S: BuildHasher + Clone + Default
+{
+    values.into_iter().fold(Multiset::default(), |multiset, value| multiset.insert(value))
+}
+
+impl<T, S> Arbitrary for Multiset<T, S>
+    where T: Arbitrary + Hash + Eq,
+          S: BuildHasher + Clone + Default + 'static
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        multiset_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let values = self.iter()
+                         .flat_map(|(value, count)| (0..count).map(move |_| value.clone()))
+                         .collect();
+        shrink_by(values, multiset_of)
+    }
+}
+
+fn multimap_of<K, V, S>(bindings: Vec<(K, V)>) -> Multimap<K, V, S>
+    where K: Hash + Eq + Clone,
+          V: Hash + Eq + Clone,
+          S: BuildHasher + Clone + Default
+{
+    bindings.into_iter().fold(Multimap::default(), |map, (key, value)| map.insert(key, value))
+}
+
+impl<K, V, S> Arbitrary for Multimap<K, V, S>
+    where K: Arbitrary + Hash + Eq,
+          V: Arbitrary + Hash + Eq,
+          S: BuildHasher + Clone + Default + 'static
+{
+    fn arbitrary(g: &mut Gen) -> Self {
+        multimap_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let bindings = self.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+        shrink_by(bindings, multimap_of)
+    }
+}
+
+/// Builds a range map from pairs of ends, in either order.
+fn range_map_of<K: Ord + Clone, V: Clone + PartialEq>(bindings: Vec<((K, K), V)>)
+                                                     -> RangeMap<K, V> {
+    bindings.into_iter().fold(RangeMap::empty(), |map, ((a, b), value)| {
+        let range = if a <= b { a..b } else { b..a };
+        map.insert(range, value)
+    })
+}
+
+impl<K: Arbitrary + Ord, V: Arbitrary + PartialEq> Arbitrary for RangeMap<K, V> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        range_map_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let bindings = self.iter()
+                           .map(|(range, value)| {
+                               ((range.start.clone(), range.end.clone()), value.clone())
+                           })
+                           .collect();
+        shrink_by(bindings, range_map_of)
+    }
+}
+
+fn segment_tree_of<M: Monoid>(values: Vec<M>) -> SegmentTree<M> {
+    values.into_iter().collect()
+}
+
+impl<M: Arbitrary + Monoid> Arbitrary for SegmentTree<M> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        segment_tree_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let values = (0..self.size()).map(|i| self.get(i).unwrap().clone()).collect();
+        shrink_by(values, segment_tree_of)
+    }
+}
+
+fn sorted_list_of<T: Ord + Clone>(mut values: Vec<T>) -> SortedList<T> {
+    values.sort();
+    values.into_iter().rev().fold(SortedList::empty(), |list, value| list.insert(value))
+}
+
+impl<T: Arbitrary + Ord> Arbitrary for SortedList<T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        sorted_list_of(Vec::arbitrary(g))
+    }
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        shrink_by(self.iter().cloned().collect(), sorted_list_of)
+    }
+}
+
+// The monoids in `measure` are generated and shrunk as the value they wrap.
+macro_rules! transparent {
+    ($monoid:ident, $inner:ty $(, $param:ident)*) => {
+        impl<$($param: Arbitrary),*> Arbitrary for $monoid<$($param),*> {
+            fn arbitrary(g: &mut Gen) -> Self {
+                $monoid(<$inner>::arbitrary(g))
+            }
+            fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+                Box::new(self.0.shrink().map($monoid))
+            }
+        }
+    }
+}
+
+transparent!(Count, u32);
+transparent!(Sum, T, T);
+transparent!(Max, Option<T>, T);
+transparent!(Min, Option<T>, T);
+
+#[cfg(test)]
+use quickcheck::quickcheck;
+
+#[test]
+fn shrunk_trees_keep_their_shape() {
+    fn shape<K: Ord + Clone, V: Clone>(tree: &Tree<K, V>) -> String {
+        match *tree {
+            Tree::Empty => String::new(),
+            Tree::Node { ref left, ref right, .. } => {
+                format!("({}.{})", shape(left), shape(right))
+            }
+        }
+    }
+    fn prop(tree: Tree<u8, u8>) -> bool {
+        let mut bindings = Vec::new();
+        pre_order(&tree, &mut bindings);
+        let keys: Vec<_> = tree.iter().map(|(key, _)| *key).collect();
+        shape(&tree_of(bindings)) == shape(&tree) &&
+        keys.windows(2).all(|pair| pair[0] < pair[1]) &&
+        tree.shrink().all(|shrunk| {
+            let shrunk_keys: Vec<_> = shrunk.iter().map(|(key, _)| *key).collect();
+            shrunk_keys.windows(2).all(|pair| pair[0] < pair[1]) &&
+            shrunk_keys.len() <= keys.len()
+        })
+    }
+    quickcheck(prop as fn(Tree<u8, u8>) -> bool);
+}
+
+#[test]
+fn shrinks_are_valid_structures() {
+    fn sorted(list: SortedList<i8>) -> bool {
+        list.shrink().all(|shrunk| {
+            let values: Vec<_> = shrunk.iter().cloned().collect();
+            values.windows(2).all(|pair| pair[0] <= pair[1])
+        })
+    }
+    fn disjoint(map: RangeMap<u8, bool>) -> bool {
+        map.shrink().all(|shrunk| {
+            let ranges: Vec<_> = shrunk.iter().map(|(range, _)| range.clone()).collect();
+            ranges.iter().all(|range| range.start < range.end) &&
+            ranges.windows(2).all(|pair| pair[0].end <= pair[1].start)
+        })
+    }
+    fn maxima(tree: SegmentTree<Max<u8>>) -> bool {
+        tree.shrink().all(|shrunk| {
+            let max = (0..shrunk.size()).filter_map(|i| shrunk.get(i).unwrap().0).max();
+            shrunk.total().0 == max
+        })
+    }
+    quickcheck(sorted as fn(SortedList<i8>) -> bool);
+    quickcheck(disjoint as fn(RangeMap<u8, bool>) -> bool);
+    quickcheck(maxima as fn(SegmentTree<Max<u8>>) -> bool);
+}
+
+#[test]
+fn shrinking_removes_and_shrinks_contents() {
+    let seq = seq_of(vec![5u32, 6, 7]);
+    let shrunk: Vec<Vec<u32>> = seq.shrink().map(|seq| seq.iter().cloned().collect()).collect();
+    assert!(shrunk.contains(&vec![]));
+    assert!(shrunk.contains(&vec![6, 7]));
+    assert!(shrunk.contains(&vec![0, 6, 7]));
+    assert!(!shrunk.contains(&vec![5, 6, 7]));
+
+    let stack = stack_of(vec![3, 1, 2]);
+    let expected = format!("{:?}", stack_of(vec![1, 2]));
+    assert!(stack.shrink().any(|shrunk| format!("{:?}", shrunk) == expected));
+    let map: HamtMap<u8, u8> = HamtMap::new().insert(1, 2).insert(3, 4);
+    assert!(map.shrink().any(|shrunk| shrunk.size() == 1));
+}
@@ -0,0 +1,276 @@
+use std::ops::Range;
+
+use collection::Collection;
+use measure::{Max, Measured};
+use memory::{Census, MemoryUsage};
+use ptr::{DefaultPointer, SharedPointer};
+
+use finger_tree::{self, FingerTreeIn};
+
+/// An immutable map from disjoint half-open ranges of keys to values,
+/// implemented as a finger tree of ranges in increasing order, measured by
+/// the greatest end of a range.
+///
+/// Binding a range to a value replaces whatever the keys in it were bound to
+/// before, cutting down any ranges it overlaps, so a `RangeMap` binds each
+/// key to at most one value. Ranges which meet and are bound to equal values
+/// are coalesced into one, so the same bindings are always stored the same
+/// way. Insertions, removals and lookups of a single key take O(log n) time,
+/// and finding the k ranges which overlap a range takes O(k + log n) time.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::range_map::RangeMap;
+///
+/// let map = RangeMap::empty().insert(0..100, "reserved")
+///                            .insert(10..20, "alice")
+///                            .insert(20..30, "alice");
+///
+/// assert!(map.get(&15) == Some(&"alice"));
+/// assert!(map.get(&50) == Some(&"reserved"));
+/// assert!(map.get(&100).is_none());
+/// assert!(map.overlapping(&(25..35)) == vec![(10..30, "alice"), (30..100, "reserved")]);
+/// assert!(map.remove(0..100).is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RangeMapIn<K: Ord + Clone, V, P: SharedPointer> {
+    tree: FingerTreeIn<Entry<K, V>, P>,
+}
+
+/// A `RangeMapIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type RangeMap<K, V> = RangeMapIn<K, V, DefaultPointer>;
+
+/// A binding in a `RangeMap`, measured by the end of its range.
+#[derive(Debug, Clone)]
+struct Entry<K, V> {
+    range: Range<K>,
+    value: V,
+}
+
+/// The bindings of a `RangeMap`, in order of range.
+type Entries<K, V, P> = FingerTreeIn<Entry<K, V>, P>;
+
+impl<K: Ord + Clone, V> Measured for Entry<K, V> {
+    type Measure = Max<K>;
+    fn measure(&self) -> Max<K> {
+        Max(Some(self.range.end.clone()))
+    }
+}
+
+/// Tests whether the part of a map measured by `max_end` contains a range
+/// which ends after `key`.
+fn ends_after<K: Ord>(max_end: &Max<K>, key: &K) -> bool {
+    match max_end.0 {
+        Some(ref end) => end > key,
+        None => false,
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + PartialEq, P: SharedPointer> RangeMapIn<K, V, P> {
+    /// Returns an empty map.
+    pub fn empty() -> Self {
+        RangeMapIn { tree: FingerTreeIn::empty() }
+    }
+    /// Returns a copy of this map with the keys in `range` bound to `value`,
+    /// replacing any existing bindings for them. Inserting an empty range
+    /// leaves the map unchanged.
+    pub fn insert(&self, range: Range<K>, value: V) -> Self {
+        if range.start >= range.end {
+            return self.clone();
+        }
+        let (before, after) = self.cut(&range);
+        let mut entry = Entry {
+            range: range,
+            value: value,
+        };
+        let before = match before.pop_back() {
+            Some((ref last, ref rest)) if last.range.end == entry.range.start &&
+                                          last.value == entry.value => {
+                entry.range.start = last.range.start.clone();
+                rest.clone()
+            }
+            _ => before,
+        };
+        let after = match after.pop_front() {
+            Some((ref first, ref rest)) if first.range.start == entry.range.end &&
+                                           first.value == entry.value => {
+                entry.range.end = first.range.end.clone();
+                rest.clone()
+            }
+            _ => after,
+        };
+        RangeMapIn { tree: before.push_back(entry).concat(&after) }
+    }
+    /// Returns a copy of this map without any bindings for the keys in
+    /// `range`.
+    pub fn remove(&self, range: Range<K>) -> Self {
+        if range.start >= range.end {
+            return self.clone();
+        }
+        let (before, after) = self.cut(&range);
+        RangeMapIn { tree: before.concat(&after) }
+    }
+    /// Returns the value bound to `key`, or `None` if it is not bound.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+    /// Returns the range containing `key` and the value it is bound to, or
+    /// `None` if `key` is not bound.
+    pub fn get_key_value(&self, key: &K) -> Option<(&Range<K>, &V)> {
+        self.tree
+            .find(|max_end| ends_after(max_end, key))
+            .and_then(|entry| if entry.range.start <= *key {
+                Some((&entry.range, &entry.value))
+            } else {
+                None
+            })
+    }
+    /// Returns the bindings whose ranges overlap `range`, in increasing order.
+    pub fn overlapping(&self, range: &Range<K>) -> Vec<(Range<K>, V)> {
+        if range.start >= range.end {
+            return Vec::new();
+        }
+        let (_, rest) = self.tree.split(|max_end| ends_after(max_end, &range.start));
+        let (inside, after) = rest.split(|max_end| ends_after(max_end, &range.end));
+        let last = after.front().filter(|entry| entry.range.start < range.end);
+        inside.iter()
+              .chain(last)
+              .map(|entry| (entry.range.clone(), entry.value.clone()))
+              .collect()
+    }
+    /// Tests whether this map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    /// Returns the number of ranges bound in this map. This takes O(n) time.
+    pub fn size(&self) -> u32 {
+        self.iter().count() as u32
+    }
+    /// Returns an iterator over the bindings in this map, in increasing order
+    /// of range.
+    pub fn iter(&self) -> Iter<'_, K, V, P> {
+        Iter { iter: self.tree.iter() }
+    }
+    /// Splits this map into the bindings before `range` and those after it,
+    /// cutting down any ranges which overlap it.
+    fn cut(&self, range: &Range<K>) -> (Entries<K, V, P>, Entries<K, V, P>) {
+        let (mut before, rest) = self.tree.split(|max_end| ends_after(max_end, &range.start));
+        let (_, mut after) = rest.split(|max_end| ends_after(max_end, &range.end));
+        if let Some(first) = rest.front() {
+            if first.range.start < range.start {
+                before = before.push_back(Entry {
+                    range: first.range.start.clone()..range.start.clone(),
+                    value: first.value.clone(),
+                });
+            }
+        }
+        if let Some((first, rest)) = after.pop_front() {
+            if first.range.start < range.end {
+                after = rest.push_front(Entry {
+                    range: range.end.clone()..first.range.end,
+                    value: first.value,
+                });
+            }
+        }
+        (before, after)
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + PartialEq, P: SharedPointer> Collection for RangeMapIn<K, V, P> {
+    type Iter<'a> = Iter<'a, K, V, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, K, V, P> {
+        self.iter()
+    }
+}
+
+impl<K: Ord + Clone, V, P: SharedPointer> MemoryUsage for RangeMapIn<K, V, P> {
+    fn census(&self, census: &mut Census) {
+        self.tree.census(census)
+    }
+}
+
+/// An iterator over the bindings of a `RangeMap`, in increasing order of
+/// range.
+pub struct Iter<'a, K: Ord + Clone + 'a, V: 'a, P: SharedPointer> {
+    iter: finger_tree::Iter<'a, Entry<K, V>, P>,
+}
+
+impl<'a, K: Ord + Clone, V, P: SharedPointer> Iterator for Iter<'a, K, V, P> {
+    type Item = (&'a Range<K>, &'a V);
+
+    fn next(&mut self) -> Option<(&'a Range<K>, &'a V)> {
+        self.iter.next().map(|entry| (&entry.range, &entry.value))
+    }
+}
+
+#[cfg(test)]
+fn bindings_of(map: &RangeMap<u32, char>) -> Vec<(Range<u32>, char)> {
+    map.iter().map(|(range, value)| (range.clone(), *value)).collect()
+}
+
+#[test]
+fn empty_map_binds_nothing() {
+    let map: RangeMap<u32, char> = RangeMap::empty();
+
+    assert!(map.is_empty());
+    assert!(map.get(&0).is_none());
+    assert!(map.insert(5..5, 'a').is_empty());
+    assert!(map.overlapping(&(0..10)).is_empty());
+}
+
+#[test]
+fn insertion_splits_overlapped_ranges() {
+    let map = RangeMap::empty().insert(0..10, 'a').insert(20..30, 'b').insert(5..25, 'c');
+
+    assert!(bindings_of(&map) == vec![(0..5, 'a'), (5..25, 'c'), (25..30, 'b')]);
+    let inner = map.insert(10..12, 'd');
+    assert!(bindings_of(&inner) ==
+            vec![(0..5, 'a'), (5..10, 'c'), (10..12, 'd'), (12..25, 'c'), (25..30, 'b')]);
+    assert!(bindings_of(&inner.remove(8..27)) == vec![(0..5, 'a'), (5..8, 'c'), (27..30, 'b')]);
+    assert!(inner.size() == 5);
+}
+
+#[test]
+fn equal_neighbours_are_coalesced() {
+    let map = RangeMap::empty().insert(0..10, 'a').insert(20..30, 'a').insert(10..20, 'a');
+
+    assert!(bindings_of(&map) == vec![(0..30, 'a')]);
+    assert!(bindings_of(&map.insert(10..20, 'b').insert(10..20, 'a')) == vec![(0..30, 'a')]);
+    assert!(bindings_of(&map.insert(30..40, 'b').insert(30..35, 'a')) ==
+            vec![(0..35, 'a'), (35..40, 'b')]);
+}
+
+#[test]
+fn lookups_match_a_scan() {
+    let map = (0..200u32).fold(RangeMap::empty(), |map, i| {
+        let start = (i * 37) % 1000;
+        map.insert(start..start + i % 50 + 1, if i % 3 == 0 { 'x' } else { 'y' })
+    });
+    let bindings = bindings_of(&map);
+
+    for pair in bindings.windows(2) {
+        assert!(pair[0].0.end <= pair[1].0.start);
+        assert!(pair[0].0.end < pair[1].0.start || pair[0].1 != pair[1].1);
+    }
+    for key in 0..1100 {
+        let expected = bindings.iter().find(|&&(ref range, _)| range.contains(&key));
+        assert!(map.get(&key) == expected.map(|&(_, ref value)| value));
+    }
+    for start in (0..1000).filter(|start| start % 13 == 0) {
+        let query = start..start + 40;
+        let expected: Vec<(Range<u32>, char)> = bindings.iter()
+                                                        .filter(|&&(ref range, _)| {
+                                                            range.start < query.end &&
+                                                            query.start < range.end
+                                                        })
+                                                        .cloned()
+                                                        .collect();
+        assert!(map.overlapping(&query) == expected);
+    }
+}
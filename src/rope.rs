@@ -0,0 +1,643 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::str;
+
+use collection::Collection;
+use measure::{Measured, Monoid};
+use memory::{Census, MemoryUsage};
+use ptr::{DefaultPointer, SharedPointer};
+
+use finger_tree::{self, FingerTreeIn};
+
+/// The most bytes a chunk holds, unless a single character is longer.
+const MAX_CHUNK: usize = 512;
+
+/// An immutable string, implemented as a finger tree of UTF-8 chunks measured
+/// by their lengths.
+///
+/// Text is addressed by character index, or by line, where lines are ended
+/// by `'\n'`. Concatenation, insertion, removal, slicing and converting
+/// between lines and character indexes all take O(log n) time, and copying
+/// a rope takes O(1) time and shares all of its text, so keeping every
+/// version of a document as a snapshot is cheap.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::rope::Rope;
+///
+/// let rope = Rope::from("Hello, world!");
+/// let edited = rope.insert(7, "wide ").remove(0..5).insert(0, "Goodbye");
+///
+/// assert!(edited.to_string() == "Goodbye, wide world!");
+/// assert!(edited.slice(9..13).to_string() == "wide");
+/// assert!(rope.to_string() == "Hello, world!");
+/// assert!(rope.concat(&Rope::from(" ¡Hola!")).len_chars() == 20);
+///
+/// let poem = Rope::from("Roses are red,\nViolets are blue,\n");
+/// assert!(poem.len_lines() == 3);
+/// assert!(poem.line(1).to_string() == "Violets are blue,\n");
+/// assert!(poem.line_to_char(1) == 15);
+/// assert!(poem.char_to_line(20) == 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RopeIn<P: SharedPointer> {
+    tree: FingerTreeIn<Chunk, P>,
+}
+
+/// A `RopeIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type Rope = RopeIn<DefaultPointer>;
+
+/// A piece of a rope's text, which caches its size.
+#[derive(Debug, Clone)]
+struct Chunk {
+    text: String,
+    size: TextSize,
+}
+
+/// The length of part of a rope, in bytes and in characters, and the number
+/// of line breaks in it.
+#[derive(Debug, Clone, Copy)]
+struct TextSize {
+    bytes: usize,
+    chars: usize,
+    newlines: usize,
+}
+
+impl Monoid for TextSize {
+    fn empty() -> Self {
+        TextSize {
+            bytes: 0,
+            chars: 0,
+            newlines: 0,
+        }
+    }
+    fn combine(&self, other: &Self) -> Self {
+        TextSize {
+            bytes: self.bytes + other.bytes,
+            chars: self.chars + other.chars,
+            newlines: self.newlines + other.newlines,
+        }
+    }
+}
+
+impl Measured for Chunk {
+    type Measure = TextSize;
+    fn measure(&self) -> TextSize {
+        self.size
+    }
+}
+
+impl Chunk {
+    fn new(text: &str) -> Self {
+        Chunk {
+            text: text.to_string(),
+            size: TextSize {
+                bytes: text.len(),
+                chars: text.chars().count(),
+                newlines: text.bytes().filter(|&byte| byte == b'\n').count(),
+            },
+        }
+    }
+}
+
+impl<P: SharedPointer> RopeIn<P> {
+    /// Returns an empty rope.
+    pub fn empty() -> Self {
+        RopeIn { tree: FingerTreeIn::empty() }
+    }
+    /// Tests whether this rope is empty.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    /// Returns the number of characters in this rope.
+    pub fn len_chars(&self) -> usize {
+        self.tree.measure().chars
+    }
+    /// Returns the number of bytes in this rope's UTF-8 encoding.
+    pub fn len_bytes(&self) -> usize {
+        self.tree.measure().bytes
+    }
+    /// Returns the number of lines in this rope. This is one more than the
+    /// number of line breaks, so an empty rope has one empty line, and text
+    /// ending in a line break has an empty last line.
+    pub fn len_lines(&self) -> usize {
+        self.tree.measure().newlines + 1
+    }
+    /// Returns a rope of the text of line `line_idx`, including the line
+    /// break which ends it, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `line_idx` is not less than the number of lines.
+    pub fn line(&self, line_idx: usize) -> Self {
+        assert!(line_idx < self.len_lines(), "line index out of range");
+        self.slice(self.line_to_char(line_idx)..self.line_to_char(line_idx + 1))
+    }
+    /// Returns the index of the first character of line `line_idx`. Passing
+    /// the number of lines returns the length of the rope.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `line_idx` is greater than the number of lines.
+    pub fn line_to_char(&self, line_idx: usize) -> usize {
+        assert!(line_idx <= self.len_lines(), "line index out of range");
+        if line_idx == 0 {
+            return 0;
+        }
+        match self.tree.find_with_measure(|size| size.newlines >= line_idx) {
+            Some((before, chunk)) => {
+                // The line starts after the chunk's `n`th line break.
+                let n = line_idx - before.newlines;
+                let offset = chunk.text
+                                  .chars()
+                                  .enumerate()
+                                  .filter(|&(_, c)| c == '\n')
+                                  .nth(n - 1)
+                                  .map(|(i, _)| i + 1)
+                                  .unwrap();
+                before.chars + offset
+            }
+            None => self.len_chars(),
+        }
+    }
+    /// Returns the index of the line which the character at `char_idx` is
+    /// on. Passing the length of the rope returns the index of the last line.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `char_idx` is greater than the length of this rope.
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        assert!(char_idx <= self.len_chars(), "character index out of range");
+        match self.tree.find_with_measure(|size| size.chars > char_idx) {
+            Some((before, chunk)) => {
+                before.newlines +
+                chunk.text
+                     .chars()
+                     .take(char_idx - before.chars)
+                     .filter(|&c| c == '\n')
+                     .count()
+            }
+            None => self.tree.measure().newlines,
+        }
+    }
+    /// Returns a rope of the same text as this one, packed into as few
+    /// chunks as it fits in.
+    ///
+    /// The tree of chunks stays balanced however a rope is built, but edits
+    /// split chunks, and the pieces are only merged back where they meet
+    /// another small piece, so a rope which has been edited many times can
+    /// be left with many chunks holding a few bytes each. This takes O(n)
+    /// time.
+    pub fn rebalance(&self) -> Self {
+        let mut builder = RopeBuilderIn::new();
+        for chunk in self.chunks() {
+            builder.push_str(chunk);
+        }
+        builder.freeze()
+    }
+    /// Returns a rope of the text of this rope followed by that of `other`.
+    pub fn concat(&self, other: &Self) -> Self {
+        RopeIn { tree: join(&self.tree, &other.tree) }
+    }
+    /// Returns a copy of this rope with `text` inserted before the character
+    /// at `char_idx`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `char_idx` is greater than the length of this rope.
+    pub fn insert(&self, char_idx: usize, text: &str) -> Self {
+        let (before, after) = self.split_at(char_idx);
+        before.concat(&RopeIn::from(text)).concat(&after)
+    }
+    /// Returns a copy of this rope without the characters in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range starts after it ends, or ends after the end of
+    /// this rope.
+    pub fn remove(&self, range: Range<usize>) -> Self {
+        assert!(range.start <= range.end, "the range starts after it ends");
+        let (before, rest) = self.split_at(range.start);
+        let (_, after) = rest.split_at(range.end - range.start);
+        before.concat(&after)
+    }
+    /// Returns a rope of the characters of this rope in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range starts after it ends, or ends after the end of
+    /// this rope.
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        assert!(range.start <= range.end, "the range starts after it ends");
+        let (_, rest) = self.split_at(range.start);
+        rest.split_at(range.end - range.start).0
+    }
+    /// Splits this rope into the characters before `char_idx` and those from
+    /// `char_idx` onwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `char_idx` is greater than the length of this rope.
+    pub fn split_at(&self, char_idx: usize) -> (Self, Self) {
+        assert!(char_idx <= self.len_chars(), "character index out of range");
+        let (before, after) = self.tree.split(|size| size.chars > char_idx);
+        let offset = char_idx - before.measure().chars;
+        if offset == 0 {
+            return (RopeIn { tree: before }, RopeIn { tree: after });
+        }
+        // The split falls inside the first chunk of `after`.
+        let (chunk, after) = after.pop_front().unwrap();
+        let byte = byte_offset(&chunk.text, offset);
+        (RopeIn { tree: before.push_back(Chunk::new(&chunk.text[..byte])) },
+         RopeIn { tree: after.push_front(Chunk::new(&chunk.text[byte..])) })
+    }
+    /// Returns an iterator over the characters of this rope.
+    pub fn chars(&self) -> Chars<'_, P> {
+        Chars {
+            chunks: self.chunks(),
+            current: "".chars(),
+        }
+    }
+    /// Returns an iterator over the chunks of text this rope is made of, in
+    /// order.
+    pub fn chunks(&self) -> Chunks<'_, P> {
+        Chunks { iter: self.tree.iter() }
+    }
+}
+
+impl<P: SharedPointer> Collection for RopeIn<P> {
+    type Iter<'a> = Chars<'a, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.len_chars()
+    }
+    fn iter<'a>(&'a self) -> Chars<'a, P> {
+        self.chars()
+    }
+}
+
+impl<P: SharedPointer> MemoryUsage for RopeIn<P> {
+    fn census(&self, census: &mut Census) {
+        self.tree.census_with(census, |chunk| chunk.text.capacity())
+    }
+}
+
+impl<'a, P: SharedPointer> From<&'a str> for RopeIn<P> {
+    fn from(text: &'a str) -> Self {
+        let mut builder = RopeBuilderIn::new();
+        builder.push_str(text);
+        builder.freeze()
+    }
+}
+
+/// Builds a `Rope` from text given a piece at a time, such as the lines of a
+/// file or the contents of a reader.
+///
+/// The text is packed into full chunks as it arrives, so the rope built
+/// holds the fewest chunks its text fits in however the pieces are sized,
+/// and building it does not make a new version of the rope for every
+/// piece as concatenating ropes of the pieces does.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::rope::RopeBuilder;
+///
+/// let mut builder = RopeBuilder::new();
+/// for i in 0..1000 {
+///     builder.push_str(&format!("line {}\n", i));
+/// }
+/// builder.read_from("the end".as_bytes()).unwrap();
+/// let rope = builder.freeze();
+///
+/// assert!(rope.len_lines() == 1001);
+/// assert!(rope.line(999).to_string() == "line 999\n");
+/// assert!(rope.line(1000).to_string() == "the end");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RopeBuilderIn<P: SharedPointer> {
+    tree: FingerTreeIn<Chunk, P>,
+    /// The text added since the last full chunk.
+    pending: String,
+}
+
+/// A `RopeBuilderIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type RopeBuilder = RopeBuilderIn<DefaultPointer>;
+
+impl<P: SharedPointer> RopeBuilderIn<P> {
+    /// Returns a builder with no text.
+    pub fn new() -> Self {
+        RopeBuilderIn {
+            tree: FingerTreeIn::empty(),
+            pending: String::new(),
+        }
+    }
+    /// Adds `text` at the end of the rope being built.
+    pub fn push_str(&mut self, text: &str) {
+        let mut rest = text;
+        loop {
+            let room = MAX_CHUNK - self.pending.len();
+            if rest.len() <= room {
+                self.pending.push_str(rest);
+                return;
+            }
+            let mut end = room;
+            while !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            self.pending.push_str(&rest[..end]);
+            rest = &rest[end..];
+            self.tree.push_back_mut(Chunk::new(&self.pending));
+            self.pending.clear();
+        }
+    }
+    /// Adds the text read from `reader` until it is exhausted at the end of
+    /// the rope being built, returning the number of bytes read.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error if reading fails, or one of kind `InvalidData` if
+    /// what is read is not UTF-8. The text read before the error is kept.
+    pub fn read_from<R: Read>(&mut self, mut reader: R) -> io::Result<usize> {
+        let mut buffer = vec![0; 8 * MAX_CHUNK];
+        // The bytes read in a character which was cut off by the end of the
+        // last read.
+        let mut partial = 0;
+        let mut total = 0;
+        loop {
+            let read = match reader.read(&mut buffer[partial..]) {
+                Ok(read) => read,
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            if read == 0 {
+                if partial > 0 {
+                    return Err(invalid_utf8());
+                }
+                return Ok(total);
+            }
+            total += read;
+            let filled = partial + read;
+            let valid = match str::from_utf8(&buffer[..filled]) {
+                Ok(text) => text.len(),
+                Err(ref error) if error.error_len().is_none() => error.valid_up_to(),
+                Err(_) => return Err(invalid_utf8()),
+            };
+            self.push_str(str::from_utf8(&buffer[..valid]).unwrap());
+            buffer.copy_within(valid..filled, 0);
+            partial = filled - valid;
+        }
+    }
+    /// Returns a rope of the text added, in the order it was added.
+    pub fn freeze(mut self) -> RopeIn<P> {
+        if !self.pending.is_empty() {
+            self.tree.push_back_mut(Chunk::new(&self.pending));
+        }
+        RopeIn { tree: self.tree }
+    }
+}
+
+impl<P: SharedPointer> Default for RopeBuilderIn<P> {
+    fn default() -> Self {
+        RopeBuilderIn::new()
+    }
+}
+
+impl<'a, P: SharedPointer> Extend<&'a str> for RopeBuilderIn<P> {
+    fn extend<I: IntoIterator<Item = &'a str>>(&mut self, pieces: I) {
+        for piece in pieces {
+            self.push_str(piece);
+        }
+    }
+}
+
+impl<P: SharedPointer> fmt::Display for RopeIn<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl<P: SharedPointer> PartialEq for RopeIn<P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len_bytes() == other.len_bytes() &&
+        self.chunks()
+            .flat_map(str::bytes)
+            .eq(other.chunks().flat_map(str::bytes))
+    }
+}
+
+impl<P: SharedPointer> Eq for RopeIn<P> {}
+
+/// An iterator over the characters of a `Rope`.
+pub struct Chars<'a, P: SharedPointer> {
+    chunks: Chunks<'a, P>,
+    current: str::Chars<'a>,
+}
+
+impl<'a, P: SharedPointer> Iterator for Chars<'a, P> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if let Some(c) = self.current.next() {
+                return Some(c);
+            }
+            match self.chunks.next() {
+                Some(chunk) => self.current = chunk.chars(),
+                None => return None,
+            }
+        }
+    }
+}
+
+/// An iterator over the chunks of text a `Rope` is made of.
+pub struct Chunks<'a, P: SharedPointer> {
+    iter: finger_tree::Iter<'a, Chunk, P>,
+}
+
+impl<'a, P: SharedPointer> Iterator for Chunks<'a, P> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.iter.next().map(|chunk| &chunk.text[..])
+    }
+}
+
+/// Concatenates two trees of chunks, merging the chunks where they meet if
+/// together they are small enough, so that repeated small edits do not
+/// leave the rope in many tiny pieces.
+fn join<P: SharedPointer>(left: &FingerTreeIn<Chunk, P>,
+                          right: &FingerTreeIn<Chunk, P>)
+                          -> FingerTreeIn<Chunk, P> {
+    match (left.back(), right.front()) {
+        (Some(last), Some(first)) if last.size.bytes + first.size.bytes <= MAX_CHUNK => {}
+        _ => return left.concat(right),
+    }
+    let (last, left) = left.pop_back().unwrap();
+    let (first, right) = right.pop_front().unwrap();
+    left.push_back(Chunk::new(&(last.text + &first.text))).concat(&right)
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+}
+
+/// Returns the byte offset of the character at `char_idx` in `text`.
+fn byte_offset(text: &str, char_idx: usize) -> usize {
+    text.char_indices().nth(char_idx).map_or(text.len(), |(byte, _)| byte)
+}
+
+#[test]
+fn empty_rope_has_no_text() {
+    let rope = Rope::empty();
+
+    assert!(rope.is_empty());
+    assert!(rope.len_chars() == 0);
+    assert!(rope.to_string() == "");
+    assert!(rope == Rope::from(""));
+    assert!(rope.insert(0, "x").to_string() == "x");
+}
+
+#[test]
+fn long_text_is_split_into_chunks() {
+    let text: String = (0..5000).map(|i| if i % 7 == 0 { 'é' } else { 'a' }).collect();
+    let rope = Rope::from(&text[..]);
+
+    assert!(rope.chunks().count() > 1);
+    assert!(rope.chunks().all(|chunk| chunk.len() <= MAX_CHUNK));
+    assert!(rope.len_chars() == 5000);
+    assert!(rope.len_bytes() == text.len());
+    assert!(rope.to_string() == text);
+    assert!(rope.chars().eq(text.chars()));
+}
+
+#[test]
+fn edits_match_string_edits() {
+    let mut expected: Vec<char> = "naïve café ☕ text".chars().cycle().take(3000).collect();
+    let mut rope = Rope::from(&expected.iter().collect::<String>()[..]);
+
+    for i in 0..200 {
+        let at = (i * 7919) % expected.len();
+        if i % 3 == 0 {
+            let end = (at + i % 50).min(expected.len());
+            rope = rope.remove(at..end);
+            expected.drain(at..end);
+        } else {
+            rope = rope.insert(at, "ünï");
+            for (offset, c) in "ünï".chars().enumerate() {
+                expected.insert(at + offset, c);
+            }
+        }
+        assert!(rope.len_chars() == expected.len());
+    }
+    assert!(rope.to_string() == expected.iter().collect::<String>());
+    assert!(rope.chunks().all(|chunk| !chunk.is_empty()));
+}
+
+#[test]
+fn slices_and_snapshots() {
+    let rope = Rope::from("αβγδε").concat(&Rope::from("ζηθ"));
+    let snapshot = rope.clone();
+    let edited = rope.remove(1..7);
+
+    assert!(rope.slice(2..6).to_string() == "γδεζ");
+    assert!(rope.slice(3..3).is_empty());
+    assert!(edited.to_string() == "αθ");
+    assert!(snapshot.to_string() == "αβγδεζηθ");
+    assert!(rope.split_at(5).1 == Rope::from("ζηθ"));
+}
+
+#[test]
+fn lines_match_string_lines() {
+    let line_text = |i: usize| "x".repeat(i * 37 % 300) + "ß\n";
+    let text: String = (0..200).map(line_text).collect();
+    let rope = Rope::from(&text[..]);
+
+    assert!(rope.len_lines() == 201);
+    assert!(rope.line(200).is_empty());
+    let mut start = 0;
+    for i in 0..200 {
+        assert!(rope.line_to_char(i) == start);
+        assert!(rope.line(i).to_string() == line_text(i));
+        let len = line_text(i).chars().count();
+        assert!(rope.char_to_line(start) == i);
+        assert!(rope.char_to_line(start + len - 1) == i);
+        start += len;
+    }
+    assert!(rope.line_to_char(201) == rope.len_chars());
+    assert!(rope.char_to_line(rope.len_chars()) == 200);
+}
+
+#[test]
+fn line_counts_follow_edits() {
+    let rope = Rope::from("one\ntwo\nthree");
+    let edited = rope.insert(4, "one and a half\n").remove(0..4);
+
+    assert!(rope.len_lines() == 3);
+    assert!(rope.line(2).to_string() == "three");
+    assert!(edited.len_lines() == 3);
+    assert!(edited.line(0).to_string() == "one and a half\n");
+    assert!(edited.char_to_line(15) == 1);
+    assert!(Rope::empty().len_lines() == 1);
+    assert!(Rope::empty().line(0).is_empty());
+}
+
+#[test]
+#[should_panic]
+fn inserting_past_the_end_panics() {
+    Rope::from("abc").insert(4, "d");
+}
+
+#[test]
+fn built_ropes_are_packed_into_full_chunks() {
+    let lines: Vec<String> = (0..2000).map(|i| format!("línea {}\n", i)).collect();
+    let text: String = lines.concat();
+    let concatenated = lines.iter().fold(Rope::empty(), |rope, line| {
+        rope.concat(&Rope::from(&line[..]))
+    });
+    let mut builder = RopeBuilder::new();
+    builder.extend(lines.iter().map(|line| &line[..]));
+    let built = builder.freeze();
+
+    let fewest = text.len().div_ceil(MAX_CHUNK);
+    assert!(built == concatenated && built.to_string() == text);
+    assert!(built.chunks().all(|chunk| chunk.len() <= MAX_CHUNK));
+    assert!(built.chunks().count() <= fewest + 1);
+
+    let edited = (0..500).fold(built.clone(), |rope, i| rope.insert(i * 29, "x").remove(i..i + 1));
+    let rebalanced = edited.rebalance();
+    assert!(rebalanced == edited && rebalanced.chunks().count() <= fewest + 1);
+    assert!(edited.chunks().count() > fewest + 1);
+}
+
+#[test]
+fn ropes_are_read_across_split_characters() {
+    let text: String = (0..3000).map(|i| if i % 3 == 0 { '€' } else { 'a' }).collect();
+    // Hands out a few bytes at a time, so that reads end inside characters.
+    struct Trickle<'a>(&'a [u8]);
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let count = self.0.len().min(buffer.len()).min(7);
+            buffer[..count].copy_from_slice(&self.0[..count]);
+            self.0 = &self.0[count..];
+            Ok(count)
+        }
+    }
+
+    let mut builder = RopeBuilder::new();
+    assert!(builder.read_from(Trickle(text.as_bytes())).unwrap() == text.len());
+    assert!(builder.freeze().to_string() == text);
+
+    let mut builder = RopeBuilder::new();
+    builder.push_str("ok");
+    let error = builder.read_from(&text.as_bytes()[3..7]).unwrap_err();
+    assert!(error.kind() == io::ErrorKind::InvalidData);
+    assert!(builder.freeze().to_string() == "okaa");
+}
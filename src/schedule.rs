@@ -0,0 +1,135 @@
+use ptr::{DefaultPointer, SendFor, SharedPointer, SyncFor};
+use stack::{CustomStackIn, Stack};
+use stream::{StreamIn, StreamCellIn};
+
+/// A schedule of suspended work: a list of stream suffixes which have not
+/// been fully evaluated yet.
+///
+/// This is the device used in Chapter 7 of PFDL to turn amortized bounds
+/// into worst-case bounds. A structure keeps a schedule of the suspensions it
+/// has created and calls `exec1` or `exec2` on every operation, which forces
+/// a constant number of cells ahead of time so that no single operation is
+/// left with a long chain of suspensions to evaluate.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::schedule::Schedule;
+/// use functional_datastructures::stream::Stream;
+///
+/// let work = Stream::empty().cons(2).cons(1);
+/// let schedule = Schedule::empty().push(work);
+///
+/// assert!(schedule.size() == 1);
+/// assert!(schedule.exec2().exec1().is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScheduleIn<T, P: SharedPointer> {
+    streams: CustomStackIn<StreamIn<T, P>, P>,
+}
+
+/// A `ScheduleIn` of the streams exported at the root of the crate.
+pub type Schedule<T> = ScheduleIn<T, DefaultPointer>;
+
+impl<T, P> ScheduleIn<T, P>
+    where T: Clone + SendFor<P> + SyncFor<P> + 'static,
+          P: SharedPointer
+{
+    /// Returns a schedule with no outstanding work.
+    pub fn empty() -> Self {
+        ScheduleIn { streams: CustomStackIn::empty() }
+    }
+    /// Tests whether there is any outstanding work in this schedule.
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+    /// Returns the number of streams which have not yet been completely
+    /// forced by this schedule.
+    pub fn size(&self) -> u32 {
+        self.streams.size()
+    }
+    /// Returns a new schedule which will evaluate `stream` before any of the
+    /// work already in this schedule.
+    pub fn push(&self, stream: StreamIn<T, P>) -> Self {
+        ScheduleIn { streams: self.streams.cons(stream) }
+    }
+    /// Forces one cell of the first stream in this schedule, returning the
+    /// schedule of the work that remains.
+    ///
+    /// Streams which turn out to be exhausted are dropped without counting
+    /// as a step, so a step only finishes early if the schedule is empty.
+    pub fn exec1(&self) -> Self {
+        let mut streams = self.streams.clone();
+        loop {
+            let (first, rest) = match streams {
+                CustomStackIn::Empty => return ScheduleIn { streams: streams },
+                CustomStackIn::Cons { ref value, ref tail } => (value.clone(), (**tail).clone()),
+            };
+            match *first.force() {
+                StreamCellIn::Nil => streams = rest,
+                StreamCellIn::Cons(_, ref suffix) => {
+                    return ScheduleIn { streams: rest.cons(suffix.clone()) };
+                }
+            }
+        }
+    }
+    /// Performs two steps of work. Equivalent to `exec1().exec1()`.
+    pub fn exec2(&self) -> Self {
+        self.exec1().exec1()
+    }
+}
+
+
+#[cfg(test)]
+use stream::{Stream, StreamCell};
+
+#[cfg(test)]
+fn lazy_range(from: u32, to: u32) -> Stream<u32> {
+    Stream::lazy(move || if from == to {
+        StreamCell::Nil
+    } else {
+        StreamCell::Cons(from, lazy_range(from + 1, to))
+    })
+}
+
+#[test]
+fn exec1_on_empty_is_empty() {
+    let schedule: Schedule<u32> = Schedule::empty();
+
+    assert!(schedule.exec1().is_empty());
+}
+
+#[test]
+fn exec1_forces_one_cell() {
+    let work = lazy_range(0, 3);
+    let schedule = Schedule::empty().push(work.clone());
+
+    let after = schedule.exec1();
+
+    assert!(work.is_forced());
+    assert!(!work.tail().unwrap().is_forced());
+    assert!(after.size() == 1);
+}
+
+#[test]
+fn exec_drops_finished_streams() {
+    let first = lazy_range(0, 1);
+    let second = lazy_range(10, 12);
+    let schedule = Schedule::empty().push(second.clone()).push(first.clone());
+
+    // Forces 0, then Nil at the end of `first` and 10 at the start of `second`.
+    let after = schedule.exec2();
+
+    assert!(after.size() == 1);
+    assert!(second.is_forced());
+    assert!(!second.tail().unwrap().is_forced());
+}
+
+#[test]
+fn exec_until_empty() {
+    let schedule = Schedule::empty().push(lazy_range(0, 2)).push(lazy_range(0, 2));
+
+    // The final Nil of each stream is only discovered by the step after it.
+    assert!(!schedule.exec2().exec2().is_empty());
+    assert!(schedule.exec2().exec2().exec1().is_empty());
+}
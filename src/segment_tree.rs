@@ -0,0 +1,258 @@
+use std::iter::FromIterator;
+use std::ops::Range;
+
+use measure::Monoid;
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+use stack::StackError;
+
+/// An immutable array of monoidal values which can combine any range of
+/// them in O(log n) time, implemented as a persistent segment tree.
+///
+/// The values are the leaves of a balanced binary tree, and each branch
+/// caches the combination of the leaves below it, so a range is combined
+/// from O(log n) cached results. Updating a value copies only the O(log n)
+/// branches above it, and every earlier version of the tree can still be
+/// queried, which makes it cheap to keep a version for each point in a
+/// history.
+///
+/// The values can be of any `Monoid`, such as `Sum`, `Min` or `Max` from the
+/// `measure` module.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::measure::{Max, Sum};
+/// use functional_datastructures::segment_tree::SegmentTree;
+///
+/// let sums: SegmentTree<Sum<u32>> = (1..11).map(Sum).collect();
+/// let updated = sums.update(4, Sum(100)).unwrap();
+///
+/// assert!(sums.query(0..10).unwrap() == Sum(55));
+/// assert!(sums.query(3..6).unwrap() == Sum(15));
+/// assert!(updated.query(3..6).unwrap() == Sum(110));
+/// assert!(sums.query(3..11).is_err());
+///
+/// let maxes: SegmentTree<Max<u32>> = vec![3, 9, 2, 7].into_iter().map(|v| Max(Some(v))).collect();
+/// assert!(maxes.query(2..4).unwrap() == Max(Some(7)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SegmentTreeIn<M, P: SharedPointer> {
+    root: Option<Shared<Node<M, P>, P>>,
+    len: u32,
+}
+
+/// A `SegmentTreeIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type SegmentTree<M> = SegmentTreeIn<M, DefaultPointer>;
+
+/// A node of a segment tree. A branch caches the combination of all the
+/// leaves below it.
+#[derive(Debug)]
+enum Node<M, P: SharedPointer> {
+    Leaf(M),
+    Branch(M, Shared<Node<M, P>, P>, Shared<Node<M, P>, P>),
+}
+
+impl<M: Monoid, P: SharedPointer> SegmentTreeIn<M, P> {
+    /// Returns a segment tree of no values.
+    pub fn empty() -> Self {
+        SegmentTreeIn {
+            root: None,
+            len: 0,
+        }
+    }
+    /// Tests whether this tree holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Returns the number of values in this tree.
+    pub fn size(&self) -> u32 {
+        self.len
+    }
+    /// Returns the value at index `i`, or `None` if `i` is not less than the
+    /// size of this tree.
+    pub fn get(&self, i: u32) -> Option<&M> {
+        if i >= self.len {
+            return None;
+        }
+        let mut node = self.root.as_ref().unwrap();
+        let (mut low, mut high) = (0, self.len);
+        loop {
+            match **node {
+                Node::Leaf(ref value) => return Some(value),
+                Node::Branch(_, ref left, ref right) => {
+                    let mid = midpoint(low, high);
+                    if i < mid {
+                        node = left;
+                        high = mid;
+                    } else {
+                        node = right;
+                        low = mid;
+                    }
+                }
+            }
+        }
+    }
+    /// Returns the combination of all the values in this tree.
+    pub fn total(&self) -> M {
+        self.root.as_ref().map_or_else(M::empty, |root| root.measure().clone())
+    }
+    /// Returns the combination of the values in `range`, in order. An empty
+    /// range combines to `M::empty()`.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::IndexOutOfRange` if the range starts after it
+    /// ends, or ends after the end of this tree.
+    pub fn query(&self, range: Range<u32>) -> Result<M, StackError> {
+        if range.start > range.end || range.end > self.len {
+            return Err(StackError::IndexOutOfRange);
+        }
+        Ok(match self.root {
+            Some(ref root) if range.start < range.end => root.query(0, self.len, &range),
+            _ => M::empty(),
+        })
+    }
+    /// Returns a copy of this tree with the value at index `i` replaced by
+    /// `value`.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::IndexOutOfRange` if `i` is not less than the size
+    /// of this tree.
+    pub fn update(&self, i: u32, value: M) -> Result<Self, StackError> {
+        match self.root {
+            Some(ref root) if i < self.len => {
+                Ok(SegmentTreeIn {
+                    root: Some(Shared::new(root.update(0, self.len, i, value))),
+                    len: self.len,
+                })
+            }
+            _ => Err(StackError::IndexOutOfRange),
+        }
+    }
+}
+
+impl<M: Monoid, P: SharedPointer> Node<M, P> {
+    fn measure(&self) -> &M {
+        match *self {
+            Node::Leaf(ref value) => value,
+            Node::Branch(ref total, _, _) => total,
+        }
+    }
+    fn branch(left: Shared<Self, P>, right: Shared<Self, P>) -> Self {
+        Node::Branch(left.measure().combine(right.measure()), left, right)
+    }
+    /// Builds a balanced tree of `values`, which must not be empty.
+    fn build(values: &[M]) -> Self {
+        if values.len() == 1 {
+            return Node::Leaf(values[0].clone());
+        }
+        let (left, right) = values.split_at(values.len() / 2);
+        Node::branch(Shared::new(Node::build(left)), Shared::new(Node::build(right)))
+    }
+    /// Combines the values in `range`, given that this node holds those from
+    /// `low` up to `high` and overlaps the range.
+    fn query(&self, low: u32, high: u32, range: &Range<u32>) -> M {
+        if range.start <= low && high <= range.end {
+            return self.measure().clone();
+        }
+        match *self {
+            Node::Leaf(ref value) => value.clone(),
+            Node::Branch(_, ref left, ref right) => {
+                let mid = midpoint(low, high);
+                if range.end <= mid {
+                    left.query(low, mid, range)
+                } else if range.start >= mid {
+                    right.query(mid, high, range)
+                } else {
+                    left.query(low, mid, range).combine(&right.query(mid, high, range))
+                }
+            }
+        }
+    }
+    /// Returns a copy of this node, which holds the values from `low` up to
+    /// `high`, with the value at index `i` replaced by `value`.
+    fn update(&self, low: u32, high: u32, i: u32, value: M) -> Self {
+        match *self {
+            Node::Leaf(_) => Node::Leaf(value),
+            Node::Branch(_, ref left, ref right) => {
+                let mid = midpoint(low, high);
+                if i < mid {
+                    Node::branch(Shared::new(left.update(low, mid, i, value)), right.clone())
+                } else {
+                    Node::branch(left.clone(), Shared::new(right.update(mid, high, i, value)))
+                }
+            }
+        }
+    }
+}
+
+/// Returns the index at which a node holding the values from `low` up to
+/// `high` divides them between its children, matching `Node::build`.
+fn midpoint(low: u32, high: u32) -> u32 {
+    low + (high - low) / 2
+}
+
+impl<M: Monoid, P: SharedPointer> FromIterator<M> for SegmentTreeIn<M, P> {
+    fn from_iter<I: IntoIterator<Item = M>>(values: I) -> Self {
+        let values: Vec<M> = values.into_iter().collect();
+        if values.is_empty() {
+            return SegmentTreeIn::empty();
+        }
+        SegmentTreeIn {
+            root: Some(Shared::new(Node::build(&values))),
+            len: values.len() as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+use measure::{Min, Sum};
+
+#[test]
+fn empty_tree_combines_to_nothing() {
+    let tree: SegmentTree<Sum<u32>> = SegmentTree::empty();
+
+    assert!(tree.is_empty());
+    assert!(tree.total() == Sum(0));
+    assert!(tree.query(0..0).unwrap() == Sum(0));
+    assert!(tree.query(0..1).is_err());
+    assert!(tree.update(0, Sum(1)).is_err());
+    assert!(tree.get(0).is_none());
+}
+
+#[test]
+fn range_queries_match_a_scan() {
+    let values: Vec<u64> = (0..157).map(|i| (i * 7919) % 1000).collect();
+    let sums: SegmentTree<Sum<u64>> = values.iter().cloned().map(Sum).collect();
+    let mins: SegmentTree<Min<u64>> = values.iter().cloned().map(|v| Min(Some(v))).collect();
+
+    assert!(sums.size() == 157);
+    for start in 0..158 {
+        for end in start..158 {
+            let slice = &values[start as usize..end as usize];
+            assert!(sums.query(start..end).unwrap() == Sum(slice.iter().sum()));
+            assert!(mins.query(start..end).unwrap() == Min(slice.iter().cloned().min()));
+        }
+    }
+    assert!(sums.query(10..9).is_err());
+}
+
+#[test]
+fn earlier_versions_stay_queryable() {
+    let mut versions = vec![(0..64).map(|_| Sum(1u32)).collect::<SegmentTree<_>>()];
+    for i in 0..64 {
+        let next = versions[i as usize].update(i, Sum(0)).unwrap();
+        versions.push(next);
+    }
+
+    for (i, version) in versions.iter().enumerate() {
+        assert!(version.total() == Sum(64 - i as u32));
+        assert!(version.query(0..32).unwrap() == Sum(32 - (i as u32).min(32)));
+    }
+    assert!(*versions[10].get(9).unwrap() == Sum(0));
+    assert!(*versions[10].get(10).unwrap() == Sum(1));
+    assert!(versions[0].update(64, Sum(0)).is_err());
+}
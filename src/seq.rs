@@ -0,0 +1,1046 @@
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::mem;
+use std::slice;
+
+use collection::{Collection, MapElems, Traverse};
+use measure::{Count, Measured};
+use memory::{Census, MemoryUsage};
+use sequence::{Cons, Sequence, Snoc, Uncons, Unsnoc};
+#[cfg(feature = "rayon")]
+use par::Split;
+
+use finger_tree::{self, FingerTreeIn};
+use ptr::{DefaultPointer, Shared, SharedPointer};
+#[cfg(feature = "rayon")]
+use ptr::{MaybeSend, MaybeSync};
+use stack::{CustomStackIn, StackError};
+use zipper::ListZipperIn;
+
+/// The most elements a chunk holds.
+const MAX_CHUNK: usize = 32;
+/// The fewest elements `par_map` splits into two halves to map as separate
+/// tasks, rather than mapping them all on the current thread.
+#[cfg(feature = "rayon")]
+const PARALLEL_MAP: u32 = 4096;
+
+/// An immutable sequence, implemented as a finger tree of chunks of elements
+/// measured by size.
+///
+/// Both ends can be read in O(1) time and pushed to or popped from in O(1)
+/// amortized time, so a `Seq` serves as a stack, a queue or a deque. Since
+/// every subtree knows how many elements it holds, indexing, splitting,
+/// concatenation and insertion or removal in the middle all take O(log n)
+/// time as well.
+///
+/// Keeping up to 32 elements together in each leaf of the tree, rather than
+/// one, means iterating over a sequence mostly walks along arrays instead
+/// of chasing a pointer per element.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::seq::Seq;
+///
+/// let seq = (0..10).fold(Seq::empty(), |seq, i| seq.push_back(i));
+/// let (front, back) = seq.split_at(4);
+///
+/// assert!(seq.size() == 10);
+/// assert!(*seq.get(7).unwrap() == 7);
+/// assert!(*back.front().unwrap() == 4);
+/// assert!(back.concat(&front).iter().cloned().collect::<Vec<_>>() ==
+///         vec![4, 5, 6, 7, 8, 9, 0, 1, 2, 3]);
+///
+/// let changed = seq.remove_at(0).unwrap().1.insert_at(5, 100).unwrap();
+/// assert!(changed.iter().cloned().collect::<Vec<_>>() == vec![1, 2, 3, 4, 5, 100, 6, 7, 8, 9]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SeqIn<T, P: SharedPointer> {
+    tree: FingerTreeIn<Chunk<T>, P>,
+}
+
+/// A `SeqIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type Seq<T> = SeqIn<T, DefaultPointer>;
+
+/// A non-empty run of consecutive elements of a sequence, measured by its
+/// length.
+#[derive(Debug, Clone)]
+struct Chunk<T>(Vec<T>);
+
+impl<T> Measured for Chunk<T> {
+    type Measure = Count;
+    fn measure(&self) -> Count {
+        Count(self.0.len() as u32)
+    }
+}
+
+impl<T: Clone, P: SharedPointer> SeqIn<T, P> {
+    /// Returns an empty sequence.
+    pub fn empty() -> Self {
+        SeqIn { tree: FingerTreeIn::empty() }
+    }
+    /// Tests whether this sequence is empty.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    /// Returns the number of elements in this sequence.
+    pub fn size(&self) -> u32 {
+        self.tree.measure().0
+    }
+    /// Returns a copy of this sequence with `value` added at the front.
+    pub fn push_front(&self, value: T) -> Self {
+        let tree = match self.tree.front() {
+            Some(first) if first.0.len() < MAX_CHUNK => {
+                let (Chunk(mut values), rest) = self.tree.pop_front().unwrap();
+                values.insert(0, value);
+                rest.push_front(Chunk(values))
+            }
+            _ => self.tree.push_front(Chunk(vec![value])),
+        };
+        SeqIn { tree: tree }
+    }
+    /// Returns a copy of this sequence with `value` added at the back.
+    pub fn push_back(&self, value: T) -> Self {
+        let tree = match self.tree.back() {
+            Some(last) if last.0.len() < MAX_CHUNK => {
+                let (Chunk(mut values), rest) = self.tree.pop_back().unwrap();
+                values.push(value);
+                rest.push_back(Chunk(values))
+            }
+            _ => self.tree.push_back(Chunk(vec![value])),
+        };
+        SeqIn { tree: tree }
+    }
+    /// Adds `value` at the front of this sequence in place.
+    ///
+    /// The chunk at the front is extended where it is unless another
+    /// sequence shares it, so a sequence which is pushed to repeatedly
+    /// without being cloned in between copies no elements, where
+    /// `push_front` copies the whole chunk every time.
+    pub fn push_front_mut(&mut self, value: T) {
+        let room = self.tree.front().is_some_and(|first| first.0.len() < MAX_CHUNK);
+        if room {
+            self.tree.modify_front(|chunk| chunk.0.insert(0, value));
+        } else {
+            self.tree.push_front_mut(Chunk(vec![value]));
+        }
+    }
+    /// Adds `value` at the back of this sequence in place, as for
+    /// `push_front_mut`.
+    pub fn push_back_mut(&mut self, value: T) {
+        let room = self.tree.back().is_some_and(|last| last.0.len() < MAX_CHUNK);
+        if room {
+            self.tree.modify_back(|chunk| chunk.0.push(value));
+        } else {
+            self.tree.push_back_mut(Chunk(vec![value]));
+        }
+    }
+    /// Returns the element at the front of this sequence and the sequence
+    /// without it, or `None` if it is empty.
+    pub fn pop_front(&self) -> Option<(T, Self)> {
+        self.tree.pop_front().map(|(Chunk(mut values), tree)| {
+            let value = values.remove(0);
+            let tree = if values.is_empty() { tree } else { tree.push_front(Chunk(values)) };
+            (value, SeqIn { tree: tree })
+        })
+    }
+    /// Returns the element at the back of this sequence and the sequence
+    /// without it, or `None` if it is empty.
+    pub fn pop_back(&self) -> Option<(T, Self)> {
+        self.tree.pop_back().map(|(Chunk(mut values), tree)| {
+            let value = values.pop().unwrap();
+            let tree = if values.is_empty() { tree } else { tree.push_back(Chunk(values)) };
+            (value, SeqIn { tree: tree })
+        })
+    }
+    /// Returns the element at the front of this sequence, or `None` if it is
+    /// empty.
+    pub fn front(&self) -> Option<&T> {
+        self.tree.front().and_then(|chunk| chunk.0.first())
+    }
+    /// Returns the element at the back of this sequence, or `None` if it is
+    /// empty.
+    pub fn back(&self) -> Option<&T> {
+        self.tree.back().and_then(|chunk| chunk.0.last())
+    }
+    /// Returns the element at index `i`, or `None` if `i` is not less than
+    /// the size of this sequence.
+    pub fn get(&self, i: u32) -> Option<&T> {
+        self.tree
+            .find_with_measure(|size| size.0 > i)
+            .map(|(before, chunk)| &chunk.0[(i - before.0) as usize])
+    }
+    /// Splits this sequence into the elements before index `i` and those from
+    /// index `i` onwards. If `i` is not less than the size of this sequence,
+    /// the second part is empty.
+    pub fn split_at(&self, i: u32) -> (Self, Self) {
+        let (before, after) = self.tree.split(|size| size.0 > i);
+        let offset = i.saturating_sub(before.measure().0) as usize;
+        if offset == 0 || after.is_empty() {
+            return (SeqIn { tree: before }, SeqIn { tree: after });
+        }
+        // The split falls inside the first chunk of `after`.
+        let (Chunk(mut values), after) = after.pop_front().unwrap();
+        let rest = values.split_off(offset);
+        (SeqIn { tree: before.push_back(Chunk(values)) },
+         SeqIn { tree: after.push_front(Chunk(rest)) })
+    }
+    /// Returns a sequence of the first `k` elements of this sequence, or of
+    /// all of them if there are no more than `k`.
+    ///
+    /// Like `split_at`, this splits the tree of chunks rather than visiting
+    /// the elements, and copies at most the one chunk the split falls
+    /// inside, so it takes O(log(min(k, n - k))) time.
+    pub fn take(&self, k: u32) -> Self {
+        self.split_at(k).0
+    }
+    /// Returns a sequence of the elements of this sequence after the first
+    /// `k`, which is empty if there are no more than `k`. This takes
+    /// O(log(min(k, n - k))) time, as for `take`.
+    pub fn drop(&self, k: u32) -> Self {
+        self.split_at(k).1
+    }
+    /// Returns a sequence of the elements of this sequence followed by those
+    /// of `other`.
+    pub fn concat(&self, other: &Self) -> Self {
+        SeqIn { tree: join(&self.tree, &other.tree) }
+    }
+    /// Returns a copy of this sequence with `value` inserted at index `i`,
+    /// moving the elements from index `i` onwards back by one.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::IndexOutOfRange` if `i` is greater than the size
+    /// of this sequence.
+    pub fn insert_at(&self, i: u32, value: T) -> Result<Self, StackError> {
+        if i > self.size() {
+            return Err(StackError::IndexOutOfRange);
+        }
+        let (before, after) = self.split_at(i);
+        Ok(before.push_back(value).concat(&after))
+    }
+    /// Returns the element at index `i` and a copy of this sequence without
+    /// it.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::IndexOutOfRange` if `i` is greater than the
+    /// greatest index currently in this sequence (size - 1).
+    pub fn remove_at(&self, i: u32) -> Result<(T, Self), StackError> {
+        let (before, after) = self.split_at(i);
+        match after.pop_front() {
+            Some((value, after)) => Ok((value, before.concat(&after))),
+            None => Err(StackError::IndexOutOfRange),
+        }
+    }
+    /// Returns a copy of this sequence with the element at index `i` replaced
+    /// by `value`.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::IndexOutOfRange` if `i` is not less than the size
+    /// of this sequence.
+    pub fn update(&self, i: u32, value: T) -> Result<Self, StackError> {
+        self.modify(i, |element| *element = value)
+    }
+    /// Returns a copy of this sequence with the element at index `i` replaced
+    /// by the result of applying `f` to it.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::IndexOutOfRange` if `i` is not less than the size
+    /// of this sequence.
+    pub fn adjust<F>(&self, i: u32, f: F) -> Result<Self, StackError>
+        where F: FnOnce(&T) -> T
+    {
+        self.modify(i, |element| *element = f(element))
+    }
+    /// Returns a copy of this sequence with the elements at indexes `i` and
+    /// `j` exchanged.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::IndexOutOfRange` if either index is not less than
+    /// the size of this sequence.
+    pub fn swap(&self, i: u32, j: u32) -> Result<Self, StackError> {
+        match (self.get(i).cloned(), self.get(j).cloned()) {
+            (Some(at_i), Some(at_j)) => self.update(i, at_j)?.update(j, at_i),
+            _ => Err(StackError::IndexOutOfRange),
+        }
+    }
+    /// Returns a copy of this sequence with the elements in `range` replaced
+    /// by those of `replacement`.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::IndexOutOfRange` if the range starts after it
+    /// ends, or ends after the end of this sequence.
+    pub fn splice(&self, range: Range<u32>, replacement: &Self) -> Result<Self, StackError> {
+        if range.start > range.end || range.end > self.size() {
+            return Err(StackError::IndexOutOfRange);
+        }
+        let (before, rest) = self.split_at(range.start);
+        let (_, after) = rest.split_at(range.end - range.start);
+        Ok(before.concat(replacement).concat(&after))
+    }
+    /// Returns a copy of this sequence with `f` applied to the element at
+    /// index `i`, copying only the chunk which holds it.
+    fn modify<F>(&self, i: u32, f: F) -> Result<Self, StackError>
+        where F: FnOnce(&mut T)
+    {
+        let (before, after) = self.tree.split(|size| size.0 > i);
+        match after.pop_front() {
+            Some((Chunk(mut values), after)) => {
+                f(&mut values[(i - before.measure().0) as usize]);
+                Ok(SeqIn { tree: before.push_back(Chunk(values)).concat(&after) })
+            }
+            None => Err(StackError::IndexOutOfRange),
+        }
+    }
+    /// Returns a cursor just before the element at index `i`, or at the end
+    /// of this sequence if `i` is its size.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::IndexOutOfRange` if `i` is greater than the size
+    /// of this sequence.
+    pub fn cursor(&self, i: u32) -> Result<Cursor<T, P>, StackError> {
+        if i > self.size() {
+            return Err(StackError::IndexOutOfRange);
+        }
+        let (before, after) = self.split_at(i);
+        Ok(Cursor {
+            before: before,
+            local: ListZipperIn::new(&Shared::new(CustomStackIn::Empty)),
+            after: after,
+            position: i,
+        })
+    }
+    /// Returns a sequence of clones of `values`, in the same order, built
+    /// chunk by chunk as `SeqBuilder` builds it.
+    pub fn from_slice(values: &[T]) -> Self {
+        values.iter().cloned().collect()
+    }
+    /// Returns a copy of this sequence with `values` added at the back, in
+    /// order. The new elements are built into a sequence of their own, which
+    /// is then concatenated onto this one, so this takes O(m + log n) time
+    /// for m new elements.
+    pub fn extended<I: IntoIterator<Item = T>>(&self, values: I) -> Self {
+        self.concat(&values.into_iter().collect())
+    }
+    /// Returns an iterator over the elements of this sequence, from front to
+    /// back.
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter {
+            chunks: self.tree.iter(),
+            current: [].iter(),
+        }
+    }
+    /// Returns an iterator over each run of `size` consecutive elements of
+    /// this sequence, front first, as sequences of their own.
+    ///
+    /// Each window is made from the one before by popping its front element
+    /// and pushing the next one onto its back, so consecutive windows share
+    /// their chunks and moving on takes O(1) amortized time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn windows(&self, size: u32) -> Windows<T, P> {
+        assert!(size > 0, "windows must hold at least one element");
+        let (first, rest) = self.split_at(size);
+        Windows {
+            next: if first.size() == size { Some(first) } else { None },
+            rest: rest,
+        }
+    }
+    /// Returns a sequence of `f` of each element of this sequence, in the
+    /// same order. `f` is called on the elements from front to back, and the
+    /// new sequence is built chunk by chunk.
+    pub fn map<U: Clone, F: FnMut(&T) -> U>(&self, mut f: F) -> SeqIn<U, P> {
+        let mut mapped = SeqIn::empty();
+        for chunk in self.tree.iter() {
+            mapped.tree.push_back_mut(Chunk(chunk.0.iter().map(&mut f).collect()));
+        }
+        mapped
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Clone> SeqIn<T, DefaultPointer> {
+    /// Returns the same sequence as `map`, but maps large sequences on
+    /// rayon's thread pool: the sequence is split in half, the halves mapped
+    /// as separate tasks, split again in turn while they are large enough,
+    /// and the results concatenated back together in order.
+    ///
+    /// Splitting and concatenating each take O(log n) time, so this is
+    /// worthwhile when `f` is costly; the result is the same however the
+    /// work is divided.
+    pub fn par_map<U, F>(&self, f: F) -> SeqIn<U, DefaultPointer>
+        where T: MaybeSend + MaybeSync,
+              U: Clone + MaybeSend + MaybeSync,
+              F: Fn(&T) -> U + MaybeSync
+    {
+        self.par_map_with(&f)
+    }
+    /// Maps this sequence as `par_map` does, borrowing `f` so that the tasks
+    /// can share it.
+    fn par_map_with<U, F>(&self, f: &F) -> SeqIn<U, DefaultPointer>
+        where T: MaybeSend + MaybeSync,
+              U: Clone + MaybeSend + MaybeSync,
+              F: Fn(&T) -> U + MaybeSync
+    {
+        if self.size() < PARALLEL_MAP {
+            return self.map(f);
+        }
+        let (front, back) = self.split_at(self.size() / 2);
+        let (front, back) = ::ptr::join(|| front.par_map_with(f), || back.par_map_with(f));
+        front.concat(&back)
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Collection for SeqIn<T, P> {
+    type Iter<'a> = Iter<'a, T, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Default for SeqIn<T, P> {
+    fn default() -> Self {
+        SeqIn::empty()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> FromIterator<T> for SeqIn<T, P> {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        let mut builder = SeqBuilderIn::new();
+        builder.extend(values);
+        builder.freeze()
+    }
+}
+
+impl<'a, T: Clone, P: SharedPointer> IntoIterator for &'a SeqIn<T, P> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+
+    fn into_iter(self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Sequence<T> for SeqIn<T, P> {}
+
+impl<T: Clone, P: SharedPointer> Cons<T> for SeqIn<T, P> {
+    fn cons(&self, value: T) -> Self {
+        self.push_front(value)
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Uncons<T> for SeqIn<T, P> {
+    fn uncons(&self) -> Option<(T, Self)> {
+        self.pop_front()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Snoc<T> for SeqIn<T, P> {
+    fn snoc(&self, value: T) -> Self {
+        self.push_back(value)
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Unsnoc<T> for SeqIn<T, P> {
+    fn unsnoc(&self) -> Option<(Self, T)> {
+        self.pop_back().map(|(value, rest)| (rest, value))
+    }
+}
+
+impl<T: Clone, P: SharedPointer> MapElems<T> for &SeqIn<T, P> {
+    type Output<U: Clone> = SeqIn<U, P>;
+
+    fn map<U: Clone, F: Fn(&T) -> U>(self, f: F) -> SeqIn<U, P> {
+        SeqIn::map(self, f)
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Traverse<T> for &SeqIn<T, P> {
+    fn try_map<U, E, F>(self, mut f: F) -> Result<SeqIn<U, P>, E>
+        where U: Clone,
+              F: FnMut(&T) -> Result<U, E>
+    {
+        let mapped = self.iter().map(&mut f).collect::<Result<Vec<U>, E>>()?;
+        let mut mapped = mapped.into_iter();
+        Ok(self.map(|_| mapped.next().unwrap()))
+    }
+}
+
+impl<T, P: SharedPointer> MemoryUsage for SeqIn<T, P> {
+    fn census(&self, census: &mut Census) {
+        self.tree.census_with(census, |chunk| chunk.0.capacity() * mem::size_of::<T>())
+    }
+}
+
+/// A builder which collects elements for a `Seq` in a vector and packs
+/// them into full chunks once all of them are known.
+///
+/// Pushing the elements onto a sequence one at a time copies the chunk at
+/// the back for every element; freezing a builder copies nothing, and pushes
+/// each chunk onto the tree in place.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::seq::SeqBuilder;
+///
+/// let mut builder = SeqBuilder::new();
+/// builder.extend(0..100);
+/// builder.push_back(100);
+/// let seq = builder.freeze();
+///
+/// assert!(seq.size() == 101);
+/// assert!(*seq.get(100).unwrap() == 100);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SeqBuilderIn<T, P: SharedPointer> {
+    values: Vec<T>,
+    marker: PhantomData<P>,
+}
+
+/// A `SeqBuilderIn` of the sequences exported at the root of the crate.
+pub type SeqBuilder<T> = SeqBuilderIn<T, DefaultPointer>;
+
+impl<T: Clone, P: SharedPointer> SeqBuilderIn<T, P> {
+    /// Returns a builder with no elements.
+    pub fn new() -> Self {
+        SeqBuilderIn {
+            values: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+    /// Adds `value` at the back of the sequence being built.
+    pub fn push_back(&mut self, value: T) {
+        self.values.push(value);
+    }
+    /// Returns the number of elements added so far.
+    pub fn size(&self) -> u32 {
+        self.values.len() as u32
+    }
+    /// Returns a sequence of the elements added, in the order they were
+    /// added.
+    pub fn freeze(self) -> SeqIn<T, P> {
+        let mut seq = SeqIn::empty();
+        let mut values = self.values.into_iter().peekable();
+        while values.peek().is_some() {
+            let chunk: Vec<T> = values.by_ref().take(MAX_CHUNK).collect();
+            seq.tree.push_back_mut(Chunk(chunk));
+        }
+        seq
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Default for SeqBuilderIn<T, P> {
+    fn default() -> Self {
+        SeqBuilderIn::new()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Extend<T> for SeqBuilderIn<T, P> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        self.values.extend(values);
+    }
+}
+
+/// An iterator over the runs of consecutive elements of a `Seq`, returned by
+/// `Seq::windows`.
+#[derive(Debug, Clone)]
+pub struct Windows<T, P: SharedPointer> {
+    /// The next window, or `None` once every one has been returned.
+    next: Option<SeqIn<T, P>>,
+    /// The elements after the next window.
+    rest: SeqIn<T, P>,
+}
+
+impl<T: Clone, P: SharedPointer> Iterator for Windows<T, P> {
+    type Item = SeqIn<T, P>;
+
+    fn next(&mut self) -> Option<SeqIn<T, P>> {
+        let window = self.next.take()?;
+        if let Some((value, rest)) = self.rest.pop_front() {
+            self.next = window.pop_front().map(|(_, kept)| kept.push_back(value));
+            self.rest = rest;
+        }
+        Some(window)
+    }
+}
+
+/// An iterator over the elements of a `Seq`, from front to back.
+pub struct Iter<'a, T: 'a, P: SharedPointer> {
+    chunks: finger_tree::Iter<'a, Chunk<T>, P>,
+    current: slice::Iter<'a, T>,
+}
+
+impl<'a, T, P: SharedPointer> Iterator for Iter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(value) = self.current.next() {
+                return Some(value);
+            }
+            match self.chunks.next() {
+                Some(chunk) => self.current = chunk.0.iter(),
+                None => return None,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, T, P: SharedPointer> Split for Iter<'a, T, P> {
+    fn split(&mut self) -> Option<Self> {
+        if let Some(chunks) = self.chunks.split() {
+            return Some(Iter {
+                chunks: chunks,
+                current: [].iter(),
+            });
+        }
+        // There is at most one chunk left besides the current one.
+        if self.current.len() == 0 {
+            match self.chunks.next() {
+                Some(chunk) => self.current = chunk.0.iter(),
+                None => return None,
+            }
+        } else {
+            let rest = self.chunks.clone();
+            if self.chunks.next().is_some() {
+                return Some(Iter {
+                    chunks: rest,
+                    current: [].iter(),
+                });
+            }
+        }
+        let current = self.current.as_slice();
+        if current.len() < 2 {
+            return None;
+        }
+        let (front, back) = current.split_at(current.len() / 2);
+        self.current = front.iter();
+        Some(Iter {
+            chunks: self.chunks.clone(),
+            current: back.iter(),
+        })
+    }
+}
+
+/// A persistent cursor into a `Seq`, for making many edits close together.
+///
+/// The cursor keeps the elements it has moved past or had inserted at it in
+/// a `ListZipper`, between the untouched parts of the sequence before and
+/// after it. Moving it one step, or inserting, deleting or replacing the
+/// element after it, takes O(1) amortized time, with none of the splitting
+/// and joining that editing the sequence directly costs. `to_seq` puts the
+/// sequence back together in O(k + log n) time, where k is the number of
+/// elements held by the `ListZipper`.
+///
+/// To jump to a distant index, materialize the sequence and make a new
+/// cursor there.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::seq::Seq;
+///
+/// let seq = (0..10).fold(Seq::empty(), |seq, i| seq.push_back(i));
+/// let cursor = seq.cursor(5).unwrap().insert(100).insert(99).right().unwrap();
+///
+/// assert!(cursor.position() == 6);
+/// assert!(cursor.focus() == Some(&100));
+/// let edited = cursor.delete().unwrap().replace(50).unwrap().to_seq();
+/// assert!(edited.iter().cloned().collect::<Vec<_>>() == vec![0, 1, 2, 3, 4, 99, 50, 6, 7, 8, 9]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cursor<T, P: SharedPointer> {
+    before: SeqIn<T, P>,
+    local: ListZipperIn<T, P>,
+    after: SeqIn<T, P>,
+    position: u32,
+}
+
+impl<T: Clone, P: SharedPointer> Cursor<T, P> {
+    /// Returns the index of the element just after this cursor, which is the
+    /// size of the sequence if it is at the end.
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+    /// Returns the element just after this cursor, or `None` if it is at the
+    /// end of the sequence.
+    pub fn focus(&self) -> Option<&T> {
+        self.local.focus().or_else(|| self.after.front())
+    }
+    /// Returns a cursor moved one element towards the front, or `None` if
+    /// this one is at the front.
+    pub fn left(&self) -> Option<Self> {
+        let (before, local) = match self.local.left() {
+            Some(local) => (self.before.clone(), local),
+            None => {
+                let (value, before) = self.before.pop_back()?;
+                (before, self.local.insert(value))
+            }
+        };
+        Some(Cursor {
+            before: before,
+            local: local,
+            after: self.after.clone(),
+            position: self.position - 1,
+        })
+    }
+    /// Returns a cursor moved one element towards the back, past the focus,
+    /// or `None` if this one is at the end.
+    pub fn right(&self) -> Option<Self> {
+        let (local, after) = match self.local.right() {
+            Some(local) => (local, self.after.clone()),
+            None => {
+                let (value, after) = self.after.pop_front()?;
+                (self.local.insert(value).right().unwrap(), after)
+            }
+        };
+        Some(Cursor {
+            before: self.before.clone(),
+            local: local,
+            after: after,
+            position: self.position + 1,
+        })
+    }
+    /// Returns a copy of this cursor with `value` inserted at it, as the new
+    /// focus.
+    pub fn insert(&self, value: T) -> Self {
+        Cursor { local: self.local.insert(value), ..self.clone() }
+    }
+    /// Returns a copy of this cursor with the focus removed, so that the
+    /// element after it becomes the focus.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::NoSuchElementException` if the cursor is at the
+    /// end of the sequence.
+    pub fn delete(&self) -> Result<Self, StackError> {
+        if let Ok(local) = self.local.delete() {
+            return Ok(Cursor { local: local, ..self.clone() });
+        }
+        match self.after.pop_front() {
+            Some((_, after)) => Ok(Cursor { after: after, ..self.clone() }),
+            None => Err(StackError::NoSuchElementException),
+        }
+    }
+    /// Returns a copy of this cursor with the focus replaced by `value`.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::NoSuchElementException` if the cursor is at the
+    /// end of the sequence.
+    pub fn replace(&self, value: T) -> Result<Self, StackError> {
+        self.delete().map(|deleted| deleted.insert(value))
+    }
+    /// Returns the whole sequence, with the edits made through this cursor.
+    pub fn to_seq(&self) -> SeqIn<T, P> {
+        let mut local = &self.local.to_stack();
+        let mut before = self.before.clone();
+        while let CustomStackIn::Cons { ref value, ref tail } = **local {
+            before = before.push_back(value.clone());
+            local = tail;
+        }
+        before.concat(&self.after)
+    }
+}
+
+/// Concatenates two trees of chunks, merging the chunks where they meet if
+/// together they are small enough, so that repeated splits and joins do not
+/// leave the sequence in many tiny pieces.
+fn join<T: Clone, P: SharedPointer>(left: &FingerTreeIn<Chunk<T>, P>,
+                                    right: &FingerTreeIn<Chunk<T>, P>)
+                                    -> FingerTreeIn<Chunk<T>, P> {
+    match (left.back(), right.front()) {
+        (Some(last), Some(first)) if last.0.len() + first.0.len() <= MAX_CHUNK => {}
+        _ => return left.concat(right),
+    }
+    let (Chunk(mut values), left) = left.pop_back().unwrap();
+    let (Chunk(rest), right) = right.pop_front().unwrap();
+    values.extend(rest);
+    left.push_back(Chunk(values)).concat(&right)
+}
+
+#[cfg(test)]
+fn seq_of(values: ::std::ops::Range<u32>) -> Seq<u32> {
+    values.fold(Seq::empty(), |seq, value| seq.push_back(value))
+}
+
+#[cfg(test)]
+fn contents(seq: &Seq<u32>) -> Vec<u32> {
+    seq.iter().cloned().collect()
+}
+
+#[test]
+fn empty_seq_has_nothing() {
+    let seq: Seq<u32> = Seq::empty();
+
+    assert!(seq.is_empty());
+    assert!(seq.size() == 0);
+    assert!(seq.get(0).is_none());
+    assert!(seq.pop_front().is_none());
+    assert!(seq.remove_at(0).is_err());
+    assert!(seq.insert_at(0, 1).unwrap().size() == 1);
+}
+
+#[test]
+fn works_as_a_deque() {
+    let seq = (0..50).fold(Seq::empty(), |seq, i| seq.push_front(i).push_back(i));
+
+    assert!(seq.size() == 100);
+    assert!(*seq.front().unwrap() == 49);
+    assert!(*seq.back().unwrap() == 49);
+
+    let (first, rest) = seq.pop_front().unwrap();
+    let (last, rest) = rest.pop_back().unwrap();
+    assert!(first == 49 && last == 49);
+    assert!(rest.size() == 98);
+    assert!(seq.size() == 100);
+}
+
+#[test]
+fn get_finds_every_index() {
+    let seq = seq_of(0..300).concat(&seq_of(300..1000));
+
+    for i in 0..1000 {
+        assert!(*seq.get(i).unwrap() == i);
+    }
+    assert!(seq.get(1000).is_none());
+}
+
+#[test]
+fn split_at_and_concat_round_trip() {
+    let seq = seq_of(0..100);
+
+    for i in 0..102 {
+        let (before, after) = seq.split_at(i);
+
+        assert!(before.size() == i.min(100));
+        assert!(contents(&before.concat(&after)) == (0..100).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn take_and_drop_split_at_and_across_chunk_boundaries() {
+    let seq = seq_of(0..1000);
+
+    for &k in &[0, 1, 31, 32, 33, 64, 500, 999, 1000, 1001, u32::MAX] {
+        let taken = seq.take(k);
+        let dropped = seq.drop(k);
+        let size = k.min(1000);
+
+        assert!(contents(&taken) == (0..size).collect::<Vec<_>>());
+        assert!(contents(&dropped) == (size..1000).collect::<Vec<_>>());
+        assert!(taken.tree.iter().chain(dropped.tree.iter()).all(|chunk| !chunk.0.is_empty()));
+    }
+    assert!(seq.take(32).tree.iter().count() == 1 && seq.drop(32).tree.iter().count() == 31);
+    assert!(Seq::<u32>::empty().take(5).is_empty() && Seq::<u32>::empty().drop(5).is_empty());
+}
+
+#[test]
+fn elements_are_kept_in_full_chunks() {
+    let seq = seq_of(0..1000);
+    let chunks: Vec<usize> = seq.tree.iter().map(|chunk| chunk.0.len()).collect();
+
+    assert!(chunks.len() == 32);
+    assert!(chunks.iter().all(|&len| len > 0 && len <= MAX_CHUNK));
+    let (before, after) = seq.split_at(500);
+    assert!(before.concat(&after).tree.iter().count() == 32);
+    assert!(contents(&after.concat(&before)) == (500..1000).chain(0..500).collect::<Vec<_>>());
+}
+
+#[test]
+fn pops_cross_chunk_boundaries() {
+    let mut rest = seq_of(0..100).concat(&seq_of(100..200));
+    for i in 0..100 {
+        let (first, remaining) = rest.pop_front().unwrap();
+        let (last, remaining) = remaining.pop_back().unwrap();
+        assert!(first == i && last == 199 - i);
+        assert!(remaining.size() == 198 - 2 * i);
+        rest = remaining;
+    }
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn insert_and_remove_in_the_middle() {
+    let seq = seq_of(0..100);
+
+    let inserted = seq.insert_at(40, 1000).unwrap();
+    assert!(inserted.size() == 101);
+    assert!(*inserted.get(40).unwrap() == 1000);
+    assert!(*inserted.get(41).unwrap() == 40);
+    assert!(seq.insert_at(101, 0).is_err());
+
+    let (removed, rest) = inserted.remove_at(40).unwrap();
+    assert!(removed == 1000);
+    assert!(contents(&rest) == contents(&seq));
+    assert!(seq.remove_at(100).is_err());
+}
+
+#[test]
+fn elements_can_be_updated_in_place() {
+    let seq = seq_of(0..100);
+
+    let updated = seq.update(40, 1000).unwrap().adjust(99, |v| v * 2).unwrap();
+    assert!(*updated.get(40).unwrap() == 1000);
+    assert!(*updated.get(99).unwrap() == 198);
+    assert!(updated.size() == 100);
+    assert!(contents(&seq) == (0..100).collect::<Vec<_>>());
+    assert!(seq.update(100, 0).is_err());
+    assert!(seq.adjust(100, |v| *v).is_err());
+
+    let swapped = seq.swap(3, 70).unwrap();
+    assert!(*swapped.get(3).unwrap() == 70 && *swapped.get(70).unwrap() == 3);
+    assert!(contents(&swapped.swap(70, 3).unwrap()) == contents(&seq));
+    assert!(seq.swap(3, 100).is_err());
+}
+
+#[test]
+fn splice_replaces_a_range() {
+    let seq = seq_of(0..100);
+
+    let spliced = seq.splice(10..60, &seq_of(1000..1003)).unwrap();
+    let expected: Vec<u32> = (0..10).chain(1000..1003).chain(60..100).collect();
+    assert!(contents(&spliced) == expected);
+    assert!(contents(&seq.splice(50..50, &seq_of(0..2)).unwrap()).len() == 102);
+    assert!(seq.splice(0..100, &Seq::empty()).unwrap().is_empty());
+    assert!(seq.splice(50..101, &Seq::empty()).is_err());
+    assert!(seq.splice(60..50, &Seq::empty()).is_err());
+}
+
+#[test]
+fn cursor_walks_across_the_whole_sequence() {
+    let seq = seq_of(0..100);
+    assert!(seq.cursor(101).is_err());
+
+    let mut cursor = seq.cursor(50).unwrap();
+    for i in 50..100 {
+        assert!(cursor.focus() == Some(&i) && cursor.position() == i);
+        cursor = cursor.right().unwrap();
+    }
+    assert!(cursor.focus().is_none() && cursor.right().is_none());
+    for i in (0..100).rev() {
+        cursor = cursor.left().unwrap();
+        assert!(cursor.focus() == Some(&i) && cursor.position() == i);
+    }
+    assert!(cursor.left().is_none());
+    assert!(contents(&cursor.to_seq()) == contents(&seq));
+}
+
+#[test]
+fn cursor_edits_match_direct_edits() {
+    let seq = seq_of(0..100);
+
+    let mut cursor = seq.cursor(30).unwrap();
+    let mut expected = seq.clone();
+    for i in 0..1000 {
+        cursor = match i % 4 {
+            0 | 1 => cursor.insert(i),
+            2 => cursor.replace(i).unwrap().right().unwrap(),
+            _ => cursor.delete().unwrap().left().unwrap(),
+        };
+        expected = match i % 4 {
+            0 | 1 => expected.insert_at(cursor.position(), i).unwrap(),
+            2 => expected.update(cursor.position() - 1, i).unwrap(),
+            _ => {
+                let (_, removed) = expected.remove_at(cursor.position() + 1).unwrap();
+                removed
+            }
+        };
+    }
+    assert!(contents(&cursor.to_seq()) == contents(&expected));
+    assert!(contents(&seq) == (0..100).collect::<Vec<_>>());
+    assert!(seq.cursor(100).unwrap().delete().is_err());
+}
+
+#[test]
+fn in_place_pushes_match_persistent_ones() {
+    let mut seq = Seq::empty();
+    let mut snapshot = Seq::empty();
+    for i in 0..500 {
+        seq.push_back_mut(i);
+        seq.push_front_mut(-i);
+        if i == 100 {
+            snapshot = seq.clone();
+        }
+    }
+    let expected = (0..500).fold(Seq::empty(), |seq, i| seq.push_back(i).push_front(-i));
+    assert!(seq.size() == 1000 && seq.iter().eq(expected.iter()));
+    assert!(*seq.get(499).unwrap() == 0 && *seq.get(999).unwrap() == 499);
+    assert!(snapshot.size() == 202 && *snapshot.back().unwrap() == 100);
+}
+
+#[test]
+fn builders_freeze_into_full_chunks() {
+    let mut builder = SeqBuilder::new();
+    assert!(builder.clone().freeze().is_empty());
+    builder.extend(0..999);
+    builder.push_back(999);
+    assert!(builder.size() == 1000);
+    let seq = builder.freeze();
+
+    assert!(contents(&seq) == (0..1000).collect::<Vec<_>>());
+    assert!(seq.tree.iter().count() == 32);
+    assert!(seq.push_back(1000).size() == 1001);
+}
+
+#[test]
+fn mapping_keeps_order() {
+    let seq = (0..1000).fold(Seq::empty(), |seq, i| seq.push_front(i).push_back(i));
+    let mut visited = Vec::new();
+    let mapped = seq.map(|value| {
+        visited.push(*value);
+        value.to_string()
+    });
+
+    assert!(visited == seq.iter().cloned().collect::<Vec<_>>());
+    assert!(mapped.size() == 2000 && mapped.get(0) == Some(&"999".to_string()));
+    assert!(mapped.iter().eq(seq.iter().map(|value| value.to_string()).collect::<Vec<_>>().iter()));
+    assert!(Seq::<u32>::empty().map(|value| *value).is_empty());
+}
+
+#[test]
+fn sequences_can_be_collected_and_extended() {
+    let collected: Seq<u32> = (0..100).collect();
+    let sliced = Seq::from_slice(&[0, 1, 2]);
+    let extended = sliced.extended(3..100);
+
+    assert!(contents(&collected) == (0..100).collect::<Vec<_>>());
+    assert!(collected.tree.iter().count() == 4);
+    assert!(contents(&extended) == contents(&collected) && contents(&sliced) == vec![0, 1, 2]);
+    assert!(sliced.extended(None).size() == 3 && Seq::<u32>::from_slice(&[]).is_empty());
+
+    let mut total = 0;
+    for value in &extended {
+        total += *value;
+    }
+    assert!(total == 4950);
+}
+
+#[test]
+fn windows_slide_one_element_at_a_time() {
+    let seq: Seq<u32> = (0..100).collect();
+    let windows: Vec<Vec<u32>> = seq.windows(10).map(|window| contents(&window)).collect();
+
+    assert!(windows.len() == 91);
+    for (i, window) in windows.iter().enumerate() {
+        assert!(*window == (i as u32..i as u32 + 10).collect::<Vec<_>>());
+    }
+    assert!(seq.windows(100).count() == 1 && seq.windows(101).count() == 0);
+    assert!(Seq::<u32>::empty().windows(1).next().is_none());
+}
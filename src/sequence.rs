@@ -0,0 +1,125 @@
+use collection::Collection;
+
+/// Implemented by the structures in this crate which hold their items in a
+/// sequence built up and taken apart at its ends. An empty sequence is its
+/// `Default`.
+///
+/// What can be done at each end is given by the capability traits `Cons`,
+/// `Uncons`, `Snoc` and `Unsnoc`, which each structure implements where it
+/// can do so cheaply. Code which needs a certain set of them can ask for
+/// exactly those, or for one of `LifoSequence`, `FifoSequence` and
+/// `DequeSequence`, which every structure with the matching capabilities
+/// implements, and so work with the cheapest structure which provides
+/// them:
+///
+/// | Structure     | `cons` | `uncons` | `snoc` | `unsnoc` |
+/// |---------------|--------|----------|--------|----------|
+/// | `CustomStack` | yes    | yes      |        |          |
+/// | `Stream`      | yes    | yes      |        |          |
+/// | `Seq`         | yes    | yes      | yes    | yes      |
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::sequence::{FifoSequence, LifoSequence};
+/// use functional_datastructures::seq::Seq;
+/// use functional_datastructures::stack::CustomStack;
+///
+/// fn undo_order<S: LifoSequence<u32>>() -> Vec<u32> {
+///     let mut stack = (1..4).fold(S::default(), |stack, i| stack.cons(i));
+///     let mut order = Vec::new();
+///     while let Some((value, rest)) = stack.uncons() {
+///         order.push(value);
+///         stack = rest;
+///     }
+///     order
+/// }
+///
+/// fn arrival_order<Q: FifoSequence<u32>>() -> Vec<u32> {
+///     let queue = (1..4).fold(Q::default(), |queue, i| queue.snoc(i));
+///     let (first, rest) = queue.uncons().unwrap();
+///     vec![first, rest.uncons().unwrap().0]
+/// }
+///
+/// assert!(undo_order::<CustomStack<u32>>() == vec![3, 2, 1]);
+/// assert!(undo_order::<Seq<u32>>() == vec![3, 2, 1]);
+/// assert!(arrival_order::<Seq<u32>>() == vec![1, 2]);
+/// ```
+pub trait Sequence<T>: Collection + Default + Clone {}
+
+/// A sequence which can have items added at the front.
+pub trait Cons<T>: Sequence<T> {
+    /// Returns a copy of this sequence with `value` added at the front.
+    fn cons(&self, value: T) -> Self;
+}
+
+/// A sequence which can have items taken from the front.
+pub trait Uncons<T>: Sequence<T> {
+    /// Returns the item at the front of this sequence and the sequence
+    /// without it, or `None` if it is empty.
+    fn uncons(&self) -> Option<(T, Self)>;
+}
+
+/// A sequence which can have items added at the back.
+pub trait Snoc<T>: Sequence<T> {
+    /// Returns a copy of this sequence with `value` added at the back.
+    fn snoc(&self, value: T) -> Self;
+}
+
+/// A sequence which can have items taken from the back.
+pub trait Unsnoc<T>: Sequence<T> {
+    /// Returns the sequence without the item at its back, and that item, or
+    /// `None` if it is empty.
+    fn unsnoc(&self) -> Option<(Self, T)>;
+}
+
+/// A sequence used as a stack: items are added and taken at the front.
+pub trait LifoSequence<T>: Cons<T> + Uncons<T> {}
+
+impl<T, S: Cons<T> + Uncons<T>> LifoSequence<T> for S {}
+
+/// A sequence used as a queue: items are added at the back and taken from
+/// the front.
+pub trait FifoSequence<T>: Snoc<T> + Uncons<T> {}
+
+impl<T, S: Snoc<T> + Uncons<T>> FifoSequence<T> for S {}
+
+/// A sequence used as a double-ended queue: items are added and taken at
+/// both ends.
+pub trait DequeSequence<T>: Cons<T> + Uncons<T> + Snoc<T> + Unsnoc<T> {}
+
+impl<T, S: Cons<T> + Uncons<T> + Snoc<T> + Unsnoc<T>> DequeSequence<T> for S {}
+
+#[cfg(test)]
+use seq::Seq;
+#[cfg(test)]
+use stack::CustomStack;
+#[cfg(test)]
+use stream::Stream;
+
+#[cfg(test)]
+fn drain_front<S: Uncons<u32>>(mut sequence: S) -> Vec<u32> {
+    let mut items = Vec::new();
+    while let Some((value, rest)) = sequence.uncons() {
+        items.push(value);
+        sequence = rest;
+    }
+    items
+}
+
+#[test]
+fn each_end_behaves_the_same_across_structures() {
+    fn pushed<S: Cons<u32>>() -> S {
+        (0..5).fold(S::default(), |sequence, i| sequence.cons(i))
+    }
+
+    assert!(drain_front(pushed::<CustomStack<u32>>()) == vec![4, 3, 2, 1, 0]);
+    assert!(drain_front(pushed::<Stream<u32>>()) == vec![4, 3, 2, 1, 0]);
+    assert!(drain_front(pushed::<Seq<u32>>()) == vec![4, 3, 2, 1, 0]);
+
+    let deque = (0..5).fold(Seq::default(), |deque: Seq<u32>, i| deque.snoc(i).cons(10 + i));
+    let (rest, last) = deque.unsnoc().unwrap();
+    assert!(last == 4 && rest.len() == 9 && deque.uncons().unwrap().0 == 14);
+    let empty: Seq<u32> = Seq::default();
+    assert!(drain_front(empty.clone()).is_empty() && empty.unsnoc().is_none());
+}
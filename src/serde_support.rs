@@ -0,0 +1,532 @@
+// Implementations of `serde::Serialize` and `serde::Deserialize`, available
+// with the `serde` feature.
+//
+// Structures are written out as the plain sequences and maps they represent,
+// never their internal shape, so the format does not change with the
+// implementation and can be produced by hand. Reading one back rebuilds it
+// from scratch, so trees come back balanced and tries canonical.
+//
+// Maps are written as maps, except those keyed by byte strings, which most
+// formats cannot use as map keys; those are written as sequences of pairs, as
+// are `RangeMap` and `IntervalMap`. `Rope` is written as a string and
+// `Multiset` as a map from each value to its count.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+use std::ops::Range;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use finger_tree::FingerTree;
+use hamt::{HamtMap, HamtSet};
+use interval_map::{Interval, IntervalMap};
+use intmap::{IntMap, IntSet};
+use measure::{Count, Max, Measured, Min, Monoid, Sum};
+use multimap::Multimap;
+use multiset::Multiset;
+use ord_seq::{OrdSeqMap, OrdSeqSet};
+use priority_seq::PrioritySeq;
+use ptr::Shared;
+use range_map::RangeMap;
+use rope::Rope;
+use segment_tree::SegmentTree;
+use seq::Seq;
+use set::{Map, Set, Tree};
+use skip_list::SkipListMap;
+use sorted_list::SortedList;
+use stack::CustomStack;
+use trie::TrieMap;
+use art::ArtMap;
+
+impl<T: Serialize> Serialize for CustomStack<T> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let mut values = Vec::new();
+        let mut rest = self;
+        while let CustomStack::Cons { ref value, ref tail } = *rest {
+            values.push(value);
+            rest = tail;
+        }
+        serializer.collect_seq(values)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for CustomStack<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        Ok(values.into_iter().rev().fold(CustomStack::Empty, |tail, value| {
+            CustomStack::Cons {
+                value: value,
+                tail: Shared::new(tail),
+            }
+        }))
+    }
+}
+
+impl<K: Ord + Clone + Serialize, V: Clone + Serialize> Serialize for Tree<K, V> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let mut entries = Vec::new();
+        in_order(self, &mut entries);
+        serializer.collect_map(entries)
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for Tree<K, V>
+    where K: Ord + Clone + Deserialize<'de>,
+          V: Clone + Deserialize<'de>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries: Vec<(K, V)> = BTreeMap::deserialize(deserializer)?.into_iter().collect();
+        Ok(balanced(&entries))
+    }
+}
+
+/// Pushes the bindings in `tree` onto `out` in order of key.
+fn in_order<'a, K: Ord + Clone, V: Clone>(tree: &'a Tree<K, V>, out: &mut Vec<(&'a K, &'a V)>) {
+    if let Tree::Node { ref left, ref key, ref value, ref right } = *tree {
+        in_order(left, out);
+        out.push((key, value));
+        in_order(right, out);
+    }
+}
+
+/// Returns a balanced tree of `entries`, which must be in order of key.
+fn balanced<K: Ord + Clone, V: Clone>(entries: &[(K, V)]) -> Tree<K, V> {
+    if entries.is_empty() {
+        return Tree::Empty;
+    }
+    let middle = entries.len() / 2;
+    Tree::Node {
+        left: Shared::new(balanced(&entries[..middle])),
+        key: entries[middle].0.clone(),
+        value: entries[middle].1.clone(),
+        right: Shared::new(balanced(&entries[middle + 1..])),
+    }
+}
+
+impl<K, V, S> Serialize for HamtMap<K, V, S>
+    where K: Hash + Eq + Clone + Serialize,
+          V: Clone + Serialize,
+          S: BuildHasher + Clone
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for HamtMap<K, V, S>
+    where K: Hash + Eq + Clone + Deserialize<'de>,
+          V: Clone + Deserialize<'de>,
+          S: BuildHasher + Clone + Default
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = HashMap::<K, V>::deserialize(deserializer)?;
+        Ok(entries.into_iter().fold(HamtMap::default(), |map, (key, value)| map.insert(key, value)))
+    }
+}
+
+impl<T, S> Serialize for HamtSet<T, S>
+    where T: Hash + Eq + Clone + Serialize,
+          S: BuildHasher + Clone
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T, S> Deserialize<'de> for HamtSet<T, S>
+    where T: Hash + Eq + Clone + Deserialize<'de>,
+          S: BuildHasher + Clone + Default
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        Ok(values.into_iter().fold(HamtSet::default(), |set, value| set.insert(value)))
+    }
+}
+
+impl<V: Clone + Serialize> Serialize for IntMap<V> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de, V: Clone + Deserialize<'de>> Deserialize<'de> for IntMap<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = BTreeMap::<u64, V>::deserialize(deserializer)?;
+        Ok(entries.into_iter()
+                  .fold(IntMap::empty_map(), |map, (key, value)| map.insert(key, value)))
+    }
+}
+
+impl Serialize for IntSet {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for IntSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<u64>::deserialize(deserializer)?;
+        Ok(values.into_iter().fold(IntSet::empty(), |set, value| set.insert(value)))
+    }
+}
+
+impl<V: Clone + Serialize> Serialize for TrieMap<V> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, V: Clone + Deserialize<'de>> Deserialize<'de> for TrieMap<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<(Vec<u8>, V)>::deserialize(deserializer)?;
+        Ok(entries.into_iter().fold(TrieMap::empty(), |map, (key, value)| map.insert(key, value)))
+    }
+}
+
+impl<V: Clone + Serialize> Serialize for ArtMap<V> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, V: Clone + Deserialize<'de>> Deserialize<'de> for ArtMap<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<(Vec<u8>, V)>::deserialize(deserializer)?;
+        Ok(entries.into_iter().fold(ArtMap::empty(), |map, (key, value)| map.insert(key, value)))
+    }
+}
+
+impl<T: Measured + Clone + Serialize> Serialize for FingerTree<T> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Measured + Clone + Deserialize<'de>> Deserialize<'de> for FingerTree<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        Ok(values.into_iter().fold(FingerTree::empty(), |tree, value| tree.push_back(value)))
+    }
+}
+
+impl<T: Clone + Serialize> Serialize for Seq<T> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Clone + Deserialize<'de>> Deserialize<'de> for Seq<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        Ok(values.into_iter().fold(Seq::empty(), |seq, value| seq.push_back(value)))
+    }
+}
+
+impl<K: Ord + Clone + Serialize, V: Clone + Serialize> Serialize for OrdSeqMap<K, V> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for OrdSeqMap<K, V>
+    where K: Ord + Clone + Deserialize<'de>,
+          V: Clone + Deserialize<'de>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = BTreeMap::<K, V>::deserialize(deserializer)?;
+        Ok(entries.into_iter()
+                  .fold(OrdSeqMap::empty_map(), |map, (key, value)| map.insert(key, value)))
+    }
+}
+
+impl<T: Ord + Clone + Serialize> Serialize for OrdSeqSet<T> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Ord + Clone + Deserialize<'de>> Deserialize<'de> for OrdSeqSet<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        Ok(values.into_iter().fold(OrdSeqSet::empty(), |set, value| set.insert(value)))
+    }
+}
+
+impl<T: Ord + Clone + Serialize> Serialize for PrioritySeq<T> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Ord + Clone + Deserialize<'de>> Deserialize<'de> for PrioritySeq<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        Ok(values.into_iter().fold(PrioritySeq::empty(), |seq, value| seq.push_back(value)))
+    }
+}
+
+impl<K: Ord + Clone + Serialize, V: Clone + Serialize> Serialize for SkipListMap<K, V> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for SkipListMap<K, V>
+    where K: Ord + Clone + Deserialize<'de>,
+          V: Clone + Deserialize<'de>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = BTreeMap::<K, V>::deserialize(deserializer)?;
+        Ok(entries.into_iter()
+                  .fold(SkipListMap::empty_map(), |map, (key, value)| map.insert(key, value)))
+    }
+}
+
+impl<P: Ord + Clone + Serialize, V: Clone + Serialize> Serialize for IntervalMap<P, V> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter()
+                                   .map(|(interval, value)| {
+                                       ((&interval.low, &interval.high), value)
+                                   }))
+    }
+}
+
+impl<'de, P, V> Deserialize<'de> for IntervalMap<P, V>
+    where P: Ord + Clone + Deserialize<'de>,
+          V: Clone + Deserialize<'de>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<((P, P), V)>::deserialize(deserializer)?;
+        Ok(entries.into_iter().fold(IntervalMap::empty_map(), |map, ((low, high), value)| {
+            map.insert(Interval::new(low, high), value)
+        }))
+    }
+}
+
+impl Serialize for Rope {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Rope {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Ok(Rope::from(&*text))
+    }
+}
+
+impl<T, S> Serialize for Multiset<T, S>
+    where T: Hash + Eq + Clone + Serialize,
+          S: BuildHasher + Clone
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de, T, S> Deserialize<'de> for Multiset<T, S>
+    where T: Hash + Eq + Clone + Deserialize<'de>,
+          S: BuildHasher + Clone + Default
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let counts = HashMap::<T, u32>::deserialize(deserializer)?;
+        Ok(counts.into_iter().fold(Multiset::default(), |multiset, (value, count)| {
+            (0..count).fold(multiset, |multiset, _| multiset.insert(value.clone()))
+        }))
+    }
+}
+
+impl<K, V, S> Serialize for Multimap<K, V, S>
+    where K: Hash + Eq + Clone + Serialize,
+          V: Hash + Eq + Clone + Serialize,
+          S: BuildHasher + Clone
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_map(self.keys().map(|key| (key, self.get_all(key))))
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for Multimap<K, V, S>
+    where K: Hash + Eq + Clone + Deserialize<'de>,
+          V: Hash + Eq + Clone + Deserialize<'de>,
+          S: BuildHasher + Clone + Default
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let buckets = HashMap::<K, Vec<V>>::deserialize(deserializer)?;
+        Ok(buckets.into_iter().fold(Multimap::default(), |multimap, (key, values)| {
+            values.into_iter().fold(multimap, |multimap, value| multimap.insert(key.clone(), value))
+        }))
+    }
+}
+
+impl<K: Ord + Clone + Serialize, V: Clone + PartialEq + Serialize> Serialize for RangeMap<K, V> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for RangeMap<K, V>
+    where K: Ord + Clone + Deserialize<'de>,
+          V: Clone + PartialEq + Deserialize<'de>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<(Range<K>, V)>::deserialize(deserializer)?;
+        Ok(entries.into_iter()
+                  .fold(RangeMap::empty(), |map, (range, value)| map.insert(range, value)))
+    }
+}
+
+// The monoids in `measure` are written as the value they wrap, so that a
+// `SegmentTree` of them is written as a plain sequence of values.
+macro_rules! transparent {
+    ($monoid:ident, $inner:ty $(, $param:ident)*) => {
+        impl<$($param: Serialize),*> Serialize for $monoid<$($param),*> {
+            fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+                self.0.serialize(serializer)
+            }
+        }
+
+        impl<'de, $($param: Deserialize<'de>),*> Deserialize<'de> for $monoid<$($param),*> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                <$inner>::deserialize(deserializer).map($monoid)
+            }
+        }
+    }
+}
+
+transparent!(Count, u32);
+transparent!(Sum, T, T);
+transparent!(Max, Option<T>, T);
+transparent!(Min, Option<T>, T);
+
+impl<M: Monoid + Serialize> Serialize for SegmentTree<M> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq((0..self.size()).map(|i| self.get(i).unwrap()))
+    }
+}
+
+impl<'de, M: Monoid + Deserialize<'de>> Deserialize<'de> for SegmentTree<M> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<M>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+impl<T: Ord + Clone + Serialize> Serialize for SortedList<T> {
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Ord + Clone + Deserialize<'de>> Deserialize<'de> for SortedList<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut values = Vec::<T>::deserialize(deserializer)?;
+        values.sort();
+        // Inserting from the greatest down puts each value at the front, so
+        // the list is built in linear time.
+        Ok(values.into_iter().rev().fold(SortedList::empty(), |list, value| list.insert(value)))
+    }
+}
+
+#[cfg(test)]
+use serde_json;
+
+#[cfg(test)]
+fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(value: &T) -> (String, T) {
+    let json = serde_json::to_string(value).unwrap();
+    let back = serde_json::from_str(&json).unwrap();
+    (json, back)
+}
+
+#[test]
+fn stacks_and_sequences_are_written_as_sequences() {
+    let stack = CustomStack::Cons {
+        value: 1,
+        tail: Shared::new(CustomStack::Cons {
+            value: 2,
+            tail: Shared::new(CustomStack::Empty),
+        }),
+    };
+    let (json, back) = round_trip(&stack);
+    assert!(json == "[1,2]");
+    assert!(serde_json::to_string(&back).unwrap() == json);
+
+    let seq = (0..100).fold(Seq::empty(), |seq, i| seq.push_back(i));
+    let (json, back) = round_trip(&seq);
+    assert!(json == serde_json::to_string(&(0..100).collect::<Vec<_>>()).unwrap());
+    assert!(back.iter().cloned().eq(0..100));
+
+    let list: SortedList<u32> = serde_json::from_str("[5, 1, 3, 1]").unwrap();
+    assert!(list.iter().cloned().collect::<Vec<_>>() == vec![1, 1, 3, 5]);
+    assert!(round_trip(&list).0 == "[1,1,3,5]");
+
+    let priorities = PrioritySeq::empty().push_back(3).push_back(9);
+    assert!(round_trip(&priorities).1.max() == Some(&9));
+    let sums: SegmentTree<Sum<u32>> = serde_json::from_str("[1, 2, 3]").unwrap();
+    assert!(sums.total() == Sum(6));
+    assert!(round_trip(&sums).0 == "[1,2,3]");
+    let rope = Rope::from("hello\nworld");
+    assert!(round_trip(&rope) == ("\"hello\\nworld\"".to_string(), rope));
+}
+
+#[test]
+fn trees_come_back_balanced() {
+    let degenerate = (0..1000).fold(Tree::empty_map(), |tree, i| tree.bind(i, i * 2));
+    let (json, back) = round_trip(&degenerate);
+
+    assert!(json.starts_with("{\"0\":0,\"1\":2,"));
+    fn depth<K: Ord + Clone, V: Clone>(tree: &Tree<K, V>) -> u32 {
+        match *tree {
+            Tree::Empty => 0,
+            Tree::Node { ref left, ref right, .. } => 1 + depth(left).max(depth(right)),
+        }
+    }
+    assert!(depth(&degenerate) == 1000);
+    assert!(depth(&back) == 10);
+    assert!((0..1000).all(|i| back.lookup(i) == Some(i * 2)));
+}
+
+#[test]
+fn maps_and_sets_are_written_as_maps_and_sequences() {
+    let hamt = (0..50).fold(HamtMap::new(), |map, i| map.insert(i.to_string(), i));
+    let back: HamtMap<String, u32> = round_trip(&hamt).1;
+    assert!(back.diff(&hamt).is_empty());
+
+    let set: HamtSet<u32> = serde_json::from_str("[1, 2, 2, 3]").unwrap();
+    assert!(set.size() == 3);
+    let ints: IntMap<&str> = serde_json::from_str("{\"7\": \"seven\"}").unwrap();
+    assert!(ints.lookup(7) == Some("seven"));
+    assert!(round_trip(&IntSet::empty().insert(4).insert(2)).0 == "[2,4]");
+
+    let ord: OrdSeqMap<u32, char> = serde_json::from_str("{\"2\": \"b\", \"1\": \"a\"}").unwrap();
+    assert!(round_trip(&ord).0 == "{\"1\":\"a\",\"2\":\"b\"}");
+    let ord_set: OrdSeqSet<u32> = serde_json::from_str("[3, 1, 2]").unwrap();
+    assert!(round_trip(&ord_set).0 == "[1,2,3]");
+    let skip: SkipListMap<u32, u32> = serde_json::from_str("{\"2\": 4, \"1\": 1}").unwrap();
+    assert!(round_trip(&skip).0 == "{\"1\":1,\"2\":4}");
+
+    let trie = TrieMap::empty().insert("ab", 1).insert("a", 2);
+    assert!(round_trip(&trie).0 == "[[[97],2],[[97,98],1]]");
+    let art: ArtMap<u32> = round_trip(&ArtMap::empty().insert("key", 3)).1;
+    assert!(art.lookup("key") == Some(3));
+}
+
+#[test]
+fn bags_and_ranges_round_trip() {
+    let multiset = Multiset::new().insert('a').insert('a').insert('b');
+    let back = round_trip(&multiset).1;
+    assert!(back.count(&'a') == 2 && back.size() == 3);
+
+    let multimap: Multimap<String, u32> = serde_json::from_str("{\"a\": [1, 2], \"b\": [3]}")
+                                              .unwrap();
+    assert!(multimap.size() == 3 && multimap.contains(&"a".to_string(), &2));
+    assert!(round_trip(&multimap).1.size() == 3);
+
+    let ranges = RangeMap::empty().insert(0..10, 'a').insert(5..20, 'b');
+    let (json, back) = round_trip(&ranges);
+    assert!(json == "[[{\"start\":0,\"end\":5},\"a\"],[{\"start\":5,\"end\":20},\"b\"]]");
+    assert!(back.get(&7) == Some(&'b'));
+
+    let intervals = IntervalMap::empty_map().insert(Interval::new(1, 5), 'x');
+    assert!(round_trip(&intervals).0 == "[[[1,5],\"x\"]]");
+}
@@ -1,9 +1,27 @@
-use std::sync::Arc;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::mem;
+
+use collection::{Collection, MapElems, Traverse};
+use memory::{Census, MemoryUsage};
+#[cfg(feature = "rayon")]
+use par::Split;
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+#[cfg(feature = "rayon")]
+use ptr::{join, MaybeSend, MaybeSync};
+use stack::{CustomStackIn, StackBuilderIn};
 
 #[cfg(test)]
 use std::cmp;
 
+/// The number of levels at the top of a tree at which the parallel
+/// operations work on the two subtrees of each node as separate tasks.
+#[cfg(feature = "rayon")]
+const PARALLEL_DEPTH: u32 = 10;
+
 /// A trait representing an immutable Set type.
 pub trait Set<T: Eq> {
     /// Returns a set containing nothing.
@@ -12,6 +30,14 @@ pub trait Set<T: Eq> {
     fn insert(&self, value: T) -> Self;
     /// Tests whether `value` is a member of this set.
     fn member(&self, value: T) -> bool;
+    /// Adds `value` to this set in place. Implementations update the nodes
+    /// which are not shared with another set where they are, rather than
+    /// copying them as `insert` does.
+    fn insert_mut(&mut self, value: T)
+        where Self: Sized
+    {
+        *self = self.insert(value);
+    }
 }
 
 /// A trait representing an immutable Map type.
@@ -23,6 +49,114 @@ pub trait Map<K: Eq, V> {
     /// Returns a `Some` of the value bound to `key`, or `None`
     /// if `key` is not a key in this map.
     fn lookup(&self, key: K) -> Option<V>;
+    /// Binds `key` to `value` in this map in place. Implementations update
+    /// the nodes which are not shared with another map where they are,
+    /// rather than copying them as `bind` does.
+    fn bind_mut(&mut self, key: K, value: V)
+        where Self: Sized
+    {
+        *self = self.bind(key, value);
+    }
+    /// Returns the value bound to `key` along with this map, or if `key` is
+    /// not bound, `default()` along with a copy of this map binding `key` to
+    /// it. `default` is only called if `key` is not bound.
+    ///
+    /// Implementations find the binding and the place to add it in a single
+    /// traversal where they can, so that a cache kept in a map searches it
+    /// once per miss rather than twice.
+    fn get_or_insert_with<F>(&self, key: &K, default: F) -> (V, Self)
+        where Self: Sized + Clone,
+              K: Clone,
+              V: Clone,
+              F: FnOnce() -> V
+    {
+        match self.lookup(key.clone()) {
+            Some(value) => (value, self.clone()),
+            None => {
+                let value = default();
+                (value.clone(), self.bind(key.clone(), value))
+            }
+        }
+    }
+}
+
+/// A `Set` which can also have members removed, be iterated over and be
+/// combined with other sets, for programming against any of the set
+/// structures in this crate. Code written against this trait can switch
+/// between them by changing a type alias.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::collection::Collection;
+/// use functional_datastructures::hamt::HamtSet;
+/// use functional_datastructures::set::{PersistentSet, Set, Tree};
+///
+/// fn without_evens<S: PersistentSet<u32>>(set: S) -> S {
+///     (0..10).filter(|i| i % 2 == 0).fold(set, |set, i| set.remove(&i))
+/// }
+///
+/// let tree: Tree<u32, ()> = (0..10).fold(Tree::empty(), |set, i| set.insert(i));
+/// let hamt = (0..10).fold(HamtSet::new(), |set, i| set.insert(i));
+///
+/// assert!(without_evens(tree).len() == 5);
+/// assert!(!without_evens(hamt).contains(&4));
+/// ```
+pub trait PersistentSet<T: Eq>: Set<T> + Collection + Clone {
+    /// Tests whether `value` is a member of this set.
+    fn contains(&self, value: &T) -> bool;
+    /// Returns a copy of this set without `value`.
+    fn remove(&self, value: &T) -> Self;
+    /// Returns a set of the values which are members of either this set or
+    /// `other`.
+    fn union(&self, other: &Self) -> Self;
+    /// Returns a set of the values which are members of both this set and
+    /// `other`.
+    fn intersection(&self, other: &Self) -> Self;
+    /// Returns a set of the values which are members of this set but not of
+    /// `other`.
+    fn difference(&self, other: &Self) -> Self;
+}
+
+/// A `Map` which can also have bindings removed, be iterated over and be
+/// combined with other maps, for programming against any of the map
+/// structures in this crate. Code written against this trait can switch
+/// between them by changing a type alias.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::hamt::HamtMap;
+/// use functional_datastructures::set::{Map, PersistentMap, Tree};
+///
+/// fn tally<M: PersistentMap<char, u32>>(text: &str) -> M {
+///     text.chars().fold(M::empty_map(), |counts, c| {
+///         let count = counts.get(&c).cloned().unwrap_or(0);
+///         counts.bind(c, count + 1)
+///     })
+/// }
+///
+/// assert!(tally::<Tree<char, u32>>("hello").get(&'l') == Some(&2));
+/// assert!(tally::<HamtMap<char, u32>>("hello").remove(&'l').get(&'l').is_none());
+/// ```
+pub trait PersistentMap<K: Eq, V>: Map<K, V> + Collection + Clone {
+    /// Returns the value bound to `key`, or `None` if `key` is not bound.
+    fn get(&self, key: &K) -> Option<&V>;
+    /// Tests whether `key` is bound in this map.
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+    /// Returns a copy of this map without any binding for `key`.
+    fn remove(&self, key: &K) -> Self;
+    /// Returns a map of the bindings in this map and `other`. Where a key is
+    /// bound in both, the binding from this map is kept.
+    fn union(&self, other: &Self) -> Self;
+    /// Returns a map of the bindings in this map whose keys are also bound in
+    /// `other`.
+    fn intersection(&self, other: &Self) -> Self;
+    /// Returns a map of the bindings in this map whose keys are not bound in
+    /// `other`.
+    fn difference(&self, other: &Self) -> Self;
 }
 
 /// An unbalanced binary tree that can be used as both an immutable `Map`
@@ -36,8 +170,8 @@ pub trait Map<K: Eq, V> {
 /// Using the type as a map:
 /// 
 /// ```
-/// use functional_datastructures::set::Map;
-/// use functional_datastructures::set::Tree;
+/// use functional_datastructures::prelude::*;
+/// use functional_datastructures::Tree;
 ///
 /// let empty_map: Tree<u32,String> = Tree::empty_map();
 /// let map_with_values = empty_map.bind(1, "Harold".to_string())
@@ -63,176 +197,1010 @@ pub trait Map<K: Eq, V> {
 /// 
 /// 
 /// ```
-/// use functional_datastructures::set::Set;
-/// use functional_datastructures::set::Tree;
+/// use functional_datastructures::prelude::*;
+/// use functional_datastructures::Tree;
 ///
 /// let empty_set: Tree<u32,()> = Tree::empty();
 /// let set_with_contents = empty_set.insert(1).insert(2);
 ///
 /// assert!(!empty_set.member(1));
 /// assert!(!empty_set.member(2));
-
+///
 /// assert!(set_with_contents.member(1));
 /// assert!(set_with_contents.member(2));
 /// ```
 #[derive(Debug, Clone)]
-pub enum Tree<K: Ord + Clone, V: Clone> {
+pub enum TreeIn<K: Ord + Clone, V: Clone, P: SharedPointer> {
     #[doc(hidden)]
     Empty,
     #[doc(hidden)]
     Node {
-        left: Arc<Tree<K, V>>,
+        left: Shared<TreeIn<K, V, P>, P>,
         key: K,
         value: V,
-        right: Arc<Tree<K, V>>,
+        right: Shared<TreeIn<K, V, P>, P>,
     },
 }
 
-impl <K: Ord + Clone + Debug, V: Clone + Debug> Map<K, V> for Tree<K,V> {
+/// A `TreeIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type Tree<K, V> = TreeIn<K, V, DefaultPointer>;
+
+impl<K: Ord + Clone + Debug, V: Clone + Debug, P: SharedPointer> Map<K, V> for TreeIn<K, V, P> {
     fn empty_map() -> Self {
-        return Tree::Empty;
+        return TreeIn::Empty;
     }
     fn bind(&self, new_key: K, new_value: V) -> Self {
-        match *self {
-            Tree::Empty => Tree::singleton(new_key, new_value),
-            Tree::Node { ref left, ref key, ref right, ref value } => {
-                if new_key < *key {
-                    Tree::Node {
-                        left: Arc::new(left.bind(new_key, new_value)),
-                        key: key.clone(),
-                        value: value.clone(),
-                        right: right.clone(),
-                    }
-                } else if new_key > *key {
-                    Tree::Node {
-                        left: left.clone(),
-                        key: key.clone(),
-                        value: value.clone(),
-                        right: Arc::new(right.bind(new_key, new_value)),
-                    }
-                } else {
-                    // Update "this" node.
-                    Tree::Node {
-                        left: left.clone(),
-                        key: new_key,
-                        value: new_value,
-                        right: right.clone(),
-                    }
-                }
+        let mut path = Vec::new();
+        let mut tree = self;
+        while let TreeIn::Node { ref left, ref key, ref right, .. } = *tree {
+            if new_key < *key {
+                path.push((tree, true));
+                tree = left;
+            } else if new_key > *key {
+                path.push((tree, false));
+                tree = right;
+            } else {
+                // Update "this" node.
+                let updated = TreeIn::Node {
+                    left: left.clone(),
+                    key: new_key,
+                    value: new_value,
+                    right: right.clone(),
+                };
+                return TreeIn::copy_path(path, updated);
             }
         }
+        TreeIn::copy_path(path, TreeIn::singleton(new_key, new_value))
     }
     fn lookup(&self, search_key: K) -> Option<V> {
-        match *self {
-            Tree::Empty => None,
-            Tree::Node { ref key, ref value, .. } =>
-                self.lookup_with_candidate(search_key, &key, &value),
+        // Only the last key not greater than the one searched for is tested
+        // for equality, once the bottom of the tree is reached, which saves
+        // a comparison at every level on the way down (Exercise 2.2).
+        let mut candidate = None;
+        let mut tree = self;
+        while let TreeIn::Node { ref left, ref key, ref value, ref right } = *tree {
+            if search_key < *key {
+                tree = left;
+            } else {
+                candidate = Some((key, value));
+                tree = right;
+            }
+        }
+        match candidate {
+            Some((key, value)) if *key == search_key => Some(value.clone()),
+            _ => None,
         }
     }
+    fn bind_mut(&mut self, new_key: K, new_value: V) {
+        let mut tree = self;
+        loop {
+            let subtree = match *tree {
+                TreeIn::Empty => break,
+                TreeIn::Node { ref mut left, ref mut key, ref mut value, ref mut right } => {
+                    if new_key < *key {
+                        left
+                    } else if new_key > *key {
+                        right
+                    } else {
+                        *key = new_key;
+                        *value = new_value;
+                        return;
+                    }
+                }
+            };
+            // Nodes on the path are only copied if another tree shares them.
+            tree = Shared::make_mut(subtree);
+        }
+        *tree = TreeIn::singleton(new_key, new_value);
+    }
+    fn get_or_insert_with<F>(&self, search_key: &K, default: F) -> (V, Self)
+        where F: FnOnce() -> V
+    {
+        let mut path = Vec::new();
+        let mut tree = self;
+        while let TreeIn::Node { ref left, ref key, ref value, ref right } = *tree {
+            if *search_key < *key {
+                path.push((tree, true));
+                tree = left;
+            } else if *search_key > *key {
+                path.push((tree, false));
+                tree = right;
+            } else {
+                return (value.clone(), self.clone());
+            }
+        }
+        let value = default();
+        (value.clone(), TreeIn::copy_path(path, TreeIn::singleton(search_key.clone(), value)))
+    }
 }
 
-impl <T: Ord + Clone + Debug> Set<T> for Tree<T, ()> {
+impl<T: Ord + Clone + Debug, P: SharedPointer> Set<T> for TreeIn<T, (), P> {
     fn empty() -> Self {
-        return Tree::Empty;
+        return TreeIn::Empty;
     }
     fn insert(&self, new_value: T) -> Self {
-        match *self {
-            Tree::Empty => Tree::singleton(new_value, ()),
-            Tree::Node { ref key, .. } => self.try_insert_with_candidate(new_value, key.clone())
-                                              .unwrap_or_else(|| self.clone()),
+        // As for `lookup`, the value is only compared for equality with the
+        // last key not greater than it.
+        let mut path = Vec::new();
+        let mut candidate = None;
+        let mut tree = self;
+        while let TreeIn::Node { ref left, ref key, ref right, .. } = *tree {
+            if new_value < *key {
+                path.push((tree, true));
+                tree = left;
+            } else {
+                candidate = Some(key);
+                path.push((tree, false));
+                tree = right;
+            }
         }
+        if candidate == Some(&new_value) {
+            return self.clone();
+        }
+        TreeIn::copy_path(path, TreeIn::singleton(new_value, ()))
     }
     fn member(&self, search_value: T) -> bool {
         self.lookup(search_value).is_some()
     }
+    fn insert_mut(&mut self, new_value: T) {
+        // Checking first means a value which is already present leaves any
+        // nodes shared with other trees shared.
+        if !self.member(new_value.clone()) {
+            self.bind_mut(new_value, ());
+        }
+    }
 }
 
+impl<K, V, P> PersistentMap<K, V> for TreeIn<K, V, P>
+    where K: Ord + Clone + Debug,
+          V: Clone + Debug,
+          P: SharedPointer
+{
+    fn get(&self, key: &K) -> Option<&V> {
+        TreeIn::get(self, key)
+    }
+    fn remove(&self, key: &K) -> Self {
+        TreeIn::remove(self, key)
+    }
+    fn union(&self, other: &Self) -> Self {
+        TreeIn::union(self, other)
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        TreeIn::intersection(self, other)
+    }
+    fn difference(&self, other: &Self) -> Self {
+        TreeIn::difference(self, other)
+    }
+}
 
-impl<K: Ord + Clone + Debug, V: Clone + Debug> Tree<K, V> {
+impl<T: Ord + Clone + Debug, P: SharedPointer> PersistentSet<T> for TreeIn<T, (), P> {
+    fn contains(&self, value: &T) -> bool {
+        self.contains_key(value)
+    }
+    fn remove(&self, value: &T) -> Self {
+        TreeIn::remove(self, value)
+    }
+    fn union(&self, other: &Self) -> Self {
+        TreeIn::union(self, other)
+    }
+    fn intersection(&self, other: &Self) -> Self {
+        TreeIn::intersection(self, other)
+    }
+    fn difference(&self, other: &Self) -> Self {
+        TreeIn::difference(self, other)
+    }
+}
+
+
+impl<K: Ord + Clone + Debug, V: Clone + Debug, P: SharedPointer> TreeIn<K, V, P> {
     fn singleton(key: K, value: V) -> Self {
-        let empty: Arc<Self> = Arc::new(Tree::empty_map());
-        Tree::Node {
+        let empty: Shared<Self, P> = Shared::new(TreeIn::empty_map());
+        TreeIn::Node {
             left: empty.clone(),
             right: empty,
             key: key,
             value: value,
         }
     }
-    fn lookup_with_candidate(&self,
-                             search_key: K,
-                             candidate_key: &K,
-                             candidate_value: &V)
-                             -> Option<V> {
+}
+
+impl<T: Ord + Clone + Debug, P: SharedPointer> TreeIn<T, (), P> {
+    /// Returns a complete tree of the given depth, with `2^depth - 1` nodes
+    /// which all hold `value`.
+    ///
+    /// Both children of each node are the same shared subtree, so this takes
+    /// O(depth) time and space. As every node holds the same value this is
+    /// not a well-formed set once it has more than one node, and
+    /// `check_invariants` rejects it; it is useful for its shape.
+    pub fn complete(value: T, depth: u32) -> Self {
+        let mut tree: Shared<Self, P> = Shared::new(TreeIn::empty());
+        for _ in 0..depth {
+            tree = Shared::new(TreeIn::Node {
+                left: tree.clone(),
+                key: value.clone(),
+                right: tree,
+                value: (),
+            })
+        }
+        return (*tree).clone();
+    }
+    /// Returns a balanced tree of exactly `size` nodes which all hold
+    /// `value`.
+    ///
+    /// Subtrees of equal size are shared, so this takes O(log size) time and
+    /// space. As with `complete`, this is not a well-formed set once it has
+    /// more than one node.
+    pub fn create(value: T, size: u32) -> Self {
+        (*TreeIn::create_pair(&value, size).0).clone()
+    }
+    // Returns balanced trees of `size` and `size + 1` nodes holding `value`,
+    // built from the pair for about half of `size`.
+    fn create_pair(value: &T, size: u32) -> (Shared<Self, P>, Shared<Self, P>) {
+        let node = |left: &Shared<Self, P>, right: &Shared<Self, P>| {
+            Shared::new(TreeIn::Node {
+                left: left.clone(),
+                key: value.clone(),
+                right: right.clone(),
+                value: (),
+            })
+        };
+        if size == 0 {
+            let empty = Shared::new(TreeIn::empty());
+            let singleton = node(&empty, &empty);
+            (empty, singleton)
+        } else if size % 2 == 1 {
+            let (half, half_plus_one) = TreeIn::create_pair(value, size / 2);
+            (node(&half, &half), node(&half, &half_plus_one))
+        } else {
+            let (below, half) = TreeIn::create_pair(value, size / 2 - 1);
+            (node(&below, &half), node(&half, &half))
+        }
+    }
+    /// Returns an iterator over every subset of this set, starting with the
+    /// whole set.
+    ///
+    /// The subsets come in Gray code order: each is made from the one
+    /// before by adding or removing a single member, so it shares all but
+    /// one path of nodes with it, and the first shares everything with this
+    /// set. Only the subset being made is held at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this set has 64 or more members, whose subsets could never
+    /// all be visited.
+    pub fn powerset(&self) -> Powerset<T, P> {
+        let members: Vec<T> = self.iter().map(|(member, _)| member.clone()).collect();
+        assert!(members.len() < 64, "too many members to visit every subset");
+        Powerset {
+            chosen: vec![true; members.len()],
+            remaining: 1 << members.len(),
+            visited: 0,
+            members: members,
+            current: self.clone(),
+        }
+    }
+    /// Returns an iterator over the subsets of this set with `size` members,
+    /// in lexicographic order of their members.
+    ///
+    /// Each subset is made from the one before by removing the members it
+    /// drops and adding the ones it gains, which are usually only the last
+    /// few, so consecutive subsets share most of their nodes. The first is
+    /// made by removing members from this set, and shares nodes with it.
+    pub fn combinations(&self, size: usize) -> Combinations<T, P> {
+        let members: Vec<T> = self.iter().map(|(member, _)| member.clone()).collect();
+        let current = if size > members.len() {
+            None
+        } else {
+            Some(members[size..].iter().fold(self.clone(), |subset, member| subset.remove(member)))
+        };
+        Combinations {
+            members: members,
+            chosen: (0..size).collect(),
+            current: current,
+        }
+    }
+    #[cfg(test)]
+    fn depth(&self) -> u32 {
         match *self {
-            Tree::Empty => if search_key == *candidate_key {
-                Some(candidate_value.clone())
-            } else {
-                None
-            },
-            Tree::Node { ref left, ref key, ref right, ref value } => if search_key < *key {
-                left.lookup_with_candidate(search_key, candidate_key, candidate_value)
-            } else {
-                right.lookup_with_candidate(search_key, &key, &value)
-            },
+            TreeIn::Empty => 0,
+            TreeIn::Node { ref left, ref right, .. } => 1 + cmp::max(left.depth(), right.depth()),
         }
     }
 }
 
-impl<T: Ord + Clone + Debug> Tree<T, ()> {
-    fn try_insert_with_candidate(&self, new_value: T, candidate: T) -> Option<Self> {
+impl<T: Ord + Clone, P: SharedPointer> TreeIn<T, (), P> {
+    /// Returns an iterator over every pair of a member of this set and a
+    /// member of `other`, in lexicographic order: by the member of this set,
+    /// then by the member of `other`.
+    ///
+    /// The pairs are made as they are asked for, by walking `other` once for
+    /// each member of this set, so only the positions of the two walks are
+    /// held at a time.
+    pub fn product<'a, U: Ord + Clone>(&'a self,
+                                       other: &'a TreeIn<U, (), P>)
+                                       -> Product<'a, T, U, P> {
+        Product {
+            outer: self.iter(),
+            current: None,
+            other: other,
+            inner: other.iter(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> TreeIn<K, V, P> {
+    /// Returns an iterator over the bindings in this tree, in order of key.
+    pub fn iter(&self) -> Iter<'_, K, V, P> {
+        Iter { stack: vec![Work::Tree(self)] }
+    }
+    /// Returns a tree binding each key of this tree to `f` of its value.
+    ///
+    /// `f` is called on the values in order of key, and the new tree is
+    /// built balanced from the results, as `TreeBuilder` builds it, rather
+    /// than with the shape of this one.
+    pub fn map_values<W: Clone, F: FnMut(&V) -> W>(&self, mut f: F) -> TreeIn<K, W, P> {
+        let bindings: Vec<(K, W)> = self.iter()
+                                        .map(|(key, value)| (key.clone(), f(value)))
+                                        .collect();
+        TreeIn::from_ordered(bindings)
+    }
+    /// Returns a tree binding each class `classify` puts the bindings of
+    /// this tree into to a stack of the values of the bindings in it, in
+    /// order of key, with that of the least key at its head.
+    ///
+    /// `classify` is called on the bindings in order of key, and the new
+    /// tree is built balanced, as `TreeBuilder` builds it.
+    pub fn group_by<C, F>(&self, mut classify: F) -> TreeIn<C, CustomStackIn<V, P>, P>
+        where C: Ord + Clone,
+              F: FnMut(&K, &V) -> C
+    {
+        let mut groups: BTreeMap<C, StackBuilderIn<V, P>> = BTreeMap::new();
+        for (key, value) in self.iter() {
+            groups.entry(classify(key, value)).or_default().push(value.clone());
+        }
+        let bindings = groups.into_iter().map(|(class, values)| (class, values.freeze()));
+        TreeIn::from_ordered(bindings.collect())
+    }
+    /// Returns the value bound to `key`, or `None` if `key` is not bound in
+    /// this tree. Unlike `Map::lookup`, this borrows the value rather than
+    /// cloning it.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut tree = self;
+        while let TreeIn::Node { ref left, key: ref node_key, ref value, ref right } = *tree {
+            match key.cmp(node_key) {
+                Ordering::Less => tree = left,
+                Ordering::Greater => tree = right,
+                Ordering::Equal => return Some(value),
+            }
+        }
+        None
+    }
+    /// Tests whether `key` is bound in this tree.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+    /// Returns a copy of this tree without any binding for `key`.
+    ///
+    /// A node with two subtrees is replaced by the least binding of its
+    /// right subtree, which is removed from there in turn. As with `bind`,
+    /// only the nodes on the path to the removed binding are copied, and the
+    /// path is copied with a loop, so that the stack stays flat however deep
+    /// the tree is.
+    pub fn remove(&self, key: &K) -> Self {
+        let mut path = Vec::new();
+        let mut tree = self;
+        while let TreeIn::Node { ref left, key: ref node_key, ref right, .. } = *tree {
+            match key.cmp(node_key) {
+                Ordering::Less => {
+                    path.push((tree, true));
+                    tree = left;
+                }
+                Ordering::Greater => {
+                    path.push((tree, false));
+                    tree = right;
+                }
+                Ordering::Equal => {
+                    let replacement = match (&**left, &**right) {
+                        (&TreeIn::Empty, subtree) | (subtree, &TreeIn::Empty) => subtree.clone(),
+                        _ => {
+                            let ((key, value), rest) = right.remove_least();
+                            TreeIn::Node {
+                                left: left.clone(),
+                                key: key,
+                                value: value,
+                                right: Shared::new(rest),
+                            }
+                        }
+                    };
+                    return TreeIn::copy_path(path, replacement);
+                }
+            }
+        }
+        self.clone()
+    }
+    /// Returns a tree of the bindings in this tree and `other`. Where a key
+    /// is bound in both, the binding from this tree is kept.
+    ///
+    /// This and the other set operations walk both trees in order, taking
+    /// O(n + m) time, and build the result balanced.
+    pub fn union(&self, other: &Self) -> Self {
+        self.merge(other, true, true, true)
+    }
+    /// Returns a tree of the bindings in this tree whose keys are also bound
+    /// in `other`.
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.merge(other, false, true, false)
+    }
+    /// Returns a tree of the bindings in this tree whose keys are not bound
+    /// in `other`.
+    pub fn difference(&self, other: &Self) -> Self {
+        self.merge(other, true, false, false)
+    }
+    /// Checks that the keys of this tree are in order, as searching it
+    /// relies on, returning a description of the first key found out of
+    /// order if not. Trees built by this crate always are, but trees built
+    /// from their variants by hand need not be.
+    pub fn check_invariants(&self) -> Result<(), String>
+        where K: Debug
+    {
+        self.check_order(None, None)
+    }
+    /// Checks that the keys of this tree are in order and lie strictly
+    /// between `lower` and `upper`, where given.
+    fn check_order<'a>(&'a self, lower: Option<&'a K>, upper: Option<&'a K>) -> Result<(), String>
+        where K: Debug
+    {
+        let mut previous = lower;
+        for key in self.iter().map(|(key, _)| key).chain(upper) {
+            if let Some(previous) = previous {
+                if previous >= key {
+                    return Err(format!("{:?} comes after {:?} but is not greater", key, previous));
+                }
+            }
+            previous = Some(key);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: Ord + Clone, V: Clone> TreeIn<K, V, DefaultPointer> {
+    /// Like `check_invariants`, but checks the subtrees of the top levels of
+    /// the tree as separate tasks on rayon's thread pool.
+    pub fn par_check_invariants(&self) -> Result<(), String>
+        where K: Debug + MaybeSend + MaybeSync,
+              V: MaybeSend + MaybeSync
+    {
+        self.par_check_order(None, None, PARALLEL_DEPTH)
+    }
+    /// Tests whether this tree and `other` hold the same bindings, as `==`
+    /// does, but compares the subtrees of the top levels as separate tasks
+    /// on rayon's thread pool.
+    ///
+    /// The subtrees are compared in parallel for as long as the two trees
+    /// have the same keys at the same nodes, as trees built the same way
+    /// from the same bindings do. Where they differ, the bindings of the
+    /// subtrees are compared in order instead.
+    pub fn par_eq(&self, other: &Self) -> bool
+        where K: MaybeSend + MaybeSync,
+              V: PartialEq + MaybeSend + MaybeSync
+    {
+        self.par_equals(other, PARALLEL_DEPTH)
+    }
+    /// Like `check_order`, but for the top `depth` levels checks the two
+    /// subtrees of each node as separate tasks.
+    fn par_check_order<'a>(&'a self,
+                           lower: Option<&'a K>,
+                           upper: Option<&'a K>,
+                           depth: u32)
+                           -> Result<(), String>
+        where K: Debug + MaybeSend + MaybeSync,
+              V: MaybeSend + MaybeSync
+    {
         match *self {
-            Tree::Empty => {
-                if new_value == candidate {
-                    None
-                } else {
-                    Some(Tree::singleton(new_value, ()))
+            TreeIn::Node { ref left, ref key, ref right, .. } if depth > 0 => {
+                // Checking the left subtree against `key` as its upper bound
+                // also checks `key` against `lower`.
+                let (left, right) = join(|| left.par_check_order(lower, Some(key), depth - 1),
+                                         || right.par_check_order(Some(key), upper, depth - 1));
+                left.and(right)
+            }
+            _ => self.check_order(lower, upper),
+        }
+    }
+    /// Tests whether this tree and `other` hold the same bindings, comparing
+    /// the subtrees of nodes with the same keys as separate tasks for the
+    /// top `depth` levels.
+    fn par_equals(&self, other: &Self, depth: u32) -> bool
+        where K: MaybeSend + MaybeSync,
+              V: PartialEq + MaybeSend + MaybeSync
+    {
+        match (self, other) {
+            (TreeIn::Node { left, key, value, right },
+             TreeIn::Node { left: other_left,
+                            key: other_key,
+                            value: other_value,
+                            right: other_right }) if depth > 0 && key == other_key => {
+                if value != other_value {
+                    return false;
                 }
+                let (left, right) = join(|| {
+                                             Shared::ptr_eq(left, other_left) ||
+                                             left.par_equals(other_left, depth - 1)
+                                         },
+                                         || {
+                                             Shared::ptr_eq(right, other_right) ||
+                                             right.par_equals(other_right, depth - 1)
+                                         });
+                left && right
             }
-            Tree::Node { ref left, ref key, ref right, .. } => {
-                if new_value < *key {
-                    left.try_insert_with_candidate(new_value, candidate).map(|new_left| {
-                        Tree::Node {
-                            left: Arc::new(new_left),
-                            key: key.clone(),
-                            right: right.clone(),
-                            value: (),
-                        }
-                    })
-                } else if new_value > *key {
-                    right.try_insert_with_candidate(new_value, key.clone()).map(|new_right| {
-                        Tree::Node {
-                            left: left.clone(),
-                            key: key.clone(),
-                            right: Arc::new(new_right),
-                            value: (),
-                        }
-                    })
+            _ => self.iter().eq(other.iter()),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Collection for TreeIn<K, V, P> {
+    type Iter<'a> = Iter<'a, K, V, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        // A tree keeps no count of its bindings, so this takes linear time.
+        self.iter().count()
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, K, V, P> {
+        self.iter()
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> MapElems<V> for &TreeIn<K, V, P> {
+    type Output<U: Clone> = TreeIn<K, U, P>;
+
+    fn map<U: Clone, F: Fn(&V) -> U>(self, f: F) -> TreeIn<K, U, P> {
+        self.map_values(f)
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Traverse<V> for &TreeIn<K, V, P> {
+    fn try_map<U, E, F>(self, mut f: F) -> Result<TreeIn<K, U, P>, E>
+        where U: Clone,
+              F: FnMut(&V) -> Result<U, E>
+    {
+        let mapped = self.iter().map(|(_, value)| f(value)).collect::<Result<Vec<U>, E>>()?;
+        let mut mapped = mapped.into_iter();
+        Ok(self.map_values(|_| mapped.next().unwrap()))
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> TreeIn<K, V, P> {
+    /// Returns `bottom` with copies of the nodes on `path` above it, from
+    /// the root down, where each step says whether the path went to the
+    /// left of its node.
+    ///
+    /// Copying the path with a loop rather than on the way back out of a
+    /// recursive descent keeps the stack flat however deep the tree is.
+    fn copy_path(mut path: Vec<(&Self, bool)>, bottom: Self) -> Self {
+        let mut tree = bottom;
+        while let Some((node, went_left)) = path.pop() {
+            if let TreeIn::Node { ref left, ref key, ref value, ref right } = *node {
+                let (left, right) = if went_left {
+                    (Shared::new(tree), right.clone())
                 } else {
-                    None
+                    (left.clone(), Shared::new(tree))
+                };
+                tree = TreeIn::Node {
+                    left: left,
+                    key: key.clone(),
+                    value: value.clone(),
+                    right: right,
+                };
+            }
+        }
+        tree
+    }
+    /// Returns the least binding of this tree, and a copy of this tree
+    /// without it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this tree is empty.
+    fn remove_least(&self) -> ((K, V), Self) {
+        let mut path = Vec::new();
+        let mut tree = self;
+        loop {
+            match *tree {
+                TreeIn::Empty => panic!("an empty tree has no least binding"),
+                TreeIn::Node { ref left, ref key, ref value, ref right } => {
+                    if let TreeIn::Empty = **left {
+                        let rest = TreeIn::copy_path(path, (**right).clone());
+                        return ((key.clone(), value.clone()), rest);
+                    }
+                    path.push((tree, true));
+                    tree = left;
                 }
             }
         }
     }
-    #[cfg(test)]
-    fn complete(value: T, depth: u32) -> Self {
-        let mut tree: Arc<Self> = Arc::new(Tree::empty());
-        for _ in 0..depth {
-            tree = Arc::new(Tree::Node {
-                left: tree.clone(),
-                key: value.clone(),
-                right: tree,
-                value: (),
-            })
+    /// Returns a balanced tree of the bindings of this tree and `other`,
+    /// found by walking the two in order of key together: those whose keys
+    /// only this tree binds if `ours`, the bindings from this tree of the
+    /// keys both bind if `both`, and those whose keys only `other` binds if
+    /// `theirs`.
+    fn merge(&self, other: &Self, ours: bool, both: bool, theirs: bool) -> Self {
+        let mut bindings = Vec::new();
+        let mut mine = self.iter().peekable();
+        let mut others = other.iter().peekable();
+        loop {
+            let order = match (mine.peek(), others.peek()) {
+                (None, None) => break,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(&(a, _)), Some(&(b, _))) => a.cmp(b),
+            };
+            let (binding, keep) = match order {
+                Ordering::Less => (mine.next(), ours),
+                Ordering::Greater => (others.next(), theirs),
+                Ordering::Equal => {
+                    others.next();
+                    (mine.next(), both)
+                }
+            };
+            if let (Some((key, value)), true) = (binding, keep) {
+                bindings.push((key.clone(), value.clone()));
+            }
         }
-        return (*tree).clone();
+        TreeIn::from_ordered(bindings)
     }
-    #[cfg(test)]
-    fn depth(&self) -> u32 {
-        match *self {
-            Tree::Empty => 0,
-            Tree::Node { ref left, ref right, .. } => 1 + cmp::max(left.depth(), right.depth()),
+    /// Returns a balanced tree of `bindings`, which must be in order of key
+    /// with no key repeated.
+    fn from_ordered(bindings: Vec<(K, V)>) -> Self {
+        let size = bindings.len();
+        let empty = Shared::new(TreeIn::Empty);
+        match Shared::try_unwrap(balanced(&mut bindings.into_iter(), size, &empty)) {
+            Ok(tree) => tree,
+            Err(_) => TreeIn::Empty,
         }
     }
+    /// Moves each subtree of this node which no other tree shares onto
+    /// `subtrees`, leaving an empty tree in its place.
+    fn take_unshared_subtrees(&mut self, subtrees: &mut Vec<Self>) {
+        if let TreeIn::Node { ref mut left, ref mut right, .. } = *self {
+            for subtree in &mut [left, right] {
+                if let Some(subtree) = Shared::get_mut(subtree) {
+                    if let TreeIn::Node { .. } = *subtree {
+                        subtrees.push(mem::replace(subtree, TreeIn::Empty));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Drop for TreeIn<K, V, P> {
+    fn drop(&mut self) {
+        // Left to the compiler, dropping a node drops its subtrees from
+        // within, one stack frame per level. Taking them out first means each
+        // node is dropped once its subtrees are empty.
+        let mut subtrees = Vec::new();
+        self.take_unshared_subtrees(&mut subtrees);
+        while let Some(mut subtree) = subtrees.pop() {
+            subtree.take_unshared_subtrees(&mut subtrees);
+        }
+    }
+}
+
+/// Two trees are equal if they hold the same bindings, whatever their
+/// shapes.
+impl<K: Ord + Clone, V: Clone + PartialEq, P: SharedPointer> PartialEq for TreeIn<K, V, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Ord + Clone, V: Clone + Eq, P: SharedPointer> Eq for TreeIn<K, V, P> {}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> MemoryUsage for TreeIn<K, V, P> {
+    fn census(&self, census: &mut Census) {
+        let mut stack = vec![self];
+        while let Some(tree) = stack.pop() {
+            if let TreeIn::Node { ref left, ref right, .. } = *tree {
+                for child in &[left, right] {
+                    if census.node(&***child, 0) {
+                        stack.push(child);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A builder which collects bindings for a `Tree` in a vector and builds the
+/// tree only once all of them are known.
+///
+/// Freezing the builder sorts the bindings and builds a balanced tree from
+/// them directly, in O(n log n) time with one allocation per key. Binding the
+/// keys into a tree one at a time allocates a path of nodes for every
+/// binding instead, and leaves the tree as unbalanced as the order of the
+/// keys makes it. Where a key is bound more than once, the last binding
+/// wins, as it would with `bind`.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::set::{Map, Set, TreeBuilder};
+///
+/// let mut builder = TreeBuilder::new();
+/// for i in 0..1000 {
+///     builder.bind(i % 100, i);
+/// }
+/// let map = builder.freeze();
+/// assert!(map.lookup(42).unwrap() == 942);
+///
+/// let mut builder = TreeBuilder::new();
+/// builder.extend(vec![3, 1, 2].into_iter().map(|value| (value, ())));
+/// builder.insert(4);
+/// assert!(builder.freeze().member(4));
+/// ```
+#[derive(Debug, Clone)]
+pub struct TreeBuilderIn<K, V, P: SharedPointer> {
+    bindings: Vec<(K, V)>,
+    marker: PhantomData<P>,
+}
+
+/// A `TreeBuilderIn` of the trees exported at the root of the crate.
+pub type TreeBuilder<K, V> = TreeBuilderIn<K, V, DefaultPointer>;
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> TreeBuilderIn<K, V, P> {
+    /// Returns a builder with no bindings.
+    pub fn new() -> Self {
+        TreeBuilderIn {
+            bindings: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+    /// Binds `key` to `value`, replacing any earlier binding for `key`.
+    pub fn bind(&mut self, key: K, value: V) {
+        self.bindings.push((key, value));
+    }
+    /// Returns a balanced tree of the bindings made.
+    pub fn freeze(self) -> TreeIn<K, V, P> {
+        let mut bindings = self.bindings;
+        // The sort is stable, so the last binding of each key is the last of
+        // its run.
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut unique: Vec<(K, V)> = Vec::with_capacity(bindings.len());
+        for binding in bindings {
+            match unique.last_mut() {
+                Some(last) if last.0 == binding.0 => {
+                    *last = binding;
+                    continue;
+                }
+                _ => {}
+            }
+            unique.push(binding);
+        }
+        TreeIn::from_ordered(unique)
+    }
+}
+
+impl<T: Ord + Clone, P: SharedPointer> TreeBuilderIn<T, (), P> {
+    /// Adds `value` to the set being built.
+    pub fn insert(&mut self, value: T) {
+        self.bind(value, ());
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Default for TreeBuilderIn<K, V, P> {
+    fn default() -> Self {
+        TreeBuilderIn::new()
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Extend<(K, V)> for TreeBuilderIn<K, V, P> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, bindings: I) {
+        self.bindings.extend(bindings);
+    }
+}
+
+/// Returns a balanced tree of the next `size` bindings from `bindings`, which
+/// must come in order of key, sharing `empty` between all its empty
+/// subtrees.
+fn balanced<K, V, I, P>(bindings: &mut I,
+                        size: usize,
+                        empty: &Shared<TreeIn<K, V, P>, P>)
+                        -> Shared<TreeIn<K, V, P>, P>
+    where K: Ord + Clone,
+          V: Clone,
+          I: Iterator<Item = (K, V)>,
+          P: SharedPointer
+{
+    if size == 0 {
+        return empty.clone();
+    }
+    let left = balanced(bindings, size / 2, empty);
+    let (key, value) = bindings.next().unwrap();
+    let right = balanced(bindings, size - size / 2 - 1, empty);
+    Shared::new(TreeIn::Node {
+        left: left,
+        key: key,
+        value: value,
+        right: right,
+    })
+}
+
+/// An iterator over the bindings of a `Tree`, in order of key, returned by
+/// `Tree::iter`.
+pub struct Iter<'a, K: Ord + Clone + 'a, V: Clone + 'a, P: SharedPointer> {
+    /// The parts of the tree still to be visited, the next one last.
+    stack: Vec<Work<'a, K, V, P>>,
+}
+
+/// Part of a tree still to be visited by an `Iter`.
+enum Work<'a, K: Ord + Clone + 'a, V: Clone + 'a, P: SharedPointer> {
+    Tree(&'a TreeIn<K, V, P>),
+    Binding(&'a K, &'a V),
+}
+
+impl<'a, K: Ord + Clone, V: Clone, P: SharedPointer> Iter<'a, K, V, P> {
+    /// Pushes the parts of `tree` onto the stack, so that its left subtree
+    /// is visited next. Empty subtrees are left out, so that everything on
+    /// the stack has something in it.
+    fn push_parts(&mut self, tree: &'a TreeIn<K, V, P>) {
+        if let TreeIn::Node { ref left, ref key, ref value, ref right } = *tree {
+            self.push_tree(right);
+            self.stack.push(Work::Binding(key, value));
+            self.push_tree(left);
+        }
+    }
+    fn push_tree(&mut self, tree: &'a TreeIn<K, V, P>) {
+        if let TreeIn::Node { .. } = *tree {
+            self.stack.push(Work::Tree(tree));
+        }
+    }
+}
+
+impl<'a, K: Ord + Clone, V: Clone, P: SharedPointer> Iterator for Iter<'a, K, V, P> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        while let Some(work) = self.stack.pop() {
+            match work {
+                Work::Binding(key, value) => return Some((key, value)),
+                Work::Tree(tree) => self.push_parts(tree),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, K: Ord + Clone, V: Clone, P: SharedPointer> Split for Iter<'a, K, V, P> {
+    fn split(&mut self) -> Option<Self> {
+        while self.stack.len() == 1 {
+            match self.stack.pop() {
+                Some(Work::Tree(tree)) => self.push_parts(tree),
+                binding => {
+                    self.stack.extend(binding);
+                    return None;
+                }
+            }
+        }
+        if self.stack.len() < 2 {
+            return None;
+        }
+        let half = self.stack.len() / 2;
+        Some(Iter { stack: self.stack.drain(..half).collect() })
+    }
+}
+
+/// An iterator over the pairs of members of two `Tree`s, returned by
+/// `Tree::product`.
+pub struct Product<'a, T: Ord + Clone + 'a, U: Ord + Clone + 'a, P: SharedPointer> {
+    outer: Iter<'a, T, (), P>,
+    /// The member of the first set being paired, if one has been taken.
+    current: Option<&'a T>,
+    other: &'a TreeIn<U, (), P>,
+    /// The members of the second set still to be paired with `current`.
+    inner: Iter<'a, U, (), P>,
+}
+
+impl<'a, T: Ord + Clone, U: Ord + Clone, P: SharedPointer> Iterator for Product<'a, T, U, P> {
+    type Item = (&'a T, &'a U);
+
+    fn next(&mut self) -> Option<(&'a T, &'a U)> {
+        loop {
+            if let Some(first) = self.current {
+                if let Some((second, _)) = self.inner.next() {
+                    return Some((first, second));
+                }
+            }
+            match self.outer.next() {
+                None => return None,
+                Some((first, _)) => {
+                    self.current = Some(first);
+                    self.inner = self.other.iter();
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the subsets of a `Tree`, returned by `Tree::powerset`.
+pub struct Powerset<T: Ord + Clone, P: SharedPointer> {
+    /// The members of the whole set, in order.
+    members: Vec<T>,
+    /// Whether each member is in `current`.
+    chosen: Vec<bool>,
+    current: TreeIn<T, (), P>,
+    /// The number of subsets already returned, and still to be returned.
+    visited: u64,
+    remaining: u64,
+}
+
+impl<T: Ord + Clone + Debug, P: SharedPointer> Iterator for Powerset<T, P> {
+    type Item = TreeIn<T, (), P>;
+
+    fn next(&mut self) -> Option<TreeIn<T, (), P>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let subset = self.current.clone();
+        self.visited += 1;
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            // Successive Gray codes differ in the lowest set bit of the count.
+            let i = self.visited.trailing_zeros() as usize;
+            self.current = if self.chosen[i] {
+                self.current.remove(&self.members[i])
+            } else {
+                self.current.insert(self.members[i].clone())
+            };
+            self.chosen[i] = !self.chosen[i];
+        }
+        Some(subset)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining as usize;
+        (remaining, if remaining as u64 == self.remaining { Some(remaining) } else { None })
+    }
+}
+
+/// An iterator over the subsets of a `Tree` of a given size, returned by
+/// `Tree::combinations`.
+pub struct Combinations<T: Ord + Clone, P: SharedPointer> {
+    /// The members of the whole set, in order.
+    members: Vec<T>,
+    /// The positions in `members` of the members of `current`, in order.
+    chosen: Vec<usize>,
+    /// The next subset, or `None` once every one has been returned.
+    current: Option<TreeIn<T, (), P>>,
+}
+
+impl<T: Ord + Clone + Debug, P: SharedPointer> Iterator for Combinations<T, P> {
+    type Item = TreeIn<T, (), P>;
+
+    fn next(&mut self) -> Option<TreeIn<T, (), P>> {
+        let subset = self.current.take()?;
+        let (n, k) = (self.members.len(), self.chosen.len());
+        // The last position which can still move up, if any.
+        let i = match (0..k).rev().find(|&i| self.chosen[i] < n - k + i) {
+            None => return Some(subset),
+            Some(i) => i,
+        };
+        let dropped: Vec<usize> = self.chosen[i..].to_vec();
+        let first = self.chosen[i] + 1;
+        for (offset, position) in self.chosen[i..].iter_mut().enumerate() {
+            *position = first + offset;
+        }
+        let mut next = subset.clone();
+        for &position in dropped.iter().filter(|position| !self.chosen[i..].contains(position)) {
+            next = next.remove(&self.members[position]);
+        }
+        for &position in self.chosen[i..].iter().filter(|position| !dropped.contains(position)) {
+            next = next.insert(self.members[position].clone());
+        }
+        self.current = Some(next);
+        Some(subset)
+    }
 }
 
 
@@ -260,6 +1228,85 @@ fn complete_test() {
     assert!(complete_tree.depth() == 14);
 }
 
+#[test]
+fn created_trees_have_exactly_the_requested_size() {
+    for size in 0..100 {
+        let tree = Tree::create(7, size);
+        assert!(tree.iter().count() == size as usize);
+        assert!(tree.depth() == 32 - size.leading_zeros());
+        assert!(tree.member(7) == (size > 0) && !tree.member(8));
+    }
+    let large = Tree::create(7, 3_000_000_000);
+    assert!(large.depth() == 32 && large.member(7));
+}
+
+#[test]
+fn powersets_visit_every_subset_once() {
+    let set = (1..6).fold(Tree::empty(), |set, i| set.insert(i));
+    let subsets: Vec<Vec<u32>> = set.powerset()
+                                    .map(|subset| subset.iter().map(|(&m, _)| m).collect())
+                                    .collect();
+    let mut sorted = subsets.clone();
+    sorted.sort();
+    sorted.dedup();
+
+    assert!(subsets.len() == 32 && sorted.len() == 32);
+    assert!(subsets[0] == vec![1, 2, 3, 4, 5] && subsets.contains(&Vec::new()));
+    assert!(set.powerset().all(|subset| subset.check_invariants().is_ok()));
+    assert!(set.powerset().size_hint() == (32, Some(32)));
+    assert!(Tree::<u32, ()>::empty().powerset().count() == 1);
+}
+
+#[test]
+fn combinations_come_in_lexicographic_order() {
+    let set = (1..6).fold(Tree::empty(), |set, i| set.insert(i));
+    let pairs: Vec<Vec<u32>> = set.combinations(2)
+                                  .map(|subset| subset.iter().map(|(&m, _)| m).collect())
+                                  .collect();
+
+    assert!(pairs.len() == 10 && pairs[0] == vec![1, 2] && pairs[9] == vec![4, 5]);
+    assert!(pairs.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(pairs.iter().all(|pair| pair.len() == 2));
+    assert!(set.combinations(0).map(|subset| subset.is_empty()).collect::<Vec<_>>() == vec![true]);
+    assert!(set.combinations(5).count() == 1 && set.combinations(6).count() == 0);
+    assert!(set.combinations(3).all(|subset| subset.check_invariants().is_ok()));
+}
+
+#[test]
+fn products_pair_every_member_in_order() {
+    let numbers = (1..4).fold(Tree::empty(), |set, i| set.insert(i));
+    let letters = "ba".chars().fold(Tree::empty(), |set, c| set.insert(c));
+    let pairs: Vec<(u32, char)> = numbers.product(&letters).map(|(&n, &c)| (n, c)).collect();
+
+    assert!(pairs == vec![(1, 'a'), (1, 'b'), (2, 'a'), (2, 'b'), (3, 'a'), (3, 'b')]);
+    assert!(numbers.product(&Tree::<u32, ()>::empty()).next().is_none());
+    assert!(Tree::<u32, ()>::empty().product(&letters).next().is_none());
+}
+
+#[test]
+fn get_or_insert_with_only_computes_missing_values() {
+    let map = (0..10).fold(Tree::empty_map(), |map, i| map.bind(i, i * 10));
+    let (value, same) = map.get_or_insert_with(&4, || panic!("4 is already bound"));
+    assert!(value == 40 && same.iter().eq(map.iter()));
+
+    let (value, extended) = map.get_or_insert_with(&20, || 200);
+    assert!(value == 200 && extended.get(&20) == Some(&200));
+    assert!(extended.iter().count() == 11 && extended.check_invariants().is_ok());
+    assert!(map.get(&20).is_none());
+}
+
+#[test]
+fn grouped_values_keep_the_order_of_their_keys() {
+    let map = (0..10).fold(Tree::empty_map(), |map, i| map.bind(i, i * 10));
+    let groups = map.group_by(|key, _| key % 3);
+    let group = |class: u32| groups.get(&class).unwrap().iter().cloned().collect::<Vec<u32>>();
+
+    assert!(groups.iter().count() == 3 && groups.check_invariants().is_ok());
+    assert!(group(0) == vec![0, 30, 60, 90] && group(1) == vec![10, 40, 70]);
+    assert!(group(2) == vec![20, 50, 80]);
+    assert!(Tree::<u32, u32>::empty_map().group_by(|key, _| *key).iter().next().is_none());
+}
+
 #[test]
 fn map_missing_values_not_present() {
     let map = Tree::empty_map().bind(10, "hello".to_string());
@@ -284,3 +1331,152 @@ fn map_values_can_be_replaced() {
     assert!(map1.lookup(2).unwrap() == "two");
     assert!(map2.lookup(2).unwrap() == "not two");
 }
+
+#[test]
+fn in_place_binds_match_persistent_ones() {
+    let keys: Vec<u32> = (0..200).map(|i| (i * 61) % 200).collect();
+    let mut tree = Tree::empty_map();
+    let mut snapshots = Vec::new();
+    for &key in &keys {
+        tree.bind_mut(key, key * 2);
+        if key % 50 == 0 {
+            snapshots.push(tree.clone());
+        }
+    }
+    tree.bind_mut(7, 0);
+    assert!(tree.lookup(7) == Some(0));
+    assert!((0..200).filter(|&key| key != 7).all(|key| tree.lookup(key) == Some(key * 2)));
+    // The first key bound is 0, so the first snapshot holds nothing else.
+    assert!(snapshots.len() == 4 && snapshots[0].lookup(0) == Some(0));
+    assert!((1..200).all(|key| snapshots[0].lookup(key).is_none()));
+
+    let mut set = Tree::empty();
+    set.insert_mut(2);
+    set.insert_mut(1);
+    set.insert_mut(2);
+    assert!(set.iter().map(|(value, _)| *value).collect::<Vec<_>>() == vec![1, 2]);
+}
+
+#[test]
+fn builders_freeze_into_balanced_trees() {
+    let mut builder = TreeBuilder::new();
+    assert!(builder.clone().freeze().iter().next().is_none());
+    for i in 0..1000 {
+        builder.insert(i);
+    }
+    builder.insert(0);
+    let tree = builder.freeze();
+
+    assert!(tree.iter().map(|(value, _)| *value).collect::<Vec<_>>() ==
+            (0..1000).collect::<Vec<_>>());
+    assert!(tree.depth() == 10);
+
+    let mut builder = TreeBuilder::new();
+    builder.extend(vec![(2, "two"), (1, "one"), (2, "second two")]);
+    let map = builder.freeze();
+    assert!(map.lookup(2) == Some("second two") && map.lookup(1) == Some("one"));
+}
+
+#[test]
+fn degenerate_trees_are_searched_and_dropped_without_overflow() {
+    // Built directly, since binding keys in order takes quadratic time.
+    let size = 100000;
+    let tree = (0..size).rev().fold(Tree::empty(), |tree, key| {
+        Tree::Node {
+            left: Shared::new(Tree::Empty),
+            key: key,
+            value: (),
+            right: Shared::new(tree),
+        }
+    });
+
+    assert!(tree.member(size - 1) && !tree.member(size));
+    let inserted = tree.insert(size);
+    assert!(inserted.member(size) && !tree.member(size));
+    assert!(tree.insert(size / 2).member(size / 2));
+    let map = inserted.bind(0, ());
+    assert!(map.lookup(0).is_some() && map.iter().count() == size as usize + 1);
+
+    drop(tree);
+    drop(inserted);
+    drop(map);
+}
+
+#[test]
+fn invariant_checks_find_keys_out_of_order() {
+    let tree = (0..100).map(|i| (i * 37) % 100).fold(Tree::empty(), |tree, i| tree.insert(i));
+    assert!(tree.check_invariants().is_ok() && Tree::<u32, ()>::empty().check_invariants().is_ok());
+
+    let leaf = |key| Shared::new(Tree::singleton(key, ()));
+    let misplaced = Tree::Node {
+        left: Shared::new(Tree::Node {
+            left: leaf(1),
+            key: 3,
+            value: (),
+            right: leaf(6),
+        }),
+        key: 5,
+        value: (),
+        right: leaf(7),
+    };
+    assert!(misplaced.check_invariants() == Err("5 comes after 6 but is not greater".to_string()));
+}
+
+#[test]
+fn trees_with_the_same_bindings_are_equal() {
+    let ascending = (0..100).fold(Tree::empty(), |tree, i| tree.insert(i));
+    let shuffled = (0..100).map(|i| (i * 37) % 100).fold(Tree::empty(), |tree, i| tree.insert(i));
+
+    assert!(ascending == shuffled && ascending.depth() != shuffled.depth());
+    assert!(ascending != shuffled.insert(100));
+    assert!(Tree::empty_map().bind(1, "one") != Tree::empty_map().bind(1, "uno"));
+}
+
+#[test]
+fn map_values_keeps_keys_and_balances() {
+    let ascending = (0..1000).fold(Tree::empty_map(), |tree, i| tree.bind(i, i * 2));
+    let mapped = ascending.map_values(|&value| value + 1);
+
+    assert!(mapped.iter().all(|(&key, &value)| value == key * 2 + 1));
+    assert!(mapped.iter().count() == 1000);
+    assert!(mapped.check_invariants().is_ok() && ascending.lookup(10) == Some(20));
+}
+
+#[test]
+fn remove_keeps_the_other_bindings_in_order() {
+    let tree = (0..200).map(|i| (i * 37) % 200).fold(Tree::empty_map(), |tree, i| tree.bind(i, i));
+    let removed = (0..200).filter(|i| i % 3 == 0).fold(tree.clone(), |tree, i| tree.remove(&i));
+
+    assert!(removed.iter().map(|(&key, _)| key).eq((0..200).filter(|i| i % 3 != 0)));
+    assert!(removed.check_invariants().is_ok() && removed.get(&3).is_none());
+    assert!(tree.get(&3) == Some(&3) && tree.remove(&500) == tree);
+
+    // Built directly, as in the test above, to check removal deep in a
+    // degenerate tree.
+    let ascending = (0..100000).rev().fold(Tree::empty(), |tree, key| {
+        Tree::Node {
+            left: Shared::new(Tree::Empty),
+            key: key,
+            value: (),
+            right: Shared::new(tree),
+        }
+    });
+    let removed = ascending.remove(&99998);
+    assert!(!removed.member(99998) && removed.member(99999) && ascending.member(99998));
+}
+
+#[test]
+fn set_operations_walk_both_trees() {
+    let bind_all = |step, value| {
+        (0..100).filter(|i| i % step == 0).fold(Tree::empty_map(), |tree, i| tree.bind(i, value))
+    };
+    let evens = bind_all(2, "even");
+    let threes = bind_all(3, "three");
+
+    let union = evens.union(&threes);
+    assert!(union.iter().count() == 67 && union.get(&6) == Some(&"even"));
+    assert!(union.get(&9) == Some(&"three") && union.check_invariants().is_ok());
+    assert!(evens.intersection(&threes).iter().map(|(&key, _)| key).eq((0..100).step_by(6)));
+    assert!(evens.difference(&threes).iter().count() == 33);
+    assert!(evens.union(&Tree::empty_map()) == evens);
+}
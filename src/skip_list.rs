@@ -0,0 +1,402 @@
+use std::slice;
+
+use collection::Collection;
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+use set::Map;
+
+/// An immutable ordered map, implemented as a skip list.
+///
+/// A skip list keeps its bindings in a sorted list, and gives each binding a
+/// random height, so that about half of them also appear in a second,
+/// sparser list, half of those in a third, and so on. A search runs along
+/// the sparsest list until the next key would be too large, then drops down
+/// a level and carries on, and so passes O(log n) bindings in expectation.
+///
+/// A mutable skip list links each level into a list, but changing a link
+/// would mean copying every binding before it. Here each level is instead
+/// cut into runs, each starting at a binding tall enough to appear in the
+/// level above, and each run holds the runs of the level below which it
+/// spans. This is the same shape as the mutable version, with the links
+/// from the end of each run to the start of the next left out, so an
+/// insertion or removal only copies the O(log n) expected runs it passes
+/// through on its way down.
+///
+/// Heights are drawn from a pseudo-random sequence carried along with the
+/// map, so the same insertions into the same map always build the same
+/// skip list.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::skip_list::SkipListMap;
+/// use functional_datastructures::set::Map;
+///
+/// let empty: SkipListMap<u32, &str> = SkipListMap::empty_map();
+/// let map = empty.insert(3, "three").insert(1, "one").insert(2, "two");
+///
+/// assert!(map.lookup(2).unwrap() == "two");
+/// assert!(map.size() == 3);
+/// assert!(map.iter().map(|(k, _)| *k).collect::<Vec<_>>() == vec![1, 2, 3]);
+/// assert!(map.remove(&2).lookup(2).is_none());
+/// assert!(empty.is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct SkipListMapIn<K, V, P: SharedPointer> {
+    root: Child<K, V, P>,
+    /// The level of `root`, where the bottom level is 0.
+    level: usize,
+    size: u32,
+    /// The state of the pseudo-random sequence heights are drawn from.
+    seed: u64,
+}
+
+/// A `SkipListMapIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type SkipListMap<K, V> = SkipListMapIn<K, V, DefaultPointer>;
+
+/// A binding in a `SkipListMap`.
+#[derive(Debug, Clone)]
+struct Entry<K, V> {
+    key: K,
+    value: V,
+}
+
+/// A run of a skip list's level. A run on the bottom level holds bindings,
+/// and a run on a higher level holds the runs below it, each with its first
+/// key. Every run is non-empty, except for the root of an empty map.
+#[derive(Debug, Clone)]
+enum Node<K, V, P: SharedPointer> {
+    Leaf(Vec<Entry<K, V>>),
+    Branch(Vec<(K, Child<K, V, P>)>),
+}
+
+/// A run of the level below, shared with the other versions of the map that
+/// hold it.
+type Child<K, V, P> = Shared<Node<K, V, P>, P>;
+
+
+/// The seed of a new map's pseudo-random sequence, which must not be zero.
+const INITIAL_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> SkipListMapIn<K, V, P> {
+    /// Returns a copy of this map with `key` bound to `value`, replacing any
+    /// existing binding for `key`.
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let seed = next_seed(self.seed);
+        let height = seed.trailing_zeros() as usize;
+        let entry = Entry {
+            key: key,
+            value: value,
+        };
+        let (left, right, replaced) = self.root.insert(self.level, entry, height);
+        let size = if replaced { self.size } else { self.size + 1 };
+        match right {
+            Some(right) => {
+                let children = vec![(left.first_key().clone(), Shared::new(left)),
+                                    (right.first_key().clone(), Shared::new(right))];
+                SkipListMapIn {
+                    root: Shared::new(Node::Branch(children)),
+                    level: self.level + 1,
+                    size: size,
+                    seed: seed,
+                }
+            }
+            None => {
+                SkipListMapIn {
+                    root: Shared::new(left),
+                    level: self.level,
+                    size: size,
+                    seed: seed,
+                }
+            }
+        }
+    }
+    /// Returns a copy of this map without any binding for `key`.
+    pub fn remove(&self, key: &K) -> Self {
+        let mut root = match self.root.remove(key) {
+            Some(root) => root,
+            None => return self.clone(),
+        };
+        let mut level = self.level;
+        // Drop levels which no longer divide the bindings into several runs.
+        loop {
+            let only = match root {
+                Node::Branch(ref children) if children.len() <= 1 => {
+                    children.first().map_or(Node::Leaf(Vec::new()), |child| (*child.1).clone())
+                }
+                _ => break,
+            };
+            root = only;
+            level -= 1;
+        }
+        SkipListMapIn {
+            root: Shared::new(root),
+            level: level,
+            size: self.size - 1,
+            seed: self.seed,
+        }
+    }
+    /// Returns the value bound to `key`, or `None` if it is not bound.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut node = &*self.root;
+        loop {
+            match *node {
+                Node::Leaf(ref entries) => {
+                    return entries.binary_search_by(|entry| entry.key.cmp(key))
+                                  .ok()
+                                  .map(|i| &entries[i].value);
+                }
+                Node::Branch(ref children) => {
+                    match children.iter().rposition(|&(ref first, _)| first <= key) {
+                        Some(i) => node = &*children[i].1,
+                        None => return None,
+                    }
+                }
+            }
+        }
+    }
+    /// Tests whether `key` is bound in this map.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+    /// Tests whether this map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+    /// Returns the number of keys bound in this map.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+    /// Returns an iterator over the bindings in this map, in increasing order
+    /// of key.
+    pub fn iter(&self) -> Iter<'_, K, V, P> {
+        Iter {
+            stack: vec![&self.root],
+            current: [].iter(),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Collection for SkipListMapIn<K, V, P> {
+    type Iter<'a> = Iter<'a, K, V, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, K, V, P> {
+        self.iter()
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Node<K, V, P> {
+    /// Returns the smallest key in this run, which must not be empty.
+    fn first_key(&self) -> &K {
+        match *self {
+            Node::Leaf(ref entries) => &entries[0].key,
+            Node::Branch(ref children) => &children[0].0,
+        }
+    }
+    fn is_empty(&self) -> bool {
+        match *self {
+            Node::Leaf(ref entries) => entries.is_empty(),
+            Node::Branch(ref children) => children.is_empty(),
+        }
+    }
+    /// Returns a copy of this run, which is on level `level`, with `entry`
+    /// inserted at height `height`. If the entry is tall enough to appear in
+    /// the level above, the run is split where it starts, and the part from
+    /// there on is returned too. Also returns whether the entry replaced an
+    /// existing binding.
+    fn insert(&self,
+              level: usize,
+              entry: Entry<K, V>,
+              height: usize)
+              -> (Self, Option<Self>, bool) {
+        match *self {
+            Node::Leaf(ref entries) => {
+                let mut entries = entries.clone();
+                let i = match entries.binary_search_by(|existing| existing.key.cmp(&entry.key)) {
+                    Ok(i) => {
+                        entries[i] = entry;
+                        return (Node::Leaf(entries), None, true);
+                    }
+                    Err(i) => i,
+                };
+                entries.insert(i, entry);
+                if height == 0 || entries.len() == 1 {
+                    return (Node::Leaf(entries), None, false);
+                }
+                // A new smallest key cannot start a run of its own, so the
+                // key after it takes its height instead.
+                let right = entries.split_off(i.max(1));
+                (Node::Leaf(entries), Some(Node::Leaf(right)), false)
+            }
+            Node::Branch(ref children) => {
+                let i = children.iter()
+                                .rposition(|&(ref first, _)| *first <= entry.key)
+                                .unwrap_or(0);
+                let (child, split, replaced) = children[i].1.insert(level - 1, entry, height);
+                let mut children = children.clone();
+                children[i] = (child.first_key().clone(), Shared::new(child));
+                if let Some(right) = split {
+                    children.insert(i + 1, (right.first_key().clone(), Shared::new(right)));
+                    if height > level {
+                        let right = children.split_off(i + 1);
+                        return (Node::Branch(children), Some(Node::Branch(right)), replaced);
+                    }
+                }
+                (Node::Branch(children), None, replaced)
+            }
+        }
+    }
+    /// Returns a copy of this run without the binding for `key`, or `None` if
+    /// it has no such binding. The run returned may be empty.
+    fn remove(&self, key: &K) -> Option<Self> {
+        match *self {
+            Node::Leaf(ref entries) => {
+                entries.binary_search_by(|entry| entry.key.cmp(key)).ok().map(|i| {
+                    let mut entries = entries.clone();
+                    entries.remove(i);
+                    Node::Leaf(entries)
+                })
+            }
+            Node::Branch(ref children) => {
+                let i = children.iter().rposition(|&(ref first, _)| first <= key)?;
+                let child = children[i].1.remove(key)?;
+                let mut children = children.clone();
+                if child.is_empty() {
+                    children.remove(i);
+                } else if i > 0 && children[i].0 == *key {
+                    // The run started at the removed binding, so without it
+                    // the run carries on from the one before.
+                    let merged = children[i - 1].1.append(&child);
+                    children[i - 1].1 = Shared::new(merged);
+                    children.remove(i);
+                } else {
+                    children[i] = (child.first_key().clone(), Shared::new(child));
+                }
+                Some(Node::Branch(children))
+            }
+        }
+    }
+    /// Returns the run of the contents of this run followed by those of
+    /// `other`, which must be on the same level.
+    fn append(&self, other: &Self) -> Self {
+        match (self, other) {
+            (&Node::Leaf(ref ours), &Node::Leaf(ref theirs)) => {
+                Node::Leaf(ours.iter().chain(theirs).cloned().collect())
+            }
+            (&Node::Branch(ref ours), &Node::Branch(ref theirs)) => {
+                Node::Branch(ours.iter().chain(theirs).cloned().collect())
+            }
+            _ => unreachable!("runs on the same level are the same kind of node"),
+        }
+    }
+}
+
+/// Advances a xorshift pseudo-random sequence.
+fn next_seed(seed: u64) -> u64 {
+    let mut x = seed;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    x
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Map<K, V> for SkipListMapIn<K, V, P> {
+    fn empty_map() -> Self {
+        SkipListMapIn {
+            root: Shared::new(Node::Leaf(Vec::new())),
+            level: 0,
+            size: 0,
+            seed: INITIAL_SEED,
+        }
+    }
+    fn bind(&self, key: K, value: V) -> Self {
+        self.insert(key, value)
+    }
+    fn lookup(&self, key: K) -> Option<V> {
+        self.get(&key).cloned()
+    }
+}
+
+/// An iterator over the bindings of a `SkipListMap`, in increasing order of
+/// key.
+pub struct Iter<'a, K: 'a, V: 'a, P: SharedPointer> {
+    stack: Vec<&'a Node<K, V, P>>,
+    current: slice::Iter<'a, Entry<K, V>>,
+}
+
+impl<'a, K, V, P: SharedPointer> Iterator for Iter<'a, K, V, P> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some(entry) = self.current.next() {
+                return Some((&entry.key, &entry.value));
+            }
+            match self.stack.pop() {
+                Some(&Node::Leaf(ref entries)) => self.current = entries.iter(),
+                Some(&Node::Branch(ref children)) => {
+                    self.stack.extend(children.iter().rev().map(|&(_, ref child)| &**child));
+                }
+                None => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn keys_of(map: &SkipListMap<u32, u32>) -> Vec<u32> {
+    map.iter().map(|(k, _)| *k).collect()
+}
+
+#[test]
+fn empty_map_contains_nothing() {
+    let map: SkipListMap<u32, u32> = SkipListMap::empty_map();
+
+    assert!(map.is_empty());
+    assert!(map.lookup(0).is_none());
+    assert!(map.iter().next().is_none());
+    assert!(map.remove(&0).is_empty());
+}
+
+#[test]
+fn keys_are_kept_in_order() {
+    let keys: Vec<u32> = (0..1000).map(|i| (i * 7919) % 1000).collect();
+    let map = keys.iter().fold(SkipListMap::empty_map(), |map, k| map.insert(*k, k * 2));
+
+    assert!(map.size() == 1000);
+    assert!(keys_of(&map) == (0..1000).collect::<Vec<_>>());
+    for k in 0..1000 {
+        assert!(map.lookup(k).unwrap() == k * 2);
+    }
+    assert!(map.lookup(1000).is_none());
+}
+
+#[test]
+fn values_can_be_replaced_and_removed() {
+    let map = (0..300).rev().fold(SkipListMap::empty_map(), |map, k| map.insert(k, 0));
+    let replaced = map.insert(150, 1);
+    let removed = (0..300).filter(|k| k % 3 != 0).fold(map.clone(), |map, k| map.remove(&k));
+
+    assert!(replaced.size() == 300);
+    assert!(replaced.lookup(150).unwrap() == 1);
+    assert!(map.lookup(150).unwrap() == 0);
+    assert!(removed.size() == 100);
+    assert!(keys_of(&removed) == (0..300).filter(|k| k % 3 == 0).collect::<Vec<_>>());
+    assert!(removed.remove(&1).size() == 100);
+    assert!((0..300).fold(map, |map, k| map.remove(&k)).is_empty());
+}
+
+#[test]
+fn levels_grow_logarithmically() {
+    let ascending = (0..4096).fold(SkipListMap::empty_map(), |map, k| map.insert(k, k));
+    let descending = (0..4096).rev().fold(SkipListMap::empty_map(), |map, k| map.insert(k, k));
+
+    for map in &[ascending, descending] {
+        assert!(map.level >= 6 && map.level <= 24);
+        assert!(keys_of(map) == (0..4096).collect::<Vec<_>>());
+    }
+}
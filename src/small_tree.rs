@@ -0,0 +1,362 @@
+use std::fmt;
+use std::mem;
+use std::slice;
+
+use collection::Collection;
+use memory::{Census, MemoryUsage};
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+use set::{Map, Set};
+
+/// The most bindings a leaf holds.
+const LEAF_SIZE: usize = 8;
+
+/// An unbalanced binary tree for small `Copy` keys and values, which can be
+/// used as both an immutable `Map` and an immutable `Set` in the same way as
+/// `Tree`.
+///
+/// A `Tree` allocates a node, with two pointers and their reference counts,
+/// for every binding, and gives each leaf empty subtrees of its own, so a
+/// `Tree<u32, u32>` spends most of its memory on bookkeeping. The leaves of
+/// a `SmallTree` instead hold up to eight bindings each, in order, in an
+/// array inside the node, and there are no empty subtrees. Since the keys
+/// and values are `Copy`, updating a leaf copies the array rather than
+/// cloning anything.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::memory::MemoryReport;
+/// use functional_datastructures::set::{Map, Tree};
+/// use functional_datastructures::small_tree::SmallTree;
+///
+/// let keys: Vec<u32> = (0..1000).map(|i| (i * 7919) % 1000).collect();
+/// let small = keys.iter().fold(SmallTree::empty_map(), |map, &key| map.bind(key, key * 2));
+/// let tree = keys.iter().fold(Tree::empty_map(), |map, &key| map.bind(key, key * 2));
+///
+/// assert!(small.lookup(500) == Some(1000));
+/// assert!(small.iter().eq(tree.iter()));
+/// assert!(MemoryReport::of(&small).estimated_bytes * 2 < MemoryReport::of(&tree).estimated_bytes);
+/// ```
+#[derive(Clone)]
+pub struct SmallTreeIn<K: Copy, V: Copy, P: SharedPointer> {
+    root: Option<Shared<Node<K, V, P>, P>>,
+}
+
+/// A `SmallTreeIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type SmallTree<K, V> = SmallTreeIn<K, V, DefaultPointer>;
+
+#[derive(Clone)]
+enum Node<K: Copy, V: Copy, P: SharedPointer> {
+    /// Between one and `LEAF_SIZE` bindings, in order of key. Only the first
+    /// `len` are part of the tree; the rest are filler.
+    Leaf {
+        len: u8,
+        bindings: [(K, V); LEAF_SIZE],
+    },
+    /// A binding between two non-empty subtrees.
+    Branch {
+        left: Shared<Node<K, V, P>, P>,
+        key: K,
+        value: V,
+        right: Shared<Node<K, V, P>, P>,
+    },
+}
+
+impl<K: Ord + Copy, V: Copy, P: SharedPointer> Map<K, V> for SmallTreeIn<K, V, P> {
+    fn empty_map() -> Self {
+        SmallTreeIn { root: None }
+    }
+    fn bind(&self, new_key: K, new_value: V) -> Self {
+        let mut node = match self.root {
+            None => {
+                let root = Shared::new(Node::leaf(&[(new_key, new_value)]));
+                return SmallTreeIn { root: Some(root) };
+            }
+            Some(ref root) => root,
+        };
+        let mut path = Vec::new();
+        let bottom = loop {
+            match **node {
+                Node::Branch { ref left, key, ref right, .. } => {
+                    if new_key < key {
+                        path.push((&**node, true));
+                        node = left;
+                    } else if new_key > key {
+                        path.push((&**node, false));
+                        node = right;
+                    } else {
+                        break Node::Branch {
+                            left: left.clone(),
+                            key: new_key,
+                            value: new_value,
+                            right: right.clone(),
+                        };
+                    }
+                }
+                Node::Leaf { len, ref bindings } => {
+                    break Node::leaf_with(&bindings[..len as usize], new_key, new_value);
+                }
+            }
+        };
+        SmallTreeIn { root: Some(Shared::new(Node::copy_path(path, bottom))) }
+    }
+    fn lookup(&self, search_key: K) -> Option<V> {
+        let mut node = match self.root {
+            None => return None,
+            Some(ref root) => root,
+        };
+        loop {
+            match **node {
+                Node::Branch { ref left, key, value, ref right } => {
+                    if search_key < key {
+                        node = left;
+                    } else if search_key > key {
+                        node = right;
+                    } else {
+                        return Some(value);
+                    }
+                }
+                Node::Leaf { len, ref bindings } => {
+                    let bindings = &bindings[..len as usize];
+                    return bindings.binary_search_by(|binding| binding.0.cmp(&search_key))
+                                   .ok()
+                                   .map(|i| bindings[i].1);
+                }
+            }
+        }
+    }
+}
+
+impl<T: Ord + Copy, P: SharedPointer> Set<T> for SmallTreeIn<T, (), P> {
+    fn empty() -> Self {
+        SmallTreeIn::empty_map()
+    }
+    fn insert(&self, value: T) -> Self {
+        if self.member(value) {
+            self.clone()
+        } else {
+            self.bind(value, ())
+        }
+    }
+    fn member(&self, value: T) -> bool {
+        self.lookup(value).is_some()
+    }
+}
+
+impl<K: Copy, V: Copy, P: SharedPointer> SmallTreeIn<K, V, P> {
+    /// Tests whether this tree is empty.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+    /// Returns an iterator over the bindings in this tree, in order of key.
+    pub fn iter(&self) -> Iter<'_, K, V, P> {
+        Iter {
+            stack: self.root.iter().map(|root| Work::Node(&**root)).collect(),
+            leaf: [].iter(),
+        }
+    }
+}
+
+impl<K: Copy, V: Copy, P: SharedPointer> Collection for SmallTreeIn<K, V, P> {
+    type Iter<'a> = Iter<'a, K, V, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        // A tree keeps no count of its bindings, so this takes linear time.
+        self.iter().count()
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, K, V, P> {
+        self.iter()
+    }
+}
+
+impl<K: Ord + Copy, V: Copy, P: SharedPointer> Node<K, V, P> {
+    /// Returns a leaf of `bindings`, of which there must be between one and
+    /// `LEAF_SIZE`.
+    fn leaf(bindings: &[(K, V)]) -> Self {
+        let mut array = [bindings[0]; LEAF_SIZE];
+        array[..bindings.len()].copy_from_slice(bindings);
+        Node::Leaf {
+            len: bindings.len() as u8,
+            bindings: array,
+        }
+    }
+    /// Returns a leaf of `bindings` with `key` bound to `value`, or a branch
+    /// of two leaves if that is too many for one.
+    fn leaf_with(bindings: &[(K, V)], key: K, value: V) -> Self {
+        let mut all = [(key, value); LEAF_SIZE + 1];
+        match bindings.binary_search_by(|binding| binding.0.cmp(&key)) {
+            Ok(i) => {
+                all[..bindings.len()].copy_from_slice(bindings);
+                all[i].1 = value;
+                return Node::leaf(&all[..bindings.len()]);
+            }
+            Err(i) => {
+                all[..i].copy_from_slice(&bindings[..i]);
+                all[i + 1..bindings.len() + 1].copy_from_slice(&bindings[i..]);
+            }
+        }
+        if bindings.len() < LEAF_SIZE {
+            return Node::leaf(&all[..bindings.len() + 1]);
+        }
+        let (key, value) = all[LEAF_SIZE / 2];
+        Node::Branch {
+            left: Shared::new(Node::leaf(&all[..LEAF_SIZE / 2])),
+            key: key,
+            value: value,
+            right: Shared::new(Node::leaf(&all[LEAF_SIZE / 2 + 1..])),
+        }
+    }
+    /// Returns `bottom` with copies of the branches on `path` above it, as
+    /// for `Tree`.
+    fn copy_path(mut path: Vec<(&Self, bool)>, bottom: Self) -> Self {
+        let mut node = bottom;
+        while let Some((branch, went_left)) = path.pop() {
+            if let Node::Branch { ref left, key, value, ref right } = *branch {
+                let (left, right) = if went_left {
+                    (Shared::new(node), right.clone())
+                } else {
+                    (left.clone(), Shared::new(node))
+                };
+                node = Node::Branch {
+                    left: left,
+                    key: key,
+                    value: value,
+                    right: right,
+                };
+            }
+        }
+        node
+    }
+}
+
+impl<K: Copy, V: Copy, P: SharedPointer> Node<K, V, P> {
+    /// Moves each child of this branch which no other tree shares and which
+    /// is itself a branch onto `branches`, leaving a leaf in its place.
+    fn take_unshared_branches(&mut self, branches: &mut Vec<Self>) {
+        if let Node::Branch { ref mut left, key, value, ref mut right } = *self {
+            for child in &mut [left, right] {
+                if let Some(child) = Shared::get_mut(child) {
+                    if let Node::Branch { .. } = *child {
+                        let filler = Node::Leaf {
+                            len: 1,
+                            bindings: [(key, value); LEAF_SIZE],
+                        };
+                        branches.push(mem::replace(child, filler));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<K: Copy, V: Copy, P: SharedPointer> Drop for Node<K, V, P> {
+    fn drop(&mut self) {
+        // As for `Tree`, so that dropping a deep tree does not recurse once
+        // per level.
+        let mut branches = Vec::new();
+        self.take_unshared_branches(&mut branches);
+        while let Some(mut branch) = branches.pop() {
+            branch.take_unshared_branches(&mut branches);
+        }
+    }
+}
+
+impl<K, V, P> fmt::Debug for SmallTreeIn<K, V, P>
+    where K: Copy + fmt::Debug,
+          V: Copy + fmt::Debug,
+          P: SharedPointer
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Copy, V: Copy, P: SharedPointer> MemoryUsage for SmallTreeIn<K, V, P> {
+    fn census(&self, census: &mut Census) {
+        let mut stack: Vec<_> = self.root.iter().collect();
+        while let Some(node) = stack.pop() {
+            if census.node(&**node, 0) {
+                if let Node::Branch { ref left, ref right, .. } = **node {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+    }
+}
+
+/// An iterator over the bindings of a `SmallTree`, in order of key,
+/// returned by `SmallTree::iter`.
+pub struct Iter<'a, K: Copy + 'a, V: Copy + 'a, P: SharedPointer> {
+    /// The parts of the tree still to be visited after `leaf`, the next one
+    /// last.
+    stack: Vec<Work<'a, K, V, P>>,
+    /// The rest of the leaf being visited.
+    leaf: slice::Iter<'a, (K, V)>,
+}
+
+/// Part of a tree still to be visited by an `Iter`.
+enum Work<'a, K: Copy + 'a, V: Copy + 'a, P: SharedPointer> {
+    Node(&'a Node<K, V, P>),
+    Binding(&'a K, &'a V),
+}
+
+impl<'a, K: Copy, V: Copy, P: SharedPointer> Iterator for Iter<'a, K, V, P> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((key, value)) = self.leaf.next() {
+                return Some((key, value));
+            }
+            match self.stack.pop() {
+                None => return None,
+                Some(Work::Binding(key, value)) => return Some((key, value)),
+                Some(Work::Node(Node::Leaf { len, bindings })) => {
+                    self.leaf = bindings[..*len as usize].iter();
+                }
+                Some(Work::Node(Node::Branch { left, key, value, right })) => {
+                    self.stack.push(Work::Node(right));
+                    self.stack.push(Work::Binding(key, value));
+                    self.stack.push(Work::Node(left));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use set::Tree;
+
+#[test]
+fn small_trees_match_trees() {
+    let mut small = SmallTree::empty_map();
+    let mut tree = Tree::empty_map();
+    let mut versions = Vec::new();
+    for i in 0..500u32 {
+        let key = (i * 37) % 250;
+        small = small.bind(key, i);
+        tree = tree.bind(key, i);
+        if i % 100 == 0 {
+            versions.push((small.clone(), tree.clone()));
+        }
+    }
+
+    assert!(small.iter().eq(tree.iter()));
+    assert!((0..260).all(|key| small.lookup(key) == tree.lookup(key)));
+    for (small, tree) in versions {
+        assert!(small.iter().eq(tree.iter()));
+    }
+}
+
+#[test]
+fn small_sets_hold_each_value_once() {
+    let set = (0..100).chain(0..100).fold(SmallTree::empty(), |set, i| set.insert(i % 30));
+
+    assert!(set.iter().map(|(value, _)| *value).collect::<Vec<_>>() == (0..30).collect::<Vec<_>>());
+    assert!(set.member(29) && !set.member(30));
+    assert!(SmallTree::<u8, ()>::empty().iter().next().is_none());
+    assert!(format!("{:?}", SmallTree::empty_map().bind(1, 2)) == "{1: 2}");
+}
@@ -0,0 +1,216 @@
+use collection::Collection;
+use memory::{MemoryReport, MemoryUsage};
+use ptr::{DefaultPointer, SharedPointer};
+
+use hamt::HamtMapIn;
+use intmap::{self, IntMapIn};
+use set::Map;
+
+/// A store of numbered snapshots of a value, some of which can be named, for
+/// use with any of the persistent structures in this crate as savepoints.
+///
+/// Each snapshot saved is numbered one more than the one before it, and the
+/// latest can be given a name with `tag`, by which it can later be found.
+/// As with `History`, keeping many snapshots of a persistent structure costs
+/// little more than the changes between them, and `memory_costs` measures
+/// how much each one adds. Old snapshots can be dropped with `drop_before`.
+///
+/// The store is itself immutable: each method returns a new store and
+/// leaves this one as it was.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::hamt::HamtMap;
+/// use functional_datastructures::snapshot::SnapshotStore;
+///
+/// let accounts = HamtMap::new().insert("alice", 100).insert("bob", 50);
+/// let store = SnapshotStore::new().save(accounts.clone()).tag("opening").unwrap();
+/// let moved = accounts.insert("alice", 80).insert("bob", 70);
+/// let store = store.save(moved).tag("after transfer").unwrap();
+///
+/// assert!(store.get("opening").unwrap().get(&"alice") == Some(&100));
+/// assert!(store.get("after transfer").unwrap().get(&"bob") == Some(&70));
+///
+/// let store = store.drop_before(1);
+/// assert!(store.get("opening").is_none() && store.size() == 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SnapshotStoreIn<T, P: SharedPointer> {
+    /// The snapshots kept, by number.
+    snapshots: IntMapIn<T, P>,
+    /// The number of the snapshot each name refers to.
+    tags: HamtMapIn<String, u64, P>,
+    /// The number of the next snapshot to be saved.
+    next: u64,
+}
+
+/// A `SnapshotStoreIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type SnapshotStore<T> = SnapshotStoreIn<T, DefaultPointer>;
+
+impl<T: Clone, P: SharedPointer> SnapshotStoreIn<T, P> {
+    /// Returns a store which holds no snapshots.
+    pub fn new() -> Self {
+        SnapshotStoreIn {
+            snapshots: IntMapIn::empty_map(),
+            tags: HamtMapIn::new(),
+            next: 0,
+        }
+    }
+    /// Returns a copy of this store with `snapshot` saved as the latest
+    /// snapshot.
+    pub fn save(&self, snapshot: T) -> Self {
+        SnapshotStoreIn {
+            snapshots: self.snapshots.insert(self.next, snapshot),
+            tags: self.tags.clone(),
+            next: self.next + 1,
+        }
+    }
+    /// Returns a copy of this store with the latest snapshot named `name`,
+    /// or `None` if the latest snapshot has been dropped or none has been
+    /// saved. A snapshot can have several names, and a name which already
+    /// referred to a snapshot is moved to the latest one.
+    pub fn tag(&self, name: &str) -> Option<Self> {
+        let latest = match self.latest() {
+            Some((number, _)) => number,
+            None => return None,
+        };
+        Some(SnapshotStoreIn {
+            snapshots: self.snapshots.clone(),
+            tags: self.tags.insert(name.to_string(), latest),
+            next: self.next,
+        })
+    }
+    /// Returns the snapshot named `name`, or `None` if there is none.
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.number_of(name).and_then(|number| self.snapshot(number))
+    }
+    /// Returns the number of the snapshot named `name`, or `None` if there
+    /// is none.
+    pub fn number_of(&self, name: &str) -> Option<u64> {
+        self.tags.get(&name.to_string()).cloned()
+    }
+    /// Returns the snapshot numbered `number`, or `None` if it has been
+    /// dropped or has not been saved yet.
+    pub fn snapshot(&self, number: u64) -> Option<&T> {
+        self.snapshots.get(number)
+    }
+    /// Returns the latest snapshot and its number, or `None` if it has been
+    /// dropped or none has been saved.
+    pub fn latest(&self) -> Option<(u64, &T)> {
+        if self.next == 0 {
+            return None;
+        }
+        self.snapshot(self.next - 1).map(|snapshot| (self.next - 1, snapshot))
+    }
+    /// Returns a copy of this store without the snapshots numbered below
+    /// `number`, or the names which referred to them. Later snapshots keep
+    /// their numbers.
+    pub fn drop_before(&self, number: u64) -> Self {
+        let dropped: Vec<_> = self.snapshots.keys().take_while(|&key| key < number).collect();
+        SnapshotStoreIn {
+            snapshots: dropped.iter().fold(self.snapshots.clone(), |kept, &key| kept.remove(key)),
+            tags: self.tags
+                      .iter()
+                      .filter(|&(_, &tagged)| tagged < number)
+                      .fold(self.tags.clone(), |kept, (name, _)| kept.remove(name)),
+            next: self.next,
+        }
+    }
+    /// Returns the number of snapshots kept.
+    pub fn size(&self) -> u32 {
+        self.snapshots.size()
+    }
+    /// Tests whether this store keeps no snapshots.
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+    /// Returns an iterator over the snapshots kept and their numbers, oldest
+    /// first.
+    pub fn iter(&self) -> intmap::Iter<'_, T, P> {
+        self.snapshots.iter()
+    }
+    /// Returns the number of each snapshot kept, oldest first, along with
+    /// the estimated number of bytes it adds to the snapshots before it: its
+    /// own size and that of the nodes it shares with none of them.
+    ///
+    /// The cost of a snapshot therefore depends on which earlier snapshots
+    /// are still kept, and the first one kept is charged for all of its
+    /// nodes. See `MemoryReport` for what the estimate includes.
+    pub fn memory_costs(&self) -> Vec<(u64, usize)>
+        where T: MemoryUsage
+    {
+        let costs = MemoryReport::incremental_bytes(self.iter().map(|(_, snapshot)| snapshot));
+        self.snapshots.keys().zip(costs).collect()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Collection for SnapshotStoreIn<T, P> {
+    type Iter<'a> = intmap::Iter<'a, T, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> intmap::Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Default for SnapshotStoreIn<T, P> {
+    fn default() -> Self {
+        SnapshotStoreIn::new()
+    }
+}
+
+#[cfg(test)]
+use set::{Set, Tree};
+
+#[test]
+fn snapshots_are_found_by_name_and_number() {
+    let store = (0..5).fold(SnapshotStore::new(), |store, i| {
+        let store = store.save(i * 10);
+        if i % 2 == 0 {
+            store.tag(&format!("even {}", i)).unwrap()
+        } else {
+            store
+        }
+    });
+
+    assert!(store.size() == 5 && store.latest() == Some((4, &40)));
+    assert!(store.get("even 2") == Some(&20) && store.number_of("even 4") == Some(4));
+    assert!(store.get("odd 1").is_none() && store.snapshot(3) == Some(&30));
+
+    let moved = store.save(50).tag("even 2").unwrap();
+    assert!(moved.get("even 2") == Some(&50) && store.get("even 2") == Some(&20));
+    assert!(SnapshotStore::<u32>::new().tag("nothing").is_none());
+}
+
+#[test]
+fn dropping_old_snapshots_keeps_numbers_and_later_names() {
+    let store = (0..10).fold(SnapshotStore::new(), |store, i| {
+        store.save(i).tag(&i.to_string()).unwrap()
+    });
+    let dropped = store.drop_before(7);
+
+    assert!(dropped.iter().map(|(number, _)| number).collect::<Vec<_>>() == vec![7, 8, 9]);
+    assert!(dropped.get("6").is_none() && dropped.get("7") == Some(&7));
+    assert!(dropped.save(10).latest() == Some((10, &10)));
+    assert!(store.size() == 10 && store.get("0") == Some(&0));
+
+    let all_dropped = store.drop_before(100);
+    assert!(all_dropped.is_empty() && all_dropped.latest().is_none());
+    assert!(all_dropped.tag("gone").is_none());
+}
+
+#[test]
+fn memory_costs_count_what_each_snapshot_adds() {
+    let base: Tree<u32, ()> = (0..1000).fold(Tree::empty(), |tree, i| tree.insert((i * 37) % 1000));
+    let store = SnapshotStore::new().save(base.clone()).save(base.insert(1000)).save(base.clone());
+    let costs = store.memory_costs();
+
+    assert!(costs.iter().map(|&(number, _)| number).collect::<Vec<_>>() == vec![0, 1, 2]);
+    assert!(costs[1].1 * 10 < costs[0].1);
+    assert!(costs[2].1 == std::mem::size_of::<Tree<u32, ()>>());
+    assert!(store.drop_before(1).memory_costs()[0].1 > costs[1].1);
+}
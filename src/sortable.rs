@@ -0,0 +1,397 @@
+use lazy::SuspIn;
+use ptr::{DefaultPointer, SendFor, Shared, SharedPointer, SyncFor};
+use schedule::ScheduleIn;
+use stack::{CustomStackIn, Stack};
+use stream::{StreamIn, StreamCellIn};
+
+/// A trait representing a collection which elements can be added to one at
+/// a time and which can produce a sorted list of its contents on demand.
+pub trait Sortable<T: Ord> {
+    /// The kind of pointer the stacks returned by `sort` are shared through.
+    type Pointer: SharedPointer;
+
+    /// Returns a collection containing nothing.
+    fn empty() -> Self;
+    /// Returns a copy of this collection with `value` added to it.
+    fn add(&self, value: T) -> Self;
+    /// Returns the contents of this collection as a stack, smallest first.
+    fn sort(&self) -> CustomStackIn<T, Self::Pointer>;
+    /// Returns the number of items in this collection.
+    fn size(&self) -> u32;
+}
+
+/// A sortable collection using bottom-up mergesort with sharing.
+///
+/// The collection is a list of sorted segments whose sizes are the binary
+/// digits of the collection's size, smallest first. Adding an element merges
+/// equal-sized segments like a binary carry, inside a suspension, so `add`
+/// takes O(log n) amortized time and `sort` takes O(n) even when persistent
+/// versions of the collection are sorted repeatedly.
+///
+/// This is the `BottomUpMergeSort` structure described in Chapter 6 of PFDL.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::sortable::{MergeSort, Sortable};
+/// use functional_datastructures::stack::Stack;
+///
+/// let sortable = MergeSort::empty().add(3).add(1).add(2);
+/// let sorted = sortable.sort();
+///
+/// assert!(sorted.get(0).unwrap() == 1);
+/// assert!(sorted.get(1).unwrap() == 2);
+/// assert!(sorted.get(2).unwrap() == 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MergeSortIn<T, P: SharedPointer> {
+    size: u32,
+    segments: SuspIn<Segments<T, P>, P>,
+}
+
+/// A `MergeSortIn` whose segments are shared through `DefaultPointer`, as
+/// exported at the root of the crate.
+pub type MergeSort<T> = MergeSortIn<T, DefaultPointer>;
+
+/// The sorted segments of a `MergeSortIn`, the smallest first.
+type Segments<T, P> = CustomStackIn<Shared<CustomStackIn<T, P>, P>, P>;
+
+impl<T, P> Sortable<T> for MergeSortIn<T, P>
+    where T: Ord + Clone + SendFor<P> + SyncFor<P> + 'static,
+          P: SharedPointer
+{
+    type Pointer = P;
+
+    fn empty() -> Self {
+        MergeSortIn {
+            size: 0,
+            segments: SuspIn::ready(CustomStackIn::empty()),
+        }
+    }
+    fn add(&self, value: T) -> Self {
+        let segments = self.segments.clone();
+        let size = self.size;
+        MergeSortIn {
+            size: size + 1,
+            // SAFETY: the thunk only captures `value` and the suspended
+            // segments of `T`, which are `SendFor<P>` since `T` is.
+            segments: unsafe {
+                SuspIn::new_unchecked(move || {
+                    let mut segment = CustomStackIn::empty().cons(value);
+                    let mut segments = segments.force().clone();
+                    let mut carry = size;
+                    while carry % 2 == 1 {
+                        let (smaller, rest) = match segments {
+                            CustomStackIn::Cons { ref value, ref tail } => {
+                                (value.clone(), tail.clone())
+                            }
+                            CustomStackIn::Empty => {
+                                unreachable!("fewer segments than size implies")
+                            }
+                        };
+                        segment = merge(&smaller, &segment);
+                        segments = (*rest).clone();
+                        carry /= 2;
+                    }
+                    segments.cons(Shared::new(segment))
+                })
+            },
+        }
+    }
+    fn sort(&self) -> CustomStackIn<T, P> {
+        let mut sorted = CustomStackIn::empty();
+        let mut segments = self.segments.force().clone();
+        loop {
+            segments = match segments {
+                CustomStackIn::Empty => return sorted,
+                CustomStackIn::Cons { ref value, ref tail } => {
+                    sorted = merge(&sorted, value);
+                    (**tail).clone()
+                }
+            };
+        }
+    }
+    fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+/// Merges two sorted stacks into a single sorted stack. Where elements are
+/// equal, those from `xs` come first.
+fn merge<T: Ord + Clone, P: SharedPointer>(xs: &CustomStackIn<T, P>,
+                                           ys: &CustomStackIn<T, P>)
+                                           -> CustomStackIn<T, P> {
+    let mut merged = Vec::new();
+    let mut xs = xs;
+    let mut ys = ys;
+    loop {
+        match (xs, ys) {
+            (&CustomStackIn::Empty, _) | (_, &CustomStackIn::Empty) => break,
+            (&CustomStackIn::Cons { value: ref x, tail: ref xs_tail },
+             &CustomStackIn::Cons { value: ref y, tail: ref ys_tail }) => {
+                if y < x {
+                    merged.push(y.clone());
+                    ys = &**ys_tail;
+                } else {
+                    merged.push(x.clone());
+                    xs = &**xs_tail;
+                }
+            }
+        }
+    }
+    // Whichever stack has elements left is shared as the tail of the result.
+    let rest = if xs.is_empty() { ys.clone() } else { xs.clone() };
+    merged.into_iter().rev().fold(rest, |stack, value| stack.cons(value))
+}
+
+
+/// A sortable collection using bottom-up mergesort with a schedule per
+/// segment, so that `add` takes O(log n) time in the worst case rather than
+/// only amortized.
+///
+/// Each segment is a lazily merged stream together with a schedule of the
+/// merges that built it. Every `add` performs two steps of each segment's
+/// schedule, which is enough to have fully evaluated a segment by the time
+/// it has to be merged again. `sort` takes O(n) time.
+///
+/// This is the `ScheduledBottomUpMergeSort` structure described in Chapter 7
+/// of PFDL.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::sortable::{ScheduledMergeSort, Sortable};
+/// use functional_datastructures::stack::Stack;
+///
+/// let sortable = ScheduledMergeSort::empty().add(3).add(1).add(2);
+/// let sorted = sortable.sort();
+///
+/// assert!(sorted.get(0).unwrap() == 1);
+/// assert!(sorted.get(1).unwrap() == 2);
+/// assert!(sorted.get(2).unwrap() == 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScheduledMergeSortIn<T, P: SharedPointer> {
+    size: u32,
+    segments: CustomStackIn<Segment<T, P>, P>,
+}
+
+/// A `ScheduledMergeSortIn` whose segments are shared through
+/// `DefaultPointer`, as exported at the root of the crate.
+pub type ScheduledMergeSort<T> = ScheduledMergeSortIn<T, DefaultPointer>;
+
+/// A sorted segment and the schedule of merges still to be forced in it.
+#[derive(Debug, Clone)]
+struct Segment<T, P: SharedPointer> {
+    stream: StreamIn<T, P>,
+    schedule: ScheduleIn<T, P>,
+}
+
+impl<T, P> Sortable<T> for ScheduledMergeSortIn<T, P>
+    where T: Ord + Clone + SendFor<P> + SyncFor<P> + 'static,
+          P: SharedPointer
+{
+    type Pointer = P;
+
+    fn empty() -> Self {
+        ScheduledMergeSortIn {
+            size: 0,
+            segments: CustomStackIn::empty(),
+        }
+    }
+    fn add(&self, value: T) -> Self {
+        let mut stream = StreamIn::empty().cons(value);
+        let mut segments = self.segments.clone();
+        let mut merges = Vec::new();
+        let mut carry = self.size;
+        while carry % 2 == 1 {
+            let (smaller, rest) = match segments {
+                CustomStackIn::Cons { ref value, ref tail } => (value.clone(), (**tail).clone()),
+                CustomStackIn::Empty => unreachable!("fewer segments than size implies"),
+            };
+            debug_assert!(smaller.schedule.is_empty(),
+                          "segment merged before its schedule completed");
+            stream = merge_streams(&stream, &smaller.stream);
+            merges.push(stream.clone());
+            segments = rest;
+            carry /= 2;
+        }
+        // The earliest merge has to be forced first, so it goes on last.
+        let schedule = merges.into_iter().rev().fold(ScheduleIn::empty(), |s, m| s.push(m));
+        let segments = segments.cons(Segment {
+            stream: stream,
+            schedule: schedule,
+        });
+        ScheduledMergeSortIn {
+            size: self.size + 1,
+            segments: exec2_all(&segments),
+        }
+    }
+    fn sort(&self) -> CustomStackIn<T, P> {
+        let mut sorted = StreamIn::empty();
+        let mut segments = self.segments.clone();
+        loop {
+            segments = match segments {
+                CustomStackIn::Empty => break,
+                CustomStackIn::Cons { ref value, ref tail } => {
+                    sorted = merge_streams(&sorted, &value.stream);
+                    (**tail).clone()
+                }
+            };
+        }
+        let mut values = Vec::new();
+        loop {
+            let next = match *sorted.force() {
+                StreamCellIn::Nil => break,
+                StreamCellIn::Cons(ref value, ref tail) => {
+                    values.push(value.clone());
+                    tail.clone()
+                }
+            };
+            sorted = next;
+        }
+        values.into_iter().rev().fold(CustomStackIn::empty(), |stack, value| stack.cons(value))
+    }
+    fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+/// Performs two steps of the schedule of every segment in `segments`.
+fn exec2_all<T, P>(segments: &CustomStackIn<Segment<T, P>, P>) -> CustomStackIn<Segment<T, P>, P>
+    where T: Clone + SendFor<P> + SyncFor<P> + 'static,
+          P: SharedPointer
+{
+    match *segments {
+        CustomStackIn::Empty => CustomStackIn::empty(),
+        CustomStackIn::Cons { ref value, ref tail } => {
+            exec2_all(tail).cons(Segment {
+                stream: value.stream.clone(),
+                schedule: value.schedule.exec2(),
+            })
+        }
+    }
+}
+
+/// Lazily merges two sorted streams. This is incremental: forcing each cell
+/// of the result forces at most one more cell of each input. Where elements
+/// are equal, those from `xs` come first.
+fn merge_streams<T, P>(xs: &StreamIn<T, P>, ys: &StreamIn<T, P>) -> StreamIn<T, P>
+    where T: Ord + Clone + SendFor<P> + SyncFor<P> + 'static,
+          P: SharedPointer
+{
+    let xs = xs.clone();
+    let ys = ys.clone();
+    // SAFETY: the thunk only captures two streams of `T`, which are
+    // `SendFor<P>` since `T` is.
+    unsafe { StreamIn::lazy_unchecked(move || {
+        match (xs.force(), ys.force()) {
+            (&StreamCellIn::Nil, cell) | (cell, &StreamCellIn::Nil) => cell.clone(),
+            (&StreamCellIn::Cons(ref x, ref xs_tail), &StreamCellIn::Cons(ref y, ref ys_tail)) => {
+                if y < x {
+                    StreamCellIn::Cons(y.clone(), merge_streams(&xs, ys_tail))
+                } else {
+                    StreamCellIn::Cons(x.clone(), merge_streams(xs_tail, &ys))
+                }
+            }
+        }
+    }) }
+}
+
+
+#[cfg(test)]
+use stack::CustomStack;
+
+#[cfg(test)]
+fn to_vec<T: Clone>(stack: &CustomStack<T>) -> Vec<T> {
+    (0..stack.size()).map(|i| stack.get(i).unwrap()).collect()
+}
+
+#[test]
+fn empty_sorts_to_empty() {
+    let sortable: MergeSort<u32> = MergeSort::empty();
+
+    assert!(sortable.sort().is_empty());
+    assert!(sortable.size() == 0);
+}
+
+#[test]
+fn sorts_added_values() {
+    let values = vec![5, 3, 9, 1, 1, 7, 2, 8, 0, 4, 6];
+    let sortable = values.iter().fold(MergeSort::empty(), |s, v| s.add(*v));
+
+    let mut expected = values.clone();
+    expected.sort();
+
+    assert!(sortable.size() == 11);
+    assert!(to_vec(&sortable.sort()) == expected);
+}
+
+#[test]
+fn persistent_versions_sort_independently() {
+    let base = MergeSort::empty().add(4).add(2);
+    let with_three = base.add(3);
+    let with_one = base.add(1);
+
+    assert!(to_vec(&base.sort()) == vec![2, 4]);
+    assert!(to_vec(&with_three.sort()) == vec![2, 3, 4]);
+    assert!(to_vec(&with_one.sort()) == vec![1, 2, 4]);
+}
+
+#[test]
+fn merge_interleaves() {
+    let xs = CustomStack::empty().cons(5).cons(3).cons(1);
+    let ys = CustomStack::empty().cons(6).cons(4).cons(2);
+
+    assert!(to_vec(&merge(&xs, &ys)) == vec![1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn scheduled_sorts_added_values() {
+    let values = vec![5, 3, 9, 1, 1, 7, 2, 8, 0, 4, 6];
+    let sortable = values.iter().fold(ScheduledMergeSort::empty(), |s, v| s.add(*v));
+
+    let mut expected = values.clone();
+    expected.sort();
+
+    assert!(sortable.size() == 11);
+    assert!(to_vec(&sortable.sort()) == expected);
+}
+
+#[test]
+fn scheduled_persistent_versions_sort_independently() {
+    let base = ScheduledMergeSort::empty().add(4).add(2);
+    let with_three = base.add(3);
+    let with_one = base.add(1);
+
+    assert!(to_vec(&base.sort()) == vec![2, 4]);
+    assert!(to_vec(&with_three.sort()) == vec![2, 3, 4]);
+    assert!(to_vec(&with_one.sort()) == vec![1, 2, 4]);
+}
+
+#[test]
+fn scheduled_segments_evaluated_before_merging() {
+    let sortable = (0..15).rev().fold(ScheduledMergeSort::empty(), |s, v| s.add(v));
+
+    // The next `add` merges every segment, so each of them has to have been
+    // completely evaluated by its schedule already.
+    let mut segments = sortable.segments.clone();
+    while let CustomStack::Cons { value: ref segment, ref tail } = segments {
+        assert!(segment.schedule.is_empty());
+        let mut stream = segment.stream.clone();
+        while stream.is_forced() && !stream.is_empty() {
+            stream = stream.tail().unwrap();
+        }
+        assert!(stream.is_forced());
+        segments = (**tail).clone();
+    }
+    assert!(to_vec(&sortable.add(15).sort()) == (0..16).collect::<Vec<_>>());
+}
+
+#[test]
+fn scheduled_sorts_many_values() {
+    let values: Vec<u32> = (0..1000).map(|i| (i * 7919) % 1000).collect();
+    let sortable = values.iter().fold(ScheduledMergeSort::empty(), |s, v| s.add(*v));
+
+    assert!(to_vec(&sortable.sort()) == (0..1000).collect::<Vec<_>>());
+}
@@ -0,0 +1,282 @@
+use collection::Collection;
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+use stack::CustomStackIn;
+
+/// An immutable list which keeps its elements in ascending order,
+/// implemented as a `CustomStack`.
+///
+/// Inserting or removing an element copies the elements before it and shares
+/// the rest of the list, so both take O(n) time, while the smallest element
+/// can be read or removed in O(1) time. The greatest element is kept
+/// alongside the list, so it is also at hand in O(1) time. Merging two lists
+/// takes time linear in the elements which have to be interleaved: once one
+/// list runs out, the rest of the other is shared rather than copied.
+///
+/// This is a lightweight alternative to a balanced tree for small
+/// collections which need to stay sorted.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::sorted_list::SortedList;
+///
+/// let odds = SortedList::empty().insert(5).insert(1).insert(3);
+/// let evens = SortedList::empty().insert(2).insert(4).insert(6).insert(8);
+/// let all = odds.merge(&evens);
+///
+/// assert!(all.iter().cloned().collect::<Vec<_>>() == vec![1, 2, 3, 4, 5, 6, 8]);
+/// assert!(all.min() == Some(&1));
+/// assert!(all.max() == Some(&8));
+/// assert!(all.remove(&8).max() == Some(&6));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SortedListIn<T, P: SharedPointer> {
+    list: Shared<CustomStackIn<T, P>, P>,
+    max: Option<T>,
+}
+
+/// A `SortedListIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type SortedList<T> = SortedListIn<T, DefaultPointer>;
+
+impl<T: Ord + Clone, P: SharedPointer> SortedListIn<T, P> {
+    /// Returns an empty list.
+    pub fn empty() -> Self {
+        SortedListIn {
+            list: Shared::new(CustomStackIn::Empty),
+            max: None,
+        }
+    }
+    /// Tests whether this list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.max.is_none()
+    }
+    /// Returns the number of elements in this list. This takes O(n) time.
+    pub fn size(&self) -> u32 {
+        self.iter().count() as u32
+    }
+    /// Returns the smallest element of this list, or `None` if it is empty.
+    pub fn min(&self) -> Option<&T> {
+        match *self.list {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref value, .. } => Some(value),
+        }
+    }
+    /// Returns the greatest element of this list, or `None` if it is empty.
+    pub fn max(&self) -> Option<&T> {
+        self.max.as_ref()
+    }
+    /// Returns a copy of this list with `value` added after any elements
+    /// which are less than or equal to it.
+    pub fn insert(&self, value: T) -> Self {
+        let max = match self.max {
+            Some(ref max) if *max > value => max.clone(),
+            _ => value.clone(),
+        };
+        let mut before = Vec::new();
+        let mut rest = &self.list;
+        while let CustomStackIn::Cons { value: ref head, ref tail } = **rest {
+            if *head > value {
+                break;
+            }
+            before.push(head.clone());
+            rest = tail;
+        }
+        SortedListIn {
+            list: prepend(before, Shared::new(cons(value, rest.clone()))),
+            max: Some(max),
+        }
+    }
+    /// Returns a copy of this list with one occurrence of `value` removed, or
+    /// an unchanged copy if it does not occur.
+    pub fn remove(&self, value: &T) -> Self {
+        let mut before = Vec::new();
+        let mut rest = &self.list;
+        while let CustomStackIn::Cons { value: ref head, ref tail } = **rest {
+            if *head > *value {
+                break;
+            } else if *head == *value {
+                let max = match **tail {
+                    CustomStackIn::Empty => before.last().cloned(),
+                    _ => self.max.clone(),
+                };
+                return SortedListIn {
+                    list: prepend(before, tail.clone()),
+                    max: max,
+                };
+            }
+            before.push(head.clone());
+            rest = tail;
+        }
+        self.clone()
+    }
+    /// Returns the smallest element of this list and the list without it, or
+    /// `None` if it is empty.
+    pub fn remove_min(&self) -> Option<(T, Self)> {
+        match *self.list {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref value, ref tail } => {
+                let max = match **tail {
+                    CustomStackIn::Empty => None,
+                    _ => self.max.clone(),
+                };
+                Some((value.clone(),
+                      SortedListIn {
+                          list: tail.clone(),
+                          max: max,
+                      }))
+            }
+        }
+    }
+    /// Returns a sorted list of the elements of this list and `other`. Where
+    /// elements are equal, those from this list come first.
+    pub fn merge(&self, other: &Self) -> Self {
+        let mut merged = Vec::new();
+        let mut ours = &self.list;
+        let mut theirs = &other.list;
+        let rest = loop {
+            match (&**ours, &**theirs) {
+                (&CustomStackIn::Empty, _) => break theirs,
+                (_, &CustomStackIn::Empty) => break ours,
+                (&CustomStackIn::Cons { value: ref a, tail: ref a_tail },
+                 &CustomStackIn::Cons { value: ref b, tail: ref b_tail }) => {
+                    if a <= b {
+                        merged.push(a.clone());
+                        ours = a_tail;
+                    } else {
+                        merged.push(b.clone());
+                        theirs = b_tail;
+                    }
+                }
+            }
+        };
+        let max = match (&self.max, &other.max) {
+            (&Some(ref a), &Some(ref b)) => Some(if a > b { a } else { b }.clone()),
+            (max, &None) | (&None, max) => max.clone(),
+        };
+        SortedListIn {
+            list: prepend(merged, rest.clone()),
+            max: max,
+        }
+    }
+    /// Returns the elements of this list as a stack, smallest first.
+    pub fn to_stack(&self) -> Shared<CustomStackIn<T, P>, P> {
+        self.list.clone()
+    }
+    /// Returns an iterator over the elements of this list, in ascending order.
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter { rest: &self.list }
+    }
+}
+
+impl<T: Ord + Clone, P: SharedPointer> Collection for SortedListIn<T, P> {
+    type Iter<'a> = Iter<'a, T, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+fn cons<T, P>(value: T, tail: Shared<CustomStackIn<T, P>, P>) -> CustomStackIn<T, P>
+    where P: SharedPointer
+{
+    CustomStackIn::Cons {
+        value: value,
+        tail: tail,
+    }
+}
+
+/// Returns `list` with the elements of `values` added to its front, in the
+/// order they are in `values`.
+fn prepend<T, P>(values: Vec<T>,
+                 list: Shared<CustomStackIn<T, P>, P>)
+                 -> Shared<CustomStackIn<T, P>, P>
+    where P: SharedPointer
+{
+    values.into_iter().rev().fold(list, |tail, value| Shared::new(cons(value, tail)))
+}
+
+/// An iterator over the elements of a `SortedList`, in ascending order.
+pub struct Iter<'a, T: 'a, P: SharedPointer> {
+    rest: &'a CustomStackIn<T, P>,
+}
+
+impl<'a, T, P: SharedPointer> Iterator for Iter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        match *self.rest {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref value, ref tail } => {
+                self.rest = tail;
+                Some(value)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use stack::CustomStack;
+
+#[cfg(test)]
+fn contents(list: &SortedList<u32>) -> Vec<u32> {
+    list.iter().cloned().collect()
+}
+
+#[test]
+fn empty_list_has_no_extremes() {
+    let list: SortedList<u32> = SortedList::empty();
+
+    assert!(list.is_empty());
+    assert!(list.min().is_none() && list.max().is_none());
+    assert!(list.remove_min().is_none());
+    assert!(list.remove(&1).is_empty());
+}
+
+#[test]
+fn insertion_keeps_order() {
+    let values: Vec<u32> = (0..200).map(|i| (i * 7919) % 100).collect();
+    let list = values.iter().fold(SortedList::empty(), |list, v| list.insert(*v));
+
+    let mut expected = values;
+    expected.sort();
+    assert!(contents(&list) == expected);
+    assert!(list.size() == 200);
+    assert!(list.min() == Some(&0) && list.max() == Some(&99));
+
+    let (min, rest) = list.remove_min().unwrap();
+    assert!(min == 0 && rest.min() == Some(&0));
+    assert!(contents(&list.remove(&50)).len() == 199);
+    assert!(list.remove(&99).remove(&99).max() == Some(&98));
+    assert!(list.remove(&1000).size() == 200);
+}
+
+#[test]
+fn merge_shares_the_leftover_suffix() {
+    let low = (0..10).fold(SortedList::empty(), |list, v| list.insert(v * 2));
+    let high = (5..100).fold(SortedList::empty(), |list, v| list.insert(v));
+
+    let merged = low.merge(&high);
+    let mut expected: Vec<u32> = (0..10).map(|v| v * 2).chain(5..100).collect();
+    expected.sort();
+    assert!(contents(&merged) == expected);
+    assert!(merged.max() == Some(&99));
+
+    let mut suffix = &merged.to_stack();
+    for _ in 0..(expected.len() - 81) {
+        if let CustomStack::Cons { ref tail, .. } = **suffix {
+            suffix = tail;
+        }
+    }
+    let mut shared = &high.to_stack();
+    for _ in 0..14 {
+        if let CustomStack::Cons { ref tail, .. } = **shared {
+            shared = tail;
+        }
+    }
+    assert!(Shared::ptr_eq(suffix, shared));
+}
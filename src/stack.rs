@@ -1,13 +1,20 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::iter::Take;
+use std::marker::PhantomData;
+use std::mem;
 
-#[derive(Debug)]
-pub enum StackError {
-    NoSuchElementException,
-    IndexOutOfRange,
-}
+use collection::{Collection, MapElems, Traverse};
+use memory::{Census, MemoryUsage};
+use sequence::{Cons, Sequence, Uncons};
+
+pub use error::StackError;
+use ptr::{DefaultPointer, Shared, SharedPointer};
 
 /// A trait representing an immutable Stack type.
 pub trait Stack<T: Clone> {
+    /// The kind of pointer the tail of a stack is shared through.
+    type Pointer: SharedPointer;
+
     /// Returns an empty stack.
     fn empty() -> Self;
     /// Tests whether a stack is empty.
@@ -25,7 +32,7 @@ pub trait Stack<T: Clone> {
     /// # Failures
     /// 
     /// Returns `StackError::NoSuchElementException` if this is an empty stack.
-    fn tail(&self) -> Result<Arc<Self>, StackError>;
+    fn tail(&self) -> Result<Shared<Self, Self::Pointer>, StackError>;
     /// Returns a stack identical to this one except that the value at index `i`
     /// is replaced by `value`.
     ///
@@ -33,7 +40,7 @@ pub trait Stack<T: Clone> {
     ///
     /// Returns `StackError::IndexOutOfRange` if `i` is greater than the greatest 
     /// index currently in this stack (size - 1).
-    fn update(&self, i: u32, value: T) -> Result<Self, StackError>;
+    fn update(&self, i: u32, value: T) -> Result<Self, StackError> where Self: Sized;
     /// Returns the number of items in this stack.
     fn size(&self) -> u32;
     /// Returns the item currently at index `i` in the stack.
@@ -43,52 +50,90 @@ pub trait Stack<T: Clone> {
     /// Returns `StackError::IndexOutOfRange` if `i` is greater than the greatest 
     /// index currently in this stack (size - 1).
     fn get(&self, i: u32) -> Result<T, StackError>;
+    /// Returns the head item of the stack, or `None` if it is empty.
+    fn head_opt(&self) -> Option<T> {
+        self.head().ok()
+    }
+    /// Returns the tail of the stack, or `None` if it is empty.
+    fn tail_opt(&self) -> Option<Shared<Self, Self::Pointer>> {
+        self.tail().ok()
+    }
+    /// Returns the item at index `i` in the stack, or `None` if `i` is
+    /// greater than the greatest index currently in this stack.
+    fn get_opt(&self, i: u32) -> Option<T> {
+        self.get(i).ok()
+    }
+    /// Makes `value` the head of this stack in place.
+    fn cons_mut(&mut self, value: T)
+        where Self: Sized
+    {
+        *self = self.cons(value);
+    }
+    /// Replaces the value at index `i` of this stack in place. Implementations
+    /// update the items which are not shared with another stack where they
+    /// are, rather than copying them as `update` does.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::IndexOutOfRange` if `i` is greater than the greatest 
+    /// index currently in this stack (size - 1), in which case the stack is
+    /// left as it was.
+    fn update_mut(&mut self, i: u32, value: T) -> Result<(), StackError>
+        where Self: Sized
+    {
+        *self = self.update(i, value)?;
+        Ok(())
+    }
 }
 
 /// An immutable Stack implemented as a singly-linked list.
 ///
 /// This is the `CustomStack` type described in chapter 2 of PFDL.
-#[derive(Debug, Clone)]
-pub enum CustomStack<T> {
+#[derive(Debug, Clone, Default)]
+pub enum CustomStackIn<T, P: SharedPointer> {
+    #[default]
     Empty,
     Cons {
         value: T,
-        tail: Arc<CustomStack<T>>,
+        tail: Shared<CustomStackIn<T, P>, P>,
     },
 }
 
-impl<T: Clone> Stack<T> for CustomStack<T> {
-    fn empty() -> CustomStack<T> {
-        return CustomStack::Empty;
+/// A `CustomStackIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type CustomStack<T> = CustomStackIn<T, DefaultPointer>;
+
+impl<T: Clone, P: SharedPointer> Stack<T> for CustomStackIn<T, P> {
+    type Pointer = P;
+
+    fn empty() -> CustomStackIn<T, P> {
+        return CustomStackIn::Empty;
     }
     fn is_empty(&self) -> bool {
         return match *self {
-            CustomStack::Empty => true,
+            CustomStackIn::Empty => true,
             _ => false,
         };
     }
     fn cons(&self, value: T) -> Self {
-        return CustomStack::Cons {
-            value: value,
-            tail: Arc::new(self.clone()),
-        };
+        CustomStackIn::cons(self, value)
     }
     fn head(&self) -> Result<T, StackError> {
         return match *self {
-            CustomStack::Empty => Err(StackError::NoSuchElementException),
-            CustomStack::Cons { ref value, .. } => Ok(value.clone()),
+            CustomStackIn::Empty => Err(StackError::NoSuchElementException),
+            CustomStackIn::Cons { ref value, .. } => Ok(value.clone()),
         };
     }
-    fn tail(&self) -> Result<Arc<Self>, StackError> {
+    fn tail(&self) -> Result<Shared<Self, P>, StackError> {
         return match *self {
-            CustomStack::Empty => Err(StackError::NoSuchElementException),
-            CustomStack::Cons { ref tail, .. } => Ok(tail.clone()),
+            CustomStackIn::Empty => Err(StackError::NoSuchElementException),
+            CustomStackIn::Cons { ref tail, .. } => Ok(tail.clone()),
         };
     }
     fn update(&self, i: u32, new_value: T) -> Result<Self, StackError> {
         return match *self {
-            CustomStack::Empty => Err(StackError::IndexOutOfRange),
-            CustomStack::Cons { ref value, ref tail } => match i {
+            CustomStackIn::Empty => Err(StackError::IndexOutOfRange),
+            CustomStackIn::Cons { ref value, ref tail } => match i {
                 0 => Ok(tail.clone().cons(new_value)),
                 _ => {
                     let updated_tail = try!(tail.update(i - 1, new_value));
@@ -99,24 +144,433 @@ impl<T: Clone> Stack<T> for CustomStack<T> {
     }
     fn size(&self) -> u32 {
         return match *self {
-            CustomStack::Empty => 0,
-            CustomStack::Cons { ref tail, .. } => 1 + tail.size(),
+            CustomStackIn::Empty => 0,
+            CustomStackIn::Cons { ref tail, .. } => 1 + tail.size(),
         };
     }
     fn get(&self, i: u32) -> Result<T, StackError> {
         return match *self {
-            CustomStack::Empty => Err(StackError::IndexOutOfRange),
-            CustomStack::Cons { ref value, ref tail } => match i {
+            CustomStackIn::Empty => Err(StackError::IndexOutOfRange),
+            CustomStackIn::Cons { ref value, ref tail } => match i {
                 0 => Ok(value.clone()),
                 _ => tail.get(i - 1),
             },
         };
     }
+    fn cons_mut(&mut self, value: T) {
+        // The old stack moves into the new tail rather than being cloned.
+        let tail = mem::replace(self, CustomStackIn::Empty);
+        *self = CustomStackIn::Cons {
+            value: value,
+            tail: Shared::new(tail),
+        };
+    }
+    fn update_mut(&mut self, i: u32, new_value: T) -> Result<(), StackError> {
+        if i >= self.size() {
+            return Err(StackError::IndexOutOfRange);
+        }
+        let mut stack = self;
+        let mut i = i;
+        while let CustomStackIn::Cons { ref mut value, ref mut tail } = *stack {
+            if i == 0 {
+                *value = new_value;
+                break;
+            }
+            i -= 1;
+            stack = Shared::make_mut(tail);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Clone, P: SharedPointer> CustomStackIn<T, P> {
+    /// Returns a new stack with `value` as its head. This is the same as
+    /// `Stack::cons`, here so that calling it is not ambiguous where `Cons`
+    /// is in scope as well.
+    pub fn cons(&self, value: T) -> Self {
+        CustomStackIn::Cons {
+            value: value,
+            tail: Shared::new(self.clone()),
+        }
+    }
+    /// Returns a stack of the items of this stack in runs of `size`, head
+    /// first, where the last run holds whatever is left over.
+    ///
+    /// The last run is a suffix of this stack, so it is shared with it
+    /// rather than copied; the others are built afresh.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn chunks(&self, size: usize) -> CustomStackIn<CustomStackIn<T, P>, P> {
+        assert!(size > 0, "chunks must hold at least one item");
+        let mut remaining = self.iter().count();
+        let mut chunks = Vec::new();
+        let mut current = self;
+        while remaining > size {
+            let chunk: Vec<T> = current.iter().take(size).cloned().collect();
+            for _ in 0..size {
+                current = current.tail_ref().unwrap();
+            }
+            chunks.push(CustomStackIn::from_items(chunk));
+            remaining -= size;
+        }
+        if remaining > 0 {
+            chunks.push(current.clone());
+        }
+        CustomStackIn::from_items(chunks)
+    }
+    /// Returns a stack of the runs of consecutive items of this stack for
+    /// which `same` holds of each item and the one after it, head first.
+    ///
+    /// As with `chunks`, the last run is shared with this stack rather than
+    /// copied.
+    pub fn group_by<F>(&self, mut same: F) -> CustomStackIn<CustomStackIn<T, P>, P>
+        where F: FnMut(&T, &T) -> bool
+    {
+        let mut groups = Vec::new();
+        let mut start = self;
+        while let CustomStackIn::Cons { .. } = *start {
+            let mut group = Vec::new();
+            let mut current = start;
+            while let CustomStackIn::Cons { ref value, ref tail } = *current {
+                group.push(value.clone());
+                current = tail;
+                match **tail {
+                    CustomStackIn::Cons { value: ref next, .. } if same(value, next) => {}
+                    _ => break,
+                }
+            }
+            if current.is_empty() {
+                groups.push(start.clone());
+            } else {
+                groups.push(CustomStackIn::from_items(group));
+            }
+            start = current;
+        }
+        CustomStackIn::from_items(groups)
+    }
+    /// Returns a stack of `items`, with the first of them at its head.
+    fn from_items(items: Vec<T>) -> Self {
+        items.into_iter().rev().fold(CustomStackIn::Empty, |tail, value| {
+            CustomStackIn::Cons {
+                value: value,
+                tail: Shared::new(tail),
+            }
+        })
+    }
+}
+
+impl<T: PartialEq, P: SharedPointer> CustomStackIn<T, P> {
+    /// Returns the longest suffix of this stack which is also a suffix of
+    /// `other`.
+    ///
+    /// Both stacks are walked a step at a time until one reaches a node the
+    /// other has already visited, which with all the nodes below it is
+    /// shared by the two stacks. The suffix is then extended over the items
+    /// above the shared nodes which are equal, such as the copies `cons`
+    /// makes of the stack it is called on. Where the stacks share a tail,
+    /// this takes time in proportion to the items above it rather than to
+    /// the length of the stacks; stacks which share no nodes are compared
+    /// item by item from the bottom.
+    pub fn common_suffix<'a>(&'a self, other: &'a CustomStackIn<T, P>) -> &'a CustomStackIn<T, P> {
+        let (mut path_here, mut path_there) = (Vec::new(), Vec::new());
+        let (mut seen_here, mut seen_there) = (HashMap::new(), HashMap::new());
+        let (mut here, mut there) = (Some(self), Some(other));
+        let mut meeting = None;
+        while meeting.is_none() && (here.is_some() || there.is_some()) {
+            if let Some(node) = here {
+                let address = node as *const CustomStackIn<T, P>;
+                seen_here.insert(address, path_here.len());
+                path_here.push(node);
+                here = node.tail_ref();
+                if let Some(&j) = seen_there.get(&address) {
+                    meeting = Some((path_here.len() - 1, j));
+                    continue;
+                }
+            }
+            if let Some(node) = there {
+                let address = node as *const CustomStackIn<T, P>;
+                seen_there.insert(address, path_there.len());
+                path_there.push(node);
+                there = node.tail_ref();
+                if let Some(&i) = seen_here.get(&address) {
+                    meeting = Some((i, path_there.len() - 1));
+                }
+            }
+        }
+        // Stacks which share no nodes meet at their empty ends.
+        let (mut i, mut j) = meeting.unwrap_or((path_here.len() - 1, path_there.len() - 1));
+        while i > 0 && j > 0 && path_here[i - 1].head_ref() == path_there[j - 1].head_ref() {
+            i -= 1;
+            j -= 1;
+        }
+        path_here[i]
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Sequence<T> for CustomStackIn<T, P> {}
+
+impl<T: Clone, P: SharedPointer> Cons<T> for CustomStackIn<T, P> {
+    fn cons(&self, value: T) -> Self {
+        CustomStackIn::cons(self, value)
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Uncons<T> for CustomStackIn<T, P> {
+    fn uncons(&self) -> Option<(T, Self)> {
+        match *self {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref value, ref tail } => Some((value.clone(), (**tail).clone())),
+        }
+    }
+}
+
+/// A builder which collects values for a `CustomStack` in a vector and links
+/// them together only once all of them are known.
+///
+/// Values are pushed in the order they should be read from the stack, so the
+/// first value pushed becomes the head. Freezing the builder allocates one
+/// node per value and clones none of them, where building the same stack by
+/// `cons` means pushing the values in reverse.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::stack::{Stack, StackBuilder};
+///
+/// let mut builder = StackBuilder::new();
+/// builder.push(1);
+/// builder.extend(vec![2, 3]);
+/// let stack = builder.freeze();
+///
+/// assert!(stack.head().unwrap() == 1);
+/// assert!(stack.get(2).unwrap() == 3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StackBuilderIn<T, P: SharedPointer> {
+    values: Vec<T>,
+    marker: PhantomData<P>,
+}
+
+/// A `StackBuilderIn` of the stacks exported at the root of the crate.
+pub type StackBuilder<T> = StackBuilderIn<T, DefaultPointer>;
+
+impl<T: Clone, P: SharedPointer> StackBuilderIn<T, P> {
+    /// Returns a builder with no values.
+    pub fn new() -> Self {
+        StackBuilderIn {
+            values: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+    /// Adds `value` beneath the values pushed so far.
+    pub fn push(&mut self, value: T) {
+        self.values.push(value);
+    }
+    /// Returns the number of values pushed so far.
+    pub fn size(&self) -> u32 {
+        self.values.len() as u32
+    }
+    /// Returns a stack of the values pushed, the first of them at its head.
+    pub fn freeze(self) -> CustomStackIn<T, P> {
+        self.values.into_iter().rev().fold(CustomStackIn::Empty, |tail, value| {
+            CustomStackIn::Cons {
+                value: value,
+                tail: Shared::new(tail),
+            }
+        })
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Default for StackBuilderIn<T, P> {
+    fn default() -> Self {
+        StackBuilderIn::new()
+    }
+}
+
+impl<T: Clone, P: SharedPointer> Extend<T> for StackBuilderIn<T, P> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        self.values.extend(values);
+    }
+}
+
+impl<T, P: SharedPointer> CustomStackIn<T, P> {
+    /// Tests whether this stack is empty. This is the same test as
+    /// `Stack::is_empty`, here so that calling it is not ambiguous where
+    /// `Collection` is in scope as well.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+    /// Returns an iterator over references to the items of this stack, head
+    /// first.
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter { current: self }
+    }
+    /// Returns an iterator over each run of `size` consecutive items of this
+    /// stack, head first.
+    ///
+    /// Each window is an iterator over the stack from where the window
+    /// starts, limited to `size` items, so nothing is copied and moving on
+    /// to the next window takes O(1) time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn windows(&self, size: usize) -> Windows<'_, T, P> {
+        assert!(size > 0, "windows must hold at least one item");
+        // The node `size` places after the start of the first window.
+        let mut ahead = Some(self);
+        for _ in 0..size {
+            ahead = ahead.and_then(|node| node.tail_ref());
+        }
+        Windows {
+            start: self,
+            ahead: ahead,
+            size: size,
+        }
+    }
+    /// Returns a stack of `f` of each item of this stack, in the same order.
+    /// `f` is called on the items from the head down.
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> CustomStackIn<U, P> {
+        let values: Vec<U> = self.iter().map(f).collect();
+        values.into_iter().rev().fold(CustomStackIn::Empty, |tail, value| {
+            CustomStackIn::Cons {
+                value: value,
+                tail: Shared::new(tail),
+            }
+        })
+    }
+    fn head_ref(&self) -> Option<&T> {
+        match *self {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref value, .. } => Some(value),
+        }
+    }
+    fn tail_ref(&self) -> Option<&CustomStackIn<T, P>> {
+        match *self {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref tail, .. } => Some(tail),
+        }
+    }
+    /// Moves the tail of this stack out, leaving an empty stack in its
+    /// place, if it is not empty and no other stack shares it.
+    fn take_unshared_tail(&mut self) -> Option<Self> {
+        if let CustomStackIn::Cons { ref mut tail, .. } = *self {
+            if let Some(tail) = Shared::get_mut(tail) {
+                if let CustomStackIn::Cons { .. } = *tail {
+                    return Some(mem::replace(tail, CustomStackIn::Empty));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<T, P: SharedPointer> Collection for CustomStackIn<T, P> {
+    type Iter<'a> = Iter<'a, T, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        // Counted without recursing, as `size` would once per item.
+        self.iter().count()
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<T, P: SharedPointer> MapElems<T> for &CustomStackIn<T, P> {
+    type Output<U: Clone> = CustomStackIn<U, P>;
+
+    fn map<U: Clone, F: Fn(&T) -> U>(self, f: F) -> CustomStackIn<U, P> {
+        CustomStackIn::map(self, f)
+    }
+}
+
+impl<T, P: SharedPointer> Traverse<T> for &CustomStackIn<T, P> {
+    fn try_map<U, E, F>(self, mut f: F) -> Result<CustomStackIn<U, P>, E>
+        where U: Clone,
+              F: FnMut(&T) -> Result<U, E>
+    {
+        let mapped = self.iter().map(&mut f).collect::<Result<Vec<U>, E>>()?;
+        let mut mapped = mapped.into_iter();
+        Ok(self.map(|_| mapped.next().unwrap()))
+    }
+}
+
+/// An iterator over the items of a `CustomStack`, returned by
+/// `CustomStack::iter`.
+#[derive(Debug, Clone)]
+pub struct Iter<'a, T: 'a, P: SharedPointer> {
+    current: &'a CustomStackIn<T, P>,
+}
+
+impl<'a, T, P: SharedPointer> Iterator for Iter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        match *self.current {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref value, ref tail } => {
+                self.current = tail;
+                Some(value)
+            }
+        }
+    }
+}
+
+/// An iterator over the runs of consecutive items of a `CustomStack`,
+/// returned by `CustomStack::windows`.
+#[derive(Debug, Clone)]
+pub struct Windows<'a, T: 'a, P: SharedPointer> {
+    start: &'a CustomStackIn<T, P>,
+    /// The node just after the window starting at `start`, or `None` if the
+    /// stack ends before that window is full.
+    ahead: Option<&'a CustomStackIn<T, P>>,
+    size: usize,
+}
+
+impl<'a, T, P: SharedPointer> Iterator for Windows<'a, T, P> {
+    type Item = Take<Iter<'a, T, P>>;
+
+    fn next(&mut self) -> Option<Take<Iter<'a, T, P>>> {
+        let ahead = self.ahead?;
+        let window = self.start.iter().take(self.size);
+        self.ahead = ahead.tail_ref();
+        if let Some(tail) = self.start.tail_ref() {
+            self.start = tail;
+        }
+        Some(window)
+    }
+}
+
+impl<T, P: SharedPointer> Drop for CustomStackIn<T, P> {
+    fn drop(&mut self) {
+        // Dropping each cell after taking its tail out keeps the drop of a
+        // long stack from recursing once per cell.
+        let mut rest = self.take_unshared_tail();
+        while let Some(mut stack) = rest {
+            rest = stack.take_unshared_tail();
+        }
+    }
+}
+
+impl<T, P: SharedPointer> MemoryUsage for CustomStackIn<T, P> {
+    fn census(&self, census: &mut Census) {
+        let mut rest = self;
+        while let CustomStackIn::Cons { ref tail, .. } = *rest {
+            if !census.node(&**tail, 0) {
+                break;
+            }
+            rest = tail;
+        }
+    }
 }
 
 // Only compile this in tests to stop compiler whining.
 #[cfg(test)]
-fn suffixes<T: Clone>(stack: &Arc<CustomStack<T>>) -> CustomStack<Arc<CustomStack<T>>> {
+fn suffixes<T: Clone>(stack: &Shared<CustomStack<T>, DefaultPointer>)
+                      -> CustomStack<Shared<CustomStack<T>, DefaultPointer>> {
     let tail_suffixes = match **stack {
         CustomStack::Empty => CustomStack::empty(),
         CustomStack::Cons { ref tail, .. } => suffixes(&tail),
@@ -235,7 +689,7 @@ fn update_invalid() {
 
 #[test]
 fn suffixes_empty() {
-    let stack: Arc<CustomStack<()>> = Arc::new(CustomStack::empty());
+    let stack: Shared<CustomStack<()>, DefaultPointer> = Shared::new(CustomStack::empty());
     let suffixes = suffixes(&stack);
 
     assert!(suffixes.size() == 1);
@@ -244,7 +698,8 @@ fn suffixes_empty() {
 
 #[test]
 fn suffixes_nonempty() {
-    let stack: Arc<CustomStack<i32>> = Arc::new(CustomStack::empty().cons(1).cons(2));
+    let stack: Shared<CustomStack<i32>, DefaultPointer> =
+        Shared::new(CustomStack::empty().cons(1).cons(2));
     let suffixes = suffixes(&stack);
 
     assert!(suffixes.size() == 3);
@@ -262,3 +717,118 @@ fn suffixes_nonempty() {
     assert!(suffix3.is_empty());
 
 }
+
+#[test]
+fn in_place_updates_leave_shared_versions_alone() {
+    let mut stack = CustomStack::empty().cons(3).cons(2).cons(1);
+    let before = stack.clone();
+    stack.cons_mut(0);
+    stack.update_mut(2, 20).unwrap();
+
+    assert!(stack.size() == 4 && stack.get(2).unwrap() == 20);
+    assert!(before.size() == 3 && before.get(1).unwrap() == 2);
+    assert!(stack.update_mut(4, 40).is_err());
+    assert!(stack.get(3).unwrap() == 3);
+}
+
+#[test]
+fn builders_freeze_in_push_order() {
+    let mut builder = StackBuilder::new();
+    assert!(builder.clone().freeze().is_empty());
+    builder.extend(0..100);
+    builder.push(100);
+    let stack = builder.freeze();
+
+    assert!(stack.size() == 101);
+    assert!((0..101).all(|i| stack.get(i).unwrap() == i));
+}
+
+#[test]
+fn long_stacks_are_dropped_without_overflow() {
+    let stack = (0..1000000).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+    let shared = stack.tail().unwrap();
+    drop(stack);
+    assert!(shared.head().unwrap() == 999998);
+    drop(shared);
+}
+
+#[test]
+fn optional_accessors_match_fallible_ones() {
+    let stack: CustomStack<i32> = CustomStack::empty().cons(1).cons(2).cons(3);
+    let empty: CustomStack<i32> = CustomStack::empty();
+
+    assert!(stack.head_opt() == Some(3) && empty.head_opt().is_none());
+    assert!(stack.tail_opt().and_then(|tail| tail.head_opt()) == Some(2));
+    assert!(empty.tail_opt().is_none());
+    assert!(stack.get_opt(2) == Some(1) && stack.get_opt(3).is_none());
+}
+
+#[test]
+fn map_keeps_the_order_of_items() {
+    let stack: CustomStack<i32> = (0..1000).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+    let mapped = stack.map(|value| value.to_string());
+
+    assert!(mapped.head().unwrap() == "999" && mapped.get(999).unwrap() == "0");
+    assert!(mapped.size() == 1000 && CustomStack::<i32>::empty().map(|&v| v).is_empty());
+    assert!(stack.head().unwrap() == 999);
+}
+
+#[test]
+fn common_suffix_is_the_shared_tail() {
+    let base: CustomStack<i32> = (0..5).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+    let left = base.cons(5).cons(6).cons(7);
+    let right = base.cons(8);
+    let suffix: Vec<i32> = left.common_suffix(&right).iter().cloned().collect();
+
+    assert!(suffix == vec![4, 3, 2, 1, 0]);
+    assert!(right.common_suffix(&left).iter().count() == 5);
+    assert!(left.common_suffix(&left).iter().count() == 8);
+    assert!(left.common_suffix(&base).iter().count() == 5);
+    assert!(base.cons(4).common_suffix(&right).iter().count() == 5);
+
+    let rebuilt: CustomStack<i32> = (0..5).fold(CustomStack::empty(), |stack, i| stack.cons(i));
+    assert!(rebuilt.cons(9).common_suffix(&left).iter().count() == 5);
+    assert!(rebuilt.cons(5).common_suffix(&CustomStack::empty()).is_empty());
+}
+
+#[test]
+fn chunks_and_groups_share_the_last_run() {
+    let items = [1, 1, 2, 3, 3, 3, 4];
+    let stack: CustomStack<i32> = items.iter().rev().fold(CustomStack::empty(), |s, &i| s.cons(i));
+    let runs = |stacks: CustomStack<CustomStack<i32>>| -> Vec<Vec<i32>> {
+        stacks.iter().map(|run| run.iter().cloned().collect()).collect()
+    };
+
+    assert!(runs(stack.chunks(3)) == vec![vec![1, 1, 2], vec![3, 3, 3], vec![4]]);
+    assert!(runs(stack.chunks(7)) == vec![vec![1, 1, 2, 3, 3, 3, 4]]);
+    assert!(runs(stack.chunks(2)).len() == 4);
+    assert!(runs(CustomStack::<i32>::empty().chunks(2)).is_empty());
+    let equal_runs = vec![vec![1, 1], vec![2], vec![3, 3, 3], vec![4]];
+    assert!(runs(stack.group_by(|a, b| a == b)) == equal_runs);
+    assert!(runs(stack.group_by(|a, b| a <= b)) == vec![vec![1, 1, 2, 3, 3, 3, 4]]);
+
+    // The last run shares its nodes below the first with the end of the stack.
+    let node = |stack: &CustomStack<i32>, depth: usize| -> *const CustomStack<i32> {
+        let found: &CustomStack<i32> = (0..depth).fold(stack, |node, _| node.tail_ref().unwrap());
+        found
+    };
+    let last_chunk = stack.chunks(3).iter().last().unwrap().clone();
+    let last_group = stack.group_by(|a, b| a < b).iter().last().unwrap().clone();
+    assert!(runs(stack.group_by(|a, b| a < b)).last() == Some(&vec![3, 4]));
+    assert!(node(&last_chunk, 1) == node(&stack, 7));
+    assert!(node(&last_group, 1) == node(&stack, 6));
+}
+
+#[test]
+fn windows_slide_one_item_at_a_time() {
+    let stack: CustomStack<i32> = (1..6).rev().fold(CustomStack::empty(), |s, i| s.cons(i));
+    let windows = |size: usize| -> Vec<Vec<i32>> {
+        stack.windows(size).map(|window| window.cloned().collect()).collect()
+    };
+
+    assert!(windows(2) == vec![vec![1, 2], vec![2, 3], vec![3, 4], vec![4, 5]]);
+    assert!(windows(5) == vec![vec![1, 2, 3, 4, 5]] && windows(6).is_empty());
+    let sums: Vec<i32> = stack.windows(3).map(|window| window.sum()).collect();
+    assert!(sums == vec![6, 9, 12]);
+    assert!(CustomStack::<i32>::empty().windows(1).next().is_none());
+}
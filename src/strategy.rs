@@ -0,0 +1,291 @@
+// Proptest strategies for the structures in this crate, available with the
+// `proptest` feature.
+//
+// Each strategy generates a vector of values and builds the structure from
+// it through the structure's own interface. Proptest shrinks the vector and
+// builds again, so every value a test sees, shrunk or not, is a structure
+// which could really be reached, with no invariant to restore by hand.
+
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use proptest::collection::{vec, SizeRange};
+use proptest::strategy::Strategy;
+
+use hamt::{HamtMap, HamtSet};
+use intmap::IntMap;
+use ord_seq::OrdSeqMap;
+use ptr::Shared;
+use seq::Seq;
+use set::{Map, Set, Tree};
+use sorted_list::SortedList;
+use stack::CustomStack;
+
+/// Returns a strategy for stacks of values from `element`, with a number of
+/// items in `size`.
+///
+/// Shrinking removes items and shrinks the ones that are left, keeping the
+/// rest in the same order.
+///
+/// # Examples
+///
+/// ```
+/// extern crate functional_datastructures;
+/// extern crate proptest;
+///
+/// use functional_datastructures::stack::Stack;
+/// use functional_datastructures::strategy::any_stack;
+/// use proptest::test_runner::TestRunner;
+///
+/// # fn main() {
+/// let mut runner = TestRunner::default();
+/// runner.run(&any_stack(0..10u32, 0..20), |stack| {
+///     assert!(stack.size() < 20);
+///     Ok(())
+/// }).unwrap();
+/// # }
+/// ```
+pub fn any_stack<S, R>(element: S, size: R) -> impl Strategy<Value = CustomStack<S::Value>>
+    where S: Strategy,
+          S::Value: Clone,
+          R: Into<SizeRange>
+{
+    vec(element, size).prop_map(|values| {
+        values.into_iter().rev().fold(CustomStack::Empty, |tail, value| {
+            CustomStack::Cons {
+                value: value,
+                tail: Shared::new(tail),
+            }
+        })
+    })
+}
+
+/// Returns a strategy for `Tree` maps binding keys from `key` to values from
+/// `value`, built by making a number of bindings in `size` in turn. The map
+/// may be smaller, since a key can be bound more than once.
+///
+/// Since the shape of a `Tree` depends on the order its keys were bound in,
+/// shrinking removes bindings from that order and binds the rest in the same
+/// order, so a shrunk tree keeps much of the shape of the original.
+///
+/// # Examples
+///
+/// ```
+/// extern crate functional_datastructures;
+/// extern crate proptest;
+///
+/// use functional_datastructures::set::Map;
+/// use functional_datastructures::strategy::any_tree_map;
+/// use proptest::prelude::*;
+/// use proptest::test_runner::{TestError, TestRunner};
+///
+/// # fn main() {
+/// let mut runner = TestRunner::default();
+/// let result = runner.run(&any_tree_map(0..100u32, any::<bool>(), 0..50), |tree| {
+///     prop_assert!(tree.lookup(42).is_none());
+///     Ok(())
+/// });
+///
+/// // The smallest failing tree binds nothing but the key the test looks for.
+/// match result {
+///     Err(TestError::Fail(_, tree)) => {
+///         assert!(tree.lookup(42).is_some());
+///         assert!((0..100).filter(|&key| tree.lookup(key).is_some()).count() == 1);
+///     }
+///     _ => panic!("expected the property to fail"),
+/// }
+/// # }
+/// ```
+pub fn any_tree_map<K, V, R>(key: K,
+                             value: V,
+                             size: R)
+                             -> impl Strategy<Value = Tree<K::Value, V::Value>>
+    where K: Strategy,
+          K::Value: Ord + Clone + Debug,
+          V: Strategy,
+          V::Value: Clone + Debug,
+          R: Into<SizeRange>
+{
+    vec((key, value), size).prop_map(|bindings| {
+        bindings.into_iter().fold(Tree::empty_map(), |tree, (key, value)| tree.bind(key, value))
+    })
+}
+
+/// Returns a strategy for `Tree` sets of values from `element`, built by
+/// making a number of insertions in `size` in turn. Shrinking works as for
+/// `any_tree_map`.
+pub fn any_tree_set<S, R>(element: S, size: R) -> impl Strategy<Value = Tree<S::Value, ()>>
+    where S: Strategy,
+          S::Value: Ord + Clone + Debug,
+          R: Into<SizeRange>
+{
+    vec(element, size).prop_map(|values| {
+        values.into_iter().fold(Tree::empty(), |tree, value| tree.insert(value))
+    })
+}
+
+/// Returns a strategy for `HamtMap`s binding keys from `key` to values from
+/// `value`, built by making a number of bindings in `size`. The map may be
+/// smaller, since a key can be bound more than once.
+pub fn any_hamt_map<K, V, R>(key: K,
+                             value: V,
+                             size: R)
+                             -> impl Strategy<Value = HamtMap<K::Value, V::Value>>
+    where K: Strategy,
+          K::Value: Hash + Eq + Clone,
+          V: Strategy,
+          V::Value: Clone,
+          R: Into<SizeRange>
+{
+    vec((key, value), size).prop_map(|bindings| {
+        bindings.into_iter().fold(HamtMap::new(), |map, (key, value)| map.insert(key, value))
+    })
+}
+
+/// Returns a strategy for `HamtSet`s of values from `element`, built by
+/// making a number of insertions in `size`.
+pub fn any_hamt_set<S, R>(element: S, size: R) -> impl Strategy<Value = HamtSet<S::Value>>
+    where S: Strategy,
+          S::Value: Hash + Eq + Clone,
+          R: Into<SizeRange>
+{
+    vec(element, size).prop_map(|values| {
+        values.into_iter().fold(HamtSet::new(), |set, value| set.insert(value))
+    })
+}
+
+/// Returns a strategy for `IntMap`s binding keys from `key` to values from
+/// `value`, built by making a number of bindings in `size`.
+pub fn any_int_map<K, V, R>(key: K, value: V, size: R) -> impl Strategy<Value = IntMap<V::Value>>
+    where K: Strategy<Value = u64>,
+          V: Strategy,
+          V::Value: Clone,
+          R: Into<SizeRange>
+{
+    vec((key, value), size).prop_map(|bindings| {
+        bindings.into_iter().fold(IntMap::empty_map(), |map, (key, value)| map.insert(key, value))
+    })
+}
+
+/// Returns a strategy for sequences of values from `element`, with a number
+/// of elements in `size`. Shrinking works as for `any_stack`.
+pub fn any_seq<S, R>(element: S, size: R) -> impl Strategy<Value = Seq<S::Value>>
+    where S: Strategy,
+          S::Value: Clone,
+          R: Into<SizeRange>
+{
+    vec(element, size).prop_map(|values| {
+        values.into_iter().fold(Seq::empty(), |seq, value| seq.push_back(value))
+    })
+}
+
+/// Returns a strategy for `OrdSeqMap`s binding keys from `key` to values
+/// from `value`, built by making a number of bindings in `size`.
+pub fn any_ord_seq_map<K, V, R>(key: K,
+                                value: V,
+                                size: R)
+                                -> impl Strategy<Value = OrdSeqMap<K::Value, V::Value>>
+    where K: Strategy,
+          K::Value: Ord + Clone,
+          V: Strategy,
+          V::Value: Clone,
+          R: Into<SizeRange>
+{
+    vec((key, value), size).prop_map(|bindings| {
+        bindings.into_iter()
+                .fold(OrdSeqMap::empty_map(), |map, (key, value)| map.insert(key, value))
+    })
+}
+
+/// Returns a strategy for sorted lists of values from `element`, with a
+/// number of elements in `size`.
+pub fn any_sorted_list<S, R>(element: S, size: R) -> impl Strategy<Value = SortedList<S::Value>>
+    where S: Strategy,
+          S::Value: Ord + Clone,
+          R: Into<SizeRange>
+{
+    vec(element, size).prop_map(|mut values| {
+        values.sort();
+        values.into_iter().rev().fold(SortedList::empty(), |list, value| list.insert(value))
+    })
+}
+
+#[cfg(test)]
+use proptest::prelude::*;
+#[cfg(test)]
+use proptest::test_runner::{Config, RngAlgorithm, TestError, TestRng, TestRunner};
+#[cfg(test)]
+use stack::Stack;
+
+/// Runs `test` against values from `strategy` and returns the smallest value
+/// it fails for, after shrinking.
+///
+/// The values are drawn from a fixed seed, since proptest does not promise
+/// that shrinking from different failures ends at the same value.
+#[cfg(test)]
+fn smallest_failure<S, F>(strategy: S, test: F) -> S::Value
+    where S: Strategy,
+          F: Fn(S::Value) -> Result<(), TestCaseError>
+{
+    let config = Config { failure_persistence: None, ..Config::default() };
+    let rng = TestRng::deterministic_rng(RngAlgorithm::ChaCha);
+    match TestRunner::new_with_rng(config, rng).run(&strategy, test) {
+        Err(TestError::Fail(_, value)) => value,
+        _ => panic!("expected the test to fail"),
+    }
+}
+
+#[test]
+fn generated_structures_respect_their_sizes() {
+    let mut runner = TestRunner::default();
+    runner.run(&any_stack(any::<u8>(), 5..10), |stack| {
+              prop_assert!(stack.size() >= 5 && stack.size() < 10);
+              Ok(())
+          })
+          .unwrap();
+    runner.run(&any_hamt_map(0..1000u32, any::<u8>(), 0..20), |map| {
+              prop_assert!(map.size() < 20);
+              Ok(())
+          })
+          .unwrap();
+    runner.run(&any_sorted_list(any::<i8>(), 0..30), |list| {
+              let values: Vec<_> = list.iter().cloned().collect();
+              prop_assert!(values.windows(2).all(|pair| pair[0] <= pair[1]));
+              Ok(())
+          })
+          .unwrap();
+}
+
+#[test]
+fn failures_shrink_to_the_smallest_structure() {
+    let stack = smallest_failure(any_stack(0..100u32, 0..50), |stack| {
+        prop_assert!(stack.size() < 3);
+        Ok(())
+    });
+    assert!(stack.size() == 3);
+    assert!((0..3).all(|i| stack.get(i).unwrap() == 0));
+
+    let tree = smallest_failure(any_tree_set(0..1000u32, 0..50), |tree| {
+        prop_assert!((500..1000).all(|value| !tree.member(value)));
+        Ok(())
+    });
+    assert!(tree.member(500));
+    assert!((0..1000).filter(|&value| tree.member(value)).count() == 1);
+
+    let seq = smallest_failure(any_seq(any::<u16>(), 0..50), |seq| {
+        prop_assert!(seq.iter().all(|value| *value < 1000));
+        Ok(())
+    });
+    assert!(seq.size() == 1 && *seq.front().unwrap() == 1000);
+
+    let map = smallest_failure(any_ord_seq_map(0..100u8, any::<u8>(), 0..50), |map| {
+        prop_assert!(map.size() < 2);
+        Ok(())
+    });
+    assert!(map.iter().map(|(key, _)| *key).collect::<Vec<_>>() == vec![0, 1]);
+    let ints = smallest_failure(any_int_map(0..100u64, any::<u8>(), 0..50), |map| {
+        prop_assert!(map.lookup(7).is_none());
+        Ok(())
+    });
+    assert!(ints.lookup(7) == Some(0));
+}
@@ -0,0 +1,570 @@
+use std::fmt;
+use std::mem;
+
+use collection::Collection;
+use sequence::{Cons, Sequence, Uncons};
+
+use lazy::SuspIn;
+#[cfg(feature = "amortization-debug")]
+use lazy::{Counters, Stats};
+use ptr::{DefaultPointer, SendFor, Shared, SharedPointer, SyncFor};
+use stack::StackError;
+
+/// A lazy, memoized list in which every cell is a suspension.
+///
+/// This is the `Stream` type described in Chapter 4 of PFDL. Each cell is
+/// only computed when it is first demanded and the result is shared between
+/// all copies of the stream, so a stream can be traversed repeatedly (or by
+/// several persistent versions of a structure) and only pays for each cell
+/// once.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::stream::Stream;
+///
+/// let front = Stream::empty().cons(2).cons(1);
+/// let back = Stream::empty().cons(4).cons(3);
+/// let all = front.append(&back);
+///
+/// assert!(all.head().unwrap() == 1);
+/// assert!(all.reverse().head().unwrap() == 4);
+/// assert!(all.size() == 4);
+/// ```
+pub struct StreamIn<T, P: SharedPointer> {
+    cell: SuspIn<StreamCellIn<T, P>, P>,
+    #[cfg(feature = "amortization-debug")]
+    counters: Counters,
+}
+
+/// A `StreamIn` whose cells are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type Stream<T> = StreamIn<T, DefaultPointer>;
+
+/// The result of forcing a `Stream`: either the end of the stream or a value
+/// followed by the (still suspended) rest of the stream.
+#[derive(Debug, Clone)]
+pub enum StreamCellIn<T, P: SharedPointer> {
+    Nil,
+    Cons(T, StreamIn<T, P>),
+}
+
+/// A `StreamCellIn` of the streams exported at the root of the crate.
+pub type StreamCell<T> = StreamCellIn<T, DefaultPointer>;
+
+impl<T: Clone + SendFor<P> + SyncFor<P> + 'static, P: SharedPointer> StreamIn<T, P> {
+    /// Returns an empty stream.
+    pub fn empty() -> Self {
+        StreamIn::from_susp(SuspIn::ready(StreamCellIn::Nil))
+    }
+    /// Returns a stream whose first cell will be computed by `thunk` when it
+    /// is first demanded.
+    pub fn lazy<F>(thunk: F) -> Self
+        where F: FnOnce() -> StreamCellIn<T, P> + SendFor<P> + 'static
+    {
+        // SAFETY: `thunk` is `SendFor<P>`.
+        unsafe { StreamIn::lazy_unchecked(thunk) }
+    }
+    /// Returns a stream as `lazy` does, where the compiler cannot see that
+    /// `thunk` is `SendFor<P>`.
+    ///
+    /// # Safety
+    ///
+    /// `thunk` must be `SendFor<P>`. See `SuspIn::new_unchecked`.
+    pub(crate) unsafe fn lazy_unchecked<F>(thunk: F) -> Self
+        where F: FnOnce() -> StreamCellIn<T, P> + 'static
+    {
+        unsafe { StreamIn::empty().suspend(thunk) }
+    }
+    /// Returns a stream of the items produced by `iter`.
+    ///
+    /// Items are pulled from the iterator only as cells of the stream are
+    /// forced, and each is pulled at most once: every copy of the stream sees
+    /// every item, however many times it is traversed.
+    pub fn from_iter<I>(iter: I) -> Self
+        where I: Iterator<Item = T> + SendFor<P> + 'static
+    {
+        StreamIn::empty().pull(iter)
+    }
+    /// Returns a new stream with `value` as its head.
+    ///
+    /// The new cell is already evaluated; the tail is shared with this stream.
+    pub fn cons(&self, value: T) -> Self {
+        self.derive(SuspIn::ready(StreamCellIn::Cons(value, self.clone())))
+    }
+    /// Forces the first cell of this stream, returning it.
+    pub fn force(&self) -> &StreamCellIn<T, P> {
+        self.cell.force()
+    }
+    /// Tests whether the first cell of this stream has already been forced.
+    pub fn is_forced(&self) -> bool {
+        self.cell.is_forced()
+    }
+    /// Tests whether this stream is empty. Forces the first cell.
+    pub fn is_empty(&self) -> bool {
+        match *self.force() {
+            StreamCellIn::Nil => true,
+            StreamCellIn::Cons(..) => false,
+        }
+    }
+    /// Returns the head item of the stream. Forces the first cell.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::NoSuchElementException` if this is an empty stream.
+    pub fn head(&self) -> Result<T, StackError> {
+        match *self.force() {
+            StreamCellIn::Nil => Err(StackError::NoSuchElementException),
+            StreamCellIn::Cons(ref value, _) => Ok(value.clone()),
+        }
+    }
+    /// Returns the tail of the stream (everything but the head). Forces only
+    /// the first cell.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::NoSuchElementException` if this is an empty stream.
+    pub fn tail(&self) -> Result<Self, StackError> {
+        match *self.force() {
+            StreamCellIn::Nil => Err(StackError::NoSuchElementException),
+            StreamCellIn::Cons(_, ref tail) => Ok(tail.clone()),
+        }
+    }
+    /// Returns the head item of the stream, or `None` if it is empty. Forces
+    /// the first cell.
+    pub fn head_opt(&self) -> Option<T> {
+        self.head().ok()
+    }
+    /// Returns the tail of the stream, or `None` if it is empty. Forces only
+    /// the first cell.
+    pub fn tail_opt(&self) -> Option<Self> {
+        self.tail().ok()
+    }
+    /// Returns the number of items in this stream. Forces the entire stream.
+    pub fn size(&self) -> u32 {
+        let mut size = 0;
+        let mut current = self.clone();
+        loop {
+            let next = match *current.force() {
+                StreamCellIn::Nil => return size,
+                StreamCellIn::Cons(_, ref tail) => tail.clone(),
+            };
+            size += 1;
+            current = next;
+        }
+    }
+    /// Returns a stream of this stream followed by `other`.
+    ///
+    /// This is an incremental function: nothing is forced until the result
+    /// is, and each cell of the result forces only the corresponding cell of
+    /// this stream, so every forced cell costs O(1).
+    pub fn append(&self, other: &Self) -> Self {
+        let front = self.clone();
+        let back = other.clone();
+        // SAFETY: the thunk only captures streams of `T`, which are
+        // `SendFor<P>` since `T` is.
+        unsafe {
+            self.suspend(move || {
+                match *front.force() {
+                    StreamCellIn::Nil => back.force().clone(),
+                    StreamCellIn::Cons(ref value, ref tail) => {
+                        StreamCellIn::Cons(value.clone(), tail.append(&back))
+                    }
+                }
+            })
+        }
+    }
+    /// Returns a stream of `f` of each item of this stream, in the same
+    /// order.
+    ///
+    /// This is an incremental function, like `append`: each cell of the
+    /// result calls `f` on the corresponding item of this stream only when
+    /// it is forced, so infinite streams can be mapped too.
+    pub fn map<U, F>(&self, f: F) -> StreamIn<U, P>
+        where U: Clone + SendFor<P> + SyncFor<P> + 'static,
+              F: Fn(&T) -> U + SendFor<P> + SyncFor<P> + 'static
+    {
+        self.map_shared(Shared::new(f))
+    }
+    /// Returns a stream containing the items of this stream in reverse order.
+    ///
+    /// This is a monolithic function: nothing is forced until the result is,
+    /// but forcing the first cell of the result forces the whole of this
+    /// stream and costs O(n). The cells of the result are all evaluated once
+    /// that has happened.
+    pub fn reverse(&self) -> Self {
+        let stream = self.clone();
+        // SAFETY: the thunk only captures a stream of `T`, which is
+        // `SendFor<P>` since `T` is.
+        unsafe {
+            self.suspend(move || {
+                let mut reversed = stream.derive(SuspIn::ready(StreamCellIn::Nil));
+                let mut current = stream.clone();
+                #[cfg(feature = "amortization-debug")]
+                let mut rotated = 0;
+                loop {
+                    let next = match *current.force() {
+                        StreamCellIn::Nil => break,
+                        StreamCellIn::Cons(ref value, ref tail) => {
+                            reversed = reversed.cons(value.clone());
+                            tail.clone()
+                        }
+                    };
+                    current = next;
+                    #[cfg(feature = "amortization-debug")]
+                    {
+                        rotated += 1;
+                    }
+                }
+                #[cfg(feature = "amortization-debug")]
+                stream.counters.record_rotation(rotated);
+                reversed.force().clone()
+            })
+        }
+    }
+    /// Returns an iterator over the items of this stream. Each cell is forced
+    /// as the iterator reaches it.
+    pub fn iter(&self) -> Iter<T, P> {
+        Iter { current: self.clone() }
+    }
+    /// Returns a snapshot of the amortization counters shared by this stream
+    /// and every stream derived from it.
+    ///
+    /// Only available with the `amortization-debug` feature.
+    #[cfg(feature = "amortization-debug")]
+    pub fn stats(&self) -> Stats {
+        self.counters.stats()
+    }
+    /// Returns a stream with the given first cell which shares this stream's
+    /// counters.
+    fn derive(&self, cell: SuspIn<StreamCellIn<T, P>, P>) -> Self {
+        StreamIn {
+            cell: cell,
+            #[cfg(feature = "amortization-debug")]
+            counters: self.counters.clone(),
+        }
+    }
+    /// Returns a stream whose first cell is computed by `thunk`, sharing this
+    /// stream's counters.
+    ///
+    /// # Safety
+    ///
+    /// `thunk` must be `SendFor<P>`. See `SuspIn::new_unchecked`.
+    unsafe fn suspend<F>(&self, thunk: F) -> Self
+        where F: FnOnce() -> StreamCellIn<T, P> + 'static
+    {
+        #[cfg(feature = "amortization-debug")]
+        let cell = unsafe { SuspIn::counted_unchecked(&self.counters, thunk) };
+        #[cfg(not(feature = "amortization-debug"))]
+        let cell = unsafe { SuspIn::new_unchecked(thunk) };
+        self.derive(cell)
+    }
+    /// Returns a stream pulling its cells from `iter`, sharing this stream's
+    /// counters.
+    fn pull<I>(&self, mut iter: I) -> Self
+        where I: Iterator<Item = T> + SendFor<P> + 'static
+    {
+        let stream = self.clone();
+        // SAFETY: the thunk only captures a stream of `T` and `iter`, which
+        // are `SendFor<P>`.
+        unsafe {
+            self.suspend(move || {
+                match iter.next() {
+                    None => StreamCellIn::Nil,
+                    Some(value) => StreamCellIn::Cons(value, stream.pull(iter)),
+                }
+            })
+        }
+    }
+    /// Returns the stream `map` returns, with `f` shared between the cells
+    /// of the result.
+    fn map_shared<U, F>(&self, f: Shared<F, P>) -> StreamIn<U, P>
+        where U: Clone + SendFor<P> + SyncFor<P> + 'static,
+              F: Fn(&T) -> U + SendFor<P> + SyncFor<P> + 'static
+    {
+        let stream = self.clone();
+        let thunk = move || {
+            match *stream.force() {
+                StreamCellIn::Nil => StreamCellIn::Nil,
+                StreamCellIn::Cons(ref value, ref tail) => {
+                    StreamCellIn::Cons(f(value), tail.map_shared(f.clone()))
+                }
+            }
+        };
+        // SAFETY: the thunk only captures a stream of `T` and a pointer to
+        // `f`, which are `SendFor<P>` since `T` and `F` are `SendFor<P>` and
+        // `SyncFor<P>`.
+        #[cfg(feature = "amortization-debug")]
+        let cell = unsafe { SuspIn::counted_unchecked(&self.counters, thunk) };
+        #[cfg(not(feature = "amortization-debug"))]
+        let cell = unsafe { SuspIn::new_unchecked(thunk) };
+        StreamIn {
+            cell: cell,
+            #[cfg(feature = "amortization-debug")]
+            counters: self.counters.clone(),
+        }
+    }
+    fn from_susp(cell: SuspIn<StreamCellIn<T, P>, P>) -> Self {
+        StreamIn {
+            cell: cell,
+            #[cfg(feature = "amortization-debug")]
+            counters: Counters::new(),
+        }
+    }
+}
+
+impl<T: Clone + SendFor<P> + SyncFor<P> + 'static, P: SharedPointer> Collection for StreamIn<T, P> {
+    type Iter<'a> = Iter<T, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        // Forces the entire stream.
+        self.size() as usize
+    }
+    fn iter(&self) -> Iter<T, P> {
+        self.iter()
+    }
+}
+
+impl<T: Clone + SendFor<P> + SyncFor<P> + 'static, P: SharedPointer> Default for StreamIn<T, P> {
+    fn default() -> Self {
+        StreamIn::empty()
+    }
+}
+
+impl<T, P> Sequence<T> for StreamIn<T, P>
+    where T: Clone + SendFor<P> + SyncFor<P> + 'static,
+          P: SharedPointer
+{
+}
+
+impl<T: Clone + SendFor<P> + SyncFor<P> + 'static, P: SharedPointer> Cons<T> for StreamIn<T, P> {
+    fn cons(&self, value: T) -> Self {
+        StreamIn::cons(self, value)
+    }
+}
+
+impl<T: Clone + SendFor<P> + SyncFor<P> + 'static, P: SharedPointer> Uncons<T> for StreamIn<T, P> {
+    fn uncons(&self) -> Option<(T, Self)> {
+        // Forces only the first cell.
+        match *self.force() {
+            StreamCellIn::Nil => None,
+            StreamCellIn::Cons(ref value, ref tail) => Some((value.clone(), tail.clone())),
+        }
+    }
+}
+
+/// An iterator over the items of a `Stream`, returned by `Stream::iter`.
+#[derive(Debug, Clone)]
+pub struct Iter<T, P: SharedPointer> {
+    current: StreamIn<T, P>,
+}
+
+impl<T: Clone + SendFor<P> + SyncFor<P> + 'static, P: SharedPointer> Iterator for Iter<T, P> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let (value, tail) = match *self.current.force() {
+            StreamCellIn::Nil => return None,
+            StreamCellIn::Cons(ref value, ref tail) => (value.clone(), tail.clone()),
+        };
+        self.current = tail;
+        Some(value)
+    }
+}
+
+impl<T, P: SharedPointer> Clone for StreamIn<T, P> {
+    fn clone(&self) -> Self {
+        StreamIn {
+            cell: self.cell.clone(),
+            #[cfg(feature = "amortization-debug")]
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+impl<T, P: SharedPointer> StreamIn<T, P> {
+    /// Moves the rest of this stream out, leaving the end of the stream in
+    /// its place, if its first cell has been forced and no other stream
+    /// shares it.
+    fn take_unshared_tail(&mut self) -> Option<Self> {
+        match self.cell.get_mut().map(|cell| mem::replace(cell, StreamCellIn::Nil)) {
+            Some(StreamCellIn::Cons(_, tail)) => Some(tail),
+            _ => None,
+        }
+    }
+}
+
+impl<T, P: SharedPointer> Drop for StreamIn<T, P> {
+    fn drop(&mut self) {
+        // As for `CustomStack`, each cell is emptied before it is dropped so
+        // that dropping a long forced stream does not recurse once per cell.
+        let mut rest = self.take_unshared_tail();
+        while let Some(mut stream) = rest {
+            rest = stream.take_unshared_tail();
+        }
+    }
+}
+
+impl<T: fmt::Debug, P: SharedPointer> fmt::Debug for StreamIn<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Stream({:?})", self.cell)
+    }
+}
+
+
+#[cfg(test)]
+fn counting_stream(counter: &::std::sync::Arc<::std::sync::atomic::AtomicUsize>,
+                   from: u32,
+                   to: u32)
+                   -> Stream<u32> {
+    use std::sync::atomic::Ordering;
+
+    let counter = counter.clone();
+    Stream::lazy(move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+        if from == to {
+            StreamCell::Nil
+        } else {
+            StreamCell::Cons(from, counting_stream(&counter, from + 1, to))
+        }
+    })
+}
+
+#[test]
+fn empty_stream_is_empty() {
+    let stream: Stream<()> = Stream::empty();
+
+    assert!(stream.is_empty());
+    assert!(stream.head().is_err());
+    assert!(stream.tail().is_err());
+    assert!(stream.head_opt().is_none() && stream.tail_opt().is_none());
+}
+
+#[test]
+fn cons_then_head_and_tail() {
+    let stream = Stream::empty().cons(1).cons(2);
+
+    assert!(stream.head().unwrap() == 2);
+    assert!(stream.tail().unwrap().head().unwrap() == 1);
+    assert!(stream.tail_opt().and_then(|tail| tail.head_opt()) == Some(1));
+    assert!(stream.size() == 2);
+}
+
+#[test]
+fn append_contains_both_streams_in_order() {
+    let front = Stream::empty().cons(2).cons(1);
+    let back = Stream::empty().cons(4).cons(3);
+    let all = front.append(&back);
+
+    assert!(all.size() == 4);
+    assert!(all.head().unwrap() == 1);
+    assert!(all.tail().unwrap().tail().unwrap().head().unwrap() == 3);
+}
+
+#[test]
+fn append_is_incremental() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let counter = Arc::new(AtomicUsize::new(0));
+    let all = counting_stream(&counter, 0, 100).append(&Stream::empty());
+
+    assert!(counter.load(Ordering::SeqCst) == 0);
+    assert!(all.tail().unwrap().head().unwrap() == 1);
+    assert!(counter.load(Ordering::SeqCst) == 2);
+}
+
+#[test]
+fn reverse_is_monolithic() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let counter = Arc::new(AtomicUsize::new(0));
+    let reversed = counting_stream(&counter, 0, 100).reverse();
+
+    assert!(counter.load(Ordering::SeqCst) == 0);
+    assert!(reversed.head().unwrap() == 99);
+    assert!(counter.load(Ordering::SeqCst) == 101);
+    assert!(reversed.tail().unwrap().is_forced());
+}
+
+#[test]
+fn reverse_of_empty_is_empty() {
+    let stream: Stream<u32> = Stream::empty();
+
+    assert!(stream.reverse().is_empty());
+}
+
+#[test]
+fn from_iter_is_lazy_and_pulls_once() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pulled = Arc::new(AtomicUsize::new(0));
+    let pulled_in_iter = pulled.clone();
+    let stream = Stream::from_iter((0..5).inspect(move |_| {
+        pulled_in_iter.fetch_add(1, Ordering::SeqCst);
+    }));
+    let shared = stream.clone();
+
+    assert!(pulled.load(Ordering::SeqCst) == 0);
+    assert!(stream.head().unwrap() == 0);
+    assert!(pulled.load(Ordering::SeqCst) == 1);
+
+    assert!(stream.iter().collect::<Vec<_>>() == vec![0, 1, 2, 3, 4]);
+    assert!(shared.iter().collect::<Vec<_>>() == vec![0, 1, 2, 3, 4]);
+    assert!(pulled.load(Ordering::SeqCst) == 5);
+}
+
+#[test]
+fn iter_of_empty_is_empty() {
+    let stream: Stream<u32> = Stream::from_iter(None.into_iter());
+
+    assert!(stream.iter().next().is_none());
+}
+
+#[cfg(feature = "amortization-debug")]
+#[test]
+fn stats_count_suspensions_and_rotations() {
+    let stream = Stream::empty().cons(3).cons(2).cons(1);
+    let reversed = stream.reverse();
+    let appended = stream.append(&reversed);
+
+    assert!(appended.stats().suspensions_created == 2);
+    assert!(appended.stats().rotations == 0);
+
+    assert!(appended.size() == 6);
+
+    let stats = stream.stats();
+    assert!(stats.suspensions_created == 5);
+    assert!(stats.suspensions_forced == 5);
+    assert!(stats.rotations == 1);
+    assert!(stats.largest_rotation == 3);
+}
+
+#[test]
+fn long_forced_streams_are_dropped_without_overflow() {
+    let stream = Stream::from_iter(0..1000000);
+    assert!(stream.iter().count() == 1000000);
+    drop(stream);
+
+    let built = (0..1000000).fold(Stream::empty(), |stream, i| stream.cons(i));
+    drop(built);
+}
+
+#[test]
+fn map_is_incremental() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let counter = Arc::new(AtomicUsize::new(0));
+    let doubled = counting_stream(&counter, 0, 100).map(|value| value * 2);
+
+    assert!(counter.load(Ordering::SeqCst) == 0);
+    assert!(doubled.tail().unwrap().head().unwrap() == 2);
+    assert!(counter.load(Ordering::SeqCst) == 2);
+    assert!(doubled.iter().collect::<Vec<_>>() == (0..100).map(|i| i * 2).collect::<Vec<_>>());
+
+    let naturals = Stream::from_iter(0u64..);
+    assert!(naturals.map(|value| value.to_string()).iter().nth(1000) == Some("1000".to_string()));
+}
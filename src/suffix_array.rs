@@ -0,0 +1,190 @@
+use std::cmp::{self, Ordering};
+use std::mem;
+
+use ptr::{DefaultPointer, Shared, SharedPointer};
+use rope::RopeIn;
+
+/// An immutable index of every place each substring of a text occurs,
+/// implemented as a suffix array: the starting indexes of the suffixes of
+/// the text, sorted into order.
+///
+/// The suffixes which begin with a pattern are next to each other in the
+/// array, so `contains_substring` takes O(m log n) time to find them for a
+/// pattern of m characters, and `find_all` takes O(k log k) time more to
+/// report the k places they start. Text is addressed by character index,
+/// as in a `Rope`.
+///
+/// An array keeps the rope it was built from, so an index of a snapshot of
+/// a document stays valid however the document is edited afterwards.
+/// Building an array takes O(n log^2 n) time; to search an edited rope,
+/// build a new array from it. Copying an array takes O(1) time.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::rope::Rope;
+/// use functional_datastructures::suffix_array::SuffixArray;
+///
+/// let text = Rope::from("how much wood would a woodchuck chuck");
+/// let index = SuffixArray::new(&text);
+///
+/// assert!(index.contains_substring("chuck"));
+/// assert!(!index.contains_substring("could"));
+/// assert!(index.find_all("wood") == vec![9, 22]);
+/// assert!(index.find_all("ould") == vec![15]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SuffixArrayIn<P: SharedPointer> {
+    text: RopeIn<P>,
+    index: Shared<Index, P>,
+}
+
+/// A `SuffixArrayIn` whose nodes are shared through `DefaultPointer`, as
+/// exported at the root of the crate.
+pub type SuffixArray = SuffixArrayIn<DefaultPointer>;
+
+/// The characters of the text, and the starting indexes of its suffixes in
+/// sorted order.
+#[derive(Debug)]
+struct Index {
+    chars: Vec<char>,
+    suffixes: Vec<usize>,
+}
+
+impl<P: SharedPointer> SuffixArrayIn<P> {
+    /// Returns an index of the substrings of `text`.
+    pub fn new(text: &RopeIn<P>) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let suffixes = sorted_suffixes(&chars);
+        SuffixArrayIn {
+            text: text.clone(),
+            index: Shared::new(Index { chars: chars, suffixes: suffixes }),
+        }
+    }
+    /// Returns the text this array indexes.
+    pub fn text(&self) -> &RopeIn<P> {
+        &self.text
+    }
+    /// Returns the number of characters in the text this array indexes.
+    pub fn len_chars(&self) -> usize {
+        self.index.chars.len()
+    }
+    /// Tests whether `pattern` occurs in the text. The empty pattern occurs
+    /// in every text.
+    pub fn contains_substring(&self, pattern: &str) -> bool {
+        pattern.is_empty() || !self.matches(pattern).is_empty()
+    }
+    /// Returns the character indexes at which `pattern` occurs in the text,
+    /// in ascending order. Occurrences may overlap. The empty pattern occurs
+    /// at every index, including the end of the text.
+    pub fn find_all(&self, pattern: &str) -> Vec<usize> {
+        if pattern.is_empty() {
+            return (0..self.len_chars() + 1).collect();
+        }
+        let mut starts = self.matches(pattern).to_vec();
+        starts.sort();
+        starts
+    }
+    /// Returns the run of sorted suffixes which begin with `pattern`.
+    fn matches(&self, pattern: &str) -> &[usize] {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let index = &*self.index;
+        let compare = |start: usize| {
+            let suffix = &index.chars[start..];
+            suffix[..cmp::min(suffix.len(), pattern.len())].cmp(&pattern[..])
+        };
+        let first = index.suffixes.partition_point(|&start| compare(start) == Ordering::Less);
+        let last = index.suffixes.partition_point(|&start| compare(start) != Ordering::Greater);
+        &index.suffixes[first..last]
+    }
+}
+
+impl<'a, P: SharedPointer> From<&'a str> for SuffixArrayIn<P> {
+    fn from(text: &'a str) -> Self {
+        SuffixArrayIn::new(&RopeIn::from(text))
+    }
+}
+
+/// Returns the starting indexes of the suffixes of `chars` in sorted order.
+///
+/// This is prefix doubling: once the suffixes are sorted by their first `k`
+/// characters, sorting them by the pair of ranks of their first `k` and
+/// next `k` characters sorts them by their first `2k`.
+fn sorted_suffixes(chars: &[char]) -> Vec<usize> {
+    let n = chars.len();
+    let mut suffixes: Vec<usize> = (0..n).collect();
+    if n < 2 {
+        return suffixes;
+    }
+    let mut rank: Vec<usize> = chars.iter().map(|&c| c as usize).collect();
+    let mut next = vec![0; n];
+    let mut k = 1;
+    loop {
+        {
+            // A suffix too short to have a second half sorts before the
+            // others with the same first half.
+            let key = |i: usize| (rank[i], rank.get(i + k).map_or(0, |r| r + 1));
+            suffixes.sort_by_key(|&i| key(i));
+            next[suffixes[0]] = 0;
+            for w in 1..n {
+                let step = if key(suffixes[w - 1]) < key(suffixes[w]) { 1 } else { 0 };
+                next[suffixes[w]] = next[suffixes[w - 1]] + step;
+            }
+        }
+        mem::swap(&mut rank, &mut next);
+        if rank[suffixes[n - 1]] == n - 1 {
+            break;
+        }
+        k *= 2;
+    }
+    suffixes
+}
+
+#[cfg(test)]
+use rope::Rope;
+
+#[cfg(test)]
+fn naive_find_all(text: &str, pattern: &str) -> Vec<usize> {
+    let chars: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    (0..chars.len() + 1).filter(|&i| chars[i..].starts_with(&pattern)).collect()
+}
+
+#[test]
+fn suffixes_are_sorted() {
+    for text in &["", "a", "banana", "mississippi", "aaaaaaaa", "abcabcabcab", "¡olé, olé!"] {
+        let chars: Vec<char> = text.chars().collect();
+        let suffixes = sorted_suffixes(&chars);
+
+        assert!(suffixes.len() == chars.len());
+        assert!(suffixes.windows(2).all(|pair| chars[pair[0]..] < chars[pair[1]..]));
+    }
+}
+
+#[test]
+fn find_all_agrees_with_a_scan_of_the_text() {
+    let text: String = (0..2000u32).map(|i| (b'a' + ((i * i + i / 7) % 4) as u8) as char).collect();
+    let index = SuffixArray::new(&Rope::from(&text[..]));
+
+    for pattern in &["a", "ab", "abc", "dcba", "aaaa", "cdcd", "bbbbbbbbbbbb", &text[100..140]] {
+        let expected = naive_find_all(&text, pattern);
+        assert!(index.find_all(pattern) == expected);
+        assert!(index.contains_substring(pattern) != expected.is_empty());
+    }
+    assert!(index.find_all(&text) == vec![0]);
+    assert!(index.find_all("") == (0..2001).collect::<Vec<_>>());
+}
+
+#[test]
+fn index_of_a_snapshot_is_unchanged_by_edits() {
+    let text = Rope::from("peter piper picked a peck of pickled peppers");
+    let index = SuffixArray::new(&text);
+    let edited = text.remove(0..12).insert(0, "paul ");
+    let edited_index = SuffixArray::new(&edited);
+
+    assert!(index.find_all("pi") == vec![6, 12, 29]);
+    assert!(edited_index.find_all("pi") == vec![5, 22]);
+    assert!(index.text().to_string() == "peter piper picked a peck of pickled peppers");
+    assert!(!SuffixArray::from("").contains_substring("a"));
+    assert!(SuffixArray::from("").find_all("") == vec![0]);
+}
@@ -0,0 +1,699 @@
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::iter;
+use std::marker::PhantomData;
+use std::mem;
+
+use collection::Collection;
+use memory::{Census, MemoryUsage};
+
+use hamt::{self, HamtMapIn};
+use ptr::{DefaultPointer, Shared, SharedPointer};
+use set::{self, Map, TreeIn};
+
+/// An immutable map keyed by byte strings, implemented as a trie.
+///
+/// Each node of the trie corresponds to a prefix of the keys below it and
+/// holds its children in byte order, so a lookup takes time proportional to
+/// the length of the key rather than the size of the map, and all the keys
+/// which start with a given prefix can be found by walking down to that
+/// prefix's node. Keys may be given as anything which can be viewed as bytes,
+/// such as `&str`, `String` or `&[u8]`. Iteration visits keys in
+/// lexicographic byte order.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::trie::TrieMap;
+///
+/// let words = TrieMap::empty().insert("car", 1).insert("cart", 2).insert("dog", 3);
+///
+/// assert!(words.lookup("cart").unwrap() == 2);
+/// assert!(words.lookup("ca").is_none());
+/// assert!(words.remove("car").lookup("car").is_none());
+/// assert!(words.keys_with_prefix("car") == vec![b"car".to_vec(), b"cart".to_vec()]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TrieMapIn<V, P: SharedPointer> {
+    size: u32,
+    root: Child<V, P>,
+}
+
+/// A `TrieMapIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type TrieMap<V> = TrieMapIn<V, DefaultPointer>;
+
+#[derive(Debug)]
+struct Node<V, P: SharedPointer> {
+    /// The value bound to the prefix this node corresponds to, if any.
+    value: Option<V>,
+    /// The children of this node, sorted by the byte leading to each.
+    children: Vec<(u8, Child<V, P>)>,
+}
+
+/// A child of a node, shared with the other versions of the map that hold it.
+type Child<V, P> = Shared<Node<V, P>, P>;
+
+
+impl<V: Clone, P: SharedPointer> TrieMapIn<V, P> {
+    /// Returns a map containing nothing.
+    pub fn empty() -> Self {
+        TrieMapIn {
+            size: 0,
+            root: Shared::new(Node::empty()),
+        }
+    }
+    /// Returns a copy of this map with `key` bound to `value`, replacing any
+    /// existing binding for `key`.
+    pub fn insert<K: AsRef<[u8]>>(&self, key: K, value: V) -> Self {
+        let (root, added) = self.root.insert(key.as_ref(), value);
+        TrieMapIn {
+            size: if added { self.size + 1 } else { self.size },
+            root: Shared::new(root),
+        }
+    }
+    /// Returns a copy of this map without any binding for `key`.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Self {
+        match self.root.remove(key.as_ref()) {
+            None => self.clone(),
+            Some(root) => {
+                TrieMapIn {
+                    size: self.size - 1,
+                    root: Shared::new(root),
+                }
+            }
+        }
+    }
+    /// Returns a `Some` of the value bound to `key`, or `None` if `key` is
+    /// not a key in this map.
+    pub fn lookup<K: AsRef<[u8]>>(&self, key: K) -> Option<V> {
+        self.get(key).cloned()
+    }
+    /// Returns a reference to the value bound to `key`, or `None` if `key` is
+    /// not a key in this map.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<&V> {
+        self.root.find(key.as_ref()).and_then(|node| node.value.as_ref())
+    }
+    /// Tests whether `key` is bound in this map.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> bool {
+        self.get(key).is_some()
+    }
+    /// Tests whether this map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+    /// Returns the number of keys bound in this map.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+    /// Returns an iterator over the bindings in this map, in lexicographic
+    /// order of key.
+    pub fn iter(&self) -> Iter<'_, V, P> {
+        self.iter_prefix([])
+    }
+    /// Returns an iterator over the bindings in this map whose keys start
+    /// with `prefix`, in lexicographic order of key. Finding the first
+    /// binding takes time proportional to the length of `prefix`.
+    pub fn iter_prefix<K: AsRef<[u8]>>(&self, prefix: K) -> Iter<'_, V, P> {
+        let prefix = prefix.as_ref();
+        Iter {
+            pending: match self.root.find(prefix) {
+                None => Vec::new(),
+                Some(node) => vec![(prefix.to_vec(), node)],
+            },
+        }
+    }
+    /// Returns the keys in this map which start with `prefix`, in
+    /// lexicographic order.
+    pub fn keys_with_prefix<K: AsRef<[u8]>>(&self, prefix: K) -> Vec<Vec<u8>> {
+        self.iter_prefix(prefix).map(|(key, _)| key).collect()
+    }
+    /// Returns the keys in this map which are within Levenshtein distance
+    /// `distance` of `query`, counting in bytes, in lexicographic order.
+    ///
+    /// The trie is walked carrying a row of the edit distance table for the
+    /// prefix at each node, so prefixes shared by many keys are compared
+    /// with `query` once, and a branch is given up as soon as every entry of
+    /// its row is more than `distance`.
+    pub fn search_within_distance<K: AsRef<[u8]>>(&self, query: K, distance: u32) -> Vec<Vec<u8>> {
+        let query = query.as_ref();
+        let first_row: Vec<u32> = (0..query.len() as u32 + 1).collect();
+        let mut pending = vec![(Vec::new(), &*self.root, first_row)];
+        let mut found = Vec::new();
+        while let Some((key, node, row)) = pending.pop() {
+            if node.value.is_some() && row[query.len()] <= distance {
+                found.push(key.clone());
+            }
+            for &(byte, ref child) in node.children.iter().rev() {
+                let mut child_row = vec![row[0] + 1];
+                for (i, &query_byte) in query.iter().enumerate() {
+                    let substitution = if query_byte == byte { row[i] } else { row[i] + 1 };
+                    let cost = substitution.min(row[i + 1] + 1).min(child_row[i] + 1);
+                    child_row.push(cost);
+                }
+                if child_row.iter().any(|&cost| cost <= distance) {
+                    let mut child_key = key.clone();
+                    child_key.push(byte);
+                    pending.push((child_key, child, child_row));
+                }
+            }
+        }
+        found
+    }
+}
+
+impl<V: Clone, P: SharedPointer> Collection for TrieMapIn<V, P> {
+    type Iter<'a> = Iter<'a, V, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        self.size() as usize
+    }
+    fn iter<'a>(&'a self) -> Iter<'a, V, P> {
+        self.iter()
+    }
+}
+
+impl<V, P: SharedPointer> MemoryUsage for TrieMapIn<V, P> {
+    fn census(&self, census: &mut Census) {
+        let mut stack = vec![&self.root];
+        while let Some(node) = stack.pop() {
+            let heap_bytes = node.children.capacity() * mem::size_of::<(u8, Child<V, P>)>();
+            if census.node(&**node, heap_bytes) {
+                stack.extend(node.children.iter().map(|(_, child)| child));
+            }
+        }
+    }
+}
+
+impl<V: Clone, P: SharedPointer> Node<V, P> {
+    fn empty() -> Self {
+        Node {
+            value: None,
+            children: Vec::new(),
+        }
+    }
+    /// Returns the node for `key` below this one, if there is one.
+    fn find(&self, key: &[u8]) -> Option<&Self> {
+        let mut current = self;
+        for byte in key {
+            current = match current.child(*byte) {
+                Ok(index) => &current.children[index].1,
+                Err(_) => return None,
+            };
+        }
+        Some(current)
+    }
+    /// Returns the position of the child for `byte`, or the position it
+    /// would be inserted at if there is none.
+    fn child(&self, byte: u8) -> Result<usize, usize> {
+        self.children.binary_search_by(|child| child.0.cmp(&byte))
+    }
+    /// Returns a copy of this node with `key` bound to `value`, and whether
+    /// `key` was not already bound in it.
+    fn insert(&self, key: &[u8], value: V) -> (Self, bool) {
+        let (byte, rest) = match key.split_first() {
+            None => {
+                return (Node {
+                    value: Some(value),
+                    children: self.children.clone(),
+                },
+                        self.value.is_none());
+            }
+            Some((byte, rest)) => (*byte, rest),
+        };
+        let mut children = self.children.clone();
+        let added = match self.child(byte) {
+            Ok(index) => {
+                let (child, added) = children[index].1.insert(rest, value);
+                children[index].1 = Shared::new(child);
+                added
+            }
+            Err(index) => {
+                let (child, _) = Node::empty().insert(rest, value);
+                children.insert(index, (byte, Shared::new(child)));
+                true
+            }
+        };
+        (Node {
+            value: self.value.clone(),
+            children: children,
+        },
+         added)
+    }
+    /// Returns a copy of this node without any binding for `key`, or `None`
+    /// if `key` is not bound in it. Nodes left with neither a value nor any
+    /// children are pruned.
+    fn remove(&self, key: &[u8]) -> Option<Self> {
+        let (byte, rest) = match key.split_first() {
+            None => {
+                return self.value.as_ref().map(|_| {
+                    Node {
+                        value: None,
+                        children: self.children.clone(),
+                    }
+                });
+            }
+            Some((byte, rest)) => (*byte, rest),
+        };
+        let index = match self.child(byte) {
+            Ok(index) => index,
+            Err(_) => return None,
+        };
+        self.children[index].1.remove(rest).map(|child| {
+            let mut children = self.children.clone();
+            if child.value.is_none() && child.children.is_empty() {
+                children.remove(index);
+            } else {
+                children[index].1 = Shared::new(child);
+            }
+            Node {
+                value: self.value.clone(),
+                children: children,
+            }
+        })
+    }
+}
+
+/// An iterator over the bindings of a `TrieMap`, returned by `TrieMap::iter`
+/// and `TrieMap::iter_prefix`.
+pub struct Iter<'a, V: 'a, P: SharedPointer> {
+    /// The nodes still to be visited and their keys, the next one last.
+    pending: Vec<(Vec<u8>, &'a Node<V, P>)>,
+}
+
+impl<'a, V, P: SharedPointer> Iterator for Iter<'a, V, P> {
+    type Item = (Vec<u8>, &'a V);
+
+    fn next(&mut self) -> Option<(Vec<u8>, &'a V)> {
+        while let Some((key, node)) = self.pending.pop() {
+            for &(byte, ref child) in node.children.iter().rev() {
+                let mut child_key = key.clone();
+                child_key.push(byte);
+                self.pending.push((child_key, child));
+            }
+            if let Some(ref value) = node.value {
+                return Some((key, value));
+            }
+        }
+        None
+    }
+}
+
+/// A family of map types with keys of type `K`: one map type for each type of
+/// value.
+///
+/// This stands in for the ML functor argument in Chapter 10 of PFDL, so that
+/// a `GenericTrie` can be built on whichever map suits its element type.
+pub trait MapFamily<K: Eq> {
+    /// The map from `K` to values of type `V` in this family.
+    type Map<V: Clone + Debug>: Map<K, V> + Clone + Debug;
+    /// The iterator over the values of a map in this family, returned by
+    /// `values`.
+    type Values<'a, V: Clone + Debug + 'a>: Iterator<Item = &'a V> where Self: 'a, K: 'a;
+
+    /// Returns an iterator over the values of `map`, in the map's own order.
+    fn values<'a, V: Clone + Debug>(map: &'a Self::Map<V>) -> Self::Values<'a, V>;
+}
+
+/// Returns the value of a binding, for iterators over the values of maps.
+fn value_of<'a, K, V>(binding: (&'a K, &'a V)) -> &'a V {
+    binding.1
+}
+
+/// The family of `TreeIn` maps shared through `P`, for keys which are
+/// ordered.
+#[derive(Debug, Clone, Copy)]
+pub struct TreeMapsIn<P: SharedPointer> {
+    marker: PhantomData<P>,
+}
+
+/// The family of the `Tree` maps exported at the root of the crate.
+pub type TreeMaps = TreeMapsIn<DefaultPointer>;
+
+impl<K: Ord + Clone + Debug, P: SharedPointer> MapFamily<K> for TreeMapsIn<P> {
+    type Map<V: Clone + Debug> = TreeIn<K, V, P>;
+    type Values<'a, V: Clone + Debug + 'a> = iter::Map<set::Iter<'a, K, V, P>, ValueOf<'a, K, V>>
+        where K: 'a;
+
+    fn values<'a, V: Clone + Debug>(map: &'a TreeIn<K, V, P>) -> Self::Values<'a, V> {
+        map.iter().map(value_of)
+    }
+}
+
+/// The function `value_of`, as the type of the iterators over the values of
+/// the maps in a family.
+type ValueOf<'a, K, V> = fn((&'a K, &'a V)) -> &'a V;
+
+/// The family of `HamtMapIn` maps shared through `P`, for keys which are
+/// hashable.
+#[derive(Debug, Clone, Copy)]
+pub struct HamtMapsIn<P: SharedPointer> {
+    marker: PhantomData<P>,
+}
+
+/// The family of the `HamtMap` maps exported at the root of the crate.
+pub type HamtMaps = HamtMapsIn<DefaultPointer>;
+
+impl<K: Hash + Eq + Clone + Debug, P: SharedPointer> MapFamily<K> for HamtMapsIn<P> {
+    type Map<V: Clone + Debug> = HamtMapIn<K, V, P>;
+    type Values<'a, V: Clone + Debug + 'a> = iter::Map<hamt::Iter<'a, K, V, P>, ValueOf<'a, K, V>>
+        where K: 'a;
+
+    fn values<'a, V: Clone + Debug>(map: &'a HamtMapIn<K, V, P>) -> Self::Values<'a, V> {
+        map.iter().map(value_of)
+    }
+}
+
+/// The family of `GenericTrieIn` maps shared through `P` and built on the
+/// maps in `F`, whose keys are sequences of keys of `F`.
+pub struct TrieMapsIn<F, P: SharedPointer> {
+    family: PhantomData<F>,
+    marker: PhantomData<P>,
+}
+
+/// The family of the `GenericTrie` maps exported at the root of the crate.
+pub type TrieMaps<F> = TrieMapsIn<F, DefaultPointer>;
+
+impl<K, F, P> MapFamily<Vec<K>> for TrieMapsIn<F, P>
+    where K: Eq + Clone,
+          F: MapFamily<K>,
+          P: SharedPointer
+{
+    type Map<V: Clone + Debug> = GenericTrieIn<K, V, F, P>;
+    type Values<'a, V: Clone + Debug + 'a> = Values<'a, K, V, F, P>
+        where Self: 'a, K: 'a;
+
+    fn values<'a, V: Clone + Debug>(map: &'a GenericTrieIn<K, V, F, P>) -> Values<'a, K, V, F, P> {
+        map.values()
+    }
+}
+
+/// An immutable map keyed by sequences of elements, built from a map on the
+/// elements themselves.
+///
+/// Each node holds the value for the sequence leading to it, if there is
+/// one, and a map from elements to the nodes for the sequences continuing
+/// with them. The element map comes from the family `F`, so no requirement is
+/// placed on the keys beyond what that map needs: a trie over `TreeMaps`
+/// needs neither hashing nor more than an ordering on elements. Since a trie
+/// is itself a map, tries can be nested through `TrieMaps` to key a map by
+/// sequences of sequences.
+///
+/// This is the generalized `Trie` functor described in Chapter 10 of PFDL, an
+/// example of data-structural bootstrapping.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::set::Map;
+/// use functional_datastructures::trie::{GenericTrie, TreeMaps};
+///
+/// let empty: GenericTrie<&str, u32, TreeMaps> = GenericTrie::empty_map();
+/// let paths = empty.bind(vec!["usr", "bin"], 1).bind(vec!["usr", "lib"], 2);
+///
+/// assert!(paths.lookup(vec!["usr", "bin"]).unwrap() == 1);
+/// assert!(paths.lookup(vec!["usr", "lib"]).unwrap() == 2);
+/// assert!(paths.lookup(vec!["usr"]).is_none());
+/// ```
+pub struct GenericTrieIn<K: Eq, V: Clone + Debug, F: MapFamily<K>, P: SharedPointer> {
+    // `children` must not be the last field: the compiler decides whether a
+    // struct is sized from its last field, and looking inside the family's
+    // map type to do so would need the answer already.
+    children: F::Map<Subtrie<K, V, F, P>>,
+    value: Option<V>,
+}
+
+/// A `GenericTrieIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type GenericTrie<K, V, F> = GenericTrieIn<K, V, F, DefaultPointer>;
+
+/// A child of a `GenericTrieIn`, shared with the other versions of the trie
+/// that hold it.
+type Subtrie<K, V, F, P> = Shared<GenericTrieIn<K, V, F, P>, P>;
+
+/// The map from elements to the children of a `GenericTrieIn`.
+type Children<K, V, F, P> = <F as MapFamily<K>>::Map<Subtrie<K, V, F, P>>;
+
+impl<K: Eq + Clone, V: Clone + Debug, F: MapFamily<K>, P: SharedPointer> GenericTrieIn<K, V, F, P> {
+    /// Returns an iterator over the values in this trie. Each node's value
+    /// comes before those of its children, which are visited in the order
+    /// of the element map, so a trie over `TreeMaps` yields its values in
+    /// lexicographic order of their keys.
+    pub fn values(&self) -> Values<'_, K, V, F, P> {
+        Values { pending: Vec::new(), next: self.value.as_ref(), children: Some(&self.children) }
+    }
+    fn bind_path(&self, key: &[K], value: V) -> Self {
+        match key.split_first() {
+            None => {
+                GenericTrieIn {
+                    value: Some(value),
+                    children: self.children.clone(),
+                }
+            }
+            Some((first, rest)) => {
+                let child = match self.children.lookup(first.clone()) {
+                    Some(child) => child.bind_path(rest, value),
+                    None => GenericTrieIn::empty_map().bind_path(rest, value),
+                };
+                GenericTrieIn {
+                    value: self.value.clone(),
+                    children: self.children.bind(first.clone(), Shared::new(child)),
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, F, P> Map<Vec<K>, V> for GenericTrieIn<K, V, F, P>
+    where K: Eq + Clone,
+          V: Clone + Debug,
+          F: MapFamily<K>,
+          P: SharedPointer
+{
+    fn empty_map() -> Self {
+        GenericTrieIn {
+            value: None,
+            children: F::Map::empty_map(),
+        }
+    }
+    fn bind(&self, key: Vec<K>, value: V) -> Self {
+        self.bind_path(&key, value)
+    }
+    fn lookup(&self, key: Vec<K>) -> Option<V> {
+        let mut current = Shared::new(self.clone());
+        for element in key {
+            current = current.children.lookup(element)?;
+        }
+        current.value.clone()
+    }
+}
+
+impl<K, V, F, P> Collection for GenericTrieIn<K, V, F, P>
+    where K: Eq + Clone,
+          V: Clone + Debug,
+          F: MapFamily<K>,
+          P: SharedPointer
+{
+    type Iter<'a> = Values<'a, K, V, F, P> where Self: 'a;
+
+    fn len(&self) -> usize {
+        // A trie keeps no count of its bindings, so this takes linear time.
+        self.values().count()
+    }
+    fn iter<'a>(&'a self) -> Values<'a, K, V, F, P> {
+        self.values()
+    }
+}
+
+/// An iterator over the values of a `GenericTrie`, returned by
+/// `GenericTrie::values`.
+pub struct Values<'a, K, V, F, P>
+    where K: Eq + 'a,
+          V: Clone + Debug + 'a,
+          F: MapFamily<K> + 'a,
+          P: SharedPointer
+{
+    /// The children of the nodes being visited, the innermost last.
+    pending: Vec<F::Values<'a, Subtrie<K, V, F, P>>>,
+    /// The value of the node just reached, to be returned next.
+    next: Option<&'a V>,
+    /// The children of the node just reached, to be visited next.
+    children: Option<&'a Children<K, V, F, P>>,
+}
+
+impl<'a, K, V, F, P> Iterator for Values<'a, K, V, F, P>
+    where K: Eq + Clone + 'a,
+          V: Clone + Debug + 'a,
+          F: MapFamily<K> + 'a,
+          P: SharedPointer
+{
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        loop {
+            if let Some(children) = self.children.take() {
+                self.pending.push(F::values(children));
+            }
+            if let Some(value) = self.next.take() {
+                return Some(value);
+            }
+            let child = match self.pending.last_mut()?.next() {
+                Some(child) => child,
+                None => {
+                    self.pending.pop();
+                    continue;
+                }
+            };
+            self.next = child.value.as_ref();
+            self.children = Some(&child.children);
+        }
+    }
+}
+
+impl<K, V, F, P> Clone for GenericTrieIn<K, V, F, P>
+    where K: Eq,
+          V: Clone + Debug,
+          F: MapFamily<K>,
+          P: SharedPointer
+{
+    fn clone(&self) -> Self {
+        GenericTrieIn {
+            value: self.value.clone(),
+            children: self.children.clone(),
+        }
+    }
+}
+
+impl<K, V, F, P> Debug for GenericTrieIn<K, V, F, P>
+    where K: Eq,
+          V: Clone + Debug,
+          F: MapFamily<K>,
+          P: SharedPointer
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GenericTrie({:?}, {:?})", self.value, self.children)
+    }
+}
+
+
+#[test]
+fn empty_trie_contains_nothing() {
+    let trie: TrieMap<u32> = TrieMap::empty();
+
+    assert!(trie.lookup("").is_none());
+    assert!(trie.lookup("a").is_none());
+    assert!(trie.is_empty());
+    assert!(trie.iter().next().is_none());
+}
+
+#[test]
+fn keys_can_be_inserted_and_replaced() {
+    let trie = TrieMap::empty().insert("", 0).insert("a", 1).insert("ab", 2).insert(b"b", 3);
+    let replaced = trie.insert("a", 10);
+
+    assert!(trie.size() == 4);
+    assert!(trie.lookup("").unwrap() == 0);
+    assert!(trie.lookup("a").unwrap() == 1);
+    assert!(trie.lookup(String::from("ab")).unwrap() == 2);
+    assert!(trie.lookup("b").unwrap() == 3);
+    assert!(trie.lookup("abc").is_none());
+
+    assert!(replaced.size() == 4);
+    assert!(replaced.lookup("a").unwrap() == 10);
+    assert!(trie.lookup("a").unwrap() == 1);
+}
+
+#[test]
+fn removal_prunes_empty_branches() {
+    let trie = TrieMap::empty().insert("abc", 1).insert("a", 2);
+    let removed = trie.remove("abc");
+
+    assert!(removed.size() == 1);
+    assert!(removed.lookup("abc").is_none());
+    assert!(removed.lookup("a").unwrap() == 2);
+    assert!(removed.root.find(b"ab").is_none());
+    assert!(trie.lookup("abc").unwrap() == 1);
+
+    assert!(removed.remove("ab").size() == 1);
+    assert!(removed.remove("a").remove("a").is_empty());
+    assert!(removed.remove("a").root.children.is_empty());
+}
+
+#[test]
+fn prefix_queries_are_ordered() {
+    let words = vec!["tea", "ten", "to", "inn", "in", "tenth", "a"];
+    let trie = words.iter().fold(TrieMap::empty(), |trie, w| trie.insert(w, w.len()));
+
+    let keys = |prefix: &str| -> Vec<String> {
+        trie.keys_with_prefix(prefix)
+            .into_iter()
+            .map(|key| String::from_utf8(key).unwrap())
+            .collect()
+    };
+
+    assert!(keys("te") == vec!["tea", "ten", "tenth"]);
+    assert!(keys("ten") == vec!["ten", "tenth"]);
+    assert!(keys("") == vec!["a", "in", "inn", "tea", "ten", "tenth", "to"]);
+    assert!(keys("x").is_empty());
+    assert!(trie.iter_prefix("in").map(|(_, v)| *v).collect::<Vec<_>>() == vec![2, 3]);
+}
+
+#[test]
+fn generic_trie_binds_sequences() {
+    let empty: GenericTrie<u32, &str, TreeMaps> = GenericTrie::empty_map();
+    let trie = empty.bind(vec![], "root")
+                    .bind(vec![1, 2, 3], "one two three")
+                    .bind(vec![1, 2], "one two")
+                    .bind(vec![2], "two");
+    let replaced = trie.bind(vec![1, 2], "replaced");
+
+    assert!(trie.lookup(vec![]).unwrap() == "root");
+    assert!(trie.lookup(vec![1, 2, 3]).unwrap() == "one two three");
+    assert!(trie.lookup(vec![1, 2]).unwrap() == "one two");
+    assert!(trie.lookup(vec![2]).unwrap() == "two");
+    assert!(trie.lookup(vec![1]).is_none());
+    assert!(trie.lookup(vec![1, 2, 3, 4]).is_none());
+
+    assert!(replaced.lookup(vec![1, 2]).unwrap() == "replaced");
+    assert!(replaced.lookup(vec![1, 2, 3]).unwrap() == "one two three");
+
+    let values: Vec<_> = trie.values().cloned().collect();
+    assert!(values == vec!["root", "one two", "one two three", "two"]);
+    assert!(trie.len() == 4 && empty.iter().next().is_none() && empty.is_empty());
+}
+
+#[test]
+fn generic_tries_nest() {
+    let empty: GenericTrie<Vec<char>, u32, TrieMaps<HamtMaps>> = GenericTrie::empty_map();
+    let words = |s: &str| -> Vec<Vec<char>> { s.split(' ').map(|w| w.chars().collect()).collect() };
+    let trie = empty.bind(words("hello world"), 1).bind(words("hello there"), 2);
+
+    assert!(trie.lookup(words("hello world")).unwrap() == 1);
+    assert!(trie.lookup(words("hello there")).unwrap() == 2);
+    assert!(trie.lookup(words("hello")).is_none());
+    assert!(trie.lookup(words("hell world")).is_none());
+    let mut values: Vec<_> = trie.values().cloned().collect();
+    values.sort();
+    assert!(values == vec![1, 2]);
+}
+
+#[test]
+fn distance_search_finds_near_keys() {
+    let words = ["cat", "cart", "cast", "dog", "cut", "act", "category", ""];
+    let trie = words.iter().fold(TrieMap::empty(), |trie, w| trie.insert(w, ()));
+
+    let within = |query: &str, distance: u32| -> Vec<String> {
+        trie.search_within_distance(query, distance)
+            .into_iter()
+            .map(|key| String::from_utf8(key).unwrap())
+            .collect()
+    };
+
+    assert!(within("cat", 0) == vec!["cat"]);
+    assert!(within("cat", 1) == vec!["cart", "cast", "cat", "cut"]);
+    assert!(within("cat", 2) == vec!["act", "cart", "cast", "cat", "cut"]);
+    assert!(within("dgo", 2) == vec!["dog"]);
+    assert!(within("", 3) == vec!["", "act", "cat", "cut", "dog"]);
+    assert!(within("catgory", 1) == vec!["category"]);
+    assert!(TrieMap::<()>::empty().search_within_distance("cat", 5).is_empty());
+}
@@ -0,0 +1,452 @@
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use hamt::HamtMap;
+
+/// The tag of a record binding a key to a value.
+const BIND: u8 = 0;
+/// The tag of a record removing a key.
+const REMOVE: u8 = 1;
+/// The length of a record's header: its tag and the length of its payload.
+const HEADER: usize = 5;
+
+/// A trait for keys and values which can be written to and read back from a
+/// `LoggedMap`'s files.
+///
+/// Only available with the `persistence` feature.
+pub trait Encode: Sized {
+    /// Appends the encoding of this value to `out`.
+    fn encode(&self, out: &mut Vec<u8>);
+    /// Reads a value from the front of `input`, advancing it past the bytes
+    /// which were read.
+    ///
+    /// # Failures
+    ///
+    /// Returns an error of kind `InvalidData` if `input` does not start with
+    /// the encoding of a value.
+    fn decode(input: &mut &[u8]) -> io::Result<Self>;
+}
+
+macro_rules! encode_integer {
+    ($($int:ty),*) => {$(
+        impl Encode for $int {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+            fn decode(input: &mut &[u8]) -> io::Result<Self> {
+                let mut bytes = [0; ::std::mem::size_of::<$int>()];
+                take(input, bytes.len()).map(|taken| {
+                    bytes.copy_from_slice(taken);
+                    <$int>::from_le_bytes(bytes)
+                })
+            }
+        }
+    )*};
+}
+
+encode_integer!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        let len = u32::decode(input)? as usize;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("string is not valid UTF-8"))
+    }
+}
+
+impl Encode for Vec<u8> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        out.extend_from_slice(self);
+    }
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        let len = u32::decode(input)? as usize;
+        take(input, len).map(|bytes| bytes.to_vec())
+    }
+}
+
+/// A `HamtMap` whose updates are appended to a log on disk before they are
+/// made, so that it can be reloaded as it was after a restart.
+///
+/// The map's files live in a directory of their own: a snapshot of the
+/// bindings at the last compaction, and a log of the updates made since.
+/// Opening the directory replays the log over the snapshot. Compacting
+/// writes a new snapshot of the current bindings and empties the log, which
+/// keeps reloading quick; it can be done by hand or every so many updates.
+///
+/// Each update is written to the log before the in-memory map changes, and
+/// an update which fails to be written is not made. Updates are only synced
+/// to the disk by `sync` and `compact`, so those written since can be lost
+/// if the machine crashes. A record cut short by a crash while it was being
+/// written is ignored when the log is replayed, as if the update had never
+/// been made.
+///
+/// The map itself is an ordinary persistent `HamtMap`, so `map` hands out
+/// versions of it which are unaffected by later updates.
+///
+/// Only available with the `persistence` feature.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::wal::LoggedMap;
+///
+/// let dir = std::env::temp_dir().join(format!("wal-doc-{}", std::process::id()));
+/// let _ = std::fs::remove_dir_all(&dir);
+///
+/// let mut map = LoggedMap::open(&dir).unwrap();
+/// map.bind("answer".to_string(), 42u32).unwrap();
+/// map.bind("question".to_string(), 0).unwrap();
+/// map.remove(&"question".to_string()).unwrap();
+/// drop(map);
+///
+/// let reloaded: LoggedMap<String, u32> = LoggedMap::open(&dir).unwrap();
+/// assert!(reloaded.map().get(&"answer".to_string()) == Some(&42));
+/// assert!(reloaded.map().size() == 1);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct LoggedMap<K, V> {
+    map: HamtMap<K, V>,
+    dir: PathBuf,
+    log: File,
+    /// The length of the log in bytes, which holds only whole records.
+    len: u64,
+    /// The number of updates in the log.
+    logged: u32,
+    /// The number of updates after which the map compacts itself, if any.
+    compact_every: Option<u32>,
+}
+
+impl<K: Hash + Eq + Clone + Encode, V: Clone + Encode> LoggedMap<K, V> {
+    /// Opens the map stored in the directory `dir`, creating the directory
+    /// and an empty map if they do not exist.
+    ///
+    /// # Failures
+    ///
+    /// Returns any error from reading the map's files, or one of kind
+    /// `InvalidData` if they are corrupt.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut map = HamtMap::new();
+        if let Some(bytes) = read_if_exists(&dir.join("snapshot"))? {
+            map = replay(map, &bytes)?.0;
+        }
+        let (map, logged, len) = match read_if_exists(&dir.join("log"))? {
+            Some(bytes) => replay(map, &bytes)?,
+            None => (map, 0, 0),
+        };
+        let log = OpenOptions::new().create(true).append(true).open(dir.join("log"))?;
+        // Drop any record cut short, so that new records follow whole ones.
+        log.set_len(len as u64)?;
+        Ok(LoggedMap {
+            map: map,
+            dir: dir,
+            log: log,
+            len: len as u64,
+            logged: logged,
+            compact_every: None,
+        })
+    }
+    /// Returns this map, set to compact itself once its log holds `updates`
+    /// updates.
+    pub fn compact_every(self, updates: u32) -> Self {
+        LoggedMap { compact_every: Some(updates), ..self }
+    }
+    /// Returns the current version of the map.
+    pub fn map(&self) -> &HamtMap<K, V> {
+        &self.map
+    }
+    /// Binds `key` to `value`, replacing any existing binding for `key`.
+    ///
+    /// # Failures
+    ///
+    /// Returns any error from writing the update to the log, in which case
+    /// the map and the log are unchanged, or from compacting afterwards.
+    ///
+    /// The update is written to the log but not synced to the disk, so it
+    /// can still be lost in a crash of the machine until `sync` is called.
+    pub fn bind(&mut self, key: K, value: V) -> io::Result<()> {
+        let mut payload = Vec::new();
+        key.encode(&mut payload);
+        value.encode(&mut payload);
+        self.append(BIND, &payload)?;
+        self.map = self.map.insert(key, value);
+        self.compact_if_due()
+    }
+    /// Removes any binding for `key`.
+    ///
+    /// # Failures
+    ///
+    /// Returns any error from writing the update to the log, in which case
+    /// the map and the log are unchanged, or from compacting afterwards.
+    ///
+    /// The update is written to the log but not synced to the disk, so it
+    /// can still be lost in a crash of the machine until `sync` is called.
+    pub fn remove(&mut self, key: &K) -> io::Result<()> {
+        let mut payload = Vec::new();
+        key.encode(&mut payload);
+        self.append(REMOVE, &payload)?;
+        self.map = self.map.remove(key);
+        self.compact_if_due()
+    }
+    /// Writes a snapshot of the current bindings and empties the log.
+    ///
+    /// The snapshot is written to a temporary file and then renamed over the
+    /// old one, and the rename is synced before the log is emptied, so a
+    /// crash part way through leaves either the old snapshot and log or the
+    /// new snapshot, which replaying the log again does not change.
+    ///
+    /// # Failures
+    ///
+    /// Returns any error from writing the files.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        for (key, value) in self.map.iter() {
+            let mut payload = Vec::new();
+            key.encode(&mut payload);
+            value.encode(&mut payload);
+            push_record(&mut bytes, BIND, &payload);
+        }
+        let temporary = self.dir.join("snapshot.tmp");
+        let mut snapshot = File::create(&temporary)?;
+        snapshot.write_all(&bytes)?;
+        snapshot.sync_all()?;
+        fs::rename(&temporary, self.dir.join("snapshot"))?;
+        // Make the rename durable before emptying the log, so that a crash
+        // cannot bring back the old snapshot alongside an empty log.
+        File::open(&self.dir)?.sync_all()?;
+        self.log.set_len(0)?;
+        self.log.sync_all()?;
+        self.len = 0;
+        self.logged = 0;
+        Ok(())
+    }
+    /// Waits until every update made so far has reached the disk.
+    ///
+    /// # Failures
+    ///
+    /// Returns any error from syncing the log.
+    pub fn sync(&self) -> io::Result<()> {
+        self.log.sync_data()
+    }
+    fn append(&mut self, tag: u8, payload: &[u8]) -> io::Result<()> {
+        let mut record = Vec::with_capacity(HEADER + payload.len());
+        push_record(&mut record, tag, payload);
+        if let Err(error) = self.log.write_all(&record) {
+            // Drop any part of the record which was written, so that later
+            // records follow whole ones rather than a broken one.
+            return self.log.set_len(self.len).and(Err(error));
+        }
+        self.len += record.len() as u64;
+        self.logged += 1;
+        Ok(())
+    }
+    fn compact_if_due(&mut self) -> io::Result<()> {
+        match self.compact_every {
+            Some(updates) if self.logged >= updates => self.compact(),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn push_record(out: &mut Vec<u8>, tag: u8, payload: &[u8]) {
+    out.push(tag);
+    (payload.len() as u32).encode(out);
+    out.extend_from_slice(payload);
+}
+
+/// Returns `map` with the records in `bytes` applied to it, the number of
+/// records applied and the number of bytes they took up. A record cut short
+/// at the end is ignored.
+fn replay<K, V>(mut map: HamtMap<K, V>, bytes: &[u8]) -> io::Result<(HamtMap<K, V>, u32, usize)>
+    where K: Hash + Eq + Clone + Encode,
+          V: Clone + Encode
+{
+    let total = bytes.len();
+    let mut bytes = bytes;
+    let mut count = 0;
+    while bytes.len() >= HEADER {
+        let tag = bytes[0];
+        let mut header = &bytes[1..HEADER];
+        let len = u32::decode(&mut header)? as usize;
+        if bytes.len() < HEADER + len {
+            break;
+        }
+        let mut payload = &bytes[HEADER..HEADER + len];
+        map = match tag {
+            BIND => {
+                let key = K::decode(&mut payload)?;
+                map.insert(key, V::decode(&mut payload)?)
+            }
+            REMOVE => map.remove(&K::decode(&mut payload)?),
+            _ => return Err(invalid("unknown record tag")),
+        };
+        if !payload.is_empty() {
+            return Err(invalid("record is longer than its contents"));
+        }
+        bytes = &bytes[HEADER + len..];
+        count += 1;
+    }
+    Ok((map, count, total - bytes.len()))
+}
+
+fn read_if_exists(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match File::open(path) {
+        Ok(mut file) => {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            Ok(Some(bytes))
+        }
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Returns the first `len` bytes of `input`, advancing it past them.
+fn take<'a>(input: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if input.len() < len {
+        return Err(invalid("unexpected end of record"));
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Ok(taken)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+use std::mem;
+
+/// Returns an empty directory for a test to keep a map in.
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+    let dir = ::std::env::temp_dir().join(format!("wal-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn updates_survive_reopening() {
+    let dir = test_dir("reopen");
+    {
+        let mut map = LoggedMap::open(&dir).unwrap();
+        for i in 0..100u32 {
+            map.bind(i, format!("value {}", i)).unwrap();
+        }
+        for i in 0..50 {
+            map.remove(&(i * 2)).unwrap();
+        }
+        map.bind(1, "one".to_string()).unwrap();
+    }
+
+    let map: LoggedMap<u32, String> = LoggedMap::open(&dir).unwrap();
+    assert!(map.map().size() == 50);
+    assert!(map.map().get(&1).unwrap() == "one");
+    assert!(map.map().get(&3).unwrap() == "value 3");
+    assert!(map.map().get(&4).is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn compaction_empties_the_log() {
+    let dir = test_dir("compact");
+    {
+        let mut map = LoggedMap::open(&dir).unwrap().compact_every(10);
+        for i in 0..25u64 {
+            map.bind(i % 5, i).unwrap();
+        }
+        assert!(fs::metadata(dir.join("log")).unwrap().len() == 5 * (HEADER as u64 + 16));
+        map.compact().unwrap();
+        assert!(fs::metadata(dir.join("log")).unwrap().len() == 0);
+        map.remove(&0).unwrap();
+    }
+
+    let map: LoggedMap<u64, u64> = LoggedMap::open(&dir).unwrap();
+    assert!(map.map().size() == 4);
+    assert!((1..5).all(|i| map.map().get(&i) == Some(&(i + 20))));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn torn_records_are_ignored_but_corruption_is_not() {
+    let dir = test_dir("torn");
+    {
+        let mut map = LoggedMap::open(&dir).unwrap();
+        map.bind(1u32, vec![1u8, 2, 3]).unwrap();
+        map.bind(2, vec![4, 5]).unwrap();
+    }
+    let len = fs::metadata(dir.join("log")).unwrap().len();
+    OpenOptions::new().write(true).open(dir.join("log")).unwrap().set_len(len - 1).unwrap();
+
+    let mut map: LoggedMap<u32, Vec<u8>> = LoggedMap::open(&dir).unwrap();
+    assert!(map.map().size() == 1);
+    assert!(map.map().get(&1).unwrap() == &vec![1, 2, 3]);
+    map.bind(3, vec![6]).unwrap();
+    drop(map);
+
+    let mut map: LoggedMap<u32, Vec<u8>> = LoggedMap::open(&dir).unwrap();
+    assert!(map.map().size() == 2);
+    assert!(map.map().get(&3).unwrap() == &vec![6]);
+    map.compact().unwrap();
+    drop(map);
+
+    fs::write(dir.join("log"), [9, 0, 0, 0, 0]).unwrap();
+    let corrupt: io::Result<LoggedMap<u32, Vec<u8>>> = LoggedMap::open(&dir);
+    assert!(corrupt.unwrap_err().kind() == io::ErrorKind::InvalidData);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn failed_writes_leave_the_log_whole() {
+    let dir = test_dir("failed");
+    let mut map = LoggedMap::open(&dir).unwrap();
+    map.bind(1u32, "one".to_string()).unwrap();
+    let len = fs::metadata(dir.join("log")).unwrap().len();
+    // A handle which cannot be written to makes every write fail.
+    let log = mem::replace(&mut map.log, File::open(dir.join("log")).unwrap());
+
+    assert!(map.bind(2, "two".to_string()).is_err());
+    assert!(map.map().size() == 1 && map.len == len);
+    assert!(fs::metadata(dir.join("log")).unwrap().len() == len);
+    map.log = log;
+    map.bind(3, "three".to_string()).unwrap();
+    drop(map);
+
+    let map: LoggedMap<u32, String> = LoggedMap::open(&dir).unwrap();
+    assert!(map.map().size() == 2 && map.map().get(&3).unwrap() == "three");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn junk_between_records_is_reported_as_corruption() {
+    let dir = test_dir("junk");
+    {
+        let mut map = LoggedMap::open(&dir).unwrap();
+        map.bind(1u32, "one".to_string()).unwrap();
+    }
+    let mut bytes = fs::read(dir.join("log")).unwrap();
+    // The start of a record, as a write which failed part way would leave
+    // it, followed by a whole record which its length reaches into.
+    bytes.extend_from_slice(&[BIND, 7, 0, 0, 0, 9]);
+    let mut record = Vec::new();
+    let mut payload = Vec::new();
+    2u32.encode(&mut payload);
+    "two".to_string().encode(&mut payload);
+    push_record(&mut record, BIND, &payload);
+    bytes.extend_from_slice(&record);
+    fs::write(dir.join("log"), bytes).unwrap();
+
+    let corrupt: io::Result<LoggedMap<u32, String>> = LoggedMap::open(&dir);
+    assert!(corrupt.unwrap_err().kind() == io::ErrorKind::InvalidData);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,443 @@
+use ptr::{DefaultPointer, Shared, SharedPointer};
+use set::TreeIn;
+use stack::{CustomStackIn, StackError};
+
+/// A zipper over a `Tree`: a position in the tree, called the focus, along
+/// with the path back up to the root.
+///
+/// Moving the focus up or down takes O(1) time, as does replacing or
+/// deleting the focused subtree, and a zipper is cheap to copy, since its
+/// path is a `CustomStack`. The tree is only put back together when
+/// `rebuild` is called, copying the path from the focus to the root once,
+/// so a run of edits close together costs much less than making each one
+/// separately from the root.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::set::{Map, Tree};
+/// use functional_datastructures::zipper::TreeZipper;
+///
+/// let empty: Tree<u32, &str> = Tree::empty_map();
+/// let tree = empty.bind(5, "five").bind(2, "two").bind(8, "eight");
+///
+/// let zipper = TreeZipper::new(&tree).left().unwrap();
+/// let edited = zipper.replace(Tree::empty_map().bind(1, "one")).up().unwrap();
+/// let rebuilt = edited.focus_on(&8).delete().rebuild();
+///
+/// assert!(rebuilt.lookup(1).unwrap() == "one");
+/// assert!(rebuilt.lookup(2).is_none());
+/// assert!(rebuilt.lookup(8).is_none());
+/// assert!(tree.lookup(2).unwrap() == "two");
+/// ```
+#[derive(Debug, Clone)]
+pub struct TreeZipperIn<K: Ord + Clone, V: Clone, P: SharedPointer> {
+    focus: Shared<TreeIn<K, V, P>, P>,
+    path: Shared<CustomStackIn<Crumb<K, V, P>, P>, P>,
+}
+
+/// A `TreeZipperIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type TreeZipper<K, V> = TreeZipperIn<K, V, DefaultPointer>;
+
+/// A step down from a node to one of its children, recording the parts of
+/// the node which were left behind.
+#[derive(Debug, Clone)]
+enum Crumb<K: Ord + Clone, V: Clone, P: SharedPointer> {
+    Left {
+        key: K,
+        value: V,
+        right: Shared<TreeIn<K, V, P>, P>,
+    },
+    Right {
+        left: Shared<TreeIn<K, V, P>, P>,
+        key: K,
+        value: V,
+    },
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> TreeZipperIn<K, V, P> {
+    /// Returns a zipper focused on the root of `tree`.
+    pub fn new(tree: &TreeIn<K, V, P>) -> Self {
+        TreeZipperIn {
+            focus: Shared::new(tree.clone()),
+            path: Shared::new(CustomStackIn::Empty),
+        }
+    }
+    /// Returns the focused subtree.
+    pub fn focus(&self) -> &TreeIn<K, V, P> {
+        &self.focus
+    }
+    /// Tests whether the focus is the root of the tree.
+    pub fn is_root(&self) -> bool {
+        match *self.path {
+            CustomStackIn::Empty => true,
+            _ => false,
+        }
+    }
+    /// Returns a zipper focused on the left child of the focus, or `None` if
+    /// the focus is empty.
+    pub fn left(&self) -> Option<Self> {
+        match *self.focus {
+            TreeIn::Empty => None,
+            TreeIn::Node { ref left, ref key, ref value, ref right } => {
+                let crumb = Crumb::Left {
+                    key: key.clone(),
+                    value: value.clone(),
+                    right: right.clone(),
+                };
+                Some(self.descend(left, crumb))
+            }
+        }
+    }
+    /// Returns a zipper focused on the right child of the focus, or `None` if
+    /// the focus is empty.
+    pub fn right(&self) -> Option<Self> {
+        match *self.focus {
+            TreeIn::Empty => None,
+            TreeIn::Node { ref left, ref key, ref value, ref right } => {
+                let crumb = Crumb::Right {
+                    left: left.clone(),
+                    key: key.clone(),
+                    value: value.clone(),
+                };
+                Some(self.descend(right, crumb))
+            }
+        }
+    }
+    /// Returns a zipper focused on the parent of the focus, or `None` if the
+    /// focus is the root.
+    pub fn up(&self) -> Option<Self> {
+        match *self.path {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref value, ref tail } => {
+                Some(TreeZipperIn {
+                    focus: Shared::new(value.rejoin(self.focus.clone())),
+                    path: tail.clone(),
+                })
+            }
+        }
+    }
+    /// Returns a zipper focused on the node for `key` below the focus, or on
+    /// the empty subtree where it would be if it is not there, following the
+    /// order of the keys down from the focus.
+    pub fn focus_on(&self, key: &K) -> Self {
+        let mut zipper = self.clone();
+        loop {
+            let next = match *zipper.focus {
+                TreeIn::Node { key: ref node_key, .. } if *key < *node_key => zipper.left(),
+                TreeIn::Node { key: ref node_key, .. } if *key > *node_key => zipper.right(),
+                _ => return zipper,
+            };
+            zipper = next.unwrap();
+        }
+    }
+    /// Returns a copy of this zipper with the focused subtree replaced by
+    /// `tree`. The keys of `tree` should belong where the focus is, or the
+    /// rebuilt tree will not be ordered.
+    pub fn replace(&self, tree: TreeIn<K, V, P>) -> Self {
+        TreeZipperIn {
+            focus: Shared::new(tree),
+            path: self.path.clone(),
+        }
+    }
+    /// Returns a copy of this zipper with the focused subtree removed.
+    pub fn delete(&self) -> Self {
+        self.replace(TreeIn::Empty)
+    }
+    /// Returns the whole tree, with the edits made through this zipper.
+    pub fn rebuild(&self) -> TreeIn<K, V, P> {
+        let mut tree = self.focus.clone();
+        let mut path = &self.path;
+        while let CustomStackIn::Cons { ref value, ref tail } = **path {
+            tree = Shared::new(value.rejoin(tree));
+            path = tail;
+        }
+        (*tree).clone()
+    }
+    fn descend(&self, child: &Shared<TreeIn<K, V, P>, P>, crumb: Crumb<K, V, P>) -> Self {
+        TreeZipperIn {
+            focus: child.clone(),
+            path: Shared::new(CustomStackIn::Cons {
+                value: crumb,
+                tail: self.path.clone(),
+            }),
+        }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone, P: SharedPointer> Crumb<K, V, P> {
+    /// Returns the node this step was taken from, with `child` in place of
+    /// the child it led to.
+    fn rejoin(&self, child: Shared<TreeIn<K, V, P>, P>) -> TreeIn<K, V, P> {
+        match *self {
+            Crumb::Left { ref key, ref value, ref right } => {
+                TreeIn::Node {
+                    left: child,
+                    key: key.clone(),
+                    value: value.clone(),
+                    right: right.clone(),
+                }
+            }
+            Crumb::Right { ref left, ref key, ref value } => {
+                TreeIn::Node {
+                    left: left.clone(),
+                    key: key.clone(),
+                    value: value.clone(),
+                    right: child,
+                }
+            }
+        }
+    }
+}
+
+/// A zipper over a `CustomStack`: a cursor into a list, which keeps the
+/// elements before the cursor in a stack of their own, nearest first, and
+/// the rest of the list after it.
+///
+/// The element just after the cursor is the focus, and there is none when
+/// the cursor is at the end of the list. Moving the cursor one step, or
+/// inserting, deleting or replacing an element at it, takes O(1) time, which
+/// makes this a natural model of a text caret or a position in a playlist.
+/// `to_stack` puts the list back together in time linear in the number of
+/// elements before the cursor, sharing those after it.
+///
+/// # Examples
+///
+/// ```
+/// use functional_datastructures::ptr::Shared;
+/// use functional_datastructures::stack::{CustomStack, Stack};
+/// use functional_datastructures::zipper::ListZipper;
+///
+/// let list = Shared::new(CustomStack::empty().cons(3).cons(2).cons(1));
+/// let zipper = ListZipper::new(&list).right().unwrap();
+///
+/// assert!(zipper.focus() == Some(&2));
+/// let edited = zipper.replace(20).unwrap().insert(15).right().unwrap().right().unwrap();
+/// assert!(edited.focus() == Some(&3));
+/// assert!(edited.delete().unwrap().focus().is_none());
+///
+/// let stack = edited.to_stack();
+/// assert!(stack.size() == 4);
+/// assert!(stack.get(1).unwrap() == 15 && stack.get(2).unwrap() == 20);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ListZipperIn<T, P: SharedPointer> {
+    before: Shared<CustomStackIn<T, P>, P>,
+    after: Shared<CustomStackIn<T, P>, P>,
+}
+
+/// A `ListZipperIn` whose nodes are shared through `DefaultPointer`, as exported
+/// at the root of the crate.
+pub type ListZipper<T> = ListZipperIn<T, DefaultPointer>;
+
+impl<T: Clone, P: SharedPointer> ListZipperIn<T, P> {
+    /// Returns a zipper with its cursor at the start of `list`.
+    pub fn new(list: &Shared<CustomStackIn<T, P>, P>) -> Self {
+        ListZipperIn {
+            before: Shared::new(CustomStackIn::Empty),
+            after: list.clone(),
+        }
+    }
+    /// Returns the element just after the cursor, or `None` if the cursor is
+    /// at the end of the list.
+    pub fn focus(&self) -> Option<&T> {
+        match *self.after {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref value, .. } => Some(value),
+        }
+    }
+    /// Tests whether the cursor is at the start of the list.
+    pub fn is_start(&self) -> bool {
+        match *self.before {
+            CustomStackIn::Empty => true,
+            _ => false,
+        }
+    }
+    /// Tests whether the cursor is at the end of the list.
+    pub fn is_end(&self) -> bool {
+        self.focus().is_none()
+    }
+    /// Returns a zipper with the cursor moved one element towards the start,
+    /// or `None` if it is at the start.
+    pub fn left(&self) -> Option<Self> {
+        match *self.before {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref value, ref tail } => {
+                Some(ListZipperIn {
+                    before: tail.clone(),
+                    after: cons(value.clone(), self.after.clone()),
+                })
+            }
+        }
+    }
+    /// Returns a zipper with the cursor moved one element towards the end,
+    /// past the focus, or `None` if it is at the end.
+    pub fn right(&self) -> Option<Self> {
+        match *self.after {
+            CustomStackIn::Empty => None,
+            CustomStackIn::Cons { ref value, ref tail } => {
+                Some(ListZipperIn {
+                    before: cons(value.clone(), self.before.clone()),
+                    after: tail.clone(),
+                })
+            }
+        }
+    }
+    /// Returns a copy of this zipper with `value` inserted at the cursor, as
+    /// the new focus.
+    pub fn insert(&self, value: T) -> Self {
+        ListZipperIn {
+            before: self.before.clone(),
+            after: cons(value, self.after.clone()),
+        }
+    }
+    /// Returns a copy of this zipper with the focus removed, so that the
+    /// element after it becomes the focus.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::NoSuchElementException` if the cursor is at the
+    /// end of the list.
+    pub fn delete(&self) -> Result<Self, StackError> {
+        match *self.after {
+            CustomStackIn::Empty => Err(StackError::NoSuchElementException),
+            CustomStackIn::Cons { ref tail, .. } => {
+                Ok(ListZipperIn {
+                    before: self.before.clone(),
+                    after: tail.clone(),
+                })
+            }
+        }
+    }
+    /// Returns a copy of this zipper with the focus replaced by `value`.
+    ///
+    /// # Failures
+    ///
+    /// Returns `StackError::NoSuchElementException` if the cursor is at the
+    /// end of the list.
+    pub fn replace(&self, value: T) -> Result<Self, StackError> {
+        self.delete().map(|deleted| deleted.insert(value))
+    }
+    /// Returns the whole list, with the edits made through this zipper.
+    pub fn to_stack(&self) -> Shared<CustomStackIn<T, P>, P> {
+        let mut list = self.after.clone();
+        let mut before = &self.before;
+        while let CustomStackIn::Cons { ref value, ref tail } = **before {
+            list = cons(value.clone(), list);
+            before = tail;
+        }
+        list
+    }
+}
+
+fn cons<T, P>(value: T, tail: Shared<CustomStackIn<T, P>, P>) -> Shared<CustomStackIn<T, P>, P>
+    where P: SharedPointer
+{
+    Shared::new(CustomStackIn::Cons {
+        value: value,
+        tail: tail,
+    })
+}
+
+#[cfg(test)]
+use set::{Map, Tree};
+
+#[cfg(test)]
+use stack::{CustomStack, Stack};
+
+#[cfg(test)]
+fn tree_of(keys: &[u32]) -> Tree<u32, u32> {
+    keys.iter().fold(Tree::empty_map(), |tree, k| tree.bind(*k, k * 10))
+}
+
+#[test]
+fn moving_around_and_back_changes_nothing() {
+    let tree = tree_of(&[50, 25, 75, 10, 30, 60, 90]);
+    let zipper = TreeZipper::new(&tree);
+
+    assert!(zipper.is_root());
+    assert!(zipper.up().is_none());
+    let moved = zipper.left().unwrap().right().unwrap().up().unwrap().up().unwrap();
+    assert!(moved.is_root());
+    let deepest = zipper.left().unwrap().left().unwrap().left().unwrap();
+    assert!(deepest.left().is_none());
+    assert!((0..100).all(|k| deepest.rebuild().lookup(k) == tree.lookup(k)));
+}
+
+#[test]
+fn focus_on_finds_keys_and_gaps() {
+    let tree = tree_of(&[50, 25, 75, 10, 30]);
+    let zipper = TreeZipper::new(&tree);
+
+    match *zipper.focus_on(&30).focus() {
+        Tree::Node { key, value, .. } => assert!(key == 30 && value == 300),
+        Tree::Empty => panic!("expected to find 30"),
+    }
+    let gap = zipper.focus_on(&27);
+    assert!(match *gap.focus() {
+        Tree::Empty => true,
+        _ => false,
+    });
+    let inserted = gap.replace(tree_of(&[27])).rebuild();
+    assert!(inserted.lookup(27).unwrap() == 270);
+    assert!(inserted.lookup(30).unwrap() == 300);
+    assert!(tree.lookup(27).is_none());
+}
+
+#[test]
+fn edits_are_applied_on_rebuild() {
+    let tree = tree_of(&[50, 25, 75, 10, 30, 60, 90]);
+
+    let edited = TreeZipper::new(&tree)
+                     .focus_on(&25)
+                     .delete()
+                     .up()
+                     .unwrap()
+                     .focus_on(&90)
+                     .replace(tree_of(&[90, 95]))
+                     .rebuild();
+    for k in &[10, 25, 30] {
+        assert!(edited.lookup(*k).is_none());
+    }
+    for k in &[50, 60, 75, 90, 95] {
+        assert!(edited.lookup(*k).unwrap() == k * 10);
+    }
+}
+
+#[cfg(test)]
+fn list_of(values: &[u32]) -> Shared<CustomStack<u32>, DefaultPointer> {
+    Shared::new(values.iter().rev().fold(CustomStack::empty(), |list, v| list.cons(*v)))
+}
+
+#[cfg(test)]
+fn contents(list: &Shared<CustomStack<u32>, DefaultPointer>) -> Vec<u32> {
+    (0..list.size()).map(|i| list.get(i).unwrap()).collect()
+}
+
+#[test]
+fn list_cursor_walks_both_ways() {
+    let zipper = ListZipper::new(&list_of(&[1, 2, 3]));
+
+    assert!(zipper.is_start() && zipper.left().is_none());
+    assert!(zipper.focus() == Some(&1));
+    let end = zipper.right().unwrap().right().unwrap().right().unwrap();
+    assert!(end.is_end() && end.right().is_none());
+    assert!(end.left().unwrap().focus() == Some(&3));
+    assert!(contents(&end.to_stack()) == vec![1, 2, 3]);
+    assert!(end.delete().is_err() && end.replace(4).is_err());
+}
+
+#[test]
+fn list_edits_happen_at_the_cursor() {
+    let list = list_of(&[1, 2, 3, 4]);
+    let zipper = ListZipper::new(&list).right().unwrap().right().unwrap();
+
+    assert!(contents(&zipper.insert(9).to_stack()) == vec![1, 2, 9, 3, 4]);
+    assert!(contents(&zipper.delete().unwrap().to_stack()) == vec![1, 2, 4]);
+    assert!(contents(&zipper.replace(9).unwrap().to_stack()) == vec![1, 2, 9, 4]);
+    let appended = (5..8).fold(zipper.right().unwrap().right().unwrap(),
+                               |zipper, v| zipper.insert(v).right().unwrap());
+    assert!(contents(&appended.to_stack()) == vec![1, 2, 3, 4, 5, 6, 7]);
+    assert!(contents(&list) == vec![1, 2, 3, 4]);
+}